@@ -0,0 +1,85 @@
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use fk::builtins::string::{lowercase, uppercase};
+use std::hint::black_box;
+
+/// Ten thousand records of each kind `tolower($0)` meets: ASCII already
+/// in lower case, ASCII with capitals, and multibyte text with capitals.
+fn corpora() -> Vec<(&'static str, Vec<String>)> {
+    let ascii_lower = (0..10_000)
+        .map(|i| {
+            format!(
+                "2024-05-{:02} info request id={i} path=/api/v1/items status=200",
+                i % 28
+            )
+        })
+        .collect();
+    let ascii_mixed = (0..10_000)
+        .map(|i| {
+            format!(
+                "2024-05-{:02} INFO Request id={i} Path=/API/v1/Items Status=OK",
+                i % 28
+            )
+        })
+        .collect();
+    let multibyte = (0..10_000)
+        .map(|i| format!("Straße {i}, München — ΟΔΥΣΣΕΥΣ Ärger Ñandú Привет {i}"))
+        .collect();
+    vec![
+        ("ascii_lower", ascii_lower),
+        ("ascii_mixed", ascii_mixed),
+        ("multibyte", multibyte),
+    ]
+}
+
+/// `str::to_lowercase` / `to_uppercase`, as the builtins used to call
+/// it, against the in-place mapping on the already-owned argument.
+fn bench_case(c: &mut Criterion) {
+    let mut group = c.benchmark_group("case_convert");
+    group.sample_size(20);
+    for (name, lines) in corpora() {
+        let bytes: usize = lines.iter().map(String::len).sum();
+        group.throughput(Throughput::Bytes(bytes as u64));
+        group.bench_with_input(BenchmarkId::new("tolower_std", name), &lines, |b, lines| {
+            b.iter(|| {
+                for s in lines {
+                    black_box(s.to_lowercase());
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("tolower", name), &lines, |b, lines| {
+            b.iter_batched(
+                || lines.clone(),
+                |mut owned| {
+                    for s in &mut owned {
+                        lowercase(s);
+                    }
+                    owned
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("toupper_std", name), &lines, |b, lines| {
+            b.iter(|| {
+                for s in lines {
+                    black_box(s.to_uppercase());
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("toupper", name), &lines, |b, lines| {
+            b.iter_batched(
+                || lines.clone(),
+                |mut owned| {
+                    for s in &mut owned {
+                        uppercase(s);
+                    }
+                    owned
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_case);
+criterion_main!(benches);
@@ -1,5 +1,6 @@
-use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
 use fk::field;
+use std::hint::black_box;
 
 fn make_whitespace_line(n: usize) -> String {
     (0..n).map(|i| i.to_string()).collect::<Vec<_>>().join("  ")
@@ -1,6 +1,7 @@
-use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use criterion::{Criterion, criterion_group, criterion_main};
 use fk::lexer::Lexer;
 use fk::parser::Parser;
+use std::hint::black_box;
 
 const SIMPLE_PRINT: &str = r#"{ print $1 }"#;
 
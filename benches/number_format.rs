@@ -0,0 +1,77 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use fk::builtins;
+use std::hint::black_box;
+
+/// A million values: half integral counts, half fractional averages.
+fn make_values() -> Vec<f64> {
+    (0..1_000_000)
+        .map(|i| if i % 2 == 0 { i as f64 } else { i as f64 / 7.0 })
+        .collect()
+}
+
+/// The formatter format_number used before the stack-buffer writer.
+fn format_number_std(n: f64) -> String {
+    if n == (n as i64) as f64 {
+        (n as i64).to_string()
+    } else {
+        let s = format!("{:.6}", n);
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    }
+}
+
+fn bench_format_number(c: &mut Criterion) {
+    let values = make_values();
+    let mut group = c.benchmark_group("number_format/1M");
+    group.sample_size(10);
+    group.bench_function("format_std", |b| {
+        b.iter(|| {
+            for &n in &values {
+                black_box(format_number_std(black_box(n)));
+            }
+        })
+    });
+    group.bench_function("format_number", |b| {
+        b.iter(|| {
+            for &n in &values {
+                black_box(builtins::format_number(black_box(n)));
+            }
+        })
+    });
+    group.bench_function("write_number_reused_buffer", |b| {
+        let mut out = String::new();
+        b.iter(|| {
+            for &n in &values {
+                out.clear();
+                builtins::write_number(black_box(n), &mut out);
+                black_box(&out);
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_printf(c: &mut Criterion) {
+    let args: Vec<builtins::printf::Arg> = make_values()
+        .into_iter()
+        .take(100_000)
+        .map(|n| builtins::format_number(n).into())
+        .collect();
+    let mut group = c.benchmark_group("number_format/printf_100k");
+    group.sample_size(10);
+    for fmt in ["%.2f", "%g", "%d"] {
+        group.bench_function(fmt, |b| {
+            b.iter(|| {
+                for a in &args {
+                    black_box(builtins::printf::format_printf(
+                        fmt,
+                        std::slice::from_ref(a),
+                    ));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_format_number, bench_printf);
+criterion_main!(benches);
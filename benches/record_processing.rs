@@ -1,9 +1,13 @@
-use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
 use fk::action::Executor;
-use fk::input::Record;
+use fk::input::csv::CsvReader;
+use fk::input::{Record, RecordReader};
 use fk::lexer::Lexer;
+use fk::optimize;
 use fk::parser::Parser;
 use fk::runtime::Runtime;
+use std::hint::black_box;
+use std::io::Cursor;
 
 fn compile(src: &str) -> fk::parser::Program {
     let mut lex = Lexer::new(src);
@@ -30,6 +34,7 @@ fn bench_simple_print(c: &mut Criterion) {
                 let rec = Record {
                     text: line.clone(),
                     fields: None,
+                    malformed: None,
                 };
                 exec.run_record(black_box(&rec));
             }
@@ -50,6 +55,7 @@ fn bench_field_access(c: &mut Criterion) {
                 let rec = Record {
                     text: line.clone(),
                     fields: None,
+                    malformed: None,
                 };
                 exec.run_record(black_box(&rec));
             }
@@ -70,6 +76,7 @@ fn bench_pattern_match(c: &mut Criterion) {
                 let rec = Record {
                     text: line.clone(),
                     fields: None,
+                    malformed: None,
                 };
                 exec.run_record(black_box(&rec));
             }
@@ -78,6 +85,22 @@ fn bench_pattern_match(c: &mut Criterion) {
     });
 }
 
+fn bench_three_rules(c: &mut Criterion) {
+    let program = compile("/field_4/ { a++ }\n/extra$/ { b++ }\n$1 > 500 { c++ }");
+    let lines = make_lines(10_000);
+    c.bench_function("record/three_rules_10k", |b| {
+        b.iter(|| {
+            let mut rt = Runtime::new();
+            let mut exec = Executor::new(&program, &mut rt);
+            let mut rec = Record::default();
+            for line in &lines {
+                rec.text.clone_from(line);
+                exec.run_record(black_box(&rec));
+            }
+        })
+    });
+}
+
 fn bench_accumulate(c: &mut Criterion) {
     let program = compile("{ sum += $1; count++ } END { avg = sum / count }");
     let mut group = c.benchmark_group("record/accumulate");
@@ -92,6 +115,7 @@ fn bench_accumulate(c: &mut Criterion) {
                     let rec = Record {
                         text: line.clone(),
                         fields: None,
+                        malformed: None,
                     };
                     exec.run_record(black_box(&rec));
                 }
@@ -114,6 +138,7 @@ fn bench_computed_regex(c: &mut Criterion) {
                 let rec = Record {
                     text: line.clone(),
                     fields: None,
+                    malformed: None,
                 };
                 exec.run_record(black_box(&rec));
             }
@@ -134,6 +159,7 @@ fn bench_do_while_break(c: &mut Criterion) {
                 let rec = Record {
                     text: line.clone(),
                     fields: None,
+                    malformed: None,
                 };
                 exec.run_record(black_box(&rec));
             }
@@ -154,6 +180,7 @@ fn bench_multidim_array(c: &mut Criterion) {
                 let rec = Record {
                     text: line.clone(),
                     fields: None,
+                    malformed: None,
                 };
                 exec.run_record(black_box(&rec));
             }
@@ -174,6 +201,7 @@ fn bench_match_capture(c: &mut Criterion) {
                 let rec = Record {
                     text: line.clone(),
                     fields: None,
+                    malformed: None,
                 };
                 exec.run_record(black_box(&rec));
             }
@@ -195,6 +223,7 @@ fn bench_string_builtins(c: &mut Criterion) {
                 let rec = Record {
                     text: line.clone(),
                     fields: None,
+                    malformed: None,
                 };
                 exec.run_record(black_box(&rec));
             }
@@ -215,6 +244,7 @@ fn bench_math_builtins(c: &mut Criterion) {
                 let rec = Record {
                     text: line.clone(),
                     fields: None,
+                    malformed: None,
                 };
                 exec.run_record(black_box(&rec));
             }
@@ -223,17 +253,142 @@ fn bench_math_builtins(c: &mut Criterion) {
     });
 }
 
+fn bench_wide_csv(c: &mut Criterion) {
+    let program = compile("{ x = $1 + $60 }");
+    let data: String = (0..1000)
+        .map(|i| {
+            let row: Vec<String> = (0..60).map(|c| format!("{}", i * 60 + c)).collect();
+            row.join(",") + "\n"
+        })
+        .collect();
+    let mut group = c.benchmark_group("record/wide_csv_60x1k");
+    group.bench_function("next_record", |b| {
+        b.iter(|| {
+            let mut rt = Runtime::new();
+            let mut exec = Executor::new(&program, &mut rt);
+            let mut reader = CsvReader::comma();
+            let mut cursor = Cursor::new(data.as_bytes());
+            while let Some(rec) = reader.next_record(&mut cursor).unwrap() {
+                exec.run_record(black_box(&rec));
+            }
+        })
+    });
+    group.bench_function("read_into", |b| {
+        b.iter(|| {
+            let mut rt = Runtime::new();
+            let mut exec = Executor::new(&program, &mut rt);
+            let mut reader = CsvReader::comma();
+            let mut cursor = Cursor::new(data.as_bytes());
+            let mut rec = Record::default();
+            while reader.read_into(&mut cursor, &mut rec).unwrap() {
+                exec.run_record(black_box(&rec));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_printf_heavy(c: &mut Criterion) {
+    let src = r#"{ s = sprintf("%-10s %8.2f %5d ms", $2, $1 * 3600 / 1000, $3 * (60 * 60)) }"#;
+    let plain = compile(src);
+    let mut folded = compile(src);
+    optimize::fold_constants(&mut folded);
+    let lines = make_lines(10_000);
+    let mut group = c.benchmark_group("record/printf_10k");
+    for (name, program) in [("as_parsed", &plain), ("folded", &folded)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut rt = Runtime::new();
+                let mut exec = Executor::new(program, &mut rt);
+                let mut rec = Record::default();
+                for line in &lines {
+                    rec.text.clone_from(line);
+                    exec.run_record(black_box(&rec));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_gsub_wide(c: &mut Criterion) {
+    let lines: Vec<String> = (0..1000)
+        .map(|i| {
+            let row: Vec<String> = (0..100).map(|c| format!("v{}_{}", i, c)).collect();
+            row.join(" ")
+        })
+        .collect();
+    let mut group = c.benchmark_group("record/gsub_wide_100x1k");
+    for (name, src) in [
+        ("print", r#"{ gsub(/_/, "-"); print > "/dev/null" }"#),
+        ("print_nf", r#"{ gsub(/_/, "-"); print NF > "/dev/null" }"#),
+    ] {
+        let program = compile(src);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut rt = Runtime::new();
+                let mut exec = Executor::new(&program, &mut rt);
+                let mut rec = Record::default();
+                for line in &lines {
+                    rec.text.clone_from(line);
+                    exec.run_record(black_box(&rec));
+                }
+                exec.run_end();
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_print_throughput(c: &mut Criterion) {
+    const RECORDS: usize = 10_000_000;
+    let lines = make_lines(1000);
+    let mut group = c.benchmark_group("record/print_10m");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(RECORDS as u64));
+    for (name, src) in [
+        ("record", "{ print }"),
+        ("fields", "{ print $1, $3 }"),
+        (
+            "ofs_ors",
+            r#"BEGIN { OFS = "→"; ORS = "¶\n" } { print $2, $1 * 2 }"#,
+        ),
+    ] {
+        let program = compile(src);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut rt = Runtime::new();
+                let mut exec = Executor::new(&program, &mut rt);
+                exec.set_stdout(Box::new(std::io::sink()));
+                exec.run_begin();
+                let mut rec = Record::default();
+                for line in lines.iter().cycle().take(RECORDS) {
+                    rec.text.clone_from(line);
+                    exec.run_record(black_box(&rec));
+                }
+                exec.run_end();
+            })
+        });
+    }
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_simple_print,
     bench_field_access,
     bench_pattern_match,
+    bench_three_rules,
     bench_accumulate,
     bench_computed_regex,
     bench_do_while_break,
     bench_multidim_array,
     bench_match_capture,
     bench_string_builtins,
-    bench_math_builtins
+    bench_math_builtins,
+    bench_wide_csv,
+    bench_printf_heavy,
+    bench_gsub_wide,
+    bench_print_throughput
 );
 criterion_main!(benches);
@@ -0,0 +1,57 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use fk::builtins::string_replace;
+use regex::Regex;
+use std::hint::black_box;
+
+fn make_csv_line(n: usize) -> String {
+    (0..n)
+        .map(|i| format!("field{}", i))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The previous gsub: count the matches, then replace in a second scan.
+fn two_pass(s: &str, pattern: &str, replacement: &str) -> (String, usize) {
+    let count = s.matches(pattern).count();
+    (s.replace(pattern, replacement), count)
+}
+
+fn bench_gsub_comma(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gsub/comma_to_pipe");
+    let re = Regex::new(",").unwrap();
+    for &n in &[10, 100, 1000] {
+        let line = make_csv_line(n);
+        group.throughput(Throughput::Bytes(line.len() as u64));
+        group.bench_with_input(BenchmarkId::new("two_pass", n), &line, |b, line| {
+            b.iter(|| two_pass(black_box(line), ",", "|"))
+        });
+        group.bench_with_input(BenchmarkId::new("one_pass", n), &line, |b, line| {
+            b.iter(|| string_replace(black_box(line), ",", "|", true))
+        });
+        group.bench_with_input(BenchmarkId::new("regex", n), &line, |b, line| {
+            b.iter(|| {
+                let count = re.find_iter(black_box(line)).count();
+                (re.replace_all(line, "|").into_owned(), count)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_gsub_multichar(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gsub/multi_char");
+    for &n in &[10, 100, 1000] {
+        let line = make_csv_line(n).replace(',', "::");
+        group.throughput(Throughput::Bytes(line.len() as u64));
+        group.bench_with_input(BenchmarkId::new("two_pass", n), &line, |b, line| {
+            b.iter(|| two_pass(black_box(line), "::", "|"))
+        });
+        group.bench_with_input(BenchmarkId::new("one_pass", n), &line, |b, line| {
+            b.iter(|| string_replace(black_box(line), "::", "|", true))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_gsub_comma, bench_gsub_multichar);
+criterion_main!(benches);
@@ -0,0 +1,31 @@
+//! Records the git commit for `fk --version` as FK_GIT_COMMIT. Builds from
+//! a source tarball (no .git or no git) simply leave it unset.
+
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    let head = Path::new(".git/HEAD");
+    if head.exists() {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        if let Ok(text) = std::fs::read_to_string(head)
+            && let Some(r) = text.trim().strip_prefix("ref: ")
+            && Path::new(".git").join(r).exists()
+        {
+            println!("cargo:rerun-if-changed=.git/{r}");
+        }
+    } else {
+        println!("cargo:rerun-if-changed=build.rs");
+    }
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| String::from_utf8(o.stdout).ok());
+    if let Some(commit) = commit.map(|c| c.trim().to_string())
+        && !commit.is_empty()
+    {
+        println!("cargo:rustc-env=FK_GIT_COMMIT={commit}");
+    }
+}
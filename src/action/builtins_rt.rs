@@ -1,12 +1,19 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::io::{BufRead, Read, Write};
-use std::process::{Command, Stdio};
-use std::time::Instant;
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
 
 use crate::analyze::build_array_description;
-use crate::builtins::{self, string_replace};
-use crate::parser::Expr;
+use crate::builtins::{self, string_replace, width};
+use crate::parser::{self, Expr, Redirect};
 use crate::runtime::{ArrayMeta, Value};
 
+use super::hll::{DEFAULT_PRECISION, Hll};
+use super::pipe::{PipeRead, PipeReader};
+use super::sort::{self, smart_sort_keys};
+use super::stmt::{StdStream, is_stdin_name, std_stream};
+use super::topk::{SpaceSaving, capacity_for};
 use super::{Executor, percentile_sorted};
 
 impl<'a> Executor<'a> {
@@ -33,7 +40,7 @@ impl<'a> Executor<'a> {
             other => {
                 let val = self.eval_string(other);
                 if val.is_empty() {
-                    eprintln!("fk: {}: argument must be an array", caller);
+                    warn!(self, "{}: argument must be an array", caller);
                     return None;
                 }
                 val
@@ -44,7 +51,7 @@ impl<'a> Executor<'a> {
             if let Expr::Var(_) = expr {
                 return Some(name);
             }
-            eprintln!("fk: {}: '{}' is not an array", caller, name);
+            warn!(self, "{}: '{}' is not an array", caller, name);
             return None;
         }
         Some(name)
@@ -53,7 +60,7 @@ impl<'a> Executor<'a> {
     /// sub/gsub: these need runtime access to modify lvalues.
     pub(crate) fn builtin_sub(&mut self, args: &[Expr], global: bool) -> Value {
         if args.len() < 2 {
-            eprintln!("fk: sub/gsub requires at least 2 arguments");
+            warn!(self, "sub/gsub requires at least 2 arguments");
             return Value::from_number(0.0);
         }
         let pattern = self.extract_regex_or_eval(&args[0]);
@@ -65,9 +72,20 @@ impl<'a> Executor<'a> {
             Expr::Field(Box::new(Expr::NumberLit(0.0)))
         };
 
-        let target_val = self.eval_lvalue(&target_expr).into_string();
-        let (new_val, count) = string_replace(&target_val, &pattern, &replacement, global);
-        self.assign_to(&target_expr, Value::from_string(new_val));
+        let place = self.place(&target_expr);
+        let target_val = self.place_value(&place).into_string();
+        // Only a pattern with no metacharacters takes the plain string
+        // search; anything else is a regex.
+        let (new_val, count) =
+            if !self.rt.ignore_case() && builtins::string::is_literal_pattern(&pattern) {
+                string_replace(&target_val, &pattern, &replacement, global)
+            } else if self.ensure_regex(&pattern) {
+                let re = &self.regex_cache[&pattern];
+                builtins::string::regex_replace(&target_val, re, &replacement, global)
+            } else {
+                return Value::from_number(0.0);
+            };
+        self.store(place, Value::from_string(new_val));
 
         Value::from_number(count as f64)
     }
@@ -75,7 +93,7 @@ impl<'a> Executor<'a> {
     /// match(string, regex [, arr]) — find regex in string, optionally capture groups.
     pub(crate) fn builtin_match(&mut self, args: &[Expr]) -> Value {
         if args.len() < 2 {
-            eprintln!("fk: match requires at least 2 arguments");
+            warn!(self, "match requires at least 2 arguments");
             return Value::from_number(0.0);
         }
         let s = self.eval_string(&args[0]);
@@ -99,10 +117,10 @@ impl<'a> Executor<'a> {
         let re = &self.regex_cache[&pattern];
         if let Some(caps) = re.captures(&s) {
             let full = caps.get(0).unwrap();
-            let rstart = s[..full.start()].chars().count() as f64 + 1.0;
-            let rlength = full.as_str().chars().count() as f64;
+            let (start, len) = char_span(&s, &full);
+            let rstart = start as f64;
             self.rt.set_value("RSTART", Value::from_number(rstart));
-            self.rt.set_value("RLENGTH", Value::from_number(rlength));
+            self.rt.set_value("RLENGTH", Value::from_number(len as f64));
 
             if let Some(arr_name) = capture_arr {
                 self.rt.delete_array_all(&arr_name);
@@ -127,6 +145,50 @@ impl<'a> Executor<'a> {
         }
     }
 
+    /// matchall(string, regex, arr) — every non-overlapping match: arr[i] is
+    /// the text, arr[i,"start"] / arr[i,"length"] its char position, and
+    /// arr[i,j] capture group j. Returns the number of matches.
+    pub(crate) fn builtin_matchall(&mut self, args: &[Expr]) -> Value {
+        if args.len() < 3 {
+            warn!(self, "matchall requires 3 arguments (string, regex, array)");
+            return Value::from_number(0.0);
+        }
+        let s = self.eval_string(&args[0]);
+        let pattern = self.extract_regex_or_eval(&args[1]);
+        let arr_name = match &args[2] {
+            Expr::Var(name) => name.clone(),
+            _ => {
+                warn!(self, "matchall: third argument must be an array name");
+                return Value::from_number(0.0);
+            }
+        };
+        self.rt.delete_array_all(&arr_name);
+        if !self.ensure_regex(&pattern) {
+            return Value::from_number(0.0);
+        }
+
+        let subsep = self.rt.get_var("SUBSEP");
+        let re = &self.regex_cache[&pattern];
+        let mut n = 0;
+        for caps in re.captures_iter(&s) {
+            n += 1;
+            let full = caps.get(0).unwrap();
+            let (start, len) = char_span(&s, &full);
+            self.rt.set_array(&arr_name, &n.to_string(), full.as_str());
+            let key = |k: &str| format!("{n}{subsep}{k}");
+            let (start, len) = (start as f64, len as f64);
+            self.rt
+                .set_array_value(&arr_name, &key("start"), Value::from_number(start));
+            self.rt
+                .set_array_value(&arr_name, &key("length"), Value::from_number(len));
+            for j in 1..caps.len() {
+                let text = caps.get(j).map_or("", |m| m.as_str());
+                self.rt.set_array(&arr_name, &key(&j.to_string()), text);
+            }
+        }
+        Value::from_number(n as f64)
+    }
+
     /// jpath(json, path, array) — extract JSON value into an awk array.
     pub(crate) fn builtin_jpath_extract(&mut self, args: &[Expr]) -> Value {
         let json_str = self.eval_string(&args[0]);
@@ -134,7 +196,7 @@ impl<'a> Executor<'a> {
         let array_name = match &args[2] {
             Expr::Var(name) => name.clone(),
             _ => {
-                eprintln!("fk: jpath: third argument must be an array name");
+                warn!(self, "jpath: third argument must be an array name");
                 return Value::from_number(0.0);
             }
         };
@@ -146,17 +208,43 @@ impl<'a> Executor<'a> {
         Value::from_number(pairs.len() as f64)
     }
 
+    /// jpretty(json [, indent]) / jmin(json) — lay JSON out again with
+    /// `indent` spaces per level (default 2), or minified. Text that does
+    /// not parse comes back unchanged, with one warning per run.
+    pub(crate) fn builtin_jformat(&mut self, name: &str, args: &[Expr]) -> Value {
+        let Some(first) = args.first() else {
+            warn!(self, "{} requires a JSON argument", name);
+            return Value::default();
+        };
+        let text = self.eval_string(first);
+        let indent = match args.get(1) {
+            Some(e) if name == "jpretty" => self.eval_number(e).clamp(0.0, 16.0) as usize,
+            _ if name == "jpretty" => 2,
+            _ => 0,
+        };
+        match builtins::json::reformat(&text, indent) {
+            Some(out) => Value::from_string(out),
+            None => {
+                if !self.json_warned {
+                    self.json_warned = true;
+                    warn!(self, "{}: not valid JSON, returned unchanged", name);
+                }
+                Value::from_string(text)
+            }
+        }
+    }
+
     /// split(string, array [, separator]) — returns element count.
     pub(crate) fn builtin_split(&mut self, args: &[Expr]) -> Value {
         if args.len() < 2 {
-            eprintln!("fk: split requires at least 2 arguments");
+            warn!(self, "split requires at least 2 arguments");
             return Value::from_number(0.0);
         }
         let s = self.eval_string(&args[0]);
         let array_name = match &args[1] {
             Expr::Var(name) => name.clone(),
             _ => {
-                eprintln!("fk: split: second argument must be an array name");
+                warn!(self, "split: second argument must be an array name");
                 return Value::from_number(0.0);
             }
         };
@@ -169,24 +257,102 @@ impl<'a> Executor<'a> {
         let parts = crate::field::split(&s, &fs);
         self.rt.delete_array_all(&array_name);
         for (i, part) in parts.iter().enumerate() {
-            self.rt.set_array(&array_name, &format!("{}", i + 1), part);
+            self.rt
+                .set_array_strnum(&array_name, &format!("{}", i + 1), part);
         }
         Value::from_number(parts.len() as f64)
     }
 
-    /// fflush([file]) — flush stdout or a named output file.
+    /// The separator argument at `i` of split-like builtins, FS without it.
+    fn split_sep(&mut self, args: &[Expr], i: usize) -> String {
+        match args.get(i) {
+            Some(e) => self.extract_regex_or_eval(e),
+            None => self.rt.get_var("FS"),
+        }
+    }
+
+    /// uniqsplit(string, array [, separator]) — split like split(), keeping
+    /// only the first of repeated values. Returns the unique count.
+    pub(crate) fn builtin_uniqsplit(&mut self, args: &[Expr]) -> Value {
+        if args.len() < 2 {
+            warn!(self, "uniqsplit requires at least 2 arguments");
+            return Value::from_number(0.0);
+        }
+        let s = self.eval_string(&args[0]);
+        let array_name = match &args[1] {
+            Expr::Var(name) => name.clone(),
+            _ => {
+                warn!(self, "uniqsplit: second argument must be an array name");
+                return Value::from_number(0.0);
+            }
+        };
+        let fs = self.split_sep(args, 2);
+        let parts = unique_parts(&s, &fs);
+        self.rt.delete_array_all(&array_name);
+        for (i, part) in parts.iter().enumerate() {
+            self.rt.set_array(&array_name, &(i + 1).to_string(), part);
+        }
+        Value::from_number(parts.len() as f64)
+    }
+
+    /// joinuniq(string [, separator [, joiner]]) — the string's unique
+    /// values in first-seen order, joined by `joiner` (default: the
+    /// separator; a space for the default whitespace splitting).
+    pub(crate) fn builtin_joinuniq(&mut self, args: &[Expr]) -> Value {
+        let Some(first) = args.first() else {
+            warn!(self, "joinuniq requires at least 1 argument");
+            return Value::default();
+        };
+        let s = self.eval_string(first);
+        let fs = self.split_sep(args, 1);
+        let joiner = match args.get(2) {
+            Some(e) => self.eval_string(e),
+            None => fs.clone(),
+        };
+        Value::from_string(unique_parts(&s, &fs).join(&joiner))
+    }
+
+    /// fflush([name]) — flush a named output file or pipe, or with no name
+    /// (or "") everything; 0 on success, -1 if a flush failed.
     pub(crate) fn builtin_fflush(&mut self, args: &[Expr]) -> Value {
-        if args.is_empty() {
-            let _ = self.stdout.flush();
+        let path = match args.first() {
+            Some(arg) => self.eval_string(arg),
+            None => String::new(),
+        };
+        // fflush() and fflush("") flush stdout and every open file and pipe.
+        let ok = if path.is_empty() {
+            self.flush_all()
+        } else if std_stream(&path) == Some(StdStream::Stdout) {
+            self.stdout.flush().is_ok()
+        } else if let Some(file) = self.output_files.get_mut(&path) {
+            file.flush().is_ok()
+        } else if let Some(child) = self.output_pipes.get_mut(&path) {
+            let stdin = child.stdin.as_mut();
+            stdin.is_none_or(|stdin| stdin.flush().is_ok())
         } else {
-            let path = self.eval_string(&args[0]);
-            if path.is_empty() {
-                let _ = self.stdout.flush();
-            } else if let Some(file) = self.output_files.get_mut(&path) {
-                let _ = file.flush();
+            true
+        };
+        Value::from_number(if ok { 0.0 } else { -1.0 })
+    }
+
+    /// flushmode(["line"|"block"]) — flush stdout after every print, or
+    /// buffer it; returns the mode that was in force.
+    pub(crate) fn builtin_flushmode(&mut self, args: &[Expr]) -> Value {
+        let old = if self.line_buffered { "line" } else { "block" };
+        if let Some(arg) = args.first() {
+            match self.eval_string(arg).as_str() {
+                "line" => {
+                    self.line_buffered = true;
+                    let _ = self.stdout.flush();
+                }
+                "block" => self.line_buffered = false,
+                other => warn!(
+                    self,
+                    "flushmode: unknown mode \"{}\" (line or block)", other
+                ),
             }
         }
-        Value::from_number(0.0)
+        Value::from_str_ref(old)
     }
 
     /// system(cmd) — run a shell command, return its exit status.
@@ -194,45 +360,239 @@ impl<'a> Executor<'a> {
         if args.is_empty() {
             return Value::from_number(-1.0);
         }
-        let _ = self.stdout.flush();
+        // Whatever was printed so far, to any destination, lands before
+        // the command runs.
+        self.flush_all();
         let cmd = self.eval_string(&args[0]);
         match Command::new("sh").arg("-c").arg(&cmd).status() {
             Ok(status) => Value::from_number(status.code().unwrap_or(-1) as f64),
-            Err(_) => Value::from_number(-1.0),
+            Err(e) => {
+                self.set_errno(e);
+                Value::from_number(-1.0)
+            }
         }
     }
 
+    /// system_bg(cmd) — start a shell command without waiting for it;
+    /// returns a handle for wait(), or -1 if it could not be started.
+    pub(crate) fn builtin_system_bg(&mut self, args: &[Expr]) -> Value {
+        if args.is_empty() {
+            warn!(self, "system_bg requires 1 argument (command)");
+            return Value::from_number(-1.0);
+        }
+        self.flush_all();
+        let cmd = self.eval_string(&args[0]);
+        match Command::new("sh").arg("-c").arg(&cmd).spawn() {
+            Ok(child) => {
+                self.bg_next += 1;
+                self.bg_jobs.insert(self.bg_next, child);
+                Value::from_number(self.bg_next as f64)
+            }
+            Err(e) => {
+                self.set_errno(e);
+                Value::from_number(-1.0)
+            }
+        }
+    }
+
+    /// wait(handle) — block until a system_bg() command finishes; returns
+    /// its exit status, or -1 for an unknown (or already waited) handle.
+    pub(crate) fn builtin_wait(&mut self, args: &[Expr]) -> Value {
+        if args.is_empty() {
+            warn!(self, "wait requires 1 argument (handle from system_bg)");
+            return Value::from_number(-1.0);
+        }
+        let handle = self.eval_expr(&args[0]).to_number();
+        let Some(child) = self.bg_jobs.remove(&(handle as u64)) else {
+            warn!(self, "wait: no background command {}", handle);
+            return Value::from_number(-1.0);
+        };
+        Value::from_number(self.wait_bg(child) as f64)
+    }
+
+    /// waitall() — wait for every outstanding system_bg() command; returns
+    /// how many exited with a non-zero status.
+    pub(crate) fn builtin_waitall(&mut self) -> Value {
+        Value::from_number(self.wait_all_bg() as f64)
+    }
+
+    /// Wait for the background commands in the order they were started;
+    /// returns how many failed.
+    pub(crate) fn wait_all_bg(&mut self) -> usize {
+        let mut jobs: Vec<_> = self.bg_jobs.drain().collect();
+        jobs.sort_by_key(|(handle, _)| *handle);
+        let mut failed = 0;
+        for (_, child) in jobs {
+            if self.wait_bg(child) != 0 {
+                failed += 1;
+            }
+        }
+        failed
+    }
+
+    fn wait_bg(&mut self, mut child: Child) -> i32 {
+        match child.wait() {
+            Ok(status) => status.code().unwrap_or(-1),
+            Err(e) => {
+                self.set_errno(e);
+                -1
+            }
+        }
+    }
+
+    /// Record why an I/O operation failed in ERRNO, as gawk does: "No such
+    /// file or directory", without Rust's " (os error 2)". Success leaves
+    /// ERRNO alone.
+    pub(crate) fn set_errno(&mut self, err: impl std::fmt::Display) {
+        let text = err.to_string();
+        let text = text.split(" (os error ").next().unwrap_or_default();
+        self.rt.set_var("ERRNO", text);
+    }
+
     /// close(name) — close a file or pipe (output or input) by name.
     pub(crate) fn builtin_close(&mut self, args: &[Expr]) -> Value {
         if args.is_empty() {
             return Value::from_number(-1.0);
         }
         let name = self.eval_string(&args[0]);
-        if let Some(file) = self.output_files.remove(&name) {
-            drop(file);
+        // gawk's close(cmd, "to"|"from") half-closes a `|&` coprocess. fk
+        // has none, so like gawk on any other redirection the whole thing
+        // is closed.
+        if let Some(how) = args.get(1) {
+            let how = self.eval_string(how);
+            if !how.eq_ignore_ascii_case("to") && !how.eq_ignore_ascii_case("from") {
+                warn!(self, "close: second argument must be \"to\" or \"from\"");
+                return Value::from_number(-1.0);
+            }
+        }
+        if std_stream(&name).is_some() || is_stdin_name(&name) {
             return Value::from_number(0.0);
         }
-        if let Some(mut child) = self.output_pipes.remove(&name) {
+        // Files close with 0, commands with their exit status; -1 if `name`
+        // was never opened. A later getline or print opens it afresh.
+        let status = if let Some(file) = self.output_files.remove(&name) {
+            drop(file);
+            0
+        } else if let Some(mut child) = self.output_pipes.remove(&name) {
+            self.pipe_used.remove(&name);
             drop(child.stdin.take());
-            let _ = child.wait();
-            return Value::from_number(0.0);
+            match child.wait() {
+                Ok(status) => status.code().unwrap_or(-1),
+                Err(e) => {
+                    self.set_errno(e);
+                    -1
+                }
+            }
+        } else if self.input_files.remove(&name).is_some() {
+            0
+        } else if let Some(pipe) = self.input_pipes.remove(&name) {
+            pipe.close()
+        } else {
+            self.set_errno("close of redirection that was never opened");
+            return Value::from_number(-1.0);
+        };
+        self.sync_stream_info(&name);
+        Value::from_number(status as f64)
+    }
+
+    /// outfile(path) — create path's missing parent directories, return path.
+    /// Meant for `print > outfile(dir "/" $1 ".csv")`; only checks the
+    /// filesystem until the file is open.
+    pub(crate) fn builtin_outfile(&mut self, args: &[Expr]) -> Value {
+        if args.len() != 1 {
+            warn!(self, "outfile requires 1 argument (path)");
+            return Value::default();
         }
-        if self.input_files.remove(&name).is_some() {
+        let path = self.eval_string(&args[0]);
+        if !self.output_files.contains_key(&path)
+            && let Some(dir) = std::path::Path::new(&path).parent()
+            && !dir.as_os_str().is_empty()
+            && !dir.is_dir()
+            && let Err(e) = std::fs::create_dir_all(dir)
+        {
+            warn!(self, "outfile: cannot create '{}': {}", dir.display(), e);
+        }
+        Value::from_string(path)
+    }
+
+    /// writecsv(arr, path) — append arr's values (in key order) to path as
+    /// one quoted CSV row, sharing the handle with `print >> path`.
+    /// Returns the number of fields written.
+    pub(crate) fn builtin_writecsv(&mut self, args: &[Expr]) -> Value {
+        if args.len() != 2 {
+            warn!(self, "writecsv requires 2 arguments (array, path)");
             return Value::from_number(0.0);
         }
-        if self.input_pipe_readers.remove(&name).is_some() {
-            if let Some(mut child) = self.input_pipe_children.remove(&name) {
-                let _ = child.wait();
-            }
+        let Some(array_name) = self.resolve_array_arg(&args[0], "writecsv") else {
+            return Value::from_number(0.0);
+        };
+        let path = self.eval_string(&args[1]);
+        if path.is_empty() {
+            warn!(self, "writecsv: empty path");
             return Value::from_number(0.0);
         }
-        Value::from_number(-1.0)
+        let mut keys = self.rt.array_keys(&array_name);
+        smart_sort_keys(&mut keys);
+        let cells: Vec<String> = keys
+            .iter()
+            .map(|k| self.rt.get_array(&array_name, k))
+            .collect();
+        let mut line = crate::output::csv_row(&cells);
+        line.push('\n');
+        self.write_output(
+            line.as_bytes(),
+            &Some(Redirect::Append(Expr::StringLit(path))),
+        );
+        Value::from_number(cells.len() as f64)
+    }
+
+    /// header([sep]) — write the HDR names as one line of standard output,
+    /// joined with `sep` (default OFS; the CSV delimiter for CSV input
+    /// while OFS is a space) and quoted as needed when the input is CSV.
+    /// Under `-o tsv` it is the TSV header. Returns the column count.
+    pub(crate) fn builtin_header(&mut self, args: &[Expr]) -> Value {
+        let sep = args.first().map(|e| self.eval_string(e));
+        Value::from_number(self.write_header(sep) as f64)
+    }
+
+    /// Write the header line for `header()` and `--emit-header`. Nothing
+    /// is written without HDR, or under `-o table`/`md`/`json`, which
+    /// name their columns themselves.
+    pub(crate) fn write_header(&mut self, sep: Option<String>) -> usize {
+        let names: Vec<String> = (1..=self.header_len)
+            .map(|i| self.rt.get_array("HDR", &i.to_string()))
+            .collect();
+        if names.is_empty() {
+            return 0;
+        }
+        self.emit_header = false;
+        let mut line = if self.tsv_rows {
+            if std::mem::replace(&mut self.tsv_header_done, true) {
+                return names.len();
+            }
+            crate::output::tsv_row(&names)
+        } else if self.table.is_some() || self.json_rows {
+            return names.len();
+        } else if self.csv_output {
+            // Records printed whole keep the input's delimiter; a set
+            // OFS is what `print $a, $b` writes between fields.
+            let sep = sep.unwrap_or_else(|| match self.rt.ofs() {
+                " " => self.rt.csv_dialect().delimiter.unwrap_or(',').to_string(),
+                ofs => ofs.to_string(),
+            });
+            crate::output::csv_row_with(&names, &sep)
+        } else {
+            names.join(sep.as_deref().unwrap_or(self.rt.ofs()))
+        };
+        line.push_str(self.rt.ors());
+        let _ = self.stdout.write_all(line.as_bytes());
+        names.len()
     }
 
     /// gensub(regex, replacement, how [, target]) — like gsub but returns result.
     pub(crate) fn builtin_gensub(&mut self, args: &[Expr]) -> Value {
         if args.len() < 3 {
-            eprintln!("fk: gensub requires at least 3 arguments");
+            warn!(self, "gensub requires at least 3 arguments");
             return Value::default();
         }
         let pattern = self.extract_regex_or_eval(&args[0]);
@@ -245,11 +605,37 @@ impl<'a> Executor<'a> {
             self.rt.get_field(0)
         };
 
+        let global = how.starts_with('g') || how.starts_with('G');
+        // A pattern with no metacharacters (and a replacement with no `$`
+        // group references) needs no regex engine.
+        if !pattern.is_empty()
+            && !self.rt.ignore_case()
+            && builtins::string::is_literal_pattern(&pattern)
+            && !replacement.contains('$')
+        {
+            if global {
+                return Value::from_string(string_replace(&target, &pattern, &replacement, true).0);
+            }
+            let n: usize = how.parse().unwrap_or(1);
+            let hit = n
+                .checked_sub(1)
+                .and_then(|i| target.match_indices(pattern.as_str()).nth(i));
+            return match hit {
+                Some((pos, _)) => {
+                    let mut result = String::with_capacity(target.len());
+                    result.push_str(&target[..pos]);
+                    result.push_str(&replacement);
+                    result.push_str(&target[pos + pattern.len()..]);
+                    Value::from_string(result)
+                }
+                _ => Value::from_string(target),
+            };
+        }
+
         if !self.ensure_regex(&pattern) {
             return Value::from_string(target);
         }
 
-        let global = how.starts_with('g') || how.starts_with('G');
         let re = &self.regex_cache[&pattern];
         if global {
             Value::from_string(re.replace_all(&target, replacement.as_str()).to_string())
@@ -276,7 +662,10 @@ impl<'a> Executor<'a> {
     /// join(arr [, sep]) — join array values into a string. Defaults to OFS.
     pub(crate) fn builtin_join(&mut self, args: &[Expr]) -> Value {
         if args.is_empty() {
-            eprintln!("fk: join requires at least 1 argument (array [, separator])");
+            warn!(
+                self,
+                "join requires at least 1 argument (array [, separator])"
+            );
             return Value::default();
         }
         let array_name = match self.resolve_array_arg(&args[0], "join") {
@@ -306,31 +695,114 @@ impl<'a> Executor<'a> {
     /// keys(arr) — return sorted keys joined by ORS.
     pub(crate) fn builtin_keys(&mut self, args: &[Expr]) -> Value {
         if args.is_empty() {
-            eprintln!("fk: keys requires 1 argument (array)");
+            warn!(self, "keys requires 1 argument (array)");
             return Value::default();
         }
         let array_name = match self.resolve_array_arg(&args[0], "keys") {
             Some(n) => n,
             None => return Value::default(),
         };
+        let mode = self.sort_mode_arg(args.get(1), "keys");
         let mut keys = self.rt.array_keys(&array_name);
-        smart_sort_keys(&mut keys);
+        sort_keys_with(&mut keys, mode);
         let sep = self.rt.ors().to_owned();
         Value::from_string(keys.join(&sep))
     }
 
+    /// Keys of `array` in a for-in `@` order. Value orders read each element
+    /// once; ties fall back to smart key order so output is deterministic.
+    pub(crate) fn ordered_keys(&self, array: &str, mode: parser::SortMode) -> Vec<String> {
+        use parser::SortMode as M;
+        let by_number = |mut pairs: Vec<(String, f64)>, desc: bool| {
+            pairs.sort_by(|(ka, a), (kb, b)| {
+                let ord = a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal);
+                let ord = if desc { ord.reverse() } else { ord };
+                ord.then_with(|| smart_cmp(ka, kb))
+            });
+            pairs.into_iter().map(|(k, _)| k).collect()
+        };
+        let key_numbers = || {
+            self.rt
+                .array_keys(array)
+                .into_iter()
+                .map(|k| {
+                    let n = builtins::to_number(&k);
+                    (k, n)
+                })
+                .collect()
+        };
+        match mode {
+            M::Asc | M::Desc => {
+                let mut keys = self.rt.array_keys(array);
+                keys.sort();
+                if mode == M::Desc {
+                    keys.reverse();
+                }
+                keys
+            }
+            M::NumAsc => by_number(key_numbers(), false),
+            M::NumDesc => by_number(key_numbers(), true),
+            M::ValAsc => by_number(self.rt.array_key_numbers(array), false),
+            M::ValDesc => by_number(self.rt.array_key_numbers(array), true),
+            M::Unsorted => self.rt.array_keys(array),
+        }
+    }
+
+    /// sortkeys(arr, dest [, how]) — fill dest[1..N] with the keys of arr in
+    /// the order `how` names (a for-in modifier like "rval" or a gawk
+    /// sorted_in name like "@val_num_desc"; default: smart key order).
+    /// Returns N.
+    pub(crate) fn builtin_sortkeys(&mut self, args: &[Expr]) -> Value {
+        if args.len() < 2 || args.len() > 3 {
+            warn!(
+                self,
+                "sortkeys requires 2 or 3 arguments (array, dest [, how])"
+            );
+            return Value::from_number(0.0);
+        }
+        let Some(array_name) = self.resolve_array_arg(&args[0], "sortkeys") else {
+            return Value::from_number(0.0);
+        };
+        let Some(dest_name) = self.resolve_array_arg(&args[1], "sortkeys") else {
+            return Value::from_number(0.0);
+        };
+        let how = match args.get(2) {
+            Some(e) => self.eval_string(e),
+            None => String::new(),
+        };
+        let keys = if how.is_empty() {
+            let mut keys = self.rt.array_keys(&array_name);
+            smart_sort_keys(&mut keys);
+            keys
+        } else {
+            match parser::SortMode::from_name(&how) {
+                Some(mode) => self.ordered_keys(&array_name, mode),
+                None => {
+                    warn!(self, "sortkeys: unknown order '{}'", how);
+                    return Value::from_number(0.0);
+                }
+            }
+        };
+        self.rt.delete_array_all(&dest_name);
+        for (i, k) in keys.iter().enumerate() {
+            self.rt.set_array(&dest_name, &(i + 1).to_string(), k);
+        }
+        Value::from_number(keys.len() as f64)
+    }
+
     /// vals(arr) — return values joined by ORS, sorted by key.
     pub(crate) fn builtin_vals(&mut self, args: &[Expr]) -> Value {
         if args.is_empty() {
-            eprintln!("fk: vals requires 1 argument (array)");
+            warn!(self, "vals requires 1 argument (array)");
             return Value::default();
         }
         let array_name = match self.resolve_array_arg(&args[0], "vals") {
             Some(n) => n,
             None => return Value::default(),
         };
+        let mode = self.sort_mode_arg(args.get(1), "vals");
         let mut keys = self.rt.array_keys(&array_name);
-        smart_sort_keys(&mut keys);
+        sort_keys_with(&mut keys, mode);
         let vals: Vec<String> = keys
             .iter()
             .map(|k| self.rt.get_array(&array_name, k))
@@ -340,14 +812,17 @@ impl<'a> Executor<'a> {
     }
 
     /// Print array contents directly to stdout (used by `print arr`).
-    /// Sequential arrays (1..N) print values; associative arrays print keys.
+    /// Sequential arrays (1..N) print values; associative arrays print keys,
+    /// with SUBSEP shown as DUMPSEP.
     pub(crate) fn print_array(&mut self, name: &str) {
         let mut keys = self.rt.array_keys(name);
         if keys.is_empty() {
             return;
         }
         smart_sort_keys(&mut keys);
-        let sequential = is_sequential(&keys);
+        let subsep = self.rt.get_var("SUBSEP");
+        let sequential = is_sequential(&keys, &subsep);
+        let shown = self.dump_sep();
         let ors = self.rt.ors().to_owned();
         for (i, k) in keys.iter().enumerate() {
             if i > 0 {
@@ -357,7 +832,8 @@ impl<'a> Executor<'a> {
                 let v = self.rt.get_array(name, k);
                 let _ = self.stdout.write_all(v.as_bytes());
             } else {
-                let _ = self.stdout.write_all(k.as_bytes());
+                let key = show_key(k, &subsep, &shown);
+                let _ = self.stdout.write_all(key.as_bytes());
             }
         }
         let _ = self.stdout.write_all(ors.as_bytes());
@@ -386,6 +862,66 @@ impl<'a> Executor<'a> {
         Value::from_string(array_name)
     }
 
+    /// uniqc(arr, counts) — uniq() that also fills counts[value] with the
+    /// number of occurrences (counts is cleared first). Returns count kept.
+    pub(crate) fn builtin_uniqc(&mut self, args: &[Expr]) -> Value {
+        if args.len() != 2 {
+            warn!(self, "uniqc requires 2 arguments (array, counts)");
+            return Value::from_number(0.0);
+        }
+        let Some(array_name) = self.resolve_array_arg(&args[0], "uniqc") else {
+            return Value::from_number(0.0);
+        };
+        let Some(counts_name) = self.resolve_array_arg(&args[1], "uniqc") else {
+            return Value::from_number(0.0);
+        };
+        if counts_name == array_name {
+            warn!(self, "uniqc: counts must be a different array");
+            return Value::from_number(0.0);
+        }
+        let mut keys = self.rt.array_keys(&array_name);
+        smart_sort_keys(&mut keys);
+        let mut tally: HashMap<String, usize> = HashMap::new();
+        let mut unique: Vec<String> = Vec::new();
+        for k in &keys {
+            let v = self.rt.get_array(&array_name, k);
+            let n = tally.entry(v.clone()).or_insert(0);
+            if *n == 0 {
+                unique.push(v);
+            }
+            *n += 1;
+        }
+        self.rt.delete_array_all(&array_name);
+        self.rt.delete_array_all(&counts_name);
+        for (i, v) in unique.iter().enumerate() {
+            self.rt.set_array(&array_name, &(i + 1).to_string(), v);
+            self.rt
+                .set_array_value(&counts_name, v, Value::from_number(tally[v] as f64));
+        }
+        Value::from_number(unique.len() as f64)
+    }
+
+    /// uniqkeys(arr) — drop keys that duplicate an earlier key ignoring case,
+    /// keeping the first in key order. Returns the remaining count.
+    pub(crate) fn builtin_uniqkeys(&mut self, args: &[Expr]) -> Value {
+        if args.len() != 1 {
+            warn!(self, "uniqkeys requires 1 argument (array)");
+            return Value::from_number(0.0);
+        }
+        let Some(array_name) = self.resolve_array_arg(&args[0], "uniqkeys") else {
+            return Value::from_number(0.0);
+        };
+        let mut keys = self.rt.array_keys(&array_name);
+        smart_sort_keys(&mut keys);
+        let mut seen = std::collections::HashSet::new();
+        for k in &keys {
+            if !seen.insert(k.to_lowercase()) {
+                self.rt.delete_array(&array_name, k);
+            }
+        }
+        Value::from_number(self.rt.array_len(&array_name) as f64)
+    }
+
     /// inv(arr) — swap keys and values in place. Returns array name.
     pub(crate) fn builtin_invert(&mut self, args: &[Expr]) -> Value {
         let array_name = match self.resolve_array_arg(&args[0], "inv") {
@@ -452,6 +988,45 @@ impl<'a> Executor<'a> {
         Value::from_string(self.rt.get_field(0))
     }
 
+    /// fields(arr [, from [, to]]) — copy $from..$to (default 1..NF) into
+    /// arr[1..], clamped to the record. Returns the number copied.
+    pub(crate) fn builtin_fields(&mut self, args: &[Expr]) -> Value {
+        if args.is_empty() {
+            warn!(self, "fields requires an array argument");
+            return Value::from_number(0.0);
+        }
+        let array_name = match self.resolve_array_arg(&args[0], "fields") {
+            Some(n) => n,
+            None => return Value::from_number(0.0),
+        };
+        let nf = self.rt.nf() as f64;
+        let from = match args.get(1) {
+            Some(e) => self.eval_expr(e).to_number().max(1.0) as usize,
+            None => 1,
+        };
+        let to = match args.get(2) {
+            Some(e) => self.eval_expr(e).to_number().min(nf) as usize,
+            None => nf as usize,
+        };
+        let copied = self.rt.fields_to_array(&array_name, from, to);
+        Value::from_number(copied as f64)
+    }
+
+    /// has(name) — 1 if HDR names a column `name` and this record reaches
+    /// it (its index is at most NF), else 0.
+    pub(crate) fn builtin_has(&mut self, args: &[Expr]) -> Value {
+        let name = match args.first() {
+            Some(e) => self.eval_string(e),
+            None => return Value::from_number(0.0),
+        };
+        let idx = self
+            .rt
+            .get_array_opt("HDR", &name)
+            .map_or(0.0, |i| crate::builtins::to_number(&i));
+        let present = idx >= 1.0 && idx <= self.rt.nf() as f64;
+        Value::from_number(if present { 1.0 } else { 0.0 })
+    }
+
     /// rev(arr) — reverse order, re-key 1..N. Returns array name.
     pub(crate) fn builtin_reverse_array(&mut self, args: &[Expr]) -> Value {
         let array_name = match self.resolve_array_arg(&args[0], "rev") {
@@ -475,7 +1050,7 @@ impl<'a> Executor<'a> {
     /// diff(a, b) — remove from a any key present in b. Returns array name.
     pub(crate) fn builtin_set_op(&mut self, op: &str, args: &[Expr]) -> Value {
         if args.len() < 2 {
-            eprintln!("fk: {} requires 2 arguments (array, array)", op);
+            warn!(self, "{} requires 2 arguments (array, array)", op);
             return Value::default();
         }
         let name_a = match self.resolve_array_arg(&args[0], op) {
@@ -515,6 +1090,156 @@ impl<'a> Executor<'a> {
         Value::from_string(name_a)
     }
 
+    /// copy(dst, src) — replace dst with a copy of src. Returns count.
+    pub(crate) fn builtin_copy(&mut self, args: &[Expr]) -> Value {
+        if args.len() < 2 {
+            warn!(self, "copy requires 2 arguments (dst, src)");
+            return Value::from_number(0.0);
+        }
+        let Some(dst) = self.resolve_array_arg(&args[0], "copy") else {
+            return Value::from_number(0.0);
+        };
+        let Some(src) = self.resolve_array_arg(&args[1], "copy") else {
+            return Value::from_number(0.0);
+        };
+        Value::from_number(self.rt.copy_array(&dst, &src) as f64)
+    }
+
+    /// aempty(arr) — 1 if arr has no elements. Never creates arr.
+    pub(crate) fn builtin_aempty(&mut self, args: &[Expr]) -> Value {
+        if args.len() != 1 {
+            warn!(self, "aempty requires 1 argument (array)");
+            return Value::from_number(0.0);
+        }
+        let Some(name) = self.resolve_array_arg(&args[0], "aempty") else {
+            return Value::from_number(0.0);
+        };
+        let empty = self.rt.array_len(&name) == 0;
+        Value::from_number(if empty { 1.0 } else { 0.0 })
+    }
+
+    /// amerge(dst, src [, clobber]) — copy src's elements into dst. Existing
+    /// keys are overwritten unless clobber is 0. Returns keys added/updated.
+    pub(crate) fn builtin_amerge(&mut self, args: &[Expr]) -> Value {
+        if args.len() < 2 || args.len() > 3 {
+            warn!(
+                self,
+                "amerge requires 2 or 3 arguments (dst, src [, clobber])"
+            );
+            return Value::from_number(0.0);
+        }
+        let Some(dst) = self.resolve_array_arg(&args[0], "amerge") else {
+            return Value::from_number(0.0);
+        };
+        let Some(src) = self.resolve_array_arg(&args[1], "amerge") else {
+            return Value::from_number(0.0);
+        };
+        let clobber = args.len() < 3 || self.eval_expr(&args[2]).to_number() != 0.0;
+        if dst == src {
+            return Value::from_number(0.0);
+        }
+        let mut n = 0;
+        for k in self.rt.array_keys(&src) {
+            if !clobber && self.rt.array_has_key(&dst, &k) {
+                continue;
+            }
+            let v = self.rt.get_array_value(&src, &k);
+            self.rt.set_array_value(&dst, &k, v);
+            n += 1;
+        }
+        Value::from_number(n as f64)
+    }
+
+    /// slice(src, dst, from, to [, keep_meta]) — copy the elements of src whose
+    /// keys fall in from..to (inclusive) into dst. A sequential array (keys
+    /// 1..N) is sliced by position: negative bounds count from the end (-1 is
+    /// the last element), dst is re-keyed 1..count, and keep_meta carries a
+    /// histogram's bin ranges over. Other arrays keep their keys, compared
+    /// numerically when numeric. Returns the element count copied.
+    pub(crate) fn builtin_slice(&mut self, args: &[Expr]) -> Value {
+        if args.len() < 4 || args.len() > 5 {
+            warn!(
+                self,
+                "slice requires 4 or 5 arguments (src, dst, from, to [, keep_meta])"
+            );
+            return Value::from_number(0.0);
+        }
+        let Some(src) = self.resolve_array_arg(&args[0], "slice") else {
+            return Value::from_number(0.0);
+        };
+        let Some(dst) = self.resolve_array_arg(&args[1], "slice") else {
+            return Value::from_number(0.0);
+        };
+        let from = self.eval_expr(&args[2]);
+        let to = self.eval_expr(&args[3]);
+        let keep_meta = args.len() == 5 && self.eval_expr(&args[4]).to_number() != 0.0;
+
+        let mut keys = self.rt.array_keys(&src);
+        smart_sort_keys(&mut keys);
+        let n = keys.len() as i64;
+        let mut meta = None;
+        let subsep = self.rt.get_var("SUBSEP");
+        let picked: Vec<(String, Value)> = if is_sequential(&keys, &subsep) {
+            let pos = |v: &Value| {
+                let i = v.to_number() as i64;
+                if i < 0 { n + 1 + i } else { i }
+            };
+            let (lo, hi) = (pos(&from).max(1), pos(&to).min(n));
+            if keep_meta
+                && lo <= hi
+                && let Some(ArrayMeta::Histogram {
+                    source,
+                    source_name,
+                    description,
+                    min,
+                    max,
+                    width,
+                    ..
+                }) = self.rt.get_meta(&src).cloned()
+            {
+                let max = if hi == n {
+                    max
+                } else {
+                    min + hi as f64 * width
+                };
+                meta = Some(ArrayMeta::Histogram {
+                    source,
+                    source_name,
+                    description,
+                    bins: (hi - lo + 1) as usize,
+                    min: min + (lo - 1) as f64 * width,
+                    max,
+                    width,
+                });
+            }
+            (lo..=hi)
+                .map(|i| {
+                    let v = self.rt.get_array_value(&src, &i.to_string());
+                    ((i - lo + 1).to_string(), v)
+                })
+                .collect()
+        } else {
+            let (from, to) = (from.to_string_val(), to.to_string_val());
+            keys.into_iter()
+                .filter(|k| smart_cmp(k, &from).is_ge() && smart_cmp(k, &to).is_le())
+                .map(|k| {
+                    let v = self.rt.get_array_value(&src, &k);
+                    (k, v)
+                })
+                .collect()
+        };
+
+        let count = picked.len();
+        self.rt.delete_array_all(&dst);
+        for (k, v) in picked {
+            self.rt.set_array_value(&dst, &k, v);
+        }
+        if let Some(m) = meta {
+            self.rt.set_meta(&dst, m);
+        }
+        Value::from_number(count as f64)
+    }
+
     /// seq(arr, from, to) — fill arr with from..to, keyed 1..N. Returns count.
     pub(crate) fn builtin_seq(&mut self, args: &[Expr]) -> Value {
         // 2-arg form: seq(from, to) → return values joined by ORS
@@ -538,13 +1263,16 @@ impl<'a> Executor<'a> {
             return Value::from_string(parts.join(&ors));
         }
         if args.len() < 3 {
-            eprintln!("fk: seq requires 2 or 3 arguments: seq(from, to) or seq(arr, from, to)");
+            warn!(
+                self,
+                "seq requires 2 or 3 arguments: seq(from, to) or seq(arr, from, to)"
+            );
             return Value::from_number(0.0);
         }
         let array_name = match &args[0] {
             Expr::Var(n) => n.clone(),
             _ => {
-                eprintln!("fk: seq: first argument must be an array name");
+                warn!(self, "seq: first argument must be an array name");
                 return Value::from_number(0.0);
             }
         };
@@ -572,13 +1300,13 @@ impl<'a> Executor<'a> {
     /// samp(arr, n) — keep n random elements, re-key 1..n. Returns n.
     pub(crate) fn builtin_sample(&mut self, args: &[Expr]) -> Value {
         if args.len() < 2 {
-            eprintln!("fk: samp requires 2 arguments (array, n)");
+            warn!(self, "samp requires 2 arguments (array, n)");
             return Value::from_number(0.0);
         }
         let array_name = match &args[0] {
             Expr::Var(n) => n.clone(),
             _ => {
-                eprintln!("fk: samp: first argument must be an array name");
+                warn!(self, "samp: first argument must be an array name");
                 return Value::from_number(0.0);
             }
         };
@@ -603,17 +1331,29 @@ impl<'a> Executor<'a> {
         Value::from_number(take as f64)
     }
 
+    /// sample(p) — 1 with probability p, drawn from the srand-controlled RNG,
+    /// so `sample(0.01) { ... }` is reproducible under a fixed seed.
+    pub(crate) fn builtin_sample_prob(&mut self, args: &[Expr]) -> Value {
+        let p = self.eval_expr(&args[0]).to_number();
+        if builtins::math::rng_next() < p {
+            self.count_sampled();
+            Value::from_number(1.0)
+        } else {
+            Value::from_number(0.0)
+        }
+    }
+
     /// collect(a, expr) — append expr to array a with auto-incrementing key.
     /// Skips NaN and empty-string values. Returns the new count.
     pub(crate) fn builtin_collect(&mut self, args: &[Expr]) -> Value {
         if args.len() < 2 {
-            eprintln!("fk: collect requires 2 arguments (array, expr)");
+            warn!(self, "collect requires 2 arguments (array, expr)");
             return Value::from_number(0.0);
         }
         let array_name = match &args[0] {
             Expr::Var(n) => n.clone(),
             _ => {
-                eprintln!("fk: collect: first argument must be an array name");
+                warn!(self, "collect: first argument must be an array name");
                 return Value::from_number(0.0);
             }
         };
@@ -632,12 +1372,246 @@ impl<'a> Executor<'a> {
         Value::from_number(next_key as f64)
     }
 
+    /// reservoir(arr, value, k) — uniform reservoir sample of size k into
+    /// arr[1..k] (Algorithm R). Returns the number of items seen so far.
+    /// Clearing arr starts a new sample.
+    pub(crate) fn builtin_reservoir(&mut self, args: &[Expr]) -> Value {
+        if args.len() < 3 {
+            warn!(self, "reservoir requires 3 arguments (array, value, k)");
+            return Value::from_number(0.0);
+        }
+        let array_name = match &args[0] {
+            Expr::Var(n) => n.clone(),
+            _ => {
+                warn!(self, "reservoir: first argument must be an array name");
+                return Value::from_number(0.0);
+            }
+        };
+        let val = self.eval_expr(&args[1]);
+        let k = self.eval_expr(&args[2]).to_number().max(0.0) as u64;
+        let filled = self.rt.array_len(&array_name) as u64;
+        let seen = self.reservoir_seen.entry(array_name.clone()).or_insert(0);
+        if filled == 0 && k > 0 {
+            *seen = 0;
+        }
+        *seen += 1;
+        let seen = *seen;
+        let slot = if seen <= k {
+            Some(seen)
+        } else {
+            let j = (builtins::math::rng_next() * seen as f64) as u64;
+            (j < k).then_some(j + 1)
+        };
+        if let Some(slot) = slot {
+            self.rt.set_array_value(&array_name, &slot.to_string(), val);
+        }
+        Value::from_number(seen as f64)
+    }
+
+    /// topk(state, key, value, k) — add value to key's counter in a bounded
+    /// SpaceSaving summary named state (not an array; see topk_result).
+    /// Non-positive values are ignored. Returns key's estimated count.
+    pub(crate) fn builtin_topk(&mut self, args: &[Expr]) -> Value {
+        if args.len() != 4 {
+            warn!(self, "topk requires 4 arguments (state, key, value, k)");
+            return Value::from_number(0.0);
+        }
+        let Expr::Var(state) = &args[0] else {
+            warn!(self, "topk: first argument must be a name");
+            return Value::from_number(0.0);
+        };
+        let key = self.eval_string(&args[1]);
+        let weight = self.eval_expr(&args[2]).to_number();
+        let k = self.eval_expr(&args[3]).to_number();
+        if k < 1.0 {
+            warn!(self, "topk: k must be at least 1");
+            return Value::from_number(0.0);
+        }
+        let k = k as usize;
+        let (max_k, summary) = self
+            .topk
+            .entry(state.clone())
+            .or_insert_with(|| (k, SpaceSaving::new(capacity_for(k))));
+        if k > *max_k {
+            *max_k = k;
+            summary.grow(capacity_for(k));
+        }
+        if weight > 0.0 {
+            Value::from_number(summary.add(&key, weight))
+        } else {
+            Value::from_number(0.0)
+        }
+    }
+
+    /// topk_result(state, out) — out[rank] = key, out[rank, "count"] and
+    /// out[rank, "error"] (overcount bound) for the top k. Returns ranks filled.
+    pub(crate) fn builtin_topk_result(&mut self, args: &[Expr]) -> Value {
+        if args.len() != 2 {
+            warn!(self, "topk_result requires 2 arguments (state, out)");
+            return Value::from_number(0.0);
+        }
+        let Expr::Var(state) = &args[0] else {
+            warn!(self, "topk_result: first argument must be a name");
+            return Value::from_number(0.0);
+        };
+        let Some(out) = self.resolve_array_arg(&args[1], "topk_result") else {
+            return Value::from_number(0.0);
+        };
+        let top = match self.topk.get(state) {
+            Some((k, summary)) => summary.top(*k),
+            None => Vec::new(),
+        };
+        self.rt.delete_array_all(&out);
+        let subsep = self.rt.get_var("SUBSEP");
+        for (i, (key, count, error)) in top.iter().enumerate() {
+            let rank = (i + 1).to_string();
+            self.rt.set_array(&out, &rank, key);
+            self.rt.set_array_value(
+                &out,
+                &format!("{rank}{subsep}count"),
+                Value::from_number(*count),
+            );
+            self.rt.set_array_value(
+                &out,
+                &format!("{rank}{subsep}error"),
+                Value::from_number(*error),
+            );
+        }
+        Value::from_number(top.len() as f64)
+    }
+
+    /// Name of an `hll*()` sketch argument (a bare identifier, like topk).
+    fn hll_state_arg(&mut self, expr: &Expr, func: &str) -> Option<String> {
+        match expr {
+            Expr::Var(name) => Some(name.clone()),
+            _ => {
+                warn!(self, "{}: sketch argument must be a name", func);
+                None
+            }
+        }
+    }
+
+    /// hll(state, value [, precision]) — add value to the HyperLogLog sketch
+    /// named state (created with 2^precision registers, default 2^14).
+    /// Returns 1 if the sketch changed, else 0; the estimate, which scans
+    /// every register, is left to hll_count.
+    pub(crate) fn builtin_hll(&mut self, args: &[Expr]) -> Value {
+        if args.len() < 2 || args.len() > 3 {
+            warn!(
+                self,
+                "hll requires 2 or 3 arguments (state, value [, precision])"
+            );
+            return Value::from_number(0.0);
+        }
+        let Some(state) = self.hll_state_arg(&args[0], "hll") else {
+            return Value::from_number(0.0);
+        };
+        let value = self.eval_string(&args[1]);
+        if !self.hll.contains_key(&state) {
+            let precision = match args.get(2) {
+                Some(e) => self.eval_expr(e).to_number() as u8,
+                None => DEFAULT_PRECISION,
+            };
+            match Hll::new(precision) {
+                Ok(h) => {
+                    self.hll.insert(state.clone(), h);
+                }
+                Err(e) => {
+                    warn!(self, "hll: {}", e);
+                    return Value::from_number(0.0);
+                }
+            }
+        }
+        let sketch = self.hll.get_mut(&state).expect("sketch just created");
+        Value::from_number(if sketch.add(&value) { 1.0 } else { 0.0 })
+    }
+
+    /// hll_count(state) — estimated distinct values added to the sketch.
+    pub(crate) fn builtin_hll_count(&mut self, args: &[Expr]) -> Value {
+        if args.len() != 1 {
+            warn!(self, "hll_count requires 1 argument (state)");
+            return Value::from_number(0.0);
+        }
+        let Some(state) = self.hll_state_arg(&args[0], "hll_count") else {
+            return Value::from_number(0.0);
+        };
+        let n = self.hll.get(&state).map_or(0.0, |h| h.count().round());
+        Value::from_number(n)
+    }
+
+    /// hll_merge(a, b) — fold sketch b into a (a is created if missing).
+    /// Returns a's new estimate.
+    pub(crate) fn builtin_hll_merge(&mut self, args: &[Expr]) -> Value {
+        if args.len() != 2 {
+            warn!(self, "hll_merge requires 2 arguments (dest, src)");
+            return Value::from_number(0.0);
+        }
+        let (Some(dst), Some(src)) = (
+            self.hll_state_arg(&args[0], "hll_merge"),
+            self.hll_state_arg(&args[1], "hll_merge"),
+        ) else {
+            return Value::from_number(0.0);
+        };
+        let Some(other) = self.hll.get(&src).cloned() else {
+            return self.builtin_hll_count(&args[..1]);
+        };
+        self.merge_sketch(&dst, other, "hll_merge")
+    }
+
+    /// hll_dump(state) — the sketch as a string for hll_load() ("" if none).
+    pub(crate) fn builtin_hll_dump(&mut self, args: &[Expr]) -> Value {
+        if args.len() != 1 {
+            warn!(self, "hll_dump requires 1 argument (state)");
+            return Value::default();
+        }
+        let Some(state) = self.hll_state_arg(&args[0], "hll_dump") else {
+            return Value::default();
+        };
+        match self.hll.get(&state) {
+            Some(h) => Value::from_string(h.dump()),
+            None => Value::default(),
+        }
+    }
+
+    /// hll_load(state, dump) — merge an hll_dump() string into the sketch.
+    /// Returns the new estimate.
+    pub(crate) fn builtin_hll_load(&mut self, args: &[Expr]) -> Value {
+        if args.len() != 2 {
+            warn!(self, "hll_load requires 2 arguments (state, dump)");
+            return Value::from_number(0.0);
+        }
+        let Some(state) = self.hll_state_arg(&args[0], "hll_load") else {
+            return Value::from_number(0.0);
+        };
+        let dump = self.eval_string(&args[1]);
+        match Hll::load(&dump) {
+            Ok(h) => self.merge_sketch(&state, h, "hll_load"),
+            Err(e) => {
+                warn!(self, "hll_load: {}", e);
+                Value::from_number(0.0)
+            }
+        }
+    }
+
+    fn merge_sketch(&mut self, dst: &str, other: Hll, func: &str) -> Value {
+        let sketch = self
+            .hll
+            .entry(dst.to_string())
+            .or_insert_with(|| Hll::new(other.precision()).expect("valid precision"));
+        let merged = sketch.merge(&other);
+        let count = sketch.count().round();
+        if let Err(e) = merged {
+            warn!(self, "{}: {}", func, e);
+        }
+        Value::from_number(count)
+    }
+
     /// top(a, n) / bottom(a, n) — keep the n largest (top) or smallest (bottom)
     /// values from a numeric array, re-keyed 1..n. Returns count kept.
     pub(crate) fn builtin_top_bottom(&mut self, args: &[Expr], smallest: bool) -> Value {
         if args.len() < 2 {
             let name = if smallest { "bottom" } else { "top" };
-            eprintln!("fk: {} requires 2 arguments (array, n)", name);
+            warn!(self, "{} requires 2 arguments (array, n)", name);
             return Value::from_number(0.0);
         }
         let fn_name = if smallest { "bottom" } else { "top" };
@@ -715,13 +1689,13 @@ impl<'a> Executor<'a> {
     /// Returns the current window size.
     pub(crate) fn builtin_window(&mut self, args: &[Expr]) -> Value {
         if args.len() < 3 {
-            eprintln!("fk: window requires 3 arguments (array, n, expr)");
+            warn!(self, "window requires 3 arguments (array, n, expr)");
             return Value::from_number(0.0);
         }
         let array_name = match &args[0] {
             Expr::Var(n) => n.clone(),
             _ => {
-                eprintln!("fk: window: first argument must be an array name");
+                warn!(self, "window: first argument must be an array name");
                 return Value::from_number(0.0);
             }
         };
@@ -768,7 +1742,7 @@ impl<'a> Executor<'a> {
     /// slurp(file [, arr]) — read file into string, or into arr lines. Returns string or line count.
     pub(crate) fn builtin_slurp(&mut self, args: &[Expr]) -> Value {
         if args.is_empty() {
-            eprintln!("fk: slurp requires at least 1 argument (filename)");
+            warn!(self, "slurp requires at least 1 argument (filename)");
             return Value::default();
         }
         let filename = self.eval_string(&args[0]);
@@ -777,7 +1751,7 @@ impl<'a> Executor<'a> {
             match std::io::stdin().read_to_string(&mut buf) {
                 Ok(_) => buf,
                 Err(e) => {
-                    eprintln!("fk: slurp: stdin: {}", e);
+                    warn!(self, "slurp: stdin: {}", e);
                     return if args.len() >= 2 {
                         Value::from_number(0.0)
                     } else {
@@ -789,7 +1763,7 @@ impl<'a> Executor<'a> {
             match std::fs::read_to_string(&filename) {
                 Ok(c) => c,
                 Err(e) => {
-                    eprintln!("fk: slurp: {}: {}", filename, e);
+                    warn!(self, "slurp: {}: {}", filename, e);
                     return if args.len() >= 2 {
                         Value::from_number(0.0)
                     } else {
@@ -802,7 +1776,7 @@ impl<'a> Executor<'a> {
             let array_name = match &args[1] {
                 Expr::Var(n) => n.clone(),
                 _ => {
-                    eprintln!("fk: slurp: second argument must be an array name");
+                    warn!(self, "slurp: second argument must be an array name");
                     return Value::from_number(0.0);
                 }
             };
@@ -818,6 +1792,77 @@ impl<'a> Executor<'a> {
         }
     }
 
+    /// cliargs(arr) — parse FKARGS (the arguments after `--`) into `arr`:
+    /// `arr[name] = value` per option, operands as `arr[1..n]`. Returns n.
+    pub(crate) fn builtin_cliargs(&mut self, args: &[Expr]) -> Value {
+        let array_name = match args {
+            [Expr::Var(n)] => n.clone(),
+            _ => {
+                warn!(self, "cliargs: argument must be an array name");
+                return Value::from_number(0.0);
+            }
+        };
+        let raw: Vec<String> = (1..=self.rt.array_len("FKARGS"))
+            .map(|i| self.rt.get_array("FKARGS", &i.to_string()))
+            .collect();
+        let (options, operands) = crate::cli::parse_script_args(&raw);
+        self.rt.delete_array_all(&array_name);
+        for (name, value) in &options {
+            self.rt.set_array(&array_name, name, value);
+        }
+        for (i, operand) in operands.iter().enumerate() {
+            self.rt
+                .set_array(&array_name, &(i + 1).to_string(), operand);
+        }
+        Value::from_number(operands.len() as f64)
+    }
+
+    /// vars(out [, all]) / arrays(out [, all]) — fill `out` with every
+    /// scalar that is set and its value, or every array and its element
+    /// count, in name order; fk's own variables only when `all` is true.
+    /// Returns how many.
+    pub(crate) fn builtin_names(&mut self, args: &[Expr], arrays: bool) -> Value {
+        let caller = if arrays { "arrays" } else { "vars" };
+        let out = match args {
+            [Expr::Var(n)] | [Expr::Var(n), _] => n.clone(),
+            _ => {
+                warn!(self, "{}: first argument must be an array name", caller);
+                return Value::from_number(0.0);
+            }
+        };
+        if self.rt.has_var(&out) && !self.rt.has_array(&out) && !self.rt.is_bound_array(&out) {
+            warn!(self, "{}: '{}' is not an array", caller, out);
+            return Value::from_number(0.0);
+        }
+        let all = args.get(1).is_some_and(|e| self.eval_expr(e).is_truthy());
+        let entries: Vec<(String, Value)> = if arrays {
+            let names = self.rt.sorted_array_names(all);
+            names
+                .into_iter()
+                .filter(|n| *n != out)
+                .map(|n| {
+                    let len = self.rt.array_len(&n);
+                    (n, Value::from_number(len as f64))
+                })
+                .collect()
+        } else {
+            let names = self.rt.scalar_names(all);
+            names
+                .into_iter()
+                .filter(|n| *n != out)
+                .map(|n| {
+                    let v = self.rt.get_value(&n);
+                    (n, v)
+                })
+                .collect()
+        };
+        self.rt.delete_array_all(&out);
+        for (name, v) in &entries {
+            self.rt.set_array_value(&out, name, v.clone());
+        }
+        Value::from_number(entries.len() as f64)
+    }
+
     /// typeof(x) — return type name of a variable.
     pub(crate) fn builtin_typeof(&mut self, args: &[Expr]) -> Value {
         if args.is_empty() {
@@ -863,7 +1908,7 @@ impl<'a> Executor<'a> {
             return Value::from_number(!n as f64);
         }
         if args.len() < 2 {
-            eprintln!("fk: {} requires 2 arguments", name);
+            warn!(self, "{} requires 2 arguments", name);
             return Value::from_number(0.0);
         }
         let a = self.eval_expr(&args[0]).to_number() as i64;
@@ -881,10 +1926,11 @@ impl<'a> Executor<'a> {
 
     /// asort(arr [, dest]) — sort array by values, re-key with 1..N.
     /// asorti(arr [, dest]) — sort array by keys, store sorted keys as values with 1..N.
-    /// With dest: writes to dest, leaves source intact.
+    /// With dest: writes to dest, leaves source intact. Returns the count
+    /// when given a dest or a mode, the array name when called bare.
     pub(crate) fn builtin_asort(&mut self, args: &[Expr], by_index: bool) -> Value {
         if args.is_empty() {
-            eprintln!("fk: asort/asorti requires at least 1 argument");
+            warn!(self, "asort/asorti requires at least 1 argument");
             return Value::from_number(0.0);
         }
         let array_name = match self.resolve_array_arg(&args[0], if by_index { "asorti" } else { "asort" }) {
@@ -898,6 +1944,25 @@ impl<'a> Executor<'a> {
                 None
             }
         });
+        // asort(a, dest, mode), or asort(a, "mode") with no destination.
+        let mode_arg = match args.get(1) {
+            Some(Expr::StringLit(_)) if args.len() == 2 => args.get(1),
+            _ => args.get(2),
+        };
+        let func = if by_index { "asorti" } else { "asort" };
+        if self.in_comparator {
+            warn!(self, "{}: cannot sort inside a comparison function", func);
+            return Value::from_number(0.0);
+        }
+        let how = match mode_arg {
+            Some(e) => self.eval_string(e),
+            None => String::new(),
+        };
+        let user_cmp = self.functions.get(&how).cloned();
+        let mode = match user_cmp {
+            Some(_) => None,
+            None => self.parse_sort_mode(&how, func),
+        };
 
         let mut items: Vec<(String, String)> = self
             .rt
@@ -909,21 +1974,30 @@ impl<'a> Executor<'a> {
             })
             .collect();
 
-        if by_index {
-            items.sort_by(|a, b| a.0.cmp(&b.0));
-        } else {
-            items.sort_by(|a, b| {
-                let na = crate::builtins::to_number(&a.1);
-                let nb = crate::builtins::to_number(&b.1);
-                if na != 0.0 || a.1.is_empty() || nb != 0.0 || b.1.is_empty() {
-                    na.partial_cmp(&nb).unwrap_or(std::cmp::Ordering::Equal)
-                } else {
-                    a.1.cmp(&b.1)
-                }
+        if let Some(cmp_fn) = user_cmp {
+            // User comparator: (a, b) → <0 / 0 / >0 on values (keys for asorti).
+            // Ties keep key order.
+            items.sort_by(|a, b| smart_cmp(&a.0, &b.0));
+            self.in_comparator = true;
+            items = merge_sort_by(items, &mut |a, b| {
+                let (x, y) = if by_index { (&a.0, &b.0) } else { (&a.1, &b.1) };
+                let args = [Value::from_strnum(x.clone()), Value::from_strnum(y.clone())];
+                let r = self.call_user_func(&cmp_fn, &args, &[]).to_number();
+                r.partial_cmp(&0.0).unwrap_or(std::cmp::Ordering::Equal)
             });
+            self.in_comparator = false;
+        } else if let Some(mode) = mode {
+            if by_index {
+                items.sort_by(|a, b| mode.compare(&a.0, &b.0));
+            } else {
+                items.sort_by(|a, b| mode.compare(&a.1, &b.1));
+            }
+        } else if by_index {
+            sort::sort_by_key(&mut items);
+        } else {
+            sort::sort_by_value(&mut items);
         }
 
-        let has_dest = dest_name.is_some();
         let target = dest_name.as_deref().unwrap_or(&array_name);
         let count = items.len();
         self.rt.delete_array_all(target);
@@ -935,8 +2009,9 @@ impl<'a> Executor<'a> {
                 self.rt.set_array(target, &new_key, &val);
             }
         }
-        // 1-arg: return array name for chaining; 2-arg: return count (gawk compat)
-        if has_dest {
+        // 1-arg: return array name for chaining; with a dest or a mode:
+        // return count (gawk compat)
+        if args.len() > 1 {
             Value::from_number(count as f64)
         } else {
             Value::from_string(target.to_string())
@@ -961,13 +2036,13 @@ impl<'a> Executor<'a> {
     /// Statistical functions that operate on arrays.
     pub(crate) fn builtin_stats(&mut self, name: &str, args: &[Expr]) -> Value {
         if args.is_empty() {
-            eprintln!("fk: {}() requires an array argument", name);
+            warn!(self, "{}() requires an array argument", name);
             return Value::from_number(0.0);
         }
         let array_name = match &args[0] {
             Expr::Var(v) => v.clone(),
             _ => {
-                eprintln!("fk: {}(): first argument must be an array name", name);
+                warn!(self, "{}(): first argument must be an array name", name);
                 return Value::from_number(0.0);
             }
         };
@@ -976,7 +2051,7 @@ impl<'a> Executor<'a> {
         }
 
         if matches!(name, "sum" | "mean" | "variance" | "stddev" | "min" | "max") {
-            let Some(values) = self.rt.array_values(&array_name) else {
+            let Some(values) = self.rt.array_numbers(&array_name) else {
                 return Value::from_number(0.0);
             };
             let mut count: f64 = 0.0;
@@ -987,7 +2062,6 @@ impl<'a> Executor<'a> {
             let mut max: f64 = f64::NEG_INFINITY;
 
             for v in values {
-                let v = v.to_number();
                 count += 1.0;
                 sum += v;
                 if v < min {
@@ -1063,21 +2137,20 @@ impl<'a> Executor<'a> {
     /// Bins default to Sturges' rule. Returns the output array name (for chaining).
     pub(crate) fn builtin_hist(&mut self, args: &[Expr]) -> Value {
         if args.is_empty() {
-            eprintln!("fk: hist() requires an array argument");
+            warn!(self, "hist() requires an array argument");
             return Value::from_string(String::new());
         }
         let array_name = match &args[0] {
             Expr::Var(v) => v.clone(),
             _ => {
-                eprintln!("fk: hist(): first argument must be an array name");
+                warn!(self, "hist(): first argument must be an array name");
                 return Value::from_string(String::new());
             }
         };
 
-        let Some(values) = self.rt.array_values(&array_name) else {
+        let Some(source) = self.rt.array_numbers(&array_name) else {
             return Value::from_string(String::new());
         };
-        let source: Vec<f64> = values.into_iter().map(|v| v.to_number()).collect();
         if source.is_empty() {
             return Value::from_string(String::new());
         }
@@ -1086,7 +2159,7 @@ impl<'a> Executor<'a> {
             match expr {
                 Expr::Var(v) => v.clone(),
                 _ => {
-                    eprintln!("fk: hist(): output must be an array name");
+                    warn!(self, "hist(): output must be an array name");
                     return Value::from_string(String::new());
                 }
             }
@@ -1173,11 +2246,12 @@ impl<'a> Executor<'a> {
         Value::from_string(out_name)
     }
 
-    /// plot(arr [, width [, char [, precision [, color]]]]) — render a simple horizontal bar chart.
-    /// Auto-detects histogram arrays via ArrayMeta and uses range labels.
+    /// plot(arr [, width [, char [, precision [, color [, scale]]]]]) — render a simple horizontal
+    /// bar chart. Auto-detects histogram arrays via ArrayMeta and uses range labels.
+    /// The options may also be given as a single string: `plot(a, "width=30,log")`.
     pub(crate) fn builtin_plot(&mut self, args: &[Expr]) -> Value {
         if args.is_empty() {
-            eprintln!("fk: plot() requires an array argument");
+            warn!(self, "plot() requires an array argument");
             return Value::from_string(String::new());
         }
         let array_name = match &args[0] {
@@ -1187,45 +2261,24 @@ impl<'a> Executor<'a> {
                 if self.rt.has_array(&s) {
                     s
                 } else {
-                    eprintln!("fk: plot(): first argument must be an array name");
+                    warn!(self, "plot(): first argument must be an array name");
                     return Value::from_string(String::new());
                 }
             }
         };
-        if !self.rt.has_array(&array_name) {
-            return Value::from_string(String::new());
-        }
-
-        let width_raw = if let Some(expr) = args.get(1) {
-            builtins::to_number(&self.eval_string(expr)).round() as i64
-        } else {
-            40
-        };
-        let width = if width_raw <= 0 {
-            40
-        } else {
-            width_raw as usize
-        };
-        let ch = if let Some(expr) = args.get(2) {
-            self.eval_string(expr).chars().next().unwrap_or('▇')
-        } else {
-            '▇'
-        };
-        let precision_raw = if let Some(expr) = args.get(3) {
-            builtins::to_number(&self.eval_string(expr)).round() as i64
-        } else {
-            -1
-        };
-        let precision = if precision_raw < 0 {
-            None
-        } else {
-            Some(precision_raw as usize)
-        };
-        let color_name = args
-            .get(4)
-            .map(|expr| self.eval_string(expr))
-            .unwrap_or_default();
-        let (color_code, color_reset) = ansi_color(&color_name);
+        if !self.rt.has_array(&array_name) {
+            return Value::from_string(String::new());
+        }
+
+        let opts = self.chart_opts(
+            args,
+            "plot",
+            &["width", "char", "precision", "color", "scale"],
+        );
+        let width = opts.size.unwrap_or(40);
+        let ch = opts.ch.unwrap_or('▇');
+        let precision = opts.precision;
+        let (color_code, color_reset) = ansi_color(&opts.color);
 
         let entries = self.collect_chart_entries(&array_name);
         if entries.is_empty() {
@@ -1235,7 +2288,11 @@ impl<'a> Executor<'a> {
 
         let hist_meta = self.rt.get_meta(&array_name).cloned();
         let labels = build_chart_labels(&entries, hist_meta.as_ref(), precision);
-        let label_width = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+        let label_width = labels
+            .iter()
+            .map(|l| width::text_width(l))
+            .max()
+            .unwrap_or(0);
         let count_width = entries
             .iter()
             .map(|(_, v)| builtins::format_number(*v).len())
@@ -1244,14 +2301,20 @@ impl<'a> Executor<'a> {
 
         let mut lines: Vec<String> = Vec::new();
         for (idx, (_, count)) in entries.iter().enumerate() {
-            let bar = render_bar(*count, max_val, width, ch, color_code, color_reset);
+            let bar = render_bar(
+                chart_scale(*count, opts.log),
+                chart_scale(max_val, opts.log),
+                width,
+                ch,
+                color_code,
+                color_reset,
+            );
             let count_str = builtins::format_number(*count);
             lines.push(format!(
-                "{:label_w$} | {} {:count_w$}",
-                labels[idx],
+                "{} | {} {:count_w$}",
+                width::pad_right(&labels[idx], label_width, ' '),
                 bar,
                 count_str,
-                label_w = label_width,
                 count_w = count_width,
             ));
         }
@@ -1259,11 +2322,11 @@ impl<'a> Executor<'a> {
         Value::from_string(lines.join("\n"))
     }
 
-    /// plotbox(arr [, width [, char [, precision [, title [, xlabel [, color]]]]]])
+    /// plotbox(arr [, width [, char [, precision [, title [, xlabel [, color [, scale]]]]]]])
     /// Render a boxed horizontal bar chart. Auto-detects histogram metadata.
     pub(crate) fn builtin_plotbox(&mut self, args: &[Expr]) -> Value {
         if args.is_empty() {
-            eprintln!("fk: plotbox() requires an array argument");
+            warn!(self, "plotbox() requires an array argument");
             return Value::from_string(String::new());
         }
         let array_name = match &args[0] {
@@ -1273,7 +2336,7 @@ impl<'a> Executor<'a> {
                 if self.rt.has_array(&s) {
                     s
                 } else {
-                    eprintln!("fk: plotbox(): first argument must be an array name");
+                    warn!(self, "plotbox(): first argument must be an array name");
                     return Value::from_string(String::new());
                 }
             }
@@ -1282,38 +2345,25 @@ impl<'a> Executor<'a> {
             return Value::from_string(String::new());
         }
 
-        let width_raw = if let Some(expr) = args.get(1) {
-            builtins::to_number(&self.eval_string(expr)).round() as i64
-        } else {
-            40
-        };
-        let width = if width_raw <= 0 {
-            40
-        } else {
-            width_raw as usize
-        };
-        let ch = if let Some(expr) = args.get(2) {
-            self.eval_string(expr).chars().next().unwrap_or('▇')
-        } else {
-            '▇'
-        };
-        let precision_raw = if let Some(expr) = args.get(3) {
-            builtins::to_number(&self.eval_string(expr)).round() as i64
-        } else {
-            -1
-        };
-        let precision = if precision_raw < 0 {
-            None
-        } else {
-            Some(precision_raw as usize)
-        };
-        let title_arg = args.get(4).map(|expr| self.eval_string(expr));
-        let xlabel_arg = args.get(5).map(|expr| self.eval_string(expr));
-        let color_name = args
-            .get(6)
-            .map(|expr| self.eval_string(expr))
-            .unwrap_or_default();
-        let (color_code, color_reset) = ansi_color(&color_name);
+        let opts = self.chart_opts(
+            args,
+            "plotbox",
+            &[
+                "width",
+                "char",
+                "precision",
+                "title",
+                "xlabel",
+                "color",
+                "scale",
+            ],
+        );
+        let width = opts.size.unwrap_or(40);
+        let ch = opts.ch.unwrap_or('▇');
+        let precision = opts.precision;
+        let title_arg = opts.title;
+        let xlabel_arg = opts.xlabel;
+        let (color_code, color_reset) = ansi_color(&opts.color);
 
         let entries = self.collect_chart_entries(&array_name);
         if entries.is_empty() {
@@ -1359,7 +2409,11 @@ impl<'a> Executor<'a> {
             }
         });
         let labels = build_chart_labels(&entries, hist_meta.as_ref(), precision);
-        let label_width = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+        let label_width = labels
+            .iter()
+            .map(|l| width::text_width(l))
+            .max()
+            .unwrap_or(0);
         let count_width = entries
             .iter()
             .map(|(_, v)| builtins::format_number(*v).len())
@@ -1371,14 +2425,13 @@ impl<'a> Executor<'a> {
         let total_width = label_width + 3 + box_width + 1;
         for text in [&title] {
             if !text.is_empty() {
-                let clen = text.chars().count();
-                let display = if clen > total_width {
-                    let trunc: String = text.chars().take(total_width.saturating_sub(1)).collect();
+                let display = if width::text_width(text) > total_width {
+                    let trunc = width::truncate(text, total_width.saturating_sub(1));
                     format!("{trunc}…")
                 } else {
                     text.clone()
                 };
-                let dlen = display.chars().count();
+                let dlen = width::text_width(&display);
                 let pad = if total_width > dlen {
                     (total_width - dlen) / 2
                 } else {
@@ -1395,14 +2448,20 @@ impl<'a> Executor<'a> {
         ));
 
         for (idx, (_, count)) in entries.iter().enumerate() {
-            let bar = render_bar(*count, max_val, width, ch, color_code, color_reset);
+            let bar = render_bar(
+                chart_scale(*count, opts.log),
+                chart_scale(max_val, opts.log),
+                width,
+                ch,
+                color_code,
+                color_reset,
+            );
             let count_str = builtins::format_number(*count);
             lines.push(format!(
-                "{:>label_w$} ┤{} {:count_w$}",
-                labels[idx],
+                "{} ┤{} {:count_w$}",
+                width::pad_left(&labels[idx], label_width, ' '),
                 bar,
                 count_str,
-                label_w = label_width,
                 count_w = count_width,
             ));
         }
@@ -1415,78 +2474,450 @@ impl<'a> Executor<'a> {
         ));
         if !xlabel.is_empty() {
             let total = label_width + 3 + box_width + 1;
-            let pad = if total > xlabel.len() {
-                (total - xlabel.len()) / 2
+            let xlen = width::text_width(&xlabel);
+            let pad = if total > xlen { (total - xlen) / 2 } else { 0 };
+            lines.push(format!("{:pad$}{}", "", xlabel, pad = pad));
+        }
+
+        Value::from_string(lines.join("\n"))
+    }
+
+    /// vplot(arr [, height [, char [, precision [, color [, scale]]]]])
+    /// Render a vertical column chart, one column per entry, using eighth
+    /// blocks for the column tops so many bins fit across the terminal.
+    pub(crate) fn builtin_vplot(&mut self, args: &[Expr]) -> Value {
+        if args.is_empty() {
+            warn!(self, "vplot() requires an array argument");
+            return Value::from_string(String::new());
+        }
+        let array_name = match &args[0] {
+            Expr::Var(v) => v.clone(),
+            _ => {
+                let s = self.eval_string(&args[0]);
+                if self.rt.has_array(&s) {
+                    s
+                } else {
+                    warn!(self, "vplot(): first argument must be an array name");
+                    return Value::from_string(String::new());
+                }
+            }
+        };
+        if !self.rt.has_array(&array_name) {
+            return Value::from_string(String::new());
+        }
+
+        let opts = self.chart_opts(
+            args,
+            "vplot",
+            &["height", "char", "precision", "color", "scale"],
+        );
+        let height = opts.size.unwrap_or(10);
+        let ch = opts.ch.unwrap_or('█');
+        let (color_code, color_reset) = ansi_color(&opts.color);
+
+        let entries = self.collect_chart_entries(&array_name);
+        if entries.is_empty() {
+            return Value::from_string(String::new());
+        }
+        let max_val = entries.iter().map(|(_, v)| *v).fold(0.0f64, f64::max);
+        let scaled_max = chart_scale(max_val, opts.log);
+
+        // Column heights in eighths of a row.
+        let eighths: Vec<usize> = entries
+            .iter()
+            .map(|(_, v)| {
+                let mut e = if scaled_max > 0.0 {
+                    (chart_scale(*v, opts.log) / scaled_max * (height * 8) as f64).round() as usize
+                } else {
+                    0
+                };
+                if *v > 0.0 && e == 0 {
+                    e = 1;
+                }
+                e
+            })
+            .collect();
+
+        let max_label = builtins::format_number(max_val);
+        let axis_width = max_label.len();
+        let mut lines: Vec<String> = Vec::new();
+        for row in (0..height).rev() {
+            let mut cells = String::new();
+            for e in &eighths {
+                let fill = e.saturating_sub(row * 8).min(8);
+                cells.push(match fill {
+                    0 => ' ',
+                    8 => ch,
+                    _ if ch == '█' => VBLOCKS[fill - 1],
+                    _ => ch,
+                });
+            }
+            let cells = cells.trim_end();
+            let (label, tick) = if row + 1 == height {
+                (max_label.as_str(), '┤')
             } else {
-                0
+                ("", '│')
             };
-            lines.push(format!("{:pad$}{}", "", xlabel, pad = pad));
+            if cells.is_empty() || color_code.is_empty() {
+                lines.push(format!("{label:>axis_width$} {tick}{cells}"));
+            } else {
+                lines.push(format!(
+                    "{label:>axis_width$} {tick}{color_code}{cells}{color_reset}"
+                ));
+            }
+        }
+        lines.push(format!("{:axis_width$} └{}", "", "─".repeat(entries.len())));
+
+        // Only the first and last labels fit under single-character columns.
+        let (first, last) = match self.rt.get_meta(&array_name) {
+            Some(ArrayMeta::Histogram { min, max, .. }) => {
+                let p = opts.precision.unwrap_or(1);
+                (format!("{min:.p$}"), format!("{max:.p$}"))
+            }
+            _ => (entries[0].0.clone(), entries[entries.len() - 1].0.clone()),
+        };
+        let mut footer = first.clone();
+        if entries.len() > 1 {
+            let used = first.chars().count() + last.chars().count();
+            let gap = entries.len().saturating_sub(used).max(1);
+            footer.push_str(&" ".repeat(gap));
+            footer.push_str(&last);
+        }
+        lines.push(format!("{:axis_width$}  {footer}", ""));
+
+        Value::from_string(lines.join("\n"))
+    }
+
+    /// heatmap(arr [, opts]) — render a 2-D density plot from `a[row, col]`
+    /// counts. `opts` is a `"min=N,max=N,transpose,cell=N"` options string.
+    pub(crate) fn builtin_heatmap(&mut self, args: &[Expr]) -> Value {
+        let array_name = match args.first() {
+            Some(arg) => match self.resolve_array_arg(arg, "heatmap") {
+                Some(n) => n,
+                None => return Value::from_string(String::new()),
+            },
+            None => {
+                warn!(self, "heatmap() requires an array argument");
+                return Value::from_string(String::new());
+            }
+        };
+        let spec = args.get(1).map(|e| self.eval_string(e)).unwrap_or_default();
+        let mut lo: Option<f64> = None;
+        let mut hi: Option<f64> = None;
+        let mut transpose = false;
+        let mut cell: Option<usize> = None;
+        for tok in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            match tok.split_once('=') {
+                Some(("min", v)) => lo = Some(builtins::to_number(v)),
+                Some(("max", v)) => hi = Some(builtins::to_number(v)),
+                Some(("cell", v)) => cell = Some(builtins::to_number(v).max(1.0) as usize),
+                None if tok == "transpose" => transpose = true,
+                _ => warn!(self, "heatmap(): unknown option '{tok}'"),
+            }
+        }
+
+        let subsep = self.rt.get_var("SUBSEP");
+        let mut cells: HashMap<(String, String), f64> = HashMap::new();
+        let mut rows: Vec<String> = Vec::new();
+        let mut cols: Vec<String> = Vec::new();
+        let mut seen_rows: HashSet<String> = HashSet::new();
+        let mut seen_cols: HashSet<String> = HashSet::new();
+        for k in self.rt.array_keys(&array_name) {
+            if k.starts_with('_') {
+                continue;
+            }
+            let v = builtins::to_number(&self.rt.get_array(&array_name, &k));
+            let (mut r, mut c) = match k.split_once(subsep.as_str()) {
+                Some((r, c)) => (r.to_string(), c.to_string()),
+                None => (k.clone(), String::new()),
+            };
+            if transpose {
+                std::mem::swap(&mut r, &mut c);
+            }
+            if seen_rows.insert(r.clone()) {
+                rows.push(r.clone());
+            }
+            if seen_cols.insert(c.clone()) {
+                cols.push(c.clone());
+            }
+            cells.insert((r, c), v);
+        }
+        if cells.is_empty() {
+            return Value::from_string(String::new());
+        }
+        smart_sort_keys(&mut rows);
+        smart_sort_keys(&mut cols);
+
+        let data_min = cells.values().copied().fold(f64::INFINITY, f64::min);
+        let data_max = cells.values().copied().fold(f64::NEG_INFINITY, f64::max);
+        let lo = lo.unwrap_or(data_min.min(0.0));
+        let hi = hi.unwrap_or(data_max);
+        let levels = HEAT_RAMP.len() - 1;
+
+        let row_width = rows.iter().map(|r| r.chars().count()).max().unwrap_or(0);
+        let cw = cell.unwrap_or_else(|| {
+            cols.iter()
+                .map(|c| c.chars().count())
+                .max()
+                .unwrap_or(0)
+                .clamp(2, 4)
+        });
+
+        let mut lines: Vec<String> = Vec::new();
+        for r in &rows {
+            let mut line = format!("{r:>row_width$} ");
+            for (i, c) in cols.iter().enumerate() {
+                let shade = match cells.get(&(r.clone(), c.clone())) {
+                    Some(&v) if hi > lo => {
+                        let ratio = ((v - lo) / (hi - lo)).clamp(0.0, 1.0);
+                        let mut idx = (ratio * levels as f64).round() as usize;
+                        if ratio > 0.0 && idx == 0 {
+                            idx = 1;
+                        }
+                        HEAT_RAMP[idx]
+                    }
+                    Some(&v) if v > lo => HEAT_RAMP[levels],
+                    _ => HEAT_RAMP[0],
+                };
+                if i > 0 {
+                    line.push(' ');
+                }
+                line.extend(std::iter::repeat_n(shade, cw));
+            }
+            lines.push(line.trim_end().to_string());
         }
+        let labels: Vec<String> = cols
+            .iter()
+            .map(|c| format!("{:cw$}", c.chars().take(cw).collect::<String>()))
+            .collect();
+        lines.push(
+            format!("{:row_width$} {}", "", labels.join(" "))
+                .trim_end()
+                .to_string(),
+        );
+        let ramp: String = HEAT_RAMP[1..].iter().collect();
+        lines.push(format!(
+            "{:row_width$} {} {ramp} {}",
+            "",
+            builtins::format_number(lo),
+            builtins::format_number(hi)
+        ));
 
         Value::from_string(lines.join("\n"))
     }
 
-    pub(crate) fn exec_getline(&mut self, var: Option<&str>, source: Option<&Expr>) -> Value {
+    /// pivot(src, dst [, opts]) — lay out `src[row, col]` as a wide table:
+    /// dst[1] is the header, dst[2..] one aligned line per row, rows and
+    /// columns in sorted order. `opts` is `"fill=TEXT,transpose"`; missing
+    /// cells are empty unless `fill` is given. Returns the line count.
+    pub(crate) fn builtin_pivot(&mut self, args: &[Expr]) -> Value {
+        if args.len() < 2 || args.len() > 3 {
+            warn!(self, "pivot requires 2 or 3 arguments (src, dst [, opts])");
+            return Value::from_number(0.0);
+        }
+        let Some(src) = self.resolve_array_arg(&args[0], "pivot") else {
+            return Value::from_number(0.0);
+        };
+        let Some(dst) = self.resolve_array_arg(&args[1], "pivot") else {
+            return Value::from_number(0.0);
+        };
+        if src == dst {
+            warn!(self, "pivot: dst must be a different array");
+            return Value::from_number(0.0);
+        }
+        let spec = args.get(2).map(|e| self.eval_string(e)).unwrap_or_default();
+        let mut fill = String::new();
+        let mut transpose = false;
+        for tok in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            match tok.split_once('=') {
+                Some(("fill", v)) => fill = v.to_string(),
+                None if tok == "transpose" => transpose = true,
+                _ => warn!(self, "pivot(): unknown option '{tok}'"),
+            }
+        }
+
+        let subsep = self.rt.get_var("SUBSEP");
+        let mut cells: HashMap<(String, String), String> = HashMap::new();
+        let mut rows: Vec<String> = Vec::new();
+        let mut cols: Vec<String> = Vec::new();
+        let mut seen_rows: HashSet<String> = HashSet::new();
+        let mut seen_cols: HashSet<String> = HashSet::new();
+        for k in self.rt.array_keys(&src) {
+            let (mut r, mut c) = match k.split_once(subsep.as_str()) {
+                Some((r, c)) => (r.to_string(), c.to_string()),
+                None => (k.clone(), String::new()),
+            };
+            if transpose {
+                std::mem::swap(&mut r, &mut c);
+            }
+            if seen_rows.insert(r.clone()) {
+                rows.push(r.clone());
+            }
+            if seen_cols.insert(c.clone()) {
+                cols.push(c.clone());
+            }
+            cells.insert((r, c), self.rt.get_array(&src, &k));
+        }
+        smart_sort_keys(&mut rows);
+        smart_sort_keys(&mut cols);
+
+        let header: Vec<String> = std::iter::once(String::new())
+            .chain(cols.iter().cloned())
+            .collect();
+        let table: Vec<Vec<String>> = rows
+            .iter()
+            .map(|r| {
+                std::iter::once(r.clone())
+                    .chain(cols.iter().map(|c| {
+                        cells
+                            .remove(&(r.clone(), c.clone()))
+                            .unwrap_or_else(|| fill.clone())
+                    }))
+                    .collect()
+            })
+            .collect();
+        self.rt.delete_array_all(&dst);
+        if rows.is_empty() {
+            return Value::from_number(0.0);
+        }
+        let lines = crate::output::align_rows(&header, &table);
+        for (i, line) in lines.iter().enumerate() {
+            self.rt.set_array(&dst, &(i + 1).to_string(), line);
+        }
+        Value::from_number(lines.len() as f64)
+    }
+
+    /// Evaluate chart option arguments, either positionally (named by
+    /// `positional`) or as a single `"key=value,..."` options string.
+    fn chart_opts(&mut self, args: &[Expr], func: &str, positional: &[&str]) -> ChartOpts {
+        let mut opts = ChartOpts::default();
+        let vals: Vec<String> = args[1..].iter().map(|e| self.eval_string(e)).collect();
+        if let [spec] = vals.as_slice()
+            && !spec.is_empty()
+            && spec.trim().parse::<f64>().is_err()
+        {
+            for tok in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                match tok.split_once('=') {
+                    Some((key, val)) if opts.apply(key.trim(), val) => {}
+                    None if tok == "log" || tok == "linear" => opts.log = tok == "log",
+                    _ => warn!(self, "{func}(): unknown option '{tok}'"),
+                }
+            }
+            return opts;
+        }
+        for (name, val) in positional.iter().zip(&vals) {
+            opts.apply(name, val);
+        }
+        opts
+    }
+
+    /// `getline [var] [< file]`. The counters follow POSIX:
+    ///
+    /// | form                  | sets        | NR | FNR |
+    /// |-----------------------|-------------|----|-----|
+    /// | `getline`             | `$0`, NF    | +1 | +1  |
+    /// | `getline var`         | var         | +1 | +1  |
+    /// | `getline < file`      | `$0`, NF    |    |     |
+    /// | `getline var < file`  | var         |    |     |
+    /// | `cmd \| getline`      | `$0`, NF    | +1 |     |
+    /// | `cmd \| getline var`  | var         | +1 |     |
+    pub(crate) fn exec_getline(&mut self, var: Option<&Expr>, source: Option<&Expr>) -> Value {
         if let Some(src_expr) = source {
             let path = self.eval_string(src_expr);
+            if is_stdin_name(&path) {
+                return self.getline_stdin(var);
+            }
             return self.getline_from_file(&path, var);
         }
 
-        // No source: read the next record from the current input stream
-        let record = {
-            if let Some(ref mut inp) = self.input {
-                match inp.next_record() {
-                    Ok(Some(r)) => Some(r),
-                    Ok(None) => return Value::from_number(0.0),
-                    Err(_) => return Value::from_number(-1.0),
-                }
-            } else {
-                None
+        // No source: read the next record from the main input, opening it
+        // first when this runs in BEGIN.
+        self.open_input();
+        self.sync_csv_dialect();
+        let Some(ref mut inp) = self.input else {
+            // No main input at all (e.g. the REPL): read raw stdin.
+            let got = self.getline_stdin(var);
+            if got.to_number() > 0.0 {
+                self.rt.increment_nr();
+                self.rt.increment_fnr();
             }
+            return got;
         };
-
-        if let Some(rec) = record {
-            match var {
-                Some(name) => self.rt.set_var(name, &rec.text),
-                None => match &rec.fields {
-                    Some(fields) => self.rt.set_record_fields(&rec.text, fields.clone()),
-                    None => self.rt.set_record(&rec.text),
-                },
+        let mut rec = match inp.next_record() {
+            Ok(Some(r)) => r,
+            Ok(None) => return Value::from_number(0.0),
+            Err(_) => return Value::from_number(-1.0),
+        };
+        // Crossing into the next file runs its ENDFILE/BEGINFILE here, and
+        // the record loop will not see the boundary again.
+        if self.enter_input_source() {
+            if self.exit_code.is_some() {
+                self.call_aborted = true;
+                return Value::from_number(0.0);
+            }
+            match self.redo_first_record(&mut rec) {
+                Ok(true) => {}
+                Ok(false) => return self.exec_getline(var, None),
+                Err(_) => return Value::from_number(-1.0),
             }
-            self.rt.increment_nr();
-            return Value::from_number(1.0);
         }
+        if self.over_input_limit() {
+            return Value::from_number(0.0);
+        }
+        if self.take_header(&rec) {
+            return self.exec_getline(var, None);
+        }
+        self.sync_input_header();
+        self.sync_byte_offsets();
+        match (var, &rec.fields) {
+            (None, Some(fields)) => self.rt.set_record_fields(&rec.text, fields),
+            _ => self.set_getline_target(var, &rec.text),
+        }
+        self.rt.increment_nr();
+        self.rt.increment_fnr();
+        Value::from_number(1.0)
+    }
+
+    /// Store a line read by getline in its target (a variable, element or
+    /// field, assigned like `=` does), or in `$0` when there is none.
+    fn set_getline_target(&mut self, var: Option<&Expr>, line: &str) {
+        match var {
+            Some(Expr::Var(name)) => self.rt.set_strnum(name, line),
+            Some(target) => self.assign_to(target, Value::from_strnum(line.to_string())),
+            None => self.rt.set_record(line),
+        }
+    }
 
-        // Fallback: no attached input (e.g. in BEGIN), read raw stdin
+    /// Read one line straight from the process's standard input; the
+    /// caller updates NR and FNR if the form counts.
+    fn getline_stdin(&mut self, var: Option<&Expr>) -> Value {
         let mut line = String::new();
         match std::io::stdin().read_line(&mut line) {
             Ok(0) => Value::from_number(0.0),
             Ok(_) => {
-                if line.ends_with('\n') {
-                    line.pop();
-                }
-                if line.ends_with('\r') {
-                    line.pop();
-                }
-                match var {
-                    Some(name) => self.rt.set_var(name, &line),
-                    None => self.rt.set_record(&line),
-                }
-                self.rt.increment_nr();
+                crate::input::strip_line_end(&mut line, self.rt.preserve_cr());
+                self.set_getline_target(var, &line);
                 Value::from_number(1.0)
             }
-            Err(_) => Value::from_number(-1.0),
+            Err(e) => {
+                self.set_errno(e);
+                Value::from_number(-1.0)
+            }
         }
     }
 
-    fn getline_from_file(&mut self, path: &str, var: Option<&str>) -> Value {
+    fn getline_from_file(&mut self, path: &str, var: Option<&Expr>) -> Value {
         if !self.input_files.contains_key(path) {
             match std::fs::File::open(path) {
                 Ok(file) => {
                     self.input_files
                         .insert(path.to_string(), std::io::BufReader::new(file));
+                    self.sync_stream_info(path);
+                }
+                Err(e) => {
+                    self.set_errno(e);
+                    return Value::from_number(-1.0);
                 }
-                Err(_) => return Value::from_number(-1.0),
             }
         }
         let reader = self.input_files.get_mut(path).unwrap();
@@ -1494,67 +2925,72 @@ impl<'a> Executor<'a> {
         match reader.read_line(&mut line) {
             Ok(0) => Value::from_number(0.0),
             Ok(_) => {
-                if line.ends_with('\n') {
-                    line.pop();
-                }
-                if line.ends_with('\r') {
-                    line.pop();
-                }
-                match var {
-                    Some(name) => self.rt.set_var(name, &line),
-                    None => self.rt.set_record(&line),
-                }
-                self.rt.increment_nr();
+                crate::input::strip_line_end(&mut line, self.rt.preserve_cr());
+                self.set_getline_target(var, &line);
                 Value::from_number(1.0)
             }
-            Err(_) => Value::from_number(-1.0),
+            Err(e) => {
+                self.set_errno(e);
+                Value::from_number(-1.0)
+            }
         }
     }
 
-    pub(crate) fn exec_getline_pipe(&mut self, cmd: &str, var: Option<&str>) -> Value {
-        if !self.input_pipe_readers.contains_key(cmd) {
-            match Command::new("sh")
-                .arg("-c")
-                .arg(cmd)
-                .stdout(Stdio::piped())
-                .spawn()
-            {
-                Ok(mut child) => {
-                    let stdout = child.stdout.take().unwrap();
-                    self.input_pipe_readers
-                        .insert(cmd.to_string(), std::io::BufReader::new(stdout));
-                    self.input_pipe_children.insert(cmd.to_string(), child);
+    /// `cmd | getline [var]`. Returns -2 when GETLINE_TIMEOUT (seconds)
+    /// elapses first; the command is then killed and reaped.
+    pub(crate) fn exec_getline_pipe(&mut self, cmd: &str, var: Option<&Expr>) -> Value {
+        let secs = self.rt.get_number("GETLINE_TIMEOUT");
+        let timeout = (secs > 0.0).then(|| Duration::from_secs_f64(secs));
+        if !self.input_pipes.contains_key(cmd) {
+            match PipeReader::spawn(cmd, self.rt.preserve_cr(), timeout.is_some()) {
+                Ok(p) => {
+                    self.input_pipes.insert(cmd.to_string(), p);
+                    self.sync_stream_info(cmd);
+                }
+                Err(e) => {
+                    self.set_errno(e);
+                    return Value::from_number(-1.0);
                 }
-                Err(_) => return Value::from_number(-1.0),
             }
         }
-        let reader = self.input_pipe_readers.get_mut(cmd).unwrap();
-        let mut line = String::new();
-        match reader.read_line(&mut line) {
-            Ok(0) => Value::from_number(0.0),
-            Ok(_) => {
-                if line.ends_with('\n') {
-                    line.pop();
-                }
-                if line.ends_with('\r') {
-                    line.pop();
-                }
-                match var {
-                    Some(name) => self.rt.set_var(name, &line),
-                    None => self.rt.set_record(&line),
-                }
+        let reader = self.input_pipes.get_mut(cmd).unwrap();
+        match reader.read_line(timeout) {
+            PipeRead::Eof => Value::from_number(0.0),
+            PipeRead::Line(line) => {
+                self.set_getline_target(var, &line);
                 self.rt.increment_nr();
                 Value::from_number(1.0)
             }
-            Err(_) => Value::from_number(-1.0),
+            PipeRead::Timeout => {
+                self.kill_input_pipe(cmd);
+                self.sync_stream_info(cmd);
+                Value::from_number(-2.0)
+            }
+            PipeRead::Error(e) => {
+                warn!(self, "getline from '{}': {}", cmd, e);
+                self.set_errno(e);
+                self.kill_input_pipe(cmd);
+                self.sync_stream_info(cmd);
+                Value::from_number(-1.0)
+            }
+        }
+    }
+
+    /// Kill a `cmd | getline` command that is being abandoned.
+    fn kill_input_pipe(&mut self, cmd: &str) {
+        if let Some(p) = self.input_pipes.remove(cmd)
+            && let Err(e) = p.kill()
+        {
+            warn!(self, "getline from '{}': cannot kill command: {}", cmd, e);
         }
     }
+
     // ── Clear-and-return ─────────────────────────────────────────────
 
     /// clr(var) — return current value, then clear the variable to "".
     pub(crate) fn builtin_clr(&mut self, args: &[Expr]) -> Value {
         if args.len() != 1 {
-            eprintln!("fk: clr requires exactly 1 argument");
+            warn!(self, "clr requires exactly 1 argument");
             return Value::from_string(String::new());
         }
         let val = self.eval_expr(&args[0]);
@@ -1568,11 +3004,14 @@ impl<'a> Executor<'a> {
 
     /// Call a named function (builtin or user-defined) with a single string arg.
     fn call_func_by_name(&mut self, name: &str, arg: &str) -> String {
-        let args = &[arg.to_string()];
         if let Some(func) = self.functions.get(name).cloned() {
-            self.call_user_func(&func, args).to_string_val()
+            let args = [Value::from_strnum(arg.to_string())];
+            self.call_user_func(&func, &args, &[]).to_string_val()
+        } else if builtins::is_builtin_func(name) {
+            builtins::call_builtin(name, &[arg.to_string()])
         } else {
-            builtins::call_builtin(name, args)
+            warn!(self, "unknown function: {}", name);
+            String::new()
         }
     }
 
@@ -1581,7 +3020,10 @@ impl<'a> Executor<'a> {
     /// Optional fmt: %s for func result and original value.
     pub(crate) fn builtin_map(&mut self, args: &[Expr]) -> Value {
         if args.len() < 2 {
-            eprintln!("fk: map requires at least 2 arguments: map(arr, \"func\" [, fmt])");
+            warn!(
+                self,
+                "map requires at least 2 arguments: map(arr, \"func\" [, fmt])"
+            );
             return Value::default();
         }
         let array_name = match self.resolve_array_arg(&args[0], "map") {
@@ -1611,7 +3053,7 @@ impl<'a> Executor<'a> {
     /// filter(arr, "func") — keep elements where func(val) is truthy, re-key 1..N.
     pub(crate) fn builtin_filter(&mut self, args: &[Expr]) -> Value {
         if args.len() < 2 {
-            eprintln!("fk: filter requires 2 arguments: filter(arr, \"func\")");
+            warn!(self, "filter requires 2 arguments: filter(arr, \"func\")");
             return Value::from_number(0.0);
         }
         let array_name = match self.resolve_array_arg(&args[0], "filter") {
@@ -1641,7 +3083,7 @@ impl<'a> Executor<'a> {
     /// dump(x [, file]) — write detailed variable/array info to stderr or file.
     pub(crate) fn builtin_dump(&mut self, args: &[Expr]) -> Value {
         if args.is_empty() {
-            eprintln!("fk: dump requires at least 1 argument");
+            warn!(self, "dump requires at least 1 argument");
             return Value::from_number(0.0);
         }
 
@@ -1658,16 +3100,34 @@ impl<'a> Executor<'a> {
         {
             let mut keys = self.rt.array_keys(name);
             smart_sort_keys(&mut keys);
-            let sequential = is_sequential(&keys);
+            let subsep = self.rt.get_var("SUBSEP");
+            let sequential = is_sequential(&keys, &subsep);
+            let shown = self.dump_sep();
             buf.push_str(&format!(
                 "dump: {} = array ({} elements{})\n",
                 name,
                 keys.len(),
                 if sequential { ", sequential" } else { "" }
             ));
+            // Composite keys: how many elements share each first subscript.
+            let mut groups: Vec<(&str, usize)> = Vec::new();
             for k in &keys {
                 let v = self.rt.get_array(name, k);
-                buf.push_str(&format!("  [{}] = \"{}\"\n", k, v));
+                let key = show_key(k, &subsep, &shown);
+                buf.push_str(&format!("  [{}] = \"{}\"\n", key, v));
+                if let Some((first, _)) = k.split_once(subsep.as_str()) {
+                    match groups.iter_mut().find(|(f, _)| *f == first) {
+                        Some((_, n)) => *n += 1,
+                        None => groups.push((first, 1)),
+                    }
+                }
+            }
+            if !groups.is_empty() {
+                let counts: Vec<String> = groups
+                    .iter()
+                    .map(|(first, n)| format!("{}{}* ({})", first, shown, n))
+                    .collect();
+                buf.push_str(&format!("  by first subscript: {}\n", counts.join(", ")));
             }
             return self.dump_output(&buf, args);
         }
@@ -1685,6 +3145,15 @@ impl<'a> Executor<'a> {
         self.dump_output(&buf, args)
     }
 
+    /// What `print arr` and dump() show in place of SUBSEP: DUMPSEP, or
+    /// "," when unset.
+    fn dump_sep(&self) -> String {
+        match self.rt.get_var("DUMPSEP") {
+            s if s.is_empty() => ",".to_string(),
+            s => s,
+        }
+    }
+
     fn dump_output(&mut self, buf: &str, args: &[Expr]) -> Value {
         if args.len() >= 2 {
             let filename = self.eval_string(&args[1]);
@@ -1696,9 +3165,10 @@ impl<'a> Executor<'a> {
                 Ok(mut f) => {
                     let _ = f.write_all(buf.as_bytes());
                 }
-                Err(e) => eprintln!("fk: dump: {}: {}", filename, e),
+                Err(e) => warn!(self, "dump: {}: {}", filename, e),
             }
         } else {
+            crate::progress::clear();
             eprint!("{}", buf);
         }
         Value::from_number(1.0)
@@ -1732,11 +3202,47 @@ impl<'a> Executor<'a> {
         Value::from_number(t.elapsed().as_secs_f64())
     }
 
+    /// lap([id]) — seconds since start(id) (or the last lap), then restart
+    /// the timer from the same instant, so consecutive laps add up.
+    pub(crate) fn builtin_lap(&mut self, args: &[Expr]) -> Value {
+        let id = if args.is_empty() {
+            String::new()
+        } else {
+            self.eval_string(&args[0])
+        };
+        let now = Instant::now();
+        let since = *self.timers.get(&id).unwrap_or(&self.epoch);
+        self.timers.insert(id, now);
+        Value::from_number(now.duration_since(since).as_secs_f64())
+    }
+
+    /// Evaluate an optional comparator argument ("num", "str", "nat", "ci",
+    /// "nat+ci"). Unknown names warn and fall back to the default order.
+    fn sort_mode_arg(&mut self, arg: Option<&Expr>, func: &str) -> Option<SortMode> {
+        let name = self.eval_string(arg?);
+        self.parse_sort_mode(&name, func)
+    }
+
+    /// Parse a sort mode name; "" means the default order, anything unknown warns.
+    fn parse_sort_mode(&mut self, name: &str, func: &str) -> Option<SortMode> {
+        if name.is_empty() {
+            return None;
+        }
+        let mode = SortMode::parse(name);
+        if mode.is_none() {
+            warn!(self, "{}: unknown sort mode '{}'", func, name);
+        }
+        mode
+    }
+
     /// Collect (key, numeric_value) pairs from an array, sorted sensibly.
     fn collect_chart_entries(&self, array_name: &str) -> Vec<(String, f64)> {
         let mut numeric_keys: Vec<(i64, String)> = Vec::new();
         let mut other_keys: Vec<String> = Vec::new();
         for k in self.rt.array_keys(array_name) {
+            if k.starts_with('_') {
+                continue;
+            }
             if let Ok(n) = k.parse::<i64>() {
                 numeric_keys.push((n, k));
             } else {
@@ -1762,26 +3268,158 @@ impl<'a> Executor<'a> {
     }
 }
 
-/// Sort keys numeric-first (1, 2, 10 before "apple", "banana").
-fn smart_sort_keys(keys: &mut [String]) {
-    keys.sort_by(|a, b| {
-        let na = a.parse::<f64>();
-        let nb = b.parse::<f64>();
-        match (na, nb) {
-            (Ok(x), Ok(y)) => x.partial_cmp(&y).unwrap_or(std::cmp::Ordering::Equal),
-            (Ok(_), Err(_)) => std::cmp::Ordering::Less,
-            (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
-            (Err(_), Err(_)) => a.cmp(b),
-        }
-    });
+/// Numbers before strings; numbers numerically, strings bytewise.
+pub(crate) fn smart_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => x.partial_cmp(&y).unwrap_or(std::cmp::Ordering::Equal),
+        (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+        (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+/// Stable merge sort for user comparators: unlike `sort_by`, an
+/// inconsistent comparison function cannot make it panic.
+fn merge_sort_by<T>(
+    mut items: Vec<T>,
+    cmp: &mut impl FnMut(&T, &T) -> std::cmp::Ordering,
+) -> Vec<T> {
+    if items.len() <= 1 {
+        return items;
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort_by(items, cmp);
+    let right = merge_sort_by(right, cmp);
+    let mut out = Vec::with_capacity(left.len() + right.len());
+    let mut l = left.into_iter().peekable();
+    let mut r = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (l.peek(), r.peek()) {
+        if cmp(b, a) == std::cmp::Ordering::Less {
+            out.extend(r.next());
+        } else {
+            out.extend(l.next());
+        }
+    }
+    out.extend(l);
+    out.extend(r);
+    out
+}
+
+/// Sort keys with an explicit comparator, or the default smart order.
+fn sort_keys_with(keys: &mut [String], mode: Option<SortMode>) {
+    match mode {
+        Some(m) => keys.sort_by(|a, b| m.compare(a, b)),
+        None => smart_sort_keys(keys),
+    }
+}
+
+/// Comparator selected by the optional mode argument of asort/asorti/keys/vals.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum SortMode {
+    /// Numbers first, numerically; then strings bytewise (smart_sort_keys order).
+    Num,
+    /// Bytewise.
+    Str,
+    /// Natural: digit runs compare as numbers ("file2" < "file10").
+    Nat,
+    /// Case-insensitive.
+    Ci,
+    /// Natural and case-insensitive.
+    NatCi,
+}
+
+impl SortMode {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s {
+            "num" => Some(SortMode::Num),
+            "str" => Some(SortMode::Str),
+            "nat" => Some(SortMode::Nat),
+            "ci" => Some(SortMode::Ci),
+            "nat+ci" | "ci+nat" => Some(SortMode::NatCi),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn compare(self, a: &str, b: &str) -> std::cmp::Ordering {
+        match self {
+            SortMode::Num => smart_cmp(a, b),
+            SortMode::Str => a.cmp(b),
+            SortMode::Nat => natural_cmp(a, b, false),
+            SortMode::Ci => a
+                .chars()
+                .flat_map(char::to_lowercase)
+                .cmp(b.chars().flat_map(char::to_lowercase))
+                .then_with(|| a.cmp(b)),
+            SortMode::NatCi => natural_cmp(a, b, true),
+        }
+    }
+}
+
+/// Natural order: runs of ASCII digits compare by numeric value (leading
+/// zeros ignored, then fewer zeros first); everything else char by char,
+/// optionally case-folded. Ties fall back to bytewise order.
+pub(crate) fn natural_cmp(a: &str, b: &str, ci: bool) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    let (ab, bb) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    let digit_run = |s: &[u8], mut k: usize| {
+        let start = k;
+        while k < s.len() && s[k].is_ascii_digit() {
+            k += 1;
+        }
+        (start, k)
+    };
+    while i < ab.len() && j < bb.len() {
+        if ab[i].is_ascii_digit() && bb[j].is_ascii_digit() {
+            let (sa, ea) = digit_run(ab, i);
+            let (sb, eb) = digit_run(bb, j);
+            let da = &ab[sa..ea];
+            let db = &bb[sb..eb];
+            let ta = &da[da.iter().take_while(|&&c| c == b'0').count()..];
+            let tb = &db[db.iter().take_while(|&&c| c == b'0').count()..];
+            let ord = ta
+                .len()
+                .cmp(&tb.len())
+                .then_with(|| ta.cmp(tb))
+                .then_with(|| da.len().cmp(&db.len()));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            i = ea;
+            j = eb;
+            continue;
+        }
+        let ca = a[i..].chars().next().unwrap_or_default();
+        let cb = b[j..].chars().next().unwrap_or_default();
+        let ord = if ci {
+            ca.to_lowercase().cmp(cb.to_lowercase())
+        } else {
+            ca.cmp(&cb)
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+        i += ca.len_utf8();
+        j += cb.len_utf8();
+    }
+    (ab.len() - i).cmp(&(bb.len() - j)).then_with(|| a.cmp(b))
+}
+
+/// Check if sorted keys are sequential 1..N. A key holding SUBSEP is a
+/// composite subscript, never a position (even when SUBSEP is a digit).
+fn is_sequential(sorted_keys: &[String], subsep: &str) -> bool {
+    sorted_keys.iter().enumerate().all(|(i, k)| {
+        (subsep.is_empty() || !k.contains(subsep)) && k.parse::<usize>().is_ok_and(|n| n == i + 1)
+    })
 }
 
-/// Check if sorted keys are sequential 1..N.
-fn is_sequential(sorted_keys: &[String]) -> bool {
-    sorted_keys
-        .iter()
-        .enumerate()
-        .all(|(i, k)| k.parse::<usize>().is_ok_and(|n| n == i + 1))
+/// `key` for display, with each SUBSEP replaced by `shown`.
+fn show_key<'k>(key: &'k str, subsep: &str, shown: &str) -> Cow<'k, str> {
+    if subsep.is_empty() || !key.contains(subsep) {
+        Cow::Borrowed(key)
+    } else {
+        Cow::Owned(key.replace(subsep, shown))
+    }
 }
 
 // --- Histogram binning (uplot-style nice numbers) ---
@@ -1820,6 +3458,65 @@ fn nice_hist_bins(data_min: f64, data_max: f64, n: usize) -> (f64, f64, usize) {
 
 // --- Chart rendering helpers ---
 
+/// Lower eighth blocks used for partial column tops in vplot().
+/// 1-based char start and char length of a regex match within `s`.
+fn char_span(s: &str, m: &regex::Match) -> (usize, usize) {
+    (
+        s[..m.start()].chars().count() + 1,
+        m.as_str().chars().count(),
+    )
+}
+
+const VBLOCKS: [char; 7] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇'];
+
+/// Shade ramp for heatmap(), lightest (empty) to darkest.
+const HEAT_RAMP: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+/// Options shared by plot(), plotbox() and vplot().
+#[derive(Default)]
+struct ChartOpts {
+    /// Bar width (plot/plotbox) or column height (vplot).
+    size: Option<usize>,
+    ch: Option<char>,
+    precision: Option<usize>,
+    title: Option<String>,
+    xlabel: Option<String>,
+    color: String,
+    log: bool,
+}
+
+impl ChartOpts {
+    /// Apply one named option; returns false for unknown names.
+    fn apply(&mut self, name: &str, val: &str) -> bool {
+        match name {
+            "width" | "height" => {
+                let n = builtins::to_number(val).round() as i64;
+                self.size = if n > 0 { Some(n as usize) } else { None };
+            }
+            "char" => self.ch = val.chars().next(),
+            "precision" => {
+                let n = builtins::to_number(val).round() as i64;
+                self.precision = if n < 0 { None } else { Some(n as usize) };
+            }
+            "title" => self.title = Some(val.to_string()),
+            "xlabel" => self.xlabel = Some(val.to_string()),
+            "color" => self.color = val.to_string(),
+            "scale" => self.log = val == "log",
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Map a value onto the chart axis: identity, or log10(1 + v) for log scale.
+fn chart_scale(value: f64, log: bool) -> f64 {
+    if log {
+        (1.0 + value.max(0.0)).log10()
+    } else {
+        value
+    }
+}
+
 fn ansi_color(name: &str) -> (&'static str, &'static str) {
     let code = match name {
         "red" => "\x1b[31m",
@@ -1907,3 +3604,17 @@ fn build_chart_labels(
         entries.iter().map(|(key, _)| key.clone()).collect()
     }
 }
+
+/// `s` split on `fs` (as by `split()`) with repeats dropped, first-seen
+/// order kept. An empty string has no values.
+fn unique_parts<'s>(s: &'s str, fs: &str) -> Vec<&'s str> {
+    if s.is_empty() {
+        return Vec::new();
+    }
+    let mut seen = HashSet::new();
+    if fs == " " {
+        s.split_whitespace().filter(|p| seen.insert(*p)).collect()
+    } else {
+        s.split(fs).filter(|p| seen.insert(*p)).collect()
+    }
+}
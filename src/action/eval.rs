@@ -1,12 +1,64 @@
+use std::borrow::Cow;
 use std::io::Write;
 
-use crate::builtins::{self, format_printf};
+use crate::builtins;
+use crate::builtins::printf::{Arg, ArgMismatch, Format};
 use crate::parser::{BinOp, Expr};
 use crate::runtime::Value;
 
 use super::{Executor, bool_val};
 
 impl<'a> Executor<'a> {
+    /// Evaluate a loop condition for its truth (see `Value::is_truthy`).
+    /// Arithmetic, and comparisons whose operands are surely numbers, take
+    /// the `eval_number` fast path; anything that may be a string does not,
+    /// so `while (s)` and `while ($1 == "x")` follow the string rules.
+    pub(crate) fn eval_cond(&mut self, expr: &Expr) -> bool {
+        match expr {
+            Expr::NumberLit(n) => *n != 0.0,
+            Expr::BinOp(_, op, _) if is_arith(op) => self.eval_number(expr) != 0.0,
+            Expr::BinOp(left, op, right) => {
+                let (lo, ro) = (self.operand(left), self.operand(right));
+                if matches!(lo, Operand::Text) || matches!(ro, Operand::Text) {
+                    return self.eval_expr(expr).is_truthy();
+                }
+                let l = match lo {
+                    Operand::Known(n) => n,
+                    _ => self.eval_number(left),
+                };
+                // A right-hand variable read before the left side ran is
+                // read again in case the left side changed it.
+                let r = match ro {
+                    Operand::Known(n) if !matches!(lo, Operand::Computed) => n,
+                    _ => self.eval_number(right),
+                };
+                match op {
+                    BinOp::Eq => l == r,
+                    BinOp::Ne => l != r,
+                    BinOp::Lt => l < r,
+                    BinOp::Le => l <= r,
+                    BinOp::Gt => l > r,
+                    _ => l >= r,
+                }
+            }
+            _ => self.eval_expr(expr).is_truthy(),
+        }
+    }
+
+    /// Classify a comparison operand without running it.
+    fn operand(&self, expr: &Expr) -> Operand {
+        match expr {
+            Expr::NumberLit(n) => Operand::Known(*n),
+            Expr::Var(name) => self
+                .rt
+                .var_number(name)
+                .map_or(Operand::Text, Operand::Known),
+            Expr::UnaryMinus(_) | Expr::Increment(..) | Expr::Decrement(..) => Operand::Computed,
+            Expr::BinOp(_, op, _) if is_arith(op) => Operand::Computed,
+            _ => Operand::Text,
+        }
+    }
+
     /// Fast path: evaluate an expression as f64 without constructing a Value.
     /// Covers the common cases in arithmetic tight loops.
     pub(crate) fn eval_number(&mut self, expr: &Expr) -> f64 {
@@ -22,7 +74,7 @@ impl<'a> Executor<'a> {
                     BinOp::Mul => l * r,
                     BinOp::Pow => {
                         let ei = r as i32;
-                        if r == ei as f64 && ei >= 0 && ei <= 10 {
+                        if r == ei as f64 && (0..=10).contains(&ei) {
                             match ei {
                                 0 => 1.0,
                                 1 => l,
@@ -105,24 +157,21 @@ impl<'a> Executor<'a> {
                 }
             }
             Expr::UnaryMinus(inner) => -self.eval_number(inner),
-            Expr::Increment(target, pre) => {
-                let n = self.eval_number(target);
-                let new = n + 1.0;
-                if let Expr::Var(name) = target.as_ref() {
-                    self.rt.set_number(name, new);
+            Expr::Increment(target, pre) | Expr::Decrement(target, pre) => {
+                let step = if matches!(expr, Expr::Increment(..)) {
+                    1.0
                 } else {
-                    self.assign_to(target, Value::from_number(new));
-                }
-                if *pre { new } else { n }
-            }
-            Expr::Decrement(target, pre) => {
-                let n = self.eval_number(target);
-                let new = n - 1.0;
-                if let Expr::Var(name) = target.as_ref() {
-                    self.rt.set_number(name, new);
+                    -1.0
+                };
+                let (n, new) = if let Expr::Var(name) = target.as_ref() {
+                    let n = self.rt.get_number(name);
+                    self.rt.set_number(name, n + step);
+                    (n, n + step)
                 } else {
-                    self.assign_to(target, Value::from_number(new));
-                }
+                    let (old, new) =
+                        self.update_place(target, &BinOp::Add, Value::from_number(step));
+                    (old.to_number(), new.to_number())
+                };
                 if *pre { new } else { n }
             }
             Expr::LogicalAnd(left, right) => {
@@ -135,9 +184,7 @@ impl<'a> Executor<'a> {
                 }
             }
             Expr::LogicalOr(left, right) => {
-                if self.eval_number(left) != 0.0 {
-                    1.0
-                } else if self.eval_number(right) != 0.0 {
+                if self.eval_number(left) != 0.0 || self.eval_number(right) != 0.0 {
                     1.0
                 } else {
                     0.0
@@ -159,6 +206,10 @@ impl<'a> Executor<'a> {
                 }
                 n
             }
+            Expr::CompoundAssign(target, op, value) if !matches!(target.as_ref(), Expr::Var(_)) => {
+                let rhs = self.eval_expr(value);
+                self.update_place(target, op, rhs).1.to_number()
+            }
             Expr::CompoundAssign(target, op, value) => {
                 let current = self.eval_number(target);
                 let rhs = self.eval_number(value);
@@ -201,11 +252,11 @@ impl<'a> Executor<'a> {
             Expr::Var(name) => self.rt.get_value(name),
             Expr::Field(idx_expr) => {
                 let idx = self.eval_field_idx(idx_expr);
-                Value::from_string(self.rt.get_field(idx))
+                Value::from_strnum(self.rt.get_field(idx))
             }
             Expr::ArrayRef(name, key_expr) => {
                 let key = self.eval_expr(key_expr).into_string();
-                self.rt.get_array_value(name, &key)
+                self.rt.get_or_create_array_value(name, &key)
             }
             Expr::ArrayIn(key_expr, array) => {
                 let key = self.eval_expr(key_expr).into_string();
@@ -251,25 +302,7 @@ impl<'a> Executor<'a> {
                 let pat = self.eval_expr(pat_expr).into_string();
                 bool_val(!self.regex_is_match(&pat, &val))
             }
-            Expr::Concat(left, right) => {
-                let l = self.eval_expr(left);
-                let r = self.eval_expr(right);
-                if l.is_null() || r.is_null() {
-                    return Value::null();
-                }
-                let convfmt = self.rt.convfmt();
-                let mut s = if l.is_numeric_only() && convfmt != "%.6g" {
-                    builtins::format_number_fmt(l.to_number(), convfmt)
-                } else {
-                    l.into_string()
-                };
-                if r.is_numeric_only() && convfmt != "%.6g" {
-                    s.push_str(&builtins::format_number_fmt(r.to_number(), convfmt));
-                } else {
-                    r.write_to_string(&mut s);
-                }
-                Value::from_string(s)
-            }
+            Expr::Concat(..) => self.eval_concat(expr),
             Expr::Assign(target, value) => {
                 let val = self.eval_expr(value);
                 if let Expr::Var(name) = target.as_ref() {
@@ -313,11 +346,8 @@ impl<'a> Executor<'a> {
                     self.rt.set_number(name, result);
                     Value::from_number(result)
                 } else {
-                    let current = self.eval_lvalue(target);
                     let rhs = self.eval_expr(value);
-                    let result = eval_binop(current, op, rhs);
-                    self.assign_to(target, result.clone());
-                    result
+                    self.update_place(target, op, rhs).1
                 }
             }
             Expr::Increment(target, pre) => {
@@ -330,11 +360,12 @@ impl<'a> Executor<'a> {
                         Value::from_number(n)
                     };
                 }
-                let current = self.eval_lvalue(target);
-                let n = current.to_number();
-                let new_val = Value::from_number(n + 1.0);
-                self.assign_to(target, new_val.clone());
-                if *pre { new_val } else { Value::from_number(n) }
+                let (old, new) = self.update_place(target, &BinOp::Add, Value::from_number(1.0));
+                if *pre {
+                    new
+                } else {
+                    Value::from_number(old.to_number())
+                }
             }
             Expr::Decrement(target, pre) => {
                 if let Expr::Var(name) = target.as_ref() {
@@ -346,11 +377,12 @@ impl<'a> Executor<'a> {
                         Value::from_number(n)
                     };
                 }
-                let current = self.eval_lvalue(target);
-                let n = current.to_number();
-                let new_val = Value::from_number(n - 1.0);
-                self.assign_to(target, new_val.clone());
-                if *pre { new_val } else { Value::from_number(n) }
+                let (old, new) = self.update_place(target, &BinOp::Add, Value::from_number(-1.0));
+                if *pre {
+                    new
+                } else {
+                    Value::from_number(old.to_number())
+                }
             }
             Expr::UnaryMinus(inner) => {
                 let val = self.eval_expr(inner);
@@ -389,11 +421,10 @@ impl<'a> Executor<'a> {
                 }
             }
             Expr::Sprintf(args) => {
-                let evaled: Vec<String> = args.iter().map(|e| self.eval_string(e)).collect();
-                if evaled.is_empty() {
+                if args.is_empty() {
                     Value::default()
                 } else {
-                    Value::from_string(format_printf(&evaled[0], &evaled[1..]))
+                    Value::from_string(self.printf_text("sprintf", args))
                 }
             }
             Expr::FuncCall(name, args) => {
@@ -402,9 +433,12 @@ impl<'a> Executor<'a> {
                     "gsub" => return self.builtin_sub(args, true),
                     "match" => return self.builtin_match(args),
                     "split" => return self.builtin_split(args),
+                    "uniqsplit" => return self.builtin_uniqsplit(args),
+                    "joinuniq" => return self.builtin_joinuniq(args),
                     "jpath" | "jp" if args.len() >= 3 => {
                         return self.builtin_jpath_extract(args);
                     }
+                    "jpretty" | "jmin" => return self.builtin_jformat(name, args),
                     "length" | "len" | "l" if args.is_empty() => {
                         let s = self.rt.get_field(0);
                         return Value::from_number(s.chars().count() as f64);
@@ -417,20 +451,46 @@ impl<'a> Executor<'a> {
                         }
                     }
                     "close" => return self.builtin_close(args),
+                    "outfile" => return self.builtin_outfile(args),
+                    "writecsv" => return self.builtin_writecsv(args),
                     "gensub" => return self.builtin_gensub(args),
                     "fflush" => return self.builtin_fflush(args),
+                    "flushmode" => return self.builtin_flushmode(args),
                     "system" | "sys" => return self.builtin_system(args),
+                    "system_bg" => return self.builtin_system_bg(args),
+                    "wait" => return self.builtin_wait(args),
+                    "waitall" => return self.builtin_waitall(),
                     "join" => return self.builtin_join(args),
                     "typeof" => return self.builtin_typeof(args),
+                    "vars" => return self.builtin_names(args, false),
+                    "arrays" => return self.builtin_names(args, true),
                     "asort" => return self.builtin_asort(args, false),
                     "asorti" => return self.builtin_asort(args, true),
                     "keys" => return self.builtin_keys(args),
                     "vals" | "values" => return self.builtin_vals(args),
                     "uniq" | "unique" => return self.builtin_uniq(args),
+                    "uniqc" => return self.builtin_uniqc(args),
+                    "sortkeys" => return self.builtin_sortkeys(args),
+                    "uniqkeys" => return self.builtin_uniqkeys(args),
                     "inv" | "invert" => return self.builtin_invert(args),
                     "tidy" => return self.builtin_compact(args),
                     "shuf" | "shuffle" => return self.builtin_shuffle(args),
                     "flip" => return self.builtin_reverse_fields(),
+                    "fields" => return self.builtin_fields(args),
+                    "has" => return self.builtin_has(args),
+                    "header" => return self.builtin_header(args),
+                    "hdrlen" => return Value::from_number(self.header_len as f64),
+                    "tolower" | "lower" | "toupper" | "upper" if args.len() == 1 => {
+                        // Map the evaluated argument in place: no second
+                        // String when nothing changes or it is ASCII.
+                        let mut s = self.eval_string(&args[0]);
+                        if name.ends_with("upper") {
+                            builtins::string::uppercase(&mut s);
+                        } else {
+                            builtins::string::lowercase(&mut s);
+                        }
+                        return Value::from_string(s);
+                    }
                     "rev" | "reverse" if args.is_empty() => {
                         let s = self.rt.get_field(0);
                         let reversed: String = s.chars().rev().collect();
@@ -444,10 +504,25 @@ impl<'a> Executor<'a> {
                         }
                     }
                     "diff" | "inter" | "union" => return self.builtin_set_op(name, args),
+                    "copy" => return self.builtin_copy(args),
+                    "aempty" => return self.builtin_aempty(args),
+                    "amerge" => return self.builtin_amerge(args),
+                    "slice" => return self.builtin_slice(args),
                     "seq" | "sequence" => return self.builtin_seq(args),
+                    "sample" if args.len() == 1 => return self.builtin_sample_prob(args),
                     "samp" | "sample" => return self.builtin_sample(args),
                     "slurp" => return self.builtin_slurp(args),
+                    "cliargs" => return self.builtin_cliargs(args),
                     "collect" | "acc" | "accumulate" => return self.builtin_collect(args),
+                    "reservoir" => return self.builtin_reservoir(args),
+                    "topk" => return self.builtin_topk(args),
+                    "topk_result" => return self.builtin_topk_result(args),
+                    "hll" => return self.builtin_hll(args),
+                    "hll_count" => return self.builtin_hll_count(args),
+                    "hll_merge" => return self.builtin_hll_merge(args),
+                    "hll_dump" => return self.builtin_hll_dump(args),
+                    "hll_load" => return self.builtin_hll_load(args),
+                    "matchall" => return self.builtin_matchall(args),
                     "top" => return self.builtin_top_bottom(args, false),
                     "bottom" | "bot" => return self.builtin_top_bottom(args, true),
                     "runtotal" | "runtot" => return self.builtin_runtotal(args),
@@ -460,6 +535,7 @@ impl<'a> Executor<'a> {
                     "clk" | "clock" => return self.builtin_clock(),
                     "tic" | "start" => return self.builtin_start(args),
                     "toc" | "elapsed" => return self.builtin_elapsed(args),
+                    "lap" => return self.builtin_lap(args),
                     "and" | "or" | "xor" | "lshift" | "rshift" | "compl" => {
                         return self.builtin_bitwise(name, args);
                     }
@@ -476,6 +552,9 @@ impl<'a> Executor<'a> {
                     "hist" | "histogram" => return self.builtin_hist(args),
                     "plot" => return self.builtin_plot(args),
                     "plotbox" | "pbox" => return self.builtin_plotbox(args),
+                    "vplot" => return self.builtin_vplot(args),
+                    "heatmap" => return self.builtin_heatmap(args),
+                    "pivot" => return self.builtin_pivot(args),
                     "min" if args.len() == 1 => {
                         if let Expr::Var(v) = &args[0]
                             && self.rt.has_array(v)
@@ -492,11 +571,34 @@ impl<'a> Executor<'a> {
                     }
                     _ => {}
                 }
-                let evaled: Vec<String> = args.iter().map(|e| self.eval_string(e)).collect();
                 if let Some(func) = self.functions.get(name).cloned() {
-                    self.call_user_func(&func, &evaled)
+                    let vals: Vec<Value> = args.iter().map(|e| self.eval_expr(e)).collect();
+                    // An array, or an untyped name that may become one.
+                    let arrays: Vec<Option<String>> = args
+                        .iter()
+                        .map(|e| match e {
+                            Expr::Var(v)
+                                if !self.rt.has_var(v)
+                                    || self.rt.has_array(v)
+                                    || self.rt.is_bound_array(v) =>
+                            {
+                                Some(v.clone())
+                            }
+                            _ => None,
+                        })
+                        .collect();
+                    self.call_user_func(&func, &vals, &arrays)
+                } else if builtins::is_builtin_func(name) {
+                    let evaled: Vec<String> = args.iter().map(|e| self.eval_string(e)).collect();
+                    let out = builtins::call_builtin(name, &evaled);
+                    if builtins::returns_number(name) {
+                        Value::from_strnum(out)
+                    } else {
+                        Value::from_string(out)
+                    }
                 } else {
-                    Value::from_string(builtins::call_builtin(name, &evaled))
+                    warn!(self, "unknown function: {}", name);
+                    Value::from_string(String::new())
                 }
             }
             Expr::Getline(var, source) => self.exec_getline(var.as_deref(), source.as_deref()),
@@ -535,6 +637,99 @@ impl<'a> Executor<'a> {
         val.write_to(&mut self.stdout);
     }
 
+    /// The output line of a redirected `print exprs`, formatted into the
+    /// reusable `print_buf` so it reaches the file or pipe in one write
+    /// (hand the buffer back afterwards).
+    pub(crate) fn print_line(&mut self, exprs: &[Expr]) -> Vec<u8> {
+        let mut line = std::mem::take(&mut self.print_buf);
+        line.clear();
+        let ofs = self.snapshot_ofs(exprs.len());
+        for (i, e) in exprs.iter().enumerate() {
+            if i > 0 {
+                line.extend_from_slice(ofs.as_bytes());
+            }
+            match e {
+                Expr::Field(idx_expr) if matches!(idx_expr.as_ref(), Expr::NumberLit(_)) => {
+                    let idx = self.eval_field_idx(idx_expr);
+                    self.rt.write_field_to(idx, &mut line);
+                }
+                _ => self.eval_expr(e).write_to(&mut line),
+            }
+        }
+        self.print_ofs = ofs;
+        line.extend_from_slice(self.rt.ors().as_bytes());
+        line
+    }
+
+    /// OFS as it is before a print's values are evaluated, copied into the
+    /// reusable `print_ofs` (hand it back afterwards). Only needed when
+    /// there is more than one value.
+    pub(crate) fn snapshot_ofs(&mut self, values: usize) -> String {
+        let mut ofs = std::mem::take(&mut self.print_ofs);
+        if values > 1 {
+            ofs.clear();
+            ofs.push_str(self.rt.ofs());
+        }
+        ofs
+    }
+
+    /// printf/sprintf output for `exprs` (the format first). Formats seen
+    /// as literals in the program come pre-parsed from `printf_formats`.
+    /// `name` is the caller, for the argument-count warning.
+    pub(crate) fn printf_text(&mut self, name: &str, exprs: &[Expr]) -> String {
+        let fmt = match &exprs[0] {
+            Expr::StringLit(lit) => Cow::Borrowed(lit.as_str()),
+            e => Cow::Owned(self.eval_string(e)),
+        };
+        let args: Vec<Arg> = exprs[1..]
+            .iter()
+            .map(|e| {
+                let v = self.eval_expr(e);
+                let number = v.counts_as_number();
+                Arg {
+                    text: v.into_string(),
+                    number,
+                }
+            })
+            .collect();
+        let seps = self.rt.num_seps();
+        let dynamic;
+        let parsed = match self.printf_formats.get(fmt.as_ref()) {
+            Some(parsed) => parsed,
+            None => {
+                dynamic = Format::parse(&fmt);
+                &dynamic
+            }
+        };
+        let mismatch = parsed.check_args(args.len());
+        let text = parsed.render_with(&args, &seps);
+        if let Some(mismatch) = mismatch {
+            self.printf_mismatch(name, &fmt, mismatch);
+        }
+        text
+    }
+
+    /// Report arguments that do not fit a printf format: a warning each
+    /// time (missing ones format as "" or 0), or with `--strict` an error
+    /// that ends the run.
+    fn printf_mismatch(&mut self, name: &str, fmt: &str, mismatch: ArgMismatch) {
+        let problem = match mismatch {
+            ArgMismatch::Missing { conversion, spec } => {
+                format!("no argument for conversion {} ({})", conversion, spec)
+            }
+            ArgMismatch::Extra(1) => "1 extra argument ignored".to_string(),
+            ArgMismatch::Extra(n) => format!("{} extra arguments ignored", n),
+        };
+        if !self.strict {
+            warn!(self, "{} {:?}: {}", name, fmt, problem);
+            return;
+        }
+        crate::progress::clear();
+        eprintln!("fk: {} {:?}: {}", name, fmt, problem);
+        self.flush_all();
+        std::process::exit(2);
+    }
+
     /// Resolve a field index, supporting negative values ($-1 = last field).
     pub(crate) fn resolve_field_idx(&self, n: f64) -> usize {
         let i = n as isize;
@@ -553,53 +748,297 @@ impl<'a> Executor<'a> {
         if let Expr::NumberLit(n) = idx_expr {
             return self.resolve_field_idx(*n);
         }
+        if let Some(idx) = self.header_column(idx_expr) {
+            return idx;
+        }
         let val = self.eval_expr(idx_expr);
+        match self.field_idx_of(val) {
+            Ok(idx) => idx,
+            // A key no object has had yet reads as empty, like a key this
+            // object lacks, rather than as $0.
+            Err(name) if self.keyed_header && !name.is_empty() => self.rt.nf() + 1,
+            Err(_) => 0,
+        }
+    }
+
+    /// `$name` where the program has no variable `name` but HDR has a
+    /// column of that name: that column.
+    fn header_column(&self, idx_expr: &Expr) -> Option<usize> {
+        let Expr::Var(name) = idx_expr else {
+            return None;
+        };
+        if self.rt.has_var(name) {
+            return None;
+        }
+        let idx = crate::builtins::to_number(&self.rt.get_array_opt("HDR", name)?);
+        (idx > 0.0).then(|| self.resolve_field_idx(idx))
+    }
+
+    /// The field a `$` index value names; `Err(name)` for a column name
+    /// not in HDR.
+    fn field_idx_of(&self, val: Value) -> Result<usize, String> {
         if val.is_numeric() {
-            return self.resolve_field_idx(val.to_number());
+            return Ok(self.resolve_field_idx(val.to_number()));
         }
-        let s = val.to_string_val();
+        let s = val.into_string();
         let n = crate::builtins::to_number(&s);
         if n != 0.0 || s == "0" {
-            return self.resolve_field_idx(n);
+            return Ok(self.resolve_field_idx(n));
         }
         if let Some(idx_val) = self.rt.get_array_opt("HDR", &s) {
             let idx = crate::builtins::to_number(&idx_val);
             if idx > 0.0 {
-                return self.resolve_field_idx(idx);
+                return Ok(self.resolve_field_idx(idx));
             }
         }
-        0
+        Err(s)
     }
 
-    pub(crate) fn eval_lvalue(&mut self, expr: &Expr) -> Value {
+    /// Evaluate an assignment target's subscript or field index, once, so
+    /// `+=`, `++` and sub() read and write the same element. A `$"name"`
+    /// missing from HDR is reported (once per name) and goes nowhere.
+    pub(crate) fn place<'t>(&mut self, target: &'t Expr) -> Place<'t> {
+        match target {
+            Expr::Var(name) => Place::Var(name),
+            Expr::ArrayRef(name, key_expr) => Place::Elem(name, self.eval_string(key_expr)),
+            Expr::Field(idx_expr) => {
+                if let Expr::NumberLit(n) = idx_expr.as_ref() {
+                    return Place::Field(self.resolve_field_idx(*n));
+                }
+                if let Some(idx) = self.header_column(idx_expr) {
+                    return Place::Field(idx);
+                }
+                let val = self.eval_expr(idx_expr);
+                match self.field_idx_of(val) {
+                    Ok(idx) => Place::Field(idx),
+                    // Without a header, a non-numeric index is $0 as in awk.
+                    Err(_) if self.rt.array_len("HDR") == 0 => Place::Field(0),
+                    Err(name) => {
+                        if self.unknown_fields.insert(name.clone()) {
+                            warn!(
+                                self,
+                                "cannot assign to $\"{}\": no such column in HDR", name
+                            );
+                        }
+                        Place::Nowhere
+                    }
+                }
+            }
+            _ => Place::Nowhere,
+        }
+    }
+
+    /// The current value at `place` (uninitialized for `Nowhere`).
+    pub(crate) fn place_value(&mut self, place: &Place) -> Value {
+        match place {
+            Place::Var(name) => self.rt.get_value(name),
+            Place::Elem(name, key) => self.rt.get_array_value(name, key),
+            Place::Field(idx) => Value::from_strnum(self.rt.get_field(*idx)),
+            Place::Nowhere => Value::default(),
+        }
+    }
+
+    /// Store `value` at `place`. Setting a field past NF extends the
+    /// record; any field assignment rebuilds $0.
+    pub(crate) fn store(&mut self, place: Place, value: Value) {
+        match place {
+            Place::Var(name) => self.rt.set_value(name, value),
+            Place::Elem(name, key) => self.rt.set_array_value(name, &key, value),
+            Place::Field(idx) => {
+                self.rt.set_field(idx, &value.into_string());
+                // A program that never reads fields or NF (say, gsub then
+                // print) leaves the new $0 unsplit.
+                if idx == 0 && (self.info.needs_fields || self.info.needs_nf) {
+                    self.rt.split_record();
+                }
+            }
+            Place::Nowhere => {}
+        }
+    }
+
+    /// A concat chain `a b c ...` (left-nested `Concat` nodes), built into
+    /// one String sized up front instead of one new String per node.
+    fn eval_concat(&mut self, expr: &Expr) -> Value {
+        let mut parts = std::mem::take(&mut self.concat_parts);
+        self.push_concat_parts(expr, &mut parts, true);
+        let result = join_parts(&parts, String::new());
+        parts.clear();
+        self.concat_parts = parts;
+        result
+    }
+
+    /// `v = v a b ...` as a statement: append to `v`'s string in place, so
+    /// building a long string in a loop is linear rather than quadratic.
+    /// Returns false, having evaluated nothing, when `expr` is not of that
+    /// shape; the caller then evaluates it normally.
+    pub(crate) fn append_in_place(&mut self, expr: &Expr) -> bool {
+        let Expr::Assign(target, value) = expr else {
+            return false;
+        };
+        let (Expr::Var(name), Expr::Concat(..)) = (target.as_ref(), value.as_ref()) else {
+            return false;
+        };
+        if !matches!(concat_head(value), Expr::Var(head) if head == name)
+            || self.tail_may_touch(value, name)
+            || self.rt.string_var_mut(name).is_none()
+        {
+            return false;
+        }
+        let mut parts = std::mem::take(&mut self.concat_parts);
+        self.push_concat_parts(value, &mut parts, false);
+        if parts.iter().any(Value::is_null) {
+            self.rt.set_value(name, Value::null());
+        } else if let Some(s) = self.rt.string_var_mut(name) {
+            s.reserve(parts.iter().map(Value::str_len_hint).sum());
+            for part in &parts {
+                part.write_to_string(s);
+            }
+        }
+        parts.clear();
+        self.concat_parts = parts;
+        true
+    }
+
+    /// Whether the leaves after the head of a concat chain could read or
+    /// write `name`: they mention it, or call a user function (which may
+    /// touch any global). Appending in place is only safe when they can't.
+    fn tail_may_touch(&self, expr: &Expr, name: &str) -> bool {
         match expr {
-            Expr::Var(name) => self.rt.get_value(name),
-            Expr::ArrayRef(name, key_expr) => {
-                let key = self.eval_string(key_expr);
-                self.rt.get_array_value(name, &key)
+            Expr::Concat(left, right) => {
+                self.may_touch(right, name) || self.tail_may_touch(left, name)
             }
-            Expr::Field(idx_expr) => {
-                let idx = self.eval_field_idx(idx_expr);
-                Value::from_string(self.rt.get_field(idx))
+            _ => false,
+        }
+    }
+
+    fn may_touch(&self, expr: &Expr, name: &str) -> bool {
+        match expr {
+            Expr::NumberLit(_) | Expr::StringLit(_) => false,
+            Expr::Var(v) => v == name,
+            Expr::Field(e)
+            | Expr::ArrayRef(_, e)
+            | Expr::ArrayIn(e, _)
+            | Expr::LogicalNot(e)
+            | Expr::Increment(e, _)
+            | Expr::Decrement(e, _)
+            | Expr::UnaryMinus(e)
+            | Expr::TryVal(e)
+            | Expr::NullFence(e) => self.may_touch(e, name),
+            Expr::BinOp(l, _, r)
+            | Expr::LogicalAnd(l, r)
+            | Expr::LogicalOr(l, r)
+            | Expr::Match(l, r)
+            | Expr::NotMatch(l, r)
+            | Expr::Assign(l, r)
+            | Expr::CompoundAssign(l, _, r)
+            | Expr::Concat(l, r)
+            | Expr::NullCoalesce(l, r) => self.may_touch(l, name) || self.may_touch(r, name),
+            Expr::Ternary(c, t, f) => {
+                self.may_touch(c, name) || self.may_touch(t, name) || self.may_touch(f, name)
+            }
+            Expr::Sprintf(args) => args.iter().any(|a| self.may_touch(a, name)),
+            Expr::FuncCall(f, args) => {
+                self.functions.contains_key(f) || args.iter().any(|a| self.may_touch(a, name))
+            }
+            Expr::Getline(var, source) => {
+                var.as_deref().is_some_and(|e| self.may_touch(e, name))
+                    || source.as_deref().is_some_and(|e| self.may_touch(e, name))
+            }
+            Expr::GetlinePipe(cmd, var) => {
+                var.as_deref().is_some_and(|e| self.may_touch(e, name)) || self.may_touch(cmd, name)
             }
-            _ => Value::default(),
         }
     }
 
-    pub(crate) fn assign_to(&mut self, target: &Expr, value: Value) {
-        match target {
-            Expr::Var(name) => self.rt.set_value(name, value),
-            Expr::ArrayRef(name, key_expr) => {
-                let key = self.eval_string(key_expr);
-                self.rt.set_array_value(name, &key, value);
+    /// Evaluate the leaves of a concat chain in order, numbers converted
+    /// under CONVFMT. `with_head: false` skips the leftmost leaf.
+    fn push_concat_parts(&mut self, expr: &Expr, parts: &mut Vec<Value>, with_head: bool) {
+        match expr {
+            Expr::Concat(left, right) => {
+                self.push_concat_parts(left, parts, with_head);
+                let v = self.eval_expr(right);
+                parts.push(self.concat_part(v));
             }
-            Expr::Field(idx_expr) => {
-                let idx = self.eval_field_idx(idx_expr);
-                self.rt.set_field(idx, &value.into_string());
+            _ if with_head => {
+                let v = self.eval_expr(expr);
+                parts.push(self.concat_part(v));
             }
             _ => {}
         }
     }
+
+    fn concat_part(&self, v: Value) -> Value {
+        let convfmt = self.rt.convfmt();
+        if v.is_numeric_only() && convfmt != "%.6g" {
+            Value::from_string(builtins::format_number_fmt(v.to_number(), convfmt))
+        } else {
+            v
+        }
+    }
+
+    pub(crate) fn assign_to(&mut self, target: &Expr, value: Value) {
+        let place = self.place(target);
+        self.store(place, value);
+    }
+
+    /// `target op= value` (or `++`/`--` with `value` 1) on an element or
+    /// field, evaluating its subscript once. Returns (old, new).
+    fn update_place(&mut self, target: &Expr, op: &BinOp, value: Value) -> (Value, Value) {
+        let place = self.place(target);
+        let current = self.place_value(&place);
+        if matches!(op, BinOp::Div | BinOp::Mod) && value.to_number() == 0.0 {
+            warn!(self, "division by zero");
+        }
+        let result = eval_binop(current.clone(), op, value);
+        self.store(place, result.clone());
+        (current, result)
+    }
+}
+
+/// An assignment target with its subscript or field index evaluated.
+pub(crate) enum Place<'t> {
+    Var(&'t str),
+    Elem(&'t str, String),
+    Field(usize),
+    /// A `$"name"` not in HDR.
+    Nowhere,
+}
+
+/// The leftmost leaf of a concat chain.
+/// What a comparison operand in a loop condition is known to be.
+enum Operand {
+    /// A number already in hand: a literal or a numeric variable.
+    Known(f64),
+    /// Arithmetic, so a number once evaluated.
+    Computed,
+    /// Possibly a string; the condition takes the general path.
+    Text,
+}
+
+fn is_arith(op: &BinOp) -> bool {
+    matches!(
+        op,
+        BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod | BinOp::Pow
+    )
+}
+
+fn concat_head(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Concat(left, _) => concat_head(left),
+        _ => expr,
+    }
+}
+
+/// `parts` appended to `s`; null if any part is null.
+fn join_parts(parts: &[Value], mut s: String) -> Value {
+    if parts.iter().any(Value::is_null) {
+        return Value::null();
+    }
+    s.reserve(parts.iter().map(Value::str_len_hint).sum());
+    for part in parts {
+        part.write_to_string(&mut s);
+    }
+    Value::from_string(s)
 }
 
 fn compare_values(left: &Value, right: &Value) -> std::cmp::Ordering {
@@ -627,7 +1066,7 @@ pub(crate) fn eval_binop(left: Value, op: &BinOp, right: Value) -> Value {
             let base = left.to_number();
             let exp = right.to_number();
             let ei = exp as i32;
-            let result = if exp == ei as f64 && ei >= 0 && ei <= 10 {
+            let result = if exp == ei as f64 && (0..=10).contains(&ei) {
                 match ei {
                     0 => 1.0,
                     1 => base,
@@ -655,7 +1094,6 @@ pub(crate) fn eval_binop(left: Value, op: &BinOp, right: Value) -> Value {
         BinOp::Div => {
             let r = right.to_number();
             if r == 0.0 {
-                eprintln!("fk: division by zero");
                 Value::from_number(0.0)
             } else {
                 Value::from_number(left.to_number() / r)
@@ -664,7 +1102,6 @@ pub(crate) fn eval_binop(left: Value, op: &BinOp, right: Value) -> Value {
         BinOp::Mod => {
             let r = right.to_number();
             if r == 0.0 {
-                eprintln!("fk: division by zero");
                 Value::from_number(0.0)
             } else {
                 Value::from_number(left.to_number() % r)
@@ -0,0 +1,191 @@
+//! `hll()` and friends: HyperLogLog distinct counting with a fixed,
+//! platform-independent hash so dumps from parallel runs can be merged.
+
+/// Register count exponent used when `hll()` gets no precision argument.
+pub(crate) const DEFAULT_PRECISION: u8 = 14;
+const MIN_PRECISION: u8 = 4;
+const MAX_PRECISION: u8 = 18;
+
+/// Prefix of `hll_dump()` strings: `hll:P:` then one hex byte per register.
+const DUMP_PREFIX: &str = "hll:";
+
+/// 64-bit FNV-1a finished with the MurmurHash3 mixer, so every output bit
+/// depends on every input byte. Stable across runs and platforms.
+pub(crate) fn hash64(s: &str) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for b in s.bytes() {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^ (h >> 33)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Hll {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl Hll {
+    /// `precision` must be within 4..=18 (2^p registers, ~1.04/sqrt(2^p) error).
+    pub(crate) fn new(precision: u8) -> Result<Self, String> {
+        if !(MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+            return Err(format!(
+                "precision must be between {MIN_PRECISION} and {MAX_PRECISION}"
+            ));
+        }
+        Ok(Hll {
+            precision,
+            registers: vec![0; 1 << precision],
+        })
+    }
+
+    pub(crate) fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// Add `value`; true if it raised a register, i.e. may have moved the
+    /// estimate. Constant time: the estimate is only computed by `count`.
+    pub(crate) fn add(&mut self, value: &str) -> bool {
+        let h = hash64(value);
+        let p = self.precision as u32;
+        let idx = (h >> (64 - p)) as usize;
+        // Rank of the first 1 bit in the remaining 64-p bits; the sentinel
+        // bit caps it at 64-p+1.
+        let rest = (h << p) | (1 << (p - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        if rank > self.registers[idx] {
+            self.registers[idx] = rank;
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn count(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let mut sum = 0.0;
+        let mut zeros = 0;
+        for &r in &self.registers {
+            sum += 1.0 / (1u64 << r) as f64;
+            if r == 0 {
+                zeros += 1;
+            }
+        }
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let estimate = alpha * m * m / sum;
+        if estimate <= 2.5 * m && zeros > 0 {
+            // Small-range correction: linear counting.
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        }
+    }
+
+    /// Register-wise max: afterwards `self` counts the union of both inputs.
+    pub(crate) fn merge(&mut self, other: &Hll) -> Result<(), String> {
+        if other.precision != self.precision {
+            return Err(format!(
+                "precision mismatch ({} vs {})",
+                self.precision, other.precision
+            ));
+        }
+        for (a, &b) in self.registers.iter_mut().zip(&other.registers) {
+            *a = (*a).max(b);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn dump(&self) -> String {
+        let mut out = format!("{DUMP_PREFIX}{}:", self.precision);
+        out.reserve(self.registers.len() * 2);
+        for r in &self.registers {
+            out.push_str(&format!("{r:02x}"));
+        }
+        out
+    }
+
+    pub(crate) fn load(s: &str) -> Result<Self, String> {
+        let bad = || "not an hll_dump() string".to_string();
+        let (p, hex) = s
+            .trim()
+            .strip_prefix(DUMP_PREFIX)
+            .and_then(|rest| rest.split_once(':'))
+            .ok_or_else(bad)?;
+        let mut hll = Hll::new(p.parse().map_err(|_| bad())?)?;
+        if !hex.is_ascii() || hex.len() != hll.registers.len() * 2 {
+            return Err(bad());
+        }
+        for (i, reg) in hll.registers.iter_mut().enumerate() {
+            *reg = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| bad())?;
+        }
+        Ok(hll)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate_within_three_percent_on_a_million() {
+        let mut h = Hll::new(DEFAULT_PRECISION).unwrap();
+        for i in 0..1_000_000 {
+            h.add(&format!("user-{i}"));
+        }
+        let err = (h.count() - 1_000_000.0).abs() / 1_000_000.0;
+        assert!(err < 0.03, "estimate {} ({err})", h.count());
+        // Duplicates do not move the estimate.
+        let before = h.count();
+        assert!(!h.add("user-7"));
+        assert_eq!(h.count(), before);
+    }
+
+    #[test]
+    fn small_counts_are_near_exact() {
+        let mut h = Hll::new(DEFAULT_PRECISION).unwrap();
+        assert_eq!(h.count(), 0.0);
+        for v in ["a", "b", "c", "a"] {
+            h.add(v);
+        }
+        assert_eq!(h.count().round(), 3.0);
+    }
+
+    #[test]
+    fn merge_equals_sketch_of_union() {
+        let (mut a, mut b, mut all) = (
+            Hll::new(12).unwrap(),
+            Hll::new(12).unwrap(),
+            Hll::new(12).unwrap(),
+        );
+        for i in 0..50_000 {
+            let v = i.to_string();
+            if i % 3 == 0 { &mut a } else { &mut b }.add(&v);
+            all.add(&v);
+        }
+        a.merge(&b).unwrap();
+        assert_eq!(a, all);
+        assert!(a.merge(&Hll::new(10).unwrap()).is_err());
+    }
+
+    #[test]
+    fn hash_and_dump_are_deterministic() {
+        assert_eq!(hash64("fk"), 0x9944_9676_d82a_1a1e);
+        let mut h = Hll::new(4).unwrap();
+        h.add("x");
+        let dump = h.dump();
+        assert!(dump.starts_with("hll:4:") && dump.len() == 6 + 32);
+        assert_eq!(Hll::load(&dump).unwrap(), h);
+        assert!(Hll::load("hll:4:zz").is_err());
+        assert!(Hll::load("nope").is_err());
+        assert!(Hll::load(&format!("hll:4:é{}", &dump[8..])).is_err());
+    }
+}
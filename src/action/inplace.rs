@@ -0,0 +1,116 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::Executor;
+
+/// State for `--in-place`: while a file is being processed, default
+/// output goes to a temp file next to it, renamed over the original
+/// once ENDFILE has run.
+pub(crate) struct InPlace {
+    suffix: String,
+    active: Option<ActiveFile>,
+}
+
+struct ActiveFile {
+    target: PathBuf,
+    tmp: PathBuf,
+    saved_stdout: BufWriter<Box<dyn Write>>,
+}
+
+impl<'a> Executor<'a> {
+    /// Enable in-place editing; a non-empty `suffix` keeps `X.suffix` backups.
+    pub fn set_in_place(&mut self, suffix: &str) {
+        self.in_place = Some(InPlace {
+            suffix: suffix.to_string(),
+            active: None,
+        });
+    }
+
+    /// The process stdout, even while default output goes to a temp file.
+    pub(crate) fn real_stdout(&mut self) -> &mut BufWriter<Box<dyn Write>> {
+        match self.in_place.as_mut().and_then(|ip| ip.active.as_mut()) {
+            Some(active) => &mut active.saved_stdout,
+            None => &mut self.stdout,
+        }
+    }
+
+    /// Start capturing default output for `path`. Call before BEGINFILE.
+    pub fn begin_in_place(&mut self, path: &str) {
+        let Some(ip) = self.in_place.as_mut() else {
+            return;
+        };
+        if ip.active.is_some() {
+            return;
+        }
+        let target = PathBuf::from(path);
+        let tmp = temp_path(&target);
+        let file = match File::create(&tmp) {
+            Ok(f) => f,
+            Err(e) => {
+                warn!(self, "cannot create '{}': {}", tmp.display(), e);
+                return;
+            }
+        };
+        if let Ok(meta) = fs::metadata(&target) {
+            let _ = fs::set_permissions(&tmp, meta.permissions());
+        }
+        let _ = self.stdout.flush();
+        let saved_stdout = std::mem::replace(
+            &mut self.stdout,
+            BufWriter::new(Box::new(file) as Box<dyn Write>),
+        );
+        ip.active = Some(ActiveFile {
+            target,
+            tmp,
+            saved_stdout,
+        });
+    }
+
+    /// Stop capturing. With `commit`, the captured output replaces the
+    /// file (after linking the backup); otherwise the original is left
+    /// untouched and the temp file removed.
+    pub fn end_in_place(&mut self, commit: bool) {
+        let Some(ip) = self.in_place.as_mut() else {
+            return;
+        };
+        let Some(active) = ip.active.take() else {
+            return;
+        };
+        let mut captured = std::mem::replace(&mut self.stdout, active.saved_stdout);
+        let flushed = captured.flush();
+        drop(captured);
+        if let Err(e) = flushed {
+            warn!(self, "{}: {}", active.tmp.display(), e);
+        } else if commit {
+            if let Err(e) = replace_file(&active.target, &active.tmp, &ip.suffix) {
+                warn!(self, "cannot replace '{}': {}", active.target.display(), e);
+            } else {
+                return;
+            }
+        }
+        let _ = fs::remove_file(&active.tmp);
+    }
+}
+
+/// Hidden temp file in the target's directory, so the final rename
+/// never crosses filesystems.
+fn temp_path(target: &Path) -> PathBuf {
+    let name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    target.with_file_name(format!(".{name}.fk{}.tmp", std::process::id()))
+}
+
+fn replace_file(target: &Path, tmp: &Path, suffix: &str) -> std::io::Result<()> {
+    if !suffix.is_empty() {
+        let mut backup = target.as_os_str().to_owned();
+        backup.push(suffix);
+        let _ = fs::remove_file(&backup);
+        if fs::hard_link(target, &backup).is_err() {
+            fs::copy(target, &backup)?;
+        }
+    }
+    fs::rename(tmp, target)
+}
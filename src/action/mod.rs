@@ -1,18 +1,40 @@
+/// `warn!(self, "format", args..)`: report a runtime warning through
+/// `Executor::warn`, with the format string as its kind.
+macro_rules! warn {
+    ($exec:expr, $fmt:literal $($arg:tt)*) => {
+        $exec.warn($fmt, format!($fmt $($arg)*))
+    };
+}
+
 mod builtins_rt;
 mod eval;
+mod hll;
+mod inplace;
+mod pipe;
+mod rules;
+mod sort;
 mod stmt;
+mod topk;
 
-use std::collections::HashMap;
+pub(crate) use eval::eval_binop;
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{self, BufReader, BufWriter, Write};
-use std::process::{Child, ChildStdout};
-use std::time::Instant;
+use std::process::Child;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 
 use crate::analyze::{self, ProgramInfo};
+use crate::builtins;
+use crate::builtins::printf::Format;
+use crate::cli::{OnError, OutputMode};
 use crate::input::{self, Record};
-use crate::parser::{FuncDef, Pattern, Program};
+use crate::output::Table;
+use crate::parser::{FuncDef, Program};
 use crate::runtime::{Runtime, Value};
 
 /// Signal used to propagate control flow out of blocks.
@@ -21,9 +43,56 @@ pub(crate) enum Signal {
     Break,
     Continue,
     Exit(i32),
+    /// A call went past `--max-depth`: the rest of the action is skipped.
+    Abort,
+}
+
+/// Default nesting limit for user function calls (`--max-depth`).
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 200;
+
+/// Highest `--max-depth` accepted.
+pub const MAX_CALL_DEPTH_LIMIT: usize = 100_000;
+
+/// Native stack reserved per call level when `--max-depth` raises the
+/// limit. A level of `return 1 + f(n-1)` takes about 2.3K in a release
+/// build and 16K in a debug one; the 8M main thread holds some 3500
+/// levels in release, 500 in debug.
+pub const STACK_PER_CALL: usize = 32 << 10;
+
+/// Compiled-size cap for one regex (`--regex-limit`). The regex crate
+/// matches in time linear in input × compiled size, so bounding the size
+/// bounds every match; there is no per-match timeout.
+pub const DEFAULT_REGEX_SIZE_LIMIT: usize = 10 << 20;
+
+/// Dynamic regexes kept compiled at once. Past this the least recently
+/// used one is dropped, so a program building a new pattern per record
+/// holds bounded memory. Literals compiled up front are not counted.
+pub const DYNAMIC_REGEX_CAP: usize = 256;
+
+/// `print | cmd` pipes kept open at once unless `--max-pipes` says
+/// otherwise. Past this the least recently used one is closed, and a
+/// later print to it runs the command again.
+pub const DEFAULT_MAX_PIPES: usize = 16;
+
+/// Times one warning is printed. Repeats past this are only counted, and
+/// a line at the end of the run says how many were left out.
+pub const WARN_REPEATS: u32 = 3;
+
+/// Which of `--max-records` / `--max-runtime` stopped input early.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputLimit {
+    Records,
+    Runtime,
 }
 
-pub(crate) const MAX_CALL_DEPTH: usize = 200;
+/// Compile `pattern` under `size_limit`; the lazy DFA cache gets a fifth of
+/// it (the regex crate's own ratio).
+pub fn compile_regex(pattern: &str, size_limit: usize) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .size_limit(size_limit)
+        .dfa_size_limit(size_limit / 5)
+        .build()
+}
 
 /// Compute the p-th percentile from a *sorted* slice using linear interpolation.
 pub(crate) fn percentile_sorted(sorted: &[f64], pct: f64) -> f64 {
@@ -46,27 +115,160 @@ pub(crate) fn percentile_sorted(sorted: &[f64], pct: f64) -> f64 {
     }
 }
 
+/// Exit status when standard output is closed under fk, as a shell reports
+/// a process killed by SIGPIPE.
+pub const STDOUT_CLOSED_EXIT: i32 = 141;
+
+/// Standard output that remembers a broken pipe, so the record loop can
+/// stop once nothing reads the output (`fk '{ print }' big | head`).
+struct StdoutSink(Rc<Cell<bool>>);
+
+impl Write for StdoutSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = io::stdout().write(buf);
+        self.note(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let flushed = io::stdout().flush();
+        self.note(flushed)
+    }
+}
+
+impl StdoutSink {
+    fn note<T>(&self, result: io::Result<T>) -> io::Result<T> {
+        if let Err(e) = &result
+            && e.kind() == io::ErrorKind::BrokenPipe
+        {
+            self.0.set(true);
+        }
+        result
+    }
+}
+
+/// Builds the main input once RS is known (see `Executor::defer_input`).
+pub(crate) type OpenInput = Box<dyn FnOnce(&str) -> input::Input>;
+
 pub struct Executor<'a> {
     pub(crate) program: &'a Program,
     pub(crate) rt: &'a mut Runtime,
     pub(crate) info: ProgramInfo,
     pub(crate) functions: HashMap<String, FuncDef>,
+    /// The program's rules, prepared for the per-record loop.
+    pub(crate) rules: Vec<rules::CompiledRule<'a>>,
+    /// IGNORECASE setting the rules' regexes were compiled under.
+    pub(crate) rules_icase: bool,
     pub(crate) range_active: Vec<bool>,
     pub(crate) output_files: HashMap<String, File>,
     pub(crate) output_pipes: HashMap<String, Child>,
+    /// Last use of each output pipe, for LRU closing past `max_pipes`.
+    pub(crate) pipe_used: HashMap<String, u64>,
+    pub(crate) pipe_tick: u64,
+    /// `--max-pipes`: output pipes open at once (0: no limit).
+    pub(crate) max_pipes: usize,
+    /// `system_bg()` commands not yet waited for, by handle.
+    pub(crate) bg_jobs: HashMap<u64, Child>,
+    pub(crate) bg_next: u64,
     pub(crate) input_files: HashMap<String, BufReader<File>>,
-    pub(crate) input_pipe_readers: HashMap<String, BufReader<ChildStdout>>,
-    pub(crate) input_pipe_children: HashMap<String, Child>,
-    pub(crate) stdout: BufWriter<io::Stdout>,
+    pub(crate) input_pipes: HashMap<String, pipe::PipeReader>,
+    pub(crate) stdout: BufWriter<Box<dyn Write>>,
+    /// Set once a write to standard output finds the pipe closed.
+    stdout_closed: Rc<Cell<bool>>,
+    /// Flush `stdout` after every print (`--line-buffered`, `flushmode`).
+    pub(crate) line_buffered: bool,
+    pub(crate) table: Option<Table>,
+    pub(crate) json_rows: bool,
+    /// `-o tsv`: rows are tab-joined with escapes; under `-H` a header
+    /// line is written before the first row.
+    pub(crate) tsv_rows: bool,
+    pub(crate) tsv_header_done: bool,
+    /// The main input is CSV, so `header()` writes a quoted CSV row.
+    pub(crate) csv_output: bool,
+    /// `--emit-header`: `header()` still runs before the first print.
+    pub(crate) emit_header: bool,
+    pub(crate) in_place: Option<inplace::InPlace>,
+    pub(crate) sample_rate: Option<f64>,
+    /// `--every N`: the stride of records that reach the rules.
+    pub(crate) every: Option<u64>,
+    pub(crate) on_error: Option<OnError>,
     pub(crate) call_depth: usize,
+    pub(crate) max_call_depth: usize,
+    /// A call went past `max_call_depth`; the current action is unwinding.
+    pub(crate) call_aborted: bool,
+    /// Set while a user comparison function runs inside asort/asorti.
+    pub(crate) in_comparator: bool,
     pub(crate) next_record: bool,
     pub(crate) next_file: bool,
     pub(crate) exit_code: Option<i32>,
     pub(crate) regex_cache: HashMap<String, Regex>,
+    /// Literal printf/sprintf formats, parsed once (see `printf_text`).
+    pub(crate) printf_formats: HashMap<String, Format>,
+    /// Scratch for the leaves of a concat chain (see `eval_concat`).
+    pub(crate) concat_parts: Vec<Value>,
+    /// Reused output line and OFS copy of `print` (see `print_line`).
+    pub(crate) print_buf: Vec<u8>,
+    pub(crate) print_ofs: String,
+    /// `$"name"` assignment targets missing from HDR; reported once.
+    pub(crate) unknown_fields: HashSet<String>,
+    /// `jpretty`/`jmin` met text that is not JSON; reported once.
+    pub(crate) json_warned: bool,
+    /// `--strict`: such a mismatch, or a call past `max_call_depth`, is an
+    /// error instead of a warning.
+    pub(crate) strict: bool,
+    /// Times each warning, by kind and text, has been raised.
+    pub(crate) warnings_seen: HashMap<(&'static str, String), u32>,
+    /// Warnings past `WARN_REPEATS` left unprinted.
+    pub(crate) warnings_suppressed: u64,
+    /// `--quiet`: warnings are counted but never printed.
+    pub(crate) quiet: bool,
+    /// Patterns that failed to compile; reported once, then skipped.
+    pub(crate) regex_failed: HashSet<String>,
+    pub(crate) regex_limit: usize,
+    /// `IGNORECASE` setting the cached regexes were compiled under.
+    pub(crate) regex_icase: bool,
+    /// Cache misses in `ensure_regex` (dynamic patterns must be reused).
+    pub(crate) regex_compiles: usize,
+    /// Last use of each dynamic pattern in `regex_cache`, for LRU eviction.
+    pub(crate) regex_used: HashMap<String, u64>,
+    pub(crate) regex_tick: u64,
+    /// Dynamic patterns dropped to stay within `DYNAMIC_REGEX_CAP`.
+    pub(crate) regex_evictions: usize,
     pub(crate) epoch: Instant,
     pub(crate) timers: HashMap<String, Instant>,
+    /// Items seen so far by `reservoir()`, per target array.
+    pub(crate) reservoir_seen: HashMap<String, u64>,
+    /// `topk()` summaries and their largest k, per state name.
+    pub(crate) topk: HashMap<String, (usize, topk::SpaceSaving)>,
+    /// `hll()` sketches, per state name.
+    pub(crate) hll: HashMap<String, hll::Hll>,
     pub(crate) input: Option<input::Input>,
+    /// Opens the main input given the current RS; run by the first plain
+    /// `getline` in BEGIN, or by `open_input` once BEGIN is done.
+    pub(crate) deferred_input: Option<OpenInput>,
+    /// The input source whose BEGINFILE has run and whose ENDFILE has
+    /// not (see `enter_input_source`).
+    pub(crate) entered_source: Option<usize>,
+    /// Columns named in HDR so far.
+    pub(crate) header_len: usize,
+    /// `-H` on input with a header row: the next record read from the
+    /// main input, by the record loop or a plain `getline`, names the
+    /// columns instead of being data.
+    pub(crate) header_pending: bool,
+    /// `-H` on JSON input: HDR grows as new keys appear (see
+    /// `sync_input_header`).
+    pub(crate) keyed_header: bool,
+    /// `--header-vars`: each column name is also set as a variable
+    /// holding its index, as before `$name` looked in HDR itself.
+    pub(crate) header_vars: bool,
     pub(crate) last_buffers: Vec<Option<(usize, std::collections::VecDeque<String>)>>,
+    /// `--max-records`: records read before input stops.
+    pub(crate) max_records: Option<u64>,
+    /// `--max-runtime`: time since `epoch` after which input stops.
+    pub(crate) max_runtime: Option<Duration>,
+    /// Records counted against `max_records`.
+    pub(crate) records_read: u64,
+    /// Set once a limit has stopped input.
+    pub(crate) input_limit: Option<InputLimit>,
 }
 
 impl<'a> Executor<'a> {
@@ -79,52 +281,262 @@ impl<'a> Executor<'a> {
         let range_active = vec![false; program.rules.len()];
         let mut regex_cache = HashMap::new();
         for pat in &info.regex_literals {
-            if let Ok(re) = Regex::new(pat) {
+            if let Ok(re) = compile_regex(pat, DEFAULT_REGEX_SIZE_LIMIT) {
                 regex_cache.insert(pat.clone(), re);
             }
         }
+        let printf_formats = info
+            .printf_formats
+            .iter()
+            .map(|f| (f.clone(), Format::parse(f)))
+            .collect();
         let last_buffers = vec![None; program.rules.len()];
-        Executor {
+        let rules = program
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| rules::CompiledRule::new(rule, i))
+            .collect();
+        let stdout_closed = Rc::new(Cell::new(false));
+        let mut exec = Executor {
             program,
             rt,
             info,
             functions,
+            rules,
+            rules_icase: false,
             range_active,
             output_files: HashMap::new(),
             output_pipes: HashMap::new(),
+            pipe_used: HashMap::new(),
+            pipe_tick: 0,
+            max_pipes: DEFAULT_MAX_PIPES,
+            bg_jobs: HashMap::new(),
+            bg_next: 0,
             input_files: HashMap::new(),
-            input_pipe_readers: HashMap::new(),
-            input_pipe_children: HashMap::new(),
-            stdout: BufWriter::new(io::stdout()),
+            input_pipes: HashMap::new(),
+            stdout: BufWriter::new(Box::new(StdoutSink(stdout_closed.clone()))),
+            stdout_closed,
+            line_buffered: false,
+            table: None,
+            json_rows: false,
+            tsv_rows: false,
+            tsv_header_done: false,
+            csv_output: false,
+            emit_header: false,
+            in_place: None,
+            sample_rate: None,
+            every: None,
+            on_error: None,
             call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            call_aborted: false,
+            in_comparator: false,
             next_record: false,
             next_file: false,
             exit_code: None,
             regex_cache,
+            printf_formats,
+            concat_parts: Vec::new(),
+            print_buf: Vec::new(),
+            print_ofs: String::new(),
+            regex_failed: HashSet::new(),
+            unknown_fields: HashSet::new(),
+            json_warned: false,
+            strict: false,
+            warnings_seen: HashMap::new(),
+            warnings_suppressed: 0,
+            quiet: false,
+            regex_limit: DEFAULT_REGEX_SIZE_LIMIT,
+            regex_icase: false,
+            regex_compiles: 0,
+            regex_used: HashMap::new(),
+            regex_tick: 0,
+            regex_evictions: 0,
             epoch: Instant::now(),
             timers: HashMap::new(),
+            reservoir_seen: HashMap::new(),
+            topk: HashMap::new(),
+            hll: HashMap::new(),
             input: None,
+            deferred_input: None,
+            entered_source: None,
+            header_len: 0,
+            header_pending: false,
+            keyed_header: false,
+            header_vars: false,
             last_buffers,
+            max_records: None,
+            max_runtime: None,
+            records_read: 0,
+            input_limit: None,
+        };
+        exec.compile_rule_regexes();
+        exec
+    }
+
+    /// Report a problem the run carries on past. Every call adds one to
+    /// WARNINGS; the same `kind` and text is printed at most
+    /// `WARN_REPEATS` times, and nothing under `--quiet`. Use `warn!`.
+    pub(crate) fn warn(&mut self, kind: &'static str, text: String) {
+        let count = self.rt.get_number("WARNINGS") + 1.0;
+        self.rt.set_value("WARNINGS", Value::from_number(count));
+        if self.quiet {
+            return;
         }
+        let key = (kind, text);
+        let seen = match self.warnings_seen.get_mut(&key) {
+            Some(seen) => {
+                *seen += 1;
+                *seen
+            }
+            None => {
+                self.warnings_seen.insert(key.clone(), 1);
+                1
+            }
+        };
+        if seen > WARN_REPEATS {
+            self.warnings_suppressed += 1;
+            return;
+        }
+        crate::progress::clear();
+        eprintln!("fk: {}", key.1);
     }
 
-    /// Ensure a regex is compiled and cached. Returns false if invalid.
+    /// Ensure a regex is compiled and cached. Returns false if invalid; the
+    /// error is printed the first time only.
     pub(crate) fn ensure_regex(&mut self, pattern: &str) -> bool {
+        if self.rt.ignore_case() != self.regex_icase {
+            // IGNORECASE changed: recompile everything under the new mode.
+            self.regex_icase = self.rt.ignore_case();
+            self.regex_cache.clear();
+            self.regex_failed.clear();
+            self.regex_used.clear();
+        }
+        self.regex_tick += 1;
+        if let Some(used) = self.regex_used.get_mut(pattern) {
+            *used = self.regex_tick;
+            return true;
+        }
         if self.regex_cache.contains_key(pattern) {
             return true;
         }
-        match Regex::new(pattern) {
+        if self.regex_failed.contains(pattern) {
+            return false;
+        }
+        self.regex_compiles += 1;
+        let compiled = if self.regex_icase {
+            compile_regex(&format!("(?i){pattern}"), self.regex_limit)
+        } else {
+            compile_regex(pattern, self.regex_limit)
+        };
+        match compiled {
             Ok(re) => {
+                if self.regex_used.len() >= DYNAMIC_REGEX_CAP {
+                    self.evict_oldest_regex();
+                }
                 self.regex_cache.insert(pattern.to_string(), re);
+                self.regex_used.insert(pattern.to_string(), self.regex_tick);
                 true
             }
+            Err(regex::Error::CompiledTooBig(limit)) => {
+                warn!(
+                    self,
+                    "regex /{}/ is too complex: compiled size exceeds {} bytes (raise with --regex-limit)",
+                    pattern,
+                    limit
+                );
+                self.remember_failed_regex(pattern);
+                false
+            }
             Err(_) => {
-                eprintln!("fk: invalid regex: {}", pattern);
+                warn!(self, "invalid regex: {}", pattern);
+                self.remember_failed_regex(pattern);
                 false
             }
         }
     }
 
+    /// Drop the least recently used dynamic regex.
+    fn evict_oldest_regex(&mut self) {
+        let oldest = self
+            .regex_used
+            .iter()
+            .min_by_key(|(_, used)| **used)
+            .map(|(pat, _)| pat.clone());
+        if let Some(pat) = oldest {
+            self.regex_used.remove(&pat);
+            self.regex_cache.remove(&pat);
+            self.regex_evictions += 1;
+        }
+    }
+
+    /// Record a pattern that failed to compile. The set is bounded like the
+    /// cache; once full it starts over, so a bad pattern may be reported again.
+    fn remember_failed_regex(&mut self, pattern: &str) {
+        if self.regex_failed.len() >= DYNAMIC_REGEX_CAP {
+            self.regex_failed.clear();
+        }
+        self.regex_failed.insert(pattern.to_string());
+    }
+
+    /// Cap the compiled size of every regex (`--regex-limit`). Patterns
+    /// compiled under the default are dropped and recompiled on use.
+    pub fn set_regex_limit(&mut self, bytes: usize) {
+        self.regex_limit = bytes;
+        self.regex_cache.clear();
+        self.regex_failed.clear();
+        self.regex_used.clear();
+        self.compile_rule_regexes();
+    }
+
+    /// Limit how deeply user functions may nest (`--max-depth`).
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Keep at most `n` output pipes open, 0 for no limit (`--max-pipes`).
+    pub fn set_max_pipes(&mut self, n: usize) {
+        self.max_pipes = n;
+    }
+
+    /// Stop reading input after `n` records in all (`--max-records`).
+    pub fn set_max_records(&mut self, n: u64) {
+        self.max_records = Some(n);
+    }
+
+    /// Stop reading input once the run has taken `limit` (`--max-runtime`).
+    pub fn set_max_runtime(&mut self, limit: Duration) {
+        self.max_runtime = Some(limit);
+    }
+
+    /// The limit that stopped input early, if one did.
+    pub fn input_limit(&self) -> Option<InputLimit> {
+        self.input_limit
+    }
+
+    /// Call with each record read: true when a limit says to drop it and
+    /// stop reading. The first time, PROCINFO["truncated"] is set to 1.
+    pub fn over_input_limit(&mut self) -> bool {
+        if self.max_records.is_none() && self.max_runtime.is_none() {
+            return false;
+        }
+        if self.input_limit.is_some() {
+            return true;
+        }
+        let limit = if self.max_records.is_some_and(|n| self.records_read >= n) {
+            InputLimit::Records
+        } else if self.max_runtime.is_some_and(|d| self.epoch.elapsed() >= d) {
+            InputLimit::Runtime
+        } else {
+            self.records_read += 1;
+            return false;
+        };
+        self.input_limit = Some(limit);
+        self.rt.set_array("PROCINFO", "truncated", "1");
+        true
+    }
+
     /// Test if text matches a cached regex pattern.
     pub(crate) fn regex_is_match(&mut self, pattern: &str, text: &str) -> bool {
         self.ensure_regex(pattern) && self.regex_cache[pattern].is_match(text)
@@ -132,35 +544,224 @@ impl<'a> Executor<'a> {
 
     /// Populate the HDR array from a header record (used with `-H`).
     pub fn set_header(&mut self, fields: &[String]) {
-        for (i, name) in fields.iter().enumerate() {
-            let idx = i + 1;
+        self.header_len = 0;
+        self.add_header_columns(fields);
+        self.rt.increment_nr();
+    }
+
+    /// Name the next columns after the ones already in HDR.
+    fn add_header_columns(&mut self, names: &[String]) {
+        for name in names {
+            self.header_len += 1;
+            let idx = self.header_len;
             let key = idx.to_string();
+            let seen = self.rt.array_has_key("HDR", name);
             self.rt.set_array("HDR", &key, name);
             self.rt.set_array("HDR", name, &key);
-            if is_valid_ident(name) && !is_builtin_var(name) {
+            if !seen && self.info.assigned_vars.contains(name) {
+                warn!(
+                    self,
+                    "column \"{}\" is also a variable in the program; $\"{}\" is the column",
+                    name,
+                    name
+                );
+            }
+            if self.header_vars && is_valid_ident(name) && !is_builtin_var(name) {
                 self.rt.set_value(name, Value::from_number(idx as f64));
             }
         }
-        self.rt.increment_nr();
+    }
+
+    /// Add the columns the input reader has discovered (JSON keys under
+    /// `-H`). Unlike a CSV header these come from data records, so no
+    /// record is consumed and NR is left alone.
+    pub fn sync_input_header(&mut self) {
+        let names = match self.input.as_mut() {
+            Some(inp) => inp.new_columns(),
+            None => return,
+        };
+        if !names.is_empty() {
+            self.add_header_columns(&names);
+        }
+    }
+
+    /// Name columns from JSON keys as they appear (`-H` on JSON input).
+    pub fn set_keyed_header(&mut self) {
+        self.keyed_header = true;
+    }
+
+    /// Take the next record read from the main input as the header row
+    /// (`-H`, and again after `nextfile`).
+    pub fn expect_header(&mut self) {
+        self.header_pending = true;
+    }
+
+    /// If a header row is expected, set HDR from `rec` and count it in NR
+    /// and FNR; the caller then reads on. The record loop and a plain
+    /// `getline` both call this after entering the record's source, so a
+    /// getline in BEGIN sees the same data records as the rules.
+    pub fn take_header(&mut self, rec: &Record) -> bool {
+        if !std::mem::take(&mut self.header_pending) {
+            return false;
+        }
+        match &rec.fields {
+            Some(fields) => self.set_header(fields),
+            None => self.set_header_from_text(&rec.text),
+        }
+        self.rt.increment_fnr();
+        true
     }
 
     /// Populate header from raw text using FS-based splitting.
     pub fn set_header_from_text(&mut self, text: &str) {
+        let text = match self.rt.preserve_cr() {
+            true => text.strip_suffix('\r').unwrap_or(text),
+            false => text,
+        };
         let fs = self.rt.get_var("FS");
         let fields = crate::field::split(text, &fs);
         self.set_header(&fields);
     }
 
+    /// Select how unredirected `print` output is rendered (`-o`).
+    pub fn set_output_mode(&mut self, mode: &OutputMode, max_rows: usize) {
+        self.table = match mode {
+            OutputMode::Plain | OutputMode::Json | OutputMode::Tsv => None,
+            OutputMode::Table => Some(Table::new(max_rows)),
+            OutputMode::Markdown => Some(Table::markdown(max_rows)),
+        };
+        self.json_rows = *mode == OutputMode::Json;
+        self.tsv_rows = *mode == OutputMode::Tsv;
+        if *mode != OutputMode::Plain {
+            // Structured rows are built from individual fields.
+            self.info.needs_fields = true;
+            self.info.needs_nf = true;
+        }
+    }
+
+    /// What static analysis learned about the program being run.
+    pub fn info(&self) -> &ProgramInfo {
+        &self.info
+    }
+
     /// Read a runtime variable (e.g. RS after BEGIN has run).
     pub fn get_var(&self, name: &str) -> String {
         self.rt.get_var(name)
     }
 
+    /// The CSV dialect set by `CSVSEP`, `CSVQUOTE` and `CSVCOMMENT`.
+    pub fn csv_dialect(&self) -> input::csv::Dialect {
+        self.rt.csv_dialect()
+    }
+
     /// Set a runtime variable from outside (e.g. FILENAME from main loop).
     pub fn set_var(&mut self, name: &str, value: &str) {
         self.rt.set_var(name, value);
     }
 
+    /// Write the persisted arrays to a `--state` file (after END).
+    pub fn save_state(&self, path: &str) -> Result<usize, String> {
+        crate::state::save(path, self.rt)
+    }
+
+    /// Keep each record with probability `p` (`--sample p`).
+    pub fn set_sample(&mut self, p: f64) {
+        self.sample_rate = Some(p);
+        self.rt.set_number("SAMPLED", 0.0);
+    }
+
+    /// Draw for `--sample`: true if the record should be skipped before any
+    /// rule runs. Skipped records still count toward NR.
+    pub fn sample_skip(&mut self) -> bool {
+        let Some(p) = self.sample_rate else {
+            return false;
+        };
+        if builtins::math::rng_next() < p {
+            self.count_sampled();
+            false
+        } else {
+            self.rt.increment_nr();
+            true
+        }
+    }
+
+    /// Run the rules on every `n`th record only (`--every n`).
+    pub fn set_every(&mut self, n: u64) {
+        self.every = Some(n);
+        self.rt.set_number("SKIPPED", 0.0);
+    }
+
+    /// For `--every`: true if the record should be skipped before it is
+    /// split, as `NR % n == 0` would not hold for it. Skipped records still
+    /// count toward NR and are counted in SKIPPED.
+    pub fn every_skip(&mut self) -> bool {
+        let Some(n) = self.every else {
+            return false;
+        };
+        let nr = self.rt.get_number("NR") as u64 + 1;
+        if nr.is_multiple_of(n) {
+            return false;
+        }
+        self.rt.increment_nr();
+        let skipped = self.rt.get_number("SKIPPED");
+        self.rt.set_number("SKIPPED", skipped + 1.0);
+        true
+    }
+
+    /// Policy for malformed records when there is no `ERROR` block.
+    pub fn set_on_error(&mut self, policy: OnError) {
+        self.on_error = Some(policy);
+    }
+
+    /// Deal with a record its reader flagged as malformed. `Ok(true)` means
+    /// the record was consumed (by the `ERROR` block, or skipped) and must
+    /// not reach the rules; `Err` carries the message for `--on-error=abort`.
+    /// Without an `ERROR` block or policy the best-effort parse is kept.
+    pub fn handle_malformed(&mut self, record: &mut Record) -> Result<bool, String> {
+        let Some(msg) = record.malformed.take() else {
+            return Ok(false);
+        };
+        let program = self.program;
+        if let Some(block) = &program.error {
+            self.rt.increment_nr();
+            self.rt.set_record(&record.text);
+            self.rt.set_var("ERRMSG", &msg);
+            if let Some(Signal::Exit(code)) = self.exec_action(block) {
+                self.exit_code = Some(code);
+            }
+            return Ok(true);
+        }
+        match self.on_error {
+            Some(OnError::Skip) => {
+                self.rt.increment_nr();
+                Ok(true)
+            }
+            Some(OnError::Abort) => {
+                let mut file = self.rt.get_var("FILENAME");
+                if file.is_empty() {
+                    file = "-".to_string();
+                }
+                Err(format!(
+                    "fk: {}:{}: malformed record: {}",
+                    file,
+                    self.rt.get_var("FNR"),
+                    msg
+                ))
+            }
+            Some(OnError::Passthrough) => {
+                record.fields = Some(vec![record.text.clone()]);
+                Ok(false)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Bump SAMPLED, the count of records accepted by `--sample`/`sample()`.
+    pub(crate) fn count_sampled(&mut self) {
+        let n = self.rt.get_number("SAMPLED");
+        self.rt.set_number("SAMPLED", n + 1.0);
+    }
+
     pub fn increment_fnr(&mut self) {
         self.rt.increment_fnr();
     }
@@ -179,6 +780,27 @@ impl<'a> Executor<'a> {
         self.input = Some(input);
     }
 
+    /// Build the main input lazily, so BEGIN can still set RS and a plain
+    /// `getline` in BEGIN reads through the same reader as the rules.
+    pub fn defer_input(&mut self, open: impl FnOnce(&str) -> input::Input + 'static) {
+        self.deferred_input = Some(Box::new(open));
+    }
+
+    /// Whether the main input is already open (BEGIN read from it).
+    pub fn input_started(&self) -> bool {
+        self.input.is_some()
+    }
+
+    /// Open the deferred main input unless BEGIN already did.
+    pub fn open_input(&mut self) {
+        if self.input.is_none()
+            && let Some(open) = self.deferred_input.take()
+        {
+            let rs = self.rt.get_var("RS");
+            self.input = Some(open(&rs));
+        }
+    }
+
     /// Read the next record from the attached Input.
     pub fn next_record(&mut self) -> io::Result<Option<Record>> {
         match self.input {
@@ -187,6 +809,57 @@ impl<'a> Executor<'a> {
         }
     }
 
+    /// Read the next record into `rec`, reusing its buffers. False at end
+    /// of input or without an attached Input.
+    pub fn read_record(&mut self, rec: &mut Record) -> io::Result<bool> {
+        self.sync_csv_dialect();
+        let read = match self.input {
+            Some(ref mut inp) => inp.read_into(rec)? && !self.over_input_limit(),
+            None => false,
+        };
+        if read {
+            self.sync_byte_offsets();
+        }
+        Ok(read)
+    }
+
+    /// Hand a changed `CSVSEP`/`CSVQUOTE`/`CSVCOMMENT` to the input; it
+    /// takes effect from the next file.
+    pub(crate) fn sync_csv_dialect(&mut self) {
+        if let Some(ref mut inp) = self.input
+            && self.rt.take_csv_changed()
+        {
+            inp.set_dialect(self.rt.csv_dialect());
+        }
+    }
+
+    /// After BEGINFILE: if it changed the CSV dialect, apply it to this
+    /// file, first record included. False when that record turns out to be
+    /// a comment line and should be skipped.
+    pub fn redo_first_record(&mut self, rec: &mut Record) -> io::Result<bool> {
+        match self.input {
+            Some(ref mut inp) if self.rt.take_csv_changed() => {
+                inp.redo_first_record(self.rt.csv_dialect(), rec)
+            }
+            _ => Ok(true),
+        }
+    }
+
+    /// Set BYTEPOS and RECBYTES from the record just read, if the program
+    /// looks at them.
+    pub(crate) fn sync_byte_offsets(&mut self) {
+        if !self.info.needs_byte_offsets {
+            return;
+        }
+        if let Some(ref inp) = self.input {
+            let (start, len) = inp.record_span();
+            self.rt
+                .set_value("BYTEPOS", Value::from_number(start as f64));
+            self.rt
+                .set_value("RECBYTES", Value::from_number(len as f64));
+        }
+    }
+
     /// Current filename from the attached Input.
     pub fn current_filename(&self) -> &str {
         match self.input {
@@ -195,6 +868,59 @@ impl<'a> Executor<'a> {
         }
     }
 
+    /// Which source of the attached Input is being read, if any.
+    pub fn input_source(&self) -> Option<usize> {
+        self.input.as_ref().map(|inp| inp.source_index())
+    }
+
+    /// Cross into the source the attached Input is reading, if it is not
+    /// the one entered last: ENDFILE for the old file, then FILENAME, FNR,
+    /// in-place capture and BEGINFILE for the new one. The record loop and
+    /// a plain `getline` both call this after each read, so the transition
+    /// runs once, in whichever crosses first. True if it ran; check
+    /// `should_exit` before going on.
+    pub fn enter_input_source(&mut self) -> bool {
+        let source = self.input_source();
+        if source == self.entered_source {
+            return false;
+        }
+        self.leave_input_source(true);
+        if self.exit_code.is_some() {
+            return true;
+        }
+        self.entered_source = source;
+        let filename = self.current_filename().to_owned();
+        self.rt.set_var("FILENAME", &filename);
+        self.rt.reset_fnr();
+        if !filename.is_empty() {
+            self.begin_in_place(&filename);
+        }
+        self.run_beginfile();
+        true
+    }
+
+    /// Run ENDFILE for the source entered last, if any, because the input
+    /// ended or moved past it; `commit` keeps its in-place edit.
+    pub fn leave_input_source(&mut self, commit: bool) {
+        if self.entered_source.take().is_some() {
+            self.run_endfile();
+            self.end_in_place(commit && self.exit_code.is_none());
+        }
+    }
+
+    /// Bytes read from the attached Input so far (`--progress`).
+    pub fn input_bytes_read(&self) -> u64 {
+        self.input.as_ref().map_or(0, |inp| inp.bytes_read())
+    }
+
+    /// Sources the attached Input gave up on under `--keep-going`.
+    pub fn skipped_inputs(&self) -> &[String] {
+        match self.input {
+            Some(ref inp) => inp.skipped(),
+            None => &[],
+        }
+    }
+
     /// Skip the current source in the attached Input.
     pub fn skip_input_source(&mut self) {
         if let Some(ref mut inp) = self.input {
@@ -204,7 +930,7 @@ impl<'a> Executor<'a> {
 
     pub fn run_begin(&mut self) {
         if let Some(ref block) = self.program.begin
-            && let Some(Signal::Exit(code)) = self.exec_block(block)
+            && let Some(Signal::Exit(code)) = self.exec_action(block)
         {
             self.exit_code = Some(code);
         }
@@ -212,15 +938,73 @@ impl<'a> Executor<'a> {
 
     pub fn run_end(&mut self) {
         if let Some(ref block) = self.program.end {
-            self.exec_block(block);
+            self.exec_action(block);
+        }
+        if let Some(table) = self.table.as_mut() {
+            table.flush(&mut self.stdout);
         }
         let _ = self.stdout.flush();
         self.close_all_handles();
+        if self.warnings_suppressed > 0 {
+            crate::progress::clear();
+            eprintln!(
+                "fk: … suppressed {} more repeated warnings",
+                self.warnings_suppressed
+            );
+        }
+    }
+
+    /// Flush what has been printed so far (before a fatal exit).
+    pub fn flush_output(&mut self) {
+        let _ = self.stdout.flush();
+    }
+
+    /// Note that the main input is CSV, which `header()` then writes.
+    pub fn set_csv_output(&mut self, on: bool) {
+        self.csv_output = on;
+    }
+
+    /// Write the header before the first unredirected print (`--emit-header`).
+    pub fn set_emit_header(&mut self, on: bool) {
+        self.emit_header = on;
+    }
+
+    /// Keep the `\r` of `\r\n` line ends in `$0` (`--preserve-cr`).
+    pub fn set_preserve_cr(&mut self, on: bool) {
+        self.rt.set_preserve_cr(on);
+    }
+
+    /// Also set each header name as a variable holding its column index
+    /// (`--header-vars`).
+    pub fn set_header_vars(&mut self, on: bool) {
+        self.header_vars = on;
+    }
+
+    /// Count warnings without printing them (`--quiet`).
+    pub fn set_quiet(&mut self, on: bool) {
+        self.quiet = on;
+    }
+
+    /// Make printf/sprintf argument-count mismatches and calls nested past
+    /// `--max-depth` fatal (`--strict`).
+    pub fn set_strict(&mut self, on: bool) {
+        self.strict = on;
+    }
+
+    /// Flush standard output after every `print` and `printf`.
+    pub fn set_line_buffered(&mut self, on: bool) {
+        self.line_buffered = on;
+    }
+
+    /// Send default output to `w` instead of standard output.
+    pub fn set_stdout(&mut self, w: Box<dyn Write>) {
+        let _ = self.stdout.flush();
+        self.stdout = BufWriter::new(w);
     }
 
     pub fn run_beginfile(&mut self) {
         if let Some(ref block) = self.program.beginfile
-            && let Some(Signal::Exit(code)) = self.exec_block(block)
+            && let Some(Signal::Exit(code)) = self.exec_action(block)
         {
             self.exit_code = Some(code);
         }
@@ -228,7 +1012,7 @@ impl<'a> Executor<'a> {
 
     pub fn run_endfile(&mut self) {
         if let Some(ref block) = self.program.endfile
-            && let Some(Signal::Exit(code)) = self.exec_block(block)
+            && let Some(Signal::Exit(code)) = self.exec_action(block)
         {
             self.exit_code = Some(code);
         }
@@ -239,6 +1023,36 @@ impl<'a> Executor<'a> {
         self.exit_code
     }
 
+    /// Mirror an open redirection in `PROCINFO[name, "type"]`: "pipe" or
+    /// "file" while something by that name is open, deleted once not.
+    pub(crate) fn sync_stream_info(&mut self, name: &str) {
+        let key = format!("{}{}type", name, self.rt.get_var("SUBSEP"));
+        if self.output_pipes.contains_key(name) || self.input_pipes.contains_key(name) {
+            self.rt.set_array("PROCINFO", &key, "pipe");
+        } else if self.output_files.contains_key(name) || self.input_files.contains_key(name) {
+            self.rt.set_array("PROCINFO", &key, "file");
+        } else {
+            self.rt.delete_array("PROCINFO", &key);
+        }
+        let open = self.output_pipes.len().to_string();
+        self.rt.set_array("PROCINFO", "open_pipes", &open);
+    }
+
+    /// Flush stdout and every open output file and pipe; false if any
+    /// flush failed.
+    pub(crate) fn flush_all(&mut self) -> bool {
+        let mut ok = self.stdout.flush().is_ok();
+        for file in self.output_files.values_mut() {
+            ok &= file.flush().is_ok();
+        }
+        for child in self.output_pipes.values_mut() {
+            if let Some(stdin) = &mut child.stdin {
+                ok &= stdin.flush().is_ok();
+            }
+        }
+        ok
+    }
+
     fn close_all_handles(&mut self) {
         for (_, file) in self.output_files.drain() {
             drop(file);
@@ -247,11 +1061,13 @@ impl<'a> Executor<'a> {
             drop(child.stdin.take());
             let _ = child.wait();
         }
+        self.pipe_used.clear();
         self.input_files.clear();
-        self.input_pipe_readers.clear();
-        for (_, mut child) in self.input_pipe_children.drain() {
-            let _ = child.wait();
+        for (_, pipe) in self.input_pipes.drain() {
+            pipe.close();
         }
+        // Background commands are not left orphaned.
+        self.wait_all_bg();
     }
 
     /// Returns true if nextfile was requested during this record.
@@ -265,70 +1081,57 @@ impl<'a> Executor<'a> {
         if self.exit_code.is_some() {
             return;
         }
-        self.next_record = false;
-        self.rt.increment_nr();
         match &record.fields {
-            Some(fields) => self.rt.set_record_fields(&record.text, fields.clone()),
-            None if !self.info.needs_fields && !self.info.needs_nf => {
-                self.rt.set_record_nosplit(&record.text);
+            Some(fields) => {
+                self.rt.increment_nr();
+                self.rt.set_record_fields(&record.text, fields);
             }
-            None if !self.info.needs_nf => {
-                if let Some(limit) = self.info.max_field {
-                    self.rt.set_record_capped(&record.text, limit);
-                } else {
-                    self.rt.set_record(&record.text);
-                }
-            }
-            None => self.rt.set_record(&record.text),
+            None => self.load_record_text(&record.text),
         }
+        self.run_rules(&record.text);
+        self.stop_if_stdout_closed();
+    }
 
-        let program = self.program;
-        for i in 0..program.rules.len() {
-            if self.next_record || self.next_file || self.exit_code.is_some() {
-                break;
-            }
-            let matched = self.match_rule(i, &record.text);
-            if matched && let Some(Signal::Exit(code)) = self.exec_block(&program.rules[i].action) {
-                self.exit_code = Some(code);
-                break;
+    /// Like `run_record`, but pre-split fields (CSV/TSV/JSON) are moved
+    /// into the runtime instead of copied; `record` gets the previous
+    /// record's field buffers back for the reader to refill.
+    pub fn run_record_mut(&mut self, record: &mut Record) {
+        if self.exit_code.is_some() {
+            return;
+        }
+        match &mut record.fields {
+            Some(fields) => {
+                self.rt.increment_nr();
+                self.rt.swap_record_fields(&record.text, fields);
             }
+            None => self.load_record_text(&record.text),
         }
+        self.run_rules(&record.text);
+        self.stop_if_stdout_closed();
     }
 
-    fn match_rule(&mut self, rule_idx: usize, line: &str) -> bool {
-        // Copy the program reference to sever the borrow through `self`,
-        // allowing `&mut self` methods (eval_expr, regex_is_match) to be
-        // called while holding pattern references from the program.
-        let program = self.program;
-        let pattern = &program.rules[rule_idx].pattern;
-        match pattern {
-            None => true,
-            Some(Pattern::Regex(pat)) => self.regex_is_match(pat, line),
-            Some(Pattern::Expression(expr)) => self.eval_expr(expr).is_truthy(),
-            Some(Pattern::Range(start, end)) => {
-                if self.range_active[rule_idx] {
-                    if self.match_single_pattern(end, line) {
-                        self.range_active[rule_idx] = false;
-                    }
-                    true
-                } else if self.match_single_pattern(start, line) {
-                    self.range_active[rule_idx] = true;
-                    true
-                } else {
-                    false
-                }
-            }
-            Some(Pattern::Last(n_expr)) => {
-                let capacity = self.eval_expr(n_expr).to_number() as usize;
-                let buf = self.last_buffers[rule_idx]
-                    .get_or_insert_with(|| (capacity, std::collections::VecDeque::with_capacity(capacity)));
-                buf.0 = capacity;
-                if buf.1.len() >= capacity {
-                    buf.1.pop_front();
-                }
-                buf.1.push_back(line.to_string());
-                false
-            }
+    /// Once nothing reads standard output, end the run the way awk ends
+    /// on SIGPIPE: redirected output is flushed and END does not run.
+    /// Rules that cannot write (`emits_output_in_rules`) skip the check.
+    fn stop_if_stdout_closed(&mut self) {
+        if self.info.emits_output_in_rules && self.stdout_closed.get() {
+            self.flush_all();
+            std::process::exit(STDOUT_CLOSED_EXIT);
+        }
+    }
+
+    /// Store a record that still needs splitting, doing only as much
+    /// splitting as the program's fields and NF use call for.
+    fn load_record_text(&mut self, text: &str) {
+        self.rt.increment_nr();
+        if !self.info.needs_fields && !self.info.needs_nf {
+            self.rt.set_record_nosplit(text);
+        } else if !self.info.needs_nf
+            && let Some(limit) = self.info.max_field
+        {
+            self.rt.set_record_capped(text, limit);
+        } else {
+            self.rt.set_record(text);
         }
     }
 
@@ -346,21 +1149,13 @@ impl<'a> Executor<'a> {
                 }
                 self.rt.set_record(&text);
                 let program = self.program;
-                if let Some(Signal::Exit(code)) = self.exec_block(&program.rules[i].action) {
+                if let Some(Signal::Exit(code)) = self.exec_action(&program.rules[i].action) {
                     self.exit_code = Some(code);
                     return;
                 }
             }
         }
     }
-
-    fn match_single_pattern(&mut self, pattern: &Pattern, line: &str) -> bool {
-        match pattern {
-            Pattern::Regex(pat) => self.regex_is_match(pat, line),
-            Pattern::Expression(expr) => self.eval_expr(expr).is_truthy(),
-            Pattern::Range(_, _) | Pattern::Last(_) => false,
-        }
-    }
 }
 
 pub(crate) fn is_valid_ident(s: &str) -> bool {
@@ -390,6 +1185,8 @@ pub(crate) fn is_builtin_var(name: &str) -> bool {
             | "ARGC"
             | "ARGV"
             | "ENVIRON"
+            | "PROCINFO"
+            | "FKARGS"
             | "BEGIN"
             | "END"
             | "HDR"
@@ -0,0 +1,239 @@
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Longest line accepted from a `cmd | getline` pipe.
+pub(crate) const MAX_PIPE_LINE: usize = 64 << 20;
+
+/// Outcome of one `cmd | getline` read.
+pub(crate) enum PipeRead {
+    Line(String),
+    Eof,
+    Error(String),
+    Timeout,
+}
+
+/// Where lines come from: the pipe itself, or a helper thread that reads
+/// one line per request so a read can be abandoned after GETLINE_TIMEOUT.
+enum Source {
+    Direct(BufReader<ChildStdout>),
+    Worker {
+        requests: Sender<()>,
+        lines: Receiver<PipeRead>,
+        worker: JoinHandle<()>,
+    },
+    Closed,
+}
+
+/// The read side of `cmd | getline`. The pipe is read directly until the
+/// first read with a timeout; from then on a helper thread does the reading.
+/// A command started while a timeout is in effect runs in its own process
+/// group so the timeout can kill everything it started; otherwise it stays
+/// in ours, keeping terminal access and Ctrl-C.
+pub(crate) struct PipeReader {
+    child: Child,
+    source: Source,
+    preserve_cr: bool,
+    grouped: bool,
+}
+
+impl PipeReader {
+    /// With `preserve_cr`, the `\r` of a `\r\n` line end is kept. With
+    /// `own_group`, the command leads a new process group.
+    pub(crate) fn spawn(cmd: &str, preserve_cr: bool, own_group: bool) -> std::io::Result<Self> {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(cmd).stdout(Stdio::piped());
+        #[cfg(unix)]
+        if own_group {
+            std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        }
+        let mut child = command.spawn()?;
+        let stdout = child.stdout.take().unwrap();
+        Ok(PipeReader {
+            child,
+            source: Source::Direct(BufReader::new(stdout)),
+            preserve_cr,
+            grouped: cfg!(unix) && own_group,
+        })
+    }
+
+    /// Read the next line, waiting at most `timeout` when one is given.
+    pub(crate) fn read_line(&mut self, timeout: Option<Duration>) -> PipeRead {
+        if timeout.is_some() {
+            self.start_worker();
+        }
+        match &mut self.source {
+            Source::Direct(reader) => read_capped_line(reader, self.preserve_cr),
+            Source::Worker {
+                requests, lines, ..
+            } => {
+                if requests.send(()).is_err() {
+                    return PipeRead::Eof;
+                }
+                match timeout {
+                    None => lines.recv().unwrap_or(PipeRead::Eof),
+                    Some(t) => match lines.recv_timeout(t) {
+                        Ok(r) => r,
+                        Err(RecvTimeoutError::Timeout) => PipeRead::Timeout,
+                        Err(RecvTimeoutError::Disconnected) => PipeRead::Eof,
+                    },
+                }
+            }
+            Source::Closed => PipeRead::Eof,
+        }
+    }
+
+    /// Hand the pipe over to a helper thread, keeping anything already
+    /// buffered.
+    fn start_worker(&mut self) {
+        self.source = match std::mem::replace(&mut self.source, Source::Closed) {
+            Source::Direct(mut reader) => {
+                let preserve_cr = self.preserve_cr;
+                let (req_tx, req_rx) = mpsc::channel::<()>();
+                let (line_tx, line_rx) = mpsc::channel();
+                let worker = thread::spawn(move || {
+                    for () in req_rx {
+                        let line = read_capped_line(&mut reader, preserve_cr);
+                        if line_tx.send(line).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Source::Worker {
+                    requests: req_tx,
+                    lines: line_rx,
+                    worker,
+                }
+            }
+            other => other,
+        };
+    }
+
+    /// Close our end of the pipe and reap the command. Returns its exit status.
+    pub(crate) fn close(mut self) -> i32 {
+        if let Source::Worker {
+            requests, worker, ..
+        } = std::mem::replace(&mut self.source, Source::Closed)
+        {
+            drop(requests);
+            let _ = worker.join();
+        }
+        match self.child.wait() {
+            Ok(status) => status.code().unwrap_or(-1),
+            Err(_) => -1,
+        }
+    }
+
+    /// Kill the command after a timeout, along with its process group when
+    /// it has one, and reap it. A reader thread is left to finish on its own
+    /// in case a process outside the group still holds the pipe open.
+    pub(crate) fn kill(mut self) -> std::io::Result<()> {
+        #[cfg(unix)]
+        let group = if self.grouped {
+            kill_group(self.child.id())
+        } else {
+            Ok(())
+        };
+        #[cfg(not(unix))]
+        let group = Ok(());
+        let killed = self.child.kill();
+        self.child.wait()?;
+        group.and(killed)
+    }
+}
+
+/// Send SIGKILL to the process group led by `pid`.
+#[cfg(unix)]
+fn kill_group(pid: u32) -> std::io::Result<()> {
+    let pgid = libc::pid_t::try_from(pid).map_err(std::io::Error::other)?;
+    // SAFETY: kill(2) takes plain integers and touches no memory of ours.
+    if unsafe { libc::kill(-pgid, libc::SIGKILL) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+fn read_capped_line(reader: &mut impl BufRead, preserve_cr: bool) -> PipeRead {
+    let mut buf = Vec::new();
+    let limit = MAX_PIPE_LINE as u64 + 1;
+    match reader.by_ref().take(limit).read_until(b'\n', &mut buf) {
+        Ok(0) => PipeRead::Eof,
+        Ok(_) if buf.len() > MAX_PIPE_LINE => {
+            PipeRead::Error(format!("line exceeds {} bytes", MAX_PIPE_LINE))
+        }
+        Ok(_) => {
+            let mut line = String::from_utf8_lossy(&buf).into_owned();
+            crate::input::strip_line_end(&mut line, preserve_cr);
+            PipeRead::Line(line)
+        }
+        Err(e) => PipeRead::Error(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn reads_lines_then_eof() {
+        let mut p = PipeReader::spawn("printf 'a\\r\\nb\\n'", false, false).unwrap();
+        assert!(matches!(p.read_line(None), PipeRead::Line(ref s) if s == "a"));
+        assert!(matches!(p.read_line(None), PipeRead::Line(ref s) if s == "b"));
+        assert!(matches!(p.read_line(None), PipeRead::Eof));
+        assert_eq!(p.close(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stays_in_our_process_group_without_a_timeout() {
+        let mut p = PipeReader::spawn("ps -o pgid= -p $$", false, false).unwrap();
+        let PipeRead::Line(pgid) = p.read_line(None) else {
+            panic!("expected the command's process group");
+        };
+        // SAFETY: getpgrp(2) takes no arguments and cannot fail.
+        let ours = unsafe { libc::getpgrp() };
+        assert_eq!(pgid.trim(), ours.to_string());
+        assert_eq!(p.close(), 0);
+    }
+
+    #[test]
+    fn timeout_after_direct_reads_kills_the_process_group() {
+        let mut p = PipeReader::spawn("sleep 5 & echo $!; echo x; wait", false, true).unwrap();
+        let PipeRead::Line(pid) = p.read_line(None) else {
+            panic!("expected the background pid");
+        };
+        assert!(matches!(p.source, Source::Direct(_)));
+        let r = p.read_line(Some(Duration::from_millis(200)));
+        assert!(matches!(r, PipeRead::Line(ref s) if s == "x"));
+        let r = p.read_line(Some(Duration::from_millis(200)));
+        assert!(matches!(r, PipeRead::Timeout));
+        assert!(p.kill().is_ok());
+        #[cfg(target_os = "linux")]
+        {
+            // The orphaned sleep is gone, or at most an unreaped zombie.
+            std::thread::sleep(Duration::from_millis(100));
+            let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).unwrap_or_default();
+            let state = stat.rsplit_once(')').map(|(_, rest)| rest.trim_start());
+            assert!(state.is_none_or(|s| s.starts_with('Z')), "{stat}");
+        }
+        let _ = pid;
+    }
+
+    #[test]
+    fn timeout_kills_and_reaps_child() {
+        let start = Instant::now();
+        let mut p = PipeReader::spawn("sleep 5", false, true).unwrap();
+        let pid = p.child.id();
+        let r = p.read_line(Some(Duration::from_millis(200)));
+        assert!(matches!(r, PipeRead::Timeout));
+        assert!(p.kill().is_ok());
+        assert!(start.elapsed() < Duration::from_secs(2));
+        #[cfg(target_os = "linux")]
+        assert!(!std::path::Path::new(&format!("/proc/{pid}")).exists());
+        let _ = pid;
+    }
+}
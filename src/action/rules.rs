@@ -0,0 +1,164 @@
+//! Rules prepared once for the per-record loop.
+//!
+//! `Executor::new` turns each `pattern { action }` into a [`CompiledRule`]:
+//! regex literals are compiled up front and held directly, so matching a
+//! record walks a flat Vec with no cache lookups or pattern-string hashing.
+
+use std::collections::VecDeque;
+
+use regex::Regex;
+
+use crate::parser::{Block, Expr, Pattern, Rule};
+
+use super::{Executor, Signal, compile_regex};
+
+/// One side of a rule pattern.
+pub(crate) enum Cond<'a> {
+    /// A regex literal and its compiled form. `None` when it failed to
+    /// compile; matching then goes through `regex_is_match`, which reports
+    /// the error once.
+    Regex(&'a str, Option<Regex>),
+    Expr(&'a Expr),
+    /// A range or `last` nested inside a range never matches.
+    Never,
+}
+
+pub(crate) enum Matcher<'a> {
+    /// No pattern: every record.
+    Always,
+    Cond(Cond<'a>),
+    /// `start, end`, with its slot in `Executor::range_active`.
+    Range(Cond<'a>, Cond<'a>, usize),
+    /// `last N`, with its slot in `Executor::last_buffers`.
+    Last(&'a Expr, usize),
+}
+
+pub(crate) struct CompiledRule<'a> {
+    pub(crate) matcher: Matcher<'a>,
+    pub(crate) action: &'a Block,
+}
+
+impl<'a> CompiledRule<'a> {
+    pub(crate) fn new(rule: &'a Rule, index: usize) -> Self {
+        let matcher = match &rule.pattern {
+            None => Matcher::Always,
+            Some(Pattern::Range(start, end)) => Matcher::Range(cond(start), cond(end), index),
+            Some(Pattern::Last(n)) => Matcher::Last(n, index),
+            Some(p) => Matcher::Cond(cond(p)),
+        };
+        CompiledRule {
+            matcher,
+            action: &rule.action,
+        }
+    }
+
+    fn conds_mut(&mut self) -> impl Iterator<Item = &mut Cond<'a>> {
+        let (a, b) = match &mut self.matcher {
+            Matcher::Cond(c) => (Some(c), None),
+            Matcher::Range(start, end, _) => (Some(start), Some(end)),
+            Matcher::Always | Matcher::Last(..) => (None, None),
+        };
+        a.into_iter().chain(b)
+    }
+}
+
+fn cond(pattern: &Pattern) -> Cond<'_> {
+    match pattern {
+        Pattern::Regex(pat) => Cond::Regex(pat, None),
+        Pattern::Expression(expr) => Cond::Expr(expr),
+        Pattern::Range(..) | Pattern::Last(_) => Cond::Never,
+    }
+}
+
+impl Executor<'_> {
+    /// (Re)compile the rules' regex literals under the current IGNORECASE
+    /// and `--regex-limit`. Failures stay `None` and are reported on use.
+    pub(crate) fn compile_rule_regexes(&mut self) {
+        let mut rules = std::mem::take(&mut self.rules);
+        self.compile_regexes_of(&mut rules);
+        self.rules = rules;
+    }
+
+    fn compile_regexes_of(&mut self, rules: &mut [CompiledRule]) {
+        self.rules_icase = self.rt.ignore_case();
+        for rule in rules {
+            for c in rule.conds_mut() {
+                if let Cond::Regex(pat, re) = c {
+                    *re = if self.rules_icase {
+                        compile_regex(&format!("(?i){pat}"), self.regex_limit).ok()
+                    } else {
+                        compile_regex(pat, self.regex_limit).ok()
+                    };
+                }
+            }
+        }
+    }
+
+    /// Run the rules against the current record, in order.
+    pub(crate) fn run_rules(&mut self, text: &str) {
+        self.next_record = false;
+        // Moved out for the loop so actions can borrow `self` mutably.
+        let mut rules = std::mem::take(&mut self.rules);
+        for i in 0..rules.len() {
+            if self.next_record || self.next_file || self.exit_code.is_some() {
+                break;
+            }
+            if self.rt.ignore_case() != self.rules_icase {
+                self.compile_regexes_of(&mut rules);
+            }
+            let rule = &rules[i];
+            if self.rule_matches(&rule.matcher, text) {
+                match self.exec_action(rule.action) {
+                    Some(Signal::Exit(code)) => {
+                        self.exit_code = Some(code);
+                        break;
+                    }
+                    // Too-deep recursion abandons the record.
+                    Some(Signal::Abort) => break,
+                    _ => {}
+                }
+            }
+        }
+        self.rules = rules;
+    }
+
+    fn rule_matches(&mut self, matcher: &Matcher, line: &str) -> bool {
+        match matcher {
+            Matcher::Always => true,
+            Matcher::Cond(c) => self.cond_matches(c, line),
+            Matcher::Range(start, end, slot) => {
+                if self.range_active[*slot] {
+                    if self.cond_matches(end, line) {
+                        self.range_active[*slot] = false;
+                    }
+                    true
+                } else if self.cond_matches(start, line) {
+                    self.range_active[*slot] = true;
+                    true
+                } else {
+                    false
+                }
+            }
+            Matcher::Last(n_expr, slot) => {
+                let capacity = self.eval_expr(n_expr).to_number() as usize;
+                let buf = self.last_buffers[*slot]
+                    .get_or_insert_with(|| (capacity, VecDeque::with_capacity(capacity)));
+                buf.0 = capacity;
+                if buf.1.len() >= capacity {
+                    buf.1.pop_front();
+                }
+                buf.1.push_back(line.to_string());
+                false
+            }
+        }
+    }
+
+    fn cond_matches(&mut self, c: &Cond, line: &str) -> bool {
+        match c {
+            Cond::Regex(_, Some(re)) => re.is_match(line),
+            Cond::Regex(pat, None) => self.regex_is_match(pat, line),
+            Cond::Expr(expr) => self.eval_expr(expr).is_truthy(),
+            Cond::Never => false,
+        }
+    }
+}
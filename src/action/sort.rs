@@ -0,0 +1,216 @@
+//! Sorting for asort/asorti and the smart key order behind keys(), vals()
+//! and `print arr`.
+//!
+//! Each element's numeric form is parsed once up front instead of in every
+//! comparison. The comparisons themselves are unchanged, so a serial sort
+//! gives exactly the old order. With the `parallel` feature, big arrays are
+//! sorted on all cores — but only when the comparison is a total order on
+//! the data (no NaN, and for asort no mix that makes "equal" intransitive).
+//! Every stable sort then produces the same order, so the parallel result
+//! is indistinguishable; otherwise the serial sort keeps the old tie-breaks.
+
+use std::cmp::Ordering;
+
+/// Arrays at least this large are sorted in parallel.
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN: usize = 100_000;
+
+/// Stable sort of `items`, in parallel when `total` says `cmp` is a total
+/// order on them and there are enough to be worth it.
+fn stable_sort<T: Send>(items: &mut [T], total: bool, cmp: impl Fn(&T, &T) -> Ordering + Sync) {
+    #[cfg(feature = "parallel")]
+    if total && items.len() >= PARALLEL_MIN {
+        use rayon::slice::ParallelSliceMut;
+        items.par_sort_by(cmp);
+        return;
+    }
+    #[cfg(not(feature = "parallel"))]
+    let _ = total;
+    items.sort_by(cmp);
+}
+
+/// Sort keys numeric-first (1, 2, 10 before "apple", "banana"); the order
+/// of `smart_cmp`.
+pub(crate) fn smart_sort_keys(keys: &mut [String]) {
+    let mut tagged: Vec<(Option<f64>, String)> = keys
+        .iter_mut()
+        .map(|k| (k.parse::<f64>().ok(), std::mem::take(k)))
+        .collect();
+    let total = !tagged.iter().any(|(n, _)| n.is_some_and(f64::is_nan));
+    stable_sort(&mut tagged, total, |a, b| match (a.0, b.0) {
+        (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.1.cmp(&b.1),
+    });
+    for (slot, (_, key)) in keys.iter_mut().zip(tagged) {
+        *slot = key;
+    }
+}
+
+/// An asort element with its numeric value parsed once.
+struct ByValue {
+    n: f64,
+    /// Compared by number: nonzero, or empty. Other zero-valued strings
+    /// ("abc", "0") compare as strings among themselves.
+    numeric: bool,
+    item: (String, String),
+}
+
+/// asort's default order on (key, value) pairs: by value, numerically when
+/// either side is numeric, else bytewise.
+pub(crate) fn sort_by_value(items: &mut Vec<(String, String)>) {
+    let mut tagged: Vec<ByValue> = items
+        .drain(..)
+        .map(|item| {
+            let n = crate::builtins::to_number(&item.1);
+            ByValue {
+                n,
+                numeric: n != 0.0 || item.1.is_empty(),
+                item,
+            }
+        })
+        .collect();
+    // Empty values equal every zero-valued string, which need not equal
+    // each other: only one of the two kinds may be present.
+    let mixed = tagged.iter().any(|t| t.item.1.is_empty()) && tagged.iter().any(|t| !t.numeric);
+    let total = !mixed && !tagged.iter().any(|t| t.n.is_nan());
+    stable_sort(&mut tagged, total, |a, b| {
+        if a.numeric || b.numeric {
+            a.n.partial_cmp(&b.n).unwrap_or(Ordering::Equal)
+        } else {
+            a.item.1.cmp(&b.item.1)
+        }
+    });
+    items.extend(tagged.into_iter().map(|t| t.item));
+}
+
+/// asorti's default order: bytewise by key.
+pub(crate) fn sort_by_key(items: &mut [(String, String)]) {
+    stable_sort(items, true, |a, b| a.0.cmp(&b.0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::builtins_rt::smart_cmp;
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// The comparator asort used before values were pre-parsed.
+    fn old_value_cmp(a: &(String, String), b: &(String, String)) -> Ordering {
+        let na = crate::builtins::to_number(&a.1);
+        let nb = crate::builtins::to_number(&b.1);
+        if na != 0.0 || a.1.is_empty() || nb != 0.0 || b.1.is_empty() {
+            na.partial_cmp(&nb).unwrap_or(Ordering::Equal)
+        } else {
+            a.1.cmp(&b.1)
+        }
+    }
+
+    #[test]
+    fn smart_keys_keep_the_old_order_and_ties() {
+        let cases = [
+            strings(&["10", "9", "b", "a", "1e1", "010", "-1", "x", "2.5"]),
+            // NaN is "equal" to everything: ties fall back to input order.
+            strings(&["nan", "3", "b", "1", "NaN", "a", "2"]),
+            strings(&["inf", "-inf", "0", "-0", "0.0", "z"]),
+        ];
+        for keys in cases {
+            let mut old = keys.clone();
+            old.sort_by(|a, b| smart_cmp(a, b));
+            let mut new = keys.clone();
+            smart_sort_keys(&mut new);
+            assert_eq!(new, old);
+        }
+        let mut keys = strings(&["10", "1e1", "010", "b", "a"]);
+        smart_sort_keys(&mut keys);
+        assert_eq!(keys, ["10", "1e1", "010", "a", "b"]);
+    }
+
+    #[test]
+    fn values_keep_the_old_order_and_ties() {
+        let pairs = |v: &[&str]| -> Vec<(String, String)> {
+            v.iter()
+                .enumerate()
+                .map(|(i, s)| (i.to_string(), s.to_string()))
+                .collect()
+        };
+        let cases = [
+            pairs(&["10", "9", "b", "a", "", "0", "-3", "abc", "2x", "0.0"]),
+            pairs(&["", "b", "", "a", "0", "1"]),
+            pairs(&["nan", "2", "b", "1", "a", "nan"]),
+            pairs(&["3", "1", "3", "2", "1"]),
+        ];
+        for items in cases {
+            let mut old = items.clone();
+            old.sort_by(old_value_cmp);
+            let mut new = items.clone();
+            sort_by_value(&mut new);
+            assert_eq!(new, old);
+        }
+        // Equal values keep their input order.
+        let mut items = pairs(&["3", "1", "3", "2", "1"]);
+        sort_by_value(&mut items);
+        let keys: Vec<&str> = items.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["1", "4", "3", "0", "2"]);
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn parallel_sort_matches_serial_on_total_orders() {
+        let n = PARALLEL_MIN + 1234;
+        let keys: Vec<String> = (0..n)
+            .map(|i| match i % 4 {
+                0 => format!("{}", (i * 7919) % 1000),
+                1 => format!("k{}", (i * 31) % 5000),
+                2 => format!("{}.5", i % 97),
+                _ => format!("-{}", i % 13),
+            })
+            .collect();
+        let mut serial = keys.clone();
+        serial.sort_by(|a, b| smart_cmp(a, b));
+        let mut parallel = keys.clone();
+        smart_sort_keys(&mut parallel);
+        assert_eq!(parallel, serial);
+
+        let items: Vec<(String, String)> = keys
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| (i.to_string(), v))
+            .collect();
+        let mut serial = items.clone();
+        serial.sort_by(old_value_cmp);
+        let mut parallel = items;
+        sort_by_value(&mut parallel);
+        assert_eq!(parallel, serial);
+    }
+
+    /// `cargo test --release -- --ignored sort_speed --nocapture`
+    #[test]
+    #[ignore]
+    fn sort_speed() {
+        use std::time::Instant;
+        let n = 2_000_000;
+        let items: Vec<(String, String)> = (0..n)
+            .map(|i| {
+                (
+                    format!("k{i}"),
+                    format!("{}", (i * 2_654_435_761u64) % 1_000_003),
+                )
+            })
+            .collect();
+        let mut old = items.clone();
+        let t = Instant::now();
+        old.sort_by(old_value_cmp);
+        let old_time = t.elapsed();
+        let mut new = items;
+        let t = Instant::now();
+        sort_by_value(&mut new);
+        let new_time = t.elapsed();
+        assert_eq!(new, old);
+        eprintln!("asort of {n}: comparator parsing {old_time:?}, pre-parsed {new_time:?}");
+    }
+}
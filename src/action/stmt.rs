@@ -2,12 +2,31 @@ use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::process::{Command, Stdio};
 
-use crate::builtins::{self, format_printf};
-use crate::parser::SortMode;
 use crate::parser::{Block, Expr, FuncDef, Redirect, Statement};
 use crate::runtime::Value;
 
-use super::{Executor, MAX_CALL_DEPTH, Signal};
+use super::{Executor, Signal};
+
+/// Redirection targets that name the process's own streams. They are
+/// never opened as files, so they work where `/dev` lacks them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum StdStream {
+    Stdout,
+    Stderr,
+}
+
+pub(crate) fn std_stream(path: &str) -> Option<StdStream> {
+    match path {
+        "/dev/stdout" | "/dev/fd/1" => Some(StdStream::Stdout),
+        "/dev/stderr" | "/dev/fd/2" => Some(StdStream::Stderr),
+        _ => None,
+    }
+}
+
+/// Names `getline < name` reads from standard input.
+pub(crate) fn is_stdin_name(path: &str) -> bool {
+    matches!(path, "-" | "/dev/stdin" | "/dev/fd/0")
+}
 
 impl<'a> Executor<'a> {
     pub(crate) fn exec_block(&mut self, block: &Block) -> Option<Signal> {
@@ -15,53 +34,90 @@ impl<'a> Executor<'a> {
             if let Some(signal) = self.exec_stmt(stmt) {
                 return Some(signal);
             }
+            if self.call_aborted {
+                return Some(Signal::Abort);
+            }
         }
         None
     }
 
+    /// After a print: push it out now under `--line-buffered`, including
+    /// what went to `/dev/stdout` while `-i` captures default output.
+    pub(crate) fn flush_if_line_buffered(&mut self) {
+        if self.line_buffered {
+            let _ = self.stdout.flush();
+            let _ = self.real_stdout().flush();
+        }
+    }
+
+    /// Run a pattern's or special block's action. An aborted call ends
+    /// here: the next action starts afresh. Array storage problems met
+    /// before or during the action are reported as warnings.
+    pub(crate) fn exec_action(&mut self, block: &Block) -> Option<Signal> {
+        self.report_storage_warnings();
+        let signal = self.exec_block(block);
+        self.call_aborted = false;
+        self.report_storage_warnings();
+        signal
+    }
+
+    fn report_storage_warnings(&mut self) {
+        if self.rt.has_warnings() {
+            for text in self.rt.take_warnings() {
+                self.warn("array storage", text);
+            }
+        }
+    }
+
     pub(crate) fn exec_stmt(&mut self, stmt: &Statement) -> Option<Signal> {
         match stmt {
             Statement::Print(exprs, redir) => {
-                if redir.is_none() {
-                    if exprs.len() == 1 {
-                        if let Expr::Var(name) = &exprs[0]
-                            && self.rt.has_array(name)
-                        {
-                            self.print_array(name);
-                        } else {
-                            self.print_expr_fast(&exprs[0]);
-                            let _ = self.stdout.write_all(self.rt.ors().as_bytes());
-                        }
-                    } else {
-                        let ofs = self.rt.ofs().to_owned();
-                        for (i, e) in exprs.iter().enumerate() {
-                            if i > 0 {
-                                let _ = self.stdout.write_all(ofs.as_bytes());
-                            }
-                            self.print_expr_fast(e);
+                if self.emit_header && redir.is_none() {
+                    self.write_header(None);
+                }
+                if redir.is_none() && (self.table.is_some() || self.json_rows || self.tsv_rows) {
+                    self.print_structured_row(exprs);
+                } else if let [Expr::Var(name)] = exprs.as_slice()
+                    && redir.is_none()
+                    && self.rt.has_array(name)
+                {
+                    self.print_array(name);
+                } else if redir.is_none() && self.functions.is_empty() {
+                    let ofs = self.snapshot_ofs(exprs.len());
+                    for (i, e) in exprs.iter().enumerate() {
+                        if i > 0 {
+                            let _ = self.stdout.write_all(ofs.as_bytes());
                         }
-                        let _ = self.stdout.write_all(self.rt.ors().as_bytes());
+                        self.print_expr_fast(e);
                     }
+                    self.print_ofs = ofs;
+                    let _ = self.stdout.write_all(self.rt.ors().as_bytes());
                 } else {
-                    let ofs = self.rt.ofs().to_owned();
-                    let ors = self.rt.ors().to_owned();
-                    let parts: Vec<String> = exprs.iter().map(|e| self.eval_string(e)).collect();
-                    let mut output = parts.join(&ofs);
-                    output.push_str(&ors);
-                    self.write_output(&output, redir);
+                    // Buffered, so a call aborted mid-line prints nothing.
+                    let line = self.print_line(exprs);
+                    if !self.call_aborted {
+                        self.write_output(&line, redir);
+                    }
+                    self.print_buf = line;
                 }
+                self.flush_if_line_buffered();
             }
             Statement::Printf(exprs, redir) => {
                 if exprs.is_empty() {
                     return None;
                 }
-                let args: Vec<String> = exprs.iter().map(|e| self.eval_string(e)).collect();
-                let output = format_printf(&args[0], &args[1..]);
-                if redir.is_none() {
+                if self.emit_header && redir.is_none() {
+                    self.write_header(None);
+                }
+                let output = self.printf_text("printf", exprs);
+                if self.call_aborted {
+                    return Some(Signal::Abort);
+                } else if redir.is_none() {
                     let _ = self.stdout.write_all(output.as_bytes());
                 } else {
-                    self.write_output(&output, redir);
+                    self.write_output(output.as_bytes(), redir);
                 }
+                self.flush_if_line_buffered();
             }
             Statement::If(cond, then_block, else_block) => {
                 let val = self.eval_expr(cond);
@@ -76,7 +132,7 @@ impl<'a> Executor<'a> {
                 }
             }
             Statement::While(cond, body) => loop {
-                if self.eval_number(cond) == 0.0 {
+                if !self.eval_cond(cond) || self.call_aborted {
                     break;
                 }
                 match self.exec_block(body) {
@@ -93,7 +149,7 @@ impl<'a> Executor<'a> {
                     Some(signal) => return Some(signal),
                     None => {}
                 }
-                if self.eval_number(cond) == 0.0 {
+                if !self.eval_cond(cond) || self.call_aborted {
                     break;
                 }
             },
@@ -108,7 +164,7 @@ impl<'a> Executor<'a> {
                 }
                 loop {
                     if let Some(cond_expr) = cond
-                        && self.eval_number(cond_expr) == 0.0
+                        && (!self.eval_cond(cond_expr) || self.call_aborted)
                     {
                         break;
                     }
@@ -129,43 +185,10 @@ impl<'a> Executor<'a> {
                 }
             }
             Statement::ForIn(var, array, sort_mode, body) => {
-                let mut keys = self.rt.array_keys(array);
-                match sort_mode {
-                    None => {}
-                    Some(SortMode::Asc) => keys.sort(),
-                    Some(SortMode::Desc) => {
-                        keys.sort();
-                        keys.reverse();
-                    }
-                    Some(SortMode::NumAsc) => {
-                        keys.sort_by(|a, b| {
-                            let na = builtins::to_number(a);
-                            let nb = builtins::to_number(b);
-                            na.partial_cmp(&nb).unwrap_or(std::cmp::Ordering::Equal)
-                        });
-                    }
-                    Some(SortMode::NumDesc) => {
-                        keys.sort_by(|a, b| {
-                            let na = builtins::to_number(a);
-                            let nb = builtins::to_number(b);
-                            nb.partial_cmp(&na).unwrap_or(std::cmp::Ordering::Equal)
-                        });
-                    }
-                    Some(SortMode::ValAsc) => {
-                        keys.sort_by(|a, b| {
-                            let va = self.rt.get_array_value(array, a).to_number();
-                            let vb = self.rt.get_array_value(array, b).to_number();
-                            va.partial_cmp(&vb).unwrap_or(std::cmp::Ordering::Equal)
-                        });
-                    }
-                    Some(SortMode::ValDesc) => {
-                        keys.sort_by(|a, b| {
-                            let va = self.rt.get_array_value(array, a).to_number();
-                            let vb = self.rt.get_array_value(array, b).to_number();
-                            vb.partial_cmp(&va).unwrap_or(std::cmp::Ordering::Equal)
-                        });
-                    }
-                }
+                let keys = match sort_mode {
+                    None => self.rt.array_keys(array),
+                    Some(mode) => self.ordered_keys(array, *mode),
+                };
                 for key in keys {
                     self.rt.set_var(var, &key);
                     match self.exec_block(body) {
@@ -213,108 +236,334 @@ impl<'a> Executor<'a> {
                 }
             }
             Statement::Expression(expr) => {
-                let _ = self.eval_expr(expr);
+                if !self.append_in_place(expr) {
+                    let _ = self.eval_expr(expr);
+                }
             }
         }
         None
     }
 
-    pub(crate) fn write_output(&mut self, text: &str, redir: &Option<Redirect>) {
+    /// Emit one `print` as a structured row (`-o table|md|json|tsv`). A bare
+    /// `print` / `print $0` becomes the record's fields; otherwise each
+    /// argument is one column.
+    fn print_structured_row(&mut self, exprs: &[Expr]) {
+        if let [Expr::Var(name)] = exprs
+            && self.rt.has_array(name)
+        {
+            self.print_array(name);
+            return;
+        }
+        let whole_record = matches!(exprs, [Expr::Field(inner)]
+            if matches!(inner.as_ref(), Expr::NumberLit(n) if *n == 0.0));
+        let row: Vec<String> = if whole_record {
+            (1..=self.rt.nf()).map(|i| self.rt.get_field(i)).collect()
+        } else {
+            exprs.iter().map(|e| self.eval_string(e)).collect()
+        };
+        let needs_names = self.json_rows
+            || (self.tsv_rows && !self.tsv_header_done)
+            || !self.table.as_ref().is_some_and(|t| t.has_header());
+        let names: Vec<String> = if !needs_names {
+            Vec::new()
+        } else if whole_record {
+            (1..=row.len())
+                .map(|i| self.rt.get_array("HDR", &i.to_string()))
+                .collect()
+        } else {
+            exprs.iter().map(|e| self.table_column_name(e)).collect()
+        };
+        if self.tsv_rows {
+            // Under -H the first row is preceded by a header line;
+            // unnamed columns are called colN, as in `-o table`.
+            if !self.tsv_header_done {
+                self.tsv_header_done = true;
+                if self.rt.has_array("HDR") {
+                    let names: Vec<String> = names
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, n)| {
+                            if n.is_empty() {
+                                format!("col{}", i + 1)
+                            } else {
+                                n
+                            }
+                        })
+                        .collect();
+                    let head = crate::output::tsv_row(&names);
+                    let _ = self.stdout.write_all(head.as_bytes());
+                    let _ = self.stdout.write_all(b"\n");
+                }
+            }
+            let line = crate::output::tsv_row(&row);
+            let _ = self.stdout.write_all(line.as_bytes());
+            let _ = self.stdout.write_all(b"\n");
+        } else if self.json_rows {
+            let names: Vec<String> = names
+                .into_iter()
+                .enumerate()
+                .map(|(i, n)| {
+                    if n.is_empty() {
+                        format!("f{}", i + 1)
+                    } else {
+                        n
+                    }
+                })
+                .collect();
+            let obj = crate::output::json_object(&names, &row);
+            let _ = self.stdout.write_all(obj.as_bytes());
+            let _ = self.stdout.write_all(b"\n");
+        } else if let Some(table) = self.table.as_mut() {
+            if needs_names {
+                table.set_header(names);
+            }
+            let ofs = self.rt.ofs().to_owned();
+            let ors = self.rt.ors().to_owned();
+            table.push(row, &mut self.stdout, &ofs, &ors);
+        }
+    }
+
+    /// Column heading for a printed expression: `$"name"` and `$N` use the
+    /// header names, plain variables their own name, anything else is unnamed.
+    fn table_column_name(&self, expr: &Expr) -> String {
+        match expr {
+            Expr::Field(inner) => match inner.as_ref() {
+                Expr::StringLit(name) => name.clone(),
+                Expr::NumberLit(n) => self.rt.get_array("HDR", &(*n as usize).to_string()),
+                Expr::Var(name) if self.rt.array_has_key("HDR", name) => name.clone(),
+                _ => String::new(),
+            },
+            Expr::Var(name) => name.clone(),
+            _ => String::new(),
+        }
+    }
+
+    pub(crate) fn write_output(&mut self, text: &[u8], redir: &Option<Redirect>) {
         match redir {
             None => {
-                let _ = self.stdout.write_all(text.as_bytes());
+                let _ = self.stdout.write_all(text);
             }
             Some(Redirect::Overwrite(target_expr)) | Some(Redirect::Append(target_expr)) => {
                 let path = self.eval_string(target_expr);
-                if path == "/dev/stderr" {
-                    eprint!("{}", text);
+                if let Some(file) = self.output_files.get_mut(&path) {
+                    let _ = file.write_all(text);
                     return;
                 }
-                if path == "/dev/stdout" {
-                    let _ = self.stdout.write_all(text.as_bytes());
-                    return;
+                match std_stream(&path) {
+                    Some(StdStream::Stderr) => {
+                        crate::progress::clear();
+                        let _ = std::io::stderr().write_all(text);
+                        return;
+                    }
+                    Some(StdStream::Stdout) => {
+                        let _ = self.real_stdout().write_all(text);
+                        return;
+                    }
+                    None => {}
                 }
                 let is_append = matches!(redir, Some(Redirect::Append(_)));
-                let file = self.output_files.entry(path.clone()).or_insert_with(|| {
-                    if is_append {
-                        OpenOptions::new()
-                            .create(true)
-                            .append(true)
-                            .open(&path)
-                            .unwrap_or_else(|e| {
-                                eprintln!("fk: cannot open '{}': {}", path, e);
-                                File::create("/dev/null").unwrap()
-                            })
-                    } else {
-                        File::create(&path).unwrap_or_else(|e| {
-                            eprintln!("fk: cannot open '{}': {}", path, e);
-                            File::create("/dev/null").unwrap()
-                        })
-                    }
+                let opened = if is_append {
+                    OpenOptions::new().create(true).append(true).open(&path)
+                } else {
+                    File::create(&path)
+                };
+                // Later prints to `path` are dropped; the warning and ERRNO
+                // say so once.
+                let mut file = opened.unwrap_or_else(|e| {
+                    warn!(self, "cannot open '{}': {}", path, e);
+                    self.set_errno(e);
+                    File::create("/dev/null").unwrap()
                 });
-                let _ = file.write_all(text.as_bytes());
+                let _ = file.write_all(text);
+                self.output_files.insert(path.clone(), file);
+                self.sync_stream_info(&path);
             }
             Some(Redirect::Pipe(cmd_expr)) => {
                 let cmd = self.eval_string(cmd_expr);
-                let child = self.output_pipes.entry(cmd.clone()).or_insert_with(|| {
-                    Command::new("sh")
-                        .arg("-c")
-                        .arg(&cmd)
-                        .stdin(Stdio::piped())
-                        .spawn()
-                        .unwrap_or_else(|e| {
-                            eprintln!("fk: cannot run '{}': {}", cmd, e);
-                            Command::new("cat")
-                                .stdin(Stdio::piped())
-                                .stdout(Stdio::null())
-                                .spawn()
-                                .unwrap()
-                        })
-                });
-                if let Some(ref mut stdin) = child.stdin {
-                    let _ = stdin.write_all(text.as_bytes());
+                self.pipe_tick += 1;
+                if let Some(child) = self.output_pipes.get_mut(&cmd) {
+                    if let Some(stdin) = &mut child.stdin {
+                        let _ = stdin.write_all(text);
+                    }
+                    if let Some(used) = self.pipe_used.get_mut(&cmd) {
+                        *used = self.pipe_tick;
+                    }
+                    return;
+                }
+                if self.max_pipes > 0 && self.output_pipes.len() >= self.max_pipes {
+                    self.close_oldest_pipe();
+                }
+                let mut child = Command::new("sh")
+                    .arg("-c")
+                    .arg(&cmd)
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .unwrap_or_else(|e| {
+                        warn!(self, "cannot run '{}': {}", cmd, e);
+                        self.set_errno(e);
+                        Command::new("cat")
+                            .stdin(Stdio::piped())
+                            .stdout(Stdio::null())
+                            .spawn()
+                            .unwrap()
+                    });
+                if let Some(stdin) = &mut child.stdin {
+                    let _ = stdin.write_all(text);
                 }
+                self.output_pipes.insert(cmd.clone(), child);
+                self.pipe_used.insert(cmd.clone(), self.pipe_tick);
+                self.sync_stream_info(&cmd);
             }
         }
     }
 
-    pub(crate) fn call_user_func(&mut self, func: &FuncDef, args: &[String]) -> Value {
-        if self.call_depth >= MAX_CALL_DEPTH {
-            eprintln!("fk: maximum call depth ({}) exceeded", MAX_CALL_DEPTH);
-            return Value::default();
+    /// Close the least recently used output pipe as `close()` would: end
+    /// its input and wait for the command.
+    fn close_oldest_pipe(&mut self) {
+        let oldest = self
+            .pipe_used
+            .iter()
+            .min_by_key(|(_, used)| **used)
+            .map(|(cmd, _)| cmd.clone());
+        if let Some(cmd) = oldest {
+            self.pipe_used.remove(&cmd);
+            if let Some(mut child) = self.output_pipes.remove(&cmd) {
+                drop(child.stdin.take());
+                let _ = child.wait();
+            }
+            self.sync_stream_info(&cmd);
         }
-        self.call_depth += 1;
+    }
 
-        let mut saved: Vec<(String, bool, Value)> = Vec::new();
-        for param in &func.params {
-            let existed = self.rt.has_var(param);
-            let old = self.rt.get_value(param);
-            saved.push((param.clone(), existed, old));
+    /// Call `func` with `args`. `arrays` names, by position, the caller's
+    /// arrays passed by reference: the parameter is bound to that array's
+    /// storage for the call, so element changes and `delete` reach it
+    /// under either name. Any other parameter used as an array is local and
+    /// starts empty on every call.
+    pub(crate) fn call_user_func(
+        &mut self,
+        func: &FuncDef,
+        args: &[Value],
+        arrays: &[Option<String>],
+    ) -> Value {
+        if self.call_aborted {
+            return Value::default();
         }
-
-        for (i, param) in func.params.iter().enumerate() {
-            let val = args.get(i).map(|s| s.as_str()).unwrap_or("");
-            self.rt.set_var(param, val);
+        if self.call_depth >= self.max_call_depth {
+            let (nr, filename) = (self.rt.get_var("NR"), self.rt.get_var("FILENAME"));
+            if self.strict {
+                crate::progress::clear();
+                eprintln!(
+                    "fk: call depth {} exceeded in function {} (NR={}, FILENAME={})",
+                    self.max_call_depth, func.name, nr, filename
+                );
+                self.flush_all();
+                std::process::exit(2);
+            }
+            warn!(
+                self,
+                "call depth {} exceeded in function {} (NR={}, FILENAME={})",
+                self.max_call_depth,
+                func.name,
+                nr,
+                filename
+            );
+            self.call_aborted = true;
+            return Value::default();
         }
+        self.call_depth += 1;
+        let saved = self.bind_params(func, args, arrays);
 
         let result = match self.exec_block(&func.body) {
             Some(Signal::Return(v)) => v,
             Some(Signal::Exit(code)) => {
                 self.exit_code = Some(code);
+                // The caller's action ends too.
+                self.call_aborted = true;
                 Value::default()
             }
             _ => Value::default(),
         };
 
-        for (name, existed, old_val) in saved {
-            if existed {
-                self.rt.set_value(&name, old_val);
-            } else {
-                self.rt.remove_var(&name);
+        self.restore_params(saved);
+        self.call_depth -= 1;
+        result
+    }
+
+    /// Set up `func`'s parameters for a call, returning what they replaced.
+    /// Kept out of `call_user_func` so deep recursion stays within the
+    /// stack.
+    #[inline(never)]
+    fn bind_params(
+        &mut self,
+        func: &FuncDef,
+        args: &[Value],
+        arrays: &[Option<String>],
+    ) -> Vec<SavedParam> {
+        // Resolve every argument before binding any parameter: f(b, a)
+        // with parameters (a, b) must still see the caller's a and b.
+        let keys: Vec<Option<String>> = arrays
+            .iter()
+            .map(|a| a.as_deref().map(|a| self.rt.array_key(a).to_string()))
+            .collect();
+        let mut saved = Vec::with_capacity(func.params.len());
+        for (i, param) in func.params.iter().enumerate() {
+            let existed = self.rt.has_var(param);
+            let value = self.rt.get_value(param);
+            // A parameter without an argument stays unset, so it can
+            // still be passed on as an array.
+            match args.get(i) {
+                Some(arg) => self.rt.set_value(param, arg.clone()),
+                None => self.rt.remove_var(param),
             }
+            let (binding, local) = match keys.get(i).cloned().flatten() {
+                Some(key) => (Some(self.rt.bind_array(param, key)), false),
+                // Hide a global array of the same name (or the caller's
+                // binding) behind storage of this call's own.
+                None if self.rt.is_bound_array(param) || self.rt.has_array(param) => {
+                    let previous = self.rt.bind_local_array(param, self.call_depth);
+                    (Some(previous), true)
+                }
+                None => (None, true),
+            };
+            saved.push(SavedParam {
+                name: param.clone(),
+                existed,
+                value,
+                binding,
+                local,
+            });
         }
+        saved
+    }
 
-        self.call_depth -= 1;
-        result
+    /// Undo `bind_params`: drop the call's local arrays and restore each
+    /// parameter's variable and array binding.
+    #[inline(never)]
+    fn restore_params(&mut self, saved: Vec<SavedParam>) {
+        for param in saved {
+            if param.local {
+                self.rt.delete_array_all(&param.name);
+            }
+            if let Some(previous) = param.binding {
+                self.rt.unbind_array(&param.name, previous);
+            }
+            if param.existed {
+                self.rt.set_value(&param.name, param.value);
+            } else {
+                self.rt.remove_var(&param.name);
+            }
+        }
     }
 }
+
+/// A parameter's variable and array binding from before a call.
+struct SavedParam {
+    name: String,
+    existed: bool,
+    value: Value,
+    /// The binding `bind_array` replaced, if the call bound the parameter.
+    binding: Option<Option<String>>,
+    /// The parameter's array belongs to the call and is dropped after it.
+    local: bool,
+}
@@ -0,0 +1,151 @@
+//! `topk()` / `topk_result()`: heavy-hitter tracking with the SpaceSaving
+//! algorithm (Metwally et al.), so "top 20 keys by bytes" needs memory
+//! proportional to k rather than to the number of distinct keys.
+
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+
+/// Counters kept for a given k: O(k log k).
+pub(crate) fn capacity_for(k: usize) -> usize {
+    let k = k.max(1);
+    k * (3 + (k as f64).log2().ceil() as usize)
+}
+
+/// f64 with a total order, for the min-ordered counter index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Count(f64);
+
+impl Eq for Count {}
+
+impl PartialOrd for Count {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Count {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Weighted SpaceSaving summary. While the distinct keys fit in `capacity`
+/// every count is exact; after that, a new key replaces the smallest
+/// counter and inherits its count as `error`, so counts overestimate by at
+/// most `error <= total / capacity` and every key whose true total exceeds
+/// that bound is still tracked.
+#[derive(Debug)]
+pub(crate) struct SpaceSaving {
+    capacity: usize,
+    /// key → (count, error); the key is shared with `by_count`, so a
+    /// record allocates it at most once, when it is new.
+    counters: HashMap<Rc<str>, (f64, f64)>,
+    by_count: BTreeSet<(Count, Rc<str>)>,
+}
+
+impl SpaceSaving {
+    pub(crate) fn new(capacity: usize) -> Self {
+        SpaceSaving {
+            capacity: capacity.max(1),
+            counters: HashMap::new(),
+            by_count: BTreeSet::new(),
+        }
+    }
+
+    /// Raise the counter budget (never shrinks; existing counts stay valid).
+    pub(crate) fn grow(&mut self, capacity: usize) {
+        self.capacity = self.capacity.max(capacity);
+    }
+
+    /// Add `weight` to `key`, evicting the smallest counter if the key is
+    /// new and the summary is full. Returns the key's estimated count.
+    pub(crate) fn add(&mut self, key: &str, weight: f64) -> f64 {
+        if let Some((name, &(count, error))) = self.counters.get_key_value(key) {
+            let name = Rc::clone(name);
+            self.by_count.remove(&(Count(count), Rc::clone(&name)));
+            let count = count + weight;
+            self.by_count.insert((Count(count), Rc::clone(&name)));
+            self.counters.insert(name, (count, error));
+            return count;
+        }
+        let mut floor = 0.0;
+        if self.counters.len() >= self.capacity
+            && let Some((Count(min), min_key)) = self.by_count.pop_first()
+        {
+            self.counters.remove(&min_key);
+            floor = min;
+        }
+        let count = floor + weight;
+        let name: Rc<str> = Rc::from(key);
+        self.counters.insert(Rc::clone(&name), (count, floor));
+        self.by_count.insert((Count(count), name));
+        count
+    }
+
+    /// The `k` largest counters as (key, count, error), count descending,
+    /// ties by key.
+    pub(crate) fn top(&self, k: usize) -> Vec<(String, f64, f64)> {
+        let mut out: Vec<(String, f64, f64)> = Vec::with_capacity(k);
+        for (Count(count), key) in self.by_count.iter().rev() {
+            if out.len() >= k && out.last().is_some_and(|l| l.1 > *count) {
+                break;
+            }
+            out.push((key.to_string(), *count, self.counters[key].1));
+        }
+        // by_count iterates equal counts in descending key order.
+        out.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        out.truncate(k);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_while_keys_fit() {
+        let mut s = SpaceSaving::new(10);
+        for (k, w) in [("a", 1.0), ("b", 5.0), ("a", 2.0), ("c", 3.0)] {
+            s.add(k, w);
+        }
+        let top = s.top(2);
+        assert_eq!(top[0], ("b".to_string(), 5.0, 0.0));
+        assert_eq!(top[1], ("a".to_string(), 3.0, 0.0));
+    }
+
+    #[test]
+    fn matches_brute_force_on_skewed_stream() {
+        // Zipf-like: key i appears 2000/i times, interleaved by an LCG.
+        let mut stream: Vec<String> = Vec::new();
+        for i in 1..=2000usize {
+            for _ in 0..(2000 / i).max(1) {
+                stream.push(format!("k{i}"));
+            }
+        }
+        let mut state: u64 = 42;
+        for i in (1..stream.len()).rev() {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            stream.swap(i, (state >> 33) as usize % (i + 1));
+        }
+
+        let k = 10;
+        let mut s = SpaceSaving::new(capacity_for(k));
+        let mut exact: HashMap<&str, f64> = HashMap::new();
+        for key in &stream {
+            s.add(key, 1.0);
+            *exact.entry(key.as_str()).or_insert(0.0) += 1.0;
+        }
+        let mut brute: Vec<(&str, f64)> = exact.into_iter().collect();
+        brute.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        let top = s.top(k);
+        let bound = stream.len() as f64 / capacity_for(k) as f64;
+        for ((key, count, error), (want, n)) in top.iter().zip(&brute) {
+            assert_eq!(key, want);
+            assert!(*count >= *n && count - n <= *error && *error <= bound);
+        }
+    }
+}
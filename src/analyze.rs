@@ -1,38 +1,72 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 
 use crate::parser::*;
 
 /// Static analysis of a parsed program — flags that let the executor
 /// skip unnecessary work on the per-record hot path.
-#[derive(Debug)]
+///
+/// Part of the public API for tools built on fk: fields are only ever
+/// added (hence `non_exhaustive`), and each errs on the safe side — a
+/// flag that lets work be skipped is only clear when that is certain.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct ProgramInfo {
     /// Program accesses $1…$N (not just $0).
     pub needs_fields: bool,
     /// Program reads the NF variable.
     pub needs_nf: bool,
+    /// Program reads BYTEPOS or RECBYTES, so the main loop keeps them set.
+    pub needs_byte_offsets: bool,
+    /// Rules (patterns and actions) can write output: print or printf to
+    /// any destination, an output builtin (system, fflush, writecsv,
+    /// dump), or a call to a user function that can. When false the
+    /// program is aggregation-only: nothing is written before END.
+    pub emits_output_in_rules: bool,
     /// Highest constant field index seen (None = dynamic $expr access).
     /// Only meaningful when needs_fields is true.
     pub max_field: Option<usize>,
     /// All regex literal strings found in patterns and expressions,
     /// suitable for pre-compilation into the regex cache.
     pub regex_literals: Vec<String>,
+    /// Literal printf/sprintf format strings, parsed once by the executor.
+    pub printf_formats: Vec<String>,
     /// For each array, the RHS expression of its first direct assignment
     /// (e.g. `a[NR] = $3` stores `$3`). Used to derive plot titles.
     pub array_sources: HashMap<String, Expr>,
     /// Simple variable assignments (first `v = expr` seen per var).
     /// Used to resolve one level of indirection in array source tracking.
     pub var_sources: HashMap<String, Expr>,
+    /// Global variables the program assigns: `=`, `op=`, `++`/`--`,
+    /// `getline var`, sub/gsub targets and for-in loops. Function
+    /// parameters are left out.
+    pub assigned_vars: HashSet<String>,
 }
 
+/// Analyze `program`.
+///
+/// ```
+/// use fk::{analyze::analyze, lexer::Lexer, parser::Parser};
+///
+/// let tokens = Lexer::new("{ sum += $3 } END { print sum }").tokenize().unwrap();
+/// let program = Parser::new(tokens).parse().unwrap();
+/// let info = analyze(&program);
+/// assert!(info.needs_fields && !info.needs_nf);
+/// assert_eq!(info.max_field, Some(3));
+/// assert!(!info.emits_output_in_rules);
+/// ```
 pub fn analyze(program: &Program) -> ProgramInfo {
     let mut info = ProgramInfo {
         needs_fields: false,
         needs_nf: false,
+        needs_byte_offsets: false,
+        emits_output_in_rules: rules_emit_output(program),
         max_field: Some(0),
         regex_literals: Vec::new(),
+        printf_formats: Vec::new(),
         array_sources: HashMap::new(),
         var_sources: HashMap::new(),
+        assigned_vars: HashSet::new(),
     };
 
     if let Some(block) = &program.begin {
@@ -53,8 +87,17 @@ pub fn analyze(program: &Program) -> ProgramInfo {
     if let Some(block) = &program.endfile {
         walk_block(block, &mut info);
     }
+    if let Some(block) = &program.error {
+        walk_block(block, &mut info);
+    }
     for func in &program.functions {
+        let globals = info.assigned_vars.clone();
         walk_block(&func.body, &mut info);
+        for param in &func.params {
+            if !globals.contains(param) {
+                info.assigned_vars.remove(param);
+            }
+        }
     }
 
     if !info.needs_fields {
@@ -73,6 +116,9 @@ fn walk_block(block: &Block, info: &mut ProgramInfo) {
 fn walk_stmt(stmt: &Statement, info: &mut ProgramInfo) {
     match stmt {
         Statement::Print(exprs, redir) | Statement::Printf(exprs, redir) => {
+            if matches!(stmt, Statement::Printf(..)) {
+                note_printf_format(exprs, info);
+            }
             for e in exprs {
                 walk_expr(e, info);
             }
@@ -103,7 +149,10 @@ fn walk_stmt(stmt: &Statement, info: &mut ProgramInfo) {
             }
             walk_block(body, info);
         }
-        Statement::ForIn(_, _, _, body) => walk_block(body, info),
+        Statement::ForIn(var, _, _, body) => {
+            info.assigned_vars.insert(var.clone());
+            walk_block(body, info);
+        }
         Statement::Delete(_, e) => walk_expr(e, info),
         Statement::Exit(Some(e)) => walk_expr(e, info),
         Statement::Return(Some(e)) => walk_expr(e, info),
@@ -119,11 +168,29 @@ fn walk_stmt(stmt: &Statement, info: &mut ProgramInfo) {
     }
 }
 
+/// A `getline` target is assigned like the left side of `=`.
+fn walk_getline_target(target: &Expr, info: &mut ProgramInfo) {
+    match target {
+        Expr::Var(name) => {
+            info.assigned_vars.insert(name.clone());
+        }
+        Expr::Field(inner) if !matches!(inner.as_ref(), Expr::NumberLit(n) if *n == 0.0) => {
+            info.max_field = None;
+        }
+        _ => {}
+    }
+    walk_expr(target, info);
+}
+
 fn walk_expr(expr: &Expr, info: &mut ProgramInfo) {
     match expr {
         Expr::Field(inner) => match inner.as_ref() {
             Expr::NumberLit(n) => {
                 let idx = *n as isize;
+                if idx < 0 {
+                    // $-1 counts back from NF.
+                    info.max_field = None;
+                }
                 if idx != 0 {
                     info.needs_fields = true;
                     if idx > 0
@@ -146,6 +213,9 @@ fn walk_expr(expr: &Expr, info: &mut ProgramInfo) {
             if name == "NF" {
                 info.needs_nf = true;
             }
+            if name == "BYTEPOS" || name == "RECBYTES" {
+                info.needs_byte_offsets = true;
+            }
         }
         Expr::Getline(None, source) => {
             info.needs_fields = true;
@@ -154,15 +224,24 @@ fn walk_expr(expr: &Expr, info: &mut ProgramInfo) {
                 walk_expr(e, info);
             }
         }
-        Expr::Getline(Some(_), source) => {
+        Expr::Getline(Some(var), source) => {
+            walk_getline_target(var, info);
             if let Some(e) = source {
                 walk_expr(e, info);
             }
         }
-        Expr::GetlinePipe(cmd, _) => walk_expr(cmd, info),
+        Expr::GetlinePipe(cmd, var) => {
+            if let Some(var) = var {
+                walk_getline_target(var, info);
+            }
+            walk_expr(cmd, info);
+        }
         Expr::ArrayRef(_, key) => walk_expr(key, info),
         Expr::ArrayIn(key, _) => walk_expr(key, info),
         Expr::Assign(target, val) | Expr::CompoundAssign(target, _, val) => {
+            if let Expr::Var(name) = target.as_ref() {
+                info.assigned_vars.insert(name.clone());
+            }
             match target.as_ref() {
                 Expr::ArrayRef(name, _) => {
                     info.array_sources
@@ -192,12 +271,13 @@ fn walk_expr(expr: &Expr, info: &mut ProgramInfo) {
             walk_expr(l, info);
             walk_expr(r, info);
         }
-        Expr::LogicalNot(e)
-        | Expr::UnaryMinus(e)
-        | Expr::Increment(e, _)
-        | Expr::Decrement(e, _)
-        | Expr::TryVal(e)
-        | Expr::NullFence(e) => {
+        Expr::Increment(e, _) | Expr::Decrement(e, _) => {
+            if let Expr::Var(name) = e.as_ref() {
+                info.assigned_vars.insert(name.clone());
+            }
+            walk_expr(e, info);
+        }
+        Expr::LogicalNot(e) | Expr::UnaryMinus(e) | Expr::TryVal(e) | Expr::NullFence(e) => {
             walk_expr(e, info);
         }
         Expr::Ternary(c, t, f) => {
@@ -210,6 +290,9 @@ fn walk_expr(expr: &Expr, info: &mut ProgramInfo) {
             walk_expr(r, info);
         }
         Expr::Sprintf(args) | Expr::FuncCall(_, args) => {
+            if matches!(expr, Expr::Sprintf(_)) {
+                note_printf_format(args, info);
+            }
             if let Expr::FuncCall(name, fargs) = expr {
                 if name == "collect"
                     && fargs.len() >= 2
@@ -219,8 +302,13 @@ fn walk_expr(expr: &Expr, info: &mut ProgramInfo) {
                         .entry(arr.clone())
                         .or_insert_with(|| fargs[1].clone());
                 }
-                // flip() implicitly operates on fields
-                if matches!(name.as_str(), "flip") {
+                if matches!(name.as_str(), "sub" | "gsub")
+                    && let Some(Expr::Var(var)) = fargs.get(2)
+                {
+                    info.assigned_vars.insert(var.clone());
+                }
+                // flip(), fields() and has() implicitly operate on fields
+                if matches!(name.as_str(), "flip" | "fields" | "has") {
                     info.needs_fields = true;
                     info.max_field = None;
                 }
@@ -233,6 +321,15 @@ fn walk_expr(expr: &Expr, info: &mut ProgramInfo) {
     }
 }
 
+/// Record a printf/sprintf format given as a string literal.
+fn note_printf_format(args: &[Expr], info: &mut ProgramInfo) {
+    if let Some(Expr::StringLit(fmt)) = args.first()
+        && !info.printf_formats.contains(fmt)
+    {
+        info.printf_formats.push(fmt.clone());
+    }
+}
+
 fn walk_pattern(pattern: &Pattern, info: &mut ProgramInfo) {
     match pattern {
         Pattern::Regex(s) => {
@@ -257,6 +354,123 @@ fn walk_redirect(redir: &Redirect, info: &mut ProgramInfo) {
     }
 }
 
+// ── Output detection ────────────────────────────────────────────────
+
+/// Builtins that write somewhere (or flush stdout) as a side effect.
+const OUTPUT_BUILTINS: &[&str] = &[
+    "system",
+    "sys",
+    "system_bg",
+    "wait",
+    "waitall",
+    "fflush",
+    "flushmode",
+    "writecsv",
+    "dump",
+    "header",
+];
+
+/// Whether any rule's pattern or action can write output, directly or
+/// through the user functions it calls.
+fn rules_emit_output(program: &Program) -> bool {
+    // Functions that can write, grown until calls add no more.
+    let mut emitting: HashSet<&str> = HashSet::new();
+    loop {
+        let before = emitting.len();
+        for func in &program.functions {
+            if !emitting.contains(func.name.as_str()) && block_emits(&func.body, &emitting) {
+                emitting.insert(&func.name);
+            }
+        }
+        if emitting.len() == before {
+            break;
+        }
+    }
+    program.rules.iter().any(|rule| {
+        rule.pattern
+            .as_ref()
+            .is_some_and(|p| pattern_emits(p, &emitting))
+            || block_emits(&rule.action, &emitting)
+    })
+}
+
+fn pattern_emits(pattern: &Pattern, emitting: &HashSet<&str>) -> bool {
+    match pattern {
+        Pattern::Regex(_) => false,
+        Pattern::Expression(e) | Pattern::Last(e) => expr_emits(e, emitting),
+        Pattern::Range(a, b) => pattern_emits(a, emitting) || pattern_emits(b, emitting),
+    }
+}
+
+fn block_emits(block: &Block, emitting: &HashSet<&str>) -> bool {
+    block.iter().any(|s| stmt_emits(s, emitting))
+}
+
+fn stmt_emits(stmt: &Statement, emitting: &HashSet<&str>) -> bool {
+    let expr = |e: &Expr| expr_emits(e, emitting);
+    match stmt {
+        Statement::Print(..) | Statement::Printf(..) => true,
+        Statement::If(cond, then_b, else_b) => {
+            expr(cond)
+                || block_emits(then_b, emitting)
+                || else_b.as_ref().is_some_and(|b| block_emits(b, emitting))
+        }
+        Statement::While(cond, body) | Statement::DoWhile(body, cond) => {
+            expr(cond) || block_emits(body, emitting)
+        }
+        Statement::For(init, cond, update, body) => {
+            init.as_ref().is_some_and(|s| stmt_emits(s, emitting))
+                || cond.as_ref().is_some_and(expr)
+                || update.as_ref().is_some_and(|s| stmt_emits(s, emitting))
+                || block_emits(body, emitting)
+        }
+        Statement::ForIn(_, _, _, body) | Statement::Block(body) => block_emits(body, emitting),
+        Statement::Delete(_, e) | Statement::Expression(e) => expr(e),
+        Statement::Exit(e) | Statement::Return(e) => e.as_ref().is_some_and(expr),
+        Statement::DeleteAll(_)
+        | Statement::Next
+        | Statement::Nextfile
+        | Statement::Break
+        | Statement::Continue => false,
+    }
+}
+
+fn expr_emits(expr: &Expr, emitting: &HashSet<&str>) -> bool {
+    let sub = |e: &Expr| expr_emits(e, emitting);
+    match expr {
+        Expr::NumberLit(_) | Expr::StringLit(_) | Expr::Var(_) => false,
+        Expr::Field(e)
+        | Expr::ArrayRef(_, e)
+        | Expr::ArrayIn(e, _)
+        | Expr::LogicalNot(e)
+        | Expr::Increment(e, _)
+        | Expr::Decrement(e, _)
+        | Expr::UnaryMinus(e)
+        | Expr::TryVal(e)
+        | Expr::NullFence(e) => sub(e),
+        Expr::BinOp(l, _, r)
+        | Expr::LogicalAnd(l, r)
+        | Expr::LogicalOr(l, r)
+        | Expr::Match(l, r)
+        | Expr::NotMatch(l, r)
+        | Expr::Assign(l, r)
+        | Expr::CompoundAssign(l, _, r)
+        | Expr::Concat(l, r)
+        | Expr::NullCoalesce(l, r) => sub(l) || sub(r),
+        Expr::Ternary(c, t, f) => sub(c) || sub(t) || sub(f),
+        Expr::Sprintf(args) => args.iter().any(sub),
+        Expr::FuncCall(name, args) => {
+            OUTPUT_BUILTINS.contains(&name.as_str())
+                || emitting.contains(name.as_str())
+                || args.iter().any(sub)
+        }
+        Expr::Getline(var, source) => {
+            var.as_deref().is_some_and(sub) || source.as_deref().is_some_and(sub)
+        }
+        Expr::GetlinePipe(cmd, var) => sub(cmd) || var.as_deref().is_some_and(sub),
+    }
+}
+
 // ── Expression formatter & smart title builder ──────────────────────
 
 /// Strip `+ 0` / `0 +` numeric coercion wrappers.
@@ -608,7 +822,8 @@ fn fmt_expr(expr: &Expr, buf: &mut String, depth: usize) {
         Expr::Getline(var, source) => {
             buf.push_str("getline");
             if let Some(v) = var {
-                let _ = write!(buf, " {v}");
+                buf.push(' ');
+                fmt_expr(v, buf, depth + 1);
             }
             if let Some(src) = source {
                 buf.push_str(" < ");
@@ -619,7 +834,8 @@ fn fmt_expr(expr: &Expr, buf: &mut String, depth: usize) {
             fmt_expr(cmd, buf, depth + 1);
             buf.push_str(" | getline");
             if let Some(v) = var {
-                let _ = write!(buf, " {v}");
+                buf.push(' ');
+                fmt_expr(v, buf, depth + 1);
             }
         }
     }
@@ -671,6 +887,33 @@ mod tests {
         assert_eq!(info.max_field, Some(5));
     }
 
+    #[test]
+    fn output_in_rules() {
+        let emits = |src: &str| analyze_program(src).emits_output_in_rules;
+        // Aggregation-only: output (or a printing function) only in BEGIN/END.
+        assert!(!emits("{ s += $3; c[$1]++ } END { print s }"));
+        assert!(!emits(
+            "function show(x) { print x }\nfunction sq(x) { return x * x }\n\
+             BEGIN { show(\"start\") } $2 > 1 { t += sq($2) } END { show(t) }"
+        ));
+        assert!(!emits("/x/ { n++; line = sprintf(\"%d\", NR) }"));
+        // Any print or printf, to any destination.
+        assert!(emits("{ print }"));
+        assert!(emits("NR > 1 { printf \"%s\\n\", $1 > \"out.txt\" }"));
+        assert!(emits("{ if ($1) { while (x) { print | \"sort\" } } }"));
+        // Output builtins, including in a pattern.
+        assert!(emits("{ system(\"true\") }"));
+        assert!(emits("fflush() { }"));
+        // User functions that print, directly or through another function.
+        assert!(emits(
+            "function warn(m) { print m > \"/dev/stderr\" }\n{ warn($1) }"
+        ));
+        assert!(emits(
+            "function a(x) { return b(x) }\nfunction b(x) { printf \"%s\", x; return 1 }\n\
+             $1 == a($2) { n++ }"
+        ));
+    }
+
     #[test]
     fn regex_pattern_collected() {
         let info = analyze_program("/^start/ { print }");
@@ -765,4 +1008,15 @@ mod tests {
         let desc = build_array_description(expr, "data.csv", &info.var_sources, 60);
         assert_eq!(desc, "column 1 + column 2 — data.csv");
     }
+
+    #[test]
+    fn assigned_vars_skip_function_params() {
+        let info = analyze_program(
+            "function f(n, tmp) { tmp = n * 2; total += tmp; return tmp }\n\
+             { count++; f($1); getline line < \"x\"; sub(/a/, \"b\", s); for (k in arr) m = k }",
+        );
+        let mut names: Vec<_> = info.assigned_vars.iter().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, ["count", "k", "line", "m", "s", "total"]);
+    }
 }
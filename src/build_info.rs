@@ -0,0 +1,65 @@
+//! What this binary was built with: version, commit and cargo features.
+//!
+//! Shown by `fk --version` and queried from programs with
+//! `feature("name")`, so scripts can branch on a capability instead of
+//! failing with "support not compiled in".
+
+/// Crate version from Cargo.toml.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Short git commit recorded by build.rs, when built from a checkout.
+pub const COMMIT: Option<&str> = option_env!("FK_GIT_COMMIT");
+
+/// Optional capabilities and whether they are compiled in. The Parquet
+/// compression codecs come with the `parquet` feature.
+pub const FEATURES: &[(&str, bool)] = &[
+    ("parquet", cfg!(feature = "parquet")),
+    ("snappy", cfg!(feature = "parquet")),
+    ("zstd", cfg!(feature = "parquet")),
+    ("lz4", cfg!(feature = "parquet")),
+    ("parallel", cfg!(feature = "parallel")),
+];
+
+/// True when `name` is a compiled-in feature; unknown names are false.
+pub fn has_feature(name: &str) -> bool {
+    FEATURES.iter().any(|&(f, on)| on && f == name)
+}
+
+/// Names of the compiled-in features, in [`FEATURES`] order.
+pub fn enabled_features() -> Vec<&'static str> {
+    FEATURES.iter().filter(|f| f.1).map(|f| f.0).collect()
+}
+
+/// The `--version` text: `fk VERSION (COMMIT)` and a features line.
+pub fn version_text() -> String {
+    let mut out = format!("fk {VERSION}");
+    if let Some(commit) = COMMIT {
+        out.push_str(&format!(" ({commit})"));
+    }
+    let features = enabled_features();
+    let features = if features.is_empty() {
+        "none".to_string()
+    } else {
+        features.join(" ")
+    };
+    out.push_str(&format!("\nfeatures: {features}"));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn features_follow_cfg_flags() {
+        assert_eq!(has_feature("parquet"), cfg!(feature = "parquet"));
+        assert_eq!(has_feature("zstd"), cfg!(feature = "parquet"));
+        assert!(!has_feature("nosuch"));
+        let text = version_text();
+        assert!(text.starts_with(&format!("fk {VERSION}")));
+        assert_eq!(
+            text.contains("features: parquet"),
+            cfg!(feature = "parquet")
+        );
+    }
+}
@@ -106,6 +106,75 @@ pub fn extract(json_str: &str, path: &str) -> Vec<(String, String)> {
     }
 }
 
+/// `jpretty(json, indent)` / `jmin(json)` (indent 0) → the same JSON laid
+/// out again: one member per line indented by `indent` spaces per level,
+/// or with no insignificant whitespace at all. Keys keep their order and
+/// strings and numbers are copied as written, so escapes and big integers
+/// come through untouched. `None` when the text does not parse.
+pub fn reformat(json_str: &str, indent: usize) -> Option<String> {
+    let text = json_str.trim();
+    let mut chars = text.chars().peekable();
+    parse_value(&mut chars)?;
+    skip_ws(&mut chars);
+    if chars.peek().is_some() {
+        return None;
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut depth = 0;
+    let newline = |out: &mut String, depth: usize| {
+        if indent > 0 {
+            out.push('\n');
+            out.extend(std::iter::repeat_n(' ', depth * indent));
+        }
+    };
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => {
+                out.push('"');
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    if c == '\\' {
+                        out.push(chars.next()?);
+                    } else if c == '"' {
+                        break;
+                    }
+                }
+            }
+            '{' | '[' => {
+                out.push(ch);
+                skip_ws(&mut chars);
+                if let Some(&close @ ('}' | ']')) = chars.peek() {
+                    out.push(close);
+                    chars.next();
+                } else {
+                    depth += 1;
+                    newline(&mut out, depth);
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                newline(&mut out, depth);
+                out.push(ch);
+            }
+            ',' => {
+                out.push(',');
+                newline(&mut out, depth);
+            }
+            ':' => {
+                out.push(':');
+                if indent > 0 {
+                    out.push(' ');
+                }
+            }
+            c if c.is_ascii_whitespace() => {}
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
 // ── path parsing ────────────────────────────────────────────────
 
 #[derive(Debug)]
@@ -548,6 +617,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn reformat_round_trips() {
+        let json = r#"{"b":[1,{"x":null},[]],"a":{},"s":"a, b: {c}"}"#;
+        let pretty = reformat(json, 2).unwrap();
+        assert_eq!(
+            pretty,
+            "{\n  \"b\": [\n    1,\n    {\n      \"x\": null\n    },\n    []\n  ],\n  \"a\": {},\n  \"s\": \"a, b: {c}\"\n}"
+        );
+        assert_eq!(reformat(&pretty, 0).unwrap(), json);
+        assert_eq!(reformat(&reformat(json, 4).unwrap(), 0), reformat(json, 0));
+        assert_eq!(reformat(" [ 1 , 2 ] ", 0).unwrap(), "[1,2]");
+        assert_eq!(reformat("42", 2).unwrap(), "42");
+    }
+
+    #[test]
+    fn reformat_keeps_escapes_and_big_integers() {
+        let json = r#"{ "id": 12345678901234567890, "t": "caf\u00e9 \ud83d\ude00 \"q\" \\", "f": 1.50e3 }"#;
+        assert_eq!(
+            reformat(json, 0).unwrap(),
+            r#"{"id":12345678901234567890,"t":"caf\u00e9 \ud83d\ude00 \"q\" \\","f":1.50e3}"#
+        );
+        assert_eq!(reformat(&reformat(json, 2).unwrap(), 0), reformat(json, 0));
+    }
+
+    #[test]
+    fn reformat_rejects_bad_json() {
+        assert_eq!(reformat(r#"{"a":1"#, 2), None);
+        assert_eq!(reformat(r#"{"a":1} x"#, 2), None);
+        assert_eq!(reformat("nope", 0), None);
+        assert_eq!(reformat("", 0), None);
+    }
+
     #[test]
     fn iterate_object_values() {
         let json = r#"{"scores":{"alice":95,"bob":87}}"#;
@@ -2,12 +2,14 @@ use std::cell::Cell;
 
 use super::{format_number, to_number};
 
+// Like gawk, rand() starts from seed 0, so a program that never calls
+// srand() draws the same numbers every run.
 thread_local! {
-    static RNG_STATE: Cell<u64> = const { Cell::new(0) };
-    static RNG_SEEDED: Cell<bool> = const { Cell::new(false) };
+    static RNG_STATE: Cell<u64> = const { Cell::new(splitmix64(0)) };
+    static RNG_SEED: Cell<f64> = const { Cell::new(0.0) };
 }
 
-fn splitmix64(x: u64) -> u64 {
+const fn splitmix64(x: u64) -> u64 {
     let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
     z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
     z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
@@ -16,16 +18,6 @@ fn splitmix64(x: u64) -> u64 {
 
 pub fn rng_next() -> f64 {
     RNG_STATE.with(|state| {
-        RNG_SEEDED.with(|seeded| {
-            if !seeded.get() {
-                let seed = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_nanos() as u64;
-                state.set(splitmix64(seed));
-                seeded.set(true);
-            }
-        });
         let mut s = state.get();
         s ^= s << 13;
         s ^= s >> 7;
@@ -56,19 +48,19 @@ pub fn call(name: &str, args: &[String]) -> String {
         "min" => format_number(n().min(n2())),
         "max" => format_number(n().max(n2())),
         "rand" => format_number(rng_next()),
+        // srand() seeds from the time of day in seconds, srand(x) from x;
+        // either way the previous seed is returned.
         "srand" => {
-            let prev = RNG_STATE.with(|s| s.get() as f64);
             let seed = if args.is_empty() {
                 std::time::SystemTime::now()
                     .duration_since(std::time::UNIX_EPOCH)
                     .unwrap_or_default()
-                    .as_nanos() as u64
+                    .as_secs() as f64
             } else {
-                n() as u64
+                n()
             };
-            RNG_STATE.with(|s| s.set(splitmix64(seed)));
-            RNG_SEEDED.with(|s| s.set(true));
-            format_number(prev)
+            RNG_STATE.with(|s| s.set(splitmix64(seed as u64)));
+            format_number(RNG_SEED.with(|s| s.replace(seed)))
         }
         _ => String::new(),
     }
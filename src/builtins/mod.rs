@@ -1,11 +1,13 @@
 pub mod emoji;
 pub mod json;
 pub mod math;
+pub mod numfmt;
 pub mod printf;
 pub mod string;
 pub mod time;
+pub mod width;
 
-pub use self::printf::format_printf;
+pub use self::printf::{NumSeps, format_printf, format_printf_with};
 pub use self::string::string_replace;
 
 /// Coerce a string to a number (awk semantics: leading numeric prefix is parsed,
@@ -49,44 +51,282 @@ pub fn format_number_fmt(n: f64, fmt: &str) -> String {
     if fmt == "%.6g" {
         return format_number(n);
     }
-    printf::format_printf(fmt, &[format_number(n)])
+    printf::format_printf(fmt, &[format_number(n).into()])
 }
 
 /// Format a number for output (integer form when exact, otherwise up to 6 decimals).
 pub fn format_number(n: f64) -> String {
-    if n.is_nan() {
-        return "nan".to_string();
-    }
-    if n.is_infinite() {
-        return if n > 0.0 {
-            "inf".to_string()
-        } else {
-            "-inf".to_string()
-        };
-    }
-    if n == (n as i64) as f64 {
-        (n as i64).to_string()
-    } else {
-        let s = format!("{:.6}", n);
-        let s = s.trim_end_matches('0');
-        let s = s.trim_end_matches('.');
-        s.to_string()
+    let mut out = String::new();
+    write_number(n, &mut out);
+    out
+}
+
+/// Append [`format_number`]'s text for `n` to `out` without allocating.
+pub fn write_number(n: f64, out: &mut String) {
+    let mut buf = numfmt::NumBuf::new();
+    match numfmt::general(n, &mut buf) {
+        Some(s) => out.push_str(s),
+        None => {
+            // |n| of 2^64 and up: not an i64 and too wide for the fast path.
+            let s = format!("{:.6}", n);
+            out.push_str(s.trim_end_matches('0').trim_end_matches('.'));
+        }
     }
 }
 
+/// Every builtin function name, aliases included, sorted. User functions may
+/// not reuse them (in any namespace).
+pub const BUILTIN_FUNCS: &[&str] = &[
+    "abs",
+    "acc",
+    "accumulate",
+    "aempty",
+    "amerge",
+    "and",
+    "arrays",
+    "asort",
+    "asorti",
+    "atan2",
+    "avg",
+    "bot",
+    "bottom",
+    "ceil",
+    "chr",
+    "clear",
+    "cliargs",
+    "clk",
+    "clock",
+    "close",
+    "clr",
+    "collect",
+    "compl",
+    "copy",
+    "cos",
+    "dice",
+    "diff",
+    "dump",
+    "elapsed",
+    "emoji",
+    "endswith",
+    "escape",
+    "ew",
+    "exp",
+    "feature",
+    "fflush",
+    "fields",
+    "filter",
+    "flip",
+    "floor",
+    "fltr",
+    "flushmode",
+    "gensub",
+    "gsub",
+    "has",
+    "hdrlen",
+    "header",
+    "heatmap",
+    "hex",
+    "hist",
+    "histogram",
+    "hll",
+    "hll_count",
+    "hll_dump",
+    "hll_load",
+    "hll_merge",
+    "idx",
+    "index",
+    "int",
+    "inter",
+    "inv",
+    "invert",
+    "iqm",
+    "jmin",
+    "join",
+    "joinuniq",
+    "jp",
+    "jpath",
+    "jpretty",
+    "keys",
+    "l",
+    "lap",
+    "len",
+    "length",
+    "levenshtein",
+    "log",
+    "log10",
+    "log2",
+    "lower",
+    "lpad",
+    "lshift",
+    "ltrim",
+    "map",
+    "match",
+    "matchall",
+    "max",
+    "mean",
+    "med",
+    "median",
+    "min",
+    "mktime",
+    "moji",
+    "norm",
+    "normalize",
+    "now",
+    "or",
+    "ord",
+    "outfile",
+    "p",
+    "parsedate",
+    "pbox",
+    "pct",
+    "pdate",
+    "percentile",
+    "pivot",
+    "plot",
+    "plotbox",
+    "q",
+    "quantile",
+    "rand",
+    "rep",
+    "repeat",
+    "reservoir",
+    "rev",
+    "reverse",
+    "round",
+    "rpad",
+    "rshift",
+    "rtrim",
+    "runtot",
+    "runtotal",
+    "rx",
+    "samp",
+    "sample",
+    "sd",
+    "seq",
+    "sequence",
+    "shuf",
+    "shuffle",
+    "sin",
+    "slice",
+    "slurp",
+    "sortkeys",
+    "soundex",
+    "split",
+    "sprintf",
+    "sqrt",
+    "srand",
+    "start",
+    "startswith",
+    "stddev",
+    "strftime",
+    "sub",
+    "substr",
+    "sum",
+    "sw",
+    "sys",
+    "system",
+    "system_bg",
+    "systime",
+    "tic",
+    "tidy",
+    "toc",
+    "tolower",
+    "top",
+    "topk",
+    "topk_result",
+    "toupper",
+    "trim",
+    "typeof",
+    "unescape",
+    "union",
+    "uniq",
+    "uniqc",
+    "uniqkeys",
+    "uniqsplit",
+    "unique",
+    "upper",
+    "vals",
+    "values",
+    "var",
+    "variance",
+    "vars",
+    "vplot",
+    "wait",
+    "waitall",
+    "win",
+    "window",
+    "writecsv",
+    "xor",
+];
+
+/// True when `name` is a builtin function (see [`BUILTIN_FUNCS`]).
+pub fn is_builtin_func(name: &str) -> bool {
+    BUILTIN_FUNCS.binary_search(&name).is_ok()
+}
+
+/// True when the pure builtin `name` computes a number, which
+/// [`call_builtin`] returns already formatted: its result is then a strnum,
+/// so `if (index(s, t))` is false for "0" as in awk.
+pub fn returns_number(name: &str) -> bool {
+    matches!(
+        name,
+        "length"
+            | "len"
+            | "l"
+            | "index"
+            | "idx"
+            | "startswith"
+            | "sw"
+            | "endswith"
+            | "ew"
+            | "ord"
+            | "levenshtein"
+            | "dice"
+            | "int"
+            | "sin"
+            | "cos"
+            | "sqrt"
+            | "log"
+            | "exp"
+            | "atan2"
+            | "abs"
+            | "ceil"
+            | "floor"
+            | "round"
+            | "log2"
+            | "log10"
+            | "min"
+            | "max"
+            | "rand"
+            | "srand"
+            | "systime"
+            | "now"
+            | "mktime"
+            | "parsedate"
+            | "pdate"
+            | "feature"
+    )
+}
+
 /// Dispatch pure built-in function calls (those that don't need runtime access).
 pub fn call_builtin(name: &str, args: &[String]) -> String {
     match name {
         "length" | "len" | "l" | "substr" | "index" | "idx" | "tolower" | "lower" | "toupper"
         | "upper" | "trim" | "ltrim" | "rtrim" | "startswith" | "sw" | "endswith" | "ew"
-        | "repeat" | "rep" | "rev" | "reverse" | "chr" | "ord" | "hex" | "lpad"
-        | "rpad" | "emoji" | "moji" => string::call(name, args),
+        | "repeat" | "rep" | "rev" | "reverse" | "chr" | "ord" | "hex" | "lpad" | "rpad"
+        | "levenshtein" | "dice" | "soundex" | "emoji" | "moji" | "escape" | "unescape" | "rx" => {
+            string::call(name, args)
+        }
         "int" | "sin" | "cos" | "sqrt" | "log" | "exp" | "atan2" | "abs" | "ceil" | "floor"
         | "round" | "log2" | "log10" | "min" | "max" | "rand" | "srand" => math::call(name, args),
         "systime" | "now" | "strftime" | "mktime" | "parsedate" | "pdate" => {
             time::call(name, args)
         }
         "jpath" | "jp" => json::call(args),
+        "feature" => {
+            let name = args.first().map(|s| s.as_str()).unwrap_or("");
+            u8::from(crate::build_info::has_feature(name)).to_string()
+        }
         _ => {
             eprintln!("fk: unknown function: {}", name);
             String::new()
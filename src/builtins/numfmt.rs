@@ -0,0 +1,274 @@
+//! Allocation-free number formatting for the hot paths: awk's default
+//! number output ([`super::format_number`]) and printf `%d`, `%f`, `%g`.
+//!
+//! Digits are produced from the exact binary value (mantissa × 2^exp in
+//! 128-bit integer arithmetic) with round-half-even, so the text is
+//! byte-identical to `format!("{:.N}", n)`. NaN, infinities, magnitudes of
+//! 2^64 and above, and precisions over [`MAX_PRECISION`] are left to
+//! `format!` (the functions return `None`).
+
+/// Largest precision the fast path handles (10^18 fits a u64).
+pub const MAX_PRECISION: usize = 18;
+
+/// Sign, 20 integer digits, '.', 18 decimals.
+const MAX_LEN: usize = 40;
+
+/// Stack buffer a formatted number is written into.
+pub struct NumBuf {
+    bytes: [u8; MAX_LEN],
+    len: usize,
+}
+
+impl Default for NumBuf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NumBuf {
+    pub fn new() -> Self {
+        NumBuf {
+            bytes: [0; MAX_LEN],
+            len: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+
+    fn push(&mut self, b: u8) {
+        self.bytes[self.len] = b;
+        self.len += 1;
+    }
+
+    fn push_u64(&mut self, v: u64) {
+        let mut digits = [b'0'; 20];
+        let start = write_digits(v, &mut digits);
+        self.push_slice(&digits[start..]);
+    }
+
+    /// `v` as exactly `width` digits, zero-padded on the left.
+    fn push_padded(&mut self, v: u64, width: usize) {
+        let mut digits = [b'0'; 20];
+        write_digits(v, &mut digits);
+        self.push_slice(&digits[20 - width..]);
+    }
+
+    fn push_slice(&mut self, s: &[u8]) {
+        self.bytes[self.len..self.len + s.len()].copy_from_slice(s);
+        self.len += s.len();
+    }
+}
+
+/// "00" through "99", for writing two digits per division.
+const PAIRS: &[u8; 200] = b"\
+0001020304050607080910111213141516171819\
+2021222324252627282930313233343536373839\
+4041424344454647484950515253545556575859\
+6061626364656667686970717273747576777879\
+8081828384858687888990919293949596979899";
+
+/// Write `v` right-aligned into `out`, returning where its digits start.
+/// Positions before that are left untouched.
+fn write_digits(mut v: u64, out: &mut [u8; 20]) -> usize {
+    let mut i = out.len();
+    while v >= 100 {
+        let pair = (v % 100) as usize * 2;
+        v /= 100;
+        i -= 2;
+        out[i..i + 2].copy_from_slice(&PAIRS[pair..pair + 2]);
+    }
+    if v >= 10 {
+        i -= 2;
+        out[i..i + 2].copy_from_slice(&PAIRS[v as usize * 2..v as usize * 2 + 2]);
+    } else {
+        i -= 1;
+        out[i] = b'0' + v as u8;
+    }
+    i
+}
+
+/// `n` rounded to `prec` decimals, exactly as `format!("{:.prec$}", n)`.
+pub fn fixed(n: f64, prec: usize, buf: &mut NumBuf) -> Option<&str> {
+    if prec > MAX_PRECISION || !n.is_finite() {
+        return None;
+    }
+    let bits = n.to_bits();
+    let exp_bits = ((bits >> 52) & 0x7ff) as i32;
+    let frac = bits & ((1 << 52) - 1);
+    let (m, e) = if exp_bits == 0 {
+        (frac, -1074)
+    } else {
+        (frac | (1 << 52), exp_bits - 1075)
+    };
+    if e > 11 {
+        return None;
+    }
+    buf.len = 0;
+    if n.is_sign_negative() {
+        buf.push(b'-');
+    }
+    if e >= 0 {
+        buf.push_u64(m << e);
+        if prec > 0 {
+            buf.push(b'.');
+            buf.push_padded(0, prec);
+        }
+        return Some(buf.as_str());
+    }
+    let scale = 10u64.pow(prec as u32);
+    let scaled = m as u128 * scale as u128;
+    let shift = e.unsigned_abs();
+    // scaled < 2^113, so anything shifted by 128 or more rounds to zero.
+    let q = if shift >= 128 {
+        0
+    } else {
+        let q = scaled >> shift;
+        let rem = scaled - (q << shift);
+        let half = 1u128 << (shift - 1);
+        if rem > half || (rem == half && q & 1 == 1) {
+            q + 1
+        } else {
+            q
+        }
+    };
+    // 128-bit division is a library call; most values fit in 64 bits.
+    let (int, dec) = match u64::try_from(q) {
+        Ok(q) => (q / scale, q % scale),
+        Err(_) => ((q / scale as u128) as u64, (q % scale as u128) as u64),
+    };
+    buf.push_u64(int);
+    if prec > 0 {
+        buf.push(b'.');
+        buf.push_padded(dec, prec);
+    }
+    Some(buf.as_str())
+}
+
+/// awk's default number text: integral values in i64 range as integers,
+/// anything else with up to six decimals, trailing zeros dropped.
+pub fn general(n: f64, buf: &mut NumBuf) -> Option<&str> {
+    if n.is_nan() {
+        return Some("nan");
+    }
+    if n.is_infinite() {
+        return Some(if n > 0.0 { "inf" } else { "-inf" });
+    }
+    let i = n as i64;
+    if n == i as f64 {
+        return Some(integer(i, buf));
+    }
+    fixed(n, 6, buf)?;
+    while buf.bytes[buf.len - 1] == b'0' {
+        buf.len -= 1;
+    }
+    if buf.bytes[buf.len - 1] == b'.' {
+        buf.len -= 1;
+    }
+    Some(buf.as_str())
+}
+
+/// Decimal text of `i`.
+pub fn integer(i: i64, buf: &mut NumBuf) -> &str {
+    buf.len = 0;
+    if i < 0 {
+        buf.push(b'-');
+    }
+    buf.push_u64(i.unsigned_abs());
+    buf.as_str()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Awkward values: ties at several precisions, tiny, subnormal,
+    /// negative zero and the edges of the fast path.
+    const SAMPLES: &[f64] = &[
+        0.0,
+        -0.0,
+        0.5,
+        1.5,
+        2.5,
+        -2.5,
+        0.25,
+        0.125,
+        0.375,
+        0.0078125,
+        1e-7,
+        5e-7,
+        -1e-9,
+        0.1,
+        0.2,
+        0.3,
+        1.0 / 3.0,
+        2.0 / 3.0,
+        123.456789,
+        999999.9999995,
+        0.9999995,
+        1234567.891011,
+        4503599627370495.5,
+        9007199254740993.0,
+        18446744073709549568.0,
+        5e-324,
+        2.2250738585072014e-308,
+        -1e300,
+        1e20,
+        f64::MAX,
+    ];
+
+    fn lcg(state: &mut u64) -> u64 {
+        *state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        *state
+    }
+
+    fn values() -> Vec<f64> {
+        let mut v = SAMPLES.to_vec();
+        let mut s = 42;
+        for _ in 0..20_000 {
+            // Random bit patterns plus values near "round" decimals.
+            let bits = f64::from_bits(lcg(&mut s));
+            let scale = 10f64.powi((lcg(&mut s) % 24) as i32 - 12);
+            let near = (lcg(&mut s) % 2_000_000) as f64 / 8.0 * scale;
+            v.extend([bits, near, -near]);
+        }
+        v
+    }
+
+    #[test]
+    fn fixed_matches_std_formatting() {
+        let mut buf = NumBuf::new();
+        for n in values() {
+            for prec in [0, 1, 2, 3, 6, 10, MAX_PRECISION] {
+                if let Some(s) = fixed(n, prec, &mut buf) {
+                    assert_eq!(s, format!("{:.*}", prec, n), "{n:e} at {prec}");
+                }
+            }
+        }
+        assert!(fixed(f64::NAN, 2, &mut buf).is_none());
+        assert!(fixed(1e20, 2, &mut buf).is_none());
+    }
+
+    #[test]
+    fn general_matches_trimmed_fixed_six() {
+        let old = |n: f64| {
+            if n == (n as i64) as f64 {
+                (n as i64).to_string()
+            } else {
+                let s = format!("{:.6}", n);
+                s.trim_end_matches('0').trim_end_matches('.').to_string()
+            }
+        };
+        let mut buf = NumBuf::new();
+        for n in values().into_iter().filter(|n| n.is_finite()) {
+            if let Some(s) = general(n, &mut buf) {
+                assert_eq!(s, old(n), "{n:e}");
+            }
+        }
+        assert_eq!(general(f64::NEG_INFINITY, &mut buf), Some("-inf"));
+        assert_eq!(integer(i64::MIN, &mut buf), i64::MIN.to_string());
+    }
+}
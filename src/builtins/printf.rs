@@ -1,4 +1,7 @@
-use super::to_number;
+use std::fmt::Write;
+
+use super::numfmt::{self, NumBuf};
+use super::{to_number, width};
 
 /// Parsed format flags from a printf conversion specifier.
 struct FmtFlags {
@@ -6,12 +9,14 @@ struct FmtFlags {
     zero_pad: bool,
     force_sign: bool,
     space_sign: bool,
+    /// `'`: group the integer digits with `GROUPSEP`.
+    group: bool,
     width: usize,
     precision: Option<usize>,
 }
 
 /// Parse flags, width, and precision from the characters between '%' and the
-/// conversion letter.  Handles flags: `-`, `0`, `+`, ` ` (space).
+/// conversion letter.  Handles flags: `-`, `0`, `+`, ` ` (space), `'`.
 fn parse_flags(spec: &str) -> FmtFlags {
     let bytes = spec.as_bytes();
     let mut i = 0;
@@ -19,6 +24,7 @@ fn parse_flags(spec: &str) -> FmtFlags {
     let mut zero_pad = false;
     let mut force_sign = false;
     let mut space_sign = false;
+    let mut group = false;
 
     while i < bytes.len() {
         match bytes[i] {
@@ -28,6 +34,7 @@ fn parse_flags(spec: &str) -> FmtFlags {
             }
             b'+' => force_sign = true,
             b' ' => space_sign = true,
+            b'\'' => group = true,
             _ => break,
         }
         i += 1;
@@ -52,224 +59,433 @@ fn parse_flags(spec: &str) -> FmtFlags {
         zero_pad,
         force_sign,
         space_sign,
+        group,
         width,
         precision,
     }
 }
 
+/// Separators for `%'d` digit grouping and the decimal point of `%f`,
+/// `%e` and `%g` (`GROUPSEP`, `DECIMALSEP`). Input is always read with
+/// a `.`; only printf output changes.
+#[derive(Clone, Copy)]
+pub struct NumSeps<'a> {
+    pub group: &'a str,
+    pub decimal: &'a str,
+}
+
+impl NumSeps<'_> {
+    pub const C: NumSeps<'static> = NumSeps {
+        group: ",",
+        decimal: ".",
+    };
+}
+
+/// Apply `'` grouping and the decimal separator to a formatted number:
+/// `-1234567.5` → `-1,234,567.5`. Only the first run of digits is grouped,
+/// and only when `group` is set; exponents and inf/nan pass through.
+fn localize(s: String, group: bool, seps: &NumSeps) -> String {
+    let group = group && !seps.group.is_empty();
+    if !group && seps.decimal == "." {
+        return s;
+    }
+    let start = s.find(|c: char| c.is_ascii_digit()).unwrap_or(s.len());
+    let digits = s[start..]
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(s.len(), |n| start + n);
+    let mut out = String::with_capacity(s.len() + (digits - start) / 3 * seps.group.len());
+    out.push_str(&s[..start]);
+    for (i, d) in s[start..digits].chars().enumerate() {
+        if group && i > 0 && (digits - start - i) % 3 == 0 {
+            out.push_str(seps.group);
+        }
+        out.push(d);
+    }
+    let rest = &s[digits..];
+    match rest.strip_prefix('.') {
+        Some(frac) => {
+            out.push_str(seps.decimal);
+            out.push_str(frac);
+        }
+        None => out.push_str(rest),
+    }
+    out
+}
+
 /// Apply width / alignment / padding to an already-formatted string.
 fn apply_width(s: &str, flags: &FmtFlags, pad: char) -> String {
-    if flags.width == 0 || s.len() >= flags.width {
+    let len = s.chars().count();
+    if flags.width == 0 || len >= flags.width {
         return s.to_string();
     }
     if flags.left_align {
         format!("{:<width$}", s, width = flags.width)
     } else {
-        let fill = String::from(pad).repeat(flags.width - s.len());
+        let fill = String::from(pad).repeat(flags.width - len);
         format!("{}{}", fill, s)
     }
 }
 
-/// printf implementation supporting %d, %i, %f, %g, %e, %s, %c, %x, %o, %%.
-/// Flags: `-` (left-align), `0` (zero-pad), `+` (force sign), ` ` (space sign).
-pub fn format_printf(fmt: &str, args: &[String]) -> String {
-    let mut result = String::new();
-    let chars: Vec<char> = fmt.chars().collect();
-    let mut i = 0;
-    let mut arg_idx = 0;
+/// Append `val` with `prec` decimals (`%.Nf`) to `out`.
+fn push_fixed(out: &mut String, val: f64, prec: usize) {
+    let mut buf = NumBuf::new();
+    match numfmt::fixed(val, prec, &mut buf) {
+        Some(s) => out.push_str(s),
+        None => {
+            let _ = write!(out, "{:.*}", prec, val);
+        }
+    }
+}
 
-    while i < chars.len() {
-        if chars[i] == '%' && i + 1 < chars.len() {
-            i += 1;
-            let mut spec = String::new();
-            while i < chars.len() && !chars[i].is_ascii_alphabetic() && chars[i] != '%' {
-                spec.push(chars[i]);
-                i += 1;
-            }
-            if i >= chars.len() {
-                result.push('%');
-                result.push_str(&spec);
-                break;
-            }
-            let conv = chars[i];
-            i += 1;
+/// One piece of a parsed format string.
+enum Segment {
+    /// Text and decoded escapes, copied as is.
+    Lit(String),
+    /// A conversion: the characters between `%` and the letter, the letter,
+    /// and the flags when they are fixed (no `*` to take from the args).
+    Conv {
+        spec: String,
+        conv: char,
+        flags: Option<FmtFlags>,
+    },
+}
 
-            // Resolve dynamic width/precision: %*d, %.*f, %*.*f
-            let resolved = if spec.contains('*') {
-                let mut buf = String::new();
-                let mut after_dot = false;
-                for ch in spec.chars() {
-                    if ch == '.' {
-                        after_dot = true;
-                        buf.push('.');
-                    } else if ch == '*' {
-                        let w = args.get(arg_idx).map(|s| to_number(s)).unwrap_or(0.0) as i64;
-                        arg_idx += 1;
-                        if after_dot {
-                            if w >= 0 {
-                                buf.push_str(&w.to_string());
-                            } else {
-                                buf.pop(); // negative precision: drop the '.'
-                            }
-                        } else if w < 0 {
-                            buf.push('-');
-                            buf.push_str(&(-w).to_string());
-                        } else {
-                            buf.push_str(&w.to_string());
-                        }
-                    } else {
-                        buf.push(ch);
-                    }
-                }
-                buf
-            } else {
-                spec.clone()
-            };
-            let flags = parse_flags(&resolved);
-            match conv {
-                '%' => result.push('%'),
-                'd' | 'i' => {
-                    let val = args.get(arg_idx).map(|s| to_number(s)).unwrap_or(0.0) as i64;
-                    arg_idx += 1;
-                    let prefix = if val < 0 {
-                        ""
-                    } else if flags.force_sign {
-                        "+"
-                    } else if flags.space_sign {
-                        " "
-                    } else {
-                        ""
-                    };
-                    let s = format!("{}{}", prefix, val);
-                    let pad = if flags.zero_pad { '0' } else { ' ' };
-                    if flags.zero_pad && (val < 0 || flags.force_sign || flags.space_sign) {
-                        let sign = &s[..1];
-                        let digits = &s[1..];
-                        if flags.width > s.len() {
-                            let zeros = "0".repeat(flags.width - s.len());
-                            result.push_str(&format!("{}{}{}", sign, zeros, digits));
-                        } else {
-                            result.push_str(&s);
-                        }
-                    } else {
-                        result.push_str(&apply_width(&s, &flags, pad));
-                    }
-                }
-                'f' | 'e' => {
-                    let val = args.get(arg_idx).map(|s| to_number(s)).unwrap_or(0.0);
-                    arg_idx += 1;
-                    let prec = flags.precision.unwrap_or(6);
-                    let prefix = if val < 0.0 || val.is_sign_negative() {
-                        ""
-                    } else if flags.force_sign {
-                        "+"
-                    } else if flags.space_sign {
-                        " "
-                    } else {
-                        ""
-                    };
-                    let s = if conv == 'e' {
-                        format!("{}{:.*e}", prefix, prec, val)
-                    } else {
-                        format!("{}{:.*}", prefix, prec, val)
-                    };
-                    let pad = if flags.zero_pad { '0' } else { ' ' };
-                    if flags.zero_pad && s.len() < flags.width && !s.is_empty() {
-                        let first = s.as_bytes()[0];
-                        if first == b'-' || first == b'+' || first == b' ' {
-                            let sign = &s[..1];
-                            let rest = &s[1..];
-                            let zeros = "0".repeat(flags.width - s.len());
-                            result.push_str(&format!("{}{}{}", sign, zeros, rest));
-                        } else {
-                            result.push_str(&apply_width(&s, &flags, '0'));
-                        }
-                    } else {
-                        result.push_str(&apply_width(&s, &flags, pad));
-                    }
-                }
-                'g' => {
-                    let val = args.get(arg_idx).map(|s| to_number(s)).unwrap_or(0.0);
-                    arg_idx += 1;
-                    let prec = flags.precision.unwrap_or(6);
-                    let prefix = if val < 0.0 || val.is_sign_negative() {
-                        ""
-                    } else if flags.force_sign {
-                        "+"
-                    } else if flags.space_sign {
-                        " "
-                    } else {
-                        ""
-                    };
-                    let s_f = format!("{:.*}", prec, val);
-                    let s_e = format!("{:.*e}", prec, val);
-                    let formatted = if s_f.len() <= s_e.len() { s_f } else { s_e };
-                    let trimmed = formatted.trim_end_matches('0');
-                    let trimmed = trimmed.trim_end_matches('.');
-                    let s = format!("{}{}", prefix, trimmed);
-                    let pad = if flags.zero_pad { '0' } else { ' ' };
-                    result.push_str(&apply_width(&s, &flags, pad));
-                }
-                'x' => {
-                    let val = args.get(arg_idx).map(|s| to_number(s)).unwrap_or(0.0) as i64;
-                    arg_idx += 1;
-                    let s = format!("{:x}", val);
-                    let pad = if flags.zero_pad { '0' } else { ' ' };
-                    result.push_str(&apply_width(&s, &flags, pad));
+/// One printf argument: its text, and whether it was a number, which
+/// `%c` prints as the character with that code point rather than the
+/// text's first character.
+pub struct Arg {
+    pub text: String,
+    pub number: bool,
+}
+
+impl From<String> for Arg {
+    fn from(text: String) -> Arg {
+        Arg {
+            text,
+            number: false,
+        }
+    }
+}
+
+impl From<&str> for Arg {
+    fn from(text: &str) -> Arg {
+        Arg::from(text.to_string())
+    }
+}
+
+/// A printf format string parsed into literal text and conversions, so a
+/// literal format is parsed once instead of on every call.
+pub struct Format {
+    segments: Vec<Segment>,
+}
+
+impl Format {
+    pub fn parse(fmt: &str) -> Format {
+        let mut segments = Vec::new();
+        let mut lit = String::new();
+        let chars: Vec<char> = fmt.chars().collect();
+        let mut i = 0;
+
+        while i < chars.len() {
+            if chars[i] == '%' && i + 1 < chars.len() {
+                i += 1;
+                let mut spec = String::new();
+                while i < chars.len() && !chars[i].is_ascii_alphabetic() && chars[i] != '%' {
+                    spec.push(chars[i]);
+                    i += 1;
                 }
-                'o' => {
-                    let val = args.get(arg_idx).map(|s| to_number(s)).unwrap_or(0.0) as i64;
-                    arg_idx += 1;
-                    let s = format!("{:o}", val);
-                    let pad = if flags.zero_pad { '0' } else { ' ' };
-                    result.push_str(&apply_width(&s, &flags, pad));
+                if i >= chars.len() {
+                    lit.push('%');
+                    lit.push_str(&spec);
+                    break;
                 }
-                's' => {
-                    let val = args.get(arg_idx).map(|s| s.as_str()).unwrap_or("");
-                    arg_idx += 1;
-                    let val = if let Some(prec) = flags.precision {
-                        &val[..val.len().min(prec)]
-                    } else {
-                        val
-                    };
-                    result.push_str(&apply_width(val, &flags, ' '));
+                let conv = chars[i];
+                i += 1;
+                if !lit.is_empty() {
+                    segments.push(Segment::Lit(std::mem::take(&mut lit)));
                 }
-                'c' => {
-                    if let Some(s) = args.get(arg_idx) {
-                        let n = to_number(s);
-                        if n != 0.0 || s == "0" {
-                            if let Some(ch) = char::from_u32(n as u32) {
-                                result.push(ch);
-                            }
-                        } else if let Some(ch) = s.chars().next() {
-                            result.push(ch);
-                        }
+                let flags = (!spec.contains('*')).then(|| parse_flags(&spec));
+                segments.push(Segment::Conv { spec, conv, flags });
+            } else if chars[i] == '\\' && i + 1 < chars.len() {
+                i += 1;
+                match chars[i] {
+                    'n' => lit.push('\n'),
+                    't' => lit.push('\t'),
+                    '\\' => lit.push('\\'),
+                    _ => {
+                        lit.push('\\');
+                        lit.push(chars[i]);
                     }
-                    arg_idx += 1;
                 }
-                _ => {
-                    result.push('%');
-                    result.push_str(&spec);
-                    result.push(conv);
+                i += 1;
+            } else {
+                lit.push(chars[i]);
+                i += 1;
+            }
+        }
+        if !lit.is_empty() {
+            segments.push(Segment::Lit(lit));
+        }
+        Format { segments }
+    }
+
+    /// Format `args` through this format.
+    pub fn render(&self, args: &[Arg]) -> String {
+        self.render_with(args, &NumSeps::C)
+    }
+
+    /// `render` with the given grouping and decimal separators.
+    pub fn render_with(&self, args: &[Arg], seps: &NumSeps) -> String {
+        let mut result = String::new();
+        let mut arg_idx = 0;
+        for seg in &self.segments {
+            match seg {
+                Segment::Lit(text) => result.push_str(text),
+                Segment::Conv { spec, conv, flags } => {
+                    let resolved;
+                    let flags = match flags {
+                        Some(f) => f,
+                        None => {
+                            resolved = parse_flags(&resolve_stars(spec, args, &mut arg_idx));
+                            &resolved
+                        }
+                    };
+                    push_conversion(&mut result, spec, *conv, flags, args, &mut arg_idx, seps);
                 }
             }
-        } else if chars[i] == '\\' && i + 1 < chars.len() {
-            i += 1;
-            match chars[i] {
-                'n' => result.push('\n'),
-                't' => result.push('\t'),
-                '\\' => result.push('\\'),
-                _ => {
-                    result.push('\\');
-                    result.push(chars[i]);
+        }
+        result
+    }
+}
+
+/// How a call's arguments fail to fit its format.
+#[derive(Debug, PartialEq)]
+pub enum ArgMismatch {
+    /// Conversion number `conversion` (from 1, `spec` as written) and the
+    /// ones after it have no argument.
+    Missing { conversion: usize, spec: String },
+    /// This many arguments are left over.
+    Extra(usize),
+}
+
+impl Format {
+    /// Compare `given` arguments with what the conversions take, counted
+    /// the way `render` consumes them: one each, plus one per `*`.
+    pub fn check_args(&self, given: usize) -> Option<ArgMismatch> {
+        let mut taken = 0;
+        let mut conversion = 0;
+        for seg in &self.segments {
+            let Segment::Conv { spec, conv, .. } = seg else {
+                continue;
+            };
+            if *conv == '%' {
+                continue;
+            }
+            conversion += 1;
+            taken += spec.matches('*').count() + usize::from(takes_arg(*conv));
+            if taken > given {
+                let spec = format!("%{}{}", spec, conv);
+                return Some(ArgMismatch::Missing { conversion, spec });
+            }
+        }
+        (given > taken).then(|| ArgMismatch::Extra(given - taken))
+    }
+}
+
+/// Whether conversion letter `conv` formats an argument (an unknown one
+/// is copied through as text).
+fn takes_arg(conv: char) -> bool {
+    matches!(conv, 'd' | 'i' | 'f' | 'e' | 'g' | 'x' | 'o' | 's' | 'c')
+}
+
+/// Replace each `*` in a spec with the next argument: `%*d`, `%.*f`, `%*.*f`.
+fn resolve_stars(spec: &str, args: &[Arg], arg_idx: &mut usize) -> String {
+    let mut buf = String::new();
+    let mut after_dot = false;
+    for ch in spec.chars() {
+        if ch == '.' {
+            after_dot = true;
+            buf.push('.');
+        } else if ch == '*' {
+            let w = args.get(*arg_idx).map_or(0.0, |a| to_number(&a.text)) as i64;
+            *arg_idx += 1;
+            if after_dot {
+                if w >= 0 {
+                    buf.push_str(&w.to_string());
+                } else {
+                    buf.pop(); // negative precision: drop the '.'
                 }
+            } else if w < 0 {
+                buf.push('-');
+                buf.push_str(&(-w).to_string());
+            } else {
+                buf.push_str(&w.to_string());
             }
-            i += 1;
         } else {
-            result.push(chars[i]);
-            i += 1;
+            buf.push(ch);
         }
     }
+    buf
+}
+
+/// printf implementation supporting %d, %i, %f, %g, %e, %s, %c, %x, %o, %%.
+/// Flags: `-` (left-align), `0` (zero-pad), `+` (force sign), ` ` (space sign),
+/// `'` (group thousands).
+pub fn format_printf(fmt: &str, args: &[Arg]) -> String {
+    Format::parse(fmt).render(args)
+}
+
+/// `format_printf` with the given grouping and decimal separators.
+pub fn format_printf_with(fmt: &str, args: &[Arg], seps: &NumSeps) -> String {
+    Format::parse(fmt).render_with(args, seps)
+}
 
-    result
+/// Append one conversion, taking its value from `args[*arg_idx]`.
+fn push_conversion(
+    result: &mut String,
+    spec: &str,
+    conv: char,
+    flags: &FmtFlags,
+    args: &[Arg],
+    arg_idx: &mut usize,
+    seps: &NumSeps,
+) {
+    match conv {
+        '%' => result.push('%'),
+        'd' | 'i' => {
+            let val = args.get(*arg_idx).map_or(0.0, |a| to_number(&a.text)) as i64;
+            *arg_idx += 1;
+            let prefix = if val < 0 {
+                ""
+            } else if flags.force_sign {
+                "+"
+            } else if flags.space_sign {
+                " "
+            } else {
+                ""
+            };
+            let mut s = String::from(prefix);
+            s.push_str(numfmt::integer(val, &mut NumBuf::new()));
+            if flags.group {
+                s = localize(s, true, seps);
+            }
+            let pad = if flags.zero_pad { '0' } else { ' ' };
+            if flags.zero_pad && (val < 0 || flags.force_sign || flags.space_sign) {
+                let sign = &s[..1];
+                let digits = &s[1..];
+                let len = s.chars().count();
+                if flags.width > len {
+                    let zeros = "0".repeat(flags.width - len);
+                    result.push_str(&format!("{}{}{}", sign, zeros, digits));
+                } else {
+                    result.push_str(&s);
+                }
+            } else {
+                result.push_str(&apply_width(&s, flags, pad));
+            }
+        }
+        'f' | 'e' => {
+            let val = args.get(*arg_idx).map_or(0.0, |a| to_number(&a.text));
+            *arg_idx += 1;
+            let prec = flags.precision.unwrap_or(6);
+            let prefix = if val < 0.0 || val.is_sign_negative() {
+                ""
+            } else if flags.force_sign {
+                "+"
+            } else if flags.space_sign {
+                " "
+            } else {
+                ""
+            };
+            let mut s = String::from(prefix);
+            if conv == 'e' {
+                let _ = write!(s, "{:.*e}", prec, val);
+            } else {
+                push_fixed(&mut s, val, prec);
+            }
+            let s = localize(s, flags.group && conv == 'f', seps);
+            let len = s.chars().count();
+            let pad = if flags.zero_pad { '0' } else { ' ' };
+            if flags.zero_pad && len < flags.width && !s.is_empty() {
+                let first = s.as_bytes()[0];
+                if first == b'-' || first == b'+' || first == b' ' {
+                    let sign = &s[..1];
+                    let rest = &s[1..];
+                    let zeros = "0".repeat(flags.width - len);
+                    result.push_str(&format!("{}{}{}", sign, zeros, rest));
+                } else {
+                    result.push_str(&apply_width(&s, flags, '0'));
+                }
+            } else {
+                result.push_str(&apply_width(&s, flags, pad));
+            }
+        }
+        'g' => {
+            let val = args.get(*arg_idx).map_or(0.0, |a| to_number(&a.text));
+            *arg_idx += 1;
+            let prec = flags.precision.unwrap_or(6);
+            let prefix = if val < 0.0 || val.is_sign_negative() {
+                ""
+            } else if flags.force_sign {
+                "+"
+            } else if flags.space_sign {
+                " "
+            } else {
+                ""
+            };
+            let mut s_f = String::new();
+            push_fixed(&mut s_f, val, prec);
+            let s_e = format!("{:.*e}", prec, val);
+            let formatted = if s_f.len() <= s_e.len() { s_f } else { s_e };
+            let trimmed = formatted.trim_end_matches('0');
+            let trimmed = trimmed.trim_end_matches('.');
+            let grouped = flags.group && !trimmed.contains('e');
+            let s = localize(format!("{}{}", prefix, trimmed), grouped, seps);
+            let pad = if flags.zero_pad { '0' } else { ' ' };
+            result.push_str(&apply_width(&s, flags, pad));
+        }
+        'x' => {
+            let val = args.get(*arg_idx).map_or(0.0, |a| to_number(&a.text)) as i64;
+            *arg_idx += 1;
+            let s = format!("{:x}", val);
+            let pad = if flags.zero_pad { '0' } else { ' ' };
+            result.push_str(&apply_width(&s, flags, pad));
+        }
+        'o' => {
+            let val = args.get(*arg_idx).map_or(0.0, |a| to_number(&a.text)) as i64;
+            *arg_idx += 1;
+            let s = format!("{:o}", val);
+            let pad = if flags.zero_pad { '0' } else { ' ' };
+            result.push_str(&apply_width(&s, flags, pad));
+        }
+        's' => {
+            let val = args.get(*arg_idx).map_or("", |a| a.text.as_str());
+            *arg_idx += 1;
+            let val = match flags.precision {
+                Some(prec) => width::truncate(val, prec),
+                None => val,
+            };
+            if flags.left_align {
+                result.push_str(&width::pad_right(val, flags.width, ' '));
+            } else {
+                result.push_str(&width::pad_left(val, flags.width, ' '));
+            }
+        }
+        'c' => {
+            let ch = match args.get(*arg_idx) {
+                Some(a) if a.number => char::from_u32(to_number(&a.text) as u32),
+                Some(a) => a.text.chars().next(),
+                None => None,
+            };
+            if let Some(ch) = ch {
+                result.push(ch);
+            }
+            *arg_idx += 1;
+        }
+        _ => {
+            result.push('%');
+            result.push_str(spec);
+            result.push(conv);
+        }
+    }
 }
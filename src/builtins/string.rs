@@ -1,4 +1,12 @@
-use super::{format_number, to_number};
+use std::cell::RefCell;
+
+use super::{format_number, to_number, width};
+
+thread_local! {
+    /// Scratch buffers for levenshtein(), reused across calls.
+    static LEV_SCRATCH: RefCell<(Vec<char>, Vec<char>, Vec<usize>)> =
+        const { RefCell::new((Vec::new(), Vec::new(), Vec::new())) };
+}
 
 /// Dispatch string built-in functions.
 pub fn call(name: &str, args: &[String]) -> String {
@@ -34,12 +42,14 @@ pub fn call(name: &str, args: &[String]) -> String {
             }
         }
         "tolower" | "lower" => {
-            let s = args.first().map(|s| s.as_str()).unwrap_or("");
-            s.to_lowercase()
+            let mut s = args.first().cloned().unwrap_or_default();
+            lowercase(&mut s);
+            s
         }
         "toupper" | "upper" => {
-            let s = args.first().map(|s| s.as_str()).unwrap_or("");
-            s.to_uppercase()
+            let mut s = args.first().cloned().unwrap_or_default();
+            uppercase(&mut s);
+            s
         }
         "trim" => {
             let s = args.first().map(|s| s.as_str()).unwrap_or("");
@@ -84,38 +94,46 @@ pub fn call(name: &str, args: &[String]) -> String {
             let n = args.first().map(|s| to_number(s) as i64).unwrap_or(0);
             format!("{:#x}", n)
         }
-        "lpad" => {
+        "lpad" | "rpad" => {
             let s = args.first().map(|s| s.as_str()).unwrap_or("");
             let width = args.get(1).map(|s| to_number(s) as usize).unwrap_or(0);
             let pad = args.get(2).and_then(|s| s.chars().next()).unwrap_or(' ');
-            let chars: usize = s.chars().count();
-            if chars >= width {
-                s.to_string()
+            if name == "lpad" {
+                width::pad_left(s, width, pad)
             } else {
-                let mut out = String::with_capacity(width);
-                for _ in 0..(width - chars) {
-                    out.push(pad);
-                }
-                out.push_str(s);
-                out
+                width::pad_right(s, width, pad)
             }
         }
-        "rpad" => {
+        "levenshtein" => {
+            let a = args.first().map(|s| s.as_str()).unwrap_or("");
+            let b = args.get(1).map(|s| s.as_str()).unwrap_or("");
+            format_number(levenshtein(a, b) as f64)
+        }
+        "dice" => {
+            let a = args.first().map(|s| s.as_str()).unwrap_or("");
+            let b = args.get(1).map(|s| s.as_str()).unwrap_or("");
+            format_number(dice(a, b))
+        }
+        "soundex" => soundex(args.first().map(|s| s.as_str()).unwrap_or("")),
+        "escape" | "unescape" => {
             let s = args.first().map(|s| s.as_str()).unwrap_or("");
-            let width = args.get(1).map(|s| to_number(s) as usize).unwrap_or(0);
-            let pad = args.get(2).and_then(|s| s.chars().next()).unwrap_or(' ');
-            let chars: usize = s.chars().count();
-            if chars >= width {
-                s.to_string()
+            let mode = args.get(1).map(|s| s.as_str()).unwrap_or("");
+            let done = if name == "escape" {
+                escape(s, mode)
             } else {
-                let mut out = String::with_capacity(width);
-                out.push_str(s);
-                for _ in 0..(width - chars) {
-                    out.push(pad);
-                }
-                out
-            }
+                unescape(s, mode)
+            };
+            done.unwrap_or_else(|| {
+                let modes = if name == "escape" {
+                    "json, csv, shell, regex or html"
+                } else {
+                    "json, csv, regex or html"
+                };
+                eprintln!("fk: {}: unknown mode \"{}\" ({})", name, mode, modes);
+                s.to_string()
+            })
         }
+        "rx" => rx(args),
         // secret — not documented
         "emoji" | "moji" => {
             let s = args.first().map(|s| s.as_str()).unwrap_or("");
@@ -125,22 +143,410 @@ pub fn call(name: &str, args: &[String]) -> String {
     }
 }
 
+/// Edit distance in unicode chars (insert, delete, substitute), using a
+/// single DP row over the shorter string.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    if a == b {
+        return 0;
+    }
+    LEV_SCRATCH.with(|scratch| {
+        let (ac, bc, row) = &mut *scratch.borrow_mut();
+        ac.clear();
+        ac.extend(a.chars());
+        bc.clear();
+        bc.extend(b.chars());
+        // Common prefix and suffix never change the distance.
+        let pre = ac.iter().zip(bc.iter()).take_while(|(x, y)| x == y).count();
+        let suf = ac[pre..]
+            .iter()
+            .rev()
+            .zip(bc[pre..].iter().rev())
+            .take_while(|(x, y)| x == y)
+            .count();
+        let (a, b) = (&ac[pre..ac.len() - suf], &bc[pre..bc.len() - suf]);
+        let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+        if short.is_empty() {
+            return long.len();
+        }
+        row.clear();
+        row.extend(0..=short.len());
+        for (i, &lc) in long.iter().enumerate() {
+            let mut diag = row[0];
+            row[0] = i + 1;
+            for (j, &sc) in short.iter().enumerate() {
+                let above = row[j + 1];
+                let cost = usize::from(lc != sc);
+                row[j + 1] = (diag + cost).min(row[j] + 1).min(above + 1);
+                diag = above;
+            }
+        }
+        row[short.len()]
+    })
+}
+
+/// Sørensen–Dice coefficient over character bigrams: 0 (nothing shared)
+/// to 1 (same bigram multiset). Strings too short for a bigram score 1
+/// only when equal.
+pub fn dice(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+    let bigrams = |s: &str| {
+        let mut v: Vec<(char, char)> = s.chars().zip(s.chars().skip(1)).collect();
+        v.sort_unstable();
+        v
+    };
+    let (ba, bb) = (bigrams(a), bigrams(b));
+    if ba.is_empty() || bb.is_empty() {
+        return 0.0;
+    }
+    let (mut i, mut j, mut shared) = (0, 0, 0);
+    while i < ba.len() && j < bb.len() {
+        match ba[i].cmp(&bb[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                shared += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    2.0 * shared as f64 / (ba.len() + bb.len()) as f64
+}
+
+/// American Soundex code ("Robert" → "R163"). Non-letters are ignored;
+/// no letters gives "".
+pub fn soundex(s: &str) -> String {
+    fn code(c: char) -> u8 {
+        match c {
+            'B' | 'F' | 'P' | 'V' => b'1',
+            'C' | 'G' | 'J' | 'K' | 'Q' | 'S' | 'X' | 'Z' => b'2',
+            'D' | 'T' => b'3',
+            'L' => b'4',
+            'M' | 'N' => b'5',
+            'R' => b'6',
+            'H' | 'W' => b'-', // transparent: does not separate equal codes
+            _ => b'0',         // vowels (and Y) separate equal codes
+        }
+    }
+    let mut letters = s
+        .chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .map(|c| c.to_ascii_uppercase());
+    let Some(first) = letters.next() else {
+        return String::new();
+    };
+    let mut out = String::with_capacity(4);
+    out.push(first);
+    let mut last = code(first);
+    for c in letters {
+        let d = code(c);
+        if d == b'-' {
+            continue;
+        }
+        if d != b'0' && d != last {
+            out.push(d as char);
+            if out.len() == 4 {
+                break;
+            }
+        }
+        last = d;
+    }
+    while out.len() < 4 {
+        out.push('0');
+    }
+    out
+}
+
+/// `rx(fmt, args...)`: `fmt` with each `%s` replaced by the next argument
+/// escaped for regex, so the result matches the arguments literally.
+/// `%%` is a literal `%`; any other directive is copied unchanged.
+fn rx(args: &[String]) -> String {
+    let fmt = args.first().map(|s| s.as_str()).unwrap_or("");
+    let mut rest = args.iter().skip(1);
+    let mut out = String::with_capacity(fmt.len());
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('s') => {
+                chars.next();
+                if let Some(a) = rest.next() {
+                    out.push_str(&regex::escape(a));
+                }
+            }
+            Some('%') => {
+                chars.next();
+                out.push('%');
+            }
+            _ => out.push('%'),
+        }
+    }
+    out
+}
+
+/// `escape(s, mode)`: `s` made safe to embed in another language. `json`
+/// and `csv` give a complete quoted value (csv quotes only when needed),
+/// `shell` a single word for `sh`, `regex` a pattern matching `s`
+/// literally, `html` text with `& < > " '` as entities. `None` for an
+/// unknown mode.
+pub fn escape(s: &str, mode: &str) -> Option<String> {
+    Some(match mode {
+        "json" => {
+            let mut out = String::with_capacity(s.len() + 2);
+            crate::output::json_string(s, &mut out);
+            out
+        }
+        "csv" => crate::output::csv_row(&[s.to_string()]),
+        "shell" => shell_quote(s),
+        "regex" => regex::escape(s),
+        "html" => {
+            let mut out = String::with_capacity(s.len());
+            for c in s.chars() {
+                match c {
+                    '&' => out.push_str("&amp;"),
+                    '<' => out.push_str("&lt;"),
+                    '>' => out.push_str("&gt;"),
+                    '"' => out.push_str("&quot;"),
+                    '\'' => out.push_str("&#39;"),
+                    c => out.push(c),
+                }
+            }
+            out
+        }
+        _ => return None,
+    })
+}
+
+/// POSIX shell quoting: words of safe characters stay bare, anything else
+/// goes in single quotes with each `'` written as `'\''`.
+fn shell_quote(s: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c);
+    if !s.is_empty() && s.chars().all(safe) {
+        return s.to_string();
+    }
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// `unescape(s, mode)`: undo `escape` for `json` (the quotes are optional;
+/// `\uXXXX` surrogate pairs are joined), `csv`, `regex` and `html` (the
+/// five entities, `&nbsp;` and numeric `&#NN;` / `&#xHH;`). Malformed
+/// escapes are kept as written. `None` for a mode without an inverse.
+pub fn unescape(s: &str, mode: &str) -> Option<String> {
+    Some(match mode {
+        "json" => unescape_json(s),
+        "csv" => match s.strip_prefix('"').and_then(|q| q.strip_suffix('"')) {
+            Some(inner) => inner.replace("\"\"", "\""),
+            None => s.to_string(),
+        },
+        "regex" => {
+            let mut out = String::with_capacity(s.len());
+            let mut chars = s.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => out.push(chars.next().unwrap_or('\\')),
+                    c => out.push(c),
+                }
+            }
+            out
+        }
+        "html" => unescape_html(s),
+        _ => return None,
+    })
+}
+
+fn unescape_json(s: &str) -> String {
+    let inner = match s.strip_prefix('"').and_then(|q| q.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => s,
+    };
+    let mut out = String::with_capacity(inner.len());
+    let mut rest = inner;
+    while let Some(pos) = rest.find('\\') {
+        out.push_str(&rest[..pos]);
+        let esc = &rest[pos + 1..];
+        let (text, used) = match esc.chars().next() {
+            Some('"') => ("\"".into(), 1),
+            Some('\\') => ("\\".into(), 1),
+            Some('/') => ("/".into(), 1),
+            Some('b') => ("\u{8}".into(), 1),
+            Some('f') => ("\u{c}".into(), 1),
+            Some('n') => ("\n".into(), 1),
+            Some('r') => ("\r".into(), 1),
+            Some('t') => ("\t".into(), 1),
+            Some('u') => match json_code_unit(esc) {
+                Some(hi @ 0xD800..=0xDBFF) => {
+                    let low = esc.get(5..).and_then(|e| e.strip_prefix('\\'));
+                    match low.and_then(json_code_unit) {
+                        Some(lo @ 0xDC00..=0xDFFF) => {
+                            let cp = 0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00);
+                            (char::from_u32(cp).map(String::from).unwrap_or_default(), 11)
+                        }
+                        _ => ("\u{fffd}".into(), 5),
+                    }
+                }
+                Some(cp) => (char::from_u32(cp).unwrap_or('\u{fffd}').to_string(), 5),
+                None => ("\\".into(), 0),
+            },
+            _ => ("\\".into(), 0),
+        };
+        out.push_str(&text);
+        rest = &esc[used..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The code unit of a `uXXXX` escape (after its backslash).
+fn json_code_unit(esc: &str) -> Option<u32> {
+    let hex = esc.strip_prefix('u')?.get(..4)?;
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+fn unescape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('&') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let entity = after.find(';').filter(|&n| n <= 10).map(|n| &after[..n]);
+        let decoded = entity.and_then(|e| match e {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let num = e.strip_prefix('#')?;
+                let cp = match num.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => num.parse().ok()?,
+                };
+                char::from_u32(cp)
+            }
+        });
+        match (entity, decoded) {
+            (Some(e), Some(c)) => {
+                out.push(c);
+                rest = &after[e.len() + 1..];
+            }
+            _ => {
+                out.push('&');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Whether `pattern` means the same thing as a regex and as a literal
+/// string, i.e. has no regex metacharacters.
+pub fn is_literal_pattern(pattern: &str) -> bool {
+    !pattern.contains([
+        '\\', '.', '+', '*', '?', '(', ')', '|', '[', ']', '{', '}', '^', '$',
+    ])
+}
+
+/// `tolower` in place, with full Unicode mappings (as `str::to_lowercase`,
+/// final sigma included). A string with nothing to map is left alone and
+/// pure ASCII is mapped byte by byte, so neither allocates.
+pub fn lowercase(s: &mut String) {
+    if s.is_ascii() {
+        s.make_ascii_lowercase();
+    } else if !s.chars().all(|c| maps_to_itself(c, c.to_lowercase())) {
+        *s = s.to_lowercase();
+    }
+}
+
+/// `toupper` in place, like [`lowercase`]; one character may become
+/// several (`ß` is `SS`).
+pub fn uppercase(s: &mut String) {
+    if s.is_ascii() {
+        s.make_ascii_uppercase();
+    } else if !s.chars().all(|c| maps_to_itself(c, c.to_uppercase())) {
+        *s = s.to_uppercase();
+    }
+}
+
+fn maps_to_itself(c: char, mut mapped: impl Iterator<Item = char>) -> bool {
+    mapped.next() == Some(c) && mapped.next().is_none()
+}
+
 /// Replace first or all occurrences of a pattern in a string.
 /// Returns (new_string, replacement_count).
 pub fn string_replace(s: &str, pattern: &str, replacement: &str, global: bool) -> (String, usize) {
     if pattern.is_empty() {
         return (s.to_string(), 0);
     }
-    if global {
-        let count = s.matches(pattern).count();
-        (s.replace(pattern, replacement), count)
-    } else if let Some(pos) = s.find(pattern) {
-        let mut result = String::with_capacity(s.len());
-        result.push_str(&s[..pos]);
+    if !global {
+        return match s.find(pattern) {
+            Some(pos) => {
+                let mut result = String::with_capacity(s.len());
+                result.push_str(&s[..pos]);
+                result.push_str(replacement);
+                result.push_str(&s[pos + pattern.len()..]);
+                (result, 1)
+            }
+            None => (s.to_string(), 0),
+        };
+    }
+    // One pass that counts as it copies; a single-char pattern searches
+    // by char, which scans with memchr.
+    let mut chars = pattern.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => replace_all(s, s.match_indices(c), pattern.len(), replacement),
+        _ => replace_all(s, s.match_indices(pattern), pattern.len(), replacement),
+    }
+}
+
+/// Replace the first or every match of `re` in a string, for sub/gsub
+/// patterns that `is_literal_pattern` turns down. The replacement is
+/// inserted as is. Returns (new_string, replacement_count).
+pub fn regex_replace(
+    s: &str,
+    re: &regex::Regex,
+    replacement: &str,
+    global: bool,
+) -> (String, usize) {
+    let hits = re.find_iter(s).map(|m| (m.start(), m.end()));
+    let limit = if global { usize::MAX } else { 1 };
+    let mut result = String::with_capacity(s.len());
+    let mut last = 0;
+    let mut count = 0;
+    for (start, end) in hits.take(limit) {
+        result.push_str(&s[last..start]);
+        result.push_str(replacement);
+        last = end;
+        count += 1;
+    }
+    result.push_str(&s[last..]);
+    (result, count)
+}
+
+fn replace_all<'a>(
+    s: &str,
+    hits: impl Iterator<Item = (usize, &'a str)>,
+    width: usize,
+    replacement: &str,
+) -> (String, usize) {
+    let mut result = String::with_capacity(s.len());
+    let mut last = 0;
+    let mut count = 0;
+    for (pos, _) in hits {
+        result.push_str(&s[last..pos]);
         result.push_str(replacement);
-        result.push_str(&s[pos + pattern.len()..]);
-        (result, 1)
-    } else {
-        (s.to_string(), 0)
+        last = pos + width;
+        count += 1;
     }
+    result.push_str(&s[last..]);
+    (result, count)
 }
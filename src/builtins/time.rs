@@ -2,16 +2,18 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use super::to_number;
 
+/// Format used by `strftime()` and `parsedate()` without one; also
+/// reported as `PROCINFO["strftime"]`.
+pub const DEFAULT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
 /// Dispatch time built-in functions.
 pub fn call(name: &str, args: &[String]) -> String {
     match name {
-        "systime" | "now" => systime(),
+        "systime" => systime(),
+        "now" => now(),
         "strftime" => {
-            let fmt = args
-                .first()
-                .map(|s| s.as_str())
-                .unwrap_or("%Y-%m-%d %H:%M:%S");
-            let ts = args.get(1).map(|s| to_number(s) as i64);
+            let fmt = args.first().map(|s| s.as_str()).unwrap_or(DEFAULT_FORMAT);
+            let ts = args.get(1).map(|s| to_number(s));
             strftime(fmt, ts)
         }
         "mktime" => {
@@ -20,10 +22,7 @@ pub fn call(name: &str, args: &[String]) -> String {
         }
         "parsedate" | "pdate" => {
             let s = args.first().map(|s| s.as_str()).unwrap_or("");
-            let fmt = args
-                .get(1)
-                .map(|s| s.as_str())
-                .unwrap_or("%Y-%m-%d %H:%M:%S");
+            let fmt = args.get(1).map(|s| s.as_str()).unwrap_or(DEFAULT_FORMAT);
             parsedate(s, fmt)
         }
         _ => String::new(),
@@ -38,16 +37,27 @@ fn systime() -> String {
     }
 }
 
+/// Return current epoch time as fractional seconds (microsecond resolution).
+fn now() -> String {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => format!("{:.6}", d.as_micros() as f64 / 1e6),
+        Err(_) => "0".to_string(),
+    }
+}
+
 /// Format a timestamp (or current time) using strftime-style specifiers.
-/// Supports: %Y, %m, %d, %H, %M, %S, %s, %%, %A, %B, %a, %b, %Z.
-fn strftime(fmt: &str, timestamp: Option<i64>) -> String {
-    let ts = match timestamp {
+/// Supports: %Y, %m, %d, %H, %M, %S, %s, %%, %A, %B, %a, %b, %Z, and
+/// %N / %3N (fractional seconds, 9 or the given number of digits).
+fn strftime(fmt: &str, timestamp: Option<f64>) -> String {
+    let t = match timestamp {
         Some(t) => t,
         None => SystemTime::now()
             .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_secs() as i64)
-            .unwrap_or(0),
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0),
     };
+    let ts = t.floor() as i64;
+    let nanos = (((t - t.floor()) * 1e9).round() as u64).min(999_999_999);
 
     let parts = epoch_to_parts(ts);
 
@@ -57,7 +67,14 @@ fn strftime(fmt: &str, timestamp: Option<i64>) -> String {
     while i < chars.len() {
         if chars[i] == '%' && i + 1 < chars.len() {
             i += 1;
+            if chars[i].is_ascii_digit() && chars.get(i + 1) == Some(&'N') {
+                let digits = chars[i].to_digit(10).unwrap_or(9).clamp(1, 9) as usize;
+                result.push_str(&format!("{:09}", nanos)[..digits]);
+                i += 2;
+                continue;
+            }
             match chars[i] {
+                'N' => result.push_str(&format!("{:09}", nanos)),
                 'Y' => result.push_str(&format!("{:04}", parts.year)),
                 'm' => result.push_str(&format!("{:02}", parts.month)),
                 'd' => result.push_str(&format!("{:02}", parts.day)),
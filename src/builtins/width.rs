@@ -0,0 +1,127 @@
+//! Column widths for padding: `lpad`, `rpad`, printf `%s`, `-o table` and
+//! chart labels all measure text here.
+//!
+//! `WIDTHMODE` picks the measure. `"display"` (the default) counts terminal
+//! columns: CJK and emoji take two, combining marks and zero-width joiners
+//! none. `"chars"` counts code points, as awk does.
+
+use std::cell::Cell;
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+thread_local! {
+    static DISPLAY: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Apply a `WIDTHMODE` value; an empty string restores the default.
+/// Returns false (leaving the mode unchanged) for anything else.
+pub fn set_mode(mode: &str) -> bool {
+    let display = match mode {
+        "" | "display" => true,
+        "chars" => false,
+        _ => return false,
+    };
+    DISPLAY.with(|d| d.set(display));
+    true
+}
+
+/// Width of `s` under the current mode.
+pub fn text_width(s: &str) -> usize {
+    if s.is_ascii() {
+        // Control characters count as one column, as they always have.
+        s.len()
+    } else if DISPLAY.with(Cell::get) {
+        s.width()
+    } else {
+        s.chars().count()
+    }
+}
+
+/// Longest prefix of `s` that fits in `max` columns, never splitting a
+/// character (or a character from the marks that follow it). Widths are
+/// added up in one pass; a character after a zero-width joiner is part of
+/// the same emoji and takes no column of its own.
+pub fn truncate(s: &str, max: usize) -> &str {
+    if s.is_ascii() {
+        return &s[..s.len().min(max)];
+    }
+    let display = DISPLAY.with(Cell::get);
+    let mut width = 0;
+    let mut joined = false;
+    for (i, c) in s.char_indices() {
+        let w = match c.width() {
+            _ if !display => 1,
+            _ if joined => 0,
+            Some(w) => w,
+            None => 1,
+        };
+        if width + w > max {
+            return &s[..i];
+        }
+        width += w;
+        joined = c == '\u{200D}';
+    }
+    s
+}
+
+/// `s` preceded by `fill` up to `width` columns.
+pub fn pad_left(s: &str, width: usize, fill: char) -> String {
+    let mut out = String::with_capacity(width.max(s.len()));
+    out.extend(std::iter::repeat_n(
+        fill,
+        width.saturating_sub(text_width(s)),
+    ));
+    out.push_str(s);
+    out
+}
+
+/// `s` followed by `fill` up to `width` columns.
+pub fn pad_right(s: &str, width: usize, fill: char) -> String {
+    let mut out = String::with_capacity(width.max(s.len()));
+    out.push_str(s);
+    out.extend(std::iter::repeat_n(
+        fill,
+        width.saturating_sub(text_width(s)),
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAMILY: &str = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+
+    #[test]
+    fn display_width_counts_columns() {
+        assert_eq!(text_width("abc"), 3);
+        assert_eq!(text_width("日本"), 4);
+        assert_eq!(text_width("e\u{301}"), 1);
+        assert_eq!(text_width(FAMILY), 2);
+        assert_eq!(pad_left("日本", 6, '.'), "..日本");
+        assert_eq!(pad_right("e\u{301}", 3, '.'), "e\u{301}..");
+    }
+
+    #[test]
+    fn chars_mode_counts_code_points() {
+        assert!(set_mode("chars"));
+        assert_eq!(text_width("日本"), 2);
+        assert_eq!(text_width(FAMILY), 5);
+        assert!(!set_mode("bytes"));
+        assert_eq!(text_width("e\u{301}"), 2);
+        assert!(set_mode(""));
+        assert_eq!(text_width("e\u{301}"), 1);
+    }
+
+    #[test]
+    fn truncate_keeps_whole_characters() {
+        assert_eq!(truncate("日本語", 3), "日");
+        assert_eq!(truncate("e\u{301}x", 1), "e\u{301}");
+        assert_eq!(truncate(FAMILY, 1), "");
+        assert_eq!(truncate(FAMILY, 2), FAMILY);
+        assert_eq!(truncate("abc", 5), "abc");
+        let long = "日本".repeat(50_000);
+        assert_eq!(truncate(&long, 7), "日本日");
+        assert_eq!(truncate(&long, 400_000), long);
+    }
+}
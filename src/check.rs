@@ -0,0 +1,471 @@
+//! `--check[=json]`: lex, parse, analyze and lint a program without
+//! reading any input.
+//!
+//! Lints are deliberately conservative (the AST carries no spans, so only
+//! parse errors have a location):
+//!
+//! - `invalid-regex` (error): a regex literal the regex crate rejects.
+//! - `uninitialized` (warning): a scalar that is read but never assigned
+//!   anywhere. ALL-CAPS names (built-in and runtime-set variables), `-v`
+//!   names and variables passed to a function (arrays filled by reference)
+//!   are exempt.
+//! - `unused-function` (warning): a function that is never called.
+
+use std::collections::HashSet;
+
+use crate::action::{DEFAULT_REGEX_SIZE_LIMIT, compile_regex};
+use crate::analyze::{ProgramInfo, analyze};
+use crate::error::{FkError, Span};
+use crate::explain::{ExplainContext, explain};
+use crate::output::json_string;
+use crate::parser::*;
+use crate::{lexer, parser};
+
+/// Exit status when any diagnostic is an error (same as a parse error).
+pub const CHECK_ERROR_EXIT: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable identifier: `syntax`, `parse`, or a lint name.
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    fn error(code: &'static str, e: FkError) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: e.message,
+            span: Some(e.span),
+        }
+    }
+
+    fn lint(severity: Severity, code: &'static str, message: String) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            message,
+            span: None,
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(span) = self.span {
+            write!(f, "{span}: ")?;
+        }
+        write!(
+            f,
+            "{}: {} [{}]",
+            self.severity.label(),
+            self.message,
+            self.code
+        )
+    }
+}
+
+/// Everything `--check` learned about a program. `info` and `explain` are
+/// `None` when the program did not parse.
+#[derive(Debug)]
+pub struct Report {
+    pub diagnostics: Vec<Diagnostic>,
+    pub explain: Option<String>,
+    pub info: Option<ProgramInfo>,
+}
+
+impl Report {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error)
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        if self.has_errors() {
+            CHECK_ERROR_EXIT
+        } else {
+            0
+        }
+    }
+
+    /// One JSON document: `ok`, `diagnostics`, `explain` and `info`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"ok\":");
+        out.push_str(if self.has_errors() { "false" } else { "true" });
+        out.push_str(",\"diagnostics\":[");
+        for (i, d) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"severity\":");
+            json_string(d.severity.label(), &mut out);
+            out.push_str(",\"code\":");
+            json_string(d.code, &mut out);
+            out.push_str(",\"message\":");
+            json_string(&d.message, &mut out);
+            match d.span {
+                Some(s) => out.push_str(&format!(",\"line\":{},\"col\":{}}}", s.line, s.col)),
+                None => out.push_str(",\"line\":null,\"col\":null}"),
+            }
+        }
+        out.push_str("],\"explain\":");
+        match &self.explain {
+            Some(e) => json_string(e, &mut out),
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"info\":");
+        match &self.info {
+            Some(info) => {
+                out.push_str(&format!(
+                    "{{\"needs_fields\":{},\"needs_nf\":{},\"emits_output_in_rules\":{},\"max_field\":",
+                    info.needs_fields, info.needs_nf, info.emits_output_in_rules
+                ));
+                match info.max_field {
+                    Some(n) => out.push_str(&n.to_string()),
+                    None => out.push_str("null"),
+                }
+                out.push_str(",\"regex_literals\":[");
+                for (i, r) in info.regex_literals.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    json_string(r, &mut out);
+                }
+                out.push_str("]}");
+            }
+            None => out.push_str("null"),
+        }
+        out.push('}');
+        out
+    }
+}
+
+/// Check `source`. `assigned` names variables set from outside the program
+/// (`-v`), which are never reported as uninitialized.
+pub fn check(source: &str, ctx: Option<&ExplainContext>, assigned: &[String]) -> Report {
+    let failed = |d: Diagnostic| Report {
+        diagnostics: vec![d],
+        explain: None,
+        info: None,
+    };
+    let tokens = match lexer::Lexer::new(source).tokenize() {
+        Ok(t) => t,
+        Err(e) => return failed(Diagnostic::error("syntax", e)),
+    };
+    let program = match parser::Parser::new(tokens).parse() {
+        Ok(p) => p,
+        Err(e) => return failed(Diagnostic::error("parse", e)),
+    };
+    Report {
+        diagnostics: lint(&program, assigned),
+        explain: Some(explain(&program, ctx)),
+        info: Some(analyze(&program)),
+    }
+}
+
+/// Run the lints over a parsed program.
+pub fn lint(program: &Program, assigned: &[String]) -> Vec<Diagnostic> {
+    let mut u = Usage::default();
+    u.assigned.extend(assigned.iter().cloned());
+    let mut blocks: Vec<&Block> = Vec::new();
+    blocks.extend(&program.begin);
+    blocks.extend(program.rules.iter().map(|r| &r.action));
+    blocks.extend(&program.end);
+    blocks.extend(&program.beginfile);
+    blocks.extend(&program.endfile);
+    blocks.extend(&program.error);
+    for rule in &program.rules {
+        if let Some(p) = &rule.pattern {
+            u.pattern(p);
+        }
+    }
+    for b in blocks {
+        u.block(b);
+    }
+    for f in &program.functions {
+        u.locals = f.params.iter().cloned().collect();
+        u.block(&f.body);
+    }
+
+    let mut out: Vec<Diagnostic> = u.bad_regex;
+    for name in &u.reads {
+        if !u.assigned.contains(name) && !u.by_ref.contains(name) && !is_all_caps(name) {
+            let msg = format!("variable `{name}` is read but never assigned");
+            out.push(Diagnostic::lint(Severity::Warning, "uninitialized", msg));
+        }
+    }
+    for f in &program.functions {
+        if !u.calls.contains(&f.name) && !program.included.contains(&f.name) {
+            let msg = format!("function `{}` is never called", f.name);
+            out.push(Diagnostic::lint(Severity::Warning, "unused-function", msg));
+        }
+    }
+    out
+}
+
+fn is_all_caps(name: &str) -> bool {
+    name.bytes().any(|b| b.is_ascii_uppercase()) && !name.bytes().any(|b| b.is_ascii_lowercase())
+}
+
+/// Names seen while walking the AST. `reads` keeps first-seen order so the
+/// report is stable.
+#[derive(Default)]
+struct Usage {
+    reads: Vec<String>,
+    assigned: HashSet<String>,
+    by_ref: HashSet<String>,
+    calls: HashSet<String>,
+    locals: HashSet<String>,
+    bad_regex: Vec<Diagnostic>,
+}
+
+impl Usage {
+    fn read(&mut self, name: &str) {
+        if !self.locals.contains(name) && !self.reads.iter().any(|r| r == name) {
+            self.reads.push(name.to_string());
+        }
+    }
+
+    fn regex(&mut self, pat: &str) {
+        if let Err(e) = compile_regex(pat, DEFAULT_REGEX_SIZE_LIMIT) {
+            let why = e.to_string();
+            let why = why.lines().last().unwrap_or_default();
+            let why = why.strip_prefix("error: ").unwrap_or(why);
+            let msg = format!("invalid regex /{pat}/: {why}");
+            self.bad_regex
+                .push(Diagnostic::lint(Severity::Error, "invalid-regex", msg));
+        }
+    }
+
+    fn pattern(&mut self, p: &Pattern) {
+        match p {
+            Pattern::Regex(s) => self.regex(s),
+            Pattern::Expression(e) | Pattern::Last(e) => self.expr(e),
+            Pattern::Range(a, b) => {
+                self.pattern(a);
+                self.pattern(b);
+            }
+        }
+    }
+
+    fn block(&mut self, b: &Block) {
+        for s in b {
+            self.stmt(s);
+        }
+    }
+
+    fn stmt(&mut self, s: &Statement) {
+        match s {
+            Statement::Print(exprs, redir) | Statement::Printf(exprs, redir) => {
+                for e in exprs {
+                    self.expr(e);
+                }
+                if let Some(Redirect::Overwrite(e) | Redirect::Append(e) | Redirect::Pipe(e)) =
+                    redir
+                {
+                    self.expr(e);
+                }
+            }
+            Statement::If(c, t, f) => {
+                self.expr(c);
+                self.block(t);
+                if let Some(f) = f {
+                    self.block(f);
+                }
+            }
+            Statement::While(c, b) | Statement::DoWhile(b, c) => {
+                self.expr(c);
+                self.block(b);
+            }
+            Statement::For(init, cond, step, b) => {
+                if let Some(s) = init {
+                    self.stmt(s);
+                }
+                if let Some(c) = cond {
+                    self.expr(c);
+                }
+                if let Some(s) = step {
+                    self.stmt(s);
+                }
+                self.block(b);
+            }
+            Statement::ForIn(var, _, _, b) => {
+                self.assigned.insert(var.clone());
+                self.block(b);
+            }
+            Statement::Delete(_, e) | Statement::Expression(e) => self.expr(e),
+            Statement::Exit(Some(e)) | Statement::Return(Some(e)) => self.expr(e),
+            Statement::Block(b) => self.block(b),
+            Statement::DeleteAll(_)
+            | Statement::Next
+            | Statement::Nextfile
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Exit(None)
+            | Statement::Return(None) => {}
+        }
+    }
+
+    fn target(&mut self, e: &Expr) {
+        match e {
+            Expr::Var(name) => {
+                self.assigned.insert(name.clone());
+            }
+            other => self.expr(other),
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        match e {
+            Expr::Var(name) => self.read(name),
+            Expr::NumberLit(_) | Expr::StringLit(_) => {}
+            Expr::Field(inner) | Expr::ArrayRef(_, inner) | Expr::ArrayIn(inner, _) => {
+                self.expr(inner)
+            }
+            Expr::Match(l, r) | Expr::NotMatch(l, r) => {
+                self.expr(l);
+                match r.as_ref() {
+                    Expr::StringLit(pat) => self.regex(pat),
+                    other => self.expr(other),
+                }
+            }
+            Expr::Assign(t, v) => {
+                self.target(t);
+                self.expr(v);
+            }
+            Expr::CompoundAssign(t, _, v) => {
+                self.target(t);
+                self.expr(v);
+            }
+            Expr::Increment(t, _) | Expr::Decrement(t, _) => self.target(t),
+            Expr::BinOp(l, _, r)
+            | Expr::LogicalAnd(l, r)
+            | Expr::LogicalOr(l, r)
+            | Expr::Concat(l, r)
+            | Expr::NullCoalesce(l, r) => {
+                self.expr(l);
+                self.expr(r);
+            }
+            Expr::LogicalNot(x) | Expr::UnaryMinus(x) | Expr::TryVal(x) | Expr::NullFence(x) => {
+                self.expr(x)
+            }
+            Expr::Ternary(c, t, f) => {
+                self.expr(c);
+                self.expr(t);
+                self.expr(f);
+            }
+            Expr::Sprintf(args) => {
+                for a in args {
+                    self.expr(a);
+                }
+            }
+            Expr::FuncCall(name, args) => {
+                self.calls.insert(name.clone());
+                for a in args {
+                    match a {
+                        Expr::Var(v) => {
+                            self.by_ref.insert(v.clone());
+                        }
+                        other => self.expr(other),
+                    }
+                }
+            }
+            Expr::Getline(var, src) => {
+                if let Some(v) = var {
+                    self.target(v);
+                }
+                if let Some(s) = src {
+                    self.expr(s);
+                }
+            }
+            Expr::GetlinePipe(cmd, var) => {
+                if let Some(v) = var {
+                    self.target(v);
+                }
+                self.expr(cmd);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(src: &str) -> Vec<&'static str> {
+        check(src, None, &[])
+            .diagnostics
+            .iter()
+            .map(|d| d.code)
+            .collect()
+    }
+
+    #[test]
+    fn clean_programs_have_no_diagnostics() {
+        assert!(codes("{ n[$1]++ } END { for (k in n) print k, n[k] }").is_empty());
+        assert!(codes("{ split($0, parts, \",\"); print parts[1] }").is_empty());
+        assert!(codes("function f(a) { return a + 1 } { print f($1) }").is_empty());
+        assert!(codes("{ while ((getline line) > 0) print line }").is_empty());
+        assert!(codes("$0 ~ /^a+$/ { total += $2 } END { print total, NR }").is_empty());
+    }
+
+    #[test]
+    fn lints_are_reported() {
+        assert_eq!(codes("{ print x }"), ["uninitialized"]);
+        assert!(
+            check("{ print x }", None, &["x".to_string()])
+                .diagnostics
+                .is_empty()
+        );
+        assert_eq!(
+            codes("function g() { return 1 } { print }"),
+            ["unused-function"]
+        );
+        assert_eq!(codes("/a(/ { print }"), ["invalid-regex"]);
+        assert_eq!(codes("$1 !~ /[z-a]/"), ["invalid-regex"]);
+    }
+
+    #[test]
+    fn json_report_golden() {
+        let report = check("$3 > 10 { print $2, total }", None, &[]);
+        assert_eq!(
+            report.to_json(),
+            concat!(
+                r#"{"ok":true,"diagnostics":[{"severity":"warning","code":"uninitialized","#,
+                r#""message":"variable `total` is read but never assigned","line":null,"col":null}],"#,
+                r#""explain":"where column 3 > 10: select 2, total","#,
+                r#""info":{"needs_fields":true,"needs_nf":false,"emits_output_in_rules":true,"#,
+                r#""max_field":3,"regex_literals":[]}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn errors_set_exit_code() {
+        let bad = check("{ print (", None, &[]);
+        assert_eq!(bad.exit_code(), CHECK_ERROR_EXIT);
+        assert_eq!(bad.diagnostics[0].code, "parse");
+        assert!(bad.diagnostics[0].span.is_some() && bad.info.is_none());
+        assert_eq!(check("{ print x }", None, &[]).exit_code(), 0);
+        assert_eq!(check("/(/", None, &[]).exit_code(), CHECK_ERROR_EXIT);
+    }
+}
@@ -1,5 +1,9 @@
 use std::env;
 use std::process;
+use std::time::Duration;
+
+use crate::action::MAX_CALL_DEPTH_LIMIT;
+use crate::input::encoding::Encoding;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum InputMode {
@@ -10,22 +14,141 @@ pub enum InputMode {
     Parquet,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputMode {
+    Plain,
+    Table,
+    Markdown,
+    Json,
+    Tsv,
+}
+
+/// `--on-error`: what to do with records a reader flags as malformed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnError {
+    Skip,
+    Abort,
+    Passthrough,
+}
+
+/// `--type` for `--join`: which rows of the second (streamed) input to emit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinType {
+    /// Rows with a match, once per matching row of the first input.
+    Inner,
+    /// Every row; unmatched ones padded with empty fields.
+    Left,
+    /// Only rows without a match.
+    Anti,
+}
+
+/// One `-f FILE` or `-e TEXT` program source, in command-line order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgramSource {
+    File(String),
+    /// `-e` text; `index` counts `-e` options from 1.
+    Fragment {
+        index: usize,
+        text: String,
+    },
+}
+
+impl ProgramSource {
+    /// How messages name this source: the path, or `-e #N`.
+    pub fn label(&self) -> String {
+        match self {
+            ProgramSource::File(path) => path.clone(),
+            ProgramSource::Fragment { index, .. } => format!("-e #{index}"),
+        }
+    }
+}
+
+/// `--check` output: diagnostics on stderr, or one JSON document on stdout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckFormat {
+    Text,
+    Json,
+}
+
 #[derive(Debug)]
 pub struct Args {
     pub field_separator: Option<String>,
     pub output_separator: Option<String>,
     pub assignments: Vec<(String, String)>,
+    /// `-v name[k1,k2]=value`: array name, subscripts, value.
+    pub array_assignments: Vec<(String, Vec<String>, String)>,
     pub program: String,
     pub files: Vec<String>,
+    /// Arguments after `--`, as given (FKARGS).
+    pub script_args: Vec<String>,
     pub repl: bool,
     pub input_mode: InputMode,
     pub header_mode: bool,
-    pub program_files: Vec<String>,
+    /// `-y`: start with `IGNORECASE = 1`.
+    pub ignore_case: bool,
+    /// `-w`: `/re/` literals match whole words only.
+    pub word_regexp: bool,
+    pub output_mode: OutputMode,
+    pub table_rows: usize,
+    pub in_place: Option<String>,
+    pub sample: Option<f64>,
+    /// `--every N`: only every Nth record reaches the rules.
+    pub every: Option<u64>,
+    pub timing: bool,
+    /// `--progress`: status line on stderr while records are read.
+    pub progress: bool,
+    pub state: Option<String>,
+    /// `--regex-limit`: compiled-size cap per regex, in bytes.
+    pub regex_limit: Option<usize>,
+    /// `--max-depth`: how deeply user function calls may nest.
+    pub max_depth: Option<usize>,
+    /// `--max-records`: records read before input stops and END runs.
+    pub max_records: Option<u64>,
+    /// `--max-runtime`: run time after which input stops (exit 124).
+    pub max_runtime: Option<Duration>,
+    /// `--max-pipes`: `print | cmd` pipes open at once (0: no limit).
+    pub max_pipes: Option<usize>,
+    /// `--seek`: byte offset to start the first input file at.
+    pub seek: Option<u64>,
+    pub keep_going: bool,
+    /// `--line-buffered`: flush standard output after every print.
+    pub line_buffered: bool,
+    /// `--strict`: printf/sprintf argument-count mismatches and calls past
+    /// `--max-depth` are errors.
+    pub strict: bool,
+    /// `--quiet`: runtime warnings are counted (WARNINGS) but not printed.
+    pub quiet: bool,
+    /// `--emit-header`: write the HDR names before the first print.
+    pub emit_header: bool,
+    /// `--header-vars`: also set each header name as a variable.
+    pub header_vars: bool,
+    /// `--tsv-raw`: keep backslash escapes in TSV fields verbatim.
+    pub tsv_raw: bool,
+    /// `--encoding`: how input without a byte-order mark is decoded.
+    pub encoding: Encoding,
+    /// `--preserve-cr`: keep the `\r` of `\r\n` line ends in `$0`.
+    pub preserve_cr: bool,
+    pub on_error: Option<OnError>,
+    /// `-f` files and `-e` fragments, concatenated in this order.
+    pub program_sources: Vec<ProgramSource>,
     pub describe: bool,
     pub suggest: bool,
+    pub emit_schema: bool,
+    pub validate: bool,
+    pub validate_schema: Option<String>,
     pub highlight: bool,
     pub format: bool,
+    pub check_format: bool,
+    /// `--format-width N` and `--format-align`.
+    pub format_options: crate::format::FormatOptions,
+    /// The program text was read from stdin (`--format -`).
+    pub program_from_stdin: bool,
     pub explain: bool,
+    /// `--dump-tokens`: list the lexer's tokens and exit.
+    pub dump_tokens: bool,
+    /// `--dump-ast`: write the parsed program as an s-expression and exit.
+    pub dump_ast: bool,
+    pub check: Option<CheckFormat>,
 }
 
 pub fn parse_args() -> Args {
@@ -34,29 +157,78 @@ pub fn parse_args() -> Args {
     let mut field_separator: Option<String> = None;
     let mut output_separator: Option<String> = None;
     let mut assignments: Vec<(String, String)> = Vec::new();
+    let mut array_assignments: Vec<(String, Vec<String>, String)> = Vec::new();
     let mut program: Option<String> = None;
     let mut files: Vec<String> = Vec::new();
+    let mut script_args: Vec<String> = Vec::new();
     let mut repl = false;
     let mut input_mode = InputMode::Line;
     let mut header_mode = false;
-    let mut program_files: Vec<String> = Vec::new();
+    let mut ignore_case = false;
+    let mut word_regexp = false;
+    let mut output_mode = OutputMode::Plain;
+    let mut table_rows = crate::output::DEFAULT_MAX_ROWS;
+    let mut in_place: Option<String> = None;
+    let mut sample: Option<f64> = None;
+    let mut every: Option<u64> = None;
+    let mut timing = false;
+    let mut progress = false;
+    let mut state: Option<String> = None;
+    let mut regex_limit: Option<usize> = None;
+    let mut max_depth: Option<usize> = None;
+    let mut max_records: Option<u64> = None;
+    let mut max_pipes: Option<usize> = None;
+    let mut max_runtime: Option<Duration> = None;
+    let mut seek: Option<u64> = None;
+    let mut keep_going = false;
+    let mut strict = false;
+    let mut quiet = false;
+    let mut emit_header = false;
+    let mut header_vars = false;
+    let mut line_buffered = false;
+    // No shell expands patterns for fk on Windows.
+    let mut glob = cfg!(windows);
+    let mut nullglob = false;
+    let mut tsv_raw = false;
+    let mut encoding = Encoding::Auto;
+    let mut preserve_cr = false;
+    let mut on_error: Option<OnError> = None;
+    let mut select: Option<String> = None;
+    let mut where_expr: Option<String> = None;
+    let mut limit: Option<usize> = None;
+    let mut join: Option<String> = None;
+    let mut join_type: Option<JoinType> = None;
+    let mut program_sources: Vec<ProgramSource> = Vec::new();
+    let mut synthesized = false;
     let mut describe = false;
     let mut suggest = false;
+    let mut emit_schema = false;
+    let mut validate = false;
+    let mut validate_schema: Option<String> = None;
     let mut highlight = false;
     let mut format = false;
+    let mut check_format = false;
+    let mut format_options = crate::format::FormatOptions::default();
     let mut explain = false;
+    let mut dump_tokens = false;
+    let mut dump_ast = false;
+    let mut check: Option<CheckFormat> = None;
 
     let mut i = 0;
     while i < args.len() {
         let arg = &args[i];
 
         if arg == "--" {
-            i += 1;
-            // Everything after -- is a file operand
-            while i < args.len() {
-                files.push(args[i].clone());
-                i += 1;
-            }
+            // Everything after -- belongs to the script (FKARGS); its
+            // operands are also input files, its --options and -x
+            // arguments are not.
+            script_args = args[i + 1..].to_vec();
+            let (_, operands) = parse_script_args(&script_args);
+            files.extend(
+                operands
+                    .into_iter()
+                    .filter(|a| !a.starts_with('-') || a == "-"),
+            );
             break;
         }
 
@@ -86,37 +258,200 @@ pub fn parse_args() -> Args {
                 eprintln!("fk: -v requires an argument");
                 process::exit(1);
             }
-            match parse_assignment(&args[i]) {
-                Some(pair) => assignments.push(pair),
+            push_assignment(&args[i], &mut assignments, &mut array_assignments);
+        } else if let Some(rest) = arg.strip_prefix("-v") {
+            push_assignment(rest, &mut assignments, &mut array_assignments);
+        } else if arg == "-f" {
+            i += 1;
+            if i >= args.len() {
+                eprintln!("fk: -f requires an argument");
+                process::exit(1);
+            }
+            program_sources.push(ProgramSource::File(args[i].clone()));
+        } else if arg == "-e" {
+            i += 1;
+            if i >= args.len() {
+                eprintln!("fk: -e requires program text");
+                process::exit(1);
+            }
+            let index = program_sources
+                .iter()
+                .filter(|s| matches!(s, ProgramSource::Fragment { .. }))
+                .count()
+                + 1;
+            program_sources.push(ProgramSource::Fragment {
+                index,
+                text: args[i].clone(),
+            });
+        } else if arg == "--repl" {
+            repl = true;
+        } else if arg == "--timing" {
+            timing = true;
+        } else if arg == "--progress" {
+            progress = true;
+        } else if arg == "--state" {
+            i += 1;
+            if i >= args.len() {
+                eprintln!("fk: --state requires a file argument");
+                process::exit(1);
+            }
+            state = Some(args[i].clone());
+        } else if let Some(path) = arg.strip_prefix("--state=") {
+            state = Some(path.to_string());
+        } else if arg == "--seek" || arg.starts_with("--seek=") {
+            let val = match arg.strip_prefix("--seek=") {
+                Some(v) => Some(v),
                 None => {
-                    eprintln!("fk: invalid -v assignment: {}", args[i]);
+                    i += 1;
+                    args.get(i).map(String::as_str)
+                }
+            };
+            match val.and_then(|v| v.parse::<u64>().ok()) {
+                Some(n) => seek = Some(n),
+                None => {
+                    eprintln!("fk: --seek requires a byte offset");
                     process::exit(1);
                 }
             }
-        } else if let Some(rest) = arg.strip_prefix("-v") {
-            match parse_assignment(rest) {
-                Some(pair) => assignments.push(pair),
+        } else if arg == "--regex-limit" || arg.starts_with("--regex-limit=") {
+            let val = match arg.strip_prefix("--regex-limit=") {
+                Some(v) => Some(v),
                 None => {
-                    eprintln!("fk: invalid -v assignment: {}", rest);
+                    i += 1;
+                    args.get(i).map(String::as_str)
+                }
+            };
+            match val.and_then(parse_size) {
+                Some(n) if n > 0 => regex_limit = Some(n),
+                _ => {
+                    eprintln!("fk: --regex-limit requires a size in bytes (e.g. 10M, 512K)");
                     process::exit(1);
                 }
             }
-        } else if arg == "-f" {
+        } else if arg == "--max-depth" || arg.starts_with("--max-depth=") {
+            let val = match arg.strip_prefix("--max-depth=") {
+                Some(v) => Some(v),
+                None => {
+                    i += 1;
+                    args.get(i).map(String::as_str)
+                }
+            };
+            match val.and_then(|v| v.parse::<usize>().ok()) {
+                Some(n) if (1..=MAX_CALL_DEPTH_LIMIT).contains(&n) => max_depth = Some(n),
+                _ => {
+                    eprintln!(
+                        "fk: --max-depth requires a call depth from 1 to {MAX_CALL_DEPTH_LIMIT}"
+                    );
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--max-records" || arg.starts_with("--max-records=") {
+            let val = match arg.strip_prefix("--max-records=") {
+                Some(v) => Some(v),
+                None => {
+                    i += 1;
+                    args.get(i).map(String::as_str)
+                }
+            };
+            match val.and_then(|v| v.parse::<u64>().ok()) {
+                Some(n) => max_records = Some(n),
+                None => {
+                    eprintln!("fk: --max-records requires a record count");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--max-pipes" || arg.starts_with("--max-pipes=") {
+            let val = match arg.strip_prefix("--max-pipes=") {
+                Some(v) => Some(v),
+                None => {
+                    i += 1;
+                    args.get(i).map(String::as_str)
+                }
+            };
+            match val.and_then(|v| v.parse::<usize>().ok()) {
+                Some(n) => max_pipes = Some(n),
+                None => {
+                    eprintln!("fk: --max-pipes requires a pipe count (0 for no limit)");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--max-runtime" || arg.starts_with("--max-runtime=") {
+            let val = match arg.strip_prefix("--max-runtime=") {
+                Some(v) => Some(v),
+                None => {
+                    i += 1;
+                    args.get(i).map(String::as_str)
+                }
+            };
+            match val.and_then(|v| Duration::try_from_secs_f64(v.parse().ok()?).ok()) {
+                Some(d) => max_runtime = Some(d),
+                None => {
+                    eprintln!("fk: --max-runtime requires a time in seconds (e.g. 30, 0.5)");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--keep-going" {
+            keep_going = true;
+        } else if arg == "--strict" {
+            strict = true;
+        } else if arg == "--quiet" {
+            quiet = true;
+        } else if arg == "--emit-header" {
+            emit_header = true;
+        } else if arg == "--header-vars" {
+            header_vars = true;
+        } else if arg == "--line-buffered" {
+            line_buffered = true;
+        } else if arg == "--glob" {
+            glob = true;
+        } else if arg == "--no-glob" {
+            glob = false;
+        } else if arg == "--nullglob" {
+            glob = true;
+            nullglob = true;
+        } else if arg == "--tsv-raw" {
+            tsv_raw = true;
+        } else if arg == "--encoding" {
             i += 1;
             if i >= args.len() {
-                eprintln!("fk: -f requires an argument");
+                eprintln!("fk: --encoding requires an argument (utf-8, utf-16le, utf-16be)");
                 process::exit(1);
             }
-            program_files.push(args[i].clone());
-        } else if arg == "--repl" {
-            repl = true;
+            encoding = parse_encoding(&args[i]);
+        } else if let Some(name) = arg.strip_prefix("--encoding=") {
+            encoding = parse_encoding(name);
+        } else if arg == "--preserve-cr" {
+            preserve_cr = true;
+        } else if arg == "--on-error" {
+            i += 1;
+            if i >= args.len() {
+                eprintln!("fk: --on-error requires an argument (skip, abort, passthrough)");
+                process::exit(1);
+            }
+            on_error = Some(parse_on_error(&args[i]));
+        } else if let Some(policy) = arg.strip_prefix("--on-error=") {
+            on_error = Some(parse_on_error(policy));
         } else if arg == "--describe" || arg == "-d" {
             describe = true;
         } else if arg == "--suggest" || arg == "-S" {
             describe = true;
             suggest = true;
+        } else if arg == "--emit-schema" {
+            describe = true;
+            emit_schema = true;
+        } else if arg == "--validate" {
+            describe = true;
+            validate = true;
+        } else if let Some(path) = arg.strip_prefix("--validate=") {
+            describe = true;
+            validate = true;
+            validate_schema = Some(path.to_string());
         } else if arg == "-H" || arg == "--header" {
             header_mode = true;
+        } else if arg == "-y" || arg == "--ignore-case" {
+            ignore_case = true;
+        } else if arg == "-w" || arg == "--word-regexp" {
+            word_regexp = true;
         } else if arg == "-i" {
             i += 1;
             if i >= args.len() {
@@ -126,11 +461,94 @@ pub fn parse_args() -> Args {
             input_mode = parse_input_mode(&args[i]);
         } else if arg.starts_with("-i") && arg.len() > 2 {
             input_mode = parse_input_mode(&arg[2..]);
+        } else if arg == "-o" {
+            i += 1;
+            if i >= args.len() {
+                eprintln!("fk: -o requires an argument (table, md, json)");
+                process::exit(1);
+            }
+            output_mode = parse_output_mode(&args[i]);
+        } else if arg.starts_with("-o") && arg.len() > 2 {
+            output_mode = parse_output_mode(&arg[2..]);
+        } else if arg == "-I" || arg == "--in-place" {
+            in_place = Some(String::new());
+        } else if let Some(suffix) = arg.strip_prefix("--in-place=") {
+            in_place = Some(suffix.to_string());
+        } else if arg.starts_with("-I") && arg.len() > 2 {
+            // `-I.bak`, as `sed -i.bak`.
+            in_place = Some(arg[2..].to_string());
+        } else if arg == "--select" || arg == "--where" || arg == "--limit" {
+            i += 1;
+            if i >= args.len() {
+                eprintln!("fk: {} requires an argument", arg);
+                process::exit(1);
+            }
+            set_query_flag(arg, &args[i], &mut select, &mut where_expr, &mut limit);
+        } else if let Some((flag @ ("--select" | "--where" | "--limit"), val)) = arg.split_once('=')
+        {
+            set_query_flag(flag, val, &mut select, &mut where_expr, &mut limit);
+        } else if arg == "--join" || arg == "--type" {
+            i += 1;
+            if i >= args.len() {
+                eprintln!("fk: {} requires an argument", arg);
+                process::exit(1);
+            }
+            if arg == "--join" {
+                join = Some(args[i].clone());
+            } else {
+                join_type = Some(parse_join_type(&args[i]));
+            }
+        } else if let Some(spec) = arg.strip_prefix("--join=") {
+            join = Some(spec.to_string());
+        } else if let Some(kind) = arg.strip_prefix("--type=") {
+            join_type = Some(parse_join_type(kind));
+        } else if arg == "--sample" || arg.starts_with("--sample=") {
+            let val = match arg.strip_prefix("--sample=") {
+                Some(v) => Some(v),
+                None => {
+                    i += 1;
+                    args.get(i).map(String::as_str)
+                }
+            };
+            match val.and_then(|p| p.parse::<f64>().ok()) {
+                Some(p) if p > 0.0 && p <= 1.0 => sample = Some(p),
+                _ => {
+                    eprintln!("fk: --sample requires a probability in (0, 1]");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--every" || arg.starts_with("--every=") {
+            let val = match arg.strip_prefix("--every=") {
+                Some(v) => Some(v),
+                None => {
+                    i += 1;
+                    args.get(i).map(String::as_str)
+                }
+            };
+            match val.and_then(|n| n.parse::<u64>().ok()) {
+                Some(n) if n > 0 => every = Some(n),
+                _ => {
+                    eprintln!("fk: --every requires a positive record count");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--table-rows" {
+            i += 1;
+            match args.get(i).and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) => table_rows = n,
+                None => {
+                    eprintln!("fk: --table-rows requires a row count");
+                    process::exit(1);
+                }
+            }
         } else if arg == "-h" || arg == "--help" {
             print_usage();
             process::exit(0);
-        } else if arg == "--version" {
-            println!("fk {}", env!("CARGO_PKG_VERSION"));
+        } else if arg == "-V" || arg == "--version" {
+            println!("{}", crate::build_info::version_text());
+            process::exit(0);
+        } else if arg == "--list-std" {
+            crate::include::print_std();
             process::exit(0);
         } else if arg == "--hierarchical-menu" {
             print_logo();
@@ -139,8 +557,35 @@ pub fn parse_args() -> Args {
             highlight = true;
         } else if arg == "--format" {
             format = true;
+        } else if arg == "--check-format" {
+            check_format = true;
+        } else if arg == "--format-width" || arg.starts_with("--format-width=") {
+            let val = match arg.strip_prefix("--format-width=") {
+                Some(v) => Some(v),
+                None => {
+                    i += 1;
+                    args.get(i).map(String::as_str)
+                }
+            };
+            match val.and_then(|v| v.parse::<usize>().ok()) {
+                Some(n) if n > 0 => format_options.width = n,
+                _ => {
+                    eprintln!("fk: --format-width requires a positive line width");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--format-align" {
+            format_options.align_assignments = true;
         } else if arg == "--explain" {
             explain = true;
+        } else if arg == "--dump-tokens" {
+            dump_tokens = true;
+        } else if arg == "--dump-ast" {
+            dump_ast = true;
+        } else if arg == "--check" {
+            check = Some(CheckFormat::Text);
+        } else if let Some(fmt) = arg.strip_prefix("--check=") {
+            check = Some(parse_check_format(fmt));
         } else if arg.starts_with('-') && arg.len() > 1 {
             eprintln!("fk: unknown option: {}", arg);
             eprintln!("Try 'fk --help' for usage.");
@@ -154,6 +599,11 @@ pub fn parse_args() -> Args {
         i += 1;
     }
 
+    if emit_header && !header_mode {
+        eprintln!("fk: --emit-header needs -H (the header it writes comes from HDR)");
+        process::exit(2);
+    }
+
     if field_separator.is_some() && input_mode != InputMode::Line {
         eprintln!(
             "fk: -F is ignored when -i {} is set",
@@ -168,22 +618,75 @@ pub fn parse_args() -> Args {
         process::exit(1);
     }
 
-    // -f takes priority; if both -f and inline program given, inline becomes a file arg
-    if !program_files.is_empty() {
-        if let Some(p) = program {
+    // --select / --where / --limit synthesize the program; every positional
+    // argument is then an input file.
+    if select.is_some() || where_expr.is_some() || limit.is_some() {
+        if program_sources
+            .iter()
+            .any(|s| matches!(s, ProgramSource::File(_)))
+        {
+            eprintln!("fk: --select/--where/--limit cannot be combined with -f");
+            process::exit(2);
+        }
+        if let Some(p) = program.take() {
+            if p != "-" && !std::path::Path::new(&p).exists() {
+                eprintln!(
+                    "fk: --select/--where/--limit cannot be combined with a program: {}",
+                    p
+                );
+                process::exit(2);
+            }
             files.insert(0, p);
         }
-        let mut parts = Vec::new();
-        for pf in &program_files {
-            match std::fs::read_to_string(pf) {
-                Ok(contents) => parts.push(contents),
-                Err(e) => {
-                    eprintln!("fk: cannot read program file '{}': {}", pf, e);
-                    process::exit(2);
-                }
+        program = Some(synthesize_program(
+            select.as_deref(),
+            where_expr.as_deref(),
+            limit,
+        ));
+        synthesized = true;
+    }
+
+    // --join synthesizes an NR==FNR program over exactly two inputs.
+    if let Some(spec) = &join {
+        if !program_sources.is_empty() || synthesized {
+            eprintln!("fk: --join cannot be combined with -f/-e or --select/--where/--limit");
+            process::exit(2);
+        }
+        if let Some(p) = program.take() {
+            files.insert(0, p);
+        }
+        if files.len() != 2 {
+            eprintln!("fk: --join needs two inputs: the file to load, then the file to stream");
+            process::exit(2);
+        }
+        match synthesize_join(spec, join_type.unwrap_or(JoinType::Inner), header_mode) {
+            Ok(p) => program = Some(p),
+            Err(e) => {
+                eprintln!("fk: --join: {}", e);
+                process::exit(2);
             }
         }
-        program = Some(parts.join("\n"));
+    } else if join_type.is_some() {
+        eprintln!("fk: --type requires --join");
+        process::exit(2);
+    }
+
+    // -f/-e take priority: an inline program becomes a file arg, while a
+    // --select/--where/--limit rule runs after the fragments.
+    if !program_sources.is_empty() {
+        let mut parts = load_sources(&program_sources);
+        match program.take() {
+            Some(p) if synthesized => parts.push(p),
+            Some(p) => files.insert(0, p),
+            None => {}
+        }
+        // Each source starts in the default namespace, as in gawk.
+        let sep = if parts.iter().any(|p| p.contains("@namespace")) {
+            format!("\n{} ", crate::namespace::RESET_DIRECTIVE)
+        } else {
+            "\n".to_string()
+        };
+        program = Some(parts.join(&sep));
     }
 
     // In describe mode, all positional args are files, not a program (unless --highlight / --format)
@@ -199,7 +702,7 @@ pub fn parse_args() -> Args {
     // as files and default to `{ print }`.
     // Guard: only trigger when the arg looks path-like (contains '/' or '.')
     // to avoid false positives on short programs like `1` or `NR>5`.
-    if program_files.is_empty()
+    if program_sources.is_empty()
         && !describe
         && !repl
         && let Some(ref p) = program
@@ -234,8 +737,31 @@ pub fn parse_args() -> Args {
         }
     }
 
+    if let Some((name, _, _)) = array_assignments
+        .iter()
+        .find(|(name, _, _)| assignments.iter().any(|(n, _)| n == name))
+    {
+        eprintln!("fk: -v assigns {} both as a scalar and as an array", name);
+        process::exit(1);
+    }
+
+    if glob {
+        match crate::fileglob::expand(&files, nullglob) {
+            Ok(expanded) => files = expanded,
+            Err(e) => {
+                eprintln!("fk: {}", e);
+                process::exit(2);
+            }
+        }
+    }
+
+    // The formatter reads the program from stdin when given `-` or nothing.
+    let formatting = format || check_format;
+    let program_from_stdin = formatting && program.as_deref().is_none_or(|p| p == "-");
     let program = match program {
+        Some(p) if formatting && p == "-" => read_stdin_program(),
         Some(p) => p,
+        None if formatting => read_stdin_program(),
         None if repl => String::new(),
         None if describe => String::new(),
         None => {
@@ -248,17 +774,96 @@ pub fn parse_args() -> Args {
         field_separator,
         output_separator,
         assignments,
+        array_assignments,
         program,
         files,
+        script_args,
         repl,
         input_mode,
         header_mode,
-        program_files,
+        ignore_case,
+        word_regexp,
+        output_mode,
+        table_rows,
+        in_place,
+        sample,
+        every,
+        timing,
+        progress,
+        state,
+        regex_limit,
+        max_depth,
+        max_records,
+        max_pipes,
+        max_runtime,
+        seek,
+        keep_going,
+        strict,
+        quiet,
+        emit_header,
+        header_vars,
+        line_buffered,
+        tsv_raw,
+        encoding,
+        preserve_cr,
+        on_error,
+        program_sources,
         describe,
         suggest,
+        emit_schema,
+        validate,
+        validate_schema,
         highlight,
         format,
+        check_format,
+        format_options,
+        program_from_stdin,
         explain,
+        dump_tokens,
+        dump_ast,
+        check,
+    }
+}
+
+/// Read the `-f` files and check the `-e` fragments, in order. Each
+/// fragment must parse on its own, so errors name it (`in -e #2`).
+fn load_sources(sources: &[ProgramSource]) -> Vec<String> {
+    let mut parts = Vec::with_capacity(sources.len());
+    for src in sources {
+        match src {
+            ProgramSource::File(path) => match std::fs::read_to_string(path) {
+                Ok(contents) => parts.push(contents),
+                Err(e) => {
+                    eprintln!("fk: cannot read program file '{}': {}", path, e);
+                    process::exit(2);
+                }
+            },
+            ProgramSource::Fragment { text, .. } => {
+                let checked = crate::lexer::Lexer::new(text)
+                    .tokenize()
+                    .map_err(|e| ("syntax", e))
+                    .and_then(|t| {
+                        let parsed = crate::parser::Parser::new(t).parse();
+                        parsed.map(drop).map_err(|e| ("parse", e))
+                    });
+                if let Err((kind, e)) = checked {
+                    eprintln!("fk: {} error in {}: {}", kind, src.label(), e);
+                    process::exit(2);
+                }
+                parts.push(text.clone());
+            }
+        }
+    }
+    parts
+}
+
+fn read_stdin_program() -> String {
+    match std::io::read_to_string(std::io::stdin()) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("fk: cannot read program from stdin: {}", e);
+            process::exit(2);
+        }
     }
 }
 
@@ -277,14 +882,56 @@ fn print_usage() {
     eprintln!("  -t               Tab-separated output (shorthand for -O '\\t')");
     eprintln!("  -v var=val       Set variable (e.g. -v 'OFS=\\t')");
     eprintln!("  -f progfile      Read program from file (repeatable)");
+    eprintln!("  -e text          Program fragment (repeatable; joined with -f in order)");
     eprintln!("  -i mode          Input mode: csv, tsv, json, parquet");
     eprintln!("  -H               Header mode (skip header, enable $name)");
-    eprintln!("  -d / -S          Describe / suggest mode");
+    eprintln!("  -y               Ignore case in regex matching (--ignore-case; IGNORECASE=1)");
+    eprintln!("  -w               /re/ literals match whole words only (--word-regexp)");
+    eprintln!("  -I[SUFFIX], --in-place[=SUFFIX]  Edit files in place (keep X.SUFFIX backups)");
+    eprintln!("  --sample P       Keep each record with probability P (srand-reproducible)");
+    eprintln!("  --every N        Run the rules on every Nth record only (NR counts all)");
+    eprintln!("  --on-error P     Malformed records: skip, abort or passthrough (raw $0)");
+    eprintln!("  --keep-going     Skip unreadable files, summarize them at exit (exit 4)");
+    eprintln!("  --line-buffered  Flush output after every print (default on a terminal)");
+    eprintln!("  --strict         printf argument-count mismatches and too-deep calls exit 2");
+    eprintln!("  --quiet          Count runtime warnings in WARNINGS without printing them");
+    eprintln!("  --emit-header    With -H, write the header line before the first print");
+    eprintln!("  --header-vars    With -H, also set each column name as a variable (its index)");
+    eprintln!("  --list-std       List the library modules for @include \"std/...\"");
+    eprintln!("  -- args ...      Script arguments (FKARGS, cliargs()); operands are input files");
+    eprintln!("  --glob           Expand *, ?, [..] and ** in file names (default on Windows)");
+    eprintln!("  --nullglob       With --glob, drop patterns that match nothing (else an error)");
+    eprintln!("  --seek N         Start the first file at byte N (next line if N is mid-line)");
+    eprintln!("  --tsv-raw        Keep \\t \\n \\\\ escapes in TSV input fields as written");
+    eprintln!("  --encoding E     Input without a BOM is utf-8 (default), utf-16le or utf-16be");
+    eprintln!("  --preserve-cr    Keep the \\r of CRLF line ends in $0 (fields split without it)");
+    eprintln!("  --timing         Report per-phase times and records/sec on stderr at exit");
+    eprintln!("  --progress       Self-updating records/bytes/ETA line on stderr (terminals only)");
+    eprintln!("  --state FILE     Keep persist_* (and STATE_ARRAYS) arrays across runs");
+    eprintln!("  --regex-limit N  Max compiled size per regex (bytes, K/M/G; default 10M)");
+    eprintln!("  --max-depth N    Max nesting of user function calls (default 200)");
+    eprintln!("  --max-records N  Stop reading after N records, then run END");
+    eprintln!("  --max-runtime S  Stop reading after S seconds, run END, exit 124");
+    eprintln!("  --max-pipes N    Keep at most N print | cmd pipes open (default 16, 0: no limit)");
+    eprintln!("  --select cols    Print these columns (names or numbers, comma-separated)");
+    eprintln!("  --where expr     Only records matching expr (with --select / --limit)");
+    eprintln!("  --limit N        Stop after N output records");
+    eprintln!("  --join on=KEYS   Join two inputs on KEYS (--type inner|left|anti)");
+    eprintln!("  -o mode          Output: table, md, json, tsv (--table-rows N: buffer limit)");
+    eprintln!("  -d / -S          Describe / suggest mode (--emit-schema: JSON schema)");
+    eprintln!("  --validate[=F]   Report cells that break inferred (or F's) column types (exit 5)");
     eprintln!("  --repl           Interactive mode");
     eprintln!("  --highlight      Print syntax-highlighted program to stdout and exit");
     eprintln!("  --format         Pretty-print program (indent, line breaks) and exit");
+    eprintln!("  --check-format   Exit 1 with a diff on stderr if the program is not formatted");
+    eprintln!("  --format-width N Wrap formatted lines past N columns (default 100)");
+    eprintln!("  --format-align   Line up the = of consecutive assignments when formatting");
     eprintln!("  --explain        Print a terse description of the program and exit");
+    eprintln!("  --dump-tokens    List the program's tokens with line:col and exit");
+    eprintln!("  --dump-ast       Print the parsed program as an s-expression and exit");
+    eprintln!("  --check[=json]   Parse and lint the program without reading input (exit 0/2)");
     eprintln!("  -h, --help       Show this help (see also: man fk)");
+    eprintln!("  -V, --version    Show version, git commit and compiled-in features");
     eprintln!();
     eprintln!("  Format auto-detected from .csv/.tsv/.json extensions (+compression).");
     eprintln!("  Files without a program default to '{{ print }}' (OFS-joined fields).");
@@ -372,6 +1019,288 @@ fn parse_input_mode(s: &str) -> InputMode {
     }
 }
 
+fn set_query_flag(
+    flag: &str,
+    val: &str,
+    select: &mut Option<String>,
+    where_expr: &mut Option<String>,
+    limit: &mut Option<usize>,
+) {
+    match flag {
+        "--select" => *select = Some(val.to_string()),
+        "--where" => *where_expr = Some(val.to_string()),
+        _ => match val.parse::<usize>() {
+            Ok(n) => *limit = Some(n),
+            Err(_) => {
+                eprintln!("fk: --limit requires a record count");
+                process::exit(1);
+            }
+        },
+    }
+}
+
+/// Build program text for `--select` / `--where` / `--limit`. The result
+/// goes through the normal lexer and parser, so `--explain` and
+/// `--format` see an ordinary program.
+///
+///   --select 'name,3' --where '$age > 30' --limit 5
+///   → `$age > 30 { print $"name", $3; if (++_n >= 5) exit }`
+pub fn synthesize_program(
+    select: Option<&str>,
+    where_expr: Option<&str>,
+    limit: Option<usize>,
+) -> String {
+    if limit == Some(0) {
+        return "BEGIN { exit }".to_string();
+    }
+    let print = match select {
+        Some(cols) => {
+            let refs: Vec<String> = cols
+                .split(',')
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .map(column_ref)
+                .collect();
+            format!("print {}", refs.join(", "))
+        }
+        None => "print".to_string(),
+    };
+    let action = match limit {
+        Some(n) => format!("{{ {print}; if (++_n >= {n}) exit }}"),
+        None => format!("{{ {print} }}"),
+    };
+    match where_expr {
+        Some(w) if !w.trim().is_empty() => format!("{} {action}", w.trim()),
+        _ => action,
+    }
+}
+
+/// Build the program for `--join 'on=KEYS' SMALL BIG`: the first input is
+/// loaded into arrays keyed by KEYS (comma-separated column refs), the
+/// second is streamed and each row is printed with the matching rows'
+/// fields appended. With `-H` the second header becomes `HDR`, extended by
+/// the first one's names, so `-o json` / `-o table` label every column.
+///
+///   --join 'on=id' --type anti → `NR == FNR { _jn[$"id"]++; next }
+///                                 !($"id" in _jn) { $1 = $1; print }`
+pub fn synthesize_join(spec: &str, kind: JoinType, header: bool) -> Result<String, String> {
+    let keys: Vec<String> = spec
+        .strip_prefix("on=")
+        .unwrap_or(spec)
+        .split(',')
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(column_ref)
+        .collect();
+    if keys.is_empty() {
+        return Err("no key columns (expected on=COL[,COL...])".into());
+    }
+    let key = keys.join(", ");
+    let in_key = if keys.len() == 1 {
+        key.clone()
+    } else {
+        format!("({key})")
+    };
+    let append = kind != JoinType::Anti;
+
+    let mut prog = format!("NR == FNR {{\n    _jn[{key}]++\n");
+    if append {
+        prog.push_str(&format!(
+            "    _jk = {}\n    _jw[_jk, _jn[_jk]] = NF\n    \
+             for (_ji = 1; _ji <= NF; _ji++) _jv[_jk, _jn[_jk], _ji] = $_ji\n    \
+             if (NF > _jx) _jx = NF\n",
+            keys.join(" SUBSEP ")
+        ));
+    }
+    prog.push_str("    next\n}\n");
+    if header {
+        prog.push_str("FNR == 1 {\n");
+        if append {
+            // A first-file name the second header already has (the key,
+            // say) gets a `_2` suffix, so JSON rows have no repeated key.
+            prog.push_str(
+                "    for (_ji = 1; _ji <= _jx; _ji++) _jh[_ji] = HDR[_ji]\n    \
+                 delete HDR\n    \
+                 for (_ji = 1; _ji <= NF; _ji++) { HDR[_ji] = $_ji; HDR[$_ji] = _ji; _jb[$_ji] = 1 }\n    \
+                 for (_ji = 1; _ji <= _jx; _ji++) {\n        \
+                 _jc = _jh[_ji]\n        \
+                 for (_jd = 2; _jc in _jb; _jd++) _jc = _jh[_ji] \"_\" _jd\n        \
+                 _jb[_jc] = 1; HDR[NF + _ji] = _jc; HDR[_jc] = NF + _ji\n    }\n    next\n}\n",
+            );
+        } else {
+            prog.push_str(
+                "    delete HDR\n    \
+                 for (_ji = 1; _ji <= NF; _ji++) { HDR[_ji] = $_ji; HDR[$_ji] = _ji }\n    next\n}\n",
+            );
+        }
+    }
+    match kind {
+        // Rebuild $0 so unmatched rows use OFS like joined ones.
+        JoinType::Anti => prog.push_str(&format!("!({in_key} in _jn) {{ $1 = $1; print }}\n")),
+        JoinType::Inner | JoinType::Left => {
+            prog.push_str(&format!(
+                "{{\n    _jm = NF\n    _jk = {}\n    if (_jk in _jn) {{\n        \
+                 for (_jr = 1; _jr <= _jn[_jk]; _jr++) {{\n            \
+                 for (_ji = 1; _ji <= _jw[_jk, _jr]; _ji++) $(_jm + _ji) = _jv[_jk, _jr, _ji]\n            \
+                 print\n            NF = _jm\n        }}\n    }}",
+                keys.join(" SUBSEP ")
+            ));
+            if kind == JoinType::Left {
+                prog.push_str(
+                    " else {\n        if (_jx) $(_jm + _jx) = \"\"\n        print\n    }",
+                );
+            }
+            prog.push_str("\n}\n");
+        }
+    }
+    Ok(prog)
+}
+
+/// `3` → `$3`, `$x` stays as written, any other name → `$"name"`.
+fn column_ref(col: &str) -> String {
+    if col.starts_with('$') {
+        col.to_string()
+    } else if col.bytes().all(|b| b.is_ascii_digit()) {
+        format!("${col}")
+    } else {
+        format!("$\"{}\"", col.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+fn parse_output_mode(s: &str) -> OutputMode {
+    match s {
+        "table" => OutputMode::Table,
+        "md" | "markdown" => OutputMode::Markdown,
+        "json" => OutputMode::Json,
+        "tsv" => OutputMode::Tsv,
+        "plain" => OutputMode::Plain,
+        other => {
+            eprintln!("fk: unknown output mode: {}", other);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_join_type(s: &str) -> JoinType {
+    match s {
+        "inner" => JoinType::Inner,
+        "left" => JoinType::Left,
+        "anti" => JoinType::Anti,
+        other => {
+            eprintln!("fk: unknown --type: {} (inner, left, anti)", other);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_check_format(s: &str) -> CheckFormat {
+    match s {
+        "text" => CheckFormat::Text,
+        "json" => CheckFormat::Json,
+        other => {
+            eprintln!("fk: unknown --check format: {} (text, json)", other);
+            process::exit(1);
+        }
+    }
+}
+
+/// `1048576`, `512K`, `10M` or `1G` (binary units) → bytes.
+pub(crate) fn parse_size(s: &str) -> Option<usize> {
+    let (digits, shift) = match s.as_bytes().last()?.to_ascii_uppercase() {
+        b'K' => (&s[..s.len() - 1], 10),
+        b'M' => (&s[..s.len() - 1], 20),
+        b'G' => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(1 << shift)
+}
+
+fn parse_encoding(s: &str) -> Encoding {
+    Encoding::parse(s).unwrap_or_else(|| {
+        eprintln!("fk: unknown encoding: {} (utf-8, utf-16le or utf-16be)", s);
+        process::exit(1);
+    })
+}
+
+fn parse_on_error(s: &str) -> OnError {
+    match s {
+        "skip" => OnError::Skip,
+        "abort" => OnError::Abort,
+        "passthrough" => OnError::Passthrough,
+        other => {
+            eprintln!("fk: unknown --on-error policy: {}", other);
+            process::exit(1);
+        }
+    }
+}
+
+/// Split script arguments (those after `--`) into options and operands:
+/// `--name=value`, `--name value` (when the next argument does not start
+/// with `--`) and `--flag` (value "1"), so a flag followed by a file
+/// takes the file as its value: put flags last or write `--flag=1`.
+/// `-x` and `-` are operands; a second `--` makes the rest operands.
+/// Shared by the CLI, which reads the operands other than `-x` as input
+/// files, and `cliargs()`.
+pub fn parse_script_args(args: &[String]) -> (Vec<(String, String)>, Vec<String>) {
+    let mut options = Vec::new();
+    let mut operands = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+        if arg == "--" {
+            operands.extend_from_slice(&args[i..]);
+            break;
+        }
+        let Some(name) = arg.strip_prefix("--") else {
+            operands.push(arg.clone());
+            continue;
+        };
+        if let Some((name, value)) = name.split_once('=') {
+            options.push((name.to_string(), value.to_string()));
+        } else if let Some(value) = args.get(i).filter(|v| !v.starts_with("--")) {
+            options.push((name.to_string(), value.clone()));
+            i += 1;
+        } else {
+            options.push((name.to_string(), "1".to_string()));
+        }
+    }
+    (options, operands)
+}
+
+/// Parse one `-v` argument into `assignments` or `array_assignments`, or
+/// exit with an error naming it.
+fn push_assignment(
+    s: &str,
+    assignments: &mut Vec<(String, String)>,
+    array_assignments: &mut Vec<(String, Vec<String>, String)>,
+) {
+    let is_element = s.find('=').is_some_and(|eq| s[..eq].contains('['));
+    let parsed = if is_element {
+        parse_array_assignment(s).map(|elem| array_assignments.push(elem))
+    } else {
+        parse_assignment(s).map(|pair| assignments.push(pair))
+    };
+    if parsed.is_none() {
+        eprintln!("fk: invalid -v assignment: {}", s);
+        process::exit(1);
+    }
+}
+
+/// `name[key]=value`: the key runs to the first `]=` and is taken
+/// literally, except that commas separate subscripts (joined with SUBSEP).
+fn parse_array_assignment(s: &str) -> Option<(String, Vec<String>, String)> {
+    let open = s.find('[')?;
+    let close = open + s[open..].find("]=")?;
+    let name = &s[..open];
+    if !is_valid_ident(name) {
+        return None;
+    }
+    let subscripts = s[open + 1..close].split(',').map(str::to_string).collect();
+    let value = interpret_escapes(&s[close + 2..]);
+    Some((name.to_string(), subscripts, value))
+}
+
 fn parse_assignment(s: &str) -> Option<(String, String)> {
     let eq = s.find('=')?;
     if eq == 0 {
@@ -564,4 +1493,93 @@ mod tests {
         assert!(!is_generator_call("tolower($1)"));
         assert!(!is_generator_call("substr($0,1,5)"));
     }
+
+    #[test]
+    fn sources_are_labelled() {
+        assert_eq!(ProgramSource::File("lib.awk".into()).label(), "lib.awk");
+        let frag = ProgramSource::Fragment {
+            index: 2,
+            text: "$3 > 10".into(),
+        };
+        assert_eq!(frag.label(), "-e #2");
+    }
+
+    #[test]
+    fn array_assignments_split_on_the_first_closing_bracket() {
+        let elem = |s: &str| parse_array_assignment(s);
+        let parts = |name: &str, keys: &[&str], value: &str| {
+            let keys = keys.iter().map(|k| k.to_string()).collect();
+            Some((name.to_string(), keys, value.to_string()))
+        };
+        assert_eq!(elem("map[a]=1"), parts("map", &["a"], "1"));
+        assert_eq!(elem("map[a=b]=x=y"), parts("map", &["a=b"], "x=y"));
+        assert_eq!(elem("m[k1,k2]=\\t"), parts("m", &["k1", "k2"], "\t"));
+        assert_eq!(elem("m[]="), parts("m", &[""], ""));
+        assert_eq!(elem("m[a]b=1"), None);
+        assert_eq!(elem("1m[a]=1"), None);
+        assert_eq!(elem("[a]=1"), None);
+    }
+
+    #[test]
+    fn sizes_take_binary_suffixes() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("512K"), Some(512 << 10));
+        assert_eq!(parse_size("10m"), Some(10 << 20));
+        assert_eq!(parse_size("1G"), Some(1 << 30));
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("ten"), None);
+    }
+
+    #[test]
+    fn synthesized_programs() {
+        assert_eq!(
+            synthesize_program(Some("name, 3"), Some("$age > 30"), Some(5)),
+            "$age > 30 { print $\"name\", $3; if (++_n >= 5) exit }"
+        );
+        assert_eq!(
+            synthesize_program(None, Some("NR > 1"), None),
+            "NR > 1 { print }"
+        );
+        assert_eq!(
+            synthesize_program(Some("$2"), None, Some(0)),
+            "BEGIN { exit }"
+        );
+    }
+
+    #[test]
+    fn synthesized_joins_parse_and_round_trip() {
+        for kind in [JoinType::Inner, JoinType::Left, JoinType::Anti] {
+            for header in [false, true] {
+                let src = synthesize_join("on=$1,user id", kind, header).unwrap();
+                assert!(src.starts_with("NR == FNR {\n    _jn[$1, $\"user id\"]++"));
+                let once = crate::format::format_program(&src).unwrap();
+                let twice = crate::format::format_program(&once).unwrap();
+                assert_eq!(once, twice);
+            }
+        }
+        assert!(synthesize_join("on=", JoinType::Inner, false).is_err());
+    }
+
+    #[test]
+    fn synthesized_program_round_trips() {
+        let src = synthesize_program(Some("name,salary"), Some("$salary > 80000"), Some(2));
+        let once = crate::format::format_program(&src).unwrap();
+        let twice = crate::format::format_program(&once).unwrap();
+        assert_eq!(once, twice);
+    }
+
+    #[test]
+    fn script_args_split_into_options_and_operands() {
+        let args: Vec<String> = "--top 5 a.csv --sep=; - --verbose --dry -- --raw"
+            .split(' ')
+            .map(String::from)
+            .collect();
+        let (options, operands) = parse_script_args(&args);
+        let pairs = [("top", "5"), ("sep", ";"), ("verbose", "1"), ("dry", "1")];
+        assert_eq!(options, pairs.map(|(n, v)| (n.to_string(), v.to_string())));
+        assert_eq!(operands, ["a.csv", "-", "--raw"]);
+        let (options, operands) = parse_script_args(&["--last".to_string()]);
+        assert_eq!(options, [("last".to_string(), "1".to_string())]);
+        assert!(operands.is_empty());
+    }
 }
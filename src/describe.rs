@@ -51,6 +51,25 @@ impl ColType {
             ColType::String => "string",
         }
     }
+
+    /// Parse a type name from a `--validate` schema file.
+    fn from_label(s: &str) -> Option<ColType> {
+        match s.to_ascii_lowercase().as_str() {
+            "int" | "integer" => Some(ColType::Int),
+            "float" | "number" => Some(ColType::Float),
+            "string" | "str" => Some(ColType::String),
+            _ => None,
+        }
+    }
+
+    /// Whether a non-empty cell conforms to this type.
+    fn accepts(&self, v: &str) -> bool {
+        match self {
+            ColType::Int => v.parse::<i64>().is_ok(),
+            ColType::Float => v.parse::<f64>().is_ok(),
+            ColType::String => true,
+        }
+    }
 }
 
 /// Schema inferred from the input.
@@ -64,11 +83,12 @@ pub struct Schema {
     pub sample_rows: Vec<Vec<String>>,
 }
 
-/// Read up to `limit` lines from a reader.
-fn read_lines(reader: &mut dyn BufRead, limit: usize) -> Vec<String> {
+/// Read up to `limit` lines from a reader, leaving out blank lines and
+/// those starting with `comment`.
+fn read_lines(reader: &mut dyn BufRead, limit: usize, comment: Option<char>) -> Vec<String> {
     let mut lines = Vec::new();
     let mut buf = String::new();
-    for _ in 0..limit {
+    while lines.len() < limit {
         buf.clear();
         match reader.read_line(&mut buf) {
             Ok(0) => break,
@@ -77,7 +97,7 @@ fn read_lines(reader: &mut dyn BufRead, limit: usize) -> Vec<String> {
                     .trim_end_matches('\n')
                     .trim_end_matches('\r')
                     .to_string();
-                if !line.is_empty() {
+                if !line.is_empty() && (comment.is_none() || line.chars().next() != comment) {
                     lines.push(line);
                 }
             }
@@ -316,7 +336,12 @@ fn infer_type(values: &[String]) -> ColType {
 
 /// Sniff input and produce a Schema.
 pub fn sniff(reader: &mut dyn BufRead) -> Schema {
-    let lines = read_lines(reader, 100);
+    sniff_with(reader, None)
+}
+
+/// Sniff input, skipping lines that start with `comment` (`CSVCOMMENT`).
+pub fn sniff_with(reader: &mut dyn BufRead, comment: Option<char>) -> Schema {
+    let lines = read_lines(reader, 100, comment);
     let format = detect_format(&lines);
 
     if format == Format::Json {
@@ -518,6 +543,16 @@ fn truncate(s: &str, max: usize) -> String {
 
 /// Open a file, decompressing transparently if needed.
 /// Returns a boxed reader and whether decompression was used.
+/// Open `path` like `open_maybe_compressed`, following a BOM so a
+/// UTF-16 file is sniffed as text.
+fn open_decoded(path: &str) -> io::Result<Box<dyn Read + Send>> {
+    let (reader, _) = crate::input::encoding::decode(
+        open_maybe_compressed(path)?,
+        crate::input::encoding::Encoding::Auto,
+    )?;
+    Ok(reader)
+}
+
 pub fn open_maybe_compressed(path: &str) -> io::Result<Box<dyn Read + Send>> {
     let (cmd, args): (&str, &[&str]) = if path.ends_with(".gz") || path.ends_with(".tgz") {
         ("gzip", &["-dc", path])
@@ -802,22 +837,32 @@ fn suggest_cmd(flags: &str, file_part: &str, program: &str, why: &str) {
 }
 
 /// Run describe mode: sniff the input and print schema + suggestions.
-pub fn run_describe(files: &[String], suggest: bool) {
+/// With `emit_schema`, print the inferred schema as JSON to stdout instead.
+/// Lines starting with `comment` are left out of the sample.
+pub fn run_describe(files: &[String], suggest: bool, emit_schema: bool, comment: Option<char>) {
     if files.is_empty() {
         let stdin = io::stdin();
         let mut reader = BufReader::new(stdin.lock());
-        let schema = sniff(&mut reader);
+        let schema = sniff_with(&mut reader, comment);
+        if emit_schema {
+            print!("{}", schema_json(&schema));
+            return;
+        }
         print_description(&schema, None);
         if suggest {
             print_suggest(&schema, "");
         }
     } else {
         for path in files {
-            if files.len() > 1 {
+            if files.len() > 1 && !emit_schema {
                 eprintln!("  \x1b[1m{}:\x1b[0m", path);
             }
+            if format_from_extension(path) == Some(Format::Parquet) {
+                describe_parquet(path, suggest, emit_schema);
+                continue;
+            }
 
-            let file_reader: Box<dyn Read> = match open_maybe_compressed(path) {
+            let file_reader: Box<dyn Read> = match open_decoded(path) {
                 Ok(r) => r,
                 Err(e) => {
                     eprintln!("fk: {}", e);
@@ -825,7 +870,11 @@ pub fn run_describe(files: &[String], suggest: bool) {
                 }
             };
             let mut reader = BufReader::new(file_reader);
-            let schema = sniff(&mut reader);
+            let schema = sniff_with(&mut reader, comment);
+            if emit_schema {
+                print!("{}", schema_json(&schema));
+                continue;
+            }
             print_description(&schema, None);
             if suggest {
                 print_suggest(&schema, path);
@@ -833,3 +882,425 @@ pub fn run_describe(files: &[String], suggest: bool) {
         }
     }
 }
+
+/// Describe a Parquet file from its footer: schema, row count, row
+/// groups, sizes and min/max statistics, without decoding a data page.
+#[cfg(feature = "parquet")]
+fn describe_parquet(path: &str, suggest: bool, emit_schema: bool) {
+    let meta = match crate::input::parquet_reader::read_parquet_metadata(path) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let schema = Schema {
+        format: Format::Parquet,
+        has_header: true,
+        columns: meta.columns.iter().map(|c| c.name.clone()).collect(),
+        types: meta.columns.iter().map(|c| c.col_type).collect(),
+        total_rows: meta.rows as usize,
+        sample_rows: Vec::new(),
+    };
+    if emit_schema {
+        print!("{}", schema_json(&schema));
+        return;
+    }
+    print_parquet_description(&meta);
+    if suggest {
+        print_suggest(&schema, path);
+    }
+}
+
+#[cfg(not(feature = "parquet"))]
+fn describe_parquet(_path: &str, _suggest: bool, _emit_schema: bool) {
+    eprintln!("fk: parquet support not compiled in. Rebuild with: cargo build --features parquet");
+}
+
+#[cfg(feature = "parquet")]
+fn print_parquet_description(meta: &crate::input::parquet_reader::ParquetMeta) {
+    use crate::timing::short_bytes;
+
+    eprintln!();
+    eprint!("  \x1b[1mformat:\x1b[0m parquet");
+    eprint!("  \x1b[1mcolumns:\x1b[0m {}", meta.columns.len());
+    eprint!("  \x1b[1mrows:\x1b[0m {}", meta.rows);
+    eprint!("  \x1b[1mrow groups:\x1b[0m {}", meta.row_groups);
+    eprint!(
+        "  \x1b[1msize:\x1b[0m {} ({} uncompressed)",
+        short_bytes(meta.compressed),
+        short_bytes(meta.uncompressed)
+    );
+    eprintln!();
+    eprintln!();
+
+    let name_width = meta
+        .columns
+        .iter()
+        .map(|c| c.name.len())
+        .max()
+        .unwrap_or(4)
+        .max(6);
+    let stored: Vec<String> = meta
+        .columns
+        .iter()
+        .map(|c| match &c.logical {
+            Some(l) => format!("{} {}", c.physical, l),
+            None => c.physical.clone(),
+        })
+        .collect();
+    let stored_width = stored.iter().map(|s| s.len()).max().unwrap_or(0).max(6);
+    let show = |v: &Option<String>| v.as_deref().map_or("-".to_string(), |v| truncate(v, 20));
+    let mins: Vec<String> = meta.columns.iter().map(|c| show(&c.min)).collect();
+    let min_width = mins
+        .iter()
+        .map(|s| s.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(3);
+    eprintln!(
+        "  \x1b[90m{:<4}  {:<nw$}  {:<6}  {:<sw$}  {:<mw$}  max\x1b[0m",
+        "#",
+        "column",
+        "type",
+        "stored",
+        "min",
+        nw = name_width,
+        sw = stored_width,
+        mw = min_width
+    );
+    eprintln!(
+        "  \x1b[90m{}  {}  {}  {}  {}  {}\x1b[0m",
+        "─".repeat(4),
+        "─".repeat(name_width),
+        "─".repeat(6),
+        "─".repeat(stored_width),
+        "─".repeat(min_width),
+        "─".repeat(20)
+    );
+    for (i, col) in meta.columns.iter().enumerate() {
+        let type_color = match col.col_type {
+            ColType::Int | ColType::Float => "\x1b[33m",
+            ColType::String => "\x1b[36m",
+        };
+        eprintln!(
+            "  {:<4}  {:<nw$}  {}{:<6}\x1b[0m  \x1b[90m{:<sw$}\x1b[0m  {:<mw$}  {}",
+            i + 1,
+            col.name,
+            type_color,
+            col.col_type.label(),
+            stored[i],
+            mins[i],
+            show(&col.max),
+            nw = name_width,
+            sw = stored_width,
+            mw = min_width
+        );
+    }
+    eprintln!();
+}
+
+/// Render a schema as `--validate` input: a JSON object of column → type.
+pub fn schema_json(schema: &Schema) -> String {
+    let mut out = String::from("{\n");
+    for (i, col) in schema.columns.iter().enumerate() {
+        let typ = schema.types.get(i).unwrap_or(&ColType::String);
+        out.push_str("  ");
+        crate::output::json_string(col, &mut out);
+        out.push_str(&format!(": \"{}\"", typ.label()));
+        if i + 1 < schema.columns.len() {
+            out.push(',');
+        }
+        out.push('\n');
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Parse a `--validate` schema file: a flat JSON object of name → type.
+pub fn parse_schema(text: &str) -> Result<Vec<(String, ColType)>, String> {
+    let body = text
+        .trim()
+        .strip_prefix('{')
+        .and_then(|t| t.strip_suffix('}'))
+        .ok_or("schema must be a JSON object")?;
+    let mut strings = Vec::new();
+    let mut chars = body.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some(c) => s.push(c),
+                            None => return Err("unterminated string".into()),
+                        },
+                        Some(c) => s.push(c),
+                        None => return Err("unterminated string".into()),
+                    }
+                }
+                strings.push(s);
+            }
+            ':' | ',' => {}
+            c if c.is_whitespace() => {}
+            c => return Err(format!("unexpected '{c}' (types must be strings)")),
+        }
+    }
+    if strings.len() % 2 != 0 {
+        return Err("expected \"name\": \"type\" pairs".into());
+    }
+    strings
+        .chunks(2)
+        .map(|pair| match ColType::from_label(&pair[1]) {
+            Some(t) => Ok((pair[0].clone(), t)),
+            None => Err(format!(
+                "column \"{}\": unknown type \"{}\" (int, float, string)",
+                pair[0], pair[1]
+            )),
+        })
+        .collect()
+}
+
+/// Data rows used to infer column types when `--validate` has no schema file.
+const VALIDATE_INFER_ROWS: usize = 10;
+/// Violations reported per column; the rest are only counted.
+const VALIDATE_MAX_PER_COLUMN: usize = 5;
+/// Exit status of `--validate` when any cell violates the schema.
+pub const VALIDATE_EXIT: i32 = 5;
+
+/// One column check: the field it reads, its name, the expected type and
+/// how many cells have failed it so far.
+struct ColumnCheck {
+    col: usize,
+    name: String,
+    typ: ColType,
+    failed: usize,
+}
+
+/// Check every data record of one input against `declared` (or against
+/// types inferred from the first `VALIDATE_INFER_ROWS` records). Records
+/// come from `records`, the reader `-i` selects, so CSV quoting holds; with
+/// `header` the first record names the columns (keyed JSON names its own).
+/// Only the sample and the per-column counts are kept while the rest
+/// streams. Returns the violation count and the report lines:
+/// `FILE:FNR: column: expected TYPE, got "value"`.
+pub fn validate(
+    reader: &mut dyn BufRead,
+    records: &mut dyn crate::input::RecordReader,
+    file: &str,
+    header: bool,
+    declared: Option<&[(String, ColType)]>,
+) -> io::Result<(usize, Vec<String>)> {
+    let mut report = Vec::new();
+    let mut columns: Vec<String> = Vec::new();
+    let mut sample: Vec<(u64, Vec<String>)> = Vec::new();
+    let mut checks: Option<Vec<ColumnCheck>> = None;
+    let mut rec = crate::input::Record::default();
+    let mut fnr: u64 = 0;
+    while records.read_into(reader, &mut rec)? {
+        fnr += 1;
+        let fresh = records.new_columns();
+        let keyed = !fresh.is_empty();
+        columns.extend(fresh);
+        let fields = match rec.fields.take() {
+            Some(f) => f,
+            None => rec.text.split_whitespace().map(str::to_string).collect(),
+        };
+        if header && fnr == 1 && !keyed {
+            columns = fields;
+            continue;
+        }
+        match checks.as_mut() {
+            Some(checks) => check_record(checks, file, fnr, &fields, &mut report),
+            None => {
+                sample.push((fnr, fields));
+                if sample.len() == VALIDATE_INFER_ROWS {
+                    let mut built = column_checks(&columns, &sample, file, declared, &mut report);
+                    for (n, fields) in sample.drain(..) {
+                        check_record(&mut built, file, n, &fields, &mut report);
+                    }
+                    checks = Some(built);
+                }
+            }
+        }
+    }
+    let checks = match checks {
+        Some(checks) => checks,
+        None if sample.is_empty() => return Ok((0, report)),
+        None => {
+            let mut built = column_checks(&columns, &sample, file, declared, &mut report);
+            for (n, fields) in &sample {
+                check_record(&mut built, file, *n, fields, &mut report);
+            }
+            built
+        }
+    };
+    for c in &checks {
+        if c.failed > VALIDATE_MAX_PER_COLUMN {
+            report.push(format!(
+                "fk: {file}: {}: {} more violation(s) not shown",
+                c.name,
+                c.failed - VALIDATE_MAX_PER_COLUMN
+            ));
+        }
+    }
+    Ok((checks.iter().map(|c| c.failed).sum(), report))
+}
+
+/// The checks for one input: the declared columns, matched by header name
+/// or `$N`, or every column with its type inferred from `sample`.
+fn column_checks(
+    columns: &[String],
+    sample: &[(u64, Vec<String>)],
+    file: &str,
+    declared: Option<&[(String, ColType)]>,
+    report: &mut Vec<String>,
+) -> Vec<ColumnCheck> {
+    let check = |col: usize, name: &str, typ: ColType| ColumnCheck {
+        col,
+        name: name.to_string(),
+        typ,
+        failed: 0,
+    };
+    match declared {
+        Some(decl) => {
+            let mut checks = Vec::new();
+            for (name, typ) in decl {
+                let pos = columns.iter().position(|c| c == name).or_else(|| {
+                    name.strip_prefix('$')
+                        .and_then(|n| n.parse::<usize>().ok())
+                        .filter(|&n| n >= 1)
+                        .map(|n| n - 1)
+                });
+                match pos {
+                    Some(i) => checks.push(check(i, name, *typ)),
+                    None => report.push(format!("fk: {file}: no column \"{name}\"")),
+                }
+            }
+            checks
+        }
+        None => {
+            let width = sample
+                .iter()
+                .map(|(_, f)| f.len())
+                .chain(std::iter::once(columns.len()))
+                .max()
+                .unwrap_or(0);
+            (0..width)
+                .map(|i| {
+                    let values: Vec<String> = sample
+                        .iter()
+                        .map(|(_, f)| f.get(i).cloned().unwrap_or_default())
+                        .collect();
+                    let name = columns
+                        .get(i)
+                        .cloned()
+                        .unwrap_or_else(|| format!("${}", i + 1));
+                    check(i, &name, infer_type(&values))
+                })
+                .collect()
+        }
+    }
+}
+
+/// Check one record's cells, counting failures and reporting the first
+/// `VALIDATE_MAX_PER_COLUMN` of each column.
+fn check_record(
+    checks: &mut [ColumnCheck],
+    file: &str,
+    fnr: u64,
+    fields: &[String],
+    report: &mut Vec<String>,
+) {
+    for c in checks {
+        let v = fields.get(c.col).map_or("", String::as_str);
+        if v.is_empty() || c.typ.accepts(v) {
+            continue;
+        }
+        c.failed += 1;
+        if c.failed <= VALIDATE_MAX_PER_COLUMN {
+            report.push(format!(
+                "{file}:{fnr}: {}: expected {}, got \"{v}\"",
+                c.name,
+                c.typ.label()
+            ));
+        }
+    }
+}
+
+/// Run `--validate[=schema.json]` over the inputs, reporting violations on
+/// stderr. Records are read with a fresh reader from `make_reader` per
+/// input, the one `-i` selects; `header` is `-H`. Returns the exit status:
+/// 0 clean, 2 on I/O or schema errors, `VALIDATE_EXIT` when any cell
+/// violates its column type.
+pub fn run_validate(
+    files: &[String],
+    schema_path: Option<&str>,
+    header: bool,
+    make_reader: &dyn Fn() -> Box<dyn crate::input::RecordReader>,
+) -> i32 {
+    let declared = match schema_path {
+        Some(path) => {
+            let parsed = std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| parse_schema(&text));
+            match parsed {
+                Ok(d) => Some(d),
+                Err(e) => {
+                    eprintln!("fk: schema '{}': {}", path, e);
+                    return 2;
+                }
+            }
+        }
+        None => None,
+    };
+
+    let stdin = ["-".to_string()];
+    let inputs = if files.is_empty() { &stdin[..] } else { files };
+    let mut violations = 0;
+    let mut failed = false;
+    for path in inputs {
+        let mut reader: Box<dyn BufRead> = if path == "-" {
+            Box::new(BufReader::new(io::stdin()))
+        } else {
+            match open_decoded(path) {
+                Ok(r) => Box::new(BufReader::new(r)),
+                Err(e) => {
+                    eprintln!("fk: {}", e);
+                    failed = true;
+                    continue;
+                }
+            }
+        };
+        let mut records = make_reader();
+        match validate(
+            &mut reader,
+            records.as_mut(),
+            path,
+            header,
+            declared.as_deref(),
+        ) {
+            Ok((n, report)) => {
+                for line in report {
+                    eprintln!("{line}");
+                }
+                violations += n;
+            }
+            Err(e) => {
+                eprintln!("fk: {}: {}", path, e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        2
+    } else if violations > 0 {
+        VALIDATE_EXIT
+    } else {
+        0
+    }
+}
@@ -14,6 +14,10 @@ pub struct ExplainContext {
     pub field_sep: Option<String>,
     pub files: Vec<String>,
     pub budget: usize,
+    /// `-y`: regexes ignore case.
+    pub ignore_case: bool,
+    /// `-w`: regex literals match whole words.
+    pub word_regexp: bool,
 }
 
 impl Default for ExplainContext {
@@ -25,6 +29,8 @@ impl Default for ExplainContext {
             field_sep: None,
             files: Vec::new(),
             budget: DEFAULT_BUDGET,
+            ignore_case: false,
+            word_regexp: false,
         }
     }
 }
@@ -61,6 +67,8 @@ impl ExplainContext {
             field_sep: field_sep.map(|s| s.to_string()),
             files: filenames,
             budget: DEFAULT_BUDGET,
+            ignore_case: false,
+            word_regexp: false,
         }
     }
 
@@ -86,6 +94,12 @@ impl ExplainContext {
             };
             parts.push(format!("delim: {display}"));
         }
+        if self.ignore_case {
+            parts.push("ignore case".into());
+        }
+        if self.word_regexp {
+            parts.push("whole words".into());
+        }
         match self.files.len() {
             0 => {}
             1 => parts.push(self.files[0].clone()),
@@ -120,7 +134,33 @@ pub fn explain(program: &Program, ctx: Option<&ExplainContext>) -> String {
 
 // ── Single-pass pattern detection ───────────────────────────────
 
+/// The main action, then where the program reads and writes besides its
+/// input and stdout: getline sources as trailing phrases, output files
+/// and pipes after an arrow (`select column 1 → per-key files`).
 fn describe(program: &Program, info: &ProgramInfo, budget: usize) -> String {
+    let effects = collect_effects(program, &info.var_sources);
+    let outputs = if effects.outputs.is_empty() {
+        String::new()
+    } else {
+        format!(" → {}", effects.outputs.join(", "))
+    };
+    let room = budget.saturating_sub(char_len(&outputs));
+    let base = describe_action(program, info, effects.inputs, room);
+    if outputs.is_empty() || char_len(&base) > room {
+        return base;
+    }
+    if base.is_empty() {
+        return outputs.trim_start_matches(" → ").to_string();
+    }
+    base + &outputs
+}
+
+fn describe_action(
+    program: &Program,
+    info: &ProgramInfo,
+    inputs: Vec<String>,
+    budget: usize,
+) -> String {
     let vs = &info.var_sources;
 
     if let Some(s) = try_dedup(program) {
@@ -168,8 +208,21 @@ fn describe(program: &Program, info: &ProgramInfo, budget: usize) -> String {
     if phrases.is_empty() {
         detect_fallback(program, vs, &mut phrases);
     }
+    join_with_inputs(phrases, inputs, budget)
+}
 
-    budget_join(&phrases, budget)
+/// Join the action's phrases and its getline sources within `budget`. The
+/// sources stay whole while the action keeps a few characters, so a long
+/// action is shortened rather than hiding where the program reads.
+fn join_with_inputs(mut phrases: Vec<String>, inputs: Vec<String>, budget: usize) -> String {
+    const MIN_ACTION: usize = 6;
+    let reads = inputs.join(", ");
+    let room = budget.saturating_sub(char_len(&reads) + 2);
+    if phrases.is_empty() || inputs.is_empty() || room < MIN_ACTION {
+        phrases.extend(inputs);
+        return budget_join(&phrases, budget);
+    }
+    format!("{}, {reads}", budget_join(&phrases, room))
 }
 
 // ── Whole-program idioms ────────────────────────────────────────
@@ -218,7 +271,8 @@ fn try_join(program: &Program) -> Option<String> {
         }
     });
 
-    let second = &program.rules[1];
+    // The streaming rule comes last (`--join -H` puts a header rule between).
+    let second = program.rules.last()?;
     let kind = match &second.pattern {
         Some(Pattern::Expression(Expr::LogicalNot(inner))) if expr_mentions_in(inner) => {
             "rows without a match"
@@ -276,7 +330,7 @@ fn try_count(program: &Program) -> Option<String> {
 fn try_chart_stats(program: &Program, vs: &HashMap<String, Expr>) -> Option<String> {
     let end = program.end.as_ref()?;
     let fns = collect_fn_names_block(end);
-    let chart_fns = ["hist", "plotbox", "plot"];
+    let chart_fns = ["hist", "plotbox", "plot", "vplot"];
     let stat_fns = [
         "mean",
         "median",
@@ -351,7 +405,11 @@ fn try_aggregation(program: &Program, vs: &HashMap<String, Expr>) -> Option<Stri
     if let Some(key) = &freq
         && has_for_in
     {
-        return Some(format!("frequency of {}", to_title_columns(&humanize(key))));
+        let key = to_title_columns(&humanize(key));
+        return Some(match detect_top_n(end, vs) {
+            Some((end_name, n)) => format!("{end_name} {n} by count of {key}"),
+            None => format!("frequency of {key}"),
+        });
     }
 
     if let Some((key, val, additive)) = &accum
@@ -361,6 +419,13 @@ fn try_aggregation(program: &Program, vs: &HashMap<String, Expr>) -> Option<Stri
         if key_text == "NR" || key_text == "FNR" {
             return None;
         }
+        if *additive && let Some((end_name, n)) = detect_top_n(end, vs) {
+            return Some(format!(
+                "{end_name} {n} of {} by sum of {}",
+                to_title_columns(&key_text),
+                to_title_columns(&humanize(val))
+            ));
+        }
         let verb = if *additive { "sum" } else { "aggregation" };
         return Some(format!(
             "{verb} of {} by {}",
@@ -376,6 +441,174 @@ fn try_aggregation(program: &Program, vs: &HashMap<String, Expr>) -> Option<Stri
     None
 }
 
+/// `("top", 10)` when END prints only the first or last few of a sorted
+/// order: through a `sort | head` pipe, or a counted loop over an asort
+/// result. asorti orders keys, not values, so its loops are not ranked.
+fn detect_top_n(end: &Block, vs: &HashMap<String, Expr>) -> Option<(&'static str, i64)> {
+    if let Some(top) = find_sort_pipe_top_n(end, vs) {
+        return Some(top);
+    }
+    if block_calls_any(end, &["asort"]) {
+        return find_sorted_loop_top_n(end);
+    }
+    None
+}
+
+fn find_sort_pipe_top_n(block: &Block, vs: &HashMap<String, Expr>) -> Option<(&'static str, i64)> {
+    block.iter().find_map(|stmt| match stmt {
+        Statement::Print(_, Some(Redirect::Pipe(cmd)))
+        | Statement::Printf(_, Some(Redirect::Pipe(cmd))) => {
+            sort_pipe_top_n(&literal_target(cmd, vs)?)
+        }
+        Statement::If(_, t, e) => find_sort_pipe_top_n(t, vs)
+            .or_else(|| e.as_ref().and_then(|b| find_sort_pipe_top_n(b, vs))),
+        Statement::For(_, _, _, body)
+        | Statement::ForIn(_, _, _, body)
+        | Statement::While(_, body)
+        | Statement::DoWhile(body, _)
+        | Statement::Block(body) => find_sort_pipe_top_n(body, vs),
+        _ => None,
+    })
+}
+
+/// `sort -rn | head -5` → top 5; `sort -n | head` → bottom 10. A `tail`
+/// instead of `head` takes the other end.
+fn sort_pipe_top_n(cmd: &str) -> Option<(&'static str, i64)> {
+    let mut stages = cmd
+        .split('|')
+        .map(|s| s.split_whitespace().collect::<Vec<_>>());
+    let sort = stages.next()?;
+    if sort.first().map(|w| path_base(w)).as_deref() != Some("sort") {
+        return None;
+    }
+    let reverse = sort[1..].iter().any(|w| {
+        *w == "--reverse"
+            || (w.starts_with('-')
+                && !w.starts_with("--")
+                && !w.starts_with("-t")
+                && w[1..].contains('r'))
+    });
+    let (take, args) = stages.find_map(|stage| {
+        let name = path_base(stage.first()?);
+        matches!(name.as_str(), "head" | "tail").then(|| (name == "head", stage[1..].to_vec()))
+    })?;
+    let n = line_count_arg(&args)?;
+    let top = reverse == take;
+    Some((if top { "top" } else { "bottom" }, n))
+}
+
+/// The line count of `head`/`tail` arguments: `-n 5`, `-n5`, `-5`,
+/// `--lines=5`, or the default 10.
+fn line_count_arg(args: &[&str]) -> Option<i64> {
+    let mut words = args.iter();
+    while let Some(w) = words.next() {
+        let n = if *w == "-n" {
+            words.next().copied()
+        } else if let Some(v) = w.strip_prefix("--lines=") {
+            Some(v)
+        } else if let Some(v) = w.strip_prefix("-n") {
+            Some(v)
+        } else {
+            w.strip_prefix('-')
+        };
+        if let Some(n) = n {
+            return n.parse().ok().filter(|&n: &i64| n > 0);
+        }
+    }
+    Some(10)
+}
+
+fn find_sorted_loop_top_n(block: &Block) -> Option<(&'static str, i64)> {
+    block.iter().find_map(|stmt| match stmt {
+        Statement::For(init, cond, step, body) => {
+            counted_loop_top_n(init, cond, step, body).or_else(|| find_sorted_loop_top_n(body))
+        }
+        Statement::If(_, t, e) => {
+            find_sorted_loop_top_n(t).or_else(|| e.as_ref().and_then(|b| find_sorted_loop_top_n(b)))
+        }
+        Statement::Block(body) => find_sorted_loop_top_n(body),
+        _ => None,
+    })
+}
+
+/// A printing loop over the first few sorted positions
+/// (`for (i = 1; i <= 10; i++)`, ascending: bottom) or the last few
+/// (`for (i = n; i > n - 10; i--)`: top). The count comes from the
+/// literals, whatever the variables are called.
+fn counted_loop_top_n(
+    init: &Option<Box<Statement>>,
+    cond: &Option<Expr>,
+    step: &Option<Box<Statement>>,
+    body: &Block,
+) -> Option<(&'static str, i64)> {
+    let Some(Statement::Expression(Expr::Assign(var, start))) = init.as_deref() else {
+        return None;
+    };
+    let Expr::Var(index) = var.as_ref() else {
+        return None;
+    };
+    let is_index = |e: &Expr| matches!(e, Expr::Var(n) if n == index);
+    let up = match step.as_deref() {
+        Some(Statement::Expression(Expr::Increment(e, _))) if is_index(e) => true,
+        Some(Statement::Expression(Expr::Decrement(e, _))) if is_index(e) => false,
+        Some(Statement::Expression(Expr::CompoundAssign(e, op, by)))
+            if is_index(e) && matches!(by.as_ref(), Expr::NumberLit(n) if *n == 1.0) =>
+        {
+            match op {
+                BinOp::Add => true,
+                BinOp::Sub => false,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    if !block_has_print(body) {
+        return None;
+    }
+    let mut bounds = Vec::new();
+    collect_index_bounds(cond.as_ref()?, &is_index, &mut bounds);
+    bounds.into_iter().find_map(|(op, bound)| {
+        let n = match (up, start.as_ref(), &op, bound) {
+            (true, Expr::NumberLit(s), BinOp::Le, Expr::NumberLit(hi)) => hi - s + 1.0,
+            (true, Expr::NumberLit(s), BinOp::Lt, Expr::NumberLit(hi)) => hi - s,
+            (false, _, BinOp::Gt | BinOp::Ge, Expr::BinOp(_, BinOp::Sub, k)) => match k.as_ref() {
+                Expr::NumberLit(k) if matches!(op, BinOp::Gt) => *k,
+                Expr::NumberLit(k) => k + 1.0,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        (n >= 1.0 && n.fract() == 0.0).then_some((if up { "bottom" } else { "top" }, n as i64))
+    })
+}
+
+/// `i <= 10` and `i > n - 10` comparisons in a loop condition, joined by
+/// `&&`, as (operator, bound) with the index on the left.
+fn collect_index_bounds<'a>(
+    cond: &'a Expr,
+    is_index: &dyn Fn(&Expr) -> bool,
+    out: &mut Vec<(BinOp, &'a Expr)>,
+) {
+    match cond {
+        Expr::LogicalAnd(l, r) => {
+            collect_index_bounds(l, is_index, out);
+            collect_index_bounds(r, is_index, out);
+        }
+        Expr::BinOp(l, op, r) if is_index(l) => out.push((op.clone(), r)),
+        Expr::BinOp(l, op, r) if is_index(r) => {
+            let flipped = match op {
+                BinOp::Lt => BinOp::Gt,
+                BinOp::Le => BinOp::Ge,
+                BinOp::Gt => BinOp::Lt,
+                BinOp::Ge => BinOp::Le,
+                _ => return,
+            };
+            out.push((flipped, l));
+        }
+        _ => {}
+    }
+}
+
 // ── Per-rule phrase detection ───────────────────────────────────
 
 fn detect_rule_phrases(rule: &Rule, vs: &HashMap<String, Expr>, phrases: &mut Vec<String>) {
@@ -583,6 +816,201 @@ fn detect_fallback(program: &Program, vs: &HashMap<String, Expr>, phrases: &mut
     }
 }
 
+// ── Side effects ────────────────────────────────────────────────
+
+/// Files and commands a program touches besides its input and stdout.
+#[derive(Default)]
+struct Effects {
+    /// `reads users.csv`, `reads from date`.
+    inputs: Vec<String>,
+    /// `out.txt`, `per-key files`, `pipes to sort`.
+    outputs: Vec<String>,
+}
+
+impl Effects {
+    fn add(list: &mut Vec<String>, phrase: String) {
+        if !list.contains(&phrase) {
+            list.push(phrase);
+        }
+    }
+}
+
+fn collect_effects(program: &Program, vs: &HashMap<String, Expr>) -> Effects {
+    let mut fx = Effects::default();
+    for block in [&program.begin, &program.beginfile].into_iter().flatten() {
+        effects_block(block, vs, &mut fx);
+    }
+    for rule in &program.rules {
+        if let Some(pat) = &rule.pattern {
+            effects_pattern(pat, vs, &mut fx);
+        }
+        effects_block(&rule.action, vs, &mut fx);
+    }
+    for block in [&program.endfile, &program.end, &program.error]
+        .into_iter()
+        .flatten()
+    {
+        effects_block(block, vs, &mut fx);
+    }
+    for func in &program.functions {
+        effects_block(&func.body, vs, &mut fx);
+    }
+    fx
+}
+
+fn effects_pattern(pat: &Pattern, vs: &HashMap<String, Expr>, fx: &mut Effects) {
+    match pat {
+        Pattern::Regex(_) => {}
+        Pattern::Expression(e) | Pattern::Last(e) => effects_expr(e, vs, fx),
+        Pattern::Range(a, b) => {
+            effects_pattern(a, vs, fx);
+            effects_pattern(b, vs, fx);
+        }
+    }
+}
+
+fn effects_block(block: &Block, vs: &HashMap<String, Expr>, fx: &mut Effects) {
+    for stmt in block {
+        effects_stmt(stmt, vs, fx);
+    }
+}
+
+fn effects_stmt(stmt: &Statement, vs: &HashMap<String, Expr>, fx: &mut Effects) {
+    match stmt {
+        Statement::Print(exprs, redir) | Statement::Printf(exprs, redir) => {
+            for e in exprs {
+                effects_expr(e, vs, fx);
+            }
+            let phrase = match redir {
+                None => None,
+                Some(Redirect::Overwrite(target) | Redirect::Append(target)) => {
+                    match literal_target(target, vs) {
+                        Some(path) => match path.as_str() {
+                            "/dev/stdout" | "/dev/fd/1" | "-" => None,
+                            "/dev/stderr" | "/dev/fd/2" => Some("stderr".to_string()),
+                            _ => Some(path_base(&path)),
+                        },
+                        None => Some("per-key files".to_string()),
+                    }
+                }
+                Some(Redirect::Pipe(cmd)) => Some(match literal_target(cmd, vs) {
+                    Some(c) => format!("pipes to {}", command_name(&c)),
+                    None => "pipes to commands".to_string(),
+                }),
+            };
+            if let Some(p) = phrase {
+                Effects::add(&mut fx.outputs, p);
+            }
+        }
+        Statement::If(c, t, e) => {
+            effects_expr(c, vs, fx);
+            effects_block(t, vs, fx);
+            if let Some(b) = e {
+                effects_block(b, vs, fx);
+            }
+        }
+        Statement::While(c, body) | Statement::DoWhile(body, c) => {
+            effects_expr(c, vs, fx);
+            effects_block(body, vs, fx);
+        }
+        Statement::For(init, cond, update, body) => {
+            for s in [init, update].into_iter().flatten() {
+                effects_stmt(s, vs, fx);
+            }
+            if let Some(c) = cond {
+                effects_expr(c, vs, fx);
+            }
+            effects_block(body, vs, fx);
+        }
+        Statement::ForIn(_, _, _, body) | Statement::Block(body) => effects_block(body, vs, fx),
+        Statement::Expression(e) | Statement::Delete(_, e) => effects_expr(e, vs, fx),
+        Statement::Exit(Some(e)) | Statement::Return(Some(e)) => effects_expr(e, vs, fx),
+        _ => {}
+    }
+}
+
+fn effects_expr(e: &Expr, vs: &HashMap<String, Expr>, fx: &mut Effects) {
+    match e {
+        Expr::Getline(_, Some(source)) => {
+            let phrase = match literal_target(source, vs) {
+                Some(path) if matches!(path.as_str(), "-" | "/dev/stdin") => None,
+                Some(path) => Some(format!("reads {}", path_base(&path))),
+                None => Some("reads files".to_string()),
+            };
+            if let Some(p) = phrase {
+                Effects::add(&mut fx.inputs, p);
+            }
+            effects_expr(source, vs, fx);
+        }
+        Expr::GetlinePipe(cmd, _) => {
+            let phrase = match literal_target(cmd, vs) {
+                Some(c) => format!("reads from {}", command_name(&c)),
+                None => "reads from commands".to_string(),
+            };
+            Effects::add(&mut fx.inputs, phrase);
+            effects_expr(cmd, vs, fx);
+        }
+        Expr::Assign(l, r)
+        | Expr::CompoundAssign(l, _, r)
+        | Expr::BinOp(l, _, r)
+        | Expr::Concat(l, r)
+        | Expr::LogicalAnd(l, r)
+        | Expr::LogicalOr(l, r)
+        | Expr::Match(l, r)
+        | Expr::NotMatch(l, r)
+        | Expr::NullCoalesce(l, r) => {
+            effects_expr(l, vs, fx);
+            effects_expr(r, vs, fx);
+        }
+        Expr::Field(e)
+        | Expr::ArrayRef(_, e)
+        | Expr::ArrayIn(e, _)
+        | Expr::UnaryMinus(e)
+        | Expr::LogicalNot(e)
+        | Expr::Increment(e, _)
+        | Expr::Decrement(e, _)
+        | Expr::TryVal(e)
+        | Expr::NullFence(e) => effects_expr(e, vs, fx),
+        Expr::Ternary(c, t, f) => {
+            for e in [c, t, f] {
+                effects_expr(e, vs, fx);
+            }
+        }
+        Expr::Sprintf(args) | Expr::FuncCall(_, args) => {
+            for a in args {
+                effects_expr(a, vs, fx);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The file name or command a redirection names, when it is a literal
+/// (directly or through a variable assigned one).
+fn literal_target(e: &Expr, vs: &HashMap<String, Expr>) -> Option<String> {
+    match e {
+        Expr::StringLit(s) => Some(s.clone()),
+        Expr::Var(name) => match vs.get(name) {
+            Some(Expr::StringLit(s)) => Some(s.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn path_base(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map_or_else(|| path.to_string(), |n| n.to_string_lossy().into_owned())
+}
+
+/// `sort -t, -k2` → `sort`; `/usr/bin/gzip > x.gz` → `gzip`.
+fn command_name(cmd: &str) -> String {
+    cmd.split_whitespace()
+        .next()
+        .map_or_else(String::new, path_base)
+}
+
 // ── Sub-detectors ───────────────────────────────────────────────
 
 fn detect_capture_filter(block: &Block) -> Vec<String> {
@@ -1177,6 +1605,15 @@ fn is_nr_eq_fnr(pat: &Pattern) -> bool {
     }
 }
 
+fn block_has_print(block: &Block) -> bool {
+    block.iter().any(|s| match s {
+        Statement::Print(..) | Statement::Printf(..) => true,
+        Statement::If(_, t, e) => block_has_print(t) || e.as_ref().is_some_and(block_has_print),
+        Statement::Block(b) => block_has_print(b),
+        _ => false,
+    })
+}
+
 fn block_has_next(block: &Block) -> bool {
     block
         .iter()
@@ -1566,7 +2003,7 @@ fn describe_capture_filter_cond(cond: &Expr) -> Option<String> {
 
 const BUILTIN_VARS: &[&str] = &[
     "NR", "NF", "FNR", "FILENAME", "FS", "RS", "OFS", "ORS", "OFMT", "SUBSEP", "ARGC", "ARGV",
-    "ENVIRON", "CONVFMT",
+    "ENVIRON", "CONVFMT", "PROCINFO",
 ];
 
 fn is_builtin_var(name: &str) -> bool {
@@ -2309,6 +2746,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn synthesized_join() {
+        use crate::cli::{JoinType, synthesize_join};
+        let anti = synthesize_join("on=id", JoinType::Anti, true).unwrap();
+        assert_eq!(ex(&anti), "rows without a match on id");
+        let left = synthesize_join("on=1,2", JoinType::Left, false).unwrap();
+        assert_eq!(ex(&left), "join on column 1, column 2");
+    }
+
     #[test]
     fn semi_join() {
         assert_eq!(
@@ -2704,6 +3150,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn env_shows_match_flags() {
+        let mut ctx = ExplainContext::from_cli("line", false, None, &["app.log".into()]);
+        ctx.ignore_case = true;
+        ctx.word_regexp = true;
+        assert_eq!(
+            ex_ctx("/error/", &ctx),
+            "where /error/ (ignore case, whole words, app.log)",
+        );
+    }
+
     #[test]
     fn env_auto_detected_line_mode_no_noise() {
         let ctx = ExplainContext::from_cli("line", false, None, &["data.txt".into()]);
@@ -2713,6 +3170,119 @@ mod tests {
         );
     }
 
+    // ── Top N ───────────────────────────────────────────────────
+
+    #[test]
+    fn top_n_from_asort_loop() {
+        assert_eq!(
+            ex("{ c[$1]++ } END { n = asort(c, v); for (i = n; i > n - 10; i--) print v[i] }"),
+            "top 10 by count of column 1"
+        );
+        assert_eq!(
+            ex("{ c[$3]++ } END { m = asort(c, s); for (j = m; j >= m - 4; j--) print s[j] }"),
+            "top 5 by count of column 3"
+        );
+        // asorti ranks keys, not counts.
+        assert_eq!(
+            ex("{ c[$1]++ } END { n = asorti(c, k); for (i = 1; i <= 10; i++) print k[i] }"),
+            "frequency of column 1"
+        );
+    }
+
+    #[test]
+    fn top_n_from_sort_pipe() {
+        assert_eq!(
+            ex(r#"{ c[$1]++ } END { for (k in c) print c[k], k | "sort -rn | head -5" }"#),
+            "top 5 by count of column 1 → pipes to sort"
+        );
+        assert_eq!(
+            ex(
+                r#"{ s[$1] += $2 } END { for (k in s) print s[k], k | "sort -k1,1nr | head -n 3" }"#
+            ),
+            "top 3 of column 1 by sum of column 2 → pipes to sort"
+        );
+        // Sorted but not cut: every key is printed.
+        assert_eq!(
+            ex(r#"{ c[$1]++ } END { for (k in c) print c[k], k | "sort -rn" }"#),
+            "frequency of column 1 → pipes to sort"
+        );
+    }
+
+    #[test]
+    fn bottom_n_ascending() {
+        assert_eq!(
+            ex(
+                "{ c[$1]++ } END { n = asort(c, v); for (i = 1; i <= n && i <= 5; i++) print v[i] }"
+            ),
+            "bottom 5 by count of column 1"
+        );
+        assert_eq!(
+            ex(r#"{ c[$2]++ } END { for (k in c) print c[k], k | "sort -n | head" }"#),
+            "bottom 10 by count of column 2 → pipes to sort"
+        );
+        assert_eq!(
+            ex(r#"{ c[$2]++ } END { for (k in c) print c[k], k | "sort -rn | tail -n 2" }"#),
+            "bottom 2 by count of column 2 → pipes to sort"
+        );
+    }
+
+    // ── Side effects ────────────────────────────────────────────
+
+    #[test]
+    fn fan_out_to_per_key_files() {
+        assert_eq!(
+            ex(r#"{ print $1 > $2 ".txt" }"#),
+            "select column 1 → per-key files"
+        );
+        assert_eq!(
+            ex(
+                r#"BEGIN { out = "/tmp/errors.log" } $0 ~ /ERR/ { print >> out; print $3 > "/dev/stderr" }"#
+            ),
+            "where /ERR/: select column 3 → errors.log, stderr"
+        );
+    }
+
+    #[test]
+    fn pipe_to_sort() {
+        assert_eq!(
+            ex(r#"{ print $2, $1 | "sort -t, -k2 -n" }"#),
+            "select columns 2, 1 → pipes to sort"
+        );
+        assert_eq!(
+            ex(r#"{ c[$1]++ } END { for (k in c) print k, c[k] | "sort -rn" }"#),
+            "frequency of column 1 → pipes to sort"
+        );
+    }
+
+    #[test]
+    fn getline_lookup_join() {
+        assert_eq!(
+            ex(
+                r#"BEGIN { while ((getline line < "users.csv") > 0) { split(line, f, ","); name[f[1]] = f[2] } } { print $1, name[$2] }"#
+            ),
+            "select column 1, reads users.csv"
+        );
+        assert_eq!(
+            ex(r#"BEGIN { "date +%F" | getline today } { print today, $0 > "stamped.txt" }"#),
+            "select today, reads from date → stamped.txt"
+        );
+    }
+
+    #[test]
+    fn getline_sources_survive_output_effects() {
+        assert_eq!(
+            ex(
+                r#"{ print $1 > "out.txt"; print $2 | "sort"; "date" | getline d; getline l < "cfg" }"#
+            ),
+            "select…, reads from date, reads cfg → out.txt, pipes to sort"
+        );
+    }
+
+    #[test]
+    fn stdout_redirects_are_not_effects() {
+        assert_eq!(ex(r#"{ print $1 > "/dev/stdout" }"#), "select column 1");
+    }
+
     // ── Budget ──────────────────────────────────────────────────
 
     #[test]
@@ -87,63 +87,48 @@ pub fn split_into_limit(fields: &mut Vec<String>, record: &str, fs: &str, limit:
 /// Each pair is (start, end) such that `record[start..end]` is the field text.
 pub fn split_offsets(offsets: &mut Vec<(usize, usize)>, record: &str, fs: &str) {
     offsets.clear();
-    if fs == " " {
-        let bytes = record.as_bytes();
-        let len = bytes.len();
-        let mut i = 0;
-        while i < len && bytes[i].is_ascii_whitespace() {
-            i += 1;
-        }
-        while i < len {
-            let start = i;
-            while i < len && !bytes[i].is_ascii_whitespace() {
-                i += 1;
-            }
-            offsets.push((start, i));
-            while i < len && bytes[i].is_ascii_whitespace() {
-                i += 1;
-            }
-        }
-    } else if fs.len() == 1 || (fs.len() > 1 && fs.chars().count() == 1) {
-        let sep = fs.as_bytes()[0];
-        let bytes = record.as_bytes();
-        let mut start = 0;
-        for (i, &b) in bytes.iter().enumerate() {
-            if b == sep {
-                offsets.push((start, i));
-                start = i + 1;
-            }
-        }
-        offsets.push((start, bytes.len()));
-    } else {
-        let mut start = 0;
-        for (i, _) in record.match_indices(fs) {
-            offsets.push((start, i));
-            start = i + fs.len();
-        }
-        offsets.push((start, record.len()));
-    }
+    split_offsets_range(offsets, record, fs, 0, usize::MAX);
 }
 
-/// Like split_offsets but stops after `limit` fields.
-pub fn split_offsets_limit(
+/// Like split_offsets but stops after `limit` fields. When fields remain,
+/// returns where the unsplit rest starts so [`split_offsets_from`] can
+/// finish the record on demand.
+pub fn split_offsets_bounded(
     offsets: &mut Vec<(usize, usize)>,
     record: &str,
     fs: &str,
     limit: usize,
-) {
+) -> Option<usize> {
     offsets.clear();
-    if limit == 0 {
-        return;
-    }
+    split_offsets_range(offsets, record, fs, 0, limit)
+}
+
+/// Append the fields of `record[from..]` to `offsets`, continuing a split
+/// that [`split_offsets_bounded`] stopped at `from`.
+pub fn split_offsets_from(offsets: &mut Vec<(usize, usize)>, record: &str, fs: &str, from: usize) {
+    split_offsets_range(offsets, record, fs, from, usize::MAX);
+}
+
+/// Push fields starting at byte `from` until `offsets` holds `limit` of
+/// them; returns the resume position if the record goes on.
+fn split_offsets_range(
+    offsets: &mut Vec<(usize, usize)>,
+    record: &str,
+    fs: &str,
+    from: usize,
+    limit: usize,
+) -> Option<usize> {
     if fs == " " {
         let bytes = record.as_bytes();
         let len = bytes.len();
-        let mut i = 0;
+        let mut i = from;
         while i < len && bytes[i].is_ascii_whitespace() {
             i += 1;
         }
-        while i < len && offsets.len() < limit {
+        while i < len {
+            if offsets.len() >= limit {
+                return Some(i);
+            }
             let start = i;
             while i < len && !bytes[i].is_ascii_whitespace() {
                 i += 1;
@@ -153,35 +138,35 @@ pub fn split_offsets_limit(
                 i += 1;
             }
         }
-    } else if fs.len() == 1 || (fs.len() > 1 && fs.chars().count() == 1) {
+        return None;
+    }
+    // Otherwise every separator starts another (possibly empty) field.
+    if offsets.len() >= limit {
+        return Some(from);
+    }
+    let mut start = from;
+    if fs.len() == 1 || (fs.len() > 1 && fs.chars().count() == 1) {
         let sep = fs.as_bytes()[0];
-        let bytes = record.as_bytes();
-        let mut start = 0;
-        for (i, &b) in bytes.iter().enumerate() {
+        for (i, &b) in record.as_bytes()[from..].iter().enumerate() {
             if b == sep {
-                offsets.push((start, i));
-                start = i + 1;
+                offsets.push((start, from + i));
+                start = from + i + 1;
                 if offsets.len() >= limit {
-                    return;
+                    return Some(start);
                 }
             }
         }
-        if offsets.len() < limit {
-            offsets.push((start, bytes.len()));
-        }
     } else {
-        let mut start = 0;
-        for (i, _) in record.match_indices(fs) {
-            offsets.push((start, i));
-            start = i + fs.len();
+        for (i, _) in record[from..].match_indices(fs) {
+            offsets.push((start, from + i));
+            start = from + i + fs.len();
             if offsets.len() >= limit {
-                return;
+                return Some(start);
             }
         }
-        if offsets.len() < limit {
-            offsets.push((start, record.len()));
-        }
     }
+    offsets.push((start, record.len()));
+    None
 }
 
 #[cfg(test)]
@@ -264,17 +249,41 @@ mod tests {
     #[test]
     fn offsets_limit() {
         let mut o = Vec::new();
-        split_offsets_limit(&mut o, "a,b,c,d", ",", 2);
+        assert_eq!(split_offsets_bounded(&mut o, "a,b,c,d", ",", 2), Some(4));
         assert_eq!(offsets_to_strings("a,b,c,d", &o), vec!["a", "b"]);
     }
 
     #[test]
     fn offsets_limit_whitespace() {
         let mut o = Vec::new();
-        split_offsets_limit(&mut o, "  one two three  ", " ", 2);
+        split_offsets_bounded(&mut o, "  one two three  ", " ", 2);
         assert_eq!(
             offsets_to_strings("  one two three  ", &o),
             vec!["one", "two"]
         );
     }
+
+    #[test]
+    fn bounded_then_resumed_matches_full_split() {
+        let cases = [
+            ("a,b,c,d", ","),
+            ("a,b,", ","),
+            ("", ","),
+            ("  one two  three  ", " "),
+            ("one two", " "),
+            ("x::y::::z", "::"),
+        ];
+        for (record, fs) in cases {
+            let mut full = Vec::new();
+            split_offsets(&mut full, record, fs);
+            for limit in 0..6 {
+                let mut o = Vec::new();
+                match split_offsets_bounded(&mut o, record, fs, limit) {
+                    Some(rest) => split_offsets_from(&mut o, record, fs, rest),
+                    None => assert!(o.len() <= limit, "{record:?} {limit}"),
+                }
+                assert_eq!(o, full, "{record:?} split by {fs:?} at {limit}");
+            }
+        }
+    }
 }
@@ -0,0 +1,141 @@
+//! File-argument glob expansion (`--glob`, on by default on Windows).
+//!
+//! Shells expand `logs/*.csv` before fk sees it; without one (Windows, or
+//! fk exec'd directly) the pattern arrives as written. Here `*`, `?`,
+//! `[...]` and a recursive `**` are expanded, each pattern's matches
+//! sorted. A name that exists as given is never treated as a pattern.
+
+use std::path::Path;
+
+use glob::MatchOptions;
+
+/// Whether `arg` has glob syntax (and so may need expanding).
+pub fn is_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+/// Expand every pattern in `files`. A pattern that matches nothing is an
+/// error unless `allow_empty` (`--nullglob`), which drops it instead.
+pub fn expand(files: &[String], allow_empty: bool) -> Result<Vec<String>, String> {
+    let mut out = Vec::with_capacity(files.len());
+    for arg in files {
+        if arg == "-" || !is_pattern(arg) || Path::new(arg).exists() {
+            out.push(arg.clone());
+            continue;
+        }
+        let matches = matches(arg)?;
+        if matches.is_empty() && !allow_empty {
+            return Err(format!("no files match '{}' (--nullglob to allow)", arg));
+        }
+        out.extend(matches);
+    }
+    Ok(out)
+}
+
+/// The paths `pattern` matches, sorted. Hidden names only match a pattern
+/// that spells out the leading dot, as in the shell.
+fn matches(pattern: &str) -> Result<Vec<String>, String> {
+    let opts = MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: true,
+    };
+    let paths = glob::glob_with(pattern, opts)
+        .map_err(|e| format!("bad glob pattern '{}': {}", pattern, e.msg))?;
+    let mut found: Vec<String> = paths
+        .filter_map(Result::ok)
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect();
+    found.sort();
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn tree(name: &str, files: &[&str]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("fk_glob_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for f in files {
+            let path = dir.join(f);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x\n").unwrap();
+        }
+        dir
+    }
+
+    fn names(dir: &Path, paths: &[String]) -> Vec<String> {
+        let prefix = format!("{}/", dir.display());
+        paths.iter().map(|p| p.replacen(&prefix, "", 1)).collect()
+    }
+
+    #[test]
+    fn patterns_expand_sorted_and_recursively() {
+        let dir = tree(
+            "sorted",
+            &[
+                "b.csv",
+                "a.csv",
+                "c.txt",
+                "10.csv",
+                ".hidden.csv",
+                "2024/01/x.csv",
+                "2024/y.csv",
+            ],
+        );
+        let d = dir.display();
+        let args = vec![
+            format!("{d}/*.csv"),
+            "-".to_string(),
+            format!("{d}/**/*.csv"),
+        ];
+        let files = expand(&args, false).unwrap();
+        assert_eq!(
+            names(&dir, &files),
+            [
+                "10.csv",
+                "a.csv",
+                "b.csv",
+                "-",
+                "10.csv",
+                "2024/01/x.csv",
+                "2024/y.csv",
+                "a.csv",
+                "b.csv"
+            ]
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn no_match_is_an_error_unless_allowed() {
+        let dir = tree("nomatch", &["a.csv"]);
+        let args = vec![format!("{}/*.json", dir.display())];
+        let err = expand(&args, false).unwrap_err();
+        assert!(err.starts_with("no files match '"), "{err}");
+        assert!(expand(&args, true).unwrap().is_empty());
+        assert!(
+            expand(&["[".to_string()], false)
+                .unwrap_err()
+                .starts_with("bad glob")
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn literal_names_win_over_patterns() {
+        let dir = tree("literal", &["a*.csv", "ab.csv", "plain.csv"]);
+        let literal = format!("{}/a*.csv", dir.display());
+        let files = expand(std::slice::from_ref(&literal), false).unwrap();
+        assert_eq!(files, [literal]);
+        // Names without glob syntax pass through even when missing.
+        let missing = format!("{}/missing.csv", dir.display());
+        assert_eq!(
+            expand(std::slice::from_ref(&missing), false).unwrap(),
+            [missing]
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,165 @@
+//! Line-based unified diff for `--check-format`.
+//!
+//! Programs are small, so a plain LCS table is fast enough and keeps the
+//! output identical to `diff -u` for the common cases.
+
+use std::fmt::Write;
+
+/// Lines of unchanged context around each hunk.
+const CONTEXT: usize = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Keep,
+    Del,
+    Add,
+}
+
+/// Unified diff of `old` → `new`, empty when they have the same lines.
+#[must_use]
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let ops = edit_script(&a, &b);
+    if ops.iter().all(|&(op, _, _)| op == Op::Keep) {
+        return String::new();
+    }
+
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    let mut i = 0;
+    while i < ops.len() {
+        if ops[i].0 == Op::Keep {
+            i += 1;
+            continue;
+        }
+        // Merge changes separated by at most 2*CONTEXT unchanged lines.
+        let start = i.saturating_sub(CONTEXT);
+        let mut last = i;
+        for (j, &(op, _, _)) in ops.iter().enumerate().skip(i + 1) {
+            if op != Op::Keep {
+                if j - last > 2 * CONTEXT + 1 {
+                    break;
+                }
+                last = j;
+            }
+        }
+        let end = (last + 1 + CONTEXT).min(ops.len());
+        let hunk = &ops[start..end];
+
+        let (mut old_len, mut new_len) = (0, 0);
+        for &(op, _, _) in hunk {
+            if op != Op::Add {
+                old_len += 1;
+            }
+            if op != Op::Del {
+                new_len += 1;
+            }
+        }
+        let old_start = hunk.iter().map(|o| o.1).min().unwrap_or(0) + usize::from(old_len > 0);
+        let new_start = hunk.iter().map(|o| o.2).min().unwrap_or(0) + usize::from(new_len > 0);
+        let _ = writeln!(
+            out,
+            "@@ -{} +{} @@",
+            range(old_start, old_len),
+            range(new_start, new_len)
+        );
+        for &(op, ai, bi) in hunk {
+            let _ = match op {
+                Op::Keep => writeln!(out, " {}", a[ai]),
+                Op::Del => writeln!(out, "-{}", a[ai]),
+                Op::Add => writeln!(out, "+{}", b[bi]),
+            };
+        }
+        i = end;
+    }
+    out
+}
+
+/// `start,len`, or just `start` for a single line (as diff -u prints it).
+fn range(start: usize, len: usize) -> String {
+    if len == 1 {
+        start.to_string()
+    } else {
+        format!("{start},{len}")
+    }
+}
+
+/// Shortest edit script as (op, index in a, index in b); for an `Add` the
+/// `a` index is the position it is inserted before (and vice versa).
+fn edit_script(a: &[&str], b: &[&str]) -> Vec<(Op, usize, usize)> {
+    let (n, m) = (a.len(), b.len());
+    // lcs[i][j] = LCS length of a[i..] and b[j..].
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut ops = Vec::with_capacity(n + m);
+    while i < n || j < m {
+        if i < n && j < m && a[i] == b[j] {
+            ops.push((Op::Keep, i, j));
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            ops.push((Op::Add, i, j));
+            j += 1;
+        } else {
+            ops.push((Op::Del, i, j));
+            i += 1;
+        }
+    }
+    // Deletions before additions within a change, like diff -u.
+    let mut k = 0;
+    while k < ops.len() {
+        let run_end = ops[k..]
+            .iter()
+            .position(|o| o.0 == Op::Keep)
+            .map_or(ops.len(), |p| k + p);
+        ops[k..run_end].sort_by_key(|o| o.0 == Op::Add);
+        k = run_end + 1;
+    }
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unified_diff;
+
+    #[test]
+    fn identical_inputs_have_no_diff() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb", "x", "y"), "");
+    }
+
+    #[test]
+    fn diff_has_headers_hunk_and_context() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n";
+        assert_eq!(
+            unified_diff(old, new, "a", "b"),
+            "--- a\n+++ b\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+        );
+        assert_eq!(
+            unified_diff("{ print }", "{\n  print\n}", "a", "b"),
+            "--- a\n+++ b\n@@ -1 +1,3 @@\n-{ print }\n+{\n+  print\n+}\n"
+        );
+    }
+
+    #[test]
+    fn separate_hunks_match_diff_u() {
+        let old: String = (1..=20).map(|i| format!("{i}\n")).collect();
+        let new = old
+            .replace("\n2\n", "\ntwo\n")
+            .replace("\n10\n", "\n")
+            .replace("\n17\n", "\nx\ny\n");
+        let diff = unified_diff(&old, &new, "a", "b");
+        let hunks: Vec<&str> = diff.lines().filter(|l| l.starts_with("@@")).collect();
+        assert_eq!(hunks, ["@@ -1,5 +1,5 @@", "@@ -7,14 +7,14 @@"]);
+        assert!(diff.contains("\n-10\n 11\n") && diff.contains("\n-17\n+x\n+y\n 18\n"));
+    }
+}
@@ -0,0 +1,469 @@
+//! `--dump-tokens` and `--dump-ast`: what the lexer and parser made of a
+//! program, for bug reports and golden tests.
+//!
+//! Tokens are listed one per line with their `line:col`. The AST is an
+//! s-expression: statements one per line, indented under the block that
+//! holds them, expressions inline. Both are written by hand rather than
+//! with `#[derive(Debug)]`, so the output only changes when the syntax
+//! tree does.
+
+use crate::error::FkError;
+use crate::lexer::{Lexer, Spanned, Token};
+use crate::parser::{BinOp, Block, Expr, Parser, Pattern, Program, Redirect, SortMode, Statement};
+use std::fmt::Write;
+
+const INDENT: &str = "  ";
+
+/// Lex `source` and list its tokens, `Eof` included.
+pub fn dump_tokens(source: &str) -> Result<String, FkError> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let mut out = String::new();
+    for Spanned { token, span } in &tokens {
+        let at = format!("{}:{}", span.line, span.col);
+        let _ = writeln!(out, "{at:<7} {}", token_label(token));
+    }
+    Ok(out)
+}
+
+/// Parse `source` and write its syntax tree, before constant folding.
+pub fn dump_ast(source: &str) -> Result<String, FkError> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    Ok(dump_program(&program))
+}
+
+/// Write an already-parsed program as an s-expression.
+#[must_use]
+pub fn dump_program(program: &Program) -> String {
+    let mut d = Dump { out: String::new() };
+    let sections = [
+        ("begin", &program.begin),
+        ("beginfile", &program.beginfile),
+        ("endfile", &program.endfile),
+        ("end", &program.end),
+        ("error", &program.error),
+    ];
+    for (name, block) in &sections[..2] {
+        if let Some(block) = block {
+            d.block_form(0, name, block);
+        }
+    }
+    for rule in &program.rules {
+        let head = match &rule.pattern {
+            Some(p) => format!("rule {}", pattern(p)),
+            None => "rule".to_string(),
+        };
+        d.block_form(0, &head, &rule.action);
+    }
+    for (name, block) in &sections[2..] {
+        if let Some(block) = block {
+            d.block_form(0, name, block);
+        }
+    }
+    for f in &program.functions {
+        let head = format!("function {} ({})", f.name, f.params.join(" "));
+        d.block_form(0, &head, &f.body);
+    }
+    d.out
+}
+
+fn token_label(token: &Token) -> String {
+    match token {
+        Token::Number(n) => format!("Number {n}"),
+        Token::StringLit(s) => format!("StringLit {s:?}"),
+        Token::Regex(s) => format!("Regex {s:?}"),
+        Token::Ident(s) => format!("Ident {s}"),
+        Token::Field(n) => format!("Field {n}"),
+        Token::FieldVar(s) => format!("FieldVar {s}"),
+        // The rest carry no payload: their Debug form is the variant name.
+        other => format!("{other:?}"),
+    }
+}
+
+struct Dump {
+    out: String,
+}
+
+impl Dump {
+    fn line(&mut self, depth: usize, text: &str) {
+        for _ in 0..depth {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// `(head` followed by the block's statements one level deeper; the
+    /// closing paren goes on the last line.
+    fn block_form(&mut self, depth: usize, head: &str, block: &Block) {
+        self.line(depth, &format!("({head}"));
+        self.statements(depth + 1, block);
+        self.close();
+    }
+
+    fn close(&mut self) {
+        self.out.pop();
+        self.out.push_str(")\n");
+    }
+
+    fn statements(&mut self, depth: usize, block: &Block) {
+        for s in block {
+            self.statement(depth, s);
+        }
+    }
+
+    fn statement(&mut self, depth: usize, s: &Statement) {
+        match s {
+            Statement::If(cond, then, otherwise) => {
+                self.line(depth, &format!("(if {}", expr(cond)));
+                self.block_form(depth + 1, "then", then);
+                if let Some(otherwise) = otherwise {
+                    self.block_form(depth + 1, "else", otherwise);
+                }
+                self.close();
+            }
+            Statement::While(cond, body) => {
+                self.block_form(depth, &format!("while {}", expr(cond)), body);
+            }
+            Statement::DoWhile(body, cond) => {
+                self.line(depth, "(do");
+                self.statements(depth + 1, body);
+                self.line(depth + 1, &format!("(while {})", expr(cond)));
+                self.close();
+            }
+            Statement::For(init, cond, step, body) => {
+                let part = |s: &Option<Box<Statement>>| s.as_deref().map_or("_".into(), simple);
+                let cond = cond.as_ref().map_or("_".into(), expr);
+                let head = format!("for {} {} {}", part(init), cond, part(step));
+                self.block_form(depth, &head, body);
+            }
+            Statement::ForIn(var, array, sort, body) => {
+                let head = match sort {
+                    Some(mode) => format!("for-in {var} {array} @{}", sort_name(*mode)),
+                    None => format!("for-in {var} {array}"),
+                };
+                self.block_form(depth, &head, body);
+            }
+            Statement::Block(block) => self.block_form(depth, "block", block),
+            other => self.line(depth, &simple(other)),
+        }
+    }
+}
+
+/// A statement without a nested block, on one line.
+fn simple(s: &Statement) -> String {
+    match s {
+        Statement::Print(args, redir) => output("print", args, redir),
+        Statement::Printf(args, redir) => output("printf", args, redir),
+        Statement::Delete(array, key) => format!("(delete {array} {})", expr(key)),
+        Statement::DeleteAll(array) => format!("(delete {array})"),
+        Statement::Next => "(next)".into(),
+        Statement::Nextfile => "(nextfile)".into(),
+        Statement::Break => "(break)".into(),
+        Statement::Continue => "(continue)".into(),
+        Statement::Exit(code) => optional("exit", code.as_ref()),
+        Statement::Return(value) => optional("return", value.as_ref()),
+        Statement::Expression(e) => expr(e),
+        // Nested blocks inside a for header: keep them on the line.
+        other => {
+            let mut d = Dump { out: String::new() };
+            d.statement(0, other);
+            d.out.trim_end().replace('\n', " ")
+        }
+    }
+}
+
+fn output(name: &str, args: &[Expr], redir: &Option<Redirect>) -> String {
+    let mut s = format!("({name}");
+    for a in args {
+        s.push(' ');
+        s.push_str(&expr(a));
+    }
+    match redir {
+        Some(Redirect::Overwrite(target)) => s.push_str(&format!(" (> {})", expr(target))),
+        Some(Redirect::Append(target)) => s.push_str(&format!(" (>> {})", expr(target))),
+        Some(Redirect::Pipe(cmd)) => s.push_str(&format!(" (| {})", expr(cmd))),
+        None => {}
+    }
+    s.push(')');
+    s
+}
+
+fn optional(name: &str, e: Option<&Expr>) -> String {
+    match e {
+        Some(e) => format!("({name} {})", expr(e)),
+        None => format!("({name})"),
+    }
+}
+
+fn pattern(p: &Pattern) -> String {
+    match p {
+        Pattern::Regex(re) => format!("(regex {re:?})"),
+        Pattern::Expression(e) => expr(e),
+        Pattern::Range(from, to) => format!("(range {} {})", pattern(from), pattern(to)),
+        Pattern::Last(n) => format!("(last {})", expr(n)),
+    }
+}
+
+fn expr(e: &Expr) -> String {
+    let form = |head: &str, parts: &[&Expr]| {
+        let mut s = format!("({head}");
+        for p in parts {
+            s.push(' ');
+            s.push_str(&expr(p));
+        }
+        s.push(')');
+        s
+    };
+    match e {
+        Expr::NumberLit(n) => format!("{n}"),
+        Expr::StringLit(s) => format!("{s:?}"),
+        Expr::Var(name) => name.clone(),
+        Expr::Field(idx) => form("$", &[idx]),
+        Expr::ArrayRef(array, key) => format!("(index {array} {})", expr(key)),
+        Expr::ArrayIn(key, array) => format!("(in {} {array})", expr(key)),
+        Expr::BinOp(l, op, r) => form(binop(op), &[l, r]),
+        Expr::LogicalAnd(l, r) => form("&&", &[l, r]),
+        Expr::LogicalOr(l, r) => form("||", &[l, r]),
+        Expr::LogicalNot(x) => form("!", &[x]),
+        Expr::Match(l, r) => form("~", &[l, r]),
+        Expr::NotMatch(l, r) => form("!~", &[l, r]),
+        Expr::Assign(l, r) => form("=", &[l, r]),
+        Expr::CompoundAssign(l, op, r) => form(&format!("{}=", binop(op)), &[l, r]),
+        Expr::Increment(x, true) => form("pre++", &[x]),
+        Expr::Increment(x, false) => form("post++", &[x]),
+        Expr::Decrement(x, true) => form("pre--", &[x]),
+        Expr::Decrement(x, false) => form("post--", &[x]),
+        Expr::UnaryMinus(x) => form("neg", &[x]),
+        Expr::Concat(l, r) => form("concat", &[l, r]),
+        Expr::Ternary(c, a, b) => form("?:", &[c, a, b]),
+        Expr::NullCoalesce(l, r) => form("??", &[l, r]),
+        Expr::TryVal(x) => form("try", &[x]),
+        Expr::NullFence(x) => form("group", &[x]),
+        Expr::Sprintf(args) => form("sprintf", &args.iter().collect::<Vec<_>>()),
+        Expr::FuncCall(name, args) => {
+            form(&format!("call {name}"), &args.iter().collect::<Vec<_>>())
+        }
+        Expr::Getline(var, source) => {
+            let mut s = "(getline".to_string();
+            if let Some(var) = var {
+                s.push(' ');
+                s.push_str(&expr(var));
+            }
+            if let Some(source) = source {
+                s.push_str(&format!(" (< {})", expr(source)));
+            }
+            s.push(')');
+            s
+        }
+        Expr::GetlinePipe(cmd, var) => match var {
+            Some(var) => format!("(getline {} (| {}))", expr(var), expr(cmd)),
+            None => format!("(getline (| {}))", expr(cmd)),
+        },
+    }
+}
+
+fn binop(op: &BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Sub => "-",
+        BinOp::Mul => "*",
+        BinOp::Div => "/",
+        BinOp::Mod => "%",
+        BinOp::Pow => "^",
+        BinOp::Eq => "==",
+        BinOp::Ne => "!=",
+        BinOp::Lt => "<",
+        BinOp::Le => "<=",
+        BinOp::Gt => ">",
+        BinOp::Ge => ">=",
+    }
+}
+
+fn sort_name(mode: SortMode) -> &'static str {
+    match mode {
+        SortMode::Asc => "sort",
+        SortMode::Desc => "rsort",
+        SortMode::NumAsc => "nsort",
+        SortMode::NumDesc => "rnsort",
+        SortMode::ValAsc => "val",
+        SortMode::ValDesc => "rval",
+        SortMode::Unsorted => "unsorted",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dump_ast, dump_tokens};
+
+    /// Every token kind at least once; it only has to lex, not parse.
+    const TOKEN_SOUP: &str = r#"BEGIN END BEGINFILE ENDFILE function f(a) { return }
+print printf if else while for do in delete getline nextfile next break continue exit
+x = 1.5 "s\t" $1 $x $(i) (/re+/) y / 2 ** 3 % 4 + 5 - 6 * 7
+a += 1; a -= 1; a *= 2; a /= 2; a %= 3; a++ b-- == != < <= > >= ~ !~ !z && || ? ?? : ns::g >> | [ ] , @val
+"#;
+
+    const TOKENS: &str = r#"1:1     Begin
+1:7     End
+1:11    Beginfile
+1:21    Endfile
+1:29    Function
+1:38    Ident f
+1:39    LParen
+1:40    Ident a
+1:41    RParen
+1:43    LBrace
+1:45    Return
+1:52    RBrace
+1:53    Newline
+2:1     Print
+2:7     Printf
+2:14    If
+2:17    Else
+2:22    While
+2:28    For
+2:32    Do
+2:35    In
+2:38    Delete
+2:45    Getline
+2:53    Nextfile
+2:62    Next
+2:67    Break
+2:73    Continue
+2:82    Exit
+2:86    Newline
+3:1     Ident x
+3:3     Assign
+3:5     Number 1.5
+3:9     StringLit "s\t"
+3:15    Field 1
+3:18    FieldVar x
+3:21    Dollar
+3:22    LParen
+3:23    Ident i
+3:24    RParen
+3:26    LParen
+3:27    Regex "re+"
+3:32    RParen
+3:34    Ident y
+3:36    Slash
+3:38    Number 2
+3:40    Power
+3:43    Number 3
+3:45    Percent
+3:47    Number 4
+3:49    Plus
+3:51    Number 5
+3:53    Minus
+3:55    Number 6
+3:57    Star
+3:59    Number 7
+3:60    Newline
+4:1     Ident a
+4:3     PlusAssign
+4:6     Number 1
+4:7     Semicolon
+4:9     Ident a
+4:11    MinusAssign
+4:14    Number 1
+4:15    Semicolon
+4:17    Ident a
+4:19    StarAssign
+4:22    Number 2
+4:23    Semicolon
+4:25    Ident a
+4:27    SlashAssign
+4:30    Number 2
+4:31    Semicolon
+4:33    Ident a
+4:35    PercentAssign
+4:38    Number 3
+4:39    Semicolon
+4:41    Ident a
+4:42    Increment
+4:45    Ident b
+4:46    Decrement
+4:49    Eq
+4:52    Ne
+4:55    Lt
+4:57    Le
+4:60    Gt
+4:62    Ge
+4:65    Match
+4:67    NotMatch
+4:70    Not
+4:71    Ident z
+4:73    And
+4:76    Or
+4:79    Question
+4:81    NullCoalesce
+4:84    Colon
+4:86    Ident ns
+4:88    ColonColon
+4:90    Ident g
+4:92    Append
+4:95    Pipe
+4:97    LBracket
+4:99    RBracket
+4:101   Comma
+4:103   At
+4:104   Ident val
+4:107   Newline
+5:1     Eof
+"#;
+
+    #[test]
+    fn token_dump_lists_every_kind_with_its_position() {
+        assert_eq!(dump_tokens(TOKEN_SOUP).unwrap(), TOKENS);
+    }
+
+    const PROGRAM: &str = r#"BEGIN { FS = ","; x = y ? 1 : -2 }
+/start/, $1 == "end" { print $1, $NF > "out.txt"; n++ }
+NR > 1 && !seen[$2]++ { "date" | getline d; getline line < "f"; getline; printf "%s\n", d | "sort" }
+last 2 { print >> "tail.txt" }
+END { for (k in seen) @val delete seen[k]; for (i = 0; i < 3; i++) if (i % 2) continue; else break; do j--; while (j > 0); exit 1 }
+function f(a, b) { return (a, b) in m ? a ?? b : -a }
+"#;
+
+    const AST: &str = r#"(begin
+  (= FS ",")
+  (= x (?: y 1 (neg 2))))
+(rule (range (regex "start") (== ($ 1) "end"))
+  (print ($ 1) ($ NF) (> "out.txt"))
+  (post++ n))
+(rule (&& (> NR 1) (! (post++ (index seen ($ 2)))))
+  (getline d (| "date"))
+  (getline line (< "f"))
+  (getline)
+  (printf "%s\n" d (| "sort")))
+(rule (last 2)
+  (print ($ 0) (>> "tail.txt")))
+(end
+  (for-in k seen @val
+    (delete seen k))
+  (for (= i 0) (< i 3) (post++ i)
+    (if (% i 2)
+      (then
+        (continue))
+      (else
+        (break))))
+  (do
+    (post-- j)
+    (while (> j 0)))
+  (exit 1))
+(function f (a b)
+  (return (?: (in (concat (concat a SUBSEP) b) m) (?? a b) (neg a))))
+"#;
+
+    #[test]
+    fn ast_dump_shows_ranges_ternaries_getline_and_redirections() {
+        assert_eq!(dump_ast(PROGRAM).unwrap(), AST);
+    }
+
+    #[test]
+    fn dumps_report_lex_and_parse_errors() {
+        assert!(dump_tokens("{ \"open").is_err());
+        assert!(dump_ast("{ print ( }").is_err());
+        assert!(dump_tokens("{ print ( }").is_ok());
+    }
+}
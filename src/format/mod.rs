@@ -3,12 +3,17 @@
 //! This module is modular and composable: themes map token kinds to styles,
 //! segment building turns source + tokens into (byte range, style) runs,
 //! and the highlighter merges runs and emits styled output. The pretty-printer
-//! formats the AST with indentation and line-breaking.
+//! formats the AST with indentation and line-breaking; the dumper lists
+//! tokens and writes the AST as an s-expression for debugging.
 
+mod diff;
+mod dump;
 mod highlight;
 mod pretty;
 mod theme;
 
+pub use diff::unified_diff;
+pub use dump::{dump_ast, dump_program, dump_tokens};
 pub use highlight::{highlight, highlight_to_stderr};
-pub use pretty::format_program;
+pub use pretty::{FormatOptions, format_program, format_program_with};
 pub use theme::{AnsiTheme, Style, Theme};
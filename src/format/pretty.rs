@@ -1,4 +1,10 @@
 //! Pretty-print fk programs: reasonable line-breaking and indentation.
+//!
+//! Statements are written on one line unless that line would pass
+//! `FormatOptions::width`: then print/printf arguments wrap after commas
+//! and `&&`/`||` conditions break after the operator, both with a
+//! continuation indent. Breaking depends only on the AST, so formatting
+//! the output again gives the same text.
 
 use crate::error::FkError;
 use crate::lexer::Lexer;
@@ -10,22 +16,49 @@ use std::fmt::Write;
 
 const INDENT: &str = "  ";
 
+/// Layout settings for `format_program_with`.
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// Lines longer than this are broken where the syntax allows.
+    pub width: usize,
+    /// Line up the `=` of consecutive plain assignments in a block.
+    pub align_assignments: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            width: 100,
+            align_assignments: false,
+        }
+    }
+}
+
 /// Format source code: parse then pretty-print with indentation.
 pub fn format_program(source: &str) -> Result<String, FkError> {
+    format_program_with(source, &FormatOptions::default())
+}
+
+/// Format source code with explicit layout options.
+pub fn format_program_with(source: &str, opts: &FormatOptions) -> Result<String, FkError> {
     let mut lexer = Lexer::new(source);
     let tokens = lexer.tokenize()?;
     let mut parser = Parser::new(tokens);
     let program = parser.parse()?;
-    Ok(pretty_program(&program))
+    Ok(pretty_program(&program, opts))
 }
 
 /// Format an already-parsed program.
 #[must_use]
-pub fn pretty_program(program: &Program) -> String {
+pub fn pretty_program(program: &Program, opts: &FormatOptions) -> String {
     let mut p = Pretty {
         out: String::new(),
         indent: 0,
         indent_cache: String::new(),
+        in_print: false,
+        width: opts.width,
+        align: opts.align_assignments,
+        assign_pad: 0,
     };
     p.program(program);
     p.out
@@ -36,6 +69,13 @@ struct Pretty {
     indent: usize,
     /// Cached indent string (grown as needed) to avoid repeated INDENT.repeat().
     indent_cache: String,
+    /// Printing print/printf arguments, where `>` is a redirection.
+    in_print: bool,
+    width: usize,
+    align: bool,
+    /// Spaces to put before the `=` of the assignment statement being
+    /// written, to line it up with its neighbours.
+    assign_pad: usize,
 }
 
 impl Pretty {
@@ -61,6 +101,27 @@ impl Pretty {
         self.out.push_str(&self.indent_cache[..end]);
     }
 
+    /// Characters on the line being written.
+    fn line_width(&self) -> usize {
+        let start = self.out.rfind('\n').map_or(0, |i| i + 1);
+        self.out[start..].chars().count()
+    }
+
+    /// Start a continuation line: the current indent plus two levels.
+    fn continuation(&mut self) {
+        self.nl();
+        self.write_indent();
+        self.out.push_str(INDENT);
+        self.out.push_str(INDENT);
+    }
+
+    /// What `f` writes, without adding it to the output.
+    fn render(&mut self, f: impl FnOnce(&mut Self)) -> String {
+        let saved = std::mem::take(&mut self.out);
+        f(self);
+        std::mem::replace(&mut self.out, saved)
+    }
+
     fn program(&mut self, prog: &Program) {
         if let Some(ref b) = prog.begin {
             self.keyword("BEGIN");
@@ -90,6 +151,12 @@ impl Pretty {
             self.block(b);
             self.nl();
         }
+        if let Some(ref b) = prog.error {
+            self.keyword("ERROR");
+            self.space();
+            self.block(b);
+            self.nl();
+        }
         for func in &prog.functions {
             self.func_def(func);
             self.nl();
@@ -115,7 +182,7 @@ impl Pretty {
                 self.out.push_str(&escape_regex(s));
                 self.out.push('/');
             }
-            Pattern::Expression(e) => self.expr(e, 0),
+            Pattern::Expression(e) => self.cond(e, " {".len()),
             Pattern::Range(a, b) => {
                 self.pattern(a);
                 self.out.push(',');
@@ -124,7 +191,7 @@ impl Pretty {
             }
             Pattern::Last(e) => {
                 self.out.push_str("last ");
-                self.expr(e, 0);
+                self.expr(e, ATOM);
             }
         }
     }
@@ -137,8 +204,14 @@ impl Pretty {
         }
         self.nl();
         self.indent += 1;
+        let pads = if self.align {
+            self.assignment_pads(block)
+        } else {
+            Vec::new()
+        };
         for (i, stmt) in block.iter().enumerate() {
             self.write_indent();
+            self.assign_pad = pads.get(i).copied().unwrap_or(0);
             self.stmt(stmt);
             if i + 1 < block.len() {
                 self.nl();
@@ -150,42 +223,67 @@ impl Pretty {
         self.out.push('}');
     }
 
+    /// Padding that lines up the `=` of each run of consecutive plain
+    /// assignment statements; 0 for every other statement.
+    fn assignment_pads(&mut self, block: &Block) -> Vec<usize> {
+        let widths: Vec<Option<usize>> = block
+            .iter()
+            .map(|s| match s {
+                Statement::Expression(Expr::Assign(target, _)) => {
+                    Some(self.render(|p| p.expr(target, ATOM)).chars().count())
+                }
+                _ => None,
+            })
+            .collect();
+        let mut pads = vec![0; block.len()];
+        let mut i = 0;
+        while i < widths.len() {
+            let run = widths[i..].iter().take_while(|w| w.is_some()).count();
+            if run >= 2 {
+                let widest = widths[i..i + run]
+                    .iter()
+                    .flatten()
+                    .max()
+                    .copied()
+                    .unwrap_or(0);
+                for j in i..i + run {
+                    pads[j] = widest - widths[j].unwrap_or(widest);
+                }
+            }
+            i += run.max(1);
+        }
+        pads
+    }
+
     fn keyword(&mut self, k: &str) {
         self.out.push_str(k);
     }
 
     fn stmt(&mut self, s: &Statement) {
+        let assign_pad = std::mem::take(&mut self.assign_pad);
         match s {
             Statement::Print(args, redir) => {
                 self.keyword("print");
-                for (i, a) in args.iter().enumerate() {
-                    if i > 0 {
-                        self.out.push(',');
-                        self.space();
-                    }
-                    self.expr(a, 0);
-                }
-                self.redirect(redir);
+                self.space();
+                self.print_body(args, redir);
             }
             Statement::Printf(args, redir) => {
                 self.keyword("printf");
-                for (i, a) in args.iter().enumerate() {
-                    if i > 0 {
-                        self.out.push(',');
-                        self.space();
-                    }
-                    self.expr(a, 0);
-                }
-                self.redirect(redir);
+                self.space();
+                self.print_body(args, redir);
             }
             Statement::If(cond, then_b, else_b) => {
                 self.keyword("if");
                 self.space();
                 self.out.push('(');
-                self.expr(cond, 0);
+                self.cond(cond, ") {".len());
                 self.out.push(')');
                 self.space();
-                if then_b.len() == 1 && !matches!(then_b[0], Statement::Block(_)) {
+                // A nested `if` keeps its braces so a following `else`
+                // cannot attach to it.
+                if then_b.len() == 1
+                    && !matches!(then_b[0], Statement::Block(_) | Statement::If(..))
+                {
                     self.stmt(&then_b[0]);
                 } else {
                     self.block(then_b);
@@ -205,7 +303,7 @@ impl Pretty {
                 self.keyword("while");
                 self.space();
                 self.out.push('(');
-                self.expr(cond, 0);
+                self.cond(cond, ") {".len());
                 self.out.push(')');
                 self.space();
                 if body.len() == 1 && !matches!(body[0], Statement::Block(_)) {
@@ -226,7 +324,7 @@ impl Pretty {
                 self.keyword("while");
                 self.space();
                 self.out.push('(');
-                self.expr(cond, 0);
+                self.cond(cond, ")".len());
                 self.out.push(')');
             }
             Statement::For(init, cond, update, body) => {
@@ -274,6 +372,7 @@ impl Pretty {
                         SortMode::NumDesc => "rnsort",
                         SortMode::ValAsc => "val",
                         SortMode::ValDesc => "rval",
+                        SortMode::Unsorted => "unsorted",
                     });
                 }
                 self.space();
@@ -313,26 +412,120 @@ impl Pretty {
             }
             Statement::Return(None) => self.keyword("return"),
             Statement::Block(b) => self.block(b),
+            Statement::Expression(Expr::Assign(l, r)) if assign_pad > 0 => {
+                self.expr(l, ATOM);
+                self.out.extend(std::iter::repeat_n(' ', assign_pad));
+                self.out.push_str(" = ");
+                self.expr(r, P_ASSIGN);
+            }
             Statement::Expression(e) => self.expr(e, 0),
         }
     }
 
+    /// Print/printf arguments and redirection, wrapped after commas when
+    /// the line would be too wide.
+    fn print_body(&mut self, args: &[Expr], redir: &Option<Redirect>) {
+        let start = self.out.len();
+        self.print_args(args);
+        self.redirect(redir);
+        if args.len() < 2 || self.line_width() <= self.width {
+            return;
+        }
+        self.out.truncate(start);
+        let pieces: Vec<String> = args
+            .iter()
+            .map(|a| {
+                self.render(|p| {
+                    p.in_print = true;
+                    p.expr(a, P_TERNARY);
+                    p.in_print = false;
+                })
+            })
+            .collect();
+        let tail = self.render(|p| p.redirect(redir));
+        for (i, piece) in pieces.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+                // The last argument keeps the redirection on its line; the
+                // others need room for their comma.
+                let after = if i + 1 == pieces.len() {
+                    tail.chars().count()
+                } else {
+                    1
+                };
+                if self.line_width() + 1 + piece.chars().count() + after > self.width {
+                    self.continuation();
+                } else {
+                    self.space();
+                }
+            }
+            self.out.push_str(piece);
+        }
+        self.out.push_str(&tail);
+    }
+
+    /// A condition followed by `tail` more characters on its line. When
+    /// that is too wide, an `&&` or `||` chain puts each operand on its
+    /// own line, breaking after the operator.
+    fn cond(&mut self, e: &Expr, tail: usize) {
+        let start = self.out.len();
+        self.expr(e, 0);
+        if self.line_width() + tail <= self.width {
+            return;
+        }
+        let (op, mins) = match e {
+            Expr::LogicalAnd(..) => ("&&", (P_AND, P_IN)),
+            Expr::LogicalOr(..) => ("||", (P_OR, P_AND)),
+            _ => return,
+        };
+        let mut operands = Vec::new();
+        let mut left = e;
+        while let (Expr::LogicalAnd(l, r), "&&") | (Expr::LogicalOr(l, r), "||") = (left, op) {
+            operands.push(r.as_ref());
+            left = l;
+        }
+        operands.push(left);
+        operands.reverse();
+        self.out.truncate(start);
+        for (i, x) in operands.into_iter().enumerate() {
+            if i > 0 {
+                self.space();
+                self.out.push_str(op);
+                self.continuation();
+            }
+            self.expr(x, if i == 0 { mins.0 } else { mins.1 });
+        }
+    }
+
+    /// Arguments are parsed below assignment and `>` is a redirection.
+    fn print_args(&mut self, args: &[Expr]) {
+        self.in_print = true;
+        for (i, a) in args.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+                self.space();
+            }
+            self.expr(a, P_TERNARY);
+        }
+        self.in_print = false;
+    }
+
     fn redirect(&mut self, redir: &Option<Redirect>) {
         if let Some(r) = redir {
             self.space();
             match r {
                 Redirect::Overwrite(e) => {
                     self.out.push('>');
-                    self.expr(e, 0);
+                    self.expr(e, ATOM);
                 }
                 Redirect::Append(e) => {
                     self.out.push('>');
                     self.out.push('>');
-                    self.expr(e, 0);
+                    self.expr(e, ATOM);
                 }
                 Redirect::Pipe(e) => {
                     self.out.push('|');
-                    self.expr(e, 0);
+                    self.expr(e, ATOM);
                 }
             }
         }
@@ -355,7 +548,32 @@ impl Pretty {
         self.block(&f.body);
     }
 
-    fn expr(&mut self, e: &Expr, _prec: u8) {
+    /// Print `e`, parenthesized if it binds looser than `min` (see `prec`).
+    /// Inside print arguments a bare `>` would read as a redirection, so
+    /// those comparisons are always parenthesized.
+    fn expr(&mut self, e: &Expr, min: u8) {
+        let wrap = prec(e) < min
+            || (self.in_print && matches!(e, Expr::BinOp(_, BinOp::Gt | BinOp::Ge, _)));
+        if !wrap {
+            self.expr_inner(e);
+            return;
+        }
+        let in_print = std::mem::replace(&mut self.in_print, false);
+        self.out.push('(');
+        self.expr_inner(e);
+        self.out.push(')');
+        self.in_print = in_print;
+    }
+
+    fn binary(&mut self, l: &Expr, op: &str, r: &Expr, (lmin, rmin): (u8, u8)) {
+        self.expr(l, lmin);
+        self.space();
+        self.out.push_str(op);
+        self.space();
+        self.expr(r, rmin);
+    }
+
+    fn expr_inner(&mut self, e: &Expr) {
         match e {
             Expr::Field(sub) => {
                 self.out.push('$');
@@ -363,18 +581,17 @@ impl Pretty {
                     && *n >= 0.0
                     && n.fract() == 0.0
                 {
-                    self.out.push_str(&(*n as i64).to_string());
+                    self.number(*n);
                     return;
                 }
-                self.expr(sub, 0);
-            }
-            Expr::NumberLit(n) => {
-                if n.fract() == 0.0 && *n >= 0.0 {
-                    self.out.push_str(&(*n as i64).to_string());
-                } else {
-                    let _ = write!(self.out, "{}", n);
-                }
+                // `$` binds tighter than any operator: `$(NF - 1)`.
+                let atomic = matches!(
+                    sub.as_ref(),
+                    Expr::NumberLit(_) | Expr::StringLit(_) | Expr::Var(_) | Expr::Field(_)
+                );
+                self.expr(sub, if atomic { 0 } else { ATOM });
             }
+            Expr::NumberLit(n) => self.number(*n),
             Expr::StringLit(s) => {
                 self.out.push('"');
                 self.out.push_str(&escape_string(s));
@@ -388,87 +605,57 @@ impl Pretty {
                 self.out.push(']');
             }
             Expr::ArrayIn(expr, arr) => {
-                self.expr(expr, 0);
+                self.expr(expr, P_MATCH);
                 self.space();
                 self.keyword("in");
                 self.space();
                 self.out.push_str(arr);
             }
             Expr::BinOp(l, op, r) => {
-                self.expr(l, 0);
-                self.space();
-                self.out.push_str(binop_str(op));
-                self.space();
-                self.expr(r, 0);
-            }
-            Expr::LogicalAnd(l, r) => {
-                self.expr(l, 0);
-                self.space();
-                self.out.push_str("&&");
-                self.space();
-                self.expr(r, 0);
-            }
-            Expr::LogicalOr(l, r) => {
-                self.expr(l, 0);
-                self.space();
-                self.out.push_str("||");
-                self.space();
-                self.expr(r, 0);
+                let mins = match op {
+                    BinOp::Add | BinOp::Sub => (P_ADD, P_MUL),
+                    BinOp::Mul | BinOp::Div | BinOp::Mod => (P_MUL, P_POW),
+                    BinOp::Pow => (P_UNARY, P_POW),
+                    _ => (P_CMP, P_CONCAT),
+                };
+                self.binary(l, binop_str(op), r, mins);
             }
+            Expr::LogicalAnd(l, r) => self.binary(l, "&&", r, (P_AND, P_IN)),
+            Expr::LogicalOr(l, r) => self.binary(l, "||", r, (P_OR, P_AND)),
             Expr::LogicalNot(x) => {
                 self.out.push('!');
-                self.expr(x, 0);
-            }
-            Expr::Match(l, r) => {
-                self.expr(l, 0);
-                self.space();
-                self.out.push('~');
-                self.space();
-                self.expr(r, 0);
-            }
-            Expr::NotMatch(l, r) => {
-                self.expr(l, 0);
-                self.space();
-                self.out.push_str("!~");
-                self.space();
-                self.expr(r, 0);
-            }
-            Expr::Assign(l, r) => {
-                self.expr(l, 0);
-                self.space();
-                self.out.push('=');
-                self.space();
-                self.expr(r, 0);
+                self.expr(x, P_UNARY);
             }
+            Expr::Match(l, r) => self.binary(l, "~", r, (P_CMP, ATOM)),
+            Expr::NotMatch(l, r) => self.binary(l, "!~", r, (P_CMP, ATOM)),
+            Expr::Assign(l, r) => self.binary(l, "=", r, (ATOM, P_ASSIGN)),
             Expr::CompoundAssign(l, op, r) => {
-                self.expr(l, 0);
-                self.space();
-                self.out.push_str(compound_assign_str(op));
-                self.space();
-                self.expr(r, 0);
+                self.binary(l, compound_assign_str(op), r, (ATOM, P_ASSIGN))
             }
             Expr::Increment(x, true) => {
                 self.out.push_str("++");
-                self.expr(x, 0);
+                self.expr(x, P_POSTFIX);
             }
             Expr::Increment(x, false) => {
-                self.expr(x, 0);
+                self.expr(x, ATOM);
                 self.out.push_str("++");
             }
             Expr::Decrement(x, true) => {
                 self.out.push_str("--");
-                self.expr(x, 0);
+                self.expr(x, P_POSTFIX);
             }
             Expr::Decrement(x, false) => {
-                self.expr(x, 0);
+                self.expr(x, ATOM);
                 self.out.push_str("--");
             }
             Expr::UnaryMinus(x) => {
                 self.out.push('-');
-                self.expr(x, 0);
+                // `- -x` must not lex as `--x`.
+                let min = if starts_with_minus(x) { NEVER } else { P_UNARY };
+                self.expr(x, min);
             }
             Expr::TryVal(x) => {
-                self.expr(x, 0);
+                self.expr(x, P_POSTFIX);
                 self.out.push('?');
             }
             Expr::NullFence(x) => {
@@ -477,78 +664,173 @@ impl Pretty {
                 self.out.push(')');
             }
             Expr::Concat(l, r) => {
-                self.expr(l, 0);
+                let start = self.out.len();
+                self.expr(l, P_CONCAT);
+                let mid = self.out.len();
                 self.space();
-                self.expr(r, 0);
+                // The right operand must start like a concatenation operand
+                // (not `-`, `++`, `getline`).
+                let min = if concat_start(r) { P_ADD } else { NEVER };
+                self.expr(r, min);
+                // `x (y)` would parse as a call to x: write `(x) (y)`.
+                let ident_end =
+                    self.out[..mid].ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_');
+                if ident_end && self.out[mid + 1..].starts_with('(') {
+                    self.out.insert(mid, ')');
+                    self.out.insert(start, '(');
+                }
             }
             Expr::Ternary(cond, then_e, else_e) => {
-                self.expr(cond, 0);
+                self.expr(cond, P_COALESCE);
                 self.space();
                 self.out.push('?');
                 self.space();
-                self.expr(then_e, 0);
+                // Parenthesize a nested ternary in the middle; chains in the
+                // else branch read naturally.
+                self.expr(then_e, P_COALESCE);
                 self.space();
                 self.out.push(':');
                 self.space();
                 self.expr(else_e, 0);
             }
-            Expr::NullCoalesce(l, r) => {
-                self.expr(l, 0);
-                self.space();
-                self.out.push_str("??");
-                self.space();
-                self.expr(r, 0);
-            }
+            Expr::NullCoalesce(l, r) => self.binary(l, "??", r, (P_COALESCE, P_OR)),
             Expr::Sprintf(args) => {
                 self.out.push_str("sprintf");
-                self.out.push('(');
-                for (i, a) in args.iter().enumerate() {
-                    if i > 0 {
-                        self.out.push(',');
-                        self.space();
-                    }
-                    self.expr(a, 0);
-                }
-                self.out.push(')');
+                self.args(args);
             }
             Expr::FuncCall(name, args) => {
                 self.out.push_str(name);
-                self.out.push('(');
-                for (i, a) in args.iter().enumerate() {
-                    if i > 0 {
-                        self.out.push(',');
-                        self.space();
-                    }
-                    self.expr(a, 0);
-                }
-                self.out.push(')');
+                self.args(args);
             }
             Expr::Getline(var, source) => {
                 self.keyword("getline");
                 if let Some(v) = var {
                     self.space();
-                    self.out.push_str(v);
+                    self.expr(v, ATOM);
                 }
                 if let Some(src) = source {
                     self.space();
                     self.out.push('<');
                     self.space();
-                    self.expr(src, 0);
+                    self.expr(src, ATOM);
                 }
             }
             Expr::GetlinePipe(cmd, var) => {
-                self.expr(cmd, 0);
+                self.expr(cmd, P_CMP);
                 self.space();
                 self.out.push('|');
                 self.space();
                 self.keyword("getline");
                 if let Some(v) = var {
                     self.space();
-                    self.out.push_str(v);
+                    self.expr(v, ATOM);
                 }
             }
         }
     }
+
+    fn args(&mut self, args: &[Expr]) {
+        let in_print = std::mem::replace(&mut self.in_print, false);
+        self.out.push('(');
+        for (i, a) in args.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+                self.space();
+            }
+            self.expr(a, 0);
+        }
+        self.out.push(')');
+        self.in_print = in_print;
+    }
+
+    fn number(&mut self, n: f64) {
+        if n.fract() == 0.0 && n.abs() < 1e15 {
+            let _ = write!(self.out, "{}", n as i64);
+        } else {
+            let _ = write!(self.out, "{}", n);
+        }
+    }
+}
+
+// Binding strength, loosest first, mirroring the parser's descent:
+// assignment < ternary < ?? < || < && < in < ~ < comparison < concatenation
+// < additive < multiplicative < ** < unary < postfix < atoms.
+const P_ASSIGN: u8 = 1;
+const P_TERNARY: u8 = 2;
+const P_COALESCE: u8 = 3;
+const P_OR: u8 = 4;
+const P_AND: u8 = 5;
+const P_IN: u8 = 6;
+const P_MATCH: u8 = 7;
+const P_CMP: u8 = 8;
+const P_CONCAT: u8 = 9;
+const P_ADD: u8 = 10;
+const P_MUL: u8 = 11;
+const P_POW: u8 = 12;
+const P_UNARY: u8 = 13;
+const P_POSTFIX: u8 = 14;
+const ATOM: u8 = 15;
+/// Forces parentheses around any operand.
+const NEVER: u8 = 16;
+
+fn prec(e: &Expr) -> u8 {
+    match e {
+        Expr::Assign(..) | Expr::CompoundAssign(..) => P_ASSIGN,
+        // `getline var` swallows a following operator's operand.
+        Expr::Ternary(..) | Expr::Getline(_, None) => P_TERNARY,
+        Expr::NullCoalesce(..) => P_COALESCE,
+        Expr::LogicalOr(..) => P_OR,
+        Expr::LogicalAnd(..) => P_AND,
+        Expr::ArrayIn(..) => P_IN,
+        Expr::Match(..) | Expr::NotMatch(..) | Expr::GetlinePipe(..) => P_MATCH,
+        Expr::BinOp(_, op, _) => match op {
+            BinOp::Add | BinOp::Sub => P_ADD,
+            BinOp::Mul | BinOp::Div | BinOp::Mod => P_MUL,
+            BinOp::Pow => P_POW,
+            _ => P_CMP,
+        },
+        Expr::Concat(..) => P_CONCAT,
+        Expr::UnaryMinus(_)
+        | Expr::LogicalNot(_)
+        | Expr::Increment(_, true)
+        | Expr::Decrement(_, true) => P_UNARY,
+        Expr::NumberLit(n) if *n < 0.0 => P_UNARY,
+        Expr::Increment(_, false) | Expr::Decrement(_, false) | Expr::TryVal(_) => P_POSTFIX,
+        _ => ATOM,
+    }
+}
+
+/// The expression whose first token starts the printed form of `e`
+/// (when `e` itself is not parenthesized).
+fn leftmost(e: &Expr) -> &Expr {
+    match e {
+        Expr::BinOp(l, _, _)
+        | Expr::LogicalAnd(l, _)
+        | Expr::LogicalOr(l, _)
+        | Expr::Concat(l, _)
+        | Expr::Match(l, _)
+        | Expr::NotMatch(l, _)
+        | Expr::Assign(l, _)
+        | Expr::CompoundAssign(l, _, _)
+        | Expr::Ternary(l, _, _)
+        | Expr::NullCoalesce(l, _)
+        | Expr::ArrayIn(l, _)
+        | Expr::GetlinePipe(l, _)
+        | Expr::Increment(l, false)
+        | Expr::Decrement(l, false)
+        | Expr::TryVal(l) => leftmost(l),
+        _ => e,
+    }
+}
+
+fn starts_with_minus(e: &Expr) -> bool {
+    matches!(leftmost(e), Expr::UnaryMinus(_) | Expr::Decrement(_, true))
+        || matches!(leftmost(e), Expr::NumberLit(n) if *n < 0.0)
+}
+
+/// Whether the parser would continue a concatenation with `e`.
+fn concat_start(e: &Expr) -> bool {
+    !starts_with_minus(e) && !matches!(leftmost(e), Expr::Increment(_, true) | Expr::Getline(..))
 }
 
 fn binop_str(op: &BinOp) -> &'static str {
@@ -594,11 +876,18 @@ fn escape_string(s: &str) -> String {
     out
 }
 
+/// The lexer keeps regex escapes verbatim, so only a bare `/` needs one.
 fn escape_regex(s: &str) -> String {
     let mut out = String::with_capacity(s.len());
-    for c in s.chars() {
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
         match c {
-            '\\' => out.push_str("\\\\"),
+            '\\' => {
+                out.push(c);
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+            }
             '/' => out.push_str("\\/"),
             _ => out.push(c),
         }
@@ -608,7 +897,7 @@ fn escape_regex(s: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::format_program;
+    use super::{FormatOptions, format_program, format_program_with};
 
     #[test]
     fn format_simple_program() {
@@ -627,4 +916,191 @@ mod tests {
         assert!(out.contains("END"));
         assert!(out.contains('\n'));
     }
+
+    #[test]
+    fn format_keeps_computed_field_parens() {
+        let out = format_program("{ $(NF-1) = $NF; print $i+1 }").unwrap();
+        assert!(out.contains("$(NF - 1) = $NF"), "{out}");
+        assert!(out.contains("print $i + 1"), "{out}");
+    }
+
+    #[test]
+    fn format_keeps_precedence_and_nesting() {
+        let cases = [
+            ("{ x = (1 + 2) * 3 }", "x = (1 + 2) * 3"),
+            ("{ print (a > 1), b }", "print (a > 1), b"),
+            ("{ y = (x) (\"k\" in h) }", "y = (x) (\"k\" in h)"),
+            ("{ print -(-x), 2 - (3 - 1) }", "print -(-x), 2 - (3 - 1)"),
+            (
+                "{ b = a ? (c ? 1 : 2) : d ? 3 : 4 }",
+                "b = a ? (c ? 1 : 2) : d ? 3 : 4",
+            ),
+            (
+                "{ while ((getline line) > 0) n++ }",
+                "while ((getline line) > 0) n++",
+            ),
+            ("/a\\/b\\./ { print }", "/a\\/b\\./ {"),
+        ];
+        for (src, want) in cases {
+            let once = format_program(src).unwrap();
+            assert!(once.contains(want), "{src}: {once}");
+            assert_eq!(format_program(&once).unwrap(), once, "{src}");
+        }
+        let nested = format_program(
+            "function f(a,   t) { t = a * 2\n if (t > 2) if (a) return t; else return -t }",
+        )
+        .unwrap();
+        assert!(
+            nested.contains("if (t > 2) {\n    if (a) return t else return -t\n  }"),
+            "{nested}"
+        );
+        assert_eq!(format_program(&nested).unwrap(), nested);
+    }
+
+    /// A log-report script with long conditions and argument lists.
+    const REPORT: &str = r#"BEGIN { FS = ","; OFS = "\t"; total = 0; errors = 0; warnings = 0; longest_name = "" }
+$3 == "ERROR" && $4 != "" && $5 !~ /^(healthcheck|metrics|readiness)$/ && length($6) > 0 { errors++; by_host[$2]++ }
+{ if (($3 == "WARN" || $3 == "WARNING") && $7 > threshold && !($2 in ignored_hosts) && NR > skip_lines) warnings++ }
+{ printf "%-20s %-10s %8.2f %8.2f %s %s\n", $1, $2, $7 / 1000, $8 * 100 / ($7 + 1), toupper(substr($3, 1, 1)), $9 > ("/tmp/report-" $2 ".txt") }
+END { print "summary", "errors=" errors, "warnings=" warnings, "hosts=" length(by_host), "ratio=" (errors + warnings) / NR, "threshold=" threshold }"#;
+
+    #[test]
+    fn format_wraps_long_lines_at_width() {
+        let out = format_program(REPORT).unwrap();
+        let want = r#"BEGIN {
+  FS = ","
+  OFS = "\t"
+  total = 0
+  errors = 0
+  warnings = 0
+  longest_name = ""
+}
+$3 == "ERROR" && $4 != "" && $5 !~ "^(healthcheck|metrics|readiness)$" && length($6) > 0 {
+  errors++
+  by_host[$2]++
+}
+{
+  if (($3 == "WARN" || $3 == "WARNING") &&
+      $7 > threshold &&
+      !($2 in ignored_hosts) &&
+      NR > skip_lines) warnings++
+}
+{
+  printf "%-20s %-10s %8.2f %8.2f %s %s\n", $1, $2, $7 / 1000, $8 * 100 / ($7 + 1),
+      toupper(substr($3, 1, 1)), $9 >("/tmp/report-" $2 ".txt")
+}
+END {
+  print "summary", "errors=" errors, "warnings=" warnings, "hosts=" length(by_host),
+      "ratio=" (errors + warnings) / NR, "threshold=" threshold
+}"#;
+        assert_eq!(out, want);
+        assert!(out.lines().all(|l| l.chars().count() <= 100), "{out}");
+        assert_eq!(format_program(&out).unwrap(), out);
+    }
+
+    #[test]
+    fn format_aligns_assignments_when_asked() {
+        let opts = FormatOptions {
+            width: 60,
+            align_assignments: true,
+        };
+        let out = format_program_with(REPORT, &opts).unwrap();
+        assert!(
+            out.starts_with(
+                "BEGIN {\n  FS           = \",\"\n  OFS          = \"\\t\"\n  total        = 0\n"
+            ),
+            "{out}"
+        );
+        assert!(
+            out.contains("$3 == \"ERROR\" &&\n    $4 != \"\" &&\n    $5 !~"),
+            "{out}"
+        );
+        assert!(out.contains("  errors++\n  by_host[$2]++\n"), "{out}");
+        assert_eq!(format_program_with(&out, &opts).unwrap(), out);
+        // A lone assignment, or one after a call, is not padded.
+        let out = format_program_with("{ x = 1; f(); longer = 2 }", &opts).unwrap();
+        assert_eq!(out, "{\n  x = 1\n  f()\n  longer = 2\n}");
+    }
+
+    /// Program literals passed to `eval(` / `eval_with_header(` in the
+    /// unit-test suite: the formatter's idempotency corpus.
+    fn corpus() -> Vec<String> {
+        let src = include_str!("../tests.rs");
+        let mut out = Vec::new();
+        for (at, _) in src.match_indices("eval") {
+            let rest = &src[at..];
+            let Some(rest) = rest
+                .strip_prefix("eval(")
+                .or_else(|| rest.strip_prefix("eval_with_header("))
+            else {
+                continue;
+            };
+            if let Some(lit) = rust_string_literal(rest.trim_start()) {
+                out.push(lit);
+            }
+        }
+        out
+    }
+
+    /// Decode the Rust string literal at the start of `s` (plain or raw).
+    fn rust_string_literal(s: &str) -> Option<String> {
+        if let Some(raw) = s.strip_prefix('r') {
+            let hashes = raw.len() - raw.trim_start_matches('#').len();
+            let body = raw[hashes..].strip_prefix('"')?;
+            let close = format!("\"{}", "#".repeat(hashes));
+            return Some(body[..body.find(&close)?].to_string());
+        }
+        let mut chars = s.strip_prefix('"')?.chars();
+        let mut out = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return Some(out),
+                '\\' => match chars.next()? {
+                    'n' => out.push('\n'),
+                    't' => out.push('\t'),
+                    'r' => out.push('\r'),
+                    '0' => out.push('\0'),
+                    '\n' => {
+                        let rest = chars.as_str().trim_start();
+                        chars = rest.chars();
+                    }
+                    other => out.push(other),
+                },
+                c => out.push(c),
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn format_is_idempotent_over_test_corpus() {
+        let corpus = corpus();
+        assert!(corpus.len() > 300, "corpus too small: {}", corpus.len());
+        let narrow = FormatOptions {
+            width: 30,
+            align_assignments: true,
+        };
+        let mut failures = Vec::new();
+        for (prog, opts) in corpus
+            .iter()
+            .flat_map(|p| [(p, FormatOptions::default()), (p, narrow.clone())])
+        {
+            let Ok(once) = format_program_with(prog, &opts) else {
+                continue;
+            };
+            match format_program_with(&once, &opts) {
+                Ok(twice) if twice == once => {}
+                Ok(twice) => {
+                    failures.push(format!("{prog}\n--- once:\n{once}\n--- twice:\n{twice}"))
+                }
+                Err(e) => failures.push(format!("{prog}\n--- once:\n{once}\n--- reparse: {e}")),
+            }
+        }
+        assert!(
+            failures.is_empty(),
+            "{} not idempotent:\n\n{}",
+            failures.len(),
+            failures.join("\n\n")
+        );
+    }
 }
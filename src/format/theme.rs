@@ -41,7 +41,7 @@ pub trait Theme {
 /// Built-in variable names (constants) — get a distinct style from user identifiers.
 const BUILTIN_VARS: &[&str] = &[
     "ARGC", "ARGV", "CONVFMT", "ENVIRON", "FILENAME", "FNR", "FS", "NF", "NR", "OFMT", "OFS",
-    "ORS", "RS", "SUBSEP",
+    "ORS", "PROCINFO", "RS", "SUBSEP",
 ];
 
 /// Default ANSI theme for terminal output.
@@ -192,6 +192,7 @@ pub fn token_style(t: &Token) -> Style {
         | Token::Question
         | Token::NullCoalesce
         | Token::Colon
+        | Token::ColonColon
         | Token::Append
         | Token::Pipe => Style::Operator,
 
@@ -0,0 +1,319 @@
+//! `@include "name"`: splice another program source in at top level.
+//!
+//! A small standard library is compiled into the binary, so
+//! `@include "std/assert"` works wherever fk is installed. Other names
+//! are files: the name as given (also with `.fk` or `.awk` appended), then
+//! the same in each directory of `FKPATH`. Each source is included once;
+//! a repeat is skipped, which also ends include cycles.
+//!
+//! Expansion runs on the token stream before namespace resolution. An
+//! included source starts in the default namespace and the includer's
+//! namespace is restored after it, as with `-f` files. Its tokens carry
+//! the position of the `@include`, so errors in it point there.
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::{FkError, Span};
+use crate::lexer::{Lexer, Spanned, Token};
+use crate::namespace::DEFAULT_NAMESPACE;
+
+/// A library module embedded in the binary.
+pub struct StdModule {
+    pub name: &'static str,
+    pub source: &'static str,
+}
+
+/// The embedded modules, by name.
+pub const STD_MODULES: &[StdModule] = &[
+    StdModule {
+        name: "std/assert",
+        source: include_str!("std/assert.fk"),
+    },
+    StdModule {
+        name: "std/dates",
+        source: include_str!("std/dates.fk"),
+    },
+    StdModule {
+        name: "std/strings",
+        source: include_str!("std/strings.fk"),
+    },
+];
+
+impl StdModule {
+    /// The module's one-line description: its first comment line, after
+    /// the name and dash.
+    pub fn summary(&self) -> &'static str {
+        let first = self.source.lines().next().unwrap_or("");
+        first.split_once(" — ").map_or("", |(_, s)| s)
+    }
+
+    /// The functions it defines, qualified with its namespace.
+    pub fn functions(&self) -> Vec<String> {
+        let mut ns = DEFAULT_NAMESPACE;
+        let mut out = Vec::new();
+        for line in self.source.lines() {
+            if let Some(rest) = line.strip_prefix("@namespace \"") {
+                ns = rest.trim_end().trim_end_matches('"');
+            } else if let Some(rest) = line.strip_prefix("function ")
+                && let Some((name, _)) = rest.split_once('(')
+            {
+                out.push(format!("{}::{}", ns, name));
+            }
+        }
+        out
+    }
+}
+
+/// `fk --list-std`: each embedded module, its summary and its functions.
+pub fn print_std() {
+    let mut out = String::new();
+    for m in STD_MODULES {
+        out.push_str(&format!("{:<12} {}\n", m.name, m.summary()));
+        out.push_str(&format!("{:<12} {}\n", "", m.functions().join(" ")));
+    }
+    // `fk --list-std | head -1` closes the pipe early; that is fine.
+    let _ = std::io::stdout().write_all(out.as_bytes());
+}
+
+/// Expand the `@include` directives in `tokens`, searching `FKPATH`.
+/// Returns the tokens and the positions of the top-level directives,
+/// which every included token now carries.
+pub fn expand(tokens: Vec<Spanned>) -> Result<(Vec<Spanned>, Vec<Span>), FkError> {
+    if !tokens
+        .windows(2)
+        .any(|w| is_directive(&w[0].token, &w[1].token))
+    {
+        return Ok((tokens, Vec::new()));
+    }
+    let search: Vec<PathBuf> = std::env::var_os("FKPATH")
+        .map(|p| std::env::split_paths(&p).collect())
+        .unwrap_or_default();
+    expand_in(tokens, &search)
+}
+
+/// `expand` with the given search directories.
+fn expand_in(
+    tokens: Vec<Spanned>,
+    search: &[PathBuf],
+) -> Result<(Vec<Spanned>, Vec<Span>), FkError> {
+    let mut out = Vec::with_capacity(tokens.len());
+    let mut sites = Vec::new();
+    let mut seen = HashSet::new();
+    splice(tokens, search, &mut seen, &mut out, Some(&mut sites))?;
+    Ok((out, sites))
+}
+
+fn is_directive(at: &Token, word: &Token) -> bool {
+    *at == Token::At && word.as_ident_str() == Some("include")
+}
+
+/// Copy `tokens` to `out`, replacing each `@include` with the source it
+/// names. `sites` collects the directive positions at the top level.
+/// Returns the brace depth at the end.
+fn splice(
+    tokens: Vec<Spanned>,
+    search: &[PathBuf],
+    seen: &mut HashSet<String>,
+    out: &mut Vec<Spanned>,
+    mut sites: Option<&mut Vec<Span>>,
+) -> Result<usize, FkError> {
+    let mut ns = DEFAULT_NAMESPACE.to_string();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < tokens.len() {
+        let span = tokens[i].span;
+        let next = tokens.get(i + 1).map(|t| &t.token);
+        match &tokens[i].token {
+            Token::LBrace => depth += 1,
+            Token::RBrace => depth = depth.saturating_sub(1),
+            Token::At if next.and_then(Token::as_ident_str) == Some("namespace") => {
+                if let Some(Token::StringLit(name)) = tokens.get(i + 2).map(|t| &t.token) {
+                    ns = name.clone();
+                }
+            }
+            Token::At if next.and_then(Token::as_ident_str) == Some("include") => {
+                if depth > 0 {
+                    return Err(FkError::new(span, "@include is only allowed at top level"));
+                }
+                let name = match tokens.get(i + 2).map(|t| &t.token) {
+                    Some(Token::StringLit(name)) => name.clone(),
+                    _ => {
+                        let msg = "@include needs a name string, as in @include \"std/assert\"";
+                        return Err(FkError::new(span, msg));
+                    }
+                };
+                i += 3;
+                let (key, source) = load(&name, search)
+                    .map_err(|e| FkError::new(span, format!("@include \"{}\": {}", name, e)))?;
+                if !seen.insert(key) {
+                    continue;
+                }
+                let mut included = Lexer::new(&source)
+                    .tokenize()
+                    .map_err(|e| FkError::new(span, format!("in @include \"{}\": {}", name, e)))?;
+                included.retain(|t| t.token != Token::Eof);
+                let start = out.len();
+                // The source starts in the default namespace; the
+                // includer's is restored after it.
+                out.extend(namespace_directive(DEFAULT_NAMESPACE, span));
+                if splice(included, search, seen, out, None)? > 0 {
+                    let msg = format!("in @include \"{}\": unclosed '{{'", name);
+                    return Err(FkError::new(span, msg));
+                }
+                out.push(Spanned {
+                    token: Token::Newline,
+                    span,
+                });
+                out.extend(namespace_directive(&ns, span));
+                for t in &mut out[start..] {
+                    t.span = span;
+                }
+                if let Some(sites) = sites.as_deref_mut() {
+                    sites.push(span);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    Ok(depth)
+}
+
+/// `@namespace "name"` as tokens.
+fn namespace_directive(name: &str, span: Span) -> [Spanned; 3] {
+    let token = |token| Spanned { token, span };
+    [
+        token(Token::At),
+        token(Token::Ident("namespace".to_string())),
+        token(Token::StringLit(name.to_string())),
+    ]
+}
+
+/// The source `name` refers to, and a key that is the same for every
+/// way of naming it.
+fn load(name: &str, search: &[PathBuf]) -> Result<(String, String), String> {
+    if let Some(m) = STD_MODULES.iter().find(|m| m.name == name) {
+        return Ok((m.name.to_string(), m.source.to_string()));
+    }
+    let path = find(name, search).ok_or_else(|| {
+        if name.starts_with("std/") {
+            let known: Vec<&str> = STD_MODULES.iter().map(|m| m.name).collect();
+            format!("no such module (the library has {})", known.join(", "))
+        } else {
+            "not found (searched the current directory and FKPATH)".to_string()
+        }
+    })?;
+    let source = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let key = path.canonicalize().unwrap_or(path);
+    Ok((key.to_string_lossy().into_owned(), source))
+}
+
+/// The file for `name`: as given, or with `.fk` or `.awk` appended, first
+/// relative to the current directory, then to each search directory.
+fn find(name: &str, search: &[PathBuf]) -> Option<PathBuf> {
+    let mut dirs = vec![Path::new("")];
+    if !Path::new(name).is_absolute() {
+        dirs.extend(search.iter().map(PathBuf::as_path));
+    }
+    for dir in dirs {
+        for ext in ["", ".fk", ".awk"] {
+            let path = dir.join(format!("{}{}", name, ext));
+            if path.is_file() {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn tokens(src: &str) -> Vec<Spanned> {
+        Lexer::new(src).tokenize().unwrap()
+    }
+
+    fn lib(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fk_include_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, text) in files {
+            fs::write(dir.join(file), text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn every_std_module_parses_and_lists_its_functions() {
+        for m in STD_MODULES {
+            let program = crate::parser::Parser::new(tokens(m.source))
+                .parse()
+                .unwrap_or_else(|e| panic!("{}: {}", m.name, e));
+            let defined: Vec<&str> = program.functions.iter().map(|f| f.name.as_str()).collect();
+            assert_eq!(defined, m.functions(), "{}", m.name);
+            assert!(!m.summary().is_empty(), "{}", m.name);
+        }
+        let names: Vec<&str> = STD_MODULES.iter().map(|m| m.name).collect();
+        assert!(
+            names.windows(2).all(|w| w[0] < w[1]),
+            "keep STD_MODULES sorted"
+        );
+    }
+
+    #[test]
+    fn files_resolve_through_the_search_path_once() {
+        let dir = lib(
+            "search",
+            &[
+                ("twice.fk", "function twice(x) { return 2 * x }\n"),
+                ("a.awk", "@include \"b\"\nfunction fa() { return 1 }\n"),
+                ("b.fk", "@include \"a\"\nfunction fb() { return 2 }\n"),
+            ],
+        );
+        let src = "@include \"twice\"\n@include \"twice.fk\"\n@include \"a\"\nBEGIN { }";
+        let (out, sites) = expand_in(tokens(src), std::slice::from_ref(&dir)).unwrap();
+        let functions = out.iter().filter(|t| t.token == Token::Function).count();
+        assert_eq!(functions, 3);
+        // The repeat of "twice" adds nothing; included tokens sit at their
+        // directive.
+        assert_eq!(sites, [Span::new(1, 1), Span::new(3, 1)]);
+        assert!(
+            out.iter()
+                .all(|t| t.token != Token::Function || sites.contains(&t.span))
+        );
+        let begin = out.iter().find(|t| t.token == Token::Begin).unwrap();
+        assert_eq!(begin.span, Span::new(4, 1));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn bad_includes_are_reported_at_the_directive() {
+        let dir = lib("errors", &[("open.fk", "BEGIN { x = 1\n")]);
+        let search = std::slice::from_ref(&dir);
+        for (src, want) in [
+            (
+                "BEGIN { }\n@include \"nope\"",
+                "@include \"nope\": not found",
+            ),
+            ("@include \"std/nope\"", "the library has std/assert"),
+            ("@include \"open\"", "in @include \"open\": unclosed '{'"),
+            (
+                "BEGIN { @include \"std/dates\" }",
+                "only allowed at top level",
+            ),
+            ("@include x", "needs a name string"),
+        ] {
+            let err = expand_in(tokens(src), search).unwrap_err();
+            assert!(err.message.contains(want), "{src}: {}", err.message);
+        }
+        let err = expand_in(tokens("\n\n@include \"nope\""), search).unwrap_err();
+        assert_eq!(err.span, Span::new(3, 1));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
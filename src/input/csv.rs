@@ -1,6 +1,6 @@
 use std::io::{self, BufRead};
 
-use super::{Record, RecordReader};
+use super::{Record, RecordReader, strip_line_end};
 
 const MAX_FIELD_CONTINUATION_LINES: usize = 50;
 
@@ -9,19 +9,63 @@ const MAX_FIELD_CONTINUATION_LINES: usize = 50;
 /// Handles:
 /// - Comma-separated fields (configurable delimiter)
 /// - Double-quoted fields (embedded commas, newlines, escaped `""`)
-/// - CRLF and LF line endings
+/// - CRLF and LF line endings (a lone CR is data, as in every reader)
 ///
 /// Uses a single-pass parser: fields are parsed character by character from
 /// the input. When a quoted field spans multiple lines, additional lines are
 /// read on demand (up to `MAX_FIELD_CONTINUATION_LINES` to guard against
 /// malformed input with unclosed quotes).
+///
+/// TSV has no quoting convention of its own, so the tab reader also decodes
+/// the escapes `-o tsv` writes (`\t`, `\n`, `\r`, `\\`) unless built
+/// with `with_escapes(false)` (`--tsv-raw`).
+///
+/// A [`Dialect`] (from `CSVSEP`, `CSVQUOTE` and `CSVCOMMENT`) can swap the
+/// delimiter, change or turn off the quote character and skip comment lines.
 pub struct CsvReader {
-    delimiter: u8,
+    /// The delimiter the reader was built with, used when the dialect
+    /// names none.
+    base_delimiter: char,
+    delimiter: char,
+    quote: Option<char>,
+    comment: Option<char>,
+    unescape: bool,
+    preserve_cr: bool,
+    /// Characters of the record being parsed, kept between records.
+    chars: Vec<char>,
+}
+
+/// Overrides for the CSV/TSV reader, set from `CSVSEP` (`delimiter`, `None`
+/// keeps the reader's own), `CSVQUOTE` (`quote`, `None` turns quoting off)
+/// and `CSVCOMMENT` (lines starting with `comment` are skipped).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dialect {
+    pub delimiter: Option<char>,
+    pub quote: Option<char>,
+    pub comment: Option<char>,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect {
+            delimiter: None,
+            quote: Some('"'),
+            comment: None,
+        }
+    }
 }
 
 impl CsvReader {
     pub fn new(delimiter: u8) -> Self {
-        CsvReader { delimiter }
+        CsvReader {
+            base_delimiter: delimiter as char,
+            delimiter: delimiter as char,
+            quote: Some('"'),
+            comment: None,
+            unescape: false,
+            preserve_cr: false,
+            chars: Vec::new(),
+        }
     }
 
     /// Convenience constructor for standard CSV (comma-delimited).
@@ -29,24 +73,66 @@ impl CsvReader {
         Self::new(b',')
     }
 
-    /// Convenience constructor for TSV (tab-delimited).
+    /// Convenience constructor for TSV (tab-delimited, escapes decoded).
     pub fn tab() -> Self {
-        Self::new(b'\t')
+        Self::new(b'\t').with_escapes(true)
+    }
+
+    /// Decode (or keep verbatim) backslash escapes in each field.
+    pub fn with_escapes(mut self, on: bool) -> Self {
+        self.unescape = on;
+        self
     }
 }
 
 impl RecordReader for CsvReader {
     fn next_record(&mut self, reader: &mut dyn BufRead) -> io::Result<Option<Record>> {
-        let mut raw = String::new();
-        let bytes = reader.read_line(&mut raw)?;
-        if bytes == 0 {
-            return Ok(None);
+        let mut rec = Record::default();
+        Ok(self.read_into(reader, &mut rec)?.then_some(rec))
+    }
+
+    fn takes_dialect(&self) -> bool {
+        true
+    }
+
+    fn set_dialect(&mut self, dialect: &Dialect) {
+        self.delimiter = dialect.delimiter.unwrap_or(self.base_delimiter);
+        self.quote = dialect.quote;
+        self.comment = dialect.comment;
+    }
+
+    fn set_preserve_cr(&mut self, on: bool) {
+        self.preserve_cr = on;
+    }
+
+    /// Parse into `rec` in place: the raw text, the fields Vec and each
+    /// field String keep their capacity, so wide files stop allocating
+    /// once the first records have sized them.
+    fn read_into(&mut self, reader: &mut dyn BufRead, rec: &mut Record) -> io::Result<bool> {
+        let Record {
+            text: raw,
+            fields,
+            malformed,
+        } = rec;
+        *malformed = None;
+        loop {
+            raw.clear();
+            if reader.read_line(raw)? == 0 {
+                return Ok(false);
+            }
+            if self.comment.is_none() || raw.chars().next() != self.comment {
+                break;
+            }
         }
 
-        let delim = self.delimiter as char;
-        let mut fields: Vec<String> = Vec::new();
-        let mut field = String::new();
-        let mut chars: Vec<char> = raw.chars().collect();
+        let delim = self.delimiter;
+        let quote = self.quote;
+        let fields = fields.get_or_insert_with(Vec::new);
+        let mut nf = 0;
+        start_field(fields, nf);
+        let chars = &mut self.chars;
+        chars.clear();
+        chars.extend(raw.chars());
         let mut pos = 0;
         let mut at_field_start = true;
 
@@ -56,35 +142,35 @@ impl RecordReader for CsvReader {
             }
             let ch = chars[pos];
 
-            if ch == '"' && at_field_start {
+            if Some(ch) == quote && at_field_start {
                 pos += 1;
                 let mut continuations = 0;
                 loop {
                     if pos >= chars.len() {
                         if continuations >= MAX_FIELD_CONTINUATION_LINES {
+                            *malformed = Some("unclosed quote".to_string());
                             break;
                         }
-                        let mut cont = String::new();
-                        let n = reader.read_line(&mut cont)?;
+                        let start = raw.len();
+                        let n = reader.read_line(raw)?;
                         if n == 0 {
+                            *malformed = Some("unclosed quote".to_string());
                             break;
                         }
-                        raw.push_str(&cont);
-                        let ext: Vec<char> = cont.chars().collect();
-                        chars.extend(ext);
+                        chars.extend(raw[start..].chars());
                         continuations += 1;
                         continue;
                     }
-                    if chars[pos] == '"' {
-                        if pos + 1 < chars.len() && chars[pos + 1] == '"' {
-                            field.push('"');
+                    if Some(chars[pos]) == quote {
+                        if pos + 1 < chars.len() && Some(chars[pos + 1]) == quote {
+                            fields[nf].push(chars[pos]);
                             pos += 2;
                             continue;
                         }
                         pos += 1; // closing quote
                         break;
                     }
-                    field.push(chars[pos]);
+                    fields[nf].push(chars[pos]);
                     pos += 1;
                 }
                 at_field_start = false;
@@ -92,39 +178,66 @@ impl RecordReader for CsvReader {
             }
 
             if ch == delim {
-                fields.push(std::mem::take(&mut field));
+                nf += 1;
+                start_field(fields, nf);
                 at_field_start = true;
                 pos += 1;
                 continue;
             }
 
-            if ch == '\n' || ch == '\r' {
+            if ch == '\n' || (ch == '\r' && chars.get(pos + 1) == Some(&'\n')) {
                 break;
             }
 
-            field.push(ch);
+            fields[nf].push(ch);
             at_field_start = false;
             pos += 1;
         }
 
-        fields.push(field);
+        fields.truncate(nf + 1);
+        if self.unescape {
+            for f in fields.iter_mut().filter(|f| f.contains('\\')) {
+                *f = unescape_tsv(f);
+            }
+        }
 
-        strip_trailing_newline(&mut raw);
+        strip_line_end(raw, self.preserve_cr);
+        Ok(true)
+    }
+}
 
-        Ok(Some(Record {
-            text: raw,
-            fields: Some(fields),
-        }))
+/// Make `fields[i]` the next (empty) field, reusing a String left over
+/// from an earlier record when there is one.
+fn start_field(fields: &mut Vec<String>, i: usize) {
+    match fields.get_mut(i) {
+        Some(f) => f.clear(),
+        None => fields.push(String::new()),
     }
 }
 
-fn strip_trailing_newline(s: &mut String) {
-    if s.ends_with('\n') {
-        s.pop();
-        if s.ends_with('\r') {
-            s.pop();
+/// Undo TSV escaping: `\t`, `\n`, `\r` and `\\`. Any other backslash is
+/// kept as is.
+pub fn unescape_tsv(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
         }
     }
+    out
 }
 
 /// Parse a single (already-assembled) CSV/TSV line into fields.
@@ -241,6 +354,7 @@ mod tests {
         assert_eq!(f2[0], "3");
         assert_eq!(f2[1], "fine");
         assert_eq!(f2[2], "end");
+        assert!(r1.malformed.is_none() && r2.malformed.is_none());
     }
 
     #[test]
@@ -255,6 +369,7 @@ mod tests {
         assert_eq!(f1[0], "1");
         assert_eq!(f1[1], "x");
         assert!(f1[2].contains("no close"));
+        assert_eq!(r1.malformed.as_deref(), Some("unclosed quote"));
 
         assert!(reader.next_record(&mut cursor).unwrap().is_none());
     }
@@ -284,6 +399,29 @@ mod tests {
         assert_eq!(r2.fields.unwrap(), vec!["d", "e", "f"]);
     }
 
+    #[test]
+    fn dialect_sets_delimiter_quote_and_comment() {
+        let input = "# exported 2024\nname;note\n#skip;me\nann;\"x;y\"\n";
+        let mut reader = CsvReader::comma();
+        reader.set_dialect(&Dialect {
+            delimiter: Some(';'),
+            quote: None,
+            comment: Some('#'),
+        });
+        let mut cursor = Cursor::new(input.as_bytes());
+        let mut got = Vec::new();
+        while let Some(rec) = reader.next_record(&mut cursor).unwrap() {
+            got.push(rec.fields.unwrap());
+        }
+        assert_eq!(got, [vec!["name", "note"], vec!["ann", "\"x", "y\""]]);
+
+        // Back to the defaults: the reader's own delimiter and `"` quotes.
+        reader.set_dialect(&Dialect::default());
+        let mut cursor = Cursor::new("#a,\"b,c\"\n".as_bytes());
+        let rec = reader.next_record(&mut cursor).unwrap().unwrap();
+        assert_eq!(rec.fields.unwrap(), vec!["#a", "b,c"]);
+    }
+
     #[test]
     fn whitespace_preserved_in_fields() {
         let fields = parse_fields(" a , b , c ", b',');
@@ -370,13 +508,30 @@ mod tests {
     }
 
     #[test]
-    fn bare_cr_line_ending() {
+    fn bare_cr_is_data() {
+        // Only \n and \r\n end a record, as in the line reader; a lone CR
+        // used to end the fields but not the text, losing "c,d".
         let input = "a,b\rc,d\r";
         let mut cursor = Cursor::new(input.as_bytes());
         let mut reader = CsvReader::comma();
 
         let r1 = reader.next_record(&mut cursor).unwrap().unwrap();
+        assert_eq!(r1.text, input);
+        assert_eq!(r1.fields.unwrap(), vec!["a", "b\rc", "d\r"]);
+        assert!(reader.next_record(&mut cursor).unwrap().is_none());
+    }
+
+    #[test]
+    fn preserve_cr_keeps_it_in_the_text_only() {
+        let mut cursor = Cursor::new("a,b\r\nc,d\n".as_bytes());
+        let mut reader = CsvReader::comma();
+        reader.set_preserve_cr(true);
+
+        let r1 = reader.next_record(&mut cursor).unwrap().unwrap();
+        assert_eq!(r1.text, "a,b\r");
         assert_eq!(r1.fields.unwrap(), vec!["a", "b"]);
+        let r2 = reader.next_record(&mut cursor).unwrap().unwrap();
+        assert_eq!(r2.text, "c,d");
     }
 
     #[test]
@@ -434,6 +589,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tsv_escapes_are_decoded_unless_raw() {
+        let line = "a\\tb\tc\\\\td\tC:\\x\n";
+        let read = |reader: &mut CsvReader| {
+            let mut cursor = Cursor::new(line.as_bytes());
+            reader
+                .next_record(&mut cursor)
+                .unwrap()
+                .unwrap()
+                .fields
+                .unwrap()
+        };
+        assert_eq!(read(&mut CsvReader::tab()), ["a\tb", "c\\td", "C:\\x"]);
+        assert_eq!(
+            read(&mut CsvReader::tab().with_escapes(false)),
+            ["a\\tb", "c\\\\td", "C:\\x"]
+        );
+        assert_eq!(read(&mut CsvReader::comma()), [line.trim_end()]);
+    }
+
     #[test]
     fn tsv_with_quoted_tab() {
         let fields = parse_fields("a\t\"b\tc\"\td", b'\t');
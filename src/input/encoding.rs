@@ -0,0 +1,231 @@
+//! Input text encodings: a byte-order mark picks UTF-8 or UTF-16, and
+//! UTF-16 is transcoded to UTF-8 as it is read, so the record readers
+//! only ever see UTF-8. `--encoding` names the encoding of files without
+//! a BOM.
+
+use std::io::{self, Cursor, Read};
+
+/// How a source's bytes are decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Encoding {
+    /// Follow the BOM if there is one, else UTF-8.
+    #[default]
+    Auto,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Parse an `--encoding` name.
+    pub fn parse(name: &str) -> Option<Encoding> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "auto" => Some(Encoding::Auto),
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            _ => None,
+        }
+    }
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16BE_BOM: &[u8] = b"\xFE\xFF";
+
+/// Wrap `inner` so it reads as UTF-8 without a BOM. Also returns the
+/// length of a UTF-8 BOM that was dropped, so byte offsets can still
+/// count from the start of the file; UTF-16 offsets are in UTF-8 bytes.
+pub fn decode<R: Read + Send + 'static>(
+    mut inner: R,
+    encoding: Encoding,
+) -> io::Result<(Box<dyn Read + Send>, u64)> {
+    // Read only as far as a BOM could go, so a live pipe whose first line
+    // is short is not held up.
+    let mut head = Vec::with_capacity(UTF8_BOM.len());
+    while head.len() < UTF8_BOM.len()
+        && [UTF8_BOM, UTF16LE_BOM, UTF16BE_BOM]
+            .iter()
+            .any(|bom| bom.starts_with(&head) && bom.len() > head.len())
+    {
+        let mut byte = [0u8];
+        if inner.read(&mut byte)? == 0 {
+            break;
+        }
+        head.push(byte[0]);
+    }
+    let encoding = match encoding {
+        Encoding::Auto if head.starts_with(UTF16LE_BOM) => Encoding::Utf16Le,
+        Encoding::Auto if head.starts_with(UTF16BE_BOM) => Encoding::Utf16Be,
+        Encoding::Auto => Encoding::Utf8,
+        other => other,
+    };
+    let bom = match encoding {
+        Encoding::Utf16Le => UTF16LE_BOM,
+        Encoding::Utf16Be => UTF16BE_BOM,
+        _ => UTF8_BOM,
+    };
+    let dropped = if head.starts_with(bom) { bom.len() } else { 0 };
+    let rest = Cursor::new(head.split_off(dropped)).chain(inner);
+    Ok(match encoding {
+        Encoding::Utf16Le => (Box::new(Utf16Reader::new(rest, false)), 0),
+        Encoding::Utf16Be => (Box::new(Utf16Reader::new(rest, true)), 0),
+        _ => (Box::new(rest), dropped as u64),
+    })
+}
+
+/// Transcodes UTF-16 to UTF-8. Unpaired surrogates and a trailing odd
+/// byte become U+FFFD.
+struct Utf16Reader<R> {
+    inner: R,
+    big_endian: bool,
+    raw: Vec<u8>,
+    /// Decoded text not yet handed out, from `pos`.
+    out: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> Utf16Reader<R> {
+    fn new(inner: R, big_endian: bool) -> Self {
+        Utf16Reader {
+            inner,
+            big_endian,
+            raw: Vec::new(),
+            out: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    /// Decode the next chunk into `out`. A code unit split across reads,
+    /// or a high surrogate at the end of one, waits in `raw`.
+    fn fill(&mut self) -> io::Result<()> {
+        self.out.clear();
+        self.pos = 0;
+        let mut chunk = [0u8; 8192];
+        let n = self.inner.read(&mut chunk)?;
+        self.raw.extend_from_slice(&chunk[..n]);
+        self.done = n == 0;
+        let units: Vec<u16> = self
+            .raw
+            .chunks_exact(2)
+            .map(|b| {
+                if self.big_endian {
+                    u16::from_be_bytes([b[0], b[1]])
+                } else {
+                    u16::from_le_bytes([b[0], b[1]])
+                }
+            })
+            .collect();
+        let mut used = units.len();
+        if !self.done && units.last().is_some_and(|u| (0xD800..0xDC00).contains(u)) {
+            used -= 1;
+        }
+        let mut utf8 = [0u8; 4];
+        for c in char::decode_utf16(units[..used].iter().copied()) {
+            let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+            self.out
+                .extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+        }
+        self.raw.drain(..used * 2);
+        if self.done && !self.raw.is_empty() {
+            self.raw.clear();
+            let c = char::REPLACEMENT_CHARACTER;
+            self.out
+                .extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Utf16Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.out.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_as(bytes: Vec<u8>, encoding: Encoding) -> (String, u64) {
+        let (mut r, dropped) = decode(Cursor::new(bytes), encoding).unwrap();
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        (s, dropped)
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|u| {
+                if big_endian {
+                    u.to_be_bytes()
+                } else {
+                    u.to_le_bytes()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn boms_select_the_encoding_and_are_dropped() {
+        let text = "name,city\nZoë,Köln 🚲\n";
+        let mut utf8 = UTF8_BOM.to_vec();
+        utf8.extend_from_slice(text.as_bytes());
+        assert_eq!(read_as(utf8, Encoding::Auto), (text.to_string(), 3));
+        for big_endian in [false, true] {
+            let mut bytes = if big_endian { UTF16BE_BOM } else { UTF16LE_BOM }.to_vec();
+            bytes.extend(utf16(text, big_endian));
+            assert_eq!(read_as(bytes, Encoding::Auto), (text.to_string(), 0));
+        }
+        assert_eq!(
+            read_as(b"ab".to_vec(), Encoding::Auto),
+            ("ab".to_string(), 0)
+        );
+        assert_eq!(read_as(Vec::new(), Encoding::Auto), (String::new(), 0));
+    }
+
+    #[test]
+    fn named_encoding_reads_files_without_a_bom() {
+        let got = read_as(utf16("a\tb\n", false), Encoding::Utf16Le);
+        assert_eq!(got.0, "a\tb\n");
+        let got = read_as(utf16("a\tb\n", true), Encoding::Utf16Be);
+        assert_eq!(got.0, "a\tb\n");
+        // Without the override the NULs come through as they are.
+        assert_eq!(read_as(utf16("a", false), Encoding::Auto).0, "a\0");
+        assert_eq!(Encoding::parse("UTF_16LE"), Some(Encoding::Utf16Le));
+        assert_eq!(Encoding::parse("latin1"), None);
+    }
+
+    #[test]
+    fn split_units_and_bad_surrogates_decode_across_reads() {
+        /// Hands out one byte per read.
+        struct Trickle(Vec<u8>, usize);
+        impl Read for Trickle {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.1 == self.0.len() || buf.is_empty() {
+                    return Ok(0);
+                }
+                buf[0] = self.0[self.1];
+                self.1 += 1;
+                Ok(1)
+            }
+        }
+        let mut bytes = utf16("x🚲y", false);
+        bytes.extend_from_slice(&[0x00, 0xD8, b'z', 0x00, b'!']);
+        let mut r = Utf16Reader::new(Trickle(bytes, 0), false);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "x🚲y\u{FFFD}z\u{FFFD}");
+    }
+}
@@ -1,12 +1,70 @@
+use std::collections::HashMap;
 use std::io::{self, BufRead};
 
-use super::{Record, RecordReader};
+use super::{Record, RecordReader, strip_line_end};
 
 /// JSON Lines record reader.
 ///
-/// Each input line is a JSON object. Top-level string/number values become
-/// fields, keyed by insertion order.  The raw line is preserved as `$0`.
-pub struct JsonReader;
+/// Each input line is a JSON object and the raw line is preserved as `$0`.
+/// By default the top-level values become fields in the object's own
+/// document order, so `$2` is the second key of *that* line.
+///
+/// In keyed mode (`-H`) fields are laid out by key instead: the first
+/// object's keys in document order, then every new key in the order it is
+/// first seen. Each record gets one field per column seen so far, empty
+/// where its object lacks the key; later keys never move earlier ones.
+#[derive(Default)]
+pub struct JsonReader {
+    keyed: Option<Columns>,
+    preserve_cr: bool,
+}
+
+/// Column positions for keyed mode.
+#[derive(Default)]
+struct Columns {
+    index: HashMap<String, usize>,
+    names: Vec<String>,
+    /// Columns already handed out by `new_columns`.
+    reported: usize,
+}
+
+impl JsonReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fields by key name, with columns reported through `new_columns`.
+    pub fn keyed() -> Self {
+        JsonReader {
+            keyed: Some(Columns::default()),
+            preserve_cr: false,
+        }
+    }
+}
+
+impl Columns {
+    fn place(&mut self, line: &str) -> Vec<String> {
+        let Some(pairs) = parse_json_pairs(line) else {
+            return vec![line.trim().to_string()];
+        };
+        let mut fields = vec![String::new(); self.names.len()];
+        for (key, value) in pairs {
+            let i = match self.index.get(&key) {
+                Some(&i) => i,
+                None => {
+                    self.names.push(key.clone());
+                    self.index.insert(key, self.names.len() - 1);
+                    self.names.len() - 1
+                }
+            };
+            if i >= fields.len() {
+                fields.resize(i + 1, String::new());
+            }
+            fields[i] = value;
+        }
+        fields
+    }
+}
 
 impl RecordReader for JsonReader {
     fn next_record(&mut self, reader: &mut dyn BufRead) -> io::Result<Option<Record>> {
@@ -15,43 +73,125 @@ impl RecordReader for JsonReader {
         if bytes == 0 {
             return Ok(None);
         }
-        if line.ends_with('\n') {
-            line.pop();
-            if line.ends_with('\r') {
-                line.pop();
-            }
-        }
+        strip_line_end(&mut line, self.preserve_cr);
 
-        let fields = parse_json_fields(&line);
+        // A kept `\r` is part of `$0`, not of the object.
+        let object = match self.preserve_cr {
+            true => line.strip_suffix('\r').unwrap_or(&line),
+            false => &line,
+        };
+        let fields = match self.keyed.as_mut() {
+            Some(columns) => columns.place(object),
+            None => parse_json_fields(object),
+        };
+        let malformed = object_error(object);
         Ok(Some(Record {
             text: line,
             fields: Some(fields),
+            malformed,
         }))
     }
+
+    fn new_columns(&mut self) -> Vec<String> {
+        match self.keyed.as_mut() {
+            Some(c) if c.reported < c.names.len() => {
+                let fresh = c.names[c.reported..].to_vec();
+                c.reported = c.names.len();
+                fresh
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn set_preserve_cr(&mut self, on: bool) {
+        self.preserve_cr = on;
+    }
+}
+
+/// Why `s` is not a well-formed JSON object, or `None` if it is (blank
+/// lines included). Checks structure only: braces, brackets, strings and
+/// `"key": value` pairs at the top level.
+fn object_error(s: &str) -> Option<String> {
+    let t = s.trim();
+    if t.is_empty() {
+        return None;
+    }
+    if !t.starts_with('{') {
+        return Some("not a JSON object".to_string());
+    }
+    let mut depth: Vec<char> = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, ch) in t.char_indices() {
+        if in_string {
+            match ch {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match ch {
+            '"' => in_string = true,
+            '{' => depth.push('}'),
+            '[' => depth.push(']'),
+            '}' | ']' => {
+                if depth.pop() != Some(ch) {
+                    return Some(format!("unexpected '{}'", ch));
+                }
+                if depth.is_empty() && i + 1 < t.len() {
+                    return Some("trailing data after object".to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    if in_string {
+        return Some("unterminated string".to_string());
+    }
+    if !depth.is_empty() {
+        return Some("unterminated object".to_string());
+    }
+    for pair in split_top_level(&t[1..t.len() - 1]) {
+        let pair = pair.trim();
+        let ok = pair.starts_with('"')
+            && find_colon(pair)
+                .is_some_and(|c| pair[c + 1..].chars().any(|ch| !ch.is_whitespace()));
+        if !ok {
+            return Some(format!("expected \"key\": value, got '{}'", pair));
+        }
+    }
+    None
 }
 
-/// Minimal JSON object parser — extracts top-level key-value pairs as strings.
-/// Does not attempt full JSON compliance; handles the common case of flat
-/// objects with string and number values.
+/// Minimal JSON object parser — extracts top-level values as strings, in
+/// document order. Does not attempt full JSON compliance; handles the
+/// common case of flat objects with string and number values.
 fn parse_json_fields(s: &str) -> Vec<String> {
-    let trimmed = s.trim();
-    if !trimmed.starts_with('{') || !trimmed.ends_with('}') {
-        return vec![trimmed.to_string()];
+    match parse_json_pairs(s) {
+        Some(pairs) => pairs.into_iter().map(|(_, v)| v).collect(),
+        None => vec![s.trim().to_string()],
     }
+}
 
+/// Top-level `(key, value)` pairs in document order, or `None` if `s` is
+/// not an object. Nested objects and arrays are kept as raw JSON text.
+fn parse_json_pairs(s: &str) -> Option<Vec<(String, String)>> {
+    let trimmed = s.trim();
+    if !trimmed.starts_with('{') || !trimmed.ends_with('}') || trimmed.len() < 2 {
+        return None;
+    }
     let inner = &trimmed[1..trimmed.len() - 1];
-    let pairs = split_top_level(inner);
-    let mut fields = Vec::new();
-
-    for pair in pairs {
+    let mut pairs = Vec::new();
+    for pair in split_top_level(inner) {
         let pair = pair.trim();
         if let Some(colon) = find_colon(pair) {
-            let value = pair[colon + 1..].trim();
-            fields.push(unquote(value));
+            let key = unquote(&pair[..colon]);
+            pairs.push((key, unquote(&pair[colon + 1..])));
         }
     }
-
-    fields
+    Some(pairs)
 }
 
 /// Split a string by commas at the top level (not inside strings/objects/arrays).
@@ -89,11 +229,12 @@ fn split_top_level(s: &str) -> Vec<String> {
     parts
 }
 
-/// Find the colon separating key from value, respecting quoted strings.
+/// Byte offset of the colon separating key from value, respecting quoted
+/// strings.
 fn find_colon(s: &str) -> Option<usize> {
     let mut in_string = false;
     let mut prev = '\0';
-    for (i, ch) in s.chars().enumerate() {
+    for (i, ch) in s.char_indices() {
         if ch == '"' && prev != '\\' {
             in_string = !in_string;
         }
@@ -142,6 +283,30 @@ fn unquote(s: &str) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn malformed_lines_are_flagged() {
+        for ok in [
+            r#"{"a":1,"b":"x}"}"#,
+            "{}",
+            r#"{"n":{"m":[1,2]}}"#,
+            "",
+            r#"{"s":"\\\""}"#,
+        ] {
+            assert_eq!(object_error(ok), None, "{ok}");
+        }
+        for bad in [
+            r#"{"a":1"#,
+            r#"[1,2]"#,
+            r#"{"a":"open}"#,
+            r#"{"a" 1}"#,
+            r#"{"a":}"#,
+            r#"{"a":1}}"#,
+            "not json",
+        ] {
+            assert!(object_error(bad).is_some(), "{bad}");
+        }
+    }
+
     #[test]
     fn flat_object() {
         let fields = parse_json_fields(r#"{"name":"Alice","age":30}"#);
@@ -160,6 +325,33 @@ mod tests {
         assert_eq!(fields, vec!["x", r#"{"c":1}"#]);
     }
 
+    #[test]
+    fn keyed_mode_places_fields_by_key() {
+        let mut cols = Columns::default();
+        assert_eq!(cols.place(r#"{"user":"ann","ms":12}"#), ["ann", "12"]);
+        assert_eq!(
+            cols.place(r#"{"ms":7,"path":"/x","user":"bo"}"#),
+            ["bo", "7", "/x"]
+        );
+        assert_eq!(cols.place(r#"{"path":"/y"}"#), ["", "", "/y"]);
+        assert_eq!(cols.names, ["user", "ms", "path"]);
+
+        let mut reader = JsonReader::keyed();
+        let mut input: &[u8] = b"{\"a\":1}\n{\"b\":2,\"a\":3}\n";
+        reader.next_record(&mut input).unwrap();
+        assert_eq!(reader.new_columns(), ["a"]);
+        let rec = reader.next_record(&mut input).unwrap().unwrap();
+        assert_eq!(rec.fields.unwrap(), ["3", "2"]);
+        assert_eq!(reader.new_columns(), ["b"]);
+        assert!(reader.new_columns().is_empty());
+    }
+
+    #[test]
+    fn non_ascii_keys_split_on_the_right_colon() {
+        assert_eq!(parse_json_fields(r#"{"größe":"é:1"}"#), ["é:1"]);
+        assert_eq!(object_error(r#"{"größe":1}"#), None);
+    }
+
     #[test]
     fn non_object_becomes_single_field() {
         let fields = parse_json_fields("just a string");
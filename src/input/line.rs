@@ -1,19 +1,13 @@
 use std::io::{self, BufRead};
 
-use super::{Record, RecordReader};
+use super::{Record, RecordReader, strip_line_end};
 
 /// Default record reader: one record per line (awk's standard behaviour).
-/// Reuses a read buffer across records to minimise allocations.
+/// Lines are read straight into the caller's record text, so a reused
+/// `Record` costs no allocation once it is long enough.
+#[derive(Default)]
 pub struct LineReader {
-    buf: String,
-}
-
-impl Default for LineReader {
-    fn default() -> Self {
-        LineReader {
-            buf: String::with_capacity(256),
-        }
-    }
+    preserve_cr: bool,
 }
 
 impl LineReader {
@@ -24,18 +18,22 @@ impl LineReader {
 
 impl RecordReader for LineReader {
     fn next_record(&mut self, reader: &mut dyn BufRead) -> io::Result<Option<Record>> {
-        self.buf.clear();
-        let bytes = reader.read_line(&mut self.buf)?;
-        if bytes == 0 {
-            return Ok(None);
-        }
-        if self.buf.ends_with('\n') {
-            self.buf.pop();
-            if self.buf.ends_with('\r') {
-                self.buf.pop();
-            }
+        let mut rec = Record::default();
+        Ok(self.read_into(reader, &mut rec)?.then_some(rec))
+    }
+
+    fn read_into(&mut self, reader: &mut dyn BufRead, rec: &mut Record) -> io::Result<bool> {
+        rec.text.clear();
+        rec.fields = None;
+        rec.malformed = None;
+        if reader.read_line(&mut rec.text)? == 0 {
+            return Ok(false);
         }
-        let text = self.buf.clone();
-        Ok(Some(Record { text, fields: None }))
+        strip_line_end(&mut rec.text, self.preserve_cr);
+        Ok(true)
+    }
+
+    fn set_preserve_cr(&mut self, on: bool) {
+        self.preserve_cr = on;
     }
 }
@@ -1,20 +1,42 @@
 pub mod csv;
+pub mod encoding;
 pub mod json;
 pub mod line;
 #[cfg(feature = "parquet")]
 pub mod parquet_reader;
 pub mod regex_rs;
 
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
 
 /// A record returned by a `RecordReader`.
 ///
 /// `text` is the raw record text (becomes `$0`).
 /// `fields` is optionally pre-split fields — when `Some`, the runtime
 /// uses these directly instead of FS-based splitting.
+/// `malformed` is set by readers that could only parse the record on a
+/// best-effort basis (invalid JSON, unclosed CSV quote); it holds the reason.
+/// I/O failures are still reported as `Err`.
+///
+/// A `Record` can be refilled with [`RecordReader::read_into`], keeping the
+/// capacity of its text and field strings from one record to the next.
+#[derive(Default)]
 pub struct Record {
     pub text: String,
     pub fields: Option<Vec<String>>,
+    pub malformed: Option<String>,
+}
+
+/// Strip the line ending from a line read with `read_line`: `\n` or
+/// `\r\n`, or with `preserve_cr` (`--preserve-cr`) only the `\n`. Every
+/// reader ends records this way, so a `\r` anywhere else (an old Mac line
+/// end, or the end of a last line without a newline) is always data.
+pub fn strip_line_end(line: &mut String, preserve_cr: bool) {
+    if line.ends_with('\n') {
+        line.pop();
+        if !preserve_cr && line.ends_with('\r') {
+            line.pop();
+        }
+    }
 }
 
 /// Strategy for reading one record from a byte stream.
@@ -22,6 +44,36 @@ pub struct Record {
 /// CSV, TSV, and JSON readers override this.
 pub trait RecordReader {
     fn next_record(&mut self, reader: &mut dyn BufRead) -> io::Result<Option<Record>>;
+
+    /// Read the next record into `rec`, reusing its buffers. Returns false
+    /// at end of input. Readers that can fill a record in place override
+    /// this; the default moves in a fresh one from `next_record`.
+    fn read_into(&mut self, reader: &mut dyn BufRead, rec: &mut Record) -> io::Result<bool> {
+        match self.next_record(reader)? {
+            Some(r) => {
+                *rec = r;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Column names first seen since the last call, for readers that name
+    /// their own columns (JSON under `-H`).
+    fn new_columns(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Whether `set_dialect` means anything to this reader.
+    fn takes_dialect(&self) -> bool {
+        false
+    }
+
+    /// Take on a CSV dialect (`CSVSEP`, `CSVQUOTE`, `CSVCOMMENT`).
+    fn set_dialect(&mut self, _dialect: &csv::Dialect) {}
+
+    /// `--preserve-cr`: leave the `\r` of a `\r\n` line end in the text.
+    fn set_preserve_cr(&mut self, _on: bool) {}
 }
 
 /// A unified reader that iterates over records from stdin or a sequence of
@@ -29,8 +81,59 @@ pub trait RecordReader {
 pub struct Input {
     sources: Vec<Source>,
     current: usize,
-    reader: Option<Box<dyn BufRead>>,
+    reader: Option<Counted>,
     record_reader: Box<dyn RecordReader>,
+    keep_going: bool,
+    skipped: Vec<String>,
+    /// `--seek`: byte offset to start the first source at.
+    seek: Option<u64>,
+    /// Byte offset and length of the last record within its source.
+    record_start: u64,
+    record_len: u64,
+    /// Records read from the current source so far.
+    source_records: u64,
+    /// CSV dialect to apply when the next source is opened.
+    dialect: Option<csv::Dialect>,
+    /// Bytes consumed from the sources already finished.
+    done_bytes: u64,
+    /// `--encoding`: how sources without a BOM are decoded.
+    encoding: encoding::Encoding,
+}
+
+/// A source's reader, counting the bytes consumed from it so far
+/// (decompressed bytes for compressed files). While `capture` is set the
+/// consumed bytes are also kept there, so they can be read again.
+struct Counted {
+    inner: Box<dyn BufRead>,
+    pos: u64,
+    capture: Option<Vec<u8>>,
+}
+
+impl Read for Counted {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.pos += n as u64;
+        if let Some(kept) = self.capture.as_mut() {
+            kept.extend_from_slice(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+impl BufRead for Counted {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Some(kept) = self.capture.as_mut()
+            && let Ok(data) = self.inner.fill_buf()
+        {
+            kept.extend_from_slice(&data[..amt.min(data.len())]);
+        }
+        self.pos += amt as u64;
+        self.inner.consume(amt);
+    }
 }
 
 enum Source {
@@ -61,15 +164,117 @@ impl Input {
             current: 0,
             reader: None,
             record_reader,
+            keep_going: false,
+            skipped: Vec::new(),
+            seek: None,
+            record_start: 0,
+            record_len: 0,
+            source_records: 0,
+            dialect: None,
+            done_bytes: 0,
+            encoding: encoding::Encoding::Auto,
+        }
+    }
+
+    /// Use `dialect` for CSV/TSV sources from the next one opened on; the
+    /// source being read keeps the dialect it was opened with.
+    pub fn set_dialect(&mut self, dialect: csv::Dialect) {
+        self.dialect = Some(dialect);
+    }
+
+    /// Apply `dialect` to the current source straight away. When `rec` is
+    /// the source's first record, the source is rewound and it is read
+    /// again (with any comment lines before it); false if the source has
+    /// no record left under the new dialect.
+    pub fn redo_first_record(
+        &mut self,
+        dialect: csv::Dialect,
+        rec: &mut Record,
+    ) -> io::Result<bool> {
+        self.record_reader.set_dialect(&dialect);
+        self.dialect = Some(dialect);
+        let Some(reader) = self.reader.as_mut() else {
+            return Ok(true);
+        };
+        let kept = match reader.capture.take() {
+            Some(kept) if self.source_records == 1 => kept,
+            _ => return Ok(true),
+        };
+        reader.pos -= kept.len() as u64;
+        let rest = std::mem::replace(&mut reader.inner, Box::new(io::empty()));
+        reader.inner = Box::new(io::Cursor::new(kept).chain(rest));
+        let start = reader.pos;
+        let read = self.record_reader.read_into(reader, rec)?;
+        self.record_start = start;
+        self.record_len = reader.pos - start;
+        Ok(read)
+    }
+
+    /// Decode sources without a BOM as `encoding` (`--encoding`).
+    pub fn set_encoding(&mut self, encoding: encoding::Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Start the first source at byte `offset`, or at the start of the
+    /// next line when `offset` falls inside one.
+    pub fn set_seek(&mut self, offset: u64) {
+        self.seek = Some(offset);
+    }
+
+    /// Byte offset of the last record within its source, and its length
+    /// including the terminator.
+    pub fn record_span(&self) -> (u64, u64) {
+        (self.record_start, self.record_len)
+    }
+
+    /// Bytes consumed from all sources so far (decompressed bytes for
+    /// compressed files), for `--progress`.
+    pub fn bytes_read(&self) -> u64 {
+        self.done_bytes + self.reader.as_ref().map_or(0, |r| r.pos)
+    }
+
+    /// With `on`, a source that cannot be opened or read is recorded in
+    /// `skipped()` and reading moves on to the next one instead of failing.
+    pub fn set_keep_going(&mut self, on: bool) {
+        self.keep_going = on;
+    }
+
+    /// `"name: error"` for each source given up on under `set_keep_going`.
+    pub fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+
+    /// Under keep-going, note the failed source and move past it.
+    fn skip_failed(&mut self, e: io::Error) -> io::Result<()> {
+        if !self.keep_going {
+            return Err(e);
         }
+        let name = match &self.sources[self.current] {
+            Source::Stdin => "-",
+            Source::File(path) => path,
+        };
+        self.skipped.push(format!("{}: {}", name, e));
+        self.skip_source();
+        Ok(())
     }
 
     /// Skip the rest of the current source and advance to the next one.
     pub fn skip_source(&mut self) {
-        self.reader = None;
+        self.done_bytes += self.reader.take().map_or(0, |r| r.pos);
         self.current += 1;
     }
 
+    /// Columns the record reader discovered since the last call.
+    pub fn new_columns(&mut self) -> Vec<String> {
+        self.record_reader.new_columns()
+    }
+
+    /// Position of the current source in the list. Unlike the name, it
+    /// tells apart a file given twice, and standard input from no input.
+    pub fn source_index(&self) -> usize {
+        self.current
+    }
+
     /// Return the name of the current input source.
     pub fn current_filename(&self) -> &str {
         if self.current < self.sources.len() {
@@ -84,31 +289,167 @@ impl Input {
 
     /// Read the next record. Returns None at end of all input.
     pub fn next_record(&mut self) -> io::Result<Option<Record>> {
+        let mut rec = Record::default();
+        Ok(self.read_into(&mut rec)?.then_some(rec))
+    }
+
+    /// Read the next record into `rec`, reusing its buffers; the main loop
+    /// keeps one `Record` for the whole run. Returns false at end of input.
+    pub fn read_into(&mut self, rec: &mut Record) -> io::Result<bool> {
         loop {
             if self.reader.is_none() {
                 if self.current >= self.sources.len() {
-                    return Ok(None);
+                    return Ok(false);
                 }
-                let reader: Box<dyn BufRead> = match &self.sources[self.current] {
-                    Source::Stdin => Box::new(BufReader::new(io::stdin())),
-                    Source::File(path) => {
-                        let reader = crate::describe::open_maybe_compressed(path).map_err(|e| {
-                            io::Error::new(e.kind(), format!("fk: {}: {}", path, e))
-                        })?;
-                        Box::new(BufReader::new(reader))
+                match self.open_current() {
+                    Ok(reader) => {
+                        self.reader = Some(reader);
+                        self.source_records = 0;
+                        if let Some(dialect) = &self.dialect {
+                            self.record_reader.set_dialect(dialect);
+                        }
+                    }
+                    Err(e) if self.keep_going => {
+                        self.skip_failed(e)?;
+                        continue;
+                    }
+                    Err(e) => {
+                        let name = self.current_filename();
+                        return Err(io::Error::new(e.kind(), format!("fk: {}: {}", name, e)));
                     }
-                };
-                self.reader = Some(reader);
+                }
             }
 
             let reader = self.reader.as_mut().unwrap();
-            match self.record_reader.next_record(reader.as_mut())? {
-                Some(record) => return Ok(Some(record)),
-                None => {
-                    self.reader = None;
-                    self.current += 1;
+            if self.source_records > 0 {
+                // Only the first record of a source can be read again.
+                reader.capture = None;
+            }
+            let start = reader.pos;
+            match self.record_reader.read_into(reader, rec) {
+                Ok(true) => {
+                    self.source_records += 1;
+                    self.record_start = start;
+                    self.record_len = reader.pos - start;
+                    return Ok(true);
                 }
+                Ok(false) => self.skip_source(),
+                Err(e) => self.skip_failed(e)?,
             }
         }
     }
+
+    /// Open the current source; the first one starts at the `--seek`
+    /// offset. A plain file seeks there, anything else reads up to it.
+    fn open_current(&mut self) -> io::Result<Counted> {
+        let seek = if self.current == 0 {
+            self.seek.take()
+        } else {
+            None
+        };
+        let mut pos = 0;
+        let raw: Box<dyn Read + Send> = match &self.sources[self.current] {
+            Source::Stdin => Box::new(io::stdin()),
+            Source::File(path) => match seek {
+                Some(offset) if offset > 0 && !crate::describe::is_compressed(path) => {
+                    let mut file = std::fs::File::open(path)?;
+                    pos = file.seek(SeekFrom::Start(offset - 1))?;
+                    Box::new(file)
+                }
+                _ => crate::describe::open_maybe_compressed(path)?,
+            },
+        };
+        // `--seek` offsets are file bytes, so a resumed read is not
+        // decoded; BYTEPOS counts a dropped UTF-8 BOM.
+        let inner: Box<dyn BufRead> = if seek.is_none_or(|o| o == 0) {
+            let (text, bom_len) = encoding::decode(raw, self.encoding)?;
+            pos = bom_len;
+            Box::new(BufReader::new(text))
+        } else {
+            Box::new(BufReader::new(raw))
+        };
+        let mut reader = Counted {
+            inner,
+            pos,
+            capture: None,
+        };
+        if let Some(offset) = seek.filter(|&o| o > 0) {
+            // Land on `offset` if the byte before it ends a line, else on
+            // the start of the next line.
+            io::copy(&mut reader.by_ref().take(offset - 1 - pos), &mut io::sink())?;
+            reader.read_until(b'\n', &mut Vec::new())?;
+        }
+        if self.record_reader.takes_dialect() {
+            reader.capture = Some(Vec::new());
+        }
+        Ok(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_going_skips_missing_source() {
+        let dir = std::env::temp_dir().join(format!("fk_keep_going_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let good = dir.join("good.txt");
+        std::fs::write(&good, "a\nb\n").unwrap();
+        let good = good.to_string_lossy().into_owned();
+        let files = vec![
+            "/no/such/fk-file".to_string(),
+            good.clone(),
+            dir.to_string_lossy().into_owned(),
+        ];
+
+        let mut strict = Input::with_reader(&files, Box::new(line::LineReader::new()));
+        assert!(strict.next_record().is_err());
+
+        let mut inp = Input::with_reader(&files, Box::new(line::LineReader::new()));
+        inp.set_keep_going(true);
+        let mut got = Vec::new();
+        while let Some(r) = inp.next_record().unwrap() {
+            got.push((inp.current_filename().to_string(), r.text));
+        }
+        assert_eq!(
+            got,
+            [(good.clone(), "a".to_string()), (good, "b".to_string())]
+        );
+        assert_eq!(inp.skipped().len(), 2);
+        assert!(inp.skipped()[0].starts_with("/no/such/fk-file: "));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn record_spans_count_bytes_and_seek_resumes_at_a_line() {
+        let dir = std::env::temp_dir().join(format!("fk_bytepos_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("crlf.txt");
+        std::fs::write(&path, "ab\r\ncdé\r\n\r\nlast").unwrap();
+        let files = vec![path.to_string_lossy().into_owned()];
+        let read_all = |seek: Option<u64>| {
+            let mut inp = Input::with_reader(&files, Box::new(line::LineReader::new()));
+            if let Some(offset) = seek {
+                inp.set_seek(offset);
+            }
+            let mut got = Vec::new();
+            while let Some(r) = inp.next_record().unwrap() {
+                let (start, len) = inp.record_span();
+                got.push(format!("{}@{}+{}", r.text, start, len));
+            }
+            got
+        };
+        // "é" is two bytes; the last line has no terminator.
+        let all = ["ab@0+4", "cdé@4+6", "@10+2", "last@12+4"];
+        assert_eq!(read_all(None), all);
+        assert_eq!(read_all(Some(0)), all);
+        // On a line start, or inside the line before it.
+        assert_eq!(read_all(Some(4)), all[1..]);
+        assert_eq!(read_all(Some(1)), all[1..]);
+        assert_eq!(read_all(Some(5)), all[2..]);
+        assert_eq!(read_all(Some(12)), all[3..]);
+        assert!(read_all(Some(99)).is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -4,6 +4,11 @@
 
 use std::io;
 
+use parquet::basic::{LogicalType, Type as PhysicalType};
+use parquet::file::statistics::Statistics;
+
+use crate::describe::ColType;
+
 /// Read all records from a Parquet file.
 /// Returns (column_names, rows) where each row is a Vec of string field values.
 pub fn read_parquet_file(path: &str) -> io::Result<(Vec<String>, Vec<Vec<String>>)> {
@@ -48,6 +53,210 @@ pub fn read_parquet_file(path: &str) -> io::Result<(Vec<String>, Vec<Vec<String>
     Ok((columns, rows))
 }
 
+/// What the footer of a Parquet file says, read without touching any
+/// data page (see [`read_parquet_metadata`]).
+#[derive(Debug)]
+pub struct ParquetMeta {
+    pub columns: Vec<ParquetColumn>,
+    pub rows: u64,
+    pub row_groups: usize,
+    /// Column chunk bytes as stored, and before compression.
+    pub compressed: u64,
+    pub uncompressed: u64,
+}
+
+/// One leaf column: its types and its min/max over all row groups,
+/// rendered the way the reader renders values.
+#[derive(Debug)]
+pub struct ParquetColumn {
+    pub name: String,
+    pub physical: String,
+    pub logical: Option<String>,
+    pub col_type: ColType,
+    pub min: Option<String>,
+    pub max: Option<String>,
+}
+
+/// A row-group min or max, kept comparable until every group is seen.
+#[derive(Clone, PartialEq, PartialOrd)]
+enum Bound {
+    Int(i128),
+    Float(f64),
+    Bytes(Vec<u8>),
+}
+
+/// Read only the footer of a Parquet file: schema, row and row-group
+/// counts, sizes and per-column min/max statistics.
+pub fn read_parquet_metadata(path: &str) -> io::Result<ParquetMeta> {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use std::fs::File;
+
+    let file =
+        File::open(path).map_err(|e| io::Error::new(e.kind(), format!("fk: {}: {}", path, e)))?;
+    let reader = SerializedFileReader::new(file)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("fk: parquet: {}", e)))?;
+    let meta = reader.metadata();
+    let groups = meta.row_groups();
+
+    let columns = meta
+        .file_metadata()
+        .schema_descr()
+        .columns()
+        .iter()
+        .enumerate()
+        .map(|(i, col)| {
+            let logical = col.logical_type();
+            let mut min: Option<Bound> = None;
+            let mut max: Option<Bound> = None;
+            for group in groups {
+                let Some((lo, hi)) = group
+                    .column(i)
+                    .statistics()
+                    .and_then(|s| bounds(s, logical.as_ref()))
+                else {
+                    continue;
+                };
+                if min.as_ref().is_none_or(|m| lo < *m) {
+                    min = Some(lo);
+                }
+                if max.as_ref().is_none_or(|m| hi > *m) {
+                    max = Some(hi);
+                }
+            }
+            let show = |b: Option<Bound>| b.and_then(|b| bound_text(b, logical.as_ref()));
+            ParquetColumn {
+                name: col.path().string(),
+                physical: col.physical_type().to_string(),
+                logical: logical.as_ref().map(logical_label),
+                col_type: col_type(col.physical_type(), logical.as_ref()),
+                min: show(min),
+                max: show(max),
+            }
+        })
+        .collect();
+
+    Ok(ParquetMeta {
+        columns,
+        rows: meta.file_metadata().num_rows().max(0) as u64,
+        row_groups: groups.len(),
+        compressed: groups
+            .iter()
+            .map(|g| g.compressed_size().max(0) as u64)
+            .sum(),
+        uncompressed: groups
+            .iter()
+            .map(|g| g.total_byte_size().max(0) as u64)
+            .sum(),
+    })
+}
+
+/// How fk sees a column's values: dates and timestamps come out as
+/// text, booleans as 1/0 and decimals as numbers.
+fn col_type(physical: PhysicalType, logical: Option<&LogicalType>) -> ColType {
+    match (physical, logical) {
+        (_, Some(LogicalType::Decimal { .. })) => ColType::Float,
+        (_, Some(LogicalType::Date | LogicalType::Time { .. } | LogicalType::Timestamp { .. })) => {
+            ColType::String
+        }
+        (PhysicalType::BOOLEAN | PhysicalType::INT32 | PhysicalType::INT64, _) => ColType::Int,
+        (PhysicalType::FLOAT | PhysicalType::DOUBLE, _) => ColType::Float,
+        _ => ColType::String,
+    }
+}
+
+fn logical_label(logical: &LogicalType) -> String {
+    match logical {
+        LogicalType::Decimal { scale, precision } => format!("decimal({},{})", precision, scale),
+        LogicalType::Integer {
+            bit_width,
+            is_signed,
+        } => format!("{}int{}", if *is_signed { "" } else { "u" }, bit_width),
+        LogicalType::Time { .. } => "time".to_string(),
+        LogicalType::Timestamp { .. } => "timestamp".to_string(),
+        other => format!("{:?}", other).to_lowercase(),
+    }
+}
+
+/// A row group's (min, max), if its statistics carry both.
+fn bounds(stats: &Statistics, logical: Option<&LogicalType>) -> Option<(Bound, Bound)> {
+    let unsigned = matches!(
+        logical,
+        Some(LogicalType::Integer {
+            is_signed: false,
+            ..
+        })
+    );
+    let pair = |lo: Option<Bound>, hi: Option<Bound>| Some((lo?, hi?));
+    match stats {
+        Statistics::Boolean(s) => pair(
+            s.min_opt().map(|&v| Bound::Int(v as i128)),
+            s.max_opt().map(|&v| Bound::Int(v as i128)),
+        ),
+        Statistics::Int32(s) => {
+            let int = |&v: &i32| {
+                Bound::Int(if unsigned {
+                    v as u32 as i128
+                } else {
+                    v as i128
+                })
+            };
+            pair(s.min_opt().map(int), s.max_opt().map(int))
+        }
+        Statistics::Int64(s) => {
+            let int = |&v: &i64| {
+                Bound::Int(if unsigned {
+                    v as u64 as i128
+                } else {
+                    v as i128
+                })
+            };
+            pair(s.min_opt().map(int), s.max_opt().map(int))
+        }
+        Statistics::Float(s) => pair(
+            s.min_opt().map(|&v| Bound::Float(v as f64)),
+            s.max_opt().map(|&v| Bound::Float(v as f64)),
+        ),
+        Statistics::Double(s) => pair(
+            s.min_opt().map(|&v| Bound::Float(v)),
+            s.max_opt().map(|&v| Bound::Float(v)),
+        ),
+        Statistics::ByteArray(_) | Statistics::FixedLenByteArray(_) => pair(
+            stats.min_bytes_opt().map(|b| Bound::Bytes(b.to_vec())),
+            stats.max_bytes_opt().map(|b| Bound::Bytes(b.to_vec())),
+        ),
+        Statistics::Int96(_) => None,
+    }
+}
+
+fn bound_text(bound: Bound, logical: Option<&LogicalType>) -> Option<String> {
+    use parquet::basic::TimeUnit;
+
+    Some(match (bound, logical) {
+        (Bound::Int(days), Some(LogicalType::Date)) => format_epoch_date(days as i64 * 86400),
+        (Bound::Int(v), Some(LogicalType::Timestamp { unit, .. })) => {
+            let per_sec = match unit {
+                TimeUnit::MILLIS(_) => 1_000,
+                TimeUnit::MICROS(_) => 1_000_000,
+                TimeUnit::NANOS(_) => 1_000_000_000,
+            };
+            format_epoch_datetime((v as i64).div_euclid(per_sec))
+        }
+        (Bound::Int(v), Some(LogicalType::Decimal { scale, .. })) => {
+            (v as f64 / 10f64.powi(*scale)).to_string()
+        }
+        (Bound::Int(v), _) => v.to_string(),
+        (Bound::Float(v), _) => {
+            if v == v.trunc() && v.abs() < 1e15 {
+                format!("{}", v as i64)
+            } else {
+                format!("{}", v)
+            }
+        }
+        (Bound::Bytes(_), Some(LogicalType::Decimal { .. })) => return None,
+        (Bound::Bytes(b), _) => String::from_utf8_lossy(&b).into_owned(),
+    })
+}
+
 fn array_value_to_string(array: &dyn arrow::array::Array, idx: usize) -> String {
     use arrow::array::*;
     use arrow::datatypes::DataType;
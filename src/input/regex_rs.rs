@@ -2,7 +2,8 @@ use std::io::{self, BufRead};
 
 use regex::Regex;
 
-use super::{Record, RecordReader};
+use super::{Record, RecordReader, strip_line_end};
+use crate::action::{DEFAULT_REGEX_SIZE_LIMIT, compile_regex};
 
 /// Record reader that splits input on a regex record separator (RS).
 ///
@@ -12,15 +13,18 @@ pub struct RegexReader {
     pattern: Regex,
     buffer: Vec<String>,
     pos: usize,
+    preserve_cr: bool,
 }
 
 impl RegexReader {
     pub fn new(pattern: &str) -> Result<Self, String> {
-        let re = Regex::new(pattern).map_err(|e| format!("invalid RS regex: {}", e))?;
+        let re = compile_regex(pattern, DEFAULT_REGEX_SIZE_LIMIT)
+            .map_err(|e| format!("invalid RS regex: {}", e))?;
         Ok(RegexReader {
             pattern: re,
             buffer: Vec::new(),
             pos: 0,
+            preserve_cr: false,
         })
     }
 }
@@ -35,12 +39,7 @@ impl RecordReader for RegexReader {
                 return Ok(None);
             }
             // Trim a single trailing newline so it doesn't produce an empty final record
-            if all.ends_with('\n') {
-                all.pop();
-                if all.ends_with('\r') {
-                    all.pop();
-                }
-            }
+            strip_line_end(&mut all, self.preserve_cr);
             self.buffer = self.pattern.split(&all).map(|s| s.to_string()).collect();
             self.pos = 0;
         }
@@ -52,7 +51,15 @@ impl RecordReader for RegexReader {
 
         let text = self.buffer[self.pos].clone();
         self.pos += 1;
-        Ok(Some(Record { text, fields: None }))
+        Ok(Some(Record {
+            text,
+            fields: None,
+            malformed: None,
+        }))
+    }
+
+    fn set_preserve_cr(&mut self, on: bool) {
+        self.preserve_cr = on;
     }
 }
 
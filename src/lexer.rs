@@ -66,6 +66,7 @@ pub enum Token {
     Question,       // ?
     NullCoalesce,   // ??
     Colon,          // :
+    ColonColon,     // :: (namespace qualifier)
     Append,   // >>
     Pipe,     // | (single, for output redirection)
 
@@ -315,7 +316,12 @@ impl Lexer {
                 }
                 ':' => {
                     self.advance_char();
-                    Token::Colon
+                    if self.peek() == Some(':') {
+                        self.advance_char();
+                        Token::ColonColon
+                    } else {
+                        Token::Colon
+                    }
                 }
                 '&' => {
                     self.advance_char();
@@ -435,6 +441,10 @@ impl Lexer {
                         s.push('\t');
                         self.advance_char();
                     }
+                    'r' => {
+                        s.push('\r');
+                        self.advance_char();
+                    }
                     '\\' => {
                         s.push('\\');
                         self.advance_char();
@@ -536,6 +546,21 @@ impl Lexer {
                 break;
             }
         }
+        // Exponent: 1e9, 2.5E-3 (only when digits follow).
+        if self.pos < self.input.len() && matches!(self.input[self.pos], 'e' | 'E') {
+            let mut p = self.pos + 1;
+            if p < self.input.len() && matches!(self.input[p], '+' | '-') {
+                p += 1;
+            }
+            if p < self.input.len() && self.input[p].is_ascii_digit() {
+                while self.pos < p {
+                    self.advance_char();
+                }
+                while self.pos < self.input.len() && self.input[self.pos].is_ascii_digit() {
+                    self.advance_char();
+                }
+            }
+        }
         let s: String = self.input[start..self.pos].iter().collect();
         let num: f64 = s
             .parse()
@@ -607,6 +632,16 @@ impl Lexer {
     }
 }
 
+/// `-w`: anchor every `/re/` literal at word boundaries. Patterns built
+/// from strings at run time are left alone.
+pub fn wrap_word_regexes(tokens: &mut [Spanned]) {
+    for t in tokens {
+        if let Token::Regex(pattern) = &mut t.token {
+            *pattern = format!(r"\b(?:{pattern})\b");
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,17 +1,29 @@
 pub mod action;
 pub mod analyze;
+pub mod build_info;
 pub mod builtins;
+pub mod check;
 pub mod cli;
 pub mod describe;
 pub mod error;
 pub mod explain;
 pub mod field;
+pub mod fileglob;
 pub mod format;
+pub mod include;
 pub mod input;
 pub mod lexer;
+pub mod namespace;
+pub mod optimize;
+pub mod ordmap;
+pub mod output;
 pub mod parser;
+pub mod progress;
 pub mod repl;
 pub mod runtime;
+pub mod spill;
+pub mod state;
+pub mod timing;
 
 #[cfg(test)]
 mod tests;
@@ -1,9 +1,11 @@
 use std::env;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 use std::process;
 
-use fk::builtins::format_number;
-use fk::{action, cli, describe, explain, format, input, lexer, parser, repl, runtime};
+use fk::{
+    action, check, cli, describe, explain, format, input, lexer, optimize, parser, progress, repl,
+    runtime, state, timing,
+};
 
 #[cfg(feature = "parquet")]
 fn run_parquet(args: &cli::Args, exec: &mut action::Executor) {
@@ -35,19 +37,25 @@ fn run_parquet(args: &cli::Args, exec: &mut action::Executor) {
             let rec = input::Record {
                 text,
                 fields: Some(fields),
+                malformed: None,
             };
+            if exec.over_input_limit() {
+                break;
+            }
             exec.increment_fnr();
+            if exec.every_skip() || exec.sample_skip() {
+                continue;
+            }
             exec.run_record(&rec);
             if exec.should_exit().is_some() {
                 return;
             }
             if exec.take_next_file() {
-                exec.run_endfile();
                 break;
             }
         }
         exec.run_endfile();
-        if exec.should_exit().is_some() {
+        if exec.should_exit().is_some() || exec.input_limit().is_some() {
             return;
         }
     }
@@ -55,7 +63,31 @@ fn run_parquet(args: &cli::Args, exec: &mut action::Executor) {
 
 fn main() {
     let args = cli::parse_args();
+    // Deep recursion needs more native stack than the main thread has:
+    // run on a thread sized for the requested depth.
+    match args.max_depth {
+        Some(depth) if depth > fk::action::DEFAULT_MAX_CALL_DEPTH => {
+            let stack = (8 << 20) + depth * fk::action::STACK_PER_CALL;
+            let spawned = std::thread::Builder::new()
+                .stack_size(stack)
+                .spawn(move || run(args));
+            match spawned {
+                Ok(handle) => {
+                    if handle.join().is_err() {
+                        process::exit(2);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("fk: cannot reserve stack for --max-depth {}: {}", depth, e);
+                    process::exit(2);
+                }
+            }
+        }
+        _ => run(args),
+    }
+}
 
+fn run(args: cli::Args) {
     // Highlight mode: print syntax-highlighted program and exit
     if args.highlight {
         match format::highlight(&args.program) {
@@ -70,9 +102,52 @@ fn main() {
         }
     }
 
+    // Dump mode: show what the lexer or parser made of the program and exit
+    if args.dump_tokens || args.dump_ast {
+        let dump = if args.dump_tokens {
+            format::dump_tokens(&args.program)
+        } else {
+            format::dump_ast(&args.program)
+        };
+        match dump {
+            Ok(s) => {
+                print!("{}", s);
+                return;
+            }
+            Err(e) => {
+                eprintln!("fk: {}", e);
+                process::exit(2);
+            }
+        }
+    }
+
+    // Check-format mode: exit 1 with a diff if the program is not formatted
+    if args.check_format {
+        match format::format_program_with(&args.program, &args.format_options) {
+            Ok(s) => {
+                let name = match args.program_sources.first() {
+                    Some(src) => src.label(),
+                    None if args.program_from_stdin => "<stdin>".to_string(),
+                    None => "program".to_string(),
+                };
+                let diff =
+                    format::unified_diff(&args.program, &s, &name, &format!("{name} (formatted)"));
+                if !diff.is_empty() {
+                    eprint!("{}", diff);
+                    process::exit(1);
+                }
+                return;
+            }
+            Err(e) => {
+                eprintln!("fk: {}", e);
+                process::exit(2);
+            }
+        }
+    }
+
     // Format mode: pretty-print program and exit
     if args.format {
-        match format::format_program(&args.program) {
+        match format::format_program_with(&args.program, &args.format_options) {
             Ok(s) => {
                 println!("{}", s);
                 return;
@@ -84,6 +159,21 @@ fn main() {
         }
     }
 
+    // Check mode: parse, analyze and lint without touching input
+    if let Some(fmt) = args.check {
+        let assigned: Vec<String> = args.assignments.iter().map(|(n, _)| n.clone()).collect();
+        let report = check::check(&args.program, Some(&explain_context(&args)), &assigned);
+        match fmt {
+            cli::CheckFormat::Json => println!("{}", report.to_json()),
+            cli::CheckFormat::Text => {
+                for d in &report.diagnostics {
+                    eprintln!("fk: {d}");
+                }
+            }
+        }
+        process::exit(report.exit_code());
+    }
+
     // Explain mode: terse description of program and exit
     if args.explain {
         let tokens = match lexer::Lexer::new(&args.program).tokenize() {
@@ -100,20 +190,7 @@ fn main() {
                 process::exit(2);
             }
         };
-        let mode_str = match &args.input_mode {
-            cli::InputMode::Line => "line",
-            cli::InputMode::Csv => "csv",
-            cli::InputMode::Tsv => "tsv",
-            cli::InputMode::Json => "json",
-            cli::InputMode::Parquet => "parquet",
-        };
-        let ctx = explain::ExplainContext::from_cli(
-            mode_str,
-            args.header_mode,
-            args.field_separator.as_deref(),
-            &args.files,
-        );
-        let desc = explain::explain(&prog, Some(&ctx));
+        let desc = explain::explain(&prog, Some(&explain_context(&args)));
         if !desc.is_empty() {
             println!("{desc}");
         }
@@ -122,29 +199,58 @@ fn main() {
 
     // Describe / suggest mode
     if args.describe {
-        describe::run_describe(&args.files, args.suggest);
+        if args.validate {
+            let mode = effective_input_mode(&args);
+            if mode == cli::InputMode::Parquet {
+                eprintln!("fk: --validate does not read parquet input");
+                process::exit(2);
+            }
+            let opts = ReaderOpts::new(&args);
+            process::exit(describe::run_validate(
+                &args.files,
+                args.validate_schema.as_deref(),
+                args.header_mode,
+                &|| record_reader(&mode, "\n", opts),
+            ));
+        }
+        // `-v CSVCOMMENT=#` skips comment lines here as it does on input.
+        let comment = args
+            .assignments
+            .iter()
+            .rfind(|(name, _)| name == "CSVCOMMENT")
+            .and_then(|(_, v)| v.chars().next());
+        describe::run_describe(&args.files, args.suggest, args.emit_schema, comment);
         return;
     }
 
+    let mut timing = args.timing.then(timing::Timing::start);
+
     // Lex
     let mut lex = lexer::Lexer::new(&args.program);
-    let tokens = match lex.tokenize() {
+    let mut tokens = match lex.tokenize() {
         Ok(t) => t,
         Err(e) => {
             eprintln!("fk: syntax error: {}", e);
             process::exit(2);
         }
     };
+    if args.word_regexp {
+        lexer::wrap_word_regexes(&mut tokens);
+    }
 
     // Parse
     let mut par = parser::Parser::new(tokens);
-    let program = match par.parse() {
+    let mut program = match par.parse() {
         Ok(p) => p,
         Err(e) => {
             eprintln!("fk: parse error: {}", e);
             process::exit(2);
         }
     };
+    optimize::fold_constants(&mut program);
+    if let Some(t) = timing.as_mut() {
+        t.parse = t.lap();
+    }
 
     // Set up runtime
     let mut rt = runtime::Runtime::new();
@@ -157,21 +263,32 @@ fn main() {
         rt.set_var("OFS", os);
     }
 
+    if args.ignore_case {
+        rt.set_var("IGNORECASE", "1");
+    }
+
     // Apply -v assignments
     for (name, value) in &args.assignments {
-        rt.set_var(name, value);
+        rt.set_strnum(name, value);
+    }
+    let subsep = rt.get_var("SUBSEP");
+    for (name, subscripts, value) in &args.array_assignments {
+        rt.set_array(name, &subscripts.join(&subsep), value);
     }
 
     // Populate ENVIRON array from process environment
     for (key, val) in env::vars() {
-        rt.set_array("ENVIRON", &key, &val);
+        rt.set_array_strnum("ENVIRON", &key, &val);
     }
 
-    // Populate ARGC / ARGV from command-line args
-    let raw_args: Vec<String> = env::args().collect();
-    rt.set_var("ARGC", &format_number(raw_args.len() as f64));
-    for (i, arg) in raw_args.iter().enumerate() {
-        rt.set_array("ARGV", &i.to_string(), arg);
+    // ARGV holds the operands: input files after --glob expansion, and the
+    // script's operands after `--`.
+    let command = env::args().next().unwrap_or_else(|| "fk".to_string());
+    rt.set_argv(&command, &args.files);
+
+    // Arguments after `--`, untouched, for the script (see cliargs()).
+    for (i, arg) in args.script_args.iter().enumerate() {
+        rt.set_array("FKARGS", &(i + 1).to_string(), arg);
     }
 
     // REPL mode
@@ -180,74 +297,181 @@ fn main() {
         return;
     }
 
+    if args.in_place.is_some() {
+        if args.files.is_empty() {
+            eprintln!("fk: --in-place requires file arguments");
+            process::exit(2);
+        }
+        if let Some(f) = args
+            .files
+            .iter()
+            .find(|f| *f == "-" || describe::is_compressed(f))
+        {
+            eprintln!("fk: --in-place cannot rewrite '{}'", f);
+            process::exit(2);
+        }
+    }
+
+    // Load persisted arrays before BEGIN
+    if let Some(ref path) = args.state
+        && let Err(e) = state::load(path, &mut rt)
+    {
+        eprintln!("fk: state: {}", e);
+        process::exit(2);
+    }
+
     // Execute
     let mut exec = action::Executor::new(&program, &mut rt);
+    exec.set_output_mode(&args.output_mode, args.table_rows);
+    let line_buffered = line_buffered(&args);
+    exec.set_line_buffered(line_buffered);
+    exec.set_strict(args.strict);
+    exec.set_quiet(args.quiet);
+    exec.set_emit_header(args.emit_header);
+    exec.set_header_vars(args.header_vars);
+    exec.set_preserve_cr(args.preserve_cr);
+    if let Some(p) = args.sample {
+        exec.set_sample(p);
+    }
+    if let Some(n) = args.every {
+        exec.set_every(n);
+    }
+    if let Some(ref suffix) = args.in_place {
+        exec.set_in_place(suffix);
+    }
+    if let Some(policy) = args.on_error {
+        exec.set_on_error(policy);
+    }
+    if let Some(bytes) = args.regex_limit {
+        exec.set_regex_limit(bytes);
+    }
+    if let Some(depth) = args.max_depth {
+        exec.set_max_call_depth(depth);
+    }
+    if let Some(n) = args.max_records {
+        exec.set_max_records(n);
+    }
+    if let Some(limit) = args.max_runtime {
+        exec.set_max_runtime(limit);
+    }
+    if let Some(n) = args.max_pipes {
+        exec.set_max_pipes(n);
+    }
+
+    let effective_mode = effective_input_mode(&args);
+
+    exec.set_csv_output(effective_mode == cli::InputMode::Csv);
+    // Keyed JSON names its columns from the keys; other input under -H
+    // starts with a header row, whoever reads it first.
+    if args.header_mode {
+        if effective_mode == cli::InputMode::Json {
+            exec.set_keyed_header();
+        } else {
+            exec.expect_header();
+        }
+    }
 
+    // A plain `getline` in BEGIN reads the same records the rules would.
+    if effective_mode != cli::InputMode::Parquet {
+        let files = args.files.clone();
+        let mode = effective_mode.clone();
+        let (keep_going, seek, opts) = (args.keep_going, args.seek, ReaderOpts::new(&args));
+        let encoding = args.encoding;
+        exec.defer_input(move |rs| {
+            let mut inp = input::Input::with_reader(&files, record_reader(&mode, rs, opts));
+            inp.set_keep_going(keep_going);
+            inp.set_encoding(encoding);
+            if let Some(offset) = seek {
+                inp.set_seek(offset);
+            }
+            inp
+        });
+    }
+
+    if let Some(t) = timing.as_mut() {
+        t.lap();
+    }
     exec.run_begin();
+    if let Some(t) = timing.as_mut() {
+        t.begin = t.lap();
+    }
 
     // Early exit from BEGIN
     if let Some(code) = exec.should_exit() {
         exec.run_end();
+        save_state(&args, &exec);
+        report_timing(&mut timing, &exec, &args.files);
         process::exit(code);
     }
 
-    // BEGIN/END-only programs with no files: skip stdin (gawk behaviour)
+    // BEGIN-only programs with no files: skip stdin (gawk behaviour). An
+    // END block still reads input so it can see NR and the last record.
     if program.rules.is_empty()
+        && program.end.is_none()
         && program.beginfile.is_none()
         && program.endfile.is_none()
+        && program.error.is_none()
         && args.files.is_empty()
     {
         exec.run_end();
+        save_state(&args, &exec);
+        report_timing(&mut timing, &exec, &args.files);
         if let Some(code) = exec.should_exit() {
             process::exit(code);
         }
         return;
     }
 
-    // Auto-detect input mode from first file extension when user didn't
-    // specify -i *and* didn't specify -F (explicit -F implies line mode).
-    let effective_mode = if args.input_mode == cli::InputMode::Line
-        && args.field_separator.is_none()
-        && !args.files.is_empty()
-    {
-        if let Some(fmt) = describe::format_from_extension(&args.files[0]) {
-            match fmt {
-                describe::Format::Csv => cli::InputMode::Csv,
-                describe::Format::Tsv => cli::InputMode::Tsv,
-                describe::Format::Json => cli::InputMode::Json,
-                describe::Format::Space => cli::InputMode::Line,
-                describe::Format::Parquet => cli::InputMode::Parquet,
-            }
-        } else {
-            args.input_mode.clone()
-        }
-    } else {
-        args.input_mode.clone()
-    };
-
     // Fast path: END { print NR } with no rules (line counting).
     let fast_count_nr = program.begin.is_none()
         && program.beginfile.is_none()
         && program.endfile.is_none()
         && program.rules.is_empty()
         && program.functions.is_empty()
+        && program.error.is_none()
+        && args.on_error.is_none()
         && !args.header_mode
+        && args.in_place.is_none()
+        && args.seek.is_none()
         && is_end_print_nr_only(&program);
-    // Fast path: head-style NR>limit { exit } 1
-    let fast_head_limit = if program.begin.is_none()
+    // Fast path: head-style NR>limit { exit } 1, or a stride NR % k == n
+    let fast_nr_print = if program.begin.is_none()
         && program.end.is_none()
         && program.beginfile.is_none()
         && program.endfile.is_none()
         && program.functions.is_empty()
+        && program.error.is_none()
+        && args.on_error.is_none()
         && !args.header_mode
+        && args.output_mode == cli::OutputMode::Plain
+        && args.in_place.is_none()
+        && args.sample.is_none()
+        && args.every.is_none()
+        && args.seek.is_none()
+        && !args.keep_going
+        && !line_buffered
     {
         head_print_limit(&program)
+            .map(NrPrint::Head)
+            .or_else(|| nr_stride(&program).map(|(k, n)| NrPrint::Stride(k, n)))
     } else {
         None
     };
 
+    let mut progress = args
+        .progress
+        .then(|| progress::Progress::start(progress_total(&args.files)))
+        .flatten();
+    let mut records: u64 = 0;
+
     // Parquet mode: reads entire file upfront (not streaming)
-    if effective_mode == cli::InputMode::Parquet {
+    if effective_mode == cli::InputMode::Parquet && args.in_place.is_some() {
+        eprintln!("fk: --in-place is not supported for parquet input");
+        process::exit(2);
+    } else if effective_mode == cli::InputMode::Parquet && args.seek.is_some() {
+        eprintln!("fk: --seek is not supported for parquet input");
+        process::exit(2);
+    } else if effective_mode == cli::InputMode::Parquet {
         #[cfg(feature = "parquet")]
         {
             run_parquet(&args, &mut exec);
@@ -261,40 +485,34 @@ fn main() {
         }
     } else if fast_count_nr {
         // Simple line/record count: avoid per-record runtime setup.
-        let reader: Box<dyn input::RecordReader> = {
-            let rs = exec.get_var("RS");
-            if effective_mode == cli::InputMode::Line && rs.len() > 1 {
-                match input::regex_rs::RegexReader::new(&rs) {
-                    Ok(r) => Box::new(r),
-                    Err(e) => {
-                        eprintln!("fk: {}", e);
-                        process::exit(2);
-                    }
-                }
-            } else {
-                match effective_mode {
-                    cli::InputMode::Csv => Box::new(input::csv::CsvReader::comma()),
-                    cli::InputMode::Tsv => Box::new(input::csv::CsvReader::tab()),
-                    cli::InputMode::Json => Box::new(input::json::JsonReader),
-                    cli::InputMode::Line => Box::new(input::line::LineReader::new()),
-                    cli::InputMode::Parquet => unreachable!(),
-                }
-            }
-        };
-
+        let reader = record_reader(&effective_mode, &exec.get_var("RS"), ReaderOpts::new(&args));
         let mut inp = input::Input::with_reader(&args.files, reader);
+        inp.set_keep_going(args.keep_going);
+        inp.set_encoding(args.encoding);
+        inp.set_dialect(exec.csv_dialect());
         loop {
             match inp.next_record() {
-                Ok(Some(_)) => exec.increment_nr(),
+                Ok(Some(_)) if exec.over_input_limit() => break,
+                Ok(Some(_)) => {
+                    exec.increment_nr();
+                    if let Some(p) = progress.as_mut() {
+                        records += 1;
+                        if p.tick(records) {
+                            p.draw(records, inp.bytes_read(), inp.current_filename());
+                        }
+                    }
+                }
                 Ok(None) => break,
                 Err(e) => {
+                    progress::clear();
                     eprintln!("{}", e);
                     process::exit(1);
                 }
             }
         }
-    } else if let Some(limit) = fast_head_limit {
-        // Head-like program: print first N records and exit.
+        exec.set_input(inp);
+    } else if let Some(nr_print) = fast_nr_print {
+        // Head-like or stride program: print the records NR picks.
         let rs = exec.get_var("RS");
         let ors = exec.get_var("ORS");
         let mut out = std::io::BufWriter::new(std::io::stdout());
@@ -306,17 +524,19 @@ fn main() {
                 args.files.clone()
             };
             for src in sources {
-                let mut reader: Box<dyn std::io::BufRead> = if src == "-" {
-                    Box::new(std::io::BufReader::new(std::io::stdin()))
+                let raw = if src == "-" {
+                    Ok(Box::new(std::io::stdin()) as Box<dyn std::io::Read + Send>)
                 } else {
-                    let r = describe::open_maybe_compressed(&src)
-                        .map_err(|e| std::io::Error::new(e.kind(), format!("fk: {}: {}", src, e)));
-                    match r {
-                        Ok(r) => Box::new(std::io::BufReader::new(r)),
-                        Err(e) => {
-                            eprintln!("{}", e);
-                            process::exit(1);
-                        }
+                    describe::open_maybe_compressed(&src)
+                };
+                let r = raw
+                    .and_then(|r| input::encoding::decode(r, args.encoding))
+                    .map_err(|e| std::io::Error::new(e.kind(), format!("fk: {}: {}", src, e)));
+                let mut reader: Box<dyn std::io::BufRead> = match r {
+                    Ok((r, _)) => Box::new(std::io::BufReader::new(r)),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        process::exit(1);
                     }
                 };
 
@@ -333,53 +553,37 @@ fn main() {
                     if bytes == 0 {
                         break;
                     }
-                    if buf.ends_with('\n') {
-                        buf.pop();
-                        if buf.ends_with('\r') {
-                            buf.pop();
-                        }
-                    }
                     nr += 1;
-                    if nr > limit {
+                    if nr_print.past_end(nr) || exec.over_input_limit() {
                         break;
                     }
+                    if !nr_print.keeps(nr) {
+                        continue;
+                    }
+                    input::strip_line_end(&mut buf, args.preserve_cr);
                     let _ = out.write_all(buf.as_bytes());
                     let _ = out.write_all(ors.as_bytes());
                 }
-                if nr >= limit {
+                if nr_print.past_end(nr + 1) || exec.input_limit().is_some() {
                     break;
                 }
             }
         } else {
-            let reader: Box<dyn input::RecordReader> = {
-                if effective_mode == cli::InputMode::Line && rs.len() > 1 {
-                    match input::regex_rs::RegexReader::new(&rs) {
-                        Ok(r) => Box::new(r),
-                        Err(e) => {
-                            eprintln!("fk: {}", e);
-                            process::exit(2);
-                        }
-                    }
-                } else {
-                    match effective_mode {
-                        cli::InputMode::Csv => Box::new(input::csv::CsvReader::comma()),
-                        cli::InputMode::Tsv => Box::new(input::csv::CsvReader::tab()),
-                        cli::InputMode::Json => Box::new(input::json::JsonReader),
-                        cli::InputMode::Line => Box::new(input::line::LineReader::new()),
-                        cli::InputMode::Parquet => unreachable!(),
-                    }
-                }
-            };
-
+            let reader = record_reader(&effective_mode, &rs, ReaderOpts::new(&args));
             let mut inp = input::Input::with_reader(&args.files, reader);
+            inp.set_dialect(exec.csv_dialect());
+            inp.set_encoding(args.encoding);
             let mut nr: u64 = 0;
             loop {
                 match inp.next_record() {
                     Ok(Some(record)) => {
                         nr += 1;
-                        if nr > limit {
+                        if nr_print.past_end(nr) || exec.over_input_limit() {
                             break;
                         }
+                        if !nr_print.keeps(nr) {
+                            continue;
+                        }
                         if !record.text.is_empty() {
                             let _ = out.write_all(record.text.as_bytes());
                         }
@@ -395,95 +599,270 @@ fn main() {
         }
         let _ = out.flush();
     } else {
-        // Select record reader based on input mode and RS (which may be set in BEGIN)
-        let reader: Box<dyn input::RecordReader> = {
-            let rs = exec.get_var("RS");
-            if effective_mode == cli::InputMode::Line && rs.len() > 1 {
-                match input::regex_rs::RegexReader::new(&rs) {
-                    Ok(r) => Box::new(r),
-                    Err(e) => {
-                        eprintln!("fk: {}", e);
-                        process::exit(2);
-                    }
-                }
-            } else {
-                match effective_mode {
-                    cli::InputMode::Csv => Box::new(input::csv::CsvReader::comma()),
-                    cli::InputMode::Tsv => Box::new(input::csv::CsvReader::tab()),
-                    cli::InputMode::Json => Box::new(input::json::JsonReader),
-                    cli::InputMode::Line => Box::new(input::line::LineReader::new()),
-                    cli::InputMode::Parquet => unreachable!(),
-                }
-            }
-        };
-
-        let inp = input::Input::with_reader(&args.files, reader);
-        exec.set_input(inp);
-        let mut first_record = true;
-        let mut prev_filename = String::new();
+        // The reader follows the input mode and RS (which may be set in
+        // BEGIN). If BEGIN already read records, carry on from there.
+        exec.open_input();
+        let keyed_json = args.header_mode && effective_mode == cli::InputMode::Json;
+        // One record for the whole run: its buffers are refilled in place.
+        let mut record = input::Record::default();
         loop {
-            match exec.next_record() {
-                Ok(Some(record)) => {
-                    let cur_filename = exec.current_filename().to_owned();
-                    if cur_filename != prev_filename {
-                        if !prev_filename.is_empty() {
-                            exec.run_endfile();
-                            if exec.should_exit().is_some() {
-                                break;
-                            }
+            match exec.read_record(&mut record) {
+                Ok(true) => {
+                    if let Some(p) = progress.as_mut() {
+                        records += 1;
+                        if p.tick(records) {
+                            p.draw(records, exec.input_bytes_read(), exec.current_filename());
                         }
-                        prev_filename = cur_filename;
-                        exec.set_var("FILENAME", &prev_filename);
-                        exec.reset_fnr();
-                        exec.run_beginfile();
+                    }
+                    // A new source (by position, so a file named twice is
+                    // two files): FILENAME and FNR are set before BEGINFILE,
+                    // and its first record is matched with FNR == 1. A
+                    // plain getline may already have crossed into it.
+                    if exec.enter_input_source() {
                         if exec.should_exit().is_some() {
                             break;
                         }
+                        match exec.redo_first_record(&mut record) {
+                            Ok(true) => {}
+                            Ok(false) => continue,
+                            Err(e) => {
+                                exec.flush_output();
+                                progress::clear();
+                                eprintln!("fk: {}: {}", exec.current_filename(), e);
+                                process::exit(1);
+                            }
+                        }
                     }
 
-                    if args.header_mode && first_record {
-                        first_record = false;
-                        if let Some(fields) = &record.fields {
-                            exec.set_header(fields);
-                        } else {
-                            exec.set_header_from_text(&record.text);
-                        }
-                        exec.increment_fnr();
+                    if keyed_json {
+                        // Header names come from the keys; every object is data.
+                        exec.sync_input_header();
+                    } else if exec.take_header(&record) {
                         continue;
                     }
                     exec.increment_fnr();
-                    exec.run_record(&record);
+                    match exec.handle_malformed(&mut record) {
+                        Ok(true) => {
+                            if exec.should_exit().is_some() {
+                                break;
+                            }
+                            continue;
+                        }
+                        Ok(false) => {}
+                        Err(e) => {
+                            exec.flush_output();
+                            progress::clear();
+                            eprintln!("{}", e);
+                            exec.end_in_place(false);
+                            process::exit(1);
+                        }
+                    }
+                    if exec.every_skip() || exec.sample_skip() {
+                        continue;
+                    }
+                    exec.run_record_mut(&mut record);
                     if exec.should_exit().is_some() {
                         break;
                     }
                     if exec.take_next_file() {
-                        exec.run_endfile();
+                        // Skipping the rest of the file: leave it untouched.
+                        exec.leave_input_source(false);
                         exec.skip_input_source();
-                        first_record = true;
-                        prev_filename.clear();
+                        if args.header_mode && !keyed_json {
+                            exec.expect_header();
+                        }
                         if exec.should_exit().is_some() {
                             break;
                         }
                     }
                 }
-                Ok(None) => {
-                    if !prev_filename.is_empty() {
-                        exec.run_endfile();
-                    }
+                Ok(false) => {
+                    exec.leave_input_source(true);
                     break;
                 }
                 Err(e) => {
+                    progress::clear();
                     eprintln!("{}", e);
+                    exec.end_in_place(false);
                     process::exit(1);
                 }
             }
         }
     }
 
+    progress::clear();
+    if let Some(t) = timing.as_mut() {
+        t.records = t.lap();
+    }
+
+    // An exit mid-file discards that file's partial in-place output.
+    exec.end_in_place(false);
     exec.run_last_rules();
     exec.run_end();
-    if let Some(code) = exec.should_exit() {
-        process::exit(code);
+    save_state(&args, &exec);
+    report_timing(&mut timing, &exec, &args.files);
+    let skipped = exec.skipped_inputs();
+    if !skipped.is_empty() {
+        eprintln!("fk: skipped {} unreadable input(s):", skipped.len());
+        for s in skipped {
+            eprintln!("  {}", s);
+        }
+    }
+    match exec.should_exit() {
+        Some(code) if code != 0 => process::exit(code),
+        _ if exec.input_limit() == Some(action::InputLimit::Runtime) => {
+            process::exit(MAX_RUNTIME_EXIT)
+        }
+        _ if !skipped.is_empty() => process::exit(KEEP_GOING_EXIT),
+        Some(code) => process::exit(code),
+        None => {}
+    }
+}
+
+/// Command-line settings that shape the record reader.
+#[derive(Clone, Copy)]
+struct ReaderOpts {
+    /// `-H`: JSON fields are laid out by key.
+    header: bool,
+    /// `--tsv-raw`: TSV escapes are not decoded.
+    tsv_raw: bool,
+    /// `--preserve-cr`: records keep the `\r` of a `\r\n` line end.
+    preserve_cr: bool,
+}
+
+impl ReaderOpts {
+    fn new(args: &cli::Args) -> Self {
+        ReaderOpts {
+            header: args.header_mode,
+            tsv_raw: args.tsv_raw,
+            preserve_cr: args.preserve_cr,
+        }
+    }
+}
+
+/// Input mode for the run: `-i`, or when neither `-i` nor `-F` is given,
+/// the one the first file's extension implies (explicit -F implies line
+/// mode).
+fn effective_input_mode(args: &cli::Args) -> cli::InputMode {
+    if args.input_mode == cli::InputMode::Line
+        && args.field_separator.is_none()
+        && !args.files.is_empty()
+    {
+        if let Some(fmt) = describe::format_from_extension(&args.files[0]) {
+            match fmt {
+                describe::Format::Csv => cli::InputMode::Csv,
+                describe::Format::Tsv => cli::InputMode::Tsv,
+                describe::Format::Json => cli::InputMode::Json,
+                describe::Format::Space => cli::InputMode::Line,
+                describe::Format::Parquet => cli::InputMode::Parquet,
+            }
+        } else {
+            args.input_mode.clone()
+        }
+    } else {
+        args.input_mode.clone()
+    }
+}
+
+/// Record reader for the input mode; a multi-character RS splits line
+/// input on a regex, and JSON under `-H` lays fields out by key.
+fn record_reader(
+    mode: &cli::InputMode,
+    rs: &str,
+    opts: ReaderOpts,
+) -> Box<dyn input::RecordReader> {
+    let mut reader = mode_reader(mode, rs, &opts);
+    reader.set_preserve_cr(opts.preserve_cr);
+    reader
+}
+
+fn mode_reader(mode: &cli::InputMode, rs: &str, opts: &ReaderOpts) -> Box<dyn input::RecordReader> {
+    if *mode == cli::InputMode::Line && rs.len() > 1 {
+        match input::regex_rs::RegexReader::new(rs) {
+            Ok(r) => Box::new(r),
+            Err(e) => {
+                eprintln!("fk: {}", e);
+                process::exit(2);
+            }
+        }
+    } else {
+        match mode {
+            cli::InputMode::Csv => Box::new(input::csv::CsvReader::comma()),
+            cli::InputMode::Tsv => {
+                Box::new(input::csv::CsvReader::tab().with_escapes(!opts.tsv_raw))
+            }
+            cli::InputMode::Json if opts.header => Box::new(input::json::JsonReader::keyed()),
+            cli::InputMode::Json => Box::new(input::json::JsonReader::new()),
+            cli::InputMode::Line => Box::new(input::line::LineReader::new()),
+            cli::InputMode::Parquet => unreachable!(),
+        }
+    }
+}
+
+/// Exit status when `--keep-going` skipped any input.
+const KEEP_GOING_EXIT: i32 = 4;
+
+/// Exit status when `--max-runtime` cut input short (as timeout(1)).
+const MAX_RUNTIME_EXIT: i32 = 124;
+
+/// Input description for `--explain` / `--check` summaries.
+fn explain_context(args: &cli::Args) -> explain::ExplainContext {
+    let mode_str = match &args.input_mode {
+        cli::InputMode::Line => "line",
+        cli::InputMode::Csv => "csv",
+        cli::InputMode::Tsv => "tsv",
+        cli::InputMode::Json => "json",
+        cli::InputMode::Parquet => "parquet",
+    };
+    let mut ctx = explain::ExplainContext::from_cli(
+        mode_str,
+        args.header_mode,
+        args.field_separator.as_deref(),
+        &args.files,
+    );
+    ctx.ignore_case = args.ignore_case;
+    ctx.word_regexp = args.word_regexp;
+    ctx
+}
+
+/// Whether to flush after every print: `--line-buffered`, else
+/// `FK_UNBUFFERED` (`0` or empty turns it off), else when standard output
+/// is a terminal, as grep does.
+fn line_buffered(args: &cli::Args) -> bool {
+    if args.line_buffered {
+        return true;
+    }
+    match env::var("FK_UNBUFFERED") {
+        Ok(v) => !v.is_empty() && v != "0",
+        Err(_) => args.in_place.is_none() && std::io::stdout().is_terminal(),
+    }
+}
+
+/// Write `--state` arrays back after END.
+fn save_state(args: &cli::Args, exec: &action::Executor) {
+    if let Some(ref path) = args.state
+        && let Err(e) = exec.save_state(path)
+    {
+        eprintln!("fk: state: {}", e);
+        process::exit(2);
+    }
+}
+
+/// Input size for `--progress`: known only when every input is a
+/// regular, uncompressed file (compressed ones are read decompressed).
+fn progress_total(files: &[String]) -> Option<u64> {
+    if files.iter().any(|f| describe::is_compressed(f)) {
+        return None;
+    }
+    timing::input_bytes(files)
+}
+
+/// Finish the `--timing` report (the last lap is END) and print it.
+fn report_timing(timing: &mut Option<timing::Timing>, exec: &action::Executor, files: &[String]) {
+    if let Some(t) = timing.as_mut() {
+        t.end = t.lap();
+        let records = exec.get_var("NR").parse::<f64>().unwrap_or(0.0) as u64;
+        eprint!("{}", t.report(records, timing::input_bytes(files)));
     }
 }
 
@@ -504,6 +883,73 @@ fn is_end_print_nr_only(program: &parser::Program) -> bool {
     }
 }
 
+/// A program that only prints whole records picked by NR, so it can run
+/// without the interpreter.
+enum NrPrint {
+    /// `NR > n { exit } 1`: the first n records.
+    Head(u64),
+    /// `NR % k == n { print }`: every kth record.
+    Stride(u64, u64),
+}
+
+impl NrPrint {
+    fn keeps(&self, nr: u64) -> bool {
+        match *self {
+            NrPrint::Head(limit) => nr <= limit,
+            NrPrint::Stride(k, n) => nr % k == n,
+        }
+    }
+
+    /// Record `nr` is past the last one printed, so reading can stop.
+    fn past_end(&self, nr: u64) -> bool {
+        matches!(*self, NrPrint::Head(limit) if nr > limit)
+    }
+}
+
+/// The `(k, n)` of a single rule `NR % k == n` that prints `$0` (also
+/// written `n == NR % k`, or `every k`).
+fn nr_stride(program: &parser::Program) -> Option<(u64, u64)> {
+    let [rule] = program.rules.as_slice() else {
+        return None;
+    };
+    if !prints_record(&rule.action) {
+        return None;
+    }
+    let Some(parser::Pattern::Expression(parser::Expr::BinOp(left, parser::BinOp::Eq, right))) =
+        &rule.pattern
+    else {
+        return None;
+    };
+    let whole = |e: &parser::Expr| match e {
+        parser::Expr::NumberLit(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as u64),
+        _ => None,
+    };
+    let modulus = |e: &parser::Expr| match e {
+        parser::Expr::BinOp(nr, parser::BinOp::Mod, k) if matches!(nr.as_ref(), parser::Expr::Var(name) if name == "NR") => {
+            whole(k).filter(|&k| k > 0)
+        }
+        _ => None,
+    };
+    match (modulus(left), modulus(right)) {
+        (Some(k), None) => Some((k, whole(right)?)),
+        (None, Some(k)) => Some((k, whole(left)?)),
+        _ => None,
+    }
+}
+
+/// The action is a plain `print` (or `print $0`) to stdout.
+fn prints_record(action: &[parser::Statement]) -> bool {
+    matches!(
+        action,
+        [parser::Statement::Print(exprs, None)]
+            if matches!(
+                exprs.as_slice(),
+                [parser::Expr::Field(inner)]
+                    if matches!(inner.as_ref(), parser::Expr::NumberLit(n) if *n == 0.0)
+            )
+    )
+}
+
 fn head_print_limit(program: &parser::Program) -> Option<u64> {
     if program.rules.len() != 2 {
         return None;
@@ -526,14 +972,9 @@ fn head_print_limit(program: &parser::Program) -> Option<u64> {
     if !exit_only {
         return None;
     }
-    let print_default = match (&second.pattern, second.action.as_slice()) {
-        (Some(parser::Pattern::Expression(expr)), [parser::Statement::Print(exprs, None)]) => {
-            matches!(expr, parser::Expr::NumberLit(n) if *n != 0.0)
-                && matches!(
-                    exprs.as_slice(),
-                    [parser::Expr::Field(inner)]
-                        if matches!(inner.as_ref(), parser::Expr::NumberLit(n) if *n == 0.0)
-                )
+    let print_default = match &second.pattern {
+        Some(parser::Pattern::Expression(expr)) => {
+            matches!(expr, parser::Expr::NumberLit(n) if *n != 0.0) && prints_record(&second.action)
         }
         _ => false,
     };
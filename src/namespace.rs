@@ -0,0 +1,276 @@
+//! gawk-5-style namespaces: `@namespace "name"` and `ns::name`.
+//!
+//! Resolution runs on the token stream before parsing, so the AST, the
+//! executor and the function table only ever see fully qualified names:
+//!
+//! - `ns::name` becomes one identifier; `awk::name` is plain `name`.
+//! - After `@namespace "stats"`, functions defined and global variables used
+//!   without a qualifier belong to `stats::`. Function parameters, ALL-CAPS
+//!   names (built-in variables) and the contextual keywords `length`,
+//!   `every` and `last` are left alone.
+//! - An unqualified call inside a namespace resolves to that namespace's
+//!   function first and falls back to the `awk` one. Builtins always win;
+//!   defining a function with a builtin's name is a parse error.
+//!
+//! Each `-f` file starts back in the default namespace.
+
+use std::collections::HashSet;
+
+use crate::builtins::is_builtin_func;
+use crate::error::FkError;
+use crate::lexer::{Spanned, Token};
+
+/// The namespace programs start in; its names carry no prefix.
+pub const DEFAULT_NAMESPACE: &str = "awk";
+
+/// Directive that resets the namespace between concatenated `-f` files.
+pub const RESET_DIRECTIVE: &str = "@namespace \"awk\"";
+
+/// Merge `a :: b` into qualified identifiers and apply `@namespace`
+/// directives. Programs that use neither are returned untouched.
+pub fn resolve(tokens: Vec<Spanned>) -> Result<Vec<Spanned>, FkError> {
+    let has_directive = tokens
+        .windows(2)
+        .any(|w| is_directive(&w[0].token, &w[1].token));
+    let has_qualified = tokens.iter().any(|t| t.token == Token::ColonColon);
+    if !has_directive && !has_qualified {
+        return Ok(tokens);
+    }
+    let mut tokens = merge_qualified(tokens)?;
+    if has_directive {
+        tokens = apply_directives(tokens)?;
+    }
+    for t in &mut tokens {
+        if let Token::Ident(name) | Token::FieldVar(name) = &mut t.token
+            && let Some(rest) = name.strip_prefix("awk::")
+        {
+            *name = rest.to_string();
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_directive(at: &Token, word: &Token) -> bool {
+    *at == Token::At && word.as_ident_str() == Some("namespace")
+}
+
+/// `Ident :: Ident` (or `$Ident :: Ident`) → one token named `a::b`.
+fn merge_qualified(tokens: Vec<Spanned>) -> Result<Vec<Spanned>, FkError> {
+    let mut out: Vec<Spanned> = Vec::with_capacity(tokens.len());
+    let mut iter = tokens.into_iter();
+    while let Some(t) = iter.next() {
+        if t.token != Token::ColonColon {
+            out.push(t);
+            continue;
+        }
+        let next = iter.next().map(|n| n.token);
+        let prev = out.last_mut().map(|p| &mut p.token);
+        match (prev, next) {
+            (Some(Token::Ident(ns) | Token::FieldVar(ns)), Some(Token::Ident(name)))
+                if !ns.contains("::") =>
+            {
+                ns.push_str("::");
+                ns.push_str(&name);
+            }
+            _ => {
+                return Err(FkError::new(
+                    t.span,
+                    "'::' must join two names, as in ns::name",
+                ));
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn apply_directives(tokens: Vec<Spanned>) -> Result<Vec<Spanned>, FkError> {
+    let mut out: Vec<Spanned> = Vec::with_capacity(tokens.len());
+    let mut ns = DEFAULT_NAMESPACE.to_string();
+    let mut defined: HashSet<String> = HashSet::new();
+    // (token index, unqualified name) for calls that may fall back to `awk`.
+    let mut implicit: Vec<(usize, String)> = Vec::new();
+    let mut locals: HashSet<String> = HashSet::new();
+    let mut depth = 0usize;
+    let mut in_func = false;
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let span = tokens[i].span;
+        let next = tokens.get(i + 1).map(|t| &t.token);
+        match &tokens[i].token {
+            Token::At if next.and_then(Token::as_ident_str) == Some("namespace") => {
+                if depth > 0 {
+                    return Err(FkError::new(
+                        span,
+                        "@namespace is only allowed at top level",
+                    ));
+                }
+                ns = match tokens.get(i + 2).map(|t| &t.token) {
+                    Some(Token::StringLit(name)) if is_namespace_name(name) => name.clone(),
+                    _ => {
+                        let msg = "@namespace needs a name string, as in @namespace \"stats\"";
+                        return Err(FkError::new(span, msg));
+                    }
+                };
+                i += 3;
+                continue;
+            }
+            Token::At => {
+                // `@sort` and friends: keep the modifier name as is.
+                out.push(tokens[i].clone());
+                if let Some(t) = tokens.get(i + 1) {
+                    out.push(t.clone());
+                }
+                i += 2;
+                continue;
+            }
+            Token::Function => {
+                if let Some(Token::Ident(name)) = next {
+                    out.push(tokens[i].clone());
+                    let name = qualify(&ns, name);
+                    defined.insert(name.clone());
+                    out.push(Spanned {
+                        token: Token::Ident(name),
+                        span: tokens[i + 1].span,
+                    });
+                    i += 2;
+                    locals.clear();
+                    in_func = true;
+                    while let Some(t) = tokens.get(i) {
+                        if let Token::Ident(p) = &t.token {
+                            locals.insert(p.clone());
+                        }
+                        out.push(t.clone());
+                        i += 1;
+                        if t.token == Token::RParen {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+            }
+            Token::LBrace => depth += 1,
+            Token::RBrace => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 && in_func {
+                    in_func = false;
+                    locals.clear();
+                }
+            }
+            Token::Ident(name)
+                if next == Some(&Token::LParen)
+                    && ns != DEFAULT_NAMESPACE
+                    && !name.contains("::")
+                    && !is_builtin_func(name) =>
+            {
+                implicit.push((out.len(), name.clone()));
+                out.push(Spanned {
+                    token: Token::Ident(format!("{ns}::{name}")),
+                    span,
+                });
+                i += 1;
+                continue;
+            }
+            // Builtin or awk-namespace call: unchanged.
+            Token::Ident(_) if next == Some(&Token::LParen) => {}
+            Token::Ident(name) | Token::FieldVar(name)
+                if !locals.contains(name) && !is_global_name(name) =>
+            {
+                let mut t = tokens[i].clone();
+                if let Token::Ident(n) | Token::FieldVar(n) = &mut t.token {
+                    *n = qualify(&ns, name);
+                }
+                out.push(t);
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+
+    for (idx, name) in implicit {
+        if let Token::Ident(qualified) = &mut out[idx].token
+            && !defined.contains(qualified.as_str())
+        {
+            *qualified = name;
+        }
+    }
+    Ok(out)
+}
+
+/// `name` as seen from namespace `ns`.
+fn qualify(ns: &str, name: &str) -> String {
+    if ns == DEFAULT_NAMESPACE || name.contains("::") {
+        name.to_string()
+    } else {
+        format!("{ns}::{name}")
+    }
+}
+
+/// Names that always live in the `awk` namespace.
+fn is_global_name(name: &str) -> bool {
+    matches!(name, "length" | "every" | "last")
+        || (name.bytes().any(|b| b.is_ascii_uppercase())
+            && !name.bytes().any(|b| b.is_ascii_lowercase()))
+}
+
+fn is_namespace_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The part of a possibly qualified name after the last `::`.
+pub fn local_name(name: &str) -> &str {
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn names(src: &str) -> Vec<String> {
+        let tokens = Lexer::new(src).tokenize().unwrap();
+        resolve(tokens)
+            .unwrap()
+            .into_iter()
+            .filter_map(|t| match t.token {
+                Token::Ident(n) | Token::FieldVar(n) => Some(n),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn qualified_names_are_merged() {
+        assert_eq!(names("{ stats::n++; print awk::x }"), ["stats::n", "x"]);
+        let bad = resolve(Lexer::new("{ x = ::y }").tokenize().unwrap());
+        assert!(bad.unwrap_err().message.contains("'::'"));
+    }
+
+    #[test]
+    fn namespace_qualifies_functions_and_globals() {
+        let src = "@namespace \"s\"\nfunction f(a) { t = a; return g(a) + length(NR) }\n\
+                   function g(b) { return b }\n{ print h(n) }";
+        assert_eq!(
+            names(src),
+            [
+                "s::f", "a", "s::t", "a", "s::g", "a", "length", "NR", "s::g", "b", "b", "h",
+                "s::n"
+            ]
+        );
+    }
+
+    #[test]
+    fn directive_must_be_top_level_and_named() {
+        let err = |src: &str| resolve(Lexer::new(src).tokenize().unwrap()).unwrap_err();
+        assert!(err("{ @namespace \"x\" }").message.contains("top level"));
+        assert!(err("@namespace x").message.contains("name string"));
+        assert!(err("@namespace \"a b\"").message.contains("name string"));
+    }
+}
@@ -0,0 +1,229 @@
+//! Post-parse optimization for execution: constant folding.
+//!
+//! Arithmetic on number literals and concatenation of string literals are
+//! evaluated once here instead of for every record, using the executor's
+//! own arithmetic so results are bit-identical. Division or modulo by a
+//! constant zero is left alone, so a zero divisor is still handled (and
+//! reported) by the executor when it runs. Only the program that runs is
+//! folded; `--explain` and `--check` see the source as written.
+
+use crate::action::eval_binop;
+use crate::parser::{BinOp, Block, Expr, Pattern, Program, Redirect, Statement};
+use crate::runtime::Value;
+
+/// Fold constant subexpressions throughout `program`.
+pub fn fold_constants(program: &mut Program) {
+    for block in [
+        &mut program.begin,
+        &mut program.end,
+        &mut program.beginfile,
+        &mut program.endfile,
+        &mut program.error,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        fold_block(block);
+    }
+    for rule in &mut program.rules {
+        if let Some(pattern) = &mut rule.pattern {
+            fold_pattern(pattern);
+        }
+        fold_block(&mut rule.action);
+    }
+    for func in &mut program.functions {
+        fold_block(&mut func.body);
+    }
+}
+
+fn fold_pattern(pattern: &mut Pattern) {
+    match pattern {
+        Pattern::Regex(_) => {}
+        Pattern::Expression(e) | Pattern::Last(e) => fold_expr(e),
+        Pattern::Range(start, end) => {
+            fold_pattern(start);
+            fold_pattern(end);
+        }
+    }
+}
+
+fn fold_block(block: &mut Block) {
+    for stmt in block {
+        fold_stmt(stmt);
+    }
+}
+
+fn fold_stmt(stmt: &mut Statement) {
+    match stmt {
+        Statement::Print(exprs, redir) | Statement::Printf(exprs, redir) => {
+            exprs.iter_mut().for_each(fold_expr);
+            if let Some(Redirect::Overwrite(e) | Redirect::Append(e) | Redirect::Pipe(e)) = redir {
+                fold_expr(e);
+            }
+        }
+        Statement::If(cond, then_block, else_block) => {
+            fold_expr(cond);
+            fold_block(then_block);
+            if let Some(b) = else_block {
+                fold_block(b);
+            }
+        }
+        Statement::While(cond, body) | Statement::DoWhile(body, cond) => {
+            fold_expr(cond);
+            fold_block(body);
+        }
+        Statement::For(init, cond, step, body) => {
+            if let Some(s) = init {
+                fold_stmt(s);
+            }
+            if let Some(c) = cond {
+                fold_expr(c);
+            }
+            if let Some(s) = step {
+                fold_stmt(s);
+            }
+            fold_block(body);
+        }
+        Statement::ForIn(_, _, _, body) | Statement::Block(body) => fold_block(body),
+        Statement::Delete(_, e) | Statement::Expression(e) => fold_expr(e),
+        Statement::Exit(e) | Statement::Return(e) => {
+            if let Some(e) = e {
+                fold_expr(e);
+            }
+        }
+        Statement::DeleteAll(_)
+        | Statement::Next
+        | Statement::Nextfile
+        | Statement::Break
+        | Statement::Continue => {}
+    }
+}
+
+fn fold_expr(expr: &mut Expr) {
+    match expr {
+        Expr::NumberLit(_) | Expr::StringLit(_) | Expr::Var(_) => {}
+        Expr::Field(e)
+        | Expr::ArrayRef(_, e)
+        | Expr::ArrayIn(e, _)
+        | Expr::LogicalNot(e)
+        | Expr::Increment(e, _)
+        | Expr::Decrement(e, _)
+        | Expr::UnaryMinus(e)
+        | Expr::TryVal(e)
+        | Expr::NullFence(e) => fold_expr(e),
+        Expr::BinOp(l, _, r)
+        | Expr::LogicalAnd(l, r)
+        | Expr::LogicalOr(l, r)
+        | Expr::Match(l, r)
+        | Expr::NotMatch(l, r)
+        | Expr::Assign(l, r)
+        | Expr::CompoundAssign(l, _, r)
+        | Expr::Concat(l, r)
+        | Expr::NullCoalesce(l, r) => {
+            fold_expr(l);
+            fold_expr(r);
+        }
+        Expr::Ternary(c, t, f) => {
+            fold_expr(c);
+            fold_expr(t);
+            fold_expr(f);
+        }
+        Expr::Sprintf(args) | Expr::FuncCall(_, args) => args.iter_mut().for_each(fold_expr),
+        Expr::Getline(var, source) => {
+            if let Some(e) = var {
+                fold_expr(e);
+            }
+            if let Some(e) = source {
+                fold_expr(e);
+            }
+        }
+        Expr::GetlinePipe(cmd, var) => {
+            fold_expr(cmd);
+            if let Some(e) = var {
+                fold_expr(e);
+            }
+        }
+    }
+    if let Some(folded) = folded(expr) {
+        *expr = folded;
+    }
+}
+
+/// The literal `expr` evaluates to, when its operands already are literals.
+fn folded(expr: &Expr) -> Option<Expr> {
+    match expr {
+        Expr::BinOp(l, op, r) => {
+            let (Expr::NumberLit(a), Expr::NumberLit(b)) = (l.as_ref(), r.as_ref()) else {
+                return None;
+            };
+            match op {
+                BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Pow => {}
+                BinOp::Div | BinOp::Mod if *b != 0.0 => {}
+                _ => return None,
+            }
+            let v = eval_binop(Value::from_number(*a), op, Value::from_number(*b));
+            Some(Expr::NumberLit(v.to_number()))
+        }
+        Expr::UnaryMinus(e) => match e.as_ref() {
+            Expr::NumberLit(n) => Some(Expr::NumberLit(-n)),
+            _ => None,
+        },
+        Expr::Concat(l, r) => match (l.as_ref(), r.as_ref()) {
+            (Expr::StringLit(a), Expr::StringLit(b)) => Some(Expr::StringLit(format!("{a}{b}"))),
+            _ => None,
+        },
+        Expr::NullFence(e) => match e.as_ref() {
+            Expr::NumberLit(_) | Expr::StringLit(_) => Some(e.as_ref().clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn folded_program(src: &str) -> Program {
+        let tokens = Lexer::new(src).tokenize().unwrap();
+        let mut program = Parser::new(tokens).parse().unwrap();
+        fold_constants(&mut program);
+        program
+    }
+
+    fn printed(program: &Program) -> &[Expr] {
+        match &program.rules[0].action[0] {
+            Statement::Print(exprs, _) => exprs,
+            other => panic!("not a print: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn folds_literal_arithmetic_and_concat() {
+        let p = folded_program(
+            r#"{ print 60 * 60 * 1000, -(2 ** 10), "a" "b" "c", $1 * (3600 / 1000) }"#,
+        );
+        let exprs = printed(&p);
+        assert!(matches!(exprs[0], Expr::NumberLit(n) if n == 3_600_000.0));
+        assert!(matches!(exprs[1], Expr::NumberLit(n) if n == -1024.0));
+        assert!(matches!(&exprs[2], Expr::StringLit(s) if s == "abc"));
+        assert!(matches!(
+            &exprs[3],
+            Expr::BinOp(_, BinOp::Mul, r) if matches!(r.as_ref(), Expr::NumberLit(n) if *n == 3.6)
+        ));
+    }
+
+    #[test]
+    fn leaves_division_by_zero_and_mixed_concat() {
+        let p = folded_program(r#"{ print 1 / 0, 5 % 0, "n" 1.5, $1 * 3600 / 1000 }"#);
+        let exprs = printed(&p);
+        assert!(matches!(exprs[0], Expr::BinOp(_, BinOp::Div, _)));
+        assert!(matches!(exprs[1], Expr::BinOp(_, BinOp::Mod, _)));
+        // Number text depends on CONVFMT at run time.
+        assert!(matches!(exprs[2], Expr::Concat(..)));
+        // Left-associative: ($1 * 3600) / 1000 has no constant part.
+        assert!(matches!(exprs[3], Expr::BinOp(_, BinOp::Div, _)));
+    }
+}
@@ -0,0 +1,186 @@
+//! Insertion-ordered string-keyed map behind awk arrays, so a plain
+//! `for (k in a)` visits keys in the order they were added and two runs
+//! of a program print the same thing.
+//!
+//! Entries live in a `Vec` in insertion order and a hash table of
+//! positions finds them by key. Deleting leaves a hole that iteration
+//! skips, so `for (k in a) delete a[k]` stays linear; the holes are
+//! squeezed out once they outnumber the live entries. A key deleted and
+//! added again goes to the end.
+
+use std::fmt;
+use std::hash::BuildHasher;
+
+use hashbrown::HashTable;
+use hashbrown::hash_table::Entry;
+use rustc_hash::FxBuildHasher;
+
+#[derive(Clone)]
+pub struct OrderedMap<V> {
+    /// Position in `entries` of each live key.
+    table: HashTable<usize>,
+    /// Entries in insertion order; `None` where one was deleted.
+    entries: Vec<Option<(String, V)>>,
+    holes: usize,
+}
+
+impl<V> Default for OrderedMap<V> {
+    fn default() -> Self {
+        OrderedMap {
+            table: HashTable::new(),
+            entries: Vec::new(),
+            holes: 0,
+        }
+    }
+}
+
+impl<V: fmt::Debug> fmt::Debug for OrderedMap<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+fn hash(key: &str) -> u64 {
+    FxBuildHasher.hash_one(key)
+}
+
+/// The key of an entry; holes are never in the table, so never asked.
+fn key_of<V>(entry: &Option<(String, V)>) -> &str {
+    entry.as_ref().map_or("", |(k, _)| k)
+}
+
+impl<V> OrderedMap<V> {
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        let entries = &self.entries;
+        self.table
+            .find(hash(key), |&i| key_of(&entries[i]) == key)
+            .copied()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.position(key).is_some()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&V> {
+        let i = self.position(key)?;
+        self.entries[i].as_ref().map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        let i = self.position(key)?;
+        self.entries[i].as_mut().map(|(_, v)| v)
+    }
+
+    /// Set `key` to `value`: in place if it exists, else at the end.
+    pub fn insert(&mut self, key: &str, value: V) {
+        let entries = &self.entries;
+        let found = self.table.entry(
+            hash(key),
+            |&i| key_of(&entries[i]) == key,
+            |&i| hash(key_of(&entries[i])),
+        );
+        match found {
+            Entry::Occupied(slot) => {
+                if let Some((_, v)) = &mut self.entries[*slot.get()] {
+                    *v = value;
+                }
+            }
+            Entry::Vacant(slot) => {
+                slot.insert(self.entries.len());
+                self.entries.push(Some((key.to_string(), value)));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        let entries = &self.entries;
+        let found = self
+            .table
+            .find_entry(hash(key), |&i| key_of(&entries[i]) == key);
+        let (i, _) = found.ok()?.remove();
+        let (_, value) = self.entries[i].take()?;
+        self.holes += 1;
+        if self.holes > self.table.len() {
+            self.compact();
+        }
+        Some(value)
+    }
+
+    /// Drop the holes left by deletions and renumber the table.
+    fn compact(&mut self) {
+        self.entries.retain(Option::is_some);
+        self.holes = 0;
+        self.table.clear();
+        let entries = &self.entries;
+        for (i, entry) in entries.iter().enumerate() {
+            self.table
+                .insert_unique(hash(key_of(entry)), i, |&j| hash(key_of(&entries[j])));
+        }
+    }
+
+    /// Every (key, value) pair, in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &V)> {
+        self.entries.iter().flatten().map(|(k, v)| (k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(map: &OrderedMap<i32>) -> Vec<&str> {
+        map.keys().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn keys_come_back_in_insertion_order() {
+        let mut map = OrderedMap::default();
+        for (i, k) in ["z", "a", "10", "9", "m"].iter().enumerate() {
+            map.insert(k, i as i32);
+        }
+        map.insert("a", 7);
+        assert_eq!(keys(&map), ["z", "a", "10", "9", "m"]);
+        assert_eq!(map.get("a"), Some(&7));
+        assert_eq!(map.remove("10"), Some(2));
+        assert_eq!(map.remove("10"), None);
+        map.insert("10", 8);
+        assert_eq!(keys(&map), ["z", "a", "9", "m", "10"]);
+        assert_eq!(map.len(), 5);
+    }
+
+    #[test]
+    fn deleting_while_walking_stays_ordered_through_compaction() {
+        let mut map = OrderedMap::default();
+        for i in 0..1000 {
+            map.insert(&i.to_string(), i);
+        }
+        for i in (0..1000).filter(|i| i % 3 != 0) {
+            map.remove(&i.to_string());
+        }
+        let want: Vec<String> = (0..1000).step_by(3).map(|i| i.to_string()).collect();
+        assert_eq!(keys(&map), want);
+        assert!(map.entries.len() < 1000, "holes were squeezed out");
+        assert_eq!(map.get("999"), Some(&999));
+        assert!(!map.contains_key("998"));
+        for k in want {
+            map.remove(&k);
+        }
+        assert!(map.is_empty() && map.iter().next().is_none());
+    }
+}
@@ -0,0 +1,450 @@
+//! Structured output formats (`-o table`, `-o md`, `-o json`, `-o tsv`).
+//!
+//! Rows produced by unredirected `print` statements are buffered here
+//! instead of being written straight to stdout, so column widths and
+//! numeric alignment can be computed before anything is emitted.
+//! JSON and TSV rows need no buffering and are rendered one per line.
+
+use std::io::Write;
+
+use crate::builtins::width;
+
+/// Default number of rows buffered before the table spills to plain output.
+pub const DEFAULT_MAX_ROWS: usize = 10_000;
+
+/// Buffered rows for aligned table output.
+#[derive(Debug)]
+pub struct Table {
+    header: Option<Vec<String>>,
+    rows: Vec<Vec<String>>,
+    max_rows: usize,
+    spilled: bool,
+    markdown: bool,
+}
+
+impl Table {
+    pub fn new(max_rows: usize) -> Self {
+        Table {
+            header: None,
+            rows: Vec::new(),
+            max_rows: max_rows.max(1),
+            spilled: false,
+            markdown: false,
+        }
+    }
+
+    /// A GitHub-flavored markdown table instead of a plain aligned one.
+    pub fn markdown(max_rows: usize) -> Self {
+        Table {
+            markdown: true,
+            ..Table::new(max_rows)
+        }
+    }
+
+    /// True once column names have been set.
+    pub fn has_header(&self) -> bool {
+        self.header.is_some()
+    }
+
+    /// Set the column names; only the first call has any effect.
+    pub fn set_header(&mut self, names: Vec<String>) {
+        if self.header.is_none() {
+            self.header = Some(names);
+        }
+    }
+
+    /// Buffer a row. Past `max_rows` the buffered table is rendered and
+    /// this and every later row is written unpadded: markdown rows stay
+    /// table rows, plain tables fall back to OFS/ORS-joined output.
+    pub fn push(&mut self, row: Vec<String>, out: &mut impl Write, ofs: &str, ors: &str) {
+        if !self.spilled && self.rows.len() >= self.max_rows {
+            self.flush(out);
+            self.spilled = true;
+        }
+        if self.spilled && self.markdown {
+            let cells: Vec<String> = row.iter().map(|c| md_escape(c)).collect();
+            let _ = writeln!(out, "| {} |", cells.join(" | "));
+        } else if self.spilled {
+            let _ = out.write_all(row.join(ofs).as_bytes());
+            let _ = out.write_all(ors.as_bytes());
+        } else {
+            self.rows.push(row);
+        }
+    }
+
+    /// Render all buffered rows as an aligned table.
+    pub fn flush(&mut self, out: &mut impl Write) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let mut rows = std::mem::take(&mut self.rows);
+        if self.markdown {
+            for row in &mut rows {
+                for cell in row.iter_mut() {
+                    *cell = md_escape(cell);
+                }
+            }
+        }
+        let ncols = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        let header: Vec<String> = (0..ncols)
+            .map(|i| {
+                self.header
+                    .as_ref()
+                    .and_then(|h| h.get(i))
+                    .filter(|s| !s.is_empty())
+                    .map(|s| {
+                        if self.markdown {
+                            md_escape(s)
+                        } else {
+                            s.clone()
+                        }
+                    })
+                    .unwrap_or_else(|| format!("col{}", i + 1))
+            })
+            .collect();
+
+        let mut widths: Vec<usize> = header.iter().map(|h| width::text_width(h)).collect();
+        let mut numeric = vec![true; ncols];
+        let mut seen = vec![false; ncols];
+        for row in &rows {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(width::text_width(cell));
+                let c = cell.trim();
+                if !c.is_empty() {
+                    seen[i] = true;
+                    numeric[i] &= c.parse::<f64>().is_ok();
+                }
+            }
+        }
+        let right: Vec<bool> = (0..ncols).map(|i| numeric[i] && seen[i]).collect();
+        if self.markdown {
+            // GFM needs at least three dashes in the delimiter row.
+            for w in &mut widths {
+                *w = (*w).max(3);
+            }
+        }
+
+        let (open, sep, close) = if self.markdown {
+            ("| ", " | ", " |")
+        } else {
+            ("", "  ", "")
+        };
+        let mut line = String::new();
+        let mut emit = |cells: &[String], out: &mut dyn Write| {
+            line.clear();
+            line.push_str(open);
+            for i in 0..ncols {
+                if i > 0 {
+                    line.push_str(sep);
+                }
+                let cell = cells.get(i).map_or("", |s| s.as_str());
+                let pad = widths[i].saturating_sub(width::text_width(cell));
+                if right[i] {
+                    line.extend(std::iter::repeat_n(' ', pad));
+                    line.push_str(cell);
+                } else {
+                    line.push_str(cell);
+                    line.extend(std::iter::repeat_n(' ', pad));
+                }
+            }
+            line.push_str(close);
+            let _ = out.write_all(line.trim_end().as_bytes());
+            let _ = out.write_all(b"\n");
+        };
+        emit(&header, out);
+        let rule: Vec<String> = if self.markdown {
+            // `:` on the right marks a right-aligned column.
+            (0..ncols)
+                .map(|i| {
+                    if right[i] {
+                        format!("{}:", "-".repeat(widths[i] - 1))
+                    } else {
+                        "-".repeat(widths[i])
+                    }
+                })
+                .collect()
+        } else {
+            widths.iter().map(|w| "─".repeat(*w)).collect()
+        };
+        emit(&rule, out);
+        for row in &rows {
+            emit(row, out);
+        }
+    }
+}
+
+/// Align a header row plus data rows into lines, two spaces between
+/// columns. Columns whose non-empty data cells are all numeric are
+/// right-aligned, as in `-o table`.
+pub fn align_rows(header: &[String], rows: &[Vec<String>]) -> Vec<String> {
+    let ncols = rows.iter().map(|r| r.len()).fold(header.len(), usize::max);
+    let mut widths = vec![0; ncols];
+    let mut numeric = vec![true; ncols];
+    for (i, h) in header.iter().enumerate() {
+        widths[i] = width::text_width(h);
+    }
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(width::text_width(cell));
+            let c = cell.trim();
+            numeric[i] &= c.is_empty() || c.parse::<f64>().is_ok();
+        }
+    }
+    std::iter::once(header)
+        .chain(rows.iter().map(|r| r.as_slice()))
+        .map(|cells| {
+            let mut line = String::new();
+            for i in 0..ncols {
+                if i > 0 {
+                    line.push_str("  ");
+                }
+                let cell = cells.get(i).map_or("", |s| s.as_str());
+                let pad = widths[i].saturating_sub(width::text_width(cell));
+                if numeric[i] {
+                    line.extend(std::iter::repeat_n(' ', pad));
+                    line.push_str(cell);
+                } else {
+                    line.push_str(cell);
+                    line.extend(std::iter::repeat_n(' ', pad));
+                }
+            }
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Join cells as one RFC 4180 CSV row (no line terminator). Cells holding
+/// a comma, quote, CR or LF are quoted, with embedded quotes doubled.
+pub fn csv_row(cells: &[String]) -> String {
+    csv_row_with(cells, ",")
+}
+
+/// `csv_row` with another delimiter; cells holding it are quoted.
+pub fn csv_row_with(cells: &[String], sep: &str) -> String {
+    let mut out = String::new();
+    for (i, cell) in cells.iter().enumerate() {
+        if i > 0 {
+            out.push_str(sep);
+        }
+        if cell.contains(['"', '\r', '\n']) || (!sep.is_empty() && cell.contains(sep)) {
+            out.push('"');
+            out.push_str(&cell.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(cell);
+        }
+    }
+    out
+}
+
+/// Join cells with tabs as one TSV row (no line terminator). TSV cannot
+/// quote, so tabs, newlines, CRs and backslashes are written as `\t`, `\n`,
+/// `\r` and `\\`; the TSV reader decodes them again.
+pub fn tsv_row(cells: &[String]) -> String {
+    let mut out = String::new();
+    for (i, cell) in cells.iter().enumerate() {
+        if i > 0 {
+            out.push('\t');
+        }
+        for ch in cell.chars() {
+            match ch {
+                '\t' => out.push_str("\\t"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\\' => out.push_str("\\\\"),
+                c => out.push(c),
+            }
+        }
+    }
+    out
+}
+
+/// Escape a markdown table cell: pipes would end the cell, newlines the row.
+fn md_escape(cell: &str) -> String {
+    cell.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Render one `-o json` row: `{"name":value,...}`. Values that are valid
+/// JSON numbers are emitted bare, everything else as a string.
+pub fn json_object(names: &[String], row: &[String]) -> String {
+    let mut out = String::from("{");
+    for (i, (name, val)) in names.iter().zip(row).enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        json_string(name, &mut out);
+        out.push(':');
+        if is_json_number(val) {
+            out.push_str(val);
+        } else {
+            json_string(val, &mut out);
+        }
+    }
+    out.push('}');
+    out
+}
+
+pub(crate) fn json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                out.push_str(&format!("\\u{:04x}", c as u32));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// JSON number grammar: `-?(0|[1-9]\d*)(\.\d+)?([eE][+-]?\d+)?`.
+fn is_json_number(s: &str) -> bool {
+    let b = s.as_bytes();
+    let mut i = 0;
+    if b.first() == Some(&b'-') {
+        i += 1;
+    }
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while *i < b.len() && b[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        *i - start
+    };
+    let int_start = i;
+    let n = digits(&mut i);
+    if n == 0 || (n > 1 && b[int_start] == b'0') {
+        return false;
+    }
+    if i < b.len() && b[i] == b'.' {
+        i += 1;
+        if digits(&mut i) == 0 {
+            return false;
+        }
+    }
+    if i < b.len() && (b[i] == b'e' || b[i] == b'E') {
+        i += 1;
+        if i < b.len() && (b[i] == b'+' || b[i] == b'-') {
+            i += 1;
+        }
+        if digits(&mut i) == 0 {
+            return false;
+        }
+    }
+    i == b.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(header: &[&str], rows: &[&[&str]], max_rows: usize) -> String {
+        let mut t = Table::new(max_rows);
+        let mut out: Vec<u8> = Vec::new();
+        if !header.is_empty() {
+            t.set_header(header.iter().map(|s| s.to_string()).collect());
+        }
+        for r in rows {
+            t.push(
+                r.iter().map(|s| s.to_string()).collect(),
+                &mut out,
+                " ",
+                "\n",
+            );
+        }
+        t.flush(&mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn aligns_numeric_right() {
+        let s = render(&["name", "n"], &[&["alice", "5"], &["bob", "120"]], 10);
+        assert_eq!(s, "name     n\n─────  ───\nalice    5\nbob    120\n");
+    }
+
+    #[test]
+    fn default_column_names() {
+        let s = render(&[], &[&["x", "1", "y"]], 10);
+        assert_eq!(s, "col1  col2  col3\n────  ────  ────\nx        1  y\n");
+    }
+
+    #[test]
+    fn spills_to_plain() {
+        let s = render(&["a"], &[&["1"], &["2"], &["3"]], 2);
+        assert_eq!(s, "a\n─\n1\n2\n3\n");
+    }
+
+    #[test]
+    fn markdown_escapes_pipes() {
+        let mut t = Table::markdown(10);
+        let mut out: Vec<u8> = Vec::new();
+        t.set_header(vec!["cmd".into(), "n".into()]);
+        t.push(vec!["a|b".into(), "7".into()], &mut out, " ", "\n");
+        t.flush(&mut out);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "| cmd  |   n |\n| ---- | --: |\n| a\\|b |   7 |\n"
+        );
+    }
+
+    #[test]
+    fn json_numbers_and_strings() {
+        let names = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let row = vec![
+            "12.5".to_string(),
+            "007".to_string(),
+            "say \"hi\"".to_string(),
+        ];
+        assert_eq!(
+            json_object(&names, &row),
+            r#"{"a":12.5,"b":"007","c":"say \"hi\""}"#
+        );
+    }
+
+    #[test]
+    fn csv_row_quotes_only_when_needed() {
+        let cells: Vec<String> = ["plain", "a,b", "say \"hi\"", "two\nlines", ""]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            csv_row(&cells),
+            "plain,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\","
+        );
+        let cells: Vec<String> = ["a;b", "c,d"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(csv_row_with(&cells, ";"), "\"a;b\";c,d");
+    }
+
+    #[test]
+    fn tsv_row_escapes_tabs_newlines_and_backslashes() {
+        let cells: Vec<String> = ["a\tb", "two\nlines", "back\\t", ""]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let row = tsv_row(&cells);
+        assert_eq!(row, "a\\tb\ttwo\\nlines\tback\\\\t\t");
+        let back: Vec<String> = row
+            .split('\t')
+            .map(crate::input::csv::unescape_tsv)
+            .collect();
+        assert_eq!(back, cells);
+    }
+
+    #[test]
+    fn align_rows_pads_header_and_cells() {
+        let header = vec!["".to_string(), "n".to_string(), "tag".to_string()];
+        let rows = vec![
+            vec!["east".to_string(), "5".to_string(), "x".to_string()],
+            vec!["w".to_string(), "".to_string(), "long".to_string()],
+        ];
+        assert_eq!(
+            align_rows(&header, &rows),
+            ["      n  tag", "east  5  x", "w        long"]
+        );
+    }
+}
@@ -1,5 +1,7 @@
+use crate::builtins::is_builtin_func;
 use crate::error::{FkError, Span};
 use crate::lexer::{Spanned, Token};
+use crate::{include, namespace};
 
 /// A complete fk program: optional BEGIN, a set of rules, optional END, and functions.
 #[derive(Debug)]
@@ -9,7 +11,11 @@ pub struct Program {
     pub end: Option<Block>,
     pub beginfile: Option<Block>,
     pub endfile: Option<Block>,
+    /// `ERROR { ... }` — runs for records a reader flagged as malformed.
+    pub error: Option<Block>,
     pub functions: Vec<FuncDef>,
+    /// Names of the functions that came from `@include`d sources.
+    pub included: Vec<String>,
 }
 
 /// A user-defined function.
@@ -38,6 +44,25 @@ pub enum SortMode {
     NumDesc,
     ValAsc,
     ValDesc,
+    /// Insertion order, the same as no modifier.
+    Unsorted,
+}
+
+impl SortMode {
+    /// Look up a for-in modifier name (`rval`, `nsort`, ...) or its gawk
+    /// `PROCINFO["sorted_in"]` spelling (`@val_num_desc`, `@ind_str_asc`, ...).
+    pub fn from_name(name: &str) -> Option<SortMode> {
+        Some(match name.strip_prefix('@').unwrap_or(name) {
+            "sort" | "asc" | "ind_str_asc" => SortMode::Asc,
+            "rsort" | "desc" | "ind_str_desc" => SortMode::Desc,
+            "nsort" | "numasc" | "ind_num_asc" => SortMode::NumAsc,
+            "rnsort" | "numdesc" | "ind_num_desc" => SortMode::NumDesc,
+            "val" | "valasc" | "val_num_asc" => SortMode::ValAsc,
+            "rval" | "valdesc" | "val_num_desc" => SortMode::ValDesc,
+            "unsorted" | "insert_order" => SortMode::Unsorted,
+            _ => return None,
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -107,10 +132,11 @@ pub enum Expr {
     NullFence(Box<Expr>), // parenthesized group — collapses null back to ""
     Sprintf(Vec<Expr>),
     FuncCall(String, Vec<Expr>),
-    /// getline [var] [< file]. Fields: optional var name, optional source file expr.
-    Getline(Option<String>, Option<Box<Expr>>),
-    /// "cmd" | getline [var]. Fields: command expr, optional var name.
-    GetlinePipe(Box<Expr>, Option<String>),
+    /// getline [lvalue] [< file]. Fields: optional target (variable, element
+    /// or field), optional source file expr.
+    Getline(Option<Box<Expr>>, Option<Box<Expr>>),
+    /// "cmd" | getline [lvalue]. Fields: command expr, optional target.
+    GetlinePipe(Box<Expr>, Option<Box<Expr>>),
 }
 
 #[derive(Debug, Clone)]
@@ -164,8 +190,12 @@ impl Parser {
         let mut end = None;
         let mut beginfile = None;
         let mut endfile = None;
+        let mut error = None;
         let mut functions = Vec::new();
+        let mut included = Vec::new();
 
+        let (tokens, sites) = include::expand(std::mem::take(&mut self.tokens))?;
+        self.tokens = namespace::resolve(tokens)?;
         self.skip_terminators();
 
         while !self.at_eof() {
@@ -194,8 +224,21 @@ impl Parser {
                     let block = self.parse_brace_block()?;
                     endfile.get_or_insert_with(Vec::new).extend(block);
                 }
+                // `ERROR {` is a special block; a bare `ERROR` stays a variable.
+                Token::Ident(s)
+                    if s == "ERROR"
+                        && matches!(self.tokens.get(self.pos + 1), Some(t) if t.token == Token::LBrace) =>
+                {
+                    self.advance();
+                    let block = self.parse_brace_block()?;
+                    error.get_or_insert_with(Vec::new).extend(block);
+                }
                 Token::Function => {
+                    let from_include = sites.contains(&self.current_span());
                     let func = self.parse_func_def()?;
+                    if from_include {
+                        included.push(func.name.clone());
+                    }
                     functions.push(func);
                 }
                 _ => {
@@ -212,13 +255,19 @@ impl Parser {
             end,
             beginfile,
             endfile,
+            error,
             functions,
+            included,
         })
     }
 
     fn parse_func_def(&mut self) -> Result<FuncDef, FkError> {
         self.advance(); // consume 'function'
         let name = match self.current().clone() {
+            Token::Ident(n) if is_builtin_func(namespace::local_name(&n)) => {
+                let msg = format!("function `{n}` would shadow a builtin; pick another name");
+                return Err(FkError::new(self.current_span(), msg));
+            }
             Token::Ident(n) => {
                 self.advance();
                 n
@@ -559,19 +608,14 @@ impl Parser {
         self.advance(); // consume @
         match self.current() {
             Token::Ident(s) => {
-                let mode = match s.as_str() {
-                    "sort" | "asc" => SortMode::Asc,
-                    "rsort" | "desc" => SortMode::Desc,
-                    "nsort" | "numasc" => SortMode::NumAsc,
-                    "rnsort" | "numdesc" => SortMode::NumDesc,
-                    "val" | "valasc" => SortMode::ValAsc,
-                    "rval" | "valdesc" => SortMode::ValDesc,
-                    other => {
+                let mode = match SortMode::from_name(s) {
+                    Some(mode) => mode,
+                    None => {
                         return Err(FkError::new(
                             self.current_span(),
                             format!(
-                                "unknown sort modifier '@{}'. Use @sort, @rsort, @nsort, @rnsort, @val, or @rval",
-                                other
+                                "unknown sort modifier '@{}'. Use @sort, @rsort, @nsort, @rnsort, @val, @rval or @unsorted",
+                                s
                             ),
                         ));
                     }
@@ -653,9 +697,7 @@ impl Parser {
         if let Token::Ident(name) = self.current().clone() {
             self.advance();
             if self.check(&Token::LBracket) {
-                self.advance();
-                let key = self.parse_expr()?;
-                self.expect(&Token::RBracket)?;
+                let key = self.parse_subscript_key()?;
                 Ok(Statement::Delete(name, key))
             } else {
                 // delete entire array
@@ -784,6 +826,7 @@ impl Parser {
 
         while self.check(&Token::Or) {
             self.advance();
+            self.skip_newlines();
             let right = self.parse_logical_and()?;
             left = Expr::LogicalOr(Box::new(left), Box::new(right));
         }
@@ -796,6 +839,7 @@ impl Parser {
 
         while self.check(&Token::And) {
             self.advance();
+            self.skip_newlines();
             let right = self.parse_in_expr()?;
             left = Expr::LogicalAnd(Box::new(left), Box::new(right));
         }
@@ -833,12 +877,7 @@ impl Parser {
             self.advance();
             if self.check(&Token::Getline) {
                 self.advance();
-                let var = if let Token::Ident(name) = self.current().clone() {
-                    self.advance();
-                    Some(name)
-                } else {
-                    None
-                };
+                let var = self.parse_getline_target(true)?;
                 return Ok(Expr::GetlinePipe(Box::new(left), var));
             }
             // Not a getline pipe, backtrack
@@ -1011,23 +1050,8 @@ impl Parser {
 
         loop {
             if self.check(&Token::LBracket) {
-                // Array subscript — supports multi-dimensional a[i,j] → a[i SUBSEP j]
                 if let Expr::Var(name) = expr {
-                    self.advance();
-                    let mut key = self.parse_expr()?;
-                    while self.check(&Token::Comma) {
-                        self.advance();
-                        let next = self.parse_expr()?;
-                        key = Expr::Concat(
-                            Box::new(Expr::Concat(
-                                Box::new(key),
-                                Box::new(Expr::Var("SUBSEP".to_string())),
-                            )),
-                            Box::new(next),
-                        );
-                    }
-                    self.expect(&Token::RBracket)?;
-                    expr = Expr::ArrayRef(name, Box::new(key));
+                    expr = self.parse_subscript(name)?;
                 } else {
                     break;
                 }
@@ -1048,6 +1072,82 @@ impl Parser {
         Ok(expr)
     }
 
+    /// Array subscript after `name` — supports multi-dimensional
+    /// a[i,j] → a[i SUBSEP j].
+    fn parse_subscript(&mut self, name: String) -> Result<Expr, FkError> {
+        let key = self.parse_subscript_key()?;
+        Ok(Expr::ArrayRef(name, Box::new(key)))
+    }
+
+    /// The key in `[...]`, from the `[`. Element reads, assignments and
+    /// `delete` all come here, so `i, j` joins to the same `i SUBSEP j`.
+    /// There are no arrays of arrays, and `a[i][j]` says so.
+    fn parse_subscript_key(&mut self) -> Result<Expr, FkError> {
+        self.advance();
+        let mut parts = vec![self.parse_expr()?];
+        while self.check(&Token::Comma) {
+            self.advance();
+            parts.push(self.parse_expr()?);
+        }
+        self.expect(&Token::RBracket)?;
+        if self.check(&Token::LBracket) {
+            return Err(FkError::new(
+                self.current_span(),
+                "arrays of arrays are not supported; write a[i, j] for a two-part key",
+            ));
+        }
+        Ok(Self::join_subsep(parts))
+    }
+
+    /// The index after `$`. `$` binds tighter than postfix `++`/`--`, so
+    /// `$(i)++` and `$a[1]++` increment the field; a prefix operator
+    /// (`$-1`, `$++i`) applies to the index.
+    fn parse_field_index(&mut self) -> Result<Expr, FkError> {
+        if self.check(&Token::Minus)
+            || self.check(&Token::Not)
+            || self.check(&Token::Increment)
+            || self.check(&Token::Decrement)
+        {
+            return self.parse_unary();
+        }
+        match self.parse_primary()? {
+            Expr::Var(name) if self.check(&Token::LBracket) => self.parse_subscript(name),
+            expr => Ok(expr),
+        }
+    }
+
+    /// The target after `getline`: a variable, an array element or any
+    /// field form (`$2`, `$"name"`, `$(expr)`). Without `after_pipe`, a bare
+    /// name only counts when the getline ends there or `<` follows.
+    fn parse_getline_target(&mut self, after_pipe: bool) -> Result<Option<Box<Expr>>, FkError> {
+        match self.current().clone() {
+            Token::Field(_) | Token::FieldVar(_) | Token::Dollar => {
+                Ok(Some(Box::new(self.parse_primary()?)))
+            }
+            Token::Ident(name) => {
+                let saved = self.pos;
+                self.advance();
+                if self.check(&Token::LBracket) {
+                    return Ok(Some(Box::new(self.parse_subscript(name)?)));
+                }
+                if after_pipe
+                    || self.check(&Token::Lt)
+                    || self.is_terminator()
+                    || self.check(&Token::RBrace)
+                    || self.check(&Token::Semicolon)
+                    || self.check(&Token::RParen)
+                    || self.at_eof()
+                {
+                    Ok(Some(Box::new(Expr::Var(name))))
+                } else {
+                    self.pos = saved;
+                    Ok(None)
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
     fn parse_primary(&mut self) -> Result<Expr, FkError> {
         match self.current().clone() {
             Token::Number(n) => {
@@ -1064,11 +1164,16 @@ impl Parser {
             }
             Token::FieldVar(name) => {
                 self.advance();
-                Ok(Expr::Field(Box::new(Expr::Var(name))))
+                let index = if self.check(&Token::LBracket) {
+                    self.parse_subscript(name)?
+                } else {
+                    Expr::Var(name)
+                };
+                Ok(Expr::Field(Box::new(index)))
             }
             Token::Dollar => {
                 self.advance();
-                let expr = self.parse_unary()?;
+                let expr = self.parse_field_index()?;
                 Ok(Expr::Field(Box::new(expr)))
             }
             Token::Regex(pat) => {
@@ -1101,25 +1206,8 @@ impl Parser {
             }
             Token::Getline => {
                 self.advance();
-                // getline [var] [< file]
-                let var = if let Token::Ident(name) = self.current().clone() {
-                    let saved = self.pos;
-                    self.advance();
-                    if self.check(&Token::Lt)
-                        || self.is_terminator()
-                        || self.check(&Token::RBrace)
-                        || self.check(&Token::Semicolon)
-                        || self.check(&Token::RParen)
-                        || self.at_eof()
-                    {
-                        Some(name)
-                    } else {
-                        self.pos = saved;
-                        None
-                    }
-                } else {
-                    None
-                };
+                // getline [lvalue] [< file]
+                let var = self.parse_getline_target(false)?;
                 let source = if self.check(&Token::Lt) {
                     self.advance();
                     Some(Box::new(self.parse_primary()?))
@@ -1240,7 +1328,8 @@ impl Parser {
 
     /// Look ahead to determine if `?` at current position is a ternary `? expr : expr`.
     /// Ternary requires a `:` at the same paren depth after the `?`.
-    /// If next token is `(`, find matching `)` and check for `:`.
+    /// If next token is `(`, look for that `:` past the matching `)`, before
+    /// the expression ends (`c ? (k, 1) in a ? x : y : z`).
     /// If next token can't start an expression, it's definitely postfix.
     fn is_ternary_question(&self) -> bool {
         let mut i = self.pos + 1; // token after ?
@@ -1265,8 +1354,26 @@ impl Parser {
                 }
                 i += 1;
             }
-            // After matching `)`, check if `:` follows → ternary
-            return self.tokens.get(i).map(|s| &s.token) == Some(&Token::Colon);
+            // A `:` after the matching `)` at this depth → ternary
+            let mut depth = 0usize;
+            while let Some(s) = self.tokens.get(i) {
+                match &s.token {
+                    Token::Colon if depth == 0 => return true,
+                    Token::LParen | Token::LBracket => depth += 1,
+                    Token::RParen | Token::RBracket if depth > 0 => depth -= 1,
+                    Token::RParen
+                    | Token::RBracket
+                    | Token::Comma
+                    | Token::LBrace
+                    | Token::RBrace
+                    | Token::Semicolon
+                    | Token::Newline
+                    | Token::Eof => return false,
+                    _ => {}
+                }
+                i += 1;
+            }
+            return false;
         }
         // Default: assume ternary for safety
         true
@@ -0,0 +1,169 @@
+//! `--progress`: a self-updating status line on stderr for long runs.
+//!
+//! The record loop calls `tick()` per record; it only looks at the clock
+//! every `stride` records, and the stride adapts so that happens a few
+//! times per redraw. Nothing is drawn unless stderr is a terminal. Other
+//! stderr output calls `clear()` first so it does not land mid-line.
+
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::timing::short_bytes;
+
+/// Time between redraws.
+const INTERVAL: Duration = Duration::from_millis(500);
+
+/// Largest number of records between clock checks.
+const MAX_STRIDE: u64 = 1 << 16;
+
+/// Whether a status line is on screen (and must be erased before other
+/// stderr output).
+static SHOWN: AtomicBool = AtomicBool::new(false);
+
+pub struct Progress {
+    start: Instant,
+    next_draw: Instant,
+    last_check: Instant,
+    /// Records between clock checks, and the count at the next check.
+    stride: u64,
+    next_check: u64,
+    /// Input size when every source is a regular, uncompressed file.
+    total: Option<u64>,
+}
+
+impl Progress {
+    /// A progress line for input of `total` bytes (`None`: unknown), or
+    /// `None` when stderr is not a terminal.
+    pub fn start(total: Option<u64>) -> Option<Self> {
+        if !std::io::stderr().is_terminal() {
+            return None;
+        }
+        let now = Instant::now();
+        Some(Progress {
+            start: now,
+            next_draw: now + INTERVAL,
+            last_check: now,
+            stride: 1,
+            next_check: 1,
+            total,
+        })
+    }
+
+    /// Per-record hook: `records` read so far. Returns true when a redraw
+    /// is due; the caller then gathers the details for `draw()`.
+    #[inline]
+    pub fn tick(&mut self, records: u64) -> bool {
+        if records < self.next_check {
+            return false;
+        }
+        let now = Instant::now();
+        // Aim for about four clock checks per redraw.
+        if now - self.last_check < INTERVAL / 8 {
+            self.stride = (self.stride * 2).min(MAX_STRIDE);
+        } else if now - self.last_check > INTERVAL / 2 {
+            self.stride = (self.stride / 2).max(1);
+        }
+        self.last_check = now;
+        self.next_check = records + self.stride;
+        if now < self.next_draw {
+            return false;
+        }
+        self.next_draw = now + INTERVAL;
+        true
+    }
+
+    /// Redraw the status line.
+    pub fn draw(&self, records: u64, bytes: u64, filename: &str) {
+        let line = status_line(records, bytes, self.total, self.start.elapsed(), filename);
+        let mut err = std::io::stderr().lock();
+        let _ = write!(err, "\r\x1b[K{}", line);
+        let _ = err.flush();
+        SHOWN.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Erase the status line, if one is showing.
+pub fn clear() {
+    if SHOWN.swap(false, Ordering::Relaxed) {
+        let mut err = std::io::stderr().lock();
+        let _ = write!(err, "\r\x1b[K");
+        let _ = err.flush();
+    }
+}
+
+/// The status text: records, bytes read, then percentage and ETA when
+/// the total is known or throughput when it is not, then the file name.
+pub fn status_line(
+    records: u64,
+    bytes: u64,
+    total: Option<u64>,
+    elapsed: Duration,
+    filename: &str,
+) -> String {
+    let secs = elapsed.as_secs_f64();
+    let mut line = format!("fk: {} records  {}", records, short_bytes(bytes));
+    match total {
+        Some(total) if total > 0 => {
+            let done = (bytes as f64 / total as f64).min(1.0);
+            line.push_str(&format!(" / {}  {:.1}%", short_bytes(total), done * 100.0));
+            if done > 0.0 && secs > 0.0 {
+                let left = secs * (1.0 - done) / done;
+                line.push_str(&format!("  ETA {}", clock(left)));
+            }
+        }
+        _ if secs > 0.0 => {
+            let rate = (bytes as f64 / secs) as u64;
+            line.push_str(&format!("  {}/s", short_bytes(rate)));
+        }
+        _ => {}
+    }
+    if !filename.is_empty() {
+        line.push_str("  ");
+        line.push_str(filename);
+    }
+    line
+}
+
+/// Seconds as `42s`, `3m05s` or `2h03m`.
+fn clock(secs: f64) -> String {
+    let s = secs.round() as u64;
+    if s < 60 {
+        format!("{s}s")
+    } else if s < 3600 {
+        format!("{}m{:02}s", s / 60, s % 60)
+    } else {
+        format!("{}h{:02}m", s / 3600, s % 3600 / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_line_with_known_total_shows_percent_and_eta() {
+        let line = status_line(
+            1500,
+            256 << 20,
+            Some(1 << 30),
+            Duration::from_secs(10),
+            "big.csv",
+        );
+        assert_eq!(
+            line,
+            "fk: 1500 records  256.0 MiB / 1.0 GiB  25.0%  ETA 30s  big.csv"
+        );
+        let line = status_line(9, 10, Some(1000), Duration::from_secs(1), "");
+        assert_eq!(line, "fk: 9 records  10 B / 1000 B  1.0%  ETA 1m39s");
+    }
+
+    #[test]
+    fn status_line_without_total_shows_throughput() {
+        let line = status_line(42, 3 << 20, None, Duration::from_secs(2), "");
+        assert_eq!(line, "fk: 42 records  3.0 MiB  1.5 MiB/s");
+        let line = status_line(0, 0, None, Duration::ZERO, "x");
+        assert_eq!(line, "fk: 0 records  0 B  x");
+        assert_eq!(clock(7322.0), "2h02m");
+    }
+}
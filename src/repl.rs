@@ -3,6 +3,7 @@ use std::io::{self, BufRead, Write};
 use crate::action::Executor;
 use crate::input::Record;
 use crate::lexer::Lexer;
+use crate::optimize;
 use crate::parser::Parser;
 use crate::runtime::Runtime;
 
@@ -14,7 +15,7 @@ use crate::runtime::Runtime;
 /// Commands:
 ///   :q / :quit   — exit
 ///   :reset       — clear all variables and arrays
-///   :vars        — show all variables
+///   :vars        — show the program's variables and arrays
 pub fn run(rt: &mut Runtime) {
     let stdin = io::stdin();
     let mut reader = stdin.lock();
@@ -86,7 +87,8 @@ fn try_run_program(source: &str, rt: &mut Runtime) -> Result<String, crate::erro
     let mut lex = Lexer::new(source);
     let tokens = lex.tokenize()?;
     let mut par = Parser::new(tokens);
-    let program = par.parse()?;
+    let mut program = par.parse()?;
+    optimize::fold_constants(&mut program);
 
     let mut exec = Executor::new(&program, rt);
     exec.run_begin();
@@ -95,6 +97,7 @@ fn try_run_program(source: &str, rt: &mut Runtime) -> Result<String, crate::erro
         let rec = Record {
             text: String::new(),
             fields: None,
+            malformed: None,
         };
         exec.run_record(&rec);
     }
@@ -123,13 +126,19 @@ fn brace_depth(s: &str) -> i32 {
     depth
 }
 
+/// The program's own scalars and arrays, as `vars()` and `arrays()` see
+/// them (fk's variables left out).
 fn print_vars(rt: &Runtime) {
-    let names = rt.all_var_names();
-    if names.is_empty() {
+    let names = rt.scalar_names(false);
+    let arrays = rt.sorted_array_names(false);
+    if names.is_empty() && arrays.is_empty() {
         println!("(no variables)");
         return;
     }
     for name in &names {
         println!("  {} = \"{}\"", name, rt.get_var(name));
     }
+    for name in &arrays {
+        println!("  {} = array ({} elements)", name, rt.array_len(name));
+    }
 }
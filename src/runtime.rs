@@ -1,8 +1,11 @@
 use rustc_hash::FxHashMap as HashMap;
-use std::collections::hash_map;
+use std::borrow::Cow;
 
-use crate::builtins;
+use crate::builtins::{self, NumSeps};
 use crate::field;
+use crate::input::csv::Dialect;
+use crate::ordmap::OrderedMap;
+use crate::spill::SpillStore;
 
 // --- Array metadata: typed annotations attached to arrays ---
 
@@ -23,6 +26,10 @@ pub enum ArrayMeta {
 
 const STR_VALID: u8 = 1;
 const NUM_VALID: u8 = 2;
+/// Text that came from input (fields, getline, split, `-v`): POSIX's
+/// strnum, which counts as a number in a boolean context when it looks
+/// like one.
+const STRNUM: u8 = 4;
 
 #[derive(Clone, Debug)]
 #[must_use]
@@ -59,6 +66,15 @@ impl Value {
         }
     }
 
+    /// Input text, a strnum: see [`Value::is_truthy`].
+    pub fn from_strnum(s: String) -> Self {
+        Value {
+            s,
+            n: 0.0,
+            flags: STR_VALID | STRNUM,
+        }
+    }
+
     pub fn from_number(n: f64) -> Self {
         Value {
             s: String::new(),
@@ -108,33 +124,76 @@ impl Value {
 
     /// Write string representation directly to a writer.
     pub fn write_to(&self, w: &mut impl std::io::Write) {
-        if self.flags == 0 {
-            return;
-        } else if self.flags & STR_VALID != 0 {
+        if self.flags & STR_VALID != 0 {
             let _ = w.write_all(self.s.as_bytes());
-        } else {
-            let s = builtins::format_number(self.n);
-            let _ = w.write_all(s.as_bytes());
+        } else if self.flags != 0 {
+            let mut buf = builtins::numfmt::NumBuf::new();
+            match builtins::numfmt::general(self.n, &mut buf) {
+                Some(s) => {
+                    let _ = w.write_all(s.as_bytes());
+                }
+                None => {
+                    let _ = w.write_all(builtins::format_number(self.n).as_bytes());
+                }
+            }
         }
     }
 
     /// Append string representation to an existing String.
     pub fn write_to_string(&self, buf: &mut String) {
-        if self.flags == 0 {
-            return;
-        } else if self.flags & STR_VALID != 0 {
+        if self.flags & STR_VALID != 0 {
             buf.push_str(&self.s);
+        } else if self.flags != 0 {
+            builtins::write_number(self.n, buf);
+        }
+    }
+
+    /// Length of the string form; for a number not yet formatted, a
+    /// typical upper bound. Used to size concatenation buffers.
+    pub fn str_len_hint(&self) -> usize {
+        if self.flags & STR_VALID != 0 {
+            self.s.len()
         } else {
-            buf.push_str(&builtins::format_number(self.n));
+            24
+        }
+    }
+
+    /// The string form for editing in place; the value becomes a plain
+    /// string. `None` when there is no string form yet (numbers, null).
+    pub fn string_mut(&mut self) -> Option<&mut String> {
+        if self.flags & STR_VALID == 0 {
+            return None;
         }
+        self.flags = STR_VALID;
+        Some(&mut self.s)
     }
 
+    /// Truth in a boolean context, as POSIX has it: a number is true when
+    /// nonzero, a strnum that looks numeric likewise, and any other string
+    /// (a constant like `"0"` included) when it is not empty.
     pub fn is_truthy(&self) -> bool {
         if self.flags & NUM_VALID != 0 {
             self.n != 0.0
+        } else if let Some(n) = self.strnum_number() {
+            n != 0.0
         } else {
-            !self.s.is_empty() && self.s != "0"
+            !self.s.is_empty()
+        }
+    }
+
+    /// Whether this is a number or input text that looks like one;
+    /// printf's `%c` prints either as the character with that code point.
+    pub fn counts_as_number(&self) -> bool {
+        self.flags & NUM_VALID != 0 || self.strnum_number().is_some()
+    }
+
+    /// The number a strnum spells, if it looks numeric.
+    fn strnum_number(&self) -> Option<f64> {
+        let s = self.s.trim();
+        if self.flags & STRNUM == 0 || !s.bytes().any(|b| b.is_ascii_digit()) {
+            return None;
         }
+        s.parse().ok()
     }
 
     pub fn is_numeric(&self) -> bool {
@@ -159,6 +218,24 @@ impl Value {
         self.flags == 0
     }
 
+    /// Append a compact binary form (flags, number, string bytes) to `out`.
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.flags);
+        out.extend_from_slice(&self.n.to_le_bytes());
+        out.extend_from_slice(self.s.as_bytes());
+    }
+
+    /// Read back what [`Value::encode`] wrote.
+    pub(crate) fn decode(bytes: &[u8]) -> Option<Value> {
+        let (&flags, rest) = bytes.split_first()?;
+        let (n, s) = rest.split_first_chunk::<8>()?;
+        Some(Value {
+            s: String::from_utf8(s.to_vec()).ok()?,
+            n: f64::from_le_bytes(*n),
+            flags,
+        })
+    }
+
     /// Check if this value looks numeric (for comparison semantics).
     pub fn looks_numeric(&self) -> bool {
         if self.flags & NUM_VALID != 0 {
@@ -173,17 +250,53 @@ impl Value {
     }
 }
 
+/// Rough memory an array element takes, for `ARRAY_SPILL_BYTES`: its
+/// key, its value's string and the entry itself.
+fn element_bytes(key: &str, val: &Value) -> usize {
+    key.len() + val.s.capacity() + std::mem::size_of::<(String, Value)>()
+}
+
+/// Joins a parameter name and call depth into a local array's storage
+/// name, which no awk identifier can spell.
+const LOCAL_ARRAY_SEP: char = '@';
+
 #[derive(Debug)]
 pub struct Runtime {
     variables: HashMap<String, Value>,
-    arrays: HashMap<String, HashMap<String, Value>>,
+    /// Elements keep their insertion order, which a plain for-in follows.
+    arrays: HashMap<String, OrderedMap<Value>>,
+    /// Arrays whose values moved to disk (see `spill`); never also in `arrays`.
+    spilled: HashMap<String, SpillStore>,
+    /// `ARRAY_SPILL_THRESHOLD`: element count past which an array spills (0: never).
+    spill_threshold: usize,
+    /// `ARRAY_SPILL_BYTES`: estimated size past which an array spills (0: never).
+    spill_bytes: usize,
+    /// Each in-memory array's estimated size (see `element_bytes`), kept
+    /// only while `spill_bytes` is set and filled in on an array's first
+    /// store after that.
+    array_bytes: HashMap<String, usize>,
+    /// Problems met while storing arrays, for the executor to report
+    /// (see `take_warnings`).
+    warnings: Vec<String>,
     array_meta: HashMap<String, ArrayMeta>,
+    /// Array names bound to another array's storage for a function call
+    /// (see `bind_array`); every array operation looks through them.
+    array_refs: HashMap<String, String>,
     pub(crate) fields: Vec<String>,
     field_offsets: Vec<(usize, usize)>,
+    /// Where the unsplit rest of a bounded split starts (see
+    /// `set_record_capped`; 0 after a `$0` assignment); the rest is split
+    /// only if something asks.
+    field_rest: Option<usize>,
     fields_lazy: bool,
     record_text: String,
     record_text_valid: bool,
     fields_dirty: bool,
+    /// `--preserve-cr`: a record's trailing `\r` stays in `$0`.
+    preserve_cr: bool,
+    /// The record ends in a kept `\r`: fields are split without it and a
+    /// `$0` rebuilt from fields gets it back.
+    record_cr: bool,
     nr: u64,
     nf: usize,
     fnr: u64,
@@ -195,6 +308,44 @@ pub struct Runtime {
     ofmt: String,
     convfmt: String,
     filename: String,
+    /// `IGNORECASE` is truthy: regex matching ignores case.
+    ignore_case: bool,
+    /// `GROUPSEP` / `DECIMALSEP`: printf's `'` grouping and decimal point.
+    group_sep: String,
+    decimal_sep: String,
+    /// `CSVSEP`, `CSVQUOTE` or `CSVCOMMENT` changed since the reader last
+    /// looked (see `take_csv_changed`).
+    csv_changed: bool,
+}
+
+/// `pgrpid` and the real and effective uid/gid, read from /proc.
+#[cfg(target_os = "linux")]
+fn process_ids() -> Vec<(&'static str, String)> {
+    let mut ids = Vec::new();
+    // Fields after the parenthesised command name: state ppid pgrp ...
+    if let Ok(stat) = std::fs::read_to_string("/proc/self/stat")
+        && let Some((_, rest)) = stat.rsplit_once(')')
+        && let Some(pgrp) = rest.split_whitespace().nth(2)
+    {
+        ids.push(("pgrpid", pgrp.to_string()));
+    }
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    for line in status.lines() {
+        let (keys, rest) = match line.split_once(':') {
+            Some(("Uid", rest)) => (["uid", "euid"], rest),
+            Some(("Gid", rest)) => (["gid", "egid"], rest),
+            _ => continue,
+        };
+        for (key, id) in keys.into_iter().zip(rest.split_whitespace()) {
+            ids.push((key, id.to_string()));
+        }
+    }
+    ids
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_ids() -> Vec<(&'static str, String)> {
+    Vec::new()
 }
 
 /// Names that are stored as dedicated fields rather than in the HashMap.
@@ -202,6 +353,50 @@ const INTERNED_NAMES: &[&str] = &[
     "CONVFMT", "FILENAME", "FNR", "FS", "NF", "NR", "OFS", "OFMT", "ORS", "RS", "SUBSEP",
 ];
 
+/// Settings kept with the user's variables whose assignment also changes
+/// how fk behaves (see `set_value`).
+const SETTING_NAMES: &[&str] = &[
+    "ARRAY_SPILL_BYTES",
+    "ARRAY_SPILL_THRESHOLD",
+    "CSVCOMMENT",
+    "CSVQUOTE",
+    "CSVSEP",
+    "DECIMALSEP",
+    "GROUPSEP",
+    "IGNORECASE",
+    "WIDTHMODE",
+];
+
+/// The other variables and arrays the executor fills in for the program.
+const PROVIDED_NAMES: &[&str] = &[
+    "ARGC",
+    "ARGV",
+    "BYTEPOS",
+    "DUMPSEP",
+    "ENVIRON",
+    "ERRMSG",
+    "ERRNO",
+    "FKARGS",
+    "GETLINE_TIMEOUT",
+    "HDR",
+    "PROCINFO",
+    "RECBYTES",
+    "RLENGTH",
+    "RSTART",
+    "SAMPLED",
+    "SKIPPED",
+    "STATE_ARRAYS",
+    "WARNINGS",
+];
+
+/// Whether `name` is one of fk's own variables, scalar or array: what
+/// `vars()` and `arrays()` leave out unless asked.
+pub fn is_builtin_name(name: &str) -> bool {
+    [INTERNED_NAMES, SETTING_NAMES, PROVIDED_NAMES]
+        .iter()
+        .any(|names| names.contains(&name))
+}
+
 impl Default for Runtime {
     fn default() -> Self {
         Self::new()
@@ -210,16 +405,25 @@ impl Default for Runtime {
 
 impl Runtime {
     pub fn new() -> Self {
-        Runtime {
+        let mut rt = Runtime {
             variables: HashMap::default(),
             arrays: HashMap::default(),
+            spilled: HashMap::default(),
+            spill_threshold: 0,
+            spill_bytes: 0,
+            array_bytes: HashMap::default(),
+            warnings: Vec::new(),
             array_meta: HashMap::default(),
+            array_refs: HashMap::default(),
             fields: Vec::new(),
             field_offsets: Vec::new(),
+            field_rest: None,
             fields_lazy: false,
             record_text: String::new(),
             record_text_valid: false,
             fields_dirty: false,
+            preserve_cr: false,
+            record_cr: false,
             nr: 0,
             nf: 0,
             fnr: 0,
@@ -231,7 +435,33 @@ impl Runtime {
             ofmt: "%.6g".to_string(),
             convfmt: "%.6g".to_string(),
             filename: String::new(),
+            ignore_case: false,
+            group_sep: String::new(),
+            decimal_sep: String::new(),
+            csv_changed: false,
+        };
+        rt.set_var("GROUPSEP", ",");
+        rt.set_var("DECIMALSEP", ".");
+        rt.set_value("WARNINGS", Value::from_number(0.0));
+        rt.populate_procinfo();
+        rt
+    }
+
+    /// gawk-style `PROCINFO`: process ids, the fk version and the default
+    /// `strftime` format. Ids the platform does not expose are left out.
+    fn populate_procinfo(&mut self) {
+        self.set_array("PROCINFO", "pid", &std::process::id().to_string());
+        #[cfg(unix)]
+        {
+            let ppid = std::os::unix::process::parent_id();
+            self.set_array("PROCINFO", "ppid", &ppid.to_string());
+        }
+        for (key, id) in process_ids() {
+            self.set_array("PROCINFO", key, &id);
         }
+        self.set_array("PROCINFO", "version", crate::build_info::VERSION);
+        self.set_array("PROCINFO", "strftime", builtins::time::DEFAULT_FORMAT);
+        self.set_array("PROCINFO", "open_pipes", "0");
     }
 
     /// Get a variable's numeric value directly (avoids Value clone).
@@ -239,7 +469,7 @@ impl Runtime {
     pub fn get_number(&self, name: &str) -> f64 {
         match name {
             "NR" => self.nr as f64,
-            "NF" => self.nf as f64,
+            "NF" => self.nf() as f64,
             "FNR" => self.fnr as f64,
             _ => {
                 if let Some(v) = self.variables.get(name) {
@@ -256,9 +486,18 @@ impl Runtime {
     pub fn set_number(&mut self, name: &str, n: f64) {
         match name {
             "NR" => self.nr = n as u64,
-            "NF" => self.nf = n as usize,
+            "NF" => self.set_nf(n as usize),
             "FNR" => self.fnr = n as u64,
             _ => {
+                match name {
+                    "IGNORECASE" => self.ignore_case = n != 0.0,
+                    "ARRAY_SPILL_THRESHOLD" => self.spill_threshold = n as usize,
+                    "ARRAY_SPILL_BYTES" => self.set_spill_bytes(&builtins::format_number(n)),
+                    "GROUPSEP" => self.group_sep = builtins::format_number(n),
+                    "DECIMALSEP" => self.decimal_sep = builtins::format_number(n),
+                    "CSVSEP" | "CSVQUOTE" | "CSVCOMMENT" => self.csv_changed = true,
+                    _ => {}
+                }
                 if let Some(existing) = self.variables.get_mut(name) {
                     existing.set_number_fast(n);
                 } else {
@@ -273,7 +512,7 @@ impl Runtime {
     pub fn get_value(&self, name: &str) -> Value {
         match name {
             "NR" => Value::from_number(self.nr as f64),
-            "NF" => Value::from_number(self.nf as f64),
+            "NF" => Value::from_number(self.nf() as f64),
             "FNR" => Value::from_number(self.fnr as f64),
             "FS" => Value::from_str_ref(&self.fs),
             "OFS" => Value::from_str_ref(&self.ofs),
@@ -291,9 +530,13 @@ impl Runtime {
     pub fn set_value(&mut self, name: &str, val: Value) {
         match name {
             "NR" => self.nr = val.to_number() as u64,
-            "NF" => self.nf = val.to_number() as usize,
+            "NF" => self.set_nf(val.to_number() as usize),
             "FNR" => self.fnr = val.to_number() as u64,
-            "FS" => self.fs = val.into_string(),
+            "FS" => {
+                // A new FS applies from the next record; split this one first.
+                self.finish_split();
+                self.fs = val.into_string();
+            }
             "OFS" => self.ofs = val.into_string(),
             "RS" => self.rs = val.into_string(),
             "ORS" => self.ors = val.into_string(),
@@ -302,6 +545,18 @@ impl Runtime {
             "CONVFMT" => self.convfmt = val.into_string(),
             "FILENAME" => self.filename = val.into_string(),
             _ => {
+                match name {
+                    "IGNORECASE" => self.ignore_case = val.is_truthy(),
+                    "ARRAY_SPILL_THRESHOLD" => self.spill_threshold = val.to_number() as usize,
+                    "ARRAY_SPILL_BYTES" => self.set_spill_bytes(&val.to_string_val()),
+                    "GROUPSEP" => self.group_sep = val.to_string_val(),
+                    "DECIMALSEP" => self.decimal_sep = val.to_string_val(),
+                    "CSVSEP" | "CSVQUOTE" | "CSVCOMMENT" => self.csv_changed = true,
+                    "WIDTHMODE" if !builtins::width::set_mode(&val.to_string_val()) => {
+                        eprintln!("fk: WIDTHMODE must be \"display\" or \"chars\"");
+                    }
+                    _ => {}
+                }
                 if let Some(existing) = self.variables.get_mut(name) {
                     *existing = val;
                 } else {
@@ -311,6 +566,15 @@ impl Runtime {
         }
     }
 
+    /// A user variable's string, for appending in place (`v = v ...`).
+    /// `None` for special variables, unset ones and non-string values.
+    pub fn string_var_mut(&mut self, name: &str) -> Option<&mut String> {
+        if INTERNED_NAMES.contains(&name) || SETTING_NAMES.contains(&name) {
+            return None;
+        }
+        self.variables.get_mut(name)?.string_mut()
+    }
+
     /// Convenience: get variable as String (for backward-compatible callers).
     pub fn get_var(&self, name: &str) -> String {
         self.get_value(name).into_string()
@@ -321,6 +585,24 @@ impl Runtime {
         self.set_value(name, Value::from_str_ref(value));
     }
 
+    /// Set a variable to input text (getline, `-v`), keeping it a strnum.
+    pub fn set_strnum(&mut self, name: &str, value: &str) {
+        self.set_value(name, Value::from_strnum(value.to_string()));
+    }
+
+    /// A variable's value if it holds a number rather than text; an
+    /// unset variable counts as 0.
+    pub fn var_number(&self, name: &str) -> Option<f64> {
+        match name {
+            "NR" | "NF" | "FNR" => Some(self.get_number(name)),
+            _ => match self.variables.get(name) {
+                Some(v) if v.is_numeric() => Some(v.to_number()),
+                Some(_) => None,
+                None => Some(0.0),
+            },
+        }
+    }
+
     /// Check whether a variable exists (interned vars always exist).
     pub fn has_var(&self, name: &str) -> bool {
         INTERNED_NAMES.contains(&name) || self.variables.contains_key(name)
@@ -330,9 +612,12 @@ impl Runtime {
     pub fn remove_var(&mut self, name: &str) {
         match name {
             "NR" => self.nr = 0,
-            "NF" => self.nf = 0,
+            "NF" => self.set_nf(0),
             "FNR" => self.fnr = 0,
-            "FS" => self.fs = " ".to_string(),
+            "FS" => {
+                self.finish_split();
+                self.fs = " ".to_string();
+            }
             "OFS" => self.ofs = " ".to_string(),
             "RS" => self.rs = "\n".to_string(),
             "ORS" => self.ors = "\n".to_string(),
@@ -341,6 +626,18 @@ impl Runtime {
             "CONVFMT" => self.convfmt = "%.6g".to_string(),
             "FILENAME" => self.filename = String::new(),
             _ => {
+                match name {
+                    "IGNORECASE" => self.ignore_case = false,
+                    "ARRAY_SPILL_THRESHOLD" => self.spill_threshold = 0,
+                    "ARRAY_SPILL_BYTES" => self.set_spill_bytes("0"),
+                    "GROUPSEP" => self.group_sep = ",".to_string(),
+                    "DECIMALSEP" => self.decimal_sep = ".".to_string(),
+                    "CSVSEP" | "CSVQUOTE" | "CSVCOMMENT" => self.csv_changed = true,
+                    "WIDTHMODE" => {
+                        builtins::width::set_mode("");
+                    }
+                    _ => {}
+                }
                 self.variables.remove(name);
             }
         }
@@ -356,6 +653,65 @@ impl Runtime {
         names
     }
 
+    /// Names of the scalars that are set, sorted; fk's own (`NR`, `FS`,
+    /// `WARNINGS`...) only with `builtins`. A function parameter bound to
+    /// an array is not a scalar.
+    pub fn scalar_names(&self, builtins: bool) -> Vec<String> {
+        let mut names = self.all_var_names();
+        names.retain(|n| (builtins || !is_builtin_name(n)) && !self.is_bound_array(n));
+        names
+    }
+
+    /// Whether regex matching ignores case (`IGNORECASE`, `-y`).
+    pub fn ignore_case(&self) -> bool {
+        self.ignore_case
+    }
+
+    /// The separators printf uses for `%'d` grouping and the decimal point.
+    pub fn num_seps(&self) -> NumSeps<'_> {
+        NumSeps {
+            group: &self.group_sep,
+            decimal: &self.decimal_sep,
+        }
+    }
+
+    /// Whether `CSVSEP`, `CSVQUOTE` or `CSVCOMMENT` changed since the last
+    /// call.
+    pub fn take_csv_changed(&mut self) -> bool {
+        std::mem::take(&mut self.csv_changed)
+    }
+
+    /// The CSV dialect `CSVSEP`, `CSVQUOTE` and `CSVCOMMENT` describe, by
+    /// their first character. An empty or unset `CSVSEP` keeps the input
+    /// mode's delimiter; an empty `CSVQUOTE` turns quoting off.
+    pub fn csv_dialect(&self) -> Dialect {
+        let first = |name: &str| self.get_var(name).chars().next();
+        Dialect {
+            delimiter: first("CSVSEP"),
+            quote: if self.has_var("CSVQUOTE") {
+                first("CSVQUOTE")
+            } else {
+                Some('"')
+            },
+            comment: first("CSVCOMMENT"),
+        }
+    }
+
+    /// `--preserve-cr`: keep a record's trailing `\r` in `$0`, splitting
+    /// fields as if it were not there.
+    pub fn set_preserve_cr(&mut self, on: bool) {
+        self.preserve_cr = on;
+    }
+
+    pub fn preserve_cr(&self) -> bool {
+        self.preserve_cr
+    }
+
+    /// Borrow FILENAME directly.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
     /// Borrow OFS directly (avoids clone in hot print path).
     pub fn ofs(&self) -> &str {
         &self.ofs
@@ -367,7 +723,15 @@ impl Runtime {
     }
 
     pub fn nf(&self) -> usize {
-        self.nf
+        match self.field_rest {
+            Some(_) => self.all_offsets().len(),
+            None => self.nf,
+        }
+    }
+
+    fn set_nf(&mut self, n: usize) {
+        self.finish_split();
+        self.nf = n;
     }
 
     pub fn ofmt(&self) -> &str {
@@ -383,10 +747,15 @@ impl Runtime {
             if self.record_text_valid && !self.fields_dirty {
                 return self.record_text.clone();
             }
-            if self.fields_lazy {
-                return self.join_from_offsets();
+            let mut joined = if self.fields_lazy {
+                self.join_from_offsets()
+            } else {
+                self.fields.join(&self.ofs)
+            };
+            if self.record_cr {
+                joined.push('\r');
             }
-            return self.fields.join(&self.ofs);
+            return joined;
         }
         if self.fields_lazy {
             return self.field_from_offset(idx - 1);
@@ -403,23 +772,25 @@ impl Runtime {
             }
             if self.fields_lazy {
                 let rt = self.record_text.as_bytes();
-                for (i, &(start, end)) in self.field_offsets.iter().enumerate() {
+                for (i, &(start, end)) in self.all_offsets().iter().enumerate() {
                     if i > 0 {
                         let _ = w.write_all(self.ofs.as_bytes());
                     }
                     let _ = w.write_all(&rt[start..end]);
                 }
-                return;
-            }
-            for (i, f) in self.fields.iter().enumerate() {
-                if i > 0 {
-                    let _ = w.write_all(self.ofs.as_bytes());
+            } else {
+                for (i, f) in self.fields.iter().enumerate() {
+                    if i > 0 {
+                        let _ = w.write_all(self.ofs.as_bytes());
+                    }
+                    let _ = w.write_all(f.as_bytes());
                 }
-                let _ = w.write_all(f.as_bytes());
+            }
+            if self.record_cr {
+                let _ = w.write_all(b"\r");
             }
         } else if self.fields_lazy {
-            let fi = idx - 1;
-            if let Some(&(start, end)) = self.field_offsets.get(fi) {
+            if let Some((start, end)) = self.field_offset(idx - 1) {
                 let _ = w.write_all(&self.record_text.as_bytes()[start..end]);
             }
         } else if let Some(f) = self.fields.get(idx - 1) {
@@ -427,15 +798,19 @@ impl Runtime {
         }
     }
 
+    /// Assign a field. A new `$0` is only stored: its fields are split
+    /// with the current FS when first read (or by [`Runtime::split_record`]).
     pub fn set_field(&mut self, idx: usize, value: &str) {
         if idx == 0 {
             self.record_text.clear();
             self.record_text.push_str(value);
             self.record_text_valid = true;
             self.fields_dirty = false;
-            self.fields_lazy = false;
-            self.fields = field::split(value, &self.fs);
-            self.nf = self.fields.len();
+            self.record_cr = self.preserve_cr && value.ends_with('\r');
+            self.fields_lazy = true;
+            self.field_offsets.clear();
+            self.field_rest = Some(0);
+            self.nf = 0;
             return;
         }
         if self.fields_lazy {
@@ -459,12 +834,27 @@ impl Runtime {
         self.record_text_valid = false;
     }
 
+    /// Replace array `name` with fields `from..=to` of the current record,
+    /// keyed from 1. Returns how many were copied.
+    pub fn fields_to_array(&mut self, name: &str, from: usize, to: usize) -> usize {
+        self.delete_array_all(name);
+        let mut copied = 0;
+        for idx in from..=to {
+            copied += 1;
+            let val = Value::from_string(self.get_field(idx));
+            self.set_array_value(name, &copied.to_string(), val);
+        }
+        copied
+    }
+
     pub fn set_record(&mut self, line: &str) {
         self.record_text.clear();
         self.record_text.push_str(line);
         self.record_text_valid = true;
         self.fields_dirty = false;
         self.fields_lazy = true;
+        self.field_rest = None;
+        let line = self.split_view(line);
         field::split_offsets(&mut self.field_offsets, line, &self.fs);
         self.nf = self.field_offsets.len();
     }
@@ -476,45 +866,125 @@ impl Runtime {
         self.record_text.push_str(line);
         self.record_text_valid = true;
         self.fields_dirty = false;
+        self.record_cr = self.preserve_cr && line.ends_with('\r');
         self.fields_lazy = false;
+        self.field_rest = None;
     }
 
     /// Split only the first `limit` fields (used when max_field_hint
     /// is known and NF is not needed).  $0 is served from record_text.
+    /// The rest is remembered unsplit: a field past `limit`, NF or a field
+    /// assignment still sees the whole record.
     pub fn set_record_capped(&mut self, line: &str, limit: usize) {
         self.record_text.clear();
         self.record_text.push_str(line);
         self.record_text_valid = true;
         self.fields_dirty = false;
         self.fields_lazy = true;
-        field::split_offsets_limit(&mut self.field_offsets, line, &self.fs, limit);
+        let line = self.split_view(line);
+        self.field_rest =
+            field::split_offsets_bounded(&mut self.field_offsets, line, &self.fs, limit);
         self.nf = self.field_offsets.len();
     }
 
     /// Set the record with pre-split fields (used by CSV/TSV/JSON readers).
     /// Preserve raw text for $0 while still serving fields directly.
-    pub fn set_record_fields(&mut self, text: &str, fields: Vec<String>) {
+    /// Fields are copied into the strings kept from the previous record,
+    /// which only reallocate when a value outgrows them.
+    pub fn set_record_fields(&mut self, text: &str, fields: &[String]) {
+        self.record_text.clear();
+        self.record_text.push_str(text);
+        self.record_text_valid = true;
+        self.fields_dirty = false;
+        self.record_cr = self.preserve_cr && text.ends_with('\r');
+        self.fields_lazy = false;
+        self.field_rest = None;
+        self.nf = fields.len();
+        self.fields.truncate(fields.len());
+        let kept = self.fields.len();
+        for (dst, src) in self.fields.iter_mut().zip(fields) {
+            dst.clone_from(src);
+        }
+        self.fields.extend_from_slice(&fields[kept..]);
+    }
+
+    /// Like `set_record_fields`, but takes the fields by swapping Vecs:
+    /// `fields` is left holding the previous record's strings to refill.
+    pub fn swap_record_fields(&mut self, text: &str, fields: &mut Vec<String>) {
         self.record_text.clear();
         self.record_text.push_str(text);
         self.record_text_valid = true;
         self.fields_dirty = false;
+        self.record_cr = self.preserve_cr && text.ends_with('\r');
         self.fields_lazy = false;
+        self.field_rest = None;
         self.nf = fields.len();
-        self.fields = fields;
+        std::mem::swap(&mut self.fields, fields);
+    }
+
+    /// Note whether `line`, about to be split, ends in a `\r` kept by
+    /// `--preserve-cr`, and return it without that `\r`.
+    fn split_view<'a>(&mut self, line: &'a str) -> &'a str {
+        let view = match self.preserve_cr {
+            true => line.strip_suffix('\r').unwrap_or(line),
+            false => line,
+        };
+        self.record_cr = view.len() < line.len();
+        view
+    }
+
+    /// The record text fields are split from: without a kept `\r`.
+    fn split_text(&self) -> &str {
+        &self.record_text[..self.record_text.len() - usize::from(self.record_cr)]
+    }
+
+    /// Offsets of every field, splitting a bounded record's rest on the fly.
+    fn all_offsets(&self) -> Cow<'_, [(usize, usize)]> {
+        match self.field_rest {
+            Some(rest) => {
+                let mut all = self.field_offsets.clone();
+                field::split_offsets_from(&mut all, self.split_text(), &self.fs, rest);
+                Cow::Owned(all)
+            }
+            None => Cow::Borrowed(&self.field_offsets),
+        }
+    }
+
+    fn field_offset(&self, fi: usize) -> Option<(usize, usize)> {
+        match self.field_offsets.get(fi) {
+            Some(&o) => Some(o),
+            None if self.field_rest.is_some() => self.all_offsets().get(fi).copied(),
+            None => None,
+        }
+    }
+
+    /// Split whatever of the record is still unsplit, so repeated field
+    /// and NF reads are served from offsets.
+    pub fn split_record(&mut self) {
+        self.finish_split();
+    }
+
+    /// Split the rest of a bounded record, before anything rewrites fields.
+    fn finish_split(&mut self) {
+        if let Some(rest) = self.field_rest.take() {
+            let end = self.record_text.len() - usize::from(self.record_cr);
+            let text = &self.record_text[..end];
+            field::split_offsets_from(&mut self.field_offsets, text, &self.fs, rest);
+            self.nf = self.field_offsets.len();
+        }
     }
 
     fn field_from_offset(&self, fi: usize) -> String {
-        if let Some(&(start, end)) = self.field_offsets.get(fi) {
-            self.record_text[start..end].to_string()
-        } else {
-            String::new()
+        match self.field_offset(fi) {
+            Some((start, end)) => self.record_text[start..end].to_string(),
+            None => String::new(),
         }
     }
 
     fn join_from_offsets(&self) -> String {
         let mut out = String::new();
         let rt = &self.record_text;
-        for (i, &(start, end)) in self.field_offsets.iter().enumerate() {
+        for (i, &(start, end)) in self.all_offsets().iter().enumerate() {
             if i > 0 {
                 out.push_str(&self.ofs);
             }
@@ -524,6 +994,7 @@ impl Runtime {
     }
 
     fn materialize_fields(&mut self) {
+        self.finish_split();
         self.fields.clear();
         for &(start, end) in &self.field_offsets {
             self.fields.push(self.record_text[start..end].to_string());
@@ -550,6 +1021,10 @@ impl Runtime {
     // --- array operations ---
 
     pub fn get_array_value(&self, name: &str, key: &str) -> Value {
+        let name = self.array_key(name);
+        if let Some(store) = self.spilled_store(name) {
+            return store.get(key).unwrap_or_default();
+        }
         self.arrays
             .get(name)
             .and_then(|a| a.get(key))
@@ -557,11 +1032,32 @@ impl Runtime {
             .unwrap_or_default()
     }
 
+    /// `a[k]` read as a value: as in awk, a missing element is created
+    /// (uninitialized), so a later `k in a` or `length(a)` sees it. Only
+    /// `in` tests without creating.
+    pub fn get_or_create_array_value(&mut self, name: &str, key: &str) -> Value {
+        if let Some(name) = self.array_ref(name) {
+            return self.get_or_create_array_value(&name, key);
+        }
+        let found = match self.spilled_store(name) {
+            Some(store) => store.get(key),
+            None => self.arrays.get(name).and_then(|a| a.get(key)).cloned(),
+        };
+        found.unwrap_or_else(|| {
+            self.set_array_value(name, key, Value::default());
+            Value::default()
+        })
+    }
+
     pub fn get_array(&self, name: &str, key: &str) -> String {
         self.get_array_value(name, key).into_string()
     }
 
     pub fn get_array_opt(&self, name: &str, key: &str) -> Option<String> {
+        let name = self.array_key(name);
+        if let Some(store) = self.spilled_store(name) {
+            return store.get(key).map(Value::into_string);
+        }
         self.arrays
             .get(name)
             .and_then(|a| a.get(key))
@@ -569,62 +1065,334 @@ impl Runtime {
     }
 
     pub fn set_array_value(&mut self, name: &str, key: &str, val: Value) {
-        self.arrays
-            .entry(name.to_string())
-            .or_default()
-            .insert(key.to_string(), val);
+        if let Some(name) = self.array_ref(name) {
+            return self.set_array_value(&name, key, val);
+        }
+        if let Some(store) = self.spilled_store_mut(name) {
+            store.insert(key, &val);
+            return;
+        }
+        let arr = self.arrays.entry(name.to_string()).or_default();
+        let mut too_big = false;
+        if self.spill_bytes > 0 {
+            let added = element_bytes(key, &val);
+            let removed = arr.get(key).map_or(0, |old| element_bytes(key, old));
+            let size = self
+                .array_bytes
+                .entry(name.to_string())
+                .or_insert_with(|| arr.iter().map(|(k, v)| element_bytes(k, v)).sum());
+            *size = *size + added - removed;
+            too_big = *size > self.spill_bytes;
+        }
+        arr.insert(key, val);
+        if too_big || (self.spill_threshold > 0 && arr.len() > self.spill_threshold) {
+            self.spill(name);
+        }
     }
 
     pub fn set_array(&mut self, name: &str, key: &str, value: &str) {
         self.set_array_value(name, key, Value::from_str_ref(value));
     }
 
+    /// Set an element to input text (`split` pieces), keeping it a strnum.
+    pub fn set_array_strnum(&mut self, name: &str, key: &str, value: &str) {
+        self.set_array_value(name, key, Value::from_strnum(value.to_string()));
+    }
+
+    /// Set ARGC and ARGV as POSIX has them: `ARGV[0]` is the program name
+    /// (the basename of `command`, wherever fk is installed), `ARGV[1..]`
+    /// the operands, all strnums.
+    pub fn set_argv(&mut self, command: &str, operands: &[String]) {
+        self.set_var(
+            "ARGC",
+            &builtins::format_number((operands.len() + 1) as f64),
+        );
+        let name = std::path::Path::new(command)
+            .file_name()
+            .map_or(command.into(), |n| n.to_string_lossy());
+        self.set_array_strnum("ARGV", "0", &name);
+        for (i, arg) in operands.iter().enumerate() {
+            self.set_array_strnum("ARGV", &(i + 1).to_string(), arg);
+        }
+    }
+
     pub fn delete_array(&mut self, name: &str, key: &str) {
-        if let Some(a) = self.arrays.get_mut(name) {
-            a.remove(key);
+        if let Some(name) = self.array_ref(name) {
+            return self.delete_array(&name, key);
+        }
+        if let Some(store) = self.spilled_store_mut(name) {
+            store.remove(key);
+        } else if let Some(a) = self.arrays.get_mut(name)
+            && let Some(old) = a.remove(key)
+            && let Some(size) = self.array_bytes.get_mut(name)
+        {
+            *size = size.saturating_sub(element_bytes(key, &old));
         }
     }
 
     pub fn delete_array_all(&mut self, name: &str) {
+        if let Some(name) = self.array_ref(name) {
+            return self.delete_array_all(&name);
+        }
         self.arrays.remove(name);
+        self.spilled.remove(name);
         self.array_meta.remove(name);
+        self.array_bytes.remove(name);
     }
 
     pub fn array_len(&self, name: &str) -> usize {
+        let name = self.array_key(name);
+        if let Some(store) = self.spilled_store(name) {
+            return store.len();
+        }
         self.arrays.get(name).map_or(0, |a| a.len())
     }
 
     pub fn array_has_key(&self, name: &str, key: &str) -> bool {
+        let name = self.array_key(name);
+        if let Some(store) = self.spilled_store(name) {
+            return store.contains_key(key);
+        }
         self.arrays.get(name).is_some_and(|a| a.contains_key(key))
     }
 
     /// Check if an array exists (may be empty).
     pub fn has_array(&self, name: &str) -> bool {
-        self.arrays.contains_key(name)
+        let name = self.array_key(name);
+        self.arrays.contains_key(name) || self.spilled.contains_key(name)
+    }
+
+    /// Replace `dst` with a copy of `src` (elements and metadata).
+    pub fn copy_array(&mut self, dst: &str, src: &str) -> usize {
+        let (dst, src) = (
+            self.array_key(dst).to_string(),
+            self.array_key(src).to_string(),
+        );
+        let (dst, src) = (dst.as_str(), src.as_str());
+        if dst == src {
+            return self.array_len(src);
+        }
+        let meta = self.array_meta.get(src).cloned();
+        self.arrays.remove(dst);
+        self.spilled.remove(dst);
+        self.array_bytes.remove(dst);
+        let n = if let Some(store) = self.spilled.get(src) {
+            let elems: Vec<(String, Value)> = store.iter().map(|(k, v)| (k.clone(), v)).collect();
+            for (k, v) in &elems {
+                self.set_array_value(dst, k, v.clone());
+            }
+            elems.len()
+        } else {
+            let elems = self.arrays.get(src).cloned().unwrap_or_default();
+            let n = elems.len();
+            self.arrays.insert(dst.to_string(), elems);
+            n
+        };
+        match meta {
+            Some(m) => self.set_meta(dst, m),
+            None => self.remove_meta(dst),
+        }
+        n
+    }
+
+    /// Make `name` refer to the storage of array `key` (itself the result
+    /// of `array_key`, so bindings never chain) and return the binding it
+    /// replaces, for `unbind_array`.
+    pub fn bind_array(&mut self, name: &str, key: String) -> Option<String> {
+        if key == name {
+            self.array_refs.remove(name)
+        } else {
+            self.array_refs.insert(name.to_string(), key)
+        }
+    }
+
+    /// Bind `name` to storage of its own for a call at `depth`, hiding
+    /// any array it named; `unbind_array` with the result undoes it.
+    pub fn bind_local_array(&mut self, name: &str, depth: usize) -> Option<String> {
+        self.bind_array(name, format!("{}{}{}", name, LOCAL_ARRAY_SEP, depth))
+    }
+
+    /// Undo `bind_array`, restoring the binding it returned.
+    pub fn unbind_array(&mut self, name: &str, previous: Option<String>) {
+        match previous {
+            Some(key) => self.array_refs.insert(name.to_string(), key),
+            None => self.array_refs.remove(name),
+        };
+    }
+
+    /// Whether `name` is bound to another array (see `bind_array`).
+    pub fn is_bound_array(&self, name: &str) -> bool {
+        !self.array_refs.is_empty() && self.array_refs.contains_key(name)
+    }
+
+    /// The name `name`'s elements are stored under.
+    pub fn array_key<'a>(&'a self, name: &'a str) -> &'a str {
+        if self.array_refs.is_empty() {
+            name
+        } else {
+            self.array_refs.get(name).map_or(name, String::as_str)
+        }
+    }
+
+    fn array_ref(&self, name: &str) -> Option<String> {
+        if self.array_refs.is_empty() {
+            None
+        } else {
+            self.array_refs.get(name).cloned()
+        }
+    }
+
+    /// Names of every array that currently exists, leaving out the
+    /// storage of function-local arrays (see `bind_local_array`).
+    pub fn array_names(&self) -> Vec<String> {
+        self.arrays
+            .keys()
+            .chain(self.spilled.keys())
+            .filter(|name| !name.contains(LOCAL_ARRAY_SEP))
+            .cloned()
+            .collect()
+    }
+
+    /// `array_names`, sorted; fk's own (`ENVIRON`, `PROCINFO`...) only
+    /// with `builtins`.
+    pub fn sorted_array_names(&self, builtins: bool) -> Vec<String> {
+        let mut names = self.array_names();
+        names.retain(|n| builtins || !is_builtin_name(n));
+        names.sort();
+        names
     }
 
     pub fn array_keys(&self, name: &str) -> Vec<String> {
+        let name = self.array_key(name);
+        if let Some(store) = self.spilled_store(name) {
+            return store.keys().cloned().collect();
+        }
         self.arrays
             .get(name)
             .map(|a| a.keys().cloned().collect())
             .unwrap_or_default()
     }
 
-    pub fn array_values(&self, name: &str) -> Option<hash_map::Values<'_, String, Value>> {
-        self.arrays.get(name).map(|a| a.values())
+    /// Keys paired with the numeric value of their elements, read in one
+    /// pass without cloning the values (for sorting by value).
+    pub fn array_key_numbers(&self, name: &str) -> Vec<(String, f64)> {
+        let name = self.array_key(name);
+        if let Some(store) = self.spilled_store(name) {
+            return store
+                .iter()
+                .map(|(k, v)| (k.clone(), v.to_number()))
+                .collect();
+        }
+        self.arrays
+            .get(name)
+            .map(|a| a.iter().map(|(k, v)| (k.clone(), v.to_number())).collect())
+            .unwrap_or_default()
+    }
+
+    /// The numeric value of every element, in insertion order.
+    pub fn array_numbers(&self, name: &str) -> Option<Vec<f64>> {
+        let name = self.array_key(name);
+        if let Some(store) = self.spilled_store(name) {
+            return Some(store.iter().map(|(_, v)| v.to_number()).collect());
+        }
+        self.arrays
+            .get(name)
+            .map(|a| a.values().map(Value::to_number).collect())
+    }
+
+    fn spilled_store(&self, name: &str) -> Option<&SpillStore> {
+        if self.spilled.is_empty() {
+            None
+        } else {
+            self.spilled.get(name)
+        }
+    }
+
+    fn spilled_store_mut(&mut self, name: &str) -> Option<&mut SpillStore> {
+        if self.spilled.is_empty() {
+            None
+        } else {
+            self.spilled.get_mut(name)
+        }
+    }
+
+    /// Set `ARRAY_SPILL_BYTES` from a byte count or a size like `64M`;
+    /// anything else turns it off with a warning.
+    fn set_spill_bytes(&mut self, limit: &str) {
+        self.array_bytes.clear();
+        self.spill_bytes = crate::cli::parse_size(limit.trim()).unwrap_or_else(|| {
+            self.warnings.push(format!(
+                "ARRAY_SPILL_BYTES must be a byte count such as 1048576 or 64M, not \"{}\"",
+                limit
+            ));
+            0
+        });
+    }
+
+    /// Whether `take_warnings` has anything to report.
+    pub fn has_warnings(&self) -> bool {
+        !self.warnings.is_empty() || self.spilled.values().any(SpillStore::has_errors)
+    }
+
+    /// Problems met while storing arrays (spilling, spilled reads and
+    /// writes) since the last call, for the executor's `warn`.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        let mut out = std::mem::take(&mut self.warnings);
+        for store in self.spilled.values() {
+            out.extend(store.take_errors());
+        }
+        out
+    }
+
+    /// Move `name`'s values to a disk-backed store, keeping its keys in
+    /// memory, and note it in `PROCINFO[name, "spilled"]`. PROCINFO and
+    /// ENVIRON stay in memory; if no store can be created the array does too.
+    fn spill(&mut self, name: &str) {
+        if matches!(name, "PROCINFO" | "ENVIRON") {
+            return;
+        }
+        let mut store = match SpillStore::create() {
+            Ok(store) => store,
+            Err(e) => {
+                self.warnings
+                    .push(format!("cannot spill array {} to disk: {}", name, e));
+                self.spill_threshold = 0;
+                self.spill_bytes = 0;
+                self.array_bytes.clear();
+                return;
+            }
+        };
+        self.array_bytes.remove(name);
+        let Some(elems) = self.arrays.remove(name) else {
+            return;
+        };
+        for (k, v) in elems.iter() {
+            store.insert(k, v);
+        }
+        let count = store.len();
+        self.spilled.insert(name.to_string(), store);
+        let key = format!("{}{}spilled", name, self.subsep);
+        self.set_array("PROCINFO", &key, &count.to_string());
     }
 
     // --- array metadata ---
 
     pub fn get_meta(&self, name: &str) -> Option<&ArrayMeta> {
+        let name = self.array_key(name);
         self.array_meta.get(name)
     }
 
     pub fn set_meta(&mut self, name: &str, meta: ArrayMeta) {
+        if let Some(name) = self.array_ref(name) {
+            return self.set_meta(&name, meta);
+        }
         self.array_meta.insert(name.to_string(), meta);
     }
 
     pub fn remove_meta(&mut self, name: &str) {
+        if let Some(name) = self.array_ref(name) {
+            return self.remove_meta(&name);
+        }
         self.array_meta.remove(name);
     }
 }
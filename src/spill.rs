@@ -0,0 +1,202 @@
+//! Disk-backed storage for arrays that outgrow `ARRAY_SPILL_THRESHOLD`.
+//!
+//! A spilled array keeps its keys in memory and its values in an
+//! append-only temp file: setting an element appends its encoded value and
+//! points the key at it, so overwritten and deleted values are simply left
+//! behind in the log. Appends are buffered; a read of a value that is
+//! still in the buffer is served from memory. If writing the log fails,
+//! the buffer stops being flushed and the array carries on in memory.
+//! I/O errors are kept for the executor to report (`take_errors`).
+
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::ordmap::OrderedMap;
+use crate::runtime::Value;
+
+/// Buffered appends are written out once they reach this size.
+const FLUSH_AT: usize = 1 << 20;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+pub struct SpillStore {
+    file: File,
+    path: PathBuf,
+    /// Key → (offset, length) of its value in the log.
+    index: OrderedMap<(u64, u32)>,
+    /// Bytes of the log already on disk; `pending` follows them.
+    flushed: u64,
+    pending: Vec<u8>,
+    /// Writing failed once; everything since stays in `pending`.
+    failed: bool,
+    /// I/O errors not yet reported; reads add to it, hence the RefCell.
+    errors: RefCell<Vec<String>>,
+}
+
+impl SpillStore {
+    /// A new, empty store backed by a fresh file in the temp directory.
+    pub fn create() -> std::io::Result<SpillStore> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("fk-spill-{}-{}.log", std::process::id(), id));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(SpillStore {
+            file,
+            path,
+            index: OrderedMap::default(),
+            flushed: 0,
+            pending: Vec::new(),
+            failed: false,
+            errors: RefCell::default(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.index.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.index.keys()
+    }
+
+    pub fn insert(&mut self, key: &str, val: &Value) {
+        let offset = self.flushed + self.pending.len() as u64;
+        val.encode(&mut self.pending);
+        let len = (self.flushed + self.pending.len() as u64 - offset) as u32;
+        self.index.insert(key, (offset, len));
+        if self.pending.len() >= FLUSH_AT && !self.failed {
+            self.flush();
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.index.remove(key);
+    }
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let &(offset, len) = self.index.get(key)?;
+        if offset >= self.flushed {
+            let start = (offset - self.flushed) as usize;
+            return Value::decode(&self.pending[start..start + len as usize]);
+        }
+        let mut buf = vec![0; len as usize];
+        let mut file = &self.file;
+        match file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(&mut buf))
+        {
+            Ok(()) => Value::decode(&buf),
+            Err(e) => {
+                self.errors
+                    .borrow_mut()
+                    .push(format!("reading spilled array: {}", e));
+                None
+            }
+        }
+    }
+
+    /// Every (key, value) pair, in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, Value)> {
+        self.index
+            .keys()
+            .map(|k| (k, self.get(k).unwrap_or_default()))
+    }
+
+    fn flush(&mut self) {
+        let mut file = &self.file;
+        let written = file
+            .seek(SeekFrom::Start(self.flushed))
+            .and_then(|_| file.write_all(&self.pending));
+        match written {
+            Ok(()) => {
+                self.flushed += self.pending.len() as u64;
+                self.pending.clear();
+            }
+            Err(e) => {
+                self.errors.get_mut().push(format!(
+                    "spilling array to {}: {}; keeping the rest of the array in memory",
+                    self.path.display(),
+                    e
+                ));
+                self.failed = true;
+            }
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.borrow().is_empty()
+    }
+
+    /// The I/O errors since the last call.
+    pub fn take_errors(&self) -> Vec<String> {
+        self.errors.take()
+    }
+}
+
+impl Drop for SpillStore {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_round_trip_through_buffer_and_file() {
+        let mut store = SpillStore::create().unwrap();
+        let path = store.path.clone();
+        store.insert("n", &Value::from_number(2.5));
+        store.insert("s", &Value::from_string("héllo".to_string()));
+        store.insert("null", &Value::null());
+        // Enough to flush the first values out of the buffer.
+        let big = "x".repeat(FLUSH_AT);
+        store.insert("big", &Value::from_string(big.clone()));
+        store.insert("after", &Value::from_str_ref("tail"));
+        store.insert("n", &Value::from_number(3.0));
+        assert!(store.flushed > 0);
+        assert_eq!(store.get("n").unwrap().to_number(), 3.0);
+        assert!(store.get("n").unwrap().is_numeric_only());
+        assert_eq!(store.get("s").unwrap().into_string(), "héllo");
+        assert!(store.get("null").unwrap().is_null());
+        assert_eq!(store.get("big").unwrap().into_string(), big);
+        assert_eq!(store.get("after").unwrap().into_string(), "tail");
+        store.remove("s");
+        assert!(store.get("s").is_none());
+        assert_eq!(store.len(), 4);
+        drop(store);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn read_errors_are_kept_for_reporting() {
+        let mut store = SpillStore::create().unwrap();
+        store.insert("big", &Value::from_string("x".repeat(FLUSH_AT)));
+        assert!(store.flushed > 0 && !store.has_errors());
+        store.file.set_len(0).unwrap();
+        assert!(store.get("big").is_none());
+        let errors = store.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].starts_with("reading spilled array: "),
+            "{errors:?}"
+        );
+        assert!(!store.has_errors());
+    }
+}
@@ -0,0 +1,213 @@
+//! `--state FILE`: arrays that survive between runs.
+//!
+//! Before BEGIN every array in the state file is loaded into the runtime;
+//! after END the arrays named `persist_*`, plus any listed in `STATE_ARRAYS`,
+//! are written back through a temp file and a rename, so an interrupted run
+//! never leaves a truncated state behind.
+//!
+//! Format (all integers little-endian):
+//!
+//! ```text
+//! "FKSTATE" version:u8
+//! per array:  name:str  count:u32  then count × (key:str  value)
+//! value:      0 str | 1 f64          (str = len:u32 + UTF-8 bytes)
+//! ```
+//!
+//! Keys are stored verbatim, so SUBSEP-joined keys round-trip, and values
+//! keep their number-versus-string flag.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::runtime::{Runtime, Value};
+
+const MAGIC: &[u8] = b"FKSTATE";
+const VERSION: u8 = 1;
+
+/// Arrays with this prefix are always saved.
+pub const PERSIST_PREFIX: &str = "persist_";
+
+const TAG_STR: u8 = 0;
+const TAG_NUM: u8 = 1;
+
+/// Load every array stored in `path` into `rt`, returning how many were
+/// read. A missing file is a first run, not an error.
+pub fn load(path: &str, rt: &mut Runtime) -> Result<usize, String> {
+    let bytes = match fs::read(path) {
+        Ok(b) => b,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(format!("{}: {}", path, e)),
+    };
+    decode(&bytes, rt).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Atomically write the persisted arrays of `rt` to `path`, returning how
+/// many were saved.
+pub fn save(path: &str, rt: &Runtime) -> Result<usize, String> {
+    let names = persisted_arrays(rt);
+    let bytes = encode(rt, &names);
+    let tmp = temp_path(path);
+    let written = fs::File::create(&tmp)
+        .and_then(|mut f| f.write_all(&bytes).and_then(|_| f.sync_all()))
+        .and_then(|_| fs::rename(&tmp, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("{}: {}", path, e));
+    }
+    Ok(names.len())
+}
+
+/// Arrays to save: every `persist_*` array plus the names listed in
+/// `STATE_ARRAYS` (comma- or space-separated), sorted and deduplicated.
+pub fn persisted_arrays(rt: &Runtime) -> Vec<String> {
+    let listed = rt.get_var("STATE_ARRAYS");
+    let mut names: Vec<String> = rt
+        .array_names()
+        .into_iter()
+        .filter(|n| n.starts_with(PERSIST_PREFIX))
+        .collect();
+    for name in listed.split(|c: char| c == ',' || c.is_whitespace()) {
+        if !name.is_empty() && rt.has_array(name) {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Same directory as the target so the rename never crosses filesystems.
+fn temp_path(path: &str) -> String {
+    let p = Path::new(path);
+    let file = p.file_name().and_then(|f| f.to_str()).unwrap_or("state");
+    let tmp = format!(".{}.tmp{}", file, std::process::id());
+    match p.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.join(tmp).to_string_lossy().into_owned(),
+        _ => tmp,
+    }
+}
+
+fn encode(rt: &Runtime, names: &[String]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    for name in names {
+        let mut keys = rt.array_keys(name);
+        keys.sort();
+        put_str(&mut out, name);
+        out.extend_from_slice(&(keys.len() as u32).to_le_bytes());
+        for key in keys {
+            put_str(&mut out, &key);
+            let val = rt.get_array_value(name, &key);
+            if val.is_numeric_only() {
+                out.push(TAG_NUM);
+                out.extend_from_slice(&val.to_number().to_le_bytes());
+            } else {
+                out.push(TAG_STR);
+                put_str(&mut out, &val.to_string_val());
+            }
+        }
+    }
+    out
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn decode(bytes: &[u8], rt: &mut Runtime) -> Result<usize, String> {
+    let mut r = Reader { bytes, pos: 0 };
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err("not an fk state file".to_string());
+    }
+    let version = r.take(1)?[0];
+    if version != VERSION {
+        return Err(format!("unsupported state version {}", version));
+    }
+    let mut arrays = 0;
+    while r.pos < bytes.len() {
+        let name = r.string()?;
+        let count = r.u32()?;
+        rt.delete_array_all(&name);
+        for _ in 0..count {
+            let key = r.string()?;
+            let val = match r.take(1)?[0] {
+                TAG_NUM => Value::from_number(f64::from_le_bytes(r.take(8)?.try_into().unwrap())),
+                TAG_STR => Value::from_string(r.string()?),
+                tag => return Err(format!("bad value tag {}", tag)),
+            };
+            rt.set_array_value(&name, &key, val);
+        }
+        arrays += 1;
+    }
+    Ok(arrays)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).filter(|&e| e <= self.bytes.len());
+        let end = end.ok_or_else(|| "truncated state file".to_string())?;
+        let s = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(s)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let n = self.u32()? as usize;
+        String::from_utf8(self.take(n)?.to_vec()).map_err(|_| "invalid UTF-8 in state file".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_subsep_keys_and_value_kinds() {
+        let mut rt = Runtime::new();
+        let key = format!("a{}b", rt.get_var("SUBSEP"));
+        rt.set_array_value("persist_n", &key, Value::from_number(2.5));
+        rt.set_array_value("persist_n", "s", Value::from_str_ref("007"));
+        rt.set_array_value("other", "x", Value::from_number(1.0));
+        rt.set_array_value("listed", "y", Value::from_number(3.0));
+        rt.set_var("STATE_ARRAYS", "listed, missing");
+        assert_eq!(persisted_arrays(&rt), ["listed", "persist_n"]);
+
+        let bytes = encode(&rt, &persisted_arrays(&rt));
+        let mut back = Runtime::new();
+        assert_eq!(decode(&bytes, &mut back).unwrap(), 2);
+        let n = back.get_array_value("persist_n", &key);
+        assert!(n.is_numeric_only() && n.to_number() == 2.5);
+        let s = back.get_array_value("persist_n", "s");
+        assert!(!s.is_numeric_only() && s.to_string_val() == "007");
+        assert_eq!(back.get_array("listed", "y"), "3");
+        assert!(!back.has_array("other"));
+        // Deterministic output.
+        assert_eq!(encode(&back, &persisted_arrays(&rt)), bytes);
+    }
+
+    #[test]
+    fn rejects_corrupt_files() {
+        let mut rt = Runtime::new();
+        assert!(decode(b"NOTSTATE", &mut rt).is_err());
+        assert!(decode(b"FKSTATE\x02", &mut rt).is_err());
+        assert!(decode(b"FKSTATE\x01\x05\x00\x00\x00ab", &mut rt).is_err());
+        assert_eq!(decode(b"FKSTATE\x01", &mut rt).unwrap(), 0);
+    }
+
+    #[test]
+    fn missing_file_is_a_first_run() {
+        let mut rt = Runtime::new();
+        assert_eq!(load("/nonexistent/dir/x.fkstate", &mut rt), Ok(0));
+    }
+}
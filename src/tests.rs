@@ -16,6 +16,7 @@ fn eval(program_text: &str, input_lines: &[&str]) -> runtime::Runtime {
         let rec = input::Record {
             text: line.to_string(),
             fields: None,
+            malformed: None,
         };
         exec.run_record(&rec);
     }
@@ -45,6 +46,7 @@ fn eval_with_header(program_text: &str, fs: &str, input_lines: &[&str]) -> runti
         let rec = input::Record {
             text: line.to_string(),
             fields: None,
+            malformed: None,
         };
         exec.run_record(&rec);
     }
@@ -336,9 +338,76 @@ fn empty_string_is_falsy() {
 }
 
 #[test]
-fn zero_string_is_falsy() {
-    let rt = eval("{ result = (\"0\" ? \"truthy\" : \"falsy\") }", &["x"]);
-    assert_eq!(rt.get_var("result"), "falsy");
+fn zero_string_constant_is_truthy() {
+    // A string constant is true when non-empty, whatever it spells.
+    let rt = eval(
+        "{ a = (\"0\" ? 1 : 2); b = (\"0.0\" ? 1 : 2); c = !\"0\" }",
+        &["x"],
+    );
+    assert_eq!(rt.get_var("a"), "1");
+    assert_eq!(rt.get_var("b"), "1");
+    assert_eq!(rt.get_var("c"), "0");
+}
+
+#[test]
+fn input_values_are_true_when_nonzero_numbers() {
+    let rt = eval(
+        concat!(
+            "{ v = ($1 ? 1 : 2); t = t v; f = $1; v = (f ? 1 : 2); g = g v\n",
+            "  v = ($1 \"\" ? 1 : 2); z = z v }\n",
+            "END { split(\"0 00 x\", p); s = (p[1] ? 1 : 2) (p[2] ? 1 : 2) (p[3] ? 1 : 2)\n",
+            "  k = \"0\" \"\"; c = (k ? 1 : 2) }",
+        ),
+        &["0", "0.0", "00", " 0 ", "abc", "", "1", "-0", "inf", "0x1"],
+    );
+    assert_eq!(rt.get_var("t"), "2222121211");
+    assert_eq!(rt.get_var("g"), rt.get_var("t"));
+    assert_eq!(rt.get_var("s"), "221");
+    assert_eq!(rt.get_var("c"), "1");
+    // Joined to a string, a field is a string: true unless empty.
+    assert_eq!(rt.get_var("z"), "1111121111");
+}
+
+#[test]
+fn loop_conditions_follow_string_truth() {
+    let rt = eval(
+        r#"BEGIN { s = "abc"; while (s) { n++; s = "" }
+            while ("a" == "b") { bad = 1; break }
+            do { m++ } while ("0" && m < 3)
+            for (i = 0; "x" < "y" && i < 2; i++) k++ }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("n"), "1");
+    assert_eq!(rt.get_var("bad"), "");
+    assert_eq!(rt.get_var("m"), "3");
+    assert_eq!(rt.get_var("k"), "2");
+}
+
+#[test]
+fn function_arguments_keep_their_truth() {
+    // A number stays a number through a parameter, a field a strnum and a
+    // constant a string; a missing argument is uninitialized.
+    let rt = eval(
+        r#"function t(v) { return v ? 1 : 2 }
+           function down(n) { if (n) { c++; down(n - 1) } }
+           { r = t(0) t(1) t("0") t($1) t($1 "") t(); down(3) }"#,
+        &["0"],
+    );
+    assert_eq!(rt.get_var("r"), "211212");
+    assert_eq!(rt.get_var("c"), "3");
+}
+
+#[test]
+fn numeric_builtin_results_are_true_when_nonzero() {
+    // index(), length() and the math builtins return numbers, not strings.
+    let rt = eval(
+        r#"BEGIN { at = index("y", "=")
+            r = (at ? 1 : 2) (index("y=", "=") ? 1 : 2) (length("") ? 1 : 2) (int(0.5) ? 1 : 2)
+            s = (substr("0", 1) ? 1 : 2) }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("r"), "2122");
+    assert_eq!(rt.get_var("s"), "1");
 }
 
 #[test]
@@ -382,6 +451,33 @@ fn split_clears_previous_array_contents() {
     assert_eq!(rt.get_var("result"), "");
 }
 
+#[test]
+fn uniqsplit_keeps_first_seen_order() {
+    let rt = eval(
+        r#"{ n = uniqsplit($0, u, ","); r = n ":" u[1] u[2] u[3] u[4] }
+           END {
+               e = uniqsplit("", empty, ",") length(empty) joinuniq("", ",") "|"
+               one = uniqsplit("solo", s, ",") s[1]
+               dot = uniqsplit("a.b.a.b", d, ".") d[1] d[2]
+               pipe = joinuniq("x|y|x||", "|")
+               star = joinuniq("a*b*a", "*", "+")
+               uni = joinuniq("żółw→🐢→żółw→🐢→é", "→")
+               ws = joinuniq("  b a   b  a ")
+           }"#,
+        &["a,b,a,c,b"],
+    );
+    assert_eq!(rt.get_var("r"), "3:abc");
+    assert_eq!(rt.get_array("u", "4"), "");
+    assert_eq!(rt.get_var("e"), "00|");
+    assert_eq!(rt.get_var("one"), "1solo");
+    // Separators are literal, as in split(): no regex meaning.
+    assert_eq!(rt.get_var("dot"), "2ab");
+    assert_eq!(rt.get_var("pipe"), "x|y|");
+    assert_eq!(rt.get_var("star"), "a+b");
+    assert_eq!(rt.get_var("uni"), "żółw→🐢→é");
+    assert_eq!(rt.get_var("ws"), "b a");
+}
+
 // ── Built-in functions: sub / gsub ───────────────────────────────
 
 #[test]
@@ -573,6 +669,17 @@ fn parse_cmd_pipe_getline() {
     assert_eq!(prog.rules.len(), 1);
 }
 
+#[test]
+fn cmd_getline_timeout_returns_minus_two() {
+    let rt = eval(
+        r#"BEGIN { GETLINE_TIMEOUT = 0.2; r = ("sleep 5" | getline x)
+            "echo hi" | getline y }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("r"), "-2");
+    assert_eq!(rt.get_var("y"), "hi");
+}
+
 // ── Exponentiation operator ──────────────────────────────────────
 
 #[test]
@@ -610,6 +717,14 @@ fn hex_literal_basic() {
     assert_eq!(rt.get_var("result"), "255");
 }
 
+#[test]
+fn exponent_literals() {
+    let rt = eval("BEGIN { a = 1e9; b = 2.5E-3; c = 3e+2 }", &[]);
+    assert_eq!(rt.get_var("a"), "1000000000");
+    assert_eq!(rt.get_var("b"), "0.0025");
+    assert_eq!(rt.get_var("c"), "300");
+}
+
 #[test]
 fn hex_literal_in_arithmetic() {
     let rt = eval("{ result = 0x10 + 1 }", &["x"]);
@@ -630,6 +745,15 @@ fn hex_escape_in_string() {
     assert_eq!(rt.get_var("result"), "ABC");
 }
 
+#[test]
+fn carriage_return_escape_in_string() {
+    let rt = eval(
+        r#"{ result = length("a\r\n") ":" ("\r" == "\x0d") }"#,
+        &["x"],
+    );
+    assert_eq!(rt.get_var("result"), "3:1");
+}
+
 #[test]
 fn unicode_escape_in_string() {
     let rt = eval(r#"{ result = "\u00e9" }"#, &["x"]);
@@ -662,6 +786,69 @@ fn delete_entire_array_then_rebuild() {
     assert_eq!(rt.get_var("result"), "new");
 }
 
+#[test]
+fn delete_composite_subscript_removes_exactly_that_key() {
+    let rt = eval(
+        r#"BEGIN { SUBSEP = ":"; i = 1; j = 2
+            a[1, 2]; a[1, 3]; a[12]; a["1:2x"]; a[0.5, "b"]
+            delete a[i, j]; delete a[1 / 2, "b"]
+            for (k in a) keys = keys "|" k }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("keys"), "|1:3|12|1:2x");
+}
+
+#[test]
+fn delete_through_array_parameter_reaches_caller() {
+    let rt = eval(
+        r#"function wipe(x) { delete x }
+           function drop(x, k) { delete x[k] }
+           function fill(y, n) { while (n) y[n--] = 1 }
+           function outer(z) { wipe(z) }
+           BEGIN { a[1]; a[2]; b[1]; b[2]; c[1]
+             wipe(a); drop(b, 1); fill(d, 3); outer(c)
+             r = length(a) length(b) length(d) length(c) (2 in b) }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("r"), "01301");
+}
+
+#[test]
+fn array_parameter_shares_storage_with_the_caller_name() {
+    let rt = eval(
+        r#"function count(a) { n = length(seen) length(a) }
+           function both(p, q) { p["x"] = 1; m = length(q) }
+           BEGIN { seen[1]; seen[2]; count(seen); b[1]; both(b, b); m = m length(b) }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("n"), "22", "global name during the call");
+    assert_eq!(rt.get_var("m"), "22", "f(a, a) binds both parameters");
+}
+
+#[test]
+fn local_array_parameters_start_empty_each_call() {
+    let rt = eval(
+        r#"function f(x,  arr) { arr[x] = 1; for (k in arr) s = s k; s = s " " }
+           function g(n,  arr) { arr[n]; if (n > 0) g(n - 1); return length(arr) }
+           function h(  arr) { fill(arr); return length(arr) }
+           function fill(a) { a[1]; a[2] }
+           BEGIN { f(1); f(2); arr["g"]; r = g(3) length(arr) h() length(arr) }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("s"), "1 2 ");
+    assert_eq!(rt.get_var("r"), "1121");
+}
+
+#[test]
+fn arrays_of_arrays_are_a_clear_parse_error() {
+    for prog in ["BEGIN { delete a[1][2] }", "BEGIN { a[1][2] = 3 }"] {
+        let tokens = lexer::Lexer::new(prog).tokenize().unwrap();
+        let err = parser::Parser::new(tokens).parse().unwrap_err();
+        assert!(err.message.contains("arrays of arrays"), "{prog}");
+        assert!(err.message.contains("a[i, j]"), "{prog}");
+    }
+}
+
 // ── length(array) ────────────────────────────────────────────────
 
 #[test]
@@ -721,12 +908,49 @@ fn system_returns_nonzero_on_failure() {
     assert_eq!(rt.get_var("result"), "1");
 }
 
+#[test]
+fn system_bg_runs_commands_concurrently() {
+    let start = std::time::Instant::now();
+    let rt = eval(
+        r#"BEGIN { a = system_bg("sleep 0.4; exit 3"); b = system_bg("sleep 0.4")
+            sa = wait(a); sb = wait(b); again = wait(a)
+            system_bg("exit 1"); system_bg("true"); system_bg("exit 2"); failed = waitall() }"#,
+        &[],
+    );
+    let took = start.elapsed();
+    assert!(took < std::time::Duration::from_millis(750), "{took:?}");
+    assert_eq!(rt.get_var("a"), "1");
+    assert_eq!(rt.get_var("b"), "2");
+    assert_eq!(rt.get_var("sa"), "3");
+    assert_eq!(rt.get_var("sb"), "0");
+    assert_eq!(rt.get_var("again"), "-1");
+    assert_eq!(rt.get_var("failed"), "2");
+}
+
 #[test]
 fn fflush_returns_zero() {
     let rt = eval("{ result = fflush() }", &["x"]);
     assert_eq!(rt.get_var("result"), "0");
 }
 
+#[test]
+fn fflush_without_name_flushes_every_file() {
+    let dir = std::env::temp_dir().join(format!("fk_fflush_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+    let prog = format!(
+        r#"{{ print "one" > "{a}"; print "two" > "{b}"; r = fflush(); e = fflush("")
+            x = slurp("{a}") slurp("{b}") }}"#,
+        a = a.display(),
+        b = b.display()
+    );
+    let rt = eval(&prog, &["x"]);
+    assert_eq!(rt.get_var("r"), "0");
+    assert_eq!(rt.get_var("e"), "0");
+    assert_eq!(rt.get_var("x"), "one\ntwo\n");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
 // ── Time functions ───────────────────────────────────────────────
 
 #[test]
@@ -736,6 +960,23 @@ fn systime_returns_positive_integer() {
     assert!(ts > 1_000_000_000.0); // after 2001
 }
 
+#[test]
+fn now_is_fractional_and_nondecreasing() {
+    let rt = eval(
+        "BEGIN { a = now(); b = now(); s = systime(); ok = (b >= a && a >= s - 1) }",
+        &[],
+    );
+    assert_eq!(rt.get_var("ok"), "1");
+    let a: f64 = rt.get_var("a").parse().unwrap();
+    assert!(a > 1_000_000_000.0);
+}
+
+#[test]
+fn strftime_fractional_seconds() {
+    let rt = eval(r#"BEGIN { result = strftime("%S.%3N %N", 61.25) }"#, &[]);
+    assert_eq!(rt.get_var("result"), "01.250 250000000");
+}
+
 #[test]
 fn strftime_formats_known_epoch() {
     // Epoch 0 = 1970-01-01 00:00:00 UTC
@@ -971,14 +1212,70 @@ fn many_fields() {
 
 #[test]
 fn deep_recursion_does_not_crash() {
-    // Should hit the call depth limit (200) and return gracefully, not stack overflow
+    // Hits the call depth limit (200) and gives up on the action, not the stack
     let rt = eval(
-        "function f(n) { if (n <= 0) return 0; return f(n-1) } BEGIN { x = f(500) }",
+        "function f(n) { if (n <= 0) return 0; return f(n-1) } BEGIN { x = f(500); y = 1 }",
+        &[],
+    );
+    assert_eq!(rt.get_var("x"), "");
+    assert_eq!(rt.get_var("y"), "");
+}
+
+/// Run under call depth `depth` on a thread with the stack `main` would
+/// give it, returning the values of `vars`.
+fn eval_at_depth(
+    prog: &'static str,
+    lines: &'static [&'static str],
+    depth: usize,
+    vars: &'static [&'static str],
+) -> Vec<String> {
+    let run = move || {
+        let tokens = lexer::Lexer::new(prog).tokenize().unwrap();
+        let program = parser::Parser::new(tokens).parse().unwrap();
+        let mut rt = runtime::Runtime::new();
+        let mut exec = action::Executor::new(&program, &mut rt);
+        exec.set_max_call_depth(depth);
+        exec.run_begin();
+        for line in lines {
+            exec.run_record(&input::Record {
+                text: line.to_string(),
+                fields: None,
+                malformed: None,
+            });
+        }
+        exec.run_end();
+        vars.iter().map(|v| rt.get_var(v)).collect()
+    };
+    std::thread::Builder::new()
+        .stack_size((8 << 20) + depth * action::STACK_PER_CALL)
+        .spawn(run)
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+#[test]
+fn too_deep_recursion_abandons_the_record() {
+    let got = eval_at_depth(
+        "function f(n) { if (n <= 0) return 0; return 1 + f(n-1) }
+         { seen++; d = f($1); done++; sum += d }
+         { later++ }",
+        &["3", "500", "4"],
+        action::DEFAULT_MAX_CALL_DEPTH,
+        &["seen", "done", "later", "sum"],
+    );
+    assert_eq!(got, ["3", "2", "2", "7"]);
+}
+
+#[test]
+fn raised_call_depth_allows_deep_recursion() {
+    let got = eval_at_depth(
+        "function f(n) { if (n <= 0) return 0; return 1 + f(n-1) } BEGIN { x = f(4999) }",
         &[],
+        5000,
+        &["x"],
     );
-    // x will be "" because the depth limit is hit before n reaches 0
-    let x = rt.get_var("x");
-    assert!(x == "0" || x == "", "unexpected result: {}", x);
+    assert_eq!(got, ["4999"]);
 }
 
 #[test]
@@ -1011,6 +1308,7 @@ fn json_mode_preserves_raw_record_text_for_jpath() {
     let rec = input::Record {
         text: r#"{"a":1}"#.to_string(),
         fields: Some(vec!["1".to_string()]),
+        malformed: None,
     };
     exec.run_record(&rec);
     exec.run_end();
@@ -1235,6 +1533,64 @@ fn gensub_regex_pattern() {
     assert_eq!(rt.get_var("x"), "abcNUMdefNUM");
 }
 
+#[test]
+fn gensub_literal_pattern_matches_regex_path() {
+    // "," takes the literal path, "[,]" the regex one; results must agree.
+    let prog = r#"{ a = gensub(",", "|", "g"); b = gensub("[,]", "|", "g")
+        c = gensub(",", "|", 3); d = gensub("[,]", "|", 3)
+        e = gensub(",", "|", 9); f = gensub(",", "|", 0) }"#;
+    let rt = eval(prog, &[",a,,b,"]);
+    assert_eq!(rt.get_var("a"), "|a||b|");
+    assert_eq!(rt.get_var("a"), rt.get_var("b"));
+    assert_eq!(rt.get_var("c"), ",a,|b,");
+    assert_eq!(rt.get_var("c"), rt.get_var("d"));
+    assert_eq!(rt.get_var("e"), ",a,,b,");
+    assert_eq!(rt.get_var("f"), ",a,,b,");
+}
+
+#[test]
+fn gsub_literal_counts_in_one_pass() {
+    let rt = eval(
+        r#"{ n = gsub(",", ""); m = gsub("ab", "abab"); k = gsub("é", "e") }"#,
+        &[",ab,,éab,é"],
+    );
+    assert_eq!(rt.get_var("n"), "4");
+    assert_eq!(rt.get_var("m"), "2");
+    assert_eq!(rt.get_var("k"), "2");
+    assert_eq!(rt.get_field(0), "ababeababe");
+}
+
+#[test]
+fn sub_gsub_metacharacters_take_the_regex_path() {
+    let prog = r#"{ a = $0; n = gsub(/\./, "-", a)
+        b = $0; gsub(/[.]/, "-", b)
+        c = "aXb"; m = gsub(/X|b/, "-", c)
+        d = "x1y22"; sub("[0-9]+", "N", d)
+        e = "a.b"; k = gsub(".", "-", e) }"#;
+    let rt = eval(prog, &["a.b.c"]);
+    assert_eq!(rt.get_var("a"), "a-b-c");
+    assert_eq!(rt.get_var("n"), "2");
+    assert_eq!(rt.get_var("b"), "a-b-c");
+    assert_eq!(rt.get_var("c"), "a--");
+    assert_eq!(rt.get_var("m"), "2");
+    assert_eq!(rt.get_var("d"), "xNy22");
+    assert_eq!(rt.get_var("e"), "---");
+    assert_eq!(rt.get_var("k"), "3");
+}
+
+#[test]
+fn sub_literal_and_regex_paths_agree() {
+    // "," takes the literal path, "[,]" the regex one; results must agree.
+    let prog = r#"{ a = $0; x = gsub(",", "|", a); b = $0; y = gsub("[,]", "|", b)
+        c = $0; sub(",", "$1", c); d = $0; sub("[,]", "$1", d) }"#;
+    let rt = eval(prog, &[",a,,b,"]);
+    assert_eq!(rt.get_var("a"), "|a||b|");
+    assert_eq!(rt.get_var("a"), rt.get_var("b"));
+    assert_eq!(rt.get_var("x"), rt.get_var("y"));
+    assert_eq!(rt.get_var("c"), "$1a,,b,");
+    assert_eq!(rt.get_var("c"), rt.get_var("d"));
+}
+
 // ── SUBSEP and multi-dimensional arrays ──────────────────────────
 
 #[test]
@@ -1316,6 +1672,24 @@ fn argc_argv_accessible_as_vars() {
     assert_eq!(rt.get_array("ARGV", "1"), "prog");
 }
 
+#[test]
+fn argv_holds_operands_as_strnums() {
+    // Like ENVIRON and -v values, ARGV elements are true when nonzero.
+    let mut lex = lexer::Lexer::new(
+        r#"BEGIN { n = ARGC; r = (ARGV[1] ? 1 : 2) (ARGV[2] ? 1 : 2) (ARGV[3] ? 1 : 2) }"#,
+    );
+    let program = parser::Parser::new(lex.tokenize().unwrap())
+        .parse()
+        .unwrap();
+    let mut rt = runtime::Runtime::new();
+    let operands = ["0", "0.0", "x.csv"].map(String::from);
+    rt.set_argv("/usr/local/bin/fk", &operands);
+    action::Executor::new(&program, &mut rt).run_begin();
+    assert_eq!(rt.get_var("n"), "4");
+    assert_eq!(rt.get_array("ARGV", "0"), "fk");
+    assert_eq!(rt.get_var("r"), "221");
+}
+
 // ── exit wiring with exit code ───────────────────────────────────
 
 #[test]
@@ -1421,6 +1795,97 @@ fn header_names_with_filter() {
     assert_eq!(rt.get_var("count"), "2");
 }
 
+#[test]
+fn named_fields_are_assignable() {
+    let rt = eval_with_header(
+        r#"BEGIN { OFS = "," }
+        { $"price" = $"price" * 2; $"qty" += 1; $"nope" = "x"; $"nope" += 1
+          r[NR] = $0; n[NR] = NF }"#,
+        ",",
+        &["name,price,qty", "a,10", "b,20,5"],
+    );
+    // qty is past the first record's NF: the gap is padded.
+    assert_eq!(rt.get_array("r", "2"), "a,20,1");
+    assert_eq!(rt.get_array("n", "2"), "3");
+    // An unknown name leaves $0 alone.
+    assert_eq!(rt.get_array("r", "3"), "b,40,6");
+}
+
+#[test]
+fn getline_assigns_fields_and_elements() {
+    let path = std::env::temp_dir().join(format!("fk_getline_lvalue_{}", std::process::id()));
+    std::fs::write(&path, "L1\nL2\nL3\n").unwrap();
+    let f = path.display();
+    let rt = eval_with_header(
+        &format!(
+            r#"{{ r = getline $2 < "{f}"; a = $0; n = NF
+               i = 1; getline $(i + 2) < "{f}"; b = $0
+               getline $"price" < "{f}"; c = $0
+               "echo X Y" | getline $1; d = $0
+               "echo z" | getline e["k"] }}"#
+        ),
+        " ",
+        &["name price qty", "a b c"],
+    );
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(rt.get_var("r"), "1");
+    assert_eq!(rt.get_var("a"), "a L1 c");
+    assert_eq!(rt.get_var("n"), "3");
+    assert_eq!(rt.get_var("b"), "a L1 L2");
+    assert_eq!(rt.get_var("c"), "a L3 L2");
+    assert_eq!(rt.get_var("d"), "X Y L3 L2");
+    assert_eq!(rt.get_array("e", "k"), "z");
+}
+
+#[test]
+fn header_names_do_not_clobber_program_variables() {
+    let prog = concat!(
+        "{ sum += $\"sum\"; count++; for (i = 1; i <= NF; i++) cells++; p += $price }\n",
+        "END { bare = price \"\" }",
+    );
+    let program = parser::Parser::new(lexer::Lexer::new(prog).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    // --header-vars brings back the globals: sum starts at its index.
+    for (header_vars, sum, bare) in [(false, "3", ""), (true, "4", "4")] {
+        let mut rt = runtime::Runtime::new();
+        rt.set_var("FS", ",");
+        let mut exec = action::Executor::new(&program, &mut rt);
+        exec.set_header_vars(header_vars);
+        exec.set_header_from_text("sum,count,i,price");
+        for line in ["1,a,b,10", "2,c,d,20"] {
+            exec.run_record(&input::Record {
+                text: line.to_string(),
+                fields: None,
+                malformed: None,
+            });
+        }
+        exec.run_end();
+        drop(exec);
+        assert_eq!(rt.get_var("sum"), sum);
+        assert_eq!(rt.get_var("count"), if header_vars { "4" } else { "2" });
+        assert_eq!(rt.get_var("cells"), "8");
+        assert_eq!(rt.get_var("p"), "30");
+        assert_eq!(rt.get_var("bare"), bare);
+    }
+}
+
+#[test]
+fn computed_field_targets_are_evaluated_once() {
+    let rt = eval(
+        r#"{ i = 1; $(i++) += 5; a = $0; $(NF + 1) = "x"; b = $0 "|" NF
+           j = 0; $(++j + 1)++; c = $0; sub(/c/, "C", $(++j + 1)); d = $0 "|" j
+           k = 0; arr[k++] += 2; e = k }"#,
+        &["1 2 c"],
+    );
+    assert_eq!(rt.get_var("i"), "2");
+    assert_eq!(rt.get_var("a"), "6 2 c");
+    assert_eq!(rt.get_var("b"), "6 2 c x|4");
+    assert_eq!(rt.get_var("c"), "6 3 c x");
+    assert_eq!(rt.get_var("d"), "6 3 C x|2");
+    assert_eq!(rt.get_var("e"), "1");
+}
+
 // ── Math builtins ───────────────────────────────────────────────
 
 #[test]
@@ -1464,7 +1929,7 @@ fn math_log2_log10() {
 fn math_rand_returns_0_to_1() {
     let rt = eval(r#"BEGIN { srand(42); x = rand() }"#, &[]);
     let x: f64 = rt.get_var("x").parse().unwrap();
-    assert!(x >= 0.0 && x < 1.0);
+    assert!((0.0..1.0).contains(&x));
 }
 
 #[test]
@@ -1474,8 +1939,272 @@ fn math_srand_makes_deterministic() {
     assert_eq!(rt1.get_var("x"), rt2.get_var("x"));
 }
 
+#[test]
+fn srand_returns_the_previous_seed() {
+    let rt = eval(
+        r#"BEGIN { a = srand(5); b = srand(); c = srand(9); d = srand() }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("a"), "0");
+    assert_eq!(rt.get_var("b"), "5");
+    // srand() seeded from the time of day, in seconds.
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as f64;
+    let c: f64 = rt.get_var("c").parse().unwrap();
+    assert!((now - c).abs() < 5.0);
+    assert_eq!(rt.get_var("d"), "9");
+}
+
+#[test]
+fn rand_without_srand_starts_from_seed_zero() {
+    // The RNG is per thread: each run gets a fresh one, as a new process would.
+    let run = |prog: String| {
+        std::thread::spawn(move || eval(&prog, &[]).get_var("x"))
+            .join()
+            .unwrap()
+    };
+    let prog = r#"BEGIN { x = rand() " " rand() " " rand() }"#;
+    let unseeded = run(prog.to_string());
+    assert_eq!(run(prog.to_string()), unseeded);
+    assert_eq!(run(format!("BEGIN {{ srand(0) }} {}", prog)), unseeded);
+    assert_ne!(run(format!("BEGIN {{ srand(1) }} {}", prog)), unseeded);
+}
+
+#[test]
+fn sample_pattern_is_reproducible() {
+    let prog = r#"BEGIN { srand(7) } sample(0.3) { got = got " " NR } END { n = NR }"#;
+    let lines: Vec<String> = (1..=50).map(|i| i.to_string()).collect();
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    let rt1 = eval(prog, &lines);
+    let rt2 = eval(prog, &lines);
+    assert_eq!(rt1.get_var("got"), rt2.get_var("got"));
+    assert_eq!(rt1.get_var("n"), "50");
+    let picked = rt1.get_var("got").split_whitespace().count();
+    assert_eq!(rt1.get_var("SAMPLED"), picked.to_string());
+    assert!(picked > 0 && picked < 50);
+}
+
+#[test]
+fn every_runs_rules_on_the_same_records_as_nr_mod() {
+    let lines: Vec<String> = (1..=20).map(|i| format!("r{}", i)).collect();
+    let naive = eval(
+        "NR % 3 == 0 { got = got \" \" NR \":\" $0 } END { n = NR }",
+        &lines.iter().map(String::as_str).collect::<Vec<_>>(),
+    );
+    let program = parser::Parser::new(
+        lexer::Lexer::new("{ got = got \" \" NR \":\" $0 } END { n = NR }")
+            .tokenize()
+            .unwrap(),
+    )
+    .parse()
+    .unwrap();
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    exec.set_every(3);
+    for line in &lines {
+        if !exec.every_skip() {
+            exec.run_record(&input::Record {
+                text: line.clone(),
+                fields: None,
+                malformed: None,
+            });
+        }
+    }
+    exec.run_end();
+    drop(exec);
+    assert_eq!(rt.get_var("got"), naive.get_var("got"));
+    assert_eq!(rt.get_var("got"), " 3:r3 6:r6 9:r9 12:r12 15:r15 18:r18");
+    assert_eq!(rt.get_var("n"), "20");
+    assert_eq!(rt.get_var("SKIPPED"), "14");
+}
+
 // ── String builtins ─────────────────────────────────────────────
 
+#[test]
+fn levenshtein_known_distances() {
+    let rt = eval(
+        r#"BEGIN { a = levenshtein("kitten", "sitting"); b = levenshtein("", "abc")
+            c = levenshtein("abc", ""); d = levenshtein("", ""); e = levenshtein("flaw", "lawn") }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("a"), "3");
+    assert_eq!(rt.get_var("b"), "3");
+    assert_eq!(rt.get_var("c"), "3");
+    assert_eq!(rt.get_var("d"), "0");
+    assert_eq!(rt.get_var("e"), "2");
+}
+
+#[test]
+fn levenshtein_counts_unicode_chars() {
+    let rt = eval(
+        r#"BEGIN { a = levenshtein("café", "cafe"); b = levenshtein("日本語", "日本") }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("a"), "1");
+    assert_eq!(rt.get_var("b"), "1");
+}
+
+#[test]
+fn dice_bigram_similarity() {
+    let rt = eval(
+        r#"BEGIN { a = dice("night", "nacht"); b = dice("abc", "abc"); c = dice("ab", "cd"); d = dice("", "x") }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("a"), "0.25");
+    assert_eq!(rt.get_var("b"), "1");
+    assert_eq!(rt.get_var("c"), "0");
+    assert_eq!(rt.get_var("d"), "0");
+}
+
+#[test]
+fn soundex_codes() {
+    let rt = eval(
+        r#"BEGIN { s = soundex("Robert") soundex("Rupert") soundex("Ashcraft") soundex("Tymczak") soundex("Lee") soundex("") }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("s"), "R163R163A261T522L000");
+}
+
+/// Strings built to break naive escaping: quotes of every kind,
+/// backslashes, line breaks, control and NUL characters, entities,
+/// regex syntax and non-ASCII text.
+const NASTY: &[&str] = &[
+    "",
+    "plain",
+    "it's \"quoted\" \\ back\\slash",
+    "line1\nline2\r\n\ttab",
+    "\u{0}nul\u{1}\u{1f}\u{7f}",
+    "a,b;c|d&e<f>g",
+    "&amp; &lt; &#39; &#x41;",
+    "$HOME `id` $(id) ; rm -rf / '\\''",
+    "^.*+?()[]{}|\\$ #&-~",
+    "é 中文 😀 \u{200d} \u{2028}",
+];
+
+#[test]
+fn escape_round_trips_nasty_strings() {
+    use crate::builtins::string::{escape, unescape};
+    for s in NASTY {
+        for mode in ["json", "csv", "regex", "html"] {
+            let e = escape(s, mode).unwrap();
+            assert_eq!(unescape(&e, mode).unwrap(), *s, "{mode}: {s:?} -> {e:?}");
+        }
+        let json = escape(s, "json").unwrap();
+        assert!(
+            !json[1..json.len() - 1].chars().any(|c| c < ' '),
+            "{json:?}"
+        );
+        assert_eq!(crate::builtins::json::call(&[json.clone(), ".".into()]), *s);
+        let html = escape(s, "html").unwrap();
+        assert!(!html.contains(['<', '>', '"', '\'']), "{html:?}");
+        let re = regex::Regex::new(&format!("^{}$", escape(s, "regex").unwrap())).unwrap();
+        assert!(re.is_match(s), "{s:?}");
+    }
+    assert_eq!(escape("a\"b", "csv").unwrap(), "\"a\"\"b\"");
+    assert_eq!(escape("ab", "csv").unwrap(), "ab");
+    assert_eq!(escape("\u{0}", "json").unwrap(), "\"\\u0000\"");
+    assert_eq!(
+        escape("<a href='x'>&", "html").unwrap(),
+        "&lt;a href=&#39;x&#39;&gt;&amp;"
+    );
+    assert_eq!(escape("x", "yaml"), None);
+    assert_eq!(unescape("x", "shell"), None);
+}
+
+#[test]
+fn case_mapping_matches_std_and_keeps_unchanged_strings() {
+    use crate::builtins::string::{lowercase, uppercase};
+    let sample = [
+        "already lower 123",
+        "MiXeD Ascii, Line!",
+        "Straße ﬁne ǅemal",
+        "ΟΔΥΣΣΕΥΣ Σ ΑΣ.",
+        "İstanbul IĞDIR",
+        "Привет МИР ёЁ",
+        "ǆ ǈ Ꭰ ꭰ 𐐀𐐨",
+    ];
+    for s in NASTY.iter().chain(&sample) {
+        let (mut lower, mut upper) = (s.to_string(), s.to_string());
+        lowercase(&mut lower);
+        uppercase(&mut upper);
+        assert_eq!(lower, s.to_lowercase(), "{s:?}");
+        assert_eq!(upper, s.to_uppercase(), "{s:?}");
+    }
+    // Nothing to map, or only ASCII: the same buffer comes back.
+    for s in ["already lower", "MIXED Ascii", "déjà vu 中文 😀"] {
+        let mut owned = s.to_string();
+        let buf = owned.as_ptr();
+        lowercase(&mut owned);
+        assert_eq!(owned.as_ptr(), buf, "{s:?}");
+    }
+    let r = eval(
+        r#"{ a = tolower($0); b = toupper($1) "|" upper("ß") lower() }"#,
+        &["ÀB c"],
+    );
+    assert_eq!(r.get_var("a"), "àb c");
+    assert_eq!(r.get_var("b"), "ÀB|SS");
+}
+
+#[test]
+fn unescape_decodes_what_escape_does_not_produce() {
+    use crate::builtins::string::unescape;
+    let json = |s| unescape(s, "json").unwrap();
+    assert_eq!(json(r#"\ud83d\ude00 \u00E9 \/ \b\f"#), "😀 é / \u{8}\u{c}");
+    // Malformed escapes stay as written; a lone surrogate becomes U+FFFD.
+    assert_eq!(json(r#"\x \u12 \ud800 end\"#), "\\x \\u12 \u{fffd} end\\");
+    let html = |s| unescape(s, "html").unwrap();
+    assert_eq!(
+        html("&lt;&#65;&#x42;&apos;&nbsp;&bogus; &amp"),
+        "<AB'\u{a0}&bogus; &amp"
+    );
+}
+
+#[test]
+fn shell_escape_survives_sh() {
+    for s in NASTY.iter().filter(|s| !s.contains('\u{0}')) {
+        let word = crate::builtins::string::escape(s, "shell").unwrap();
+        let out = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("printf '%s' {word}"))
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&out.stdout), *s, "{word}");
+    }
+    let rt = eval(
+        r#"BEGIN { a = escape("safe-name_1.txt", "shell"); b = escape("", "shell")
+                   c = escape("it's", "shell") }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("a"), "safe-name_1.txt");
+    assert_eq!(rt.get_var("b"), "''");
+    assert_eq!(rt.get_var("c"), r"'it'\''s'");
+}
+
+#[test]
+fn escaped_user_input_matches_only_itself() {
+    let rt = eval(
+        r#"{ pat = escape($1, "regex"); n += ("x" $1 "y" ~ pat) + ("xy" ~ pat)
+             out = out match($2, pat) ":" RLENGTH " " }"#,
+        &["a.b axb-a.b", "(x)* xx(x)*", "[ab] ab[ab]", "^$ $^$"],
+    );
+    assert_eq!(rt.get_var("n"), "4");
+    assert_eq!(rt.get_var("out"), "5:3 3:4 3:4 2:2 ");
+}
+
+#[test]
+fn fuzzy_join_picks_closest_name() {
+    let rt = eval(
+        r#"{ split("jonathan,margaret,elizabeth", names, ",")
+            best = ""; bd = 1e9
+            for (i in names) { d = levenshtein($1, names[i]); if (d < bd) { bd = d; best = names[i] } }
+            out = out $1 ":" best ":" bd " " }"#,
+        &["jonathon", "margret"],
+    );
+    assert_eq!(rt.get_var("out"), "jonathon:jonathan:1 margret:margaret:1 ");
+}
+
 #[test]
 fn string_trim() {
     let rt = eval(r#"BEGIN { x = trim("  hello  ") }"#, &[]);
@@ -1594,6 +2323,38 @@ fn typeof_values() {
     assert_eq!(rt.get_var("tu"), "uninitialized");
 }
 
+#[test]
+fn vars_and_arrays_list_defined_names_in_order() {
+    let rt = eval(
+        r#"{ n++; s = $1; z = 0 }
+           END { b["k"]; a[1]; a[2]
+             nv = vars(v); na = arrays(c); vars(w, 1)
+             for (k in v) vs = vs k "=" v[k] ";"
+             for (k in c) cs = cs k "=" c[k] ";" }"#,
+        &["x y", "p q"],
+    );
+    assert_eq!(rt.get_var("vs"), "n=2;s=p;z=0;");
+    assert_eq!(rt.get_var("nv"), "3");
+    assert!(!rt.array_has_key("v", "NR") && !rt.array_has_key("v", "NF"));
+    assert_eq!(rt.get_var("cs"), "a=2;b=1;v=3;");
+    assert_eq!(rt.get_var("na"), "3");
+    assert_eq!(rt.get_array("w", "NR"), "2");
+    assert_eq!(rt.get_array("w", "n"), "2");
+}
+
+#[test]
+fn vars_rejects_a_scalar_and_skips_its_own_output() {
+    let rt = eval(
+        r#"function list(p, out) { n = vars(out); for (k in out) s = s k "," }
+           BEGIN { x = 1; r = vars(x); q[1]; list(q, res) }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("r"), "0");
+    assert_eq!(rt.get_var("x"), "1", "a scalar stays a scalar");
+    assert!(!rt.has_array("x"));
+    assert_eq!(rt.get_var("s"), "r,x,", "no output or array parameter");
+}
+
 // ── asort / asorti ──────────────────────────────────────────────
 
 #[test]
@@ -1616,26 +2377,148 @@ fn asorti_by_keys() {
     assert_eq!(rt.get_var("r"), "a,b,c");
 }
 
-// ── match with captures ─────────────────────────────────────────
+fn sorted_files(mode: &str) -> (String, String) {
+    let prog = format!(
+        r#"BEGIN {{ a[1]="file10"; a[2]="File2"; a[3]="file1"
+            asort(a, b, "{mode}"); r = b[1] "," b[2] "," b[3]
+            k["file10"]=1; k["File2"]=1; k["file1"]=1; ORS=","; ks = keys(k, "{mode}") }}"#
+    );
+    let rt = eval(&prog, &[]);
+    (rt.get_var("r"), rt.get_var("ks"))
+}
 
 #[test]
-fn match_with_capture_groups() {
+fn asort_and_keys_sort_modes() {
+    for (mode, want) in [
+        ("num", "File2,file1,file10"),
+        ("str", "File2,file1,file10"),
+        ("nat", "File2,file1,file10"),
+        ("ci", "file1,file10,File2"),
+        ("nat+ci", "file1,File2,file10"),
+    ] {
+        let (r, ks) = sorted_files(mode);
+        assert_eq!(r, want, "asort {mode}");
+        assert_eq!(ks, want, "keys {mode}");
+    }
+}
+
+#[test]
+fn asorti_natural_without_dest() {
     let rt = eval(
-        r#"BEGIN { s = "2025-01-15"; match(s, "([0-9]+)-([0-9]+)-([0-9]+)", cap); y = cap[1]; m = cap[2]; d = cap[3] }"#,
+        r#"BEGIN { a["v1.10"]=1; a["v1.9"]=1; a["v1.09"]=1; n = asorti(a, "nat"); r = a[1] "," a[2] "," a[3] }"#,
         &[],
     );
-    assert_eq!(rt.get_var("y"), "2025");
-    assert_eq!(rt.get_var("m"), "01");
-    assert_eq!(rt.get_var("d"), "15");
+    assert_eq!(rt.get_var("r"), "v1.9,v1.09,v1.10");
+    let rt = eval(
+        r#"BEGIN { a[1]=10; a[2]=9; n = asort(a, "num"); r = n ":" a[1] }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("r"), "2:9");
 }
 
 #[test]
-fn match_captures_full_match() {
+fn asort_dest_preserves_source() {
     let rt = eval(
-        r#"BEGIN { match("hello world", "(hell)o (w)", cap); x = cap[0] }"#,
+        r#"BEGIN { s["x"]="b"; s["y"]="a"; n = asort(s, d); r = d[1] d[2]; keep = s["x"] s["y"] length(s) }"#,
         &[],
     );
-    assert_eq!(rt.get_var("x"), "hello w");
+    assert_eq!(rt.get_var("n"), "2");
+    assert_eq!(rt.get_var("r"), "ab");
+    assert_eq!(rt.get_var("keep"), "ba2");
+}
+
+#[test]
+fn asort_user_comparator_by_length() {
+    let rt = eval(
+        r#"function bylen(a, b) { return length(a) - length(b) }
+        BEGIN { s[1]="ccc"; s[2]="a"; s[3]="bb"; asort(s, d, "bylen"); r = d[1] "," d[2] "," d[3]
+            asorti(s, k, "bylen"); ki = k[1] }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("r"), "a,bb,ccc");
+    assert_eq!(rt.get_var("ki"), "1");
+}
+
+#[test]
+fn asort_inside_comparator_is_refused() {
+    let rt = eval(
+        r#"function bad(a, b) { inner = asort(z); return b - a }
+        BEGIN { z[1]=1; z[2]=3; z[3]=2; asort(z, d, "bad"); r = d[1] d[2] d[3] }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("inner"), "0");
+    assert_eq!(rt.get_var("r"), "321");
+}
+
+// ── match with captures ─────────────────────────────────────────
+
+#[test]
+fn match_with_capture_groups() {
+    let rt = eval(
+        r#"BEGIN { s = "2025-01-15"; match(s, "([0-9]+)-([0-9]+)-([0-9]+)", cap); y = cap[1]; m = cap[2]; d = cap[3] }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("y"), "2025");
+    assert_eq!(rt.get_var("m"), "01");
+    assert_eq!(rt.get_var("d"), "15");
+}
+
+#[test]
+fn match_captures_full_match() {
+    let rt = eval(
+        r#"BEGIN { match("hello world", "(hell)o (w)", cap); x = cap[0] }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("x"), "hello w");
+}
+
+// ── matchall ────────────────────────────────────────────────────
+
+#[test]
+fn matchall_adjacent_tokens() {
+    let rt = eval(
+        r#"BEGIN { n = matchall("aaabbb", /a|b/, m); s = m[1] m[4] m[6]; p = m[6, "start"] }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("n"), "6");
+    assert_eq!(rt.get_var("s"), "abb");
+    assert_eq!(rt.get_var("p"), "6");
+}
+
+#[test]
+fn matchall_unicode_offsets() {
+    let rt = eval(
+        r#"BEGIN { n = matchall("日本 café 東京", /[^ ]+/, m); p = m[2, "start"] "," m[2, "length"] "," m[3, "start"] }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("n"), "3");
+    assert_eq!(rt.get_var("p"), "4,4,9");
+}
+
+#[test]
+fn matchall_capture_groups() {
+    let rt = eval(
+        r#"BEGIN { n = matchall("a=1, b=22", /([a-z])=([0-9]+)/, m); s = m[2] "|" m[2, 1] "|" m[2, 2] }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("n"), "2");
+    assert_eq!(rt.get_var("s"), "b=22|b|22");
+}
+
+#[test]
+fn matchall_zero_length_matches_advance() {
+    let rt = eval(r#"BEGIN { n = matchall("abc", /x*/, m) }"#, &[]);
+    assert_eq!(rt.get_var("n"), "4");
+}
+
+#[test]
+fn matchall_invalid_regex_returns_zero() {
+    let rt = eval(
+        r#"BEGIN { m["old"] = 1; n = matchall("abc", "(", m); k = length(m) }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("n"), "0");
+    assert_eq!(rt.get_var("k"), "0");
 }
 
 // ── Quoted/string field access ($"name") ────────────────────────
@@ -1680,6 +2563,29 @@ fn quoted_field_filter() {
     assert_eq!(rt.get_var("count"), "2");
 }
 
+#[test]
+fn has_checks_header_and_record_length() {
+    let rt = eval_with_header(
+        r#"BEGIN { early = hdrlen() }
+           { r = r has("name") has("city") has("zip") has("") has("nosuch") "," }
+           END { n = hdrlen() }"#,
+        ",",
+        &[
+            "name,age,city",
+            "Alice,30,NYC",
+            "Bob,41",
+            "Carol",
+            "Dan,52,LA,extra",
+        ],
+    );
+    assert_eq!(rt.get_var("r"), "11000,10000,10000,11000,");
+    assert_eq!(rt.get_var("early"), "0");
+    assert_eq!(rt.get_var("n"), "3");
+    // Without a header nothing is named.
+    let rt = eval(r#"{ r = has("1") has("a") hdrlen() }"#, &["a b"]);
+    assert_eq!(rt.get_var("r"), "000");
+}
+
 #[test]
 fn numeric_string_field_still_works() {
     let rt = eval(r#"{ x = $"2" }"#, &["hello world"]);
@@ -1766,6 +2672,65 @@ fn printf_string_precision() {
     assert_eq!(r, "hello");
 }
 
+#[test]
+fn printf_apostrophe_groups_thousands() {
+    let f = |fmt: &str, n: &str| crate::builtins::format_printf(fmt, &[n.into()]);
+    assert_eq!(f("%'d", "1234567"), "1,234,567");
+    assert_eq!(f("%'d", "-1234567"), "-1,234,567");
+    assert_eq!(f("%'d", "-123"), "-123");
+    assert_eq!(f("%'+d", "1000"), "+1,000");
+    assert_eq!(f("%'.2f", "-1234567.891"), "-1,234,567.89");
+    assert_eq!(f("%'.3e", "1234567"), "1.235e6");
+    // Width counts the separators; zero padding goes in front, ungrouped.
+    assert_eq!(f("%'12d|", "-1234567"), "  -1,234,567|");
+    assert_eq!(f("%'-10d|", "1234"), "1,234     |");
+    assert_eq!(f("%'012d", "-1234567"), "-001,234,567");
+    assert_eq!(f("%'010.2f", "1234.5"), "001,234.50");
+    assert_eq!(f("%'010.2f", "-1234.5"), "-01,234.50");
+}
+
+#[test]
+fn groupsep_and_decimalsep_change_printf_output_only() {
+    let rt = eval(
+        r#"BEGIN { GROUPSEP = "."; DECIMALSEP = ","
+                   a = sprintf("%'d %'.2f %.1f %.1e", -1234567, 1234567.891, 2.5, 25)
+                   b = "1.5" + 1; c = 2.5 ""
+                   GROUPSEP = ""; d = sprintf("%'d", 1234567)
+                   GROUPSEP = "\u202f"; e = sprintf("%'8d|", 12345) }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("a"), "-1.234.567 1.234.567,89 2,5 2,5e1");
+    assert_eq!(rt.get_var("b"), "2.5");
+    assert_eq!(rt.get_var("c"), "2.5");
+    assert_eq!(rt.get_var("d"), "1234567");
+    assert_eq!(rt.get_var("e"), "  12\u{202f}345|");
+    let rt = eval("BEGIN { g = GROUPSEP; d = DECIMALSEP }", &[]);
+    assert_eq!((rt.get_var("g"), rt.get_var("d")), (",".into(), ".".into()));
+}
+
+#[test]
+fn printf_argument_count_is_checked_per_conversion() {
+    use crate::builtins::printf::{ArgMismatch, Format};
+    let missing = |conversion, spec: &str| {
+        Some(ArgMismatch::Missing {
+            conversion,
+            spec: spec.to_string(),
+        })
+    };
+    assert_eq!(Format::parse("%s %s").check_args(1), missing(2, "%s"));
+    assert_eq!(
+        Format::parse("%d%%").check_args(3),
+        Some(ArgMismatch::Extra(2))
+    );
+    // `*` takes an argument of its own; `%%` and unknown letters take none.
+    assert_eq!(Format::parse("%*.*f").check_args(2), missing(1, "%*.*f"));
+    assert_eq!(Format::parse("%5%|%-*s|%z").check_args(2), None);
+    assert_eq!(Format::parse("plain").check_args(0), None);
+    // Missing arguments still format as "" and 0.
+    let r = crate::builtins::format_printf("[%s|%d]", &["x".into()]);
+    assert_eq!(r, "[x|0]");
+}
+
 // ── Statistical builtins ────────────────────────────────────────
 
 #[test]
@@ -1945,6 +2910,84 @@ fn stats_hist_plotbox_chained() {
     );
 }
 
+#[test]
+fn stats_plot_log_scale() {
+    let rt = eval(
+        r##"{ a[NR] = $1 } END { s = plot(a, 10, "#", -1, "", "log"); t = plot(a, "width=10,char=#,log") }"##,
+        &["1", "9", "99"],
+    );
+    let s = rt.get_var("s");
+    let lines: Vec<&str> = s.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "1 | ##         1 ",
+            "2 | #####      9 ",
+            "3 | ########## 99"
+        ]
+    );
+    assert_eq!(
+        rt.get_var("t"),
+        s,
+        "options string should match positional args"
+    );
+}
+
+#[test]
+fn stats_vplot_columns() {
+    let rt = eval(
+        r#"{ a[NR] = $1 } END { s = vplot(a, 4); a[3] = 3; t = vplot(a, 2) }"#,
+        &["1", "2", "4"],
+    );
+    let s = rt.get_var("s");
+    let lines: Vec<&str> = s.lines().collect();
+    assert_eq!(
+        lines,
+        ["4 ┤  █", "  │  █", "  │ ██", "  │███", "  └───", "   1 3"]
+    );
+    let t = rt.get_var("t");
+    let lines: Vec<&str> = t.lines().collect();
+    assert_eq!(lines, ["3 ┤ ▃█", "  │▅██", "  └───", "   1 3"]);
+}
+
+#[test]
+fn stats_heatmap_shades() {
+    let rt = eval(
+        r#"BEGIN {
+            a[1, "a"] = 8; a[1, "b"] = 4; a[1, "c"] = 0; a[1, "d"] = 2
+            a[2, "a"] = 6; a[2, "b"] = 1; a[2, "d"] = 8
+            a[10, "a"] = 2; a[10, "b"] = 6; a[10, "c"] = 4; a[10, "d"] = 1
+            s = heatmap(a); t = heatmap(a, "max=16,transpose")
+        }"#,
+        &[],
+    );
+    let s = rt.get_var("s");
+    let lines: Vec<&str> = s.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            " 1 ██ ▒▒    ░░",
+            " 2 ▓▓ ░░    ██",
+            "10 ░░ ▓▓ ▒▒ ░░",
+            "   a  b  c  d",
+            "   0 ░▒▓█ 8",
+        ]
+    );
+    let t = rt.get_var("t");
+    let lines: Vec<&str> = t.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "a ▒▒ ▒▒ ░░",
+            "b ░░ ░░ ▒▒",
+            "c       ░░",
+            "d ░░ ▒▒ ░░",
+            "  1  2  10",
+            "  0 ░▒▓█ 16",
+        ]
+    );
+}
+
 #[test]
 fn stats_min_array() {
     let rt = eval(
@@ -2021,6 +3064,17 @@ fn sniff_csv() {
     assert_eq!(schema.types[2], crate::describe::ColType::Float);
 }
 
+#[test]
+fn sniff_skips_comment_lines() {
+    let data = "# generated by the lab\n# units: mm\nid,len\n1,2.5\n2,3.0\n";
+    let mut reader = std::io::BufReader::new(data.as_bytes());
+    let schema = crate::describe::sniff_with(&mut reader, Some('#'));
+    assert_eq!(schema.format, crate::describe::Format::Csv);
+    assert_eq!(schema.columns, vec!["id", "len"]);
+    assert_eq!(schema.types[1], crate::describe::ColType::Float);
+    assert_eq!(schema.total_rows, 2);
+}
+
 #[test]
 fn sniff_tsv() {
     let data = "host\tstatus\tlatency\nweb1\t200\t12.5\nweb2\t500\t45.1\n";
@@ -2054,6 +3108,43 @@ fn sniff_whitespace() {
     assert!(!schema.has_header);
 }
 
+#[test]
+fn validate_reports_type_flips() {
+    use crate::describe::{ColType, parse_schema, schema_json, validate};
+    let check = |data: &str, header, decl: Option<&[(String, ColType)]>| {
+        let mut reader = std::io::BufReader::new(data.as_bytes());
+        let mut csv = crate::input::csv::CsvReader::comma();
+        validate(&mut reader, &mut csv, "d.csv", header, decl).unwrap()
+    };
+    let data = "id,price\n1,2.5\n2,3\nx,4.0\n4,\n5,cheap\n";
+    let (n, report) = check(data, true, None);
+    assert_eq!(n, 0, "bad rows past the sample window: {report:?}");
+
+    let decl = parse_schema(r#"{ "id": "int", "price": "float" }"#).unwrap();
+    assert_eq!(decl[0], ("id".to_string(), ColType::Int));
+    let (n, report) = check(data, true, Some(&decl));
+    assert_eq!(n, 2);
+    assert_eq!(
+        report,
+        vec![
+            "d.csv:4: id: expected int, got \"x\"",
+            "d.csv:6: price: expected float, got \"cheap\"",
+        ]
+    );
+
+    // Quoted newlines stay in one field; without -H the first record is data.
+    let decl = parse_schema(r#"{ "$1": "int" }"#).unwrap();
+    let (n, report) = check("1,\"two\nlines\"\n2,b\nx,c\n", false, Some(&decl));
+    assert_eq!(n, 1);
+    assert_eq!(report, ["d.csv:3: $1: expected int, got \"x\""]);
+    assert!(parse_schema(r#"{"id": "date"}"#).is_err());
+
+    let mut reader = std::io::BufReader::new("a,b\n1,x\n".as_bytes());
+    let schema = crate::describe::sniff(&mut reader);
+    let round = parse_schema(&schema_json(&schema)).unwrap();
+    assert_eq!(round[1], ("b".to_string(), ColType::String));
+}
+
 #[test]
 fn sniff_csv_no_header() {
     let data = "10,20,30\n40,50,60\n70,80,90\n";
@@ -2207,6 +3298,50 @@ fn edge_csv_file_reads_correctly() {
     assert!(ids.contains(&"15"));
 }
 
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet_metadata_comes_from_the_footer() {
+    use crate::describe::ColType;
+    let fixture = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join("sales.parquet");
+    let meta = crate::input::parquet_reader::read_parquet_metadata(fixture.to_str().unwrap())
+        .expect("read sales.parquet footer");
+    assert_eq!((meta.rows, meta.row_groups), (5, 2));
+    assert!(meta.compressed > 0 && meta.uncompressed > 0);
+    let want = [
+        ("id", ColType::Int, "INT64", "1", "5"),
+        (
+            "region",
+            ColType::String,
+            "BYTE_ARRAY string",
+            "east",
+            "south",
+        ),
+        ("amount", ColType::Float, "DOUBLE", "3", "40.25"),
+        (
+            "day",
+            ColType::String,
+            "INT32 date",
+            "2024-01-01",
+            "2024-03-08",
+        ),
+    ];
+    assert_eq!(meta.columns.len(), want.len());
+    for (col, (name, col_type, stored, min, max)) in meta.columns.iter().zip(want) {
+        let got_stored = match &col.logical {
+            Some(l) => format!("{} {}", col.physical, l),
+            None => col.physical.clone(),
+        };
+        assert_eq!((col.name.as_str(), col.col_type), (name, col_type));
+        assert_eq!(got_stored, stored);
+        assert_eq!(
+            (col.min.as_deref(), col.max.as_deref()),
+            (Some(min), Some(max))
+        );
+    }
+}
+
 // --- keys(), vals(), print arr ---
 
 #[test]
@@ -2509,148 +3644,429 @@ fn sample_reduces_array() {
     assert_eq!(rt.get_var("result"), "5");
 }
 
-// --- lpad, rpad ---
-
 #[test]
-fn lpad_pads_left() {
-    let rt = eval(r#"BEGIN { result = lpad("hi", 6, ".") }"#, &[]);
-    assert_eq!(rt.get_var("result"), "....hi");
+fn reservoir_fixed_seed_is_deterministic() {
+    let prog = r#"BEGIN { srand(9); for (i = 1; i <= 500; i++) n = reservoir(r, i, 4)
+        out = r[1] "," r[2] "," r[3] "," r[4] }"#;
+    let rt1 = eval(prog, &[]);
+    let rt2 = eval(prog, &[]);
+    assert_eq!(rt1.get_var("n"), "500");
+    assert_eq!(rt1.get_var("out"), rt2.get_var("out"));
 }
 
 #[test]
-fn rpad_pads_right() {
-    let rt = eval(r#"BEGIN { result = rpad("hi", 6) }"#, &[]);
-    assert_eq!(rt.get_var("result"), "hi    ");
+fn reservoir_short_stream_and_independent_arrays() {
+    let rt = eval(
+        r#"{ reservoir(a, $0, 5); reservoir(b, $0, 1) } END { na = length(a); nb = length(b); s = a[1] a[2] a[3] }"#,
+        &["x", "y", "z"],
+    );
+    assert_eq!(rt.get_var("na"), "3");
+    assert_eq!(rt.get_var("nb"), "1");
+    assert_eq!(rt.get_var("s"), "xyz");
 }
 
 #[test]
-fn lpad_no_truncate() {
-    let rt = eval(r#"BEGIN { result = lpad("hello", 3) }"#, &[]);
-    assert_eq!(rt.get_var("result"), "hello");
+fn reservoir_is_roughly_uniform() {
+    // 2000 trials of 3-of-10: each value expected 600 times.
+    let rt = eval(
+        r#"BEGIN { srand(1)
+            for (t = 1; t <= 2000; t++) {
+                delete r
+                for (i = 1; i <= 10; i++) reservoir(r, i, 3)
+                for (j in r) cnt[r[j]]++
+            }
+            lo = 2000; hi = 0
+            for (v in cnt) { if (cnt[v] < lo) lo = cnt[v]; if (cnt[v] > hi) hi = cnt[v] }
+        }"#,
+        &[],
+    );
+    let lo: f64 = rt.get_var("lo").parse().unwrap();
+    let hi: f64 = rt.get_var("hi").parse().unwrap();
+    assert!(lo > 480.0 && hi < 720.0, "lo={lo} hi={hi}");
 }
 
-// --- shuffle ---
+// --- uniqc, uniqkeys ---
 
 #[test]
-fn shuffle_preserves_elements() {
+fn uniqc_counts_match_manual_tally() {
     let rt = eval(
-        r#"BEGIN { srand(1); a[1]="a"; a[2]="b"; a[3]="c"; shuf(a); result=length(a) }"#,
-        &[],
+        r#"{ a[NR] = $1; tally[$1]++ }
+        END { n = uniqc(a, c); ok = 1
+            for (v in tally) if (c[v] != tally[v]) ok = 0
+            for (v in c) if (!(v in tally)) ok = 0
+            s = a[1] "|" a[2] "|" a[3] "|" a[4] }"#,
+        &["1", "1.0", "x", "1", "x", "01", "x"],
     );
-    assert_eq!(rt.get_var("result"), "3");
+    assert_eq!(rt.get_var("n"), "4");
+    assert_eq!(rt.get_var("ok"), "1");
+    assert_eq!(rt.get_var("s"), "1|1.0|x|01");
 }
 
-// --- rev() 0-arg: string reversal of $0 ---
-
 #[test]
-fn rev_no_args_reverses_string() {
+fn uniqc_string_forms_are_distinct() {
     let rt = eval(
-        r#"{ r = rev() }"#,
-        &["hello"],
+        r#"BEGIN { a[1] = "1"; a[2] = "1.0"; a[3] = "1"; c["stale"] = 5
+            n = uniqc(a, c); one = c["1"]; onep = c["1.0"]; stale = ("stale" in c) }"#,
+        &[],
     );
-    assert_eq!(rt.get_var("r"), "olleh");
+    assert_eq!(rt.get_var("n"), "2");
+    assert_eq!(rt.get_var("one"), "2");
+    assert_eq!(rt.get_var("onep"), "1");
+    assert_eq!(rt.get_var("stale"), "0");
 }
 
-// --- flip (fields) ---
-
 #[test]
-fn flip_reverses_fields() {
+fn uniqkeys_drops_case_duplicates() {
     let rt = eval(
-        r#"{ flip(); r = $1 "," $2 "," $3 }"#,
-        &["a b c"],
+        r#"BEGIN { h["Name"] = "first"; h["name"] = "second"; h["NAME"] = "third"; h["age"] = 3
+            n = uniqkeys(h); v = h["NAME"]; k = ("Name" in h) ("name" in h) }"#,
+        &[],
     );
-    assert_eq!(rt.get_var("r"), "c,b,a");
+    assert_eq!(rt.get_var("n"), "2");
+    assert_eq!(rt.get_var("v"), "third");
+    assert_eq!(rt.get_var("k"), "00");
 }
 
+// --- ERROR block ---
+
 #[test]
-fn flip_returns_new_record() {
+fn error_block_parses_and_bare_error_stays_a_variable() {
+    let src = "ERROR { print ERRMSG } { ERROR = 1 } END { x = ERROR }";
+    let tokens = lexer::Lexer::new(src).tokenize().unwrap();
+    let prog = parser::Parser::new(tokens).parse().unwrap();
+    assert!(prog.error.is_some());
+    assert_eq!(prog.rules.len(), 1);
+    let rt = eval("{ ERROR = $1 } END { x = ERROR }", &["7"]);
+    assert_eq!(rt.get_var("x"), "7");
+}
+
+// --- special output names ---
+
+#[test]
+fn special_stream_names_close_as_noop() {
     let rt = eval(
-        r#"{ r = flip() }"#,
-        &["a b c"],
+        r#"BEGIN { printf "" > "/dev/stderr"; a = close("/dev/stderr"); b = close("/dev/fd/2")
+            c = close("/dev/stdout"); d = close("/dev/stdin"); e = close("/no/such/handle") }"#,
+        &[],
     );
-    assert_eq!(rt.get_var("r"), "c b a");
+    assert_eq!(rt.get_var("a"), "0");
+    assert_eq!(rt.get_var("b"), "0");
+    assert_eq!(rt.get_var("c"), "0");
+    assert_eq!(rt.get_var("d"), "0");
+    assert_eq!(rt.get_var("e"), "-1");
 }
 
-// --- rev (array) ---
+// --- pivot ---
 
 #[test]
-fn rev_reverses_array() {
+fn pivot_sparse_matrix_with_text_cells() {
     let rt = eval(
-        r#"BEGIN { a[1]="a"; a[2]="b"; a[3]="c"; rev(a); result=a[1] "," a[2] "," a[3] }"#,
-        &[],
+        r#"{ a[$1, $2] = $3 }
+        END { n = pivot(a, t); h = t[1]; r1 = t[2]; r2 = t[3]; r3 = t[4] }"#,
+        &[
+            "east jan 5",
+            "east feb 12",
+            "east mar 9",
+            "west jan 7",
+            "west mar n/a",
+            "north feb 3",
+            "north jan 40",
+            "north mar 1",
+        ],
     );
-    assert_eq!(rt.get_var("result"), "c,b,a");
+    assert_eq!(rt.get_var("n"), "4");
+    assert_eq!(rt.get_var("h"), "       feb  jan  mar");
+    assert_eq!(rt.get_var("r1"), "east    12    5  9");
+    assert_eq!(rt.get_var("r2"), "north    3   40  1");
+    assert_eq!(rt.get_var("r3"), "west          7  n/a");
 }
 
 #[test]
-fn rev_returns_array_name() {
+fn pivot_fill_and_transpose() {
     let rt = eval(
-        r#"BEGIN { a[1]="x"; a[2]="y"; n=rev(a); result=a[1] "," a[2] }"#,
+        r#"BEGIN { a[1, "x"] = 2; a[2, "y"] = 3
+            n = pivot(a, t, "fill=0,transpose"); h = t[1]; x = t[2]; y = t[3]
+            d["old"] = 1; e = pivot(empty, d); dl = length(d) }"#,
         &[],
     );
-    assert_eq!(rt.get_var("n"), "a"); // returns array name for chaining
-    assert_eq!(rt.get_var("result"), "y,x");
+    assert_eq!(rt.get_var("n"), "3");
+    assert_eq!(rt.get_var("h"), "   1  2");
+    assert_eq!(rt.get_var("x"), "x  2  0");
+    assert_eq!(rt.get_var("y"), "y  0  3");
+    assert_eq!(rt.get_var("e"), "0");
+    assert_eq!(rt.get_var("dl"), "0");
 }
 
-// --- slurp ---
+// --- copy, aempty, amerge ---
 
 #[test]
-fn slurp_into_string() {
+fn copy_replaces_larger_destination() {
     let rt = eval(
-        r#"BEGIN { s = slurp("/etc/shells"); result = (length(s) > 0) ? "ok" : "empty" }"#,
+        r#"BEGIN { for (i = 1; i <= 5; i++) d[i] = "old" i
+            s["x"] = 1; s["y"] = 2
+            n = copy(d, s); len = length(d); has = (3 in d); v = d["x"] d["y"]
+            s["x"] = 9; after = d["x"] }"#,
         &[],
     );
-    assert_eq!(rt.get_var("result"), "ok");
+    assert_eq!(rt.get_var("n"), "2");
+    assert_eq!(rt.get_var("len"), "2");
+    assert_eq!(rt.get_var("has"), "0");
+    assert_eq!(rt.get_var("v"), "12");
+    assert_eq!(rt.get_var("after"), "1");
 }
 
 #[test]
-fn slurp_into_array() {
+fn aempty_does_not_create_array() {
     let rt = eval(
-        r#"BEGIN { n = slurp("/etc/shells", a); result = (n > 0) ? "ok" : "empty" }"#,
+        r#"BEGIN { e1 = aempty(never); t = typeof(never)
+            a[1] = ""; e2 = aempty(a); delete a; e3 = aempty(a) }"#,
         &[],
     );
-    assert_eq!(rt.get_var("result"), "ok");
+    assert_eq!(rt.get_var("e1"), "1");
+    assert_eq!(rt.get_var("t"), "uninitialized");
+    assert_eq!(rt.get_var("e2"), "0");
+    assert_eq!(rt.get_var("e3"), "1");
 }
 
-// --- join defaults to OFS ---
-
 #[test]
-fn join_defaults_to_ofs() {
+fn amerge_clobber_flag_and_self_merge() {
     let rt = eval(
-        r#"BEGIN { OFS=","; a[1]="x"; a[2]="y"; a[3]="z"; result=join(a) }"#,
+        r#"BEGIN { a["k"] = 1; a["m"] = 2; b["k"] = 10; b["n"] = 20
+            c1 = amerge(a, b, 0); keep = a["k"]
+            c2 = amerge(a, b); over = a["k"]
+            c3 = amerge(a, a); len = length(a) }"#,
         &[],
     );
-    assert_eq!(rt.get_var("result"), "x,y,z");
+    assert_eq!(rt.get_var("c1"), "1");
+    assert_eq!(rt.get_var("keep"), "1");
+    assert_eq!(rt.get_var("c2"), "2");
+    assert_eq!(rt.get_var("over"), "10");
+    assert_eq!(rt.get_var("c3"), "0");
+    assert_eq!(rt.get_var("len"), "3");
 }
 
-// --- diagnostics: clock, start, elapsed ---
+// --- lpad, rpad ---
 
 #[test]
-fn clk_returns_positive() {
-    let rt = eval(r#"BEGIN { result = (clk() >= 0) ? "ok" : "bad" }"#, &[]);
-    assert_eq!(rt.get_var("result"), "ok");
+fn lpad_pads_left() {
+    let rt = eval(r#"BEGIN { result = lpad("hi", 6, ".") }"#, &[]);
+    assert_eq!(rt.get_var("result"), "....hi");
 }
 
 #[test]
-fn tic_toc_basic() {
-    let rt = eval(
-        r#"BEGIN { tic("t"); for(i=0;i<10000;i++){} result = (toc("t") >= 0) ? "ok" : "bad" }"#,
-        &[],
-    );
-    assert_eq!(rt.get_var("result"), "ok");
+fn rpad_pads_right() {
+    let rt = eval(r#"BEGIN { result = rpad("hi", 6) }"#, &[]);
+    assert_eq!(rt.get_var("result"), "hi    ");
 }
 
 #[test]
-fn toc_without_tic_uses_epoch() {
-    let rt = eval(r#"BEGIN { result = (toc() >= 0) ? "ok" : "bad" }"#, &[]);
-    assert_eq!(rt.get_var("result"), "ok");
+fn lpad_no_truncate() {
+    let rt = eval(r#"BEGIN { result = lpad("hello", 3) }"#, &[]);
+    assert_eq!(rt.get_var("result"), "hello");
 }
 
 #[test]
-fn multiple_timers() {
-    let rt = eval(
-        r#"BEGIN {
-            tic("a"); tic("b")
+fn padding_counts_display_columns() {
+    let prog = r#"BEGIN {
+        a = lpad("ab", 4, "."); c = lpad("日本", 6, ".")
+        m = rpad("é", 3, "."); z = rpad("👨‍👩‍👧", 4, ".")
+        p = sprintf("[%5s][%-4s][%.3s]", "日本", "👨‍👩‍👧", "日本語")
+    }"#;
+    let rt = eval(prog, &[]);
+    assert_eq!(rt.get_var("a"), "..ab");
+    assert_eq!(rt.get_var("c"), "..日本");
+    assert_eq!(rt.get_var("m"), "e\u{301}..");
+    assert_eq!(rt.get_var("z"), "👨‍👩‍👧..");
+    assert_eq!(rt.get_var("p"), "[ 日本][👨‍👩‍👧  ][日]");
+}
+
+#[test]
+fn widthmode_chars_counts_code_points() {
+    let prog = r#"BEGIN {
+        WIDTHMODE = "chars"
+        c = lpad("日本", 4, "."); p = sprintf("[%-3s][%.1s]", "é", "日本")
+    }"#;
+    let rt = eval(prog, &[]);
+    assert_eq!(rt.get_var("c"), "..日本");
+    assert_eq!(rt.get_var("p"), "[e\u{301} ][日]");
+}
+
+#[test]
+fn plot_labels_align_by_display_width() {
+    let prog = r#"BEGIN { h["日本"] = 2; h["ab"] = 1; out = plot(h, 4) }"#;
+    let rt = eval(prog, &[]);
+    let out = rt.get_var("out");
+    let cols: Vec<usize> = out
+        .lines()
+        .map(|l| crate::builtins::width::text_width(l.split('|').next().unwrap()))
+        .collect();
+    assert_eq!(cols, [5, 5], "{out}");
+}
+
+// --- shuffle ---
+
+#[test]
+fn shuffle_preserves_elements() {
+    let rt = eval(
+        r#"BEGIN { srand(1); a[1]="a"; a[2]="b"; a[3]="c"; shuf(a); result=length(a) }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("result"), "3");
+}
+
+// --- rev() 0-arg: string reversal of $0 ---
+
+#[test]
+fn rev_no_args_reverses_string() {
+    let rt = eval(
+        r#"{ r = rev() }"#,
+        &["hello"],
+    );
+    assert_eq!(rt.get_var("r"), "olleh");
+}
+
+// --- flip (fields) ---
+
+#[test]
+fn flip_reverses_fields() {
+    let rt = eval(
+        r#"{ flip(); r = $1 "," $2 "," $3 }"#,
+        &["a b c"],
+    );
+    assert_eq!(rt.get_var("r"), "c,b,a");
+}
+
+#[test]
+fn flip_returns_new_record() {
+    let rt = eval(
+        r#"{ r = flip() }"#,
+        &["a b c"],
+    );
+    assert_eq!(rt.get_var("r"), "c b a");
+}
+
+// --- fields ---
+
+#[test]
+fn fields_copies_the_record_into_an_array() {
+    let rt = eval(
+        r#"{ n = fields(f); r = join(f, "|"); m = mean(f) }"#,
+        &["3 4 5"],
+    );
+    assert_eq!(rt.get_var("n"), "3");
+    assert_eq!(rt.get_var("r"), "3|4|5");
+    assert_eq!(rt.get_var("m"), "4");
+}
+
+#[test]
+fn fields_clamps_its_bounds_to_the_record() {
+    let rt = eval(
+        r#"{ a = fields(f, 2, 3) ":" join(f, ",")
+             b = fields(f, -5, 99) ":" join(f, ",")
+             c = fields(f, 3) ":" join(f, ",")
+             d = fields(f, 4, 2) ":" length(f) }"#,
+        &["a b c d"],
+    );
+    assert_eq!(rt.get_var("a"), "2:b,c");
+    assert_eq!(rt.get_var("b"), "4:a,b,c,d");
+    assert_eq!(rt.get_var("c"), "2:c,d");
+    assert_eq!(rt.get_var("d"), "0:0");
+}
+
+#[test]
+fn fields_of_an_empty_record_clears_the_array() {
+    let rt = eval(r#"{ f["old"] = 1; n = fields(f); len = length(f) }"#, &[""]);
+    assert_eq!(rt.get_var("n"), "0");
+    assert_eq!(rt.get_var("len"), "0");
+}
+
+#[test]
+fn fields_sees_fields_assigned_past_nf() {
+    let rt = eval(r#"{ $5 = "e"; n = fields(f); r = join(f, ",") }"#, &["a b"]);
+    assert_eq!(rt.get_var("n"), "5");
+    assert_eq!(rt.get_var("r"), "a,b,,,e");
+}
+
+// --- rev (array) ---
+
+#[test]
+fn rev_reverses_array() {
+    let rt = eval(
+        r#"BEGIN { a[1]="a"; a[2]="b"; a[3]="c"; rev(a); result=a[1] "," a[2] "," a[3] }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("result"), "c,b,a");
+}
+
+#[test]
+fn rev_returns_array_name() {
+    let rt = eval(
+        r#"BEGIN { a[1]="x"; a[2]="y"; n=rev(a); result=a[1] "," a[2] }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("n"), "a"); // returns array name for chaining
+    assert_eq!(rt.get_var("result"), "y,x");
+}
+
+// --- slurp ---
+
+#[test]
+fn slurp_into_string() {
+    let rt = eval(
+        r#"BEGIN { s = slurp("/etc/shells"); result = (length(s) > 0) ? "ok" : "empty" }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("result"), "ok");
+}
+
+#[test]
+fn slurp_into_array() {
+    let rt = eval(
+        r#"BEGIN { n = slurp("/etc/shells", a); result = (n > 0) ? "ok" : "empty" }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("result"), "ok");
+}
+
+// --- join defaults to OFS ---
+
+#[test]
+fn join_defaults_to_ofs() {
+    let rt = eval(
+        r#"BEGIN { OFS=","; a[1]="x"; a[2]="y"; a[3]="z"; result=join(a) }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("result"), "x,y,z");
+}
+
+// --- diagnostics: clock, start, elapsed ---
+
+#[test]
+fn clk_returns_positive() {
+    let rt = eval(r#"BEGIN { result = (clk() >= 0) ? "ok" : "bad" }"#, &[]);
+    assert_eq!(rt.get_var("result"), "ok");
+}
+
+#[test]
+fn tic_toc_basic() {
+    let rt = eval(
+        r#"BEGIN { tic("t"); for(i=0;i<10000;i++){} result = (toc("t") >= 0) ? "ok" : "bad" }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("result"), "ok");
+}
+
+#[test]
+fn toc_without_tic_uses_epoch() {
+    let rt = eval(r#"BEGIN { result = (toc() >= 0) ? "ok" : "bad" }"#, &[]);
+    assert_eq!(rt.get_var("result"), "ok");
+}
+
+#[test]
+fn multiple_timers() {
+    let rt = eval(
+        r#"BEGIN {
+            tic("a"); tic("b")
             for(i=0;i<10000;i++){}
             ea = toc("a"); eb = toc("b")
             result = (ea >= 0 && eb >= 0) ? "ok" : "bad"
@@ -2660,6 +4076,24 @@ fn multiple_timers() {
     assert_eq!(rt.get_var("result"), "ok");
 }
 
+#[test]
+fn laps_add_up_to_outer_timer() {
+    let rt = eval(
+        r#"BEGIN {
+            tic("outer"); tic("phase")
+            for (i = 0; i < 20000; i++) {} a = lap("phase")
+            for (i = 0; i < 20000; i++) {} b = lap("phase")
+            total = toc("outer")
+        }"#,
+        &[],
+    );
+    let num = |k: &str| rt.get_var(k).parse::<f64>().unwrap();
+    let (a, b, total) = (num("a"), num("b"), num("total"));
+    assert!(a > 0.0 && b > 0.0);
+    assert!(a + b <= total + 1e-6);
+    assert!(total - (a + b) < 0.05, "a={a} b={b} total={total}");
+}
+
 // --- diagnostics: dump ---
 
 #[test]
@@ -2686,6 +4120,29 @@ fn dump_to_file() {
     let _ = std::fs::remove_file("/tmp/fk_test_dump.txt");
 }
 
+#[test]
+fn dump_shows_composite_keys_readably() {
+    let path = std::env::temp_dir().join(format!("fk_dump_subsep_{}", std::process::id()));
+    let prog = format!(
+        r#"BEGIN {{ a[1,2] = "x"; a[1,3] = "y"; a[2,1] = "z"; dump(a, "{p}")
+            k = (1,2) in a; n = length(a[1 SUBSEP 2])
+            SUBSEP = "1"; b["1"] = "p"; b["2"] = "q"; DUMPSEP = "/"; dump(b, "{p}") }}"#,
+        p = path.display()
+    );
+    let rt = eval(&prog, &[]);
+    let text = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(
+        text,
+        "dump: a = array (3 elements)\n  [1,2] = \"x\"\n  [1,3] = \"y\"\n  [2,1] = \"z\"\n  \
+         by first subscript: 1,* (2), 2,* (1)\n\
+         dump: b = array (2 elements)\n  [/] = \"p\"\n  [2] = \"q\"\n  by first subscript: /* (1)\n"
+    );
+    // The stored keys are untouched; with SUBSEP "1", b is not 1..N.
+    assert_eq!(rt.get_var("k"), "1");
+    assert_eq!(rt.get_var("n"), "1");
+}
+
 // --- in operator ---
 
 #[test]
@@ -2758,6 +4215,29 @@ fn printf_percent_c_string() {
     assert_eq!(rt.get_var("result"), "A");
 }
 
+#[test]
+fn printf_percent_c_code_points_past_255() {
+    let rt = eval(r#"BEGIN{result=sprintf("%c%c%c", 955, 233, 128512)}"#, &[]);
+    assert_eq!(rt.get_var("result"), "λé😀");
+}
+
+#[test]
+fn printf_percent_c_string_prints_first_char() {
+    // A string is text even when it spells a number; an empty one prints
+    // nothing, not a NUL.
+    let rt = eval(
+        r#"BEGIN{result=sprintf("[%c][%c][%c]", "65", "", "λx")}"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("result"), "[6][][λ]");
+}
+
+#[test]
+fn printf_percent_c_numeric_input_is_a_code_point() {
+    let rt = eval(r#"{result=sprintf("%c%c%c", $1, $1 "", $2)}"#, &["65 x"]);
+    assert_eq!(rt.get_var("result"), "A6x");
+}
+
 // --- length bare / length() ---
 
 #[test]
@@ -2978,6 +4458,26 @@ fn last_with_end() {
     assert_eq!(rt.get_var("result"), "70");
 }
 
+// --- END sees the last record ---
+
+#[test]
+fn end_keeps_last_record_and_fields() {
+    let rt = eval(
+        r#"{ $2 = toupper($2) } END { r = NR " " NF " " $1 "|" $0 }"#,
+        &["a b c", "d e"],
+    );
+    assert_eq!(rt.get_var("r"), "2 2 d|d E");
+}
+
+#[test]
+fn end_after_last_rules_sees_final_record() {
+    let rt = eval(
+        r#"last 2 { n++ } END { r = n ":" $0 }"#,
+        &["x 1", "y 2", "z 3"],
+    );
+    assert_eq!(rt.get_var("r"), "2:z 3");
+}
+
 // --- sorted for-in ---
 
 #[test]
@@ -3025,6 +4525,468 @@ fn forin_rval_descending() {
     assert_eq!(rt.get_var("result"), "xzy");
 }
 
+#[test]
+fn forin_value_ties_break_by_key() {
+    let rt = eval(
+        r#"{ a[$1] += $2 } END { for (k in a) @rval r1 = r1 k; for (k in a) @val_num_asc r2 = r2 k }"#,
+        &["b 3", "a 3", "c 5", "d 1", "e 3", "f 10"],
+    );
+    assert_eq!(rt.get_var("r1"), "fcabed");
+    assert_eq!(rt.get_var("r2"), "dabecf");
+}
+
+#[test]
+fn sortkeys_fills_dest_by_value_desc() {
+    let rt = eval(
+        r#"{ a[$1] += $2 } END { n = sortkeys(a, top, "@val_num_desc"); for (i = 1; i <= 3; i++) r = r top[i] "=" a[top[i]] " "; m = sortkeys(a, byk); bad = sortkeys(a, x, "nope") }"#,
+        &["b 3", "a 3", "c 5", "d 1", "10 3", "9 3"],
+    );
+    assert_eq!(rt.get_var("n"), "6");
+    assert_eq!(rt.get_var("r"), "c=5 9=3 10=3 ");
+    assert_eq!(rt.get_var("m"), "6");
+    assert_eq!(rt.get_var("bad"), "0");
+}
+
+#[test]
+fn topk_matches_brute_force_totals() {
+    // Skewed: key i sends 3000/i bytes once, the first 99 keys 5 more times
+    // at a fifth of that; 500 keys overflow the 30 counters for k=5.
+    let mut lines: Vec<String> = Vec::new();
+    for round in 0..6 {
+        let div = if round == 0 { 1 } else { 5 };
+        for i in 1..=500usize {
+            if round == 0 || i < 100 {
+                lines.push(format!("ip{i} {}", 3000 / i / div));
+            }
+        }
+    }
+    let refs: Vec<&str> = lines.iter().map(String::as_str).collect();
+    let rt = eval(
+        r#"{ topk(ips, $1, $2, 5); all[$1] += $2 }
+        END {
+            n = topk_result(ips, top); sortkeys(all, want, "rval")
+            for (i = 1; i <= 5; i++) {
+                got = got top[i] "=" top[i, "count"] " "
+                exp = exp want[i] "=" all[want[i]] " "
+            }
+        }"#,
+        &refs,
+    );
+    assert_eq!(rt.get_var("n"), "5");
+    assert_eq!(rt.get_var("got"), rt.get_var("exp"));
+}
+
+#[test]
+fn hll_builtins_count_merge_and_round_trip() {
+    let lines: Vec<String> = (0..2000)
+        .map(|i| format!("u{} f{}", i % 1500, i % 2))
+        .collect();
+    let refs: Vec<&str> = lines.iter().map(String::as_str).collect();
+    let rt = eval(
+        r#"{ grew += hll(all, $1); if ($2 == "f0") hll(even, $1); else hll(odd, $1, 14) }
+        END {
+            n = hll_count(all); e = hll_count(even)
+            m = hll_merge(even, odd)
+            d = hll_dump(all); r = hll_load(copy, d); same = (hll_dump(copy) == d)
+            bad = hll_load(x, "junk"); none = hll_count(missing)
+        }"#,
+        &refs,
+    );
+    let n: f64 = rt.get_var("n").parse().unwrap();
+    assert!((n - 1500.0).abs() < 1500.0 * 0.03, "{n}");
+    // Repeats never change the sketch.
+    let grew: f64 = rt.get_var("grew").parse().unwrap();
+    assert!(grew > 0.0 && grew <= 1500.0, "{grew}");
+    assert_eq!(rt.get_var("m"), rt.get_var("n"));
+    assert_eq!(rt.get_var("r"), rt.get_var("n"));
+    assert_eq!(rt.get_var("same"), "1");
+    assert_eq!(rt.get_var("bad"), "0");
+    assert_eq!(rt.get_var("none"), "0");
+    assert_ne!(rt.get_var("e"), rt.get_var("n"));
+}
+
+#[test]
+fn state_accumulates_across_runs() {
+    let prog = r#"{ persist_hits[$1, $2]++; persist_bytes[$1] += $3; seen[$1] = 1 }"#;
+    let program = parser::Parser::new(lexer::Lexer::new(prog).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    let dir = std::env::temp_dir().join(format!("fk_state_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("counts.fkstate").to_string_lossy().into_owned();
+
+    let run = |lines: &[&str]| {
+        let mut rt = runtime::Runtime::new();
+        crate::state::load(&path, &mut rt).unwrap();
+        let mut exec = action::Executor::new(&program, &mut rt);
+        exec.run_begin();
+        for line in lines {
+            exec.run_record(&input::Record {
+                text: line.to_string(),
+                fields: None,
+                malformed: None,
+            });
+        }
+        exec.run_end();
+        assert_eq!(exec.save_state(&path), Ok(2));
+        rt
+    };
+    run(&["a GET 10", "b GET 5"]);
+    let rt = run(&["a GET 1", "a PUT 2"]);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(rt.get_array("persist_hits", "a\x1cGET"), "2");
+    assert_eq!(rt.get_array("persist_hits", "a\x1cPUT"), "1");
+    assert_eq!(rt.get_array("persist_hits", "b\x1cGET"), "1");
+    assert_eq!(rt.get_array("persist_bytes", "a"), "13");
+    assert!(rt.get_array_value("persist_bytes", "a").is_numeric_only());
+    assert!(!rt.array_has_key("seen", "b"));
+}
+
+#[test]
+fn getline_forms_update_nr_and_fnr_per_posix() {
+    let dir = std::env::temp_dir().join(format!("fk_getline_nr_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let main = dir.join("main.txt");
+    std::fs::write(&main, "m1\nm2\nm3\nm4\n").unwrap();
+    let side = dir.join("side.txt");
+    std::fs::write(&side, "s1\ns2\n").unwrap();
+    let prog = format!(
+        concat!(
+            "NR == 1 {{ f = \"{}\"; c = \"printf 'c1\\\\nc2\\\\n'\"\n",
+            "  getline; a = NR \" \" FNR \" \" $0\n",
+            "  getline v; b = NR \" \" FNR \" \" v\n",
+            "  getline < f; d = NR \" \" FNR \" \" $0\n",
+            "  getline w < f; e = NR \" \" FNR \" \" w\n",
+            "  c | getline; g = NR \" \" FNR \" \" $0\n",
+            "  c | getline x; h = NR \" \" FNR \" \" x }}\n",
+            "END {{ last = NR \" \" FNR \" \" $0 }}",
+        ),
+        side.display()
+    );
+    let program = parser::Parser::new(lexer::Lexer::new(&prog).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    let files = vec![main.to_string_lossy().into_owned()];
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    exec.defer_input(move |_| {
+        input::Input::with_reader(&files, Box::new(input::line::LineReader::new()))
+    });
+    exec.run_begin();
+    exec.open_input();
+    let mut record = input::Record::default();
+    while exec.read_record(&mut record).unwrap() {
+        exec.enter_input_source();
+        exec.increment_fnr();
+        exec.run_record(&record);
+    }
+    exec.run_end();
+    drop(exec);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    // Main input: NR and FNR. A file: neither. A command: NR only.
+    assert_eq!(rt.get_var("a"), "2 2 m2");
+    assert_eq!(rt.get_var("b"), "3 3 m3");
+    assert_eq!(rt.get_var("d"), "3 3 s1");
+    assert_eq!(rt.get_var("e"), "3 3 s2");
+    assert_eq!(rt.get_var("g"), "4 3 c1");
+    assert_eq!(rt.get_var("h"), "5 3 c2");
+    assert_eq!(rt.get_var("last"), "6 4 m4");
+}
+
+#[test]
+fn getline_in_begin_consumes_main_input() {
+    let prog =
+        r#"BEGIN { getline first; getline; second = $1 } { n++; last = $1 } END { nr = NR }"#;
+    let program = parser::Parser::new(lexer::Lexer::new(prog).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    let dir = std::env::temp_dir().join(format!("fk_getline_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("in.csv");
+    std::fs::write(&path, "\"a,1\",x\n\"b,2\",y\nc,z\nd,w\n").unwrap();
+    let files = vec![path.to_string_lossy().into_owned()];
+
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    exec.defer_input(move |_| {
+        input::Input::with_reader(&files, Box::new(input::csv::CsvReader::comma()))
+    });
+    exec.run_begin();
+    assert!(exec.input_started());
+    exec.open_input();
+    while let Ok(Some(rec)) = exec.next_record() {
+        exec.run_record(&rec);
+    }
+    exec.run_end();
+    drop(exec);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(rt.get_var("first"), "\"a,1\",x");
+    assert_eq!(rt.get_var("second"), "b,2");
+    assert_eq!(rt.get_var("n"), "2");
+    assert_eq!(rt.get_var("last"), "d");
+    assert_eq!(rt.get_var("nr"), "4");
+}
+
+#[test]
+fn getline_in_begin_skips_the_header_row() {
+    // -H: whichever reads first, BEGIN's getline or the rules, takes the
+    // header; the other sees data rows only.
+    let prog = r#"BEGIN { getline; first = $0 " " NR " " $"h" } { rows = rows NR ":" $"h" " " }"#;
+    let program = parser::Parser::new(lexer::Lexer::new(prog).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    let dir = std::env::temp_dir().join(format!("fk_getline_hdr_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("in.csv");
+    std::fs::write(&path, "h,i\n1,2\n3,4\n5,6\n").unwrap();
+    let files = vec![path.to_string_lossy().into_owned()];
+
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    exec.defer_input(move |_| {
+        input::Input::with_reader(&files, Box::new(input::csv::CsvReader::comma()))
+    });
+    exec.expect_header();
+    exec.run_begin();
+    exec.open_input();
+    let mut record = input::Record::default();
+    while exec.read_record(&mut record).unwrap() {
+        exec.enter_input_source();
+        if exec.take_header(&record) {
+            continue;
+        }
+        exec.increment_fnr();
+        exec.run_record(&record);
+    }
+    drop(exec);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(rt.get_var("first"), "1,2 2 1");
+    assert_eq!(rt.get_var("rows"), "3:3 4:5 ");
+}
+
+#[test]
+fn plain_getline_crosses_a_file_boundary_once() {
+    let prog = r#"function note(s,  p) { log = log s " " p[split(FILENAME, p, "/")] " " FNR "|" }
+        BEGINFILE { note("B") } ENDFILE { note("E") }
+        { getline; log = log NR " " FNR " " $0 "|" }"#;
+    let program = parser::Parser::new(lexer::Lexer::new(prog).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    let dir = std::env::temp_dir().join(format!("fk_getline_files_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("t1"), "a\nb\nc\n").unwrap();
+    std::fs::write(dir.join("t2"), "x\ny\n").unwrap();
+    let files: Vec<String> = ["t1", "t2"]
+        .iter()
+        .map(|f| dir.join(f).to_string_lossy().into_owned())
+        .collect();
+
+    // As the main loop: each read may cross into a new file.
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    exec.defer_input(move |_| {
+        input::Input::with_reader(&files, Box::new(input::line::LineReader::new()))
+    });
+    exec.run_begin();
+    exec.open_input();
+    let mut record = input::Record::default();
+    while exec.read_record(&mut record).unwrap() {
+        exec.enter_input_source();
+        exec.increment_fnr();
+        exec.run_record(&record);
+    }
+    exec.leave_input_source(true);
+    exec.run_end();
+    drop(exec);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    // getline into t2 runs t1's ENDFILE and t2's BEGINFILE, once.
+    assert_eq!(
+        rt.get_var("log"),
+        "B t1 0|2 2 b|E t1 3|B t2 0|4 1 x|5 2 y|E t2 2|"
+    );
+}
+
+#[test]
+fn bom_and_utf16_files_read_like_plain_utf8() {
+    use input::encoding::Encoding;
+    let text = "name,city\nZo\u{eb},\"K\u{f6}ln, DE\"\n";
+    let utf16 = |bom: &[u8], big_endian: bool| {
+        let mut bytes = bom.to_vec();
+        for unit in text.encode_utf16() {
+            bytes.extend(if big_endian {
+                unit.to_be_bytes()
+            } else {
+                unit.to_le_bytes()
+            });
+        }
+        bytes
+    };
+    let utf8_bom = [b"\xEF\xBB\xBF", text.as_bytes()].concat();
+    let fixtures = [
+        ("utf8", text.as_bytes().to_vec(), Encoding::Auto),
+        ("utf8bom", utf8_bom, Encoding::Auto),
+        ("le", utf16(b"\xFF\xFE", false), Encoding::Auto),
+        ("be", utf16(b"\xFE\xFF", true), Encoding::Auto),
+        ("le_nobom", utf16(b"", false), Encoding::Utf16Le),
+    ];
+    let dir = std::env::temp_dir().join(format!("fk_bom_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let expected = [["name", "city"], ["Zo\u{eb}", "K\u{f6}ln, DE"]];
+    for (name, bytes, encoding) in fixtures {
+        let path = dir.join(format!("{name}.csv"));
+        std::fs::write(&path, bytes).unwrap();
+        let files = vec![path.to_string_lossy().into_owned()];
+        let csv = Box::new(input::csv::CsvReader::comma());
+        let mut inp = input::Input::with_reader(&files, csv);
+        inp.set_encoding(encoding);
+        let mut rows = Vec::new();
+        while let Some(rec) = inp.next_record().unwrap() {
+            rows.push(rec.fields.unwrap());
+        }
+        assert_eq!(rows, expected, "{name}");
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// Run `prog` over CSV `files` the way the main loop does: BEGINFILE on
+/// each new file, then the first record is redone under its dialect.
+fn run_csv_files(prog: &str, files: Vec<String>) -> runtime::Runtime {
+    let program = parser::Parser::new(lexer::Lexer::new(prog).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    exec.defer_input(move |_| {
+        input::Input::with_reader(&files, Box::new(input::csv::CsvReader::comma()))
+    });
+    exec.run_begin();
+    exec.open_input();
+    let mut record = input::Record::default();
+    while exec.read_record(&mut record).unwrap() {
+        if exec.enter_input_source() && !exec.redo_first_record(&mut record).unwrap() {
+            continue;
+        }
+        exec.run_record(&record);
+    }
+    exec.run_end();
+    drop(exec);
+    rt
+}
+
+#[test]
+fn csv_variables_configure_the_reader() {
+    let dir = std::env::temp_dir().join(format!("fk_csvvars_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let semi = dir.join("semi.csv");
+    std::fs::write(
+        &semi,
+        "# exported by a broken tool\nid;name\n#1;hidden\n2;\"O;Brien\n",
+    )
+    .unwrap();
+    let comma = dir.join("comma.csv");
+    std::fs::write(&comma, "#id,name\n3,\"Smith, J\"\n").unwrap();
+    let semi = semi.to_string_lossy().into_owned();
+    let comma = comma.to_string_lossy().into_owned();
+
+    let rt = run_csv_files(
+        r##"BEGIN { CSVSEP = ";"; CSVQUOTE = ""; CSVCOMMENT = "#" }
+           { out = out NF ":" $2 "|" }"##,
+        vec![semi.clone()],
+    );
+    assert_eq!(rt.get_var("out"), "2:name|3:\"O|");
+
+    // Switched per file in BEGINFILE; the first record of each file
+    // already follows the new settings.
+    let rt = run_csv_files(
+        r##"BEGINFILE {
+               if (FILENAME ~ /semi/) { CSVSEP = ";"; CSVQUOTE = ""; CSVCOMMENT = "#" }
+               else { CSVSEP = ""; CSVQUOTE = "\""; CSVCOMMENT = "" }
+           }
+           { out = out NF ":" $2 "|" }"##,
+        vec![semi.clone(), comma.clone(), semi],
+    );
+    assert_eq!(
+        rt.get_var("out"),
+        "2:name|3:\"O|2:name|2:Smith, J|2:name|3:\"O|"
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn header_json_selects_by_key_on_every_record() {
+    let prog = r#"{ out = out $"user" "=" $"latency" "/" $latency ";" } END { nr = NR }"#;
+    let program = parser::Parser::new(lexer::Lexer::new(prog).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    let dir = std::env::temp_dir().join(format!("fk_hjson_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("in.jsonl");
+    let data = concat!(
+        "{\"user\":\"ann\",\"latency\":12}\n",
+        "{\"latency\":7,\"path\":\"/x\",\"user\":\"bob\"}\n",
+        "{\"path\":\"/y\",\"user\":\"cy\"}\n",
+    );
+    std::fs::write(&path, data).unwrap();
+    let files = vec![path.to_string_lossy().into_owned()];
+
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    exec.defer_input(move |_| {
+        input::Input::with_reader(&files, Box::new(input::json::JsonReader::keyed()))
+    });
+    exec.run_begin();
+    exec.open_input();
+    while let Ok(Some(rec)) = exec.next_record() {
+        exec.sync_input_header();
+        exec.run_record(&rec);
+    }
+    exec.run_end();
+    drop(exec);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(rt.get_var("out"), "ann=12/12;bob=7/7;cy=/;");
+    assert_eq!(rt.get_var("nr"), "3");
+    assert_eq!(rt.get_array("HDR", "3"), "path");
+}
+
+#[test]
+fn feature_builtin_matches_cfg_flags() {
+    let rt = eval(
+        r#"BEGIN { p = feature("parquet"); z = feature("zstd"); x = feature("nosuch") }"#,
+        &[],
+    );
+    let on = if cfg!(feature = "parquet") { "1" } else { "0" };
+    assert_eq!(rt.get_var("p"), on);
+    assert_eq!(rt.get_var("z"), on);
+    assert_eq!(rt.get_var("x"), "0");
+}
+
+#[test]
+fn procinfo_has_process_info_and_open_streams() {
+    let dir = std::env::temp_dir().join(format!("fk_procinfo_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("out.txt").to_string_lossy().into_owned();
+    let prog = format!(
+        r#"BEGIN {{ f = "{out}"; pid = PROCINFO["pid"]; v = PROCINFO["version"]
+            print "x" > f; open = PROCINFO[f, "type"]
+            print "y" | "cat >/dev/null"; pipe = PROCINFO["cat >/dev/null", "type"]
+            close(f); after = ((f, "type") in PROCINFO) }}"#
+    );
+    let rt = eval(&prog, &[]);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(rt.get_var("pid"), std::process::id().to_string());
+    assert_eq!(rt.get_var("v"), env!("CARGO_PKG_VERSION"));
+    assert_eq!(rt.get_var("open"), "file");
+    assert_eq!(rt.get_var("pipe"), "pipe");
+    assert_eq!(rt.get_var("after"), "0");
+}
+
 // --- function aliases ---
 
 #[test]
@@ -3183,3 +5145,941 @@ fn alias_pdate() {
     );
     assert_eq!(rt.get_var("ok"), "1");
 }
+
+#[test]
+fn namespaced_helpers_coexist() {
+    let libs = [
+        "@namespace \"a\"\nfunction helper(x) { return \"a:\" x }\nfunction run(v) { n++; return helper(v) }",
+        "@namespace \"b\"\nfunction helper(x) { return \"b:\" x }\nfunction run(v) { return helper(v) \"/\" shared(v) }",
+        "function shared(x) { return \"awk:\" x }\n{ out = a::run($1) \" \" b::run($1); hits = a::n }",
+    ];
+    let sep = format!("\n{} ", crate::namespace::RESET_DIRECTIVE);
+    let rt = eval(&libs.join(&sep), &["x", "y"]);
+    assert_eq!(rt.get_var("out"), "a:y b:y/awk:y");
+    assert_eq!(rt.get_var("hits"), "2");
+    assert_eq!(rt.get_var("a::n"), "2");
+    assert_eq!(rt.get_var("n"), "");
+}
+
+#[test]
+fn functions_cannot_shadow_builtins() {
+    for prog in [
+        "function join(a) { return a }",
+        "function s::max(a) { return a }",
+    ] {
+        let tokens = lexer::Lexer::new(prog).tokenize().unwrap();
+        let err = parser::Parser::new(tokens).parse().unwrap_err();
+        assert!(err.message.contains("shadow a builtin"), "{prog}");
+        assert_eq!((err.span.line, err.span.col), (1, 10));
+    }
+    let names = crate::builtins::BUILTIN_FUNCS;
+    assert!(
+        names.windows(2).all(|w| w[0] < w[1]),
+        "BUILTIN_FUNCS must stay sorted"
+    );
+}
+
+#[test]
+fn std_strings_and_dates_behave_as_documented() {
+    let rt = eval(
+        r#"@include "std/strings"
+        @include "std/dates"
+        BEGIN {
+            n = strings::parse_kv("user=ann  id=7 debug x=a=b", pairs)
+            kv = pairs["user"] "|" pairs["id"] "|" pairs["debug"] "|" pairs["x"]
+            m = strings::parse_kv("a:1;b:2", other, ";", ":")
+            both = length(pairs) "|" m "|" other["a"]
+            got = strings::kv_get("a:1;b:2", "b", ";", ":")
+            t = strings::title("ada LOVELACE  x")
+            d1 = dates::iso8601(0); d2 = dates::iso8601(1709634030.25)
+            e1 = dates::from_iso8601("2024-03-05T10:20:30Z")
+            e2 = dates::from_iso8601("2024-03-05 10:20:30.5")
+            e3 = dates::from_iso8601("2024-03-05"); e4 = dates::from_iso8601("5 March")
+        }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("n"), "4");
+    assert_eq!(rt.get_var("kv"), "ann|7||a=b");
+    assert_eq!(rt.get_var("both"), "4|2|1");
+    assert_eq!(rt.get_var("got"), "2");
+    assert_eq!(rt.get_var("t"), "Ada Lovelace  X");
+    assert_eq!(rt.get_var("d1"), "1970-01-01T00:00:00Z");
+    assert_eq!(rt.get_var("d2"), "2024-03-05T10:20:30.250Z");
+    assert_eq!(rt.get_var("e1"), "1709634030");
+    assert_eq!(rt.get_var("e2"), "1709634030.5");
+    assert_eq!(rt.get_var("e3"), "1709596800");
+    assert_eq!(rt.get_var("e4"), "");
+}
+
+#[test]
+fn std_assert_failure_exits() {
+    let rt = eval(
+        r#"@include "std/assert"
+        {
+            assert::ok($1 > 0, "positive"); assert::equal($1 + 0, 1, "one")
+            assert::matches($2, "^[a-z]+$", "word"); passed++
+        }
+        END { failed = assert::failed }"#,
+        &["1 ok", "2 ok", "3 ok"],
+    );
+    // The second record fails `equal`; exit skips the rest of the input.
+    assert_eq!(rt.get_var("passed"), "1");
+    assert_eq!(rt.get_var("failed"), "1");
+    let prog = "@include \"std/assert\"\nBEGIN { assert::ok(1, \"x\") }";
+    let report = crate::check::check(prog, None, &[]);
+    assert!(report.diagnostics.is_empty(), "{:?}", report.diagnostics);
+}
+
+#[test]
+fn exit_in_a_function_ends_the_calling_action() {
+    let rt = eval(
+        "function stop() { exit 3 } { n++; if (NR == 2) stop(); after++ } END { end = 1 }",
+        &["a", "b", "c"],
+    );
+    assert_eq!(rt.get_var("n"), "2");
+    assert_eq!(rt.get_var("after"), "1");
+    assert_eq!(rt.get_var("end"), "1");
+}
+
+#[test]
+fn dynamic_regexes_hit_the_cache() {
+    let prog =
+        r#"BEGIN { p = "^[0-9]+$"; bad = "(a{1,1000}){1,1000}" } $0 ~ p { n++ } $0 ~ bad { m++ }"#;
+    let program = parser::Parser::new(lexer::Lexer::new(prog).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    exec.run_begin();
+    for i in 0..100 {
+        exec.run_record(&input::Record {
+            text: i.to_string(),
+            fields: None,
+            malformed: None,
+        });
+    }
+    exec.run_end();
+    // One compile for `p`; the oversized pattern fails once and is not retried.
+    assert_eq!(exec.regex_compiles, 2);
+    assert!(exec.regex_failed.contains("(a{1,1000}){1,1000}"));
+    drop(exec);
+    assert_eq!(rt.get_var("n"), "100");
+    assert_eq!(rt.get_var("m"), "");
+}
+
+#[test]
+fn dynamic_regex_cache_stays_bounded() {
+    let prog = r#"$0 ~ ("^" $0 "$") { n++ } $0 ~ "^[0-9]+$" { m++ }"#;
+    let program = parser::Parser::new(lexer::Lexer::new(prog).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    let cap = action::DYNAMIC_REGEX_CAP;
+    let records = 3 * cap;
+    for i in 0..records {
+        exec.run_record(&input::Record {
+            text: i.to_string(),
+            fields: None,
+            malformed: None,
+        });
+    }
+    assert!(exec.regex_cache.len() <= cap);
+    assert_eq!(exec.regex_evictions, records + 1 - cap);
+    // The hot pattern is used every record, so it is never the one dropped.
+    assert_eq!(exec.regex_compiles, records + 1);
+    drop(exec);
+    assert_eq!(rt.get_var("n"), records.to_string());
+    assert_eq!(rt.get_var("m"), records.to_string());
+}
+
+#[test]
+fn rx_escapes_its_arguments() {
+    let rt = eval(
+        r#"{ k = $1; v = $2; if ((k "=" v) ~ rx("^%s=%s$", k, v)) hit = hit k "," }
+           END { q = rx("%s|100%%%d", "a.b") }"#,
+        &["a.b x", ".* .*", "( [", "^$ |"],
+    );
+    assert_eq!(rt.get_var("hit"), "a.b,.*,(,^$,");
+    assert_eq!(rt.get_var("q"), r"a\.b|100%%d");
+    let rt = eval(
+        r#"{ if ($0 ~ rx("^%s$", ".*")) lit++ }"#,
+        &[".*", "anything", ""],
+    );
+    assert_eq!(rt.get_var("lit"), "1");
+}
+
+#[test]
+fn ignorecase_applies_to_patterns_and_match() {
+    let lines = ["ERROR disk", "error net", "Error: x", "warn"];
+    let prog = r#"/error/ { n++ } $0 ~ "^err" { m++ } match($0, /or/) { c++ }"#;
+    let rt = eval(&format!("BEGIN {{ IGNORECASE = 1 }}\n{prog}"), &lines);
+    assert_eq!(rt.get_var("n"), "3");
+    assert_eq!(rt.get_var("m"), "3");
+    assert_eq!(rt.get_var("c"), "3");
+    let rt = eval(prog, &lines);
+    assert_eq!(rt.get_var("n"), "1");
+    assert_eq!(rt.get_var("m"), "1");
+}
+
+#[test]
+fn word_regexp_wraps_only_regex_literals() {
+    let prog = r#"/err/ { n++ } $0 ~ "err" { m++ }"#;
+    let mut tokens = lexer::Lexer::new(prog).tokenize().unwrap();
+    lexer::wrap_word_regexes(&mut tokens);
+    let program = parser::Parser::new(tokens).parse().unwrap();
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    exec.run_begin();
+    for line in ["err 1", "error 2", "stderr", "x-err-y"] {
+        exec.run_record(&input::Record {
+            text: line.to_string(),
+            fields: None,
+            malformed: None,
+        });
+    }
+    exec.run_end();
+    drop(exec);
+    assert_eq!(rt.get_var("n"), "2");
+    assert_eq!(rt.get_var("m"), "4");
+}
+
+#[test]
+fn slice_sequential_with_negative_indexes() {
+    let rt = eval(
+        r#"BEGIN { split("a b c d e f", a); n = slice(a, t, -3, -1); mid = slice(a, m, 2, 3) }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("n"), "3");
+    assert_eq!(rt.get_array("t", "1"), "d");
+    assert_eq!(rt.get_array("t", "3"), "f");
+    assert_eq!(rt.get_var("mid"), "2");
+    assert_eq!(rt.get_array("m", "1"), "b");
+    assert_eq!(rt.get_array("m", "2"), "c");
+}
+
+#[test]
+fn slice_associative_compares_numeric_keys_numerically() {
+    let rt = eval(
+        r#"BEGIN { m[2] = "y"; m[10] = "x"; m[100] = "z"; m["k"] = "w"; n = slice(m, s, 2, 50) }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("n"), "2");
+    assert_eq!(rt.get_array("s", "2"), "y");
+    assert_eq!(rt.get_array("s", "10"), "x");
+    assert!(!rt.array_has_key("s", "100") && !rt.array_has_key("s", "k"));
+}
+
+#[test]
+fn slice_inverted_range_is_empty() {
+    let rt = eval(
+        r#"BEGIN { split("a b c", a); t[9] = 1; n = slice(a, t, 3, 1); len = length(t) }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("n"), "0");
+    assert_eq!(rt.get_var("len"), "0");
+}
+
+#[test]
+fn bounded_split_keeps_the_whole_record() {
+    let rt = eval(
+        "{ second = $2; len = length($0); whole = $0 }",
+        &["alpha beta gamma delta"],
+    );
+    assert_eq!(rt.get_var("second"), "beta");
+    assert_eq!(rt.get_var("len"), "22");
+    assert_eq!(rt.get_var("whole"), "alpha beta gamma delta");
+
+    // Past the bound, the unsplit rest is split on demand.
+    let mut rt = runtime::Runtime::new();
+    rt.set_record_capped("a b  c d e", 2);
+    assert_eq!(rt.get_field(2), "b");
+    assert_eq!(rt.get_field(5), "e");
+    assert_eq!(rt.get_field(6), "");
+    assert_eq!(rt.nf(), 5);
+    assert_eq!(rt.get_var("NF"), "5");
+    rt.set_field(4, "X");
+    assert_eq!(rt.get_field(0), "a b c X e");
+
+    // FS changes apply to the next record, even to the unsplit rest.
+    rt.set_record_capped("a b,c d", 1);
+    rt.set_var("FS", ",");
+    assert_eq!(rt.get_field(2), "b,c");
+    assert_eq!(rt.nf(), 3);
+}
+
+#[test]
+fn presplit_fields_move_into_the_runtime() {
+    let src = "{ second = $2; len = length($0) }";
+    let program = parser::Parser::new(lexer::Lexer::new(src).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    let mut csv = input::csv::CsvReader::comma();
+    let mut data = std::io::Cursor::new("a,\"b,1\",c\nd,e,f,g\n");
+    let mut rec = input::Record::default();
+    assert!(csv.read_into(&mut data, &mut rec).unwrap());
+    exec.run_record_mut(&mut rec);
+    // The record got the runtime's (empty) field Vec back.
+    assert_eq!(rec.fields.as_deref(), Some(&[][..]));
+    assert!(csv.read_into(&mut data, &mut rec).unwrap());
+    exec.run_record_mut(&mut rec);
+    assert_eq!(rec.fields.as_ref().map(Vec::len), Some(3));
+    drop(exec);
+    assert_eq!(rt.get_var("second"), "e");
+    assert_eq!(rt.get_var("len"), "7");
+    assert_eq!(rt.nf(), 4);
+    assert_eq!(rt.get_field(4), "g");
+}
+
+#[test]
+fn rule_regexes_follow_ignorecase_set_by_an_earlier_rule() {
+    let rt = eval(
+        "/^foo/ { hits = hits $0 \",\" }\n/^x/ { IGNORECASE = 1 }\n/^FOO/ { big++ }\n/b/, /d/ { r = r $0 }",
+        &["Foo", "x", "FOO", "foo", "a", "B", "c", "D", "e"],
+    );
+    assert_eq!(rt.get_var("hits"), "FOO,foo,");
+    assert_eq!(rt.get_var("big"), "2");
+    assert_eq!(rt.get_var("r"), "BcD");
+}
+
+/// Run `program_text` over `lines` with and without constant folding.
+fn eval_both_ways(program_text: &str, lines: &[&str], vars: &[&str]) -> Vec<String> {
+    let tokens = lexer::Lexer::new(program_text).tokenize().unwrap();
+    let plain = parser::Parser::new(tokens.clone()).parse().unwrap();
+    let mut folded = parser::Parser::new(tokens).parse().unwrap();
+    crate::optimize::fold_constants(&mut folded);
+    let run = |program: &parser::Program| {
+        let mut rt = runtime::Runtime::new();
+        let mut exec = action::Executor::new(program, &mut rt);
+        exec.run_begin();
+        for line in lines {
+            exec.run_record(&input::Record {
+                text: line.to_string(),
+                fields: None,
+                malformed: None,
+            });
+        }
+        exec.run_end();
+        drop(exec);
+        vars.iter().map(|v| rt.get_var(v)).collect::<Vec<_>>()
+    };
+    let expected = run(&plain);
+    assert_eq!(run(&folded), expected, "{program_text}");
+    expected
+}
+
+#[test]
+fn folded_programs_compute_the_same_values() {
+    let got = eval_both_ways(
+        concat!(
+            "BEGIN { CONVFMT = \"%.2g\"; ms = 60 * 60 / 1000 \" s\"; p = 2 ** 0.5 * -(3 - 1) }\n",
+            "{ t += $1 * (1 / 3); s = s sprintf(\"%5.1f|%-3s|\", $1 * 1.5, \"x\" \"y\"); ",
+            "f = \"%d/\" \"%x;\"; d = d sprintf(f, $1, $1 + 2 ** 4) }\n",
+            "END { m = 7 % 3 + t; x = (\"lit\") \"\" }",
+        ),
+        &["1", "2.5", "10"],
+        &["ms", "p", "t", "s", "d", "m", "x"],
+    );
+    assert_eq!(got[0], "3.6 s");
+    assert_eq!(got[3], "  1.5|xy |  3.8|xy | 15.0|xy |");
+    assert_eq!(got[4], "1/11;2/12;10/1a;");
+    assert_eq!(got[6], "lit");
+}
+
+#[test]
+fn concat_chains_and_self_append_keep_their_semantics() {
+    let rt = eval(
+        concat!(
+            "BEGIN { CONVFMT = \"%.2f\"; n = 1.5; n = n \"|\" 0.25 \"|\" 3; OFS = OFS \"-\" OFS; CONVFMT = \"%.6g\" }\n",
+            "{ out = out $1 \",\" $2 \";\"; twice = twice $1; twice = twice twice; ",
+            "c = c \"\" (c = $1); g = g \"\" (\"<\" $3?); t = t $3?; }\n",
+            "END { whole = \"[\" out \"]\" NR; ofs = OFS }",
+        ),
+        &["a b x", "c d", "e f y"],
+    );
+    assert_eq!(rt.get_var("n"), "1.50|0.25|3.00");
+    assert_eq!(rt.get_var("ofs"), " - ");
+    assert_eq!(rt.get_var("out"), "a,b;c,d;e,f;");
+    assert_eq!(rt.get_var("twice"), "aacaaceaacaace");
+    assert_eq!(rt.get_var("whole"), "[a,b;c,d;e,f;]3");
+    // The head is read before the tail updates it.
+    assert_eq!(rt.get_var("c"), "ace");
+    assert_eq!(rt.get_var("g"), "<x<y");
+    // A null part makes the whole result null, and it stays null.
+    assert_eq!(rt.get_var("t"), "");
+}
+
+#[test]
+fn spilled_arrays_read_back_delete_and_sort() {
+    let rt = eval(
+        concat!(
+            "BEGIN { ARRAY_SPILL_THRESHOLD = 3 }\n",
+            "{ lines[NR] = $0; n[NR] = NR * 10 }\n",
+            "END { lines[2] = \"two\"; delete lines[4]; has4 = (4 in lines); len = length(lines)\n",
+            "  for (k in lines) cnt++\n",
+            "  all = lines[1] \"|\" lines[2] \"|\" lines[6]\n",
+            "  j = join(n, \",\"); s = sum(n); m = max(n)\n",
+            "  copy[\"x\"] = 1; asort(lines, copy); first = copy[1]; last = copy[length(copy)]\n",
+            "  back4 = lines[4] \"/\" (4 in lines)\n",
+            "  spilled = PROCINFO[\"lines\", \"spilled\"] \"/\" ((\"small\", \"spilled\") in PROCINFO)\n",
+            "  delete lines; gone = length(lines) }",
+        ),
+        &["zeta", "b", "c", "d", "e", "alpha"],
+    );
+    assert_eq!(rt.get_var("has4"), "0");
+    assert_eq!(rt.get_var("len"), "5");
+    assert_eq!(rt.get_var("cnt"), "5");
+    assert_eq!(rt.get_var("all"), "zeta|two|alpha");
+    let mut j: Vec<i64> = rt
+        .get_var("j")
+        .split(',')
+        .map(|v| v.parse().unwrap())
+        .collect();
+    j.sort();
+    assert_eq!(j, [10, 20, 30, 40, 50, 60]);
+    assert_eq!(rt.get_var("s"), "210");
+    assert_eq!(rt.get_var("m"), "60");
+    assert_eq!(rt.get_var("first"), "alpha");
+    assert_eq!(rt.get_var("last"), "zeta");
+    // Reading a deleted element brings it back, empty.
+    assert_eq!(rt.get_var("back4"), "/1");
+    assert_eq!(rt.get_var("spilled"), "4/0");
+    assert_eq!(rt.get_var("gone"), "0");
+}
+
+#[test]
+fn arrays_spill_past_a_byte_size() {
+    let rt = eval(
+        concat!(
+            "BEGIN { ARRAY_SPILL_BYTES = \"1K\" }\n",
+            "{ small[NR] = NR; big[NR] = sprintf(\"%200s\", $0) }\n",
+            "END { churn[\"k\"] = sprintf(\"%600s\", \"\"); delete churn[\"k\"]\n",
+            "  churn[\"k\"] = sprintf(\"%600s\", \"\")\n",
+            "  at = PROCINFO[\"big\", \"spilled\"]; tail = length(big[6]) \"/\" length(big)\n",
+            "  kept = ((\"small\", \"spilled\") in PROCINFO) ((\"churn\", \"spilled\") in PROCINFO) }",
+        ),
+        &["a", "b", "c", "d", "e", "f"],
+    );
+    // Each element counts its key, its string and the entry itself.
+    assert_eq!(rt.get_var("at"), "4");
+    assert_eq!(rt.get_var("tail"), "200/6");
+    // A deleted element no longer counts towards the size.
+    assert_eq!(rt.get_var("kept"), "00");
+}
+
+#[test]
+fn bad_spill_byte_size_is_a_warning() {
+    let rt = eval(
+        r#"BEGIN { ARRAY_SPILL_BYTES = "lots" } END { a[1] = 1; w = WARNINGS }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("w"), "1");
+}
+
+#[test]
+fn assigned_records_split_when_their_fields_are_read() {
+    let rt = eval(
+        concat!(
+            "{ gsub(/,/, \" \"); n = n NF \";\"; f = f $2 \";\" }\n",
+            "{ $0 = \"x y b\"; sub(/b/, \"B\", $3); r = r $0 \";\" }\n",
+            "END { $0 = \"p q\"; last = $2 NF; $0 = \"x:y:z\"; FS = \":\"; a = $1 \"/\" NF }",
+        ),
+        &["a,b,c", "d e,f,g h"],
+    );
+    assert_eq!(rt.get_var("n"), "3;5;");
+    assert_eq!(rt.get_var("f"), "b;e;");
+    // The new FS applies from the next record, not to the assigned $0.
+    assert_eq!(rt.get_var("a"), "x:y:z/1");
+    assert_eq!(rt.get_var("r"), "x y B;x y B;");
+    assert_eq!(rt.get_var("last"), "q2");
+
+    // Without the executor, the new $0 is split on first use.
+    let mut rt = runtime::Runtime::new();
+    rt.set_record("a b c");
+    rt.set_field(0, "  one two  ");
+    assert_eq!(rt.get_field(0), "  one two  ");
+    assert_eq!(rt.nf(), 2);
+    assert_eq!(rt.get_field(2), "two");
+    rt.set_field(0, "u,v,w");
+    rt.set_var("FS", ",");
+    assert_eq!(rt.get_field(1), "u,v,w");
+    rt.set_field(0, "b,a,d");
+    rt.set_field(2, "X");
+    assert_eq!(rt.get_field(0), "b X d");
+    assert_eq!(rt.get_var("NF"), "3");
+}
+
+#[test]
+fn redirected_print_lines_are_formatted_whole_before_writing() {
+    let dir = std::env::temp_dir().join(format!("fk_print_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("out.txt").to_string_lossy().into_owned();
+    let prog = format!(
+        concat!(
+            "function note() {{ print \"note\" > OUT; return \"y\" }}\n",
+            "BEGIN {{ OUT = \"{}\"; OFS = \"→\"; ORS = \"¶\\n\" }}\n",
+            "{{ print $2, $1 * 1.5, note(), $-1 > OUT; print $1 > OUT }}\n",
+            "END {{ print \"a\", (OFS = \"-\"), \"b\" > OUT; print \"c\", \"d\" > OUT }}",
+        ),
+        out
+    );
+    eval(&prog, &["1 b c", "3 ü"]);
+    let text = std::fs::read_to_string(&out).unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(
+        text,
+        concat!(
+            "note¶\nb→1.5→y→c¶\n1¶\n",
+            "note¶\nü→4.5→y→ü¶\n3¶\n",
+            // OFS is taken before the values are evaluated.
+            "a→-→b¶\nc-d¶\n",
+        )
+    );
+}
+
+#[test]
+fn newline_after_logical_operators_continues_the_condition() {
+    let rt = eval(
+        "$1 > 0 &&\n  $2 > 0 { both++ }\n{ if ($1 == \"x\" ||\n\n   $2 == \"x\") x++ }",
+        &["1 2", "1 0", "x 0", "0 x"],
+    );
+    assert_eq!(rt.get_var("both"), "1");
+    assert_eq!(rt.get_var("x"), "2");
+}
+
+#[test]
+fn membership_tests_never_create_elements() {
+    let rt = eval(
+        concat!(
+            "BEGIN { if (!(\"FK_NO_SUCH_VAR\" in ENVIRON)) absent = 1\n",
+            "  for (k in ENVIRON) if (k == \"FK_NO_SUCH_VAR\") leaked++\n",
+            "  env = length(ENVIRON); if ((\"x\", 1) in ENVIRON) env = -1\n",
+            "  if (env == length(ENVIRON)) same = 1\n",
+            "  if (\"k\" in a) bad++; if ((\"k\", 2) in a) bad++; untouched = length(a)\n",
+            "  v = a[\"k\"]; w = a[\"k\", 2]; touched = length(a) \"/\" (\"k\" in a) ((\"k\", 2) in a) }",
+        ),
+        &[],
+    );
+    assert_eq!(rt.get_var("absent"), "1");
+    assert_eq!(rt.get_var("leaked"), "");
+    assert_eq!(rt.get_var("same"), "1");
+    assert_eq!(rt.get_var("bad"), "");
+    assert_eq!(rt.get_var("untouched"), "0");
+    // A plain reference is what creates an element, as in awk.
+    assert_eq!(rt.get_var("touched"), "2/11");
+}
+
+#[test]
+fn parenthesized_multi_key_in_works_in_any_expression() {
+    let rt = eval(
+        concat!(
+            "function seen1(k) { return (k, 1) in seen }\n",
+            "BEGIN { seen[\"x\", 1] }\n",
+            "($1, 1) in seen { pat = pat $1 }\n",
+            "{ r = r seen1($1); t = t \"\" (($1, 1) in seen ? \"y\" : \"n\")\n",
+            "  u = u \"\" (1 ? ($1, 1) in seen ? \"Y\" : \"N\" : \"-\")\n",
+            "  c = c substr(\"ab\", ($1, 1) in seen ? 2 : 1, 1) }\n",
+            "END { delete seen[\"x\", 1]; gone = !((\"x\", 1) in seen) }",
+        ),
+        &["x", "y"],
+    );
+    assert_eq!(rt.get_var("pat"), "x");
+    assert_eq!(rt.get_var("r"), "10");
+    assert_eq!(rt.get_var("t"), "yn");
+    assert_eq!(rt.get_var("u"), "YN");
+    assert_eq!(rt.get_var("c"), "ba");
+    assert_eq!(rt.get_var("gone"), "1");
+}
+
+#[test]
+fn output_pipes_past_the_cap_close_and_rerun() {
+    let dir = std::env::temp_dir().join(format!("fk_pipes_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let prog = concat!(
+        "BEGIN { for (i = 1; i <= 300; i++) { print i | (\"cat >> \" d \"/\" i % 100)\n",
+        "  open = PROCINFO[\"open_pipes\"]; if (open > most) most = open } }",
+    );
+    let program = parser::Parser::new(lexer::Lexer::new(prog).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    let mut rt = runtime::Runtime::new();
+    rt.set_var("d", &dir.display().to_string());
+    let mut exec = action::Executor::new(&program, &mut rt);
+    exec.set_max_pipes(4);
+    exec.run_begin();
+    exec.run_end();
+    drop(exec);
+    // Every command got all of its lines, across the reruns.
+    let mut want = vec![String::new(); 100];
+    for i in 1..=300 {
+        want[i % 100].push_str(&format!("{i}\n"));
+    }
+    for (k, want) in want.iter().enumerate() {
+        let got = std::fs::read_to_string(dir.join(k.to_string())).unwrap();
+        assert_eq!(&got, want, "pipe {k}");
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(rt.get_var("most"), "4");
+}
+
+#[test]
+fn repeated_warnings_are_counted_and_printed_a_few_times() {
+    let prog = concat!(
+        "BEGIN { for (i = 0; i < 1000; i++) x = nope(i); y = match(\"a\", \"(\")\n",
+        "  total = WARNINGS; WARNINGS = 0; z = nope(); again = WARNINGS }",
+    );
+    let program = parser::Parser::new(lexer::Lexer::new(prog).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    for quiet in [false, true] {
+        let mut rt = runtime::Runtime::new();
+        let mut exec = action::Executor::new(&program, &mut rt);
+        exec.set_quiet(quiet);
+        exec.run_begin();
+        // Three of the 1001 calls to nope() are printed; the bad regex is
+        // a warning of its own.
+        let suppressed = if quiet { 0 } else { 998 };
+        assert_eq!(exec.warnings_suppressed, suppressed);
+        exec.run_end();
+        drop(exec);
+        assert_eq!(rt.get_var("total"), "1001");
+        assert_eq!(rt.get_var("again"), "1");
+    }
+}
+
+#[test]
+fn every_printf_mismatch_counts_as_a_warning() {
+    let rt = eval(
+        r#"BEGIN { for (i = 0; i < 1000; i++) printf "%d %d\n", i > "/dev/null"; n = WARNINGS }"#,
+        &[],
+    );
+    assert_eq!(rt.get_var("n"), "1000");
+}
+
+#[test]
+fn close_lets_getline_reread_files_and_rerun_commands() {
+    let dir = std::env::temp_dir().join(format!("fk_close_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("lookup.txt");
+    std::fs::write(&path, "l1\nl2\nl3\n").unwrap();
+    let prog = format!(
+        concat!(
+            "BEGIN {{ f = \"{}\"; c = \"cat \" f\n",
+            "  while ((getline l < f) > 0) a = a l; r1 = close(f)\n",
+            "  while ((getline l < f) > 0) a = a \",\" l\n",
+            "  while ((c | getline l) > 0) b = b l; r2 = close(c)\n",
+            "  while ((c | getline l) > 0) b = b \",\" l\n",
+            "  again = close(f) close(c) close(f)\n",
+            "  print \"x\" | \"cat >/dev/null; exit 3\"; out = close(\"cat >/dev/null; exit 3\")\n",
+            "  \"exit 2\" | getline; inp = close(\"exit 2\")\n",
+            "  getline l < f; half = close(f, \"to\") close(f, \"from\") }}",
+        ),
+        path.display()
+    );
+    let rt = eval(&prog, &[]);
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(rt.get_var("a"), "l1l2l3,l1,l2,l3");
+    assert_eq!(rt.get_var("b"), "l1l2l3,l1,l2,l3");
+    assert_eq!(rt.get_var("r1"), "0");
+    assert_eq!(rt.get_var("r2"), "0");
+    // Open at EOF until closed; closing twice finds nothing to close.
+    assert_eq!(rt.get_var("again"), "00-1");
+    // Commands close with their exit status.
+    assert_eq!(rt.get_var("out"), "3");
+    assert_eq!(rt.get_var("inp"), "2");
+    // No coprocesses: "to" closes the whole file, so "from" finds it gone.
+    assert_eq!(rt.get_var("half"), "0-1");
+}
+
+#[test]
+fn failed_io_sets_errno_and_success_leaves_it() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = std::env::temp_dir().join(format!("fk_errno_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let ok = dir.join("ok.txt");
+    std::fs::write(&ok, "line\n").unwrap();
+    let locked = dir.join("locked.txt");
+    std::fs::write(&locked, "secret\n").unwrap();
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+    let prog = format!(
+        concat!(
+            "BEGIN {{ d = \"{0}\"\n",
+            "  missing = (getline l < (d \"/none.txt\")) \" \" ERRNO\n",
+            "  isdir = (getline l < d) \" \" ERRNO\n",
+            "  locked = (getline l < (d \"/locked.txt\")) \" \" ERRNO\n",
+            "  ERRNO = \"kept\"; good = (getline l < (d \"/ok.txt\")) \" \" ERRNO\n",
+            "  print \"x\" > (d \"/no/such/dir\"); unwritable = ERRNO\n",
+            "  never = close(\"never opened\") \" \" ERRNO }}",
+        ),
+        dir.display()
+    );
+    let rt = eval(&prog, &[]);
+    // Root reads anything; the permission case only means something otherwise.
+    let readable = std::fs::File::open(&locked).is_ok();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(rt.get_var("missing"), "-1 No such file or directory");
+    assert_eq!(rt.get_var("isdir"), "-1 Is a directory");
+    if !readable {
+        assert_eq!(rt.get_var("locked"), "-1 Permission denied");
+    }
+    assert_eq!(rt.get_var("good"), "1 kept");
+    assert_eq!(rt.get_var("unwritable"), "No such file or directory");
+    assert_eq!(
+        rt.get_var("never"),
+        "-1 close of redirection that was never opened"
+    );
+}
+
+/// Output sink whose contents the test can read while the program runs.
+#[derive(Clone, Default)]
+struct Sink(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+impl std::io::Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Sink {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+/// What has reached the sink after each record of `lines`.
+fn seen_after_each_record(prog: &str, lines: &[&str], line_buffered: bool) -> Vec<String> {
+    let tokens = lexer::Lexer::new(prog).tokenize().unwrap();
+    let program = parser::Parser::new(tokens).parse().unwrap();
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    let sink = Sink::default();
+    exec.set_stdout(Box::new(sink.clone()));
+    exec.set_line_buffered(line_buffered);
+    exec.run_begin();
+    let mut seen = Vec::new();
+    for line in lines {
+        exec.run_record(&input::Record {
+            text: line.to_string(),
+            fields: None,
+            malformed: None,
+        });
+        seen.push(sink.text());
+    }
+    seen
+}
+
+#[test]
+fn line_buffered_output_reaches_the_sink_before_the_next_record() {
+    let seen = seen_after_each_record("{ print $1; printf \"<%s>\", $1 }", &["a", "b"], true);
+    assert_eq!(seen, ["a\n<a>", "a\n<a>b\n<b>"]);
+    let seen = seen_after_each_record("{ print $1 }", &["a", "b"], false);
+    assert_eq!(seen, ["", ""]);
+}
+
+/// Output of `prog` over CSV `rows` (the first is the header) with
+/// `--emit-header` on or off.
+fn csv_output_with_header(prog: &str, rows: &[&[&str]], emit: bool) -> String {
+    let tokens = lexer::Lexer::new(prog).tokenize().unwrap();
+    let program = parser::Parser::new(tokens).parse().unwrap();
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    let sink = Sink::default();
+    exec.set_stdout(Box::new(sink.clone()));
+    exec.set_csv_output(true);
+    exec.set_emit_header(emit);
+    exec.run_begin();
+    let mut rows = rows
+        .iter()
+        .map(|r| r.iter().map(|c| c.to_string()).collect::<Vec<_>>());
+    exec.set_header(&rows.next().unwrap());
+    for fields in rows {
+        exec.run_record(&input::Record {
+            text: crate::output::csv_row(&fields),
+            fields: Some(fields),
+            malformed: None,
+        });
+    }
+    exec.run_end();
+    exec.flush_output();
+    sink.text()
+}
+
+#[test]
+fn header_writes_hdr_in_the_output_format() {
+    let rows: &[&[&str]] = &[
+        &["id", "name, full", "n"],
+        &["1", "Smith, J", "5"],
+        &["2", "Bo", "1"],
+    ];
+    let out = csv_output_with_header("$3 > 2", rows, true);
+    assert_eq!(out, "id,\"name, full\",n\n1,\"Smith, J\",5\n");
+    // Nothing printed, no header. A separator given to header() is used
+    // as is; quoting is only needed for cells that hold it.
+    assert_eq!(csv_output_with_header("$3 > 9", rows, true), "");
+    let out = csv_output_with_header("NR == 2 { n = header(\";\") } END { print n }", rows, false);
+    assert_eq!(out, "id;name, full;n\n3\n");
+    let out = csv_output_with_header("BEGIN { OFS = \"|\" } { print $1, $3 }", rows, true);
+    assert_eq!(out, "id|name, full|n\n1|5\n2|1\n");
+}
+
+#[test]
+fn flushmode_switches_buffering_at_runtime() {
+    let prog =
+        "NR == 1 { was = flushmode(\"line\") } NR == 3 { flushmode(\"block\") } { print was, $1 }";
+    let seen = seen_after_each_record(prog, &["a", "b", "c"], false);
+    assert_eq!(
+        seen,
+        ["block a\n", "block a\nblock b\n", "block a\nblock b\n"]
+    );
+    let rt = eval(
+        "BEGIN { a = flushmode(); b = flushmode(\"line\"); c = flushmode(\"bogus\") }",
+        &[],
+    );
+    assert_eq!(rt.get_var("a"), "block");
+    assert_eq!(rt.get_var("b"), "block");
+    assert_eq!(rt.get_var("c"), "line");
+}
+
+/// Run `prog` over `lines` (as a file) with input limits set up by `limit`;
+/// returns the runtime and the limit that stopped input, if any.
+fn run_limited(
+    prog: &str,
+    lines: usize,
+    limit: impl FnOnce(&mut action::Executor),
+) -> (runtime::Runtime, Option<action::InputLimit>) {
+    let path = std::env::temp_dir().join(format!("fk_limit_{}_{}", std::process::id(), lines));
+    let text: String = (1..=lines).map(|i| format!("{i}\n")).collect();
+    std::fs::write(&path, text).unwrap();
+    let program = parser::Parser::new(lexer::Lexer::new(prog).tokenize().unwrap())
+        .parse()
+        .unwrap();
+    let mut rt = runtime::Runtime::new();
+    let mut exec = action::Executor::new(&program, &mut rt);
+    limit(&mut exec);
+    let files = vec![path.display().to_string()];
+    exec.defer_input(move |_| {
+        input::Input::with_reader(&files, Box::new(input::line::LineReader::new()))
+    });
+    exec.run_begin();
+    exec.open_input();
+    let mut record = input::Record::default();
+    while exec.read_record(&mut record).unwrap() {
+        exec.run_record(&record);
+    }
+    exec.run_end();
+    let stopped = exec.input_limit();
+    drop(exec);
+    let _ = std::fs::remove_file(&path);
+    (rt, stopped)
+}
+
+#[test]
+fn max_records_stops_input_and_runs_end() {
+    let prog = r#"{ s += $1 } END { n = NR; t = PROCINFO["truncated"]; done = 1 }"#;
+    let (rt, stopped) = run_limited(prog, 10, |e| e.set_max_records(3));
+    assert_eq!(stopped, Some(action::InputLimit::Records));
+    assert_eq!(rt.get_var("n"), "3");
+    assert_eq!(rt.get_var("s"), "6");
+    assert_eq!(rt.get_var("t"), "1");
+    assert_eq!(rt.get_var("done"), "1");
+    // Input that fits the limit is not marked.
+    let (rt, stopped) = run_limited(prog, 3, |e| e.set_max_records(3));
+    assert_eq!(stopped, None);
+    assert_eq!(rt.get_var("n"), "3");
+    assert_eq!(rt.get_var("t"), "");
+    // getline counts against the same limit.
+    let (rt, _) = run_limited(
+        "BEGIN { while ((getline line) > 0) g++ } END { n = NR }",
+        10,
+        |e| e.set_max_records(4),
+    );
+    assert_eq!(rt.get_var("g"), "4");
+}
+
+#[test]
+fn max_runtime_stops_input_and_runs_end() {
+    let prog = r#"END { n = NR; t = PROCINFO["truncated"]; done = 1 }"#;
+    let (rt, stopped) = run_limited(prog, 5, |e| e.set_max_runtime(std::time::Duration::ZERO));
+    assert_eq!(stopped, Some(action::InputLimit::Runtime));
+    assert_eq!(rt.get_var("n"), "0");
+    assert_eq!(rt.get_var("t"), "1");
+    assert_eq!(rt.get_var("done"), "1");
+    let (rt, stopped) = run_limited(prog, 5, |e| {
+        e.set_max_runtime(std::time::Duration::from_secs(3600))
+    });
+    assert_eq!(stopped, None);
+    assert_eq!(rt.get_var("n"), "5");
+}
+
+/// Output of `prog` over `input` read by the line reader, with FS `fs`.
+fn line_output(prog: &str, fs: &str, input: &str, preserve_cr: bool) -> String {
+    use input::RecordReader;
+    let tokens = lexer::Lexer::new(prog).tokenize().unwrap();
+    let program = parser::Parser::new(tokens).parse().unwrap();
+    let mut rt = runtime::Runtime::new();
+    rt.set_var("FS", fs);
+    let mut exec = action::Executor::new(&program, &mut rt);
+    let sink = Sink::default();
+    exec.set_stdout(Box::new(sink.clone()));
+    exec.set_preserve_cr(preserve_cr);
+    let mut reader = input::line::LineReader::new();
+    reader.set_preserve_cr(preserve_cr);
+    let mut cursor = std::io::Cursor::new(input.as_bytes());
+    let mut record = input::Record::default();
+    exec.run_begin();
+    while reader.read_into(&mut cursor, &mut record).unwrap() {
+        exec.run_record(&record);
+    }
+    exec.run_end();
+    exec.flush_output();
+    sink.text()
+}
+
+#[test]
+fn preserve_cr_passes_line_ends_through() {
+    let mixed = "a,b\r\nc,d\nlast,x\r\n";
+    assert_eq!(line_output("1", ",", mixed, true), mixed);
+    assert_eq!(line_output("1", ",", mixed, false), "a,b\nc,d\nlast,x\n");
+    // Fields never see the \r; a $0 rebuilt from them gets it back.
+    let fields = line_output(r#"{ print $2 "|" NF }"#, ",", mixed, true);
+    assert_eq!(fields, "b|2\nd|2\nx|2\n");
+    let edited = line_output(r#"BEGIN { OFS = "," } { $2 = "X" } 1"#, ",", mixed, true);
+    assert_eq!(edited, "a,X\r\nc,X\nlast,X\r\n");
+    let assigned = line_output(
+        r#"{ $0 = "p,q\r"; print $2 "|" length() }"#,
+        ",",
+        "a\n",
+        true,
+    );
+    assert_eq!(assigned, "q|4\n");
+    // A lone \r, and a last line without a newline, are data either way.
+    for keep in [false, true] {
+        let out = line_output(r#"{ print NF ":" $2 }"#, ",", "old\rmac,x\nend\r", keep);
+        assert_eq!(out, "2:x\n1:\n");
+        assert_eq!(line_output("1", ",", "end\r", keep), "end\r\n");
+    }
+}
+
+#[test]
+fn for_in_walks_keys_in_insertion_order() {
+    let prog = concat!(
+        "{ n[$1]++ }\n",
+        "END { delete n[\"b\"]; n[\"b\"] = 9\n",
+        "  for (k in n) plain = plain k \" \"\n",
+        "  for (k in n) @unsorted un = un k \" \"\n",
+        "  for (k in n) @insert_order io = io k \" \" }",
+    );
+    let lines = ["z", "b", "10", "z", "a", "9", "b"];
+    let rt = eval(prog, &lines);
+    assert_eq!(rt.get_var("plain"), "z 10 a 9 b ");
+    assert_eq!(rt.get_var("un"), rt.get_var("plain"));
+    assert_eq!(rt.get_var("io"), rt.get_var("plain"));
+    assert_eq!(eval(prog, &lines).get_var("plain"), "z 10 a 9 b ");
+}
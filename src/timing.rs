@@ -0,0 +1,126 @@
+//! `--timing`: per-phase wall-clock report written to stderr at exit.
+//!
+//! Phases are measured by taking a lap at each boundary in `main`, so a
+//! disabled report costs one `Option` check per phase and nothing per record.
+
+use std::time::{Duration, Instant};
+
+/// Phase durations, filled in by `lap()` as `main` passes each boundary.
+#[derive(Debug)]
+pub struct Timing {
+    start: Instant,
+    last: Instant,
+    pub parse: Duration,
+    pub begin: Duration,
+    pub records: Duration,
+    pub end: Duration,
+}
+
+impl Timing {
+    pub fn start() -> Self {
+        let now = Instant::now();
+        Timing {
+            start: now,
+            last: now,
+            parse: Duration::ZERO,
+            begin: Duration::ZERO,
+            records: Duration::ZERO,
+            end: Duration::ZERO,
+        }
+    }
+
+    /// Time since the previous lap (or start), restarting the lap clock.
+    pub fn lap(&mut self) -> Duration {
+        let now = Instant::now();
+        let d = now - self.last;
+        self.last = now;
+        d
+    }
+
+    /// Render the report. `bytes` is the on-disk size of the input files,
+    /// `None` when unknown (stdin, pipes).
+    pub fn report(&self, records: u64, bytes: Option<u64>) -> String {
+        let secs = self.records.as_secs_f64();
+        let rate = if secs > 0.0 {
+            format!("{:.0}/s", records as f64 / secs)
+        } else {
+            "-".to_string()
+        };
+        let bytes = match bytes {
+            Some(b) => human_bytes(b),
+            None => "unknown".to_string(),
+        };
+        let mut out = String::from("fk: timing\n");
+        out.push_str(&format!("  parse    {}\n", fmt_secs(self.parse)));
+        out.push_str(&format!("  BEGIN    {}\n", fmt_secs(self.begin)));
+        out.push_str(&format!("  records  {records}\n"));
+        out.push_str(&format!("  loop     {} ({rate})\n", fmt_secs(self.records)));
+        out.push_str(&format!("  input    {bytes}\n"));
+        out.push_str(&format!("  END      {}\n", fmt_secs(self.end)));
+        out.push_str(&format!("  total    {}\n", fmt_secs(self.start.elapsed())));
+        out
+    }
+}
+
+/// Total size of the regular files among `files`; `None` if any input is
+/// stdin or not a regular file.
+pub fn input_bytes(files: &[String]) -> Option<u64> {
+    if files.is_empty() {
+        return None;
+    }
+    let mut total = 0;
+    for f in files {
+        let meta = std::fs::metadata(f).ok().filter(|m| m.is_file())?;
+        total += meta.len();
+    }
+    Some(total)
+}
+
+fn fmt_secs(d: Duration) -> String {
+    format!("{:.6}s", d.as_secs_f64())
+}
+
+fn human_bytes(b: u64) -> String {
+    if b < 1024 {
+        return format!("{b} B");
+    }
+    format!("{} ({b} bytes)", short_bytes(b))
+}
+
+/// `b` in B, KiB, MiB, GiB or TiB with one decimal.
+pub(crate) fn short_bytes(b: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if b < 1024 {
+        return format!("{b} B");
+    }
+    let mut v = b as f64 / 1024.0;
+    let mut unit = 0;
+    while v >= 1024.0 && unit < UNITS.len() - 1 {
+        v /= 1024.0;
+        unit += 1;
+    }
+    format!("{v:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_lists_every_phase() {
+        let mut t = Timing::start();
+        t.records = Duration::from_millis(500);
+        let r = t.report(1000, Some(3 * 1024 * 1024));
+        for field in ["parse", "BEGIN", "records  1000", "2000/s", "END", "total"] {
+            assert!(r.contains(field), "missing {field:?} in {r}");
+        }
+        assert!(r.contains("3.0 MiB (3145728 bytes)"));
+        assert!(t.report(0, None).contains("input    unknown"));
+    }
+
+    #[test]
+    fn input_bytes_unknown_for_stdin() {
+        assert_eq!(input_bytes(&[]), None);
+        assert_eq!(input_bytes(&["-".to_string()]), None);
+    }
+}
@@ -0,0 +1,136 @@
+//! Allocation counts for the record pipeline and string building, measured
+//! with a counting global allocator (which is why this lives in its own
+//! test binary).
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::Cursor;
+
+use fk::action::Executor;
+use fk::input::csv::CsvReader;
+use fk::input::line::LineReader;
+use fk::input::{Record, RecordReader};
+use fk::lexer::Lexer;
+use fk::parser::Parser;
+use fk::runtime::Runtime;
+
+struct Counting;
+
+thread_local! {
+    static ALLOCS: Cell<usize> = const { Cell::new(0) };
+    static BYTES: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.with(|n| n.set(n.get() + 1));
+        BYTES.with(|n| n.set(n.get() + layout.size()));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.with(|n| n.set(n.get() + 1));
+        BYTES.with(|n| n.set(n.get() + new_size));
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Allocations made on this thread while running `f`.
+fn allocs_in(f: impl FnOnce()) -> usize {
+    let before = ALLOCS.with(Cell::get);
+    f();
+    ALLOCS.with(Cell::get) - before
+}
+
+/// Bytes requested on this thread while running `f`.
+fn bytes_in(f: impl FnOnce()) -> usize {
+    let before = BYTES.with(Cell::get);
+    f();
+    BYTES.with(Cell::get) - before
+}
+
+const COLS: usize = 60;
+const ROWS: usize = 500;
+
+/// A wide CSV whose fields keep the same length from row to row.
+fn wide_csv() -> String {
+    let mut out = String::new();
+    for row in 0..ROWS {
+        let fields: Vec<String> = (0..COLS)
+            .map(|col| format!("r{:04}c{:02}", row, col))
+            .collect();
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+#[test]
+fn wide_csv_records_reuse_their_buffers() {
+    let data = wide_csv();
+    let mut cursor = Cursor::new(data.as_bytes());
+    let mut reader = CsvReader::comma();
+    let mut rt = Runtime::new();
+    let mut rec = Record::default();
+
+    // The first record sizes every buffer; the rest refill them.
+    assert!(reader.read_into(&mut cursor, &mut rec).unwrap());
+    rt.set_record_fields(&rec.text, rec.fields.as_deref().unwrap());
+    let reused = allocs_in(|| {
+        while reader.read_into(&mut cursor, &mut rec).unwrap() {
+            rt.set_record_fields(&rec.text, rec.fields.as_deref().unwrap());
+        }
+    });
+    assert_eq!(
+        rt.get_field(COLS),
+        format!("r{:04}c{:02}", ROWS - 1, COLS - 1)
+    );
+    assert_eq!(reused, 0);
+
+    // Fresh records pay for the text, the Vec and every field.
+    let mut cursor = Cursor::new(data.as_bytes());
+    let fresh = allocs_in(|| {
+        while let Some(rec) = reader.next_record(&mut cursor).unwrap() {
+            rt.set_record_fields(&rec.text, rec.fields.as_deref().unwrap());
+        }
+    });
+    assert!(fresh >= ROWS * COLS, "{fresh} allocations");
+}
+
+#[test]
+fn line_records_reuse_their_text() {
+    let data = "alpha beta\n".repeat(ROWS);
+    let mut cursor = Cursor::new(data.as_bytes());
+    let mut reader = LineReader::new();
+    let mut rec = Record::default();
+    assert!(reader.read_into(&mut cursor, &mut rec).unwrap());
+    let mut rows = 1;
+    let reused = allocs_in(|| {
+        while reader.read_into(&mut cursor, &mut rec).unwrap() {
+            assert_eq!(rec.text, "alpha beta");
+            rows += 1;
+        }
+    });
+    assert_eq!(rows, ROWS);
+    assert_eq!(reused, 0);
+}
+
+#[test]
+fn appending_to_a_string_in_a_loop_is_linear() {
+    // 10 MB in 1M appends. Copying the string on every append would
+    // request about 5 TB; appending in place requests a few times 10 MB.
+    let src = r#"BEGIN { for (i = 0; i < 1000000; i++) s = s "0123456789"; n = length(s) }"#;
+    let tokens = Lexer::new(src).tokenize().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    let mut rt = Runtime::new();
+    let bytes = bytes_in(|| Executor::new(&program, &mut rt).run_begin());
+    assert_eq!(rt.get_var("n"), "10000000");
+    assert!(bytes < 100_000_000, "{bytes} bytes");
+}
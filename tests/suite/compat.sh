@@ -3,7 +3,7 @@
 #
 # Runs the same program in both awk and fk, diffs output.
 # Covers: original 100 programs (identical-output subset),
-#         Pement one-liners (P1-P58), two-file idioms (C1-C5).
+#         Pement one-liners (P1-P58), two-file idioms (C1-C6).
 
 SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
 source "$SCRIPT_DIR/_runner.sh"
@@ -433,5 +433,14 @@ compat "C4" "diff — lines in f1 not f2" \
 compat "C5" "update from second file" \
     'NR==FNR{a[$1]=$2; next} {if($1 in a) $2=a[$1]; print}' "$W/lookup.txt" "$W/scores.txt"
 
+compat "C6" "in tests without creating; a reference creates" \
+    'NR==FNR{a[$1]; next} {if (!($1 in a)) miss++; v = b[$1]} END {for (k in a) na++; for (k in b) nb++; if (("x", 1) in a) na = -1; print miss+0, na, nb}' "$W/f1.txt" "$W/f2.txt"
+
+compat "C7" "FILENAME, FNR, NR across files (one given twice)" \
+    '{ print FILENAME, FNR, NR } FILENAME ~ /f2/ && FNR == 1 { print "first of", FILENAME }' "$W/f1.txt" "$W/f2.txt" "$W/f1.txt"
+
+
+compat "C8" "plain getline across a file boundary: NR and FNR" \
+    '{ getline; print FILENAME, NR, FNR, $0 }' "$W/f1.txt" "$W/f2.txt"
 # ════════════════════════════════════════════════════════════════════
 print_summary "compat"
@@ -241,5 +241,625 @@ assert_eq "D43" "filter" "$out" "2,4,6"
 out="$(printf "a\n\nb\n\nc\n" | $FK '{ a[NR]=$1 } END { print join(fltr(a,"length"), ",") }')"
 assert_eq "D44" "fltr alias" "$out" "a,b,c"
 
+# D45 -o table: aligned columns, header from HDR, numeric columns right-aligned
+printf "host,status,bytes\nweb1,200,512\nweb2,404,77\napi,500,1024\n" > "$W/access.csv"
+out="$($FK -i csv -H -o table '$"status" != 200' "$W/access.csv")"
+assert_eq "D45" "-o table" "$out" "$(printf 'host  status  bytes\n────  ──────  ─────\nweb2     404     77\napi      500   1024')"
+
+# D46 -o table: print args become columns, unnamed ones col1..N
+out="$(printf "a 1\nbb 22\n" | $FK -o table '{ print $1, $2 * 10 }')"
+assert_eq "D46" "-o table exprs" "$out" "$(printf 'col1  col2\n────  ────\na       10\nbb     220')"
+
+# D47 -o json: one object per print, keys from HDR, numbers unquoted
+out="$($FK -i csv -H -o json '$"status" != 200' "$W/access.csv")"
+assert_eq "D47" "-o json" "$out" "$(printf '{"host":"web2","status":404,"bytes":77}\n{"host":"api","status":500,"bytes":1024}')"
+
+# D48 -o json round-trips through -i json
+out="$($FK -i csv -H -o json '1' "$W/access.csv" | $FK -i json '{ print $1, $2, $3 }')"
+assert_eq "D48" "-o json | -i json" "$out" "$(printf 'web1 200 512\nweb2 404 77\napi 500 1024')"
+
+# D49 -o md: GitHub markdown table, numeric columns right-aligned, pipes escaped
+out="$(printf "a|b 7\nc 12\n" | $FK -o md '{ print $1, $2 }')"
+assert_eq "D49" "-o md" "$out" "$(printf '| col1 | col2 |\n| ---- | ---: |\n| a\\|b |    7 |\n| c    |   12 |')"
+
+# D50 --in-place=SUFFIX: rewrite a column across two files, keep backups
+printf "a,1\nb,2\n" > "$W/ip1.csv"
+printf "c,3\n" > "$W/ip2.csv"
+$FK -F, -O, --in-place=.bak '{ $2 = $2 * 10; print }' "$W/ip1.csv" "$W/ip2.csv"
+out="$(cat "$W/ip1.csv" "$W/ip2.csv" "$W/ip1.csv.bak" "$W/ip2.csv.bak")"
+assert_eq "D50" "--in-place backups" "$out" "$(printf 'a,10\nb,20\nc,30\na,1\nb,2\nc,3')"
+
+# D51 -I.orig: exit mid-file leaves that file untouched, earlier files rewritten
+printf "x\n" > "$W/ip3.txt"
+printf "p\nSTOP\nq\n" > "$W/ip4.txt"
+$FK -I.orig '/STOP/ { exit } { print toupper($0) }' "$W/ip3.txt" "$W/ip4.txt"
+out="$(cat "$W/ip3.txt" "$W/ip4.txt" "$W/ip3.txt.orig")"
+assert_eq "D51" "-I exit mid-file" "$out" "$(printf 'X\np\nSTOP\nq\nx')"
+
+# D52 --select/--where: named columns from a headered CSV
+out="$($FK -i csv -H --select name,salary --where '$salary > 80000' "$W/hdr.csv")"
+assert_eq "D52" "--select/--where" "$out" "$(printf 'alice 95000\ncarol 105000')"
+
+# D53 --limit with -o json, no --where
+out="$($FK -i csv -H --select=host,3 --limit=2 -o json "$W/access.csv")"
+assert_eq "D53" "--select --limit -o json" "$out" "$(printf '{"host":"web1","bytes":512}\n{"host":"web2","bytes":77}')"
+
+# D54 --where with program text is rejected
+rc=0; $FK --where 'NR > 1' '{ print }' "$W/hdr.csv" >/dev/null 2>&1 || rc=$?
+assert_eq "D54" "--where plus program" "$rc" "2"
+
+# D55 --explain describes the synthesized program
+out="$($FK --explain -H --select name --where '$salary > 80000' "$W/hdr.csv" 2>&1)"
+assert_eq "D55" "--explain synthesized" "$out" "where salary > 80000: select name (CSV, headers, hdr.csv)"
+
+# D56 --sample: fixed srand gives the same records; NR counts all, SAMPLED the kept
+a="$(seq 1 40 | $FK --sample 0.25 'BEGIN { srand(3) } { printf "%s ", $0 } END { print NR, SAMPLED }')"
+b="$(seq 1 40 | $FK --sample 0.25 'BEGIN { srand(3) } { printf "%s ", $0 } END { print NR, SAMPLED }')"
+assert_eq "D56" "--sample reproducible" "$a" "$b"
+out="$(seq 1 40 | $FK --sample 0.25 '{ n++ } END { print NR, SAMPLED == n }')"
+assert_eq "D57" "--sample NR/SAMPLED" "$out" "40 1"
+
+# D58 --sample composes with -i csv -H (header is never sampled away)
+out="$($FK -i csv -H --sample 1 '{ print $name }' "$W/hdr.csv")"
+assert_eq "D58" "--sample -i csv" "$out" "$(printf 'alice\nbob\ncarol')"
+
+# D59 GETLINE_TIMEOUT: a stalled command returns -2 quickly
+start=$(date +%s)
+out="$($FK 'BEGIN { GETLINE_TIMEOUT = 0.2; print ("sleep 5" | getline x) }')"
+elapsed=$(( $(date +%s) - start ))
+assert_eq "D59" "getline timeout" "$out $((elapsed < 3))" "-2 1"
+
+# D60 outfile()/writecsv(): fan a CSV out by $1 into nested directories
+printf 'region,name,note\neast,"Smith, J",ok\nwest,Lee,"say ""hi"""\neast,Kim,x\n' > "$W/fan.csv"
+( cd "$W" && $FK -i csv -H '{ r[1] = $name; r[2] = $note; writecsv(r, outfile("fan/" $region "/rows.csv")) }' fan.csv )
+out="$(cat "$W/fan/east/rows.csv" "$W/fan/west/rows.csv")"
+assert_eq "D60" "writecsv fan-out" "$out" "$(printf '"Smith, J",ok\nKim,x\nLee,"say ""hi"""')"
+
+# D61 print > outfile(): directories are created only through outfile()
+( cd "$W" && $FK '{ print > outfile("split/" substr($1, 1, 1) "/a/b.txt") }' f1.txt )
+out="$(cat "$W"/split/*/a/b.txt | wc -l | tr -d ' ')"
+rc=0; $FK 'BEGIN { print "x" > "'"$W"'/nodir/x.txt" }' 2>/dev/null; [ -d "$W/nodir" ] && rc=1
+assert_eq "D61" "print > outfile()" "$out $rc" "$(wc -l < "$W/f1.txt" | tr -d ' ') 0"
+
+# D62 /dev/stderr, /dev/fd/N and /dev/stdin are handled without opening files
+err="$($FK '{ print "out " $0; print "err " $0 > "/dev/stderr"; printf "fd2\n" > "/dev/fd/2"; print "fd1" > "/dev/fd/1" }' "$W/nums5.txt" 2>&1 >/dev/null | head -3 | tr '\n' ' ')"
+out="$($FK 'NR == 1 { print "out " $0; print "e" > "/dev/stderr"; print close("/dev/stderr") }' "$W/nums5.txt" 2>/dev/null | tr '\n' ' ')"
+in="$(printf 'a\nb\n' | $FK 'BEGIN { while ((getline l < "/dev/stdin") > 0) n = n l; print n }')"
+assert_eq "D62" "special stream names" "$err|$out|$in" "err 10 fd2 err 20 |out 10 0 |ab"
+
+# D63 --timing: report on stderr with every phase; record count matches NR
+err="$($FK --timing '{ n++ } END { print NR > "/dev/null" }' "$W/numbers.txt" 2>&1 >/dev/null)"
+fields="$(printf '%s\n' "$err" | awk '{ print $1 }' | tr '\n' ' ')"
+recs="$(printf '%s\n' "$err" | awk '$1 == "records" { print $2 }')"
+assert_eq "D63" "--timing report" "$fields|$recs" "fk: parse BEGIN records loop input END total |$(wc -l < "$W/numbers.txt" | tr -d ' ')"
+
+# D64 --keep-going: skip missing/unreadable files, keep FNR/ENDFILE right, exit 4
+mkdir -p "$W/kg/adir"; printf 'x\n' > "$W/kg/noperm"; chmod 000 "$W/kg/noperm"
+perm=""; [ -r "$W/kg/noperm" ] && perm="noperm:1 "
+rc=0; out="$(cd "$W/kg" && $FK --keep-going 'ENDFILE { printf "%s:%d ", FILENAME, FNR }' ../f1.txt missing adir noperm ../f2.txt 2>"$W/kg/err")" || rc=$?
+chmod 644 "$W/kg/noperm"
+assert_eq "D64" "--keep-going" "$out|$rc|$(grep -c '^  ' "$W/kg/err")" "../f1.txt:4 ${perm}../f2.txt:4 |4|$([ -n "$perm" ] && echo 2 || echo 3)"
+
+# D65 malformed JSONL records: default, --on-error policies, ERROR block
+printf '{"a":1}\n{"a":2,\n{"a":3}\n' > "$W/bad.jsonl"
+def="$($FK -i json '{ printf "%s:%s ", NR, NF }' "$W/bad.jsonl")"
+skip="$($FK -i json --on-error=skip '{ printf "%s:%s ", NR, $1 }' "$W/bad.jsonl")"
+pass="$($FK -i json --on-error passthrough '{ printf "%s ", NF }' "$W/bad.jsonl")"
+rc=0; abort="$($FK -i json --on-error=abort '{ printf "%s ", $1 }' "$W/bad.jsonl" 2>&1)" || rc=$?
+blk="$($FK -i json 'ERROR { printf "E%s[%s] ", FNR, ERRMSG } { printf "%s ", $1 }' "$W/bad.jsonl")"
+assert_eq "D65" "--on-error / ERROR block" "$def|$skip|$pass|$abort|$rc|$blk" \
+    "1:1 2:1 3:1 |1:1 3:3 |1 1 1 |1 fk: $W/bad.jsonl:2: malformed record: unterminated object|1|1 E2[unterminated object] 3 "
+
+# D66 --validate: inferred types flag late type flips; --emit-schema feeds --validate=
+{ echo "name,qty,price"; for i in 1 2 3 4 5 6 7 8 9 10; do echo "item$i,$i,$i.5"; done
+  echo "bad,x7,3.0"; echo "ok,4,cheap"; } > "$W/val.csv"
+rc=0; inferred="$($FK -H --validate "$W/val.csv" 2>&1)" || rc=$?
+head -11 "$W/val.csv" | $FK --emit-schema > "$W/val.json"
+rc2=0; declared="$($FK -H --validate="$W/val.json" "$W/val.csv" 2>&1)" || rc2=$?
+assert_eq "D66" "--validate / --emit-schema" "$inferred|$rc|$(echo "$declared" | wc -l | tr -d ' ')|$rc2" \
+    "$W/val.csv:12: qty: expected int, got \"x7\"
+$W/val.csv:13: price: expected float, got \"cheap\"|5|2|5"
+
+# D67 --join: inner / left / anti, with and without headers; under -H the
+# first file's repeated names (the key) are suffixed, so JSON keys are unique
+printf 'id,name\n1,ann\n2,bob\n2,bo2\n' > "$W/js.csv"
+printf 'uid,id,amt\nu1,2,10\nu2,3,20\nu3,1,30\n' > "$W/jb.csv"
+printf '1 ann\n2 bob\n' > "$W/js.txt"
+printf 'u1 2\nu2 3\nu3 1\n' > "$W/jb.txt"
+inner="$($FK -H --join 'on=$"id"' "$W/js.csv" "$W/jb.csv" | tr '\n' ';')"
+left="$($FK -H -o json --join on=id --type left "$W/js.csv" "$W/jb.csv" | sed -n 3p)"
+json="$($FK -H -o json --join on=id "$W/js.csv" "$W/jb.csv" | sed -n 1p)"
+anti="$($FK -H --join on=id --type=anti "$W/js.csv" "$W/jb.csv")"
+plain="$($FK --join 'on=$1' --type left "$W/js.txt" "$W/jb.txt" | awk '{ print NF }' | tr '\n' ' ')"
+anti2="$($FK --join=on=2 --type anti "$W/js.txt" "$W/jb.txt" | tr '\n' ' ')"
+assert_eq "D67" "--join inner/left/anti" "$inner|$left|$json|$anti|$plain|$anti2" \
+    'u1 2 10 2 bob;u1 2 10 2 bo2;u3 1 30 1 ann;|{"uid":"u2","id":3,"amt":20,"id_2":"","name":""}|{"uid":"u1","id":2,"amt":10,"id_2":2,"name":"bob"}|u2 3 20|2 2 2 |u1 2 u2 3 u3 1 '
+
+# D68 --state: persist_* / STATE_ARRAYS arrays accumulate across runs
+printf 'a 1\nb 2\na 3\n' > "$W/st1.txt"
+printf 'a 10\nc 5\n' > "$W/st2.txt"
+rm -f "$W/counts.fkstate"
+prog='{ persist_n[$1]++; sum[$1] += $2 } END { for (k in persist_n) print k, persist_n[k], sum[k] }'
+$FK --state "$W/counts.fkstate" -v STATE_ARRAYS=sum "$prog" "$W/st1.txt" > /dev/null
+st="$($FK --state="$W/counts.fkstate" -v STATE_ARRAYS=sum "$prog" "$W/st2.txt" | sort | tr '\n' ';')"
+plain="$($FK --state "$W/counts.fkstate" 'BEGIN { print length(persist_n), length(sum) }')"
+rc=0; $FK --state "$W/st1.txt" 'BEGIN { }' 2>/dev/null || rc=$?
+assert_eq "D68" "--state load/save" "$st|$plain|$rc" "a 3 14;b 1 2;c 1 5;|3 3|2"
+
+# D69 --check: lint without input, exit 0/2, JSON diagnostics
+rc1=0; $FK --check '{ print $1 }' < /dev/null || rc1=$?
+rc2=0; $FK --check '{ print (' 2>/dev/null || rc2=$?
+rc3=0; $FK --check '/a(/' 2>/dev/null || rc3=$?
+warn="$($FK --check '{ print x }' 2>&1)"
+json="$($FK --check=json 'function f() { return 1 } { print $2 }')"
+assert_eq "D69" "--check exit codes and JSON" "$rc1|$rc2|$rc3|$warn|$json" \
+    '0|2|2|fk: warning: variable `x` is read but never assigned [uninitialized]|{"ok":true,"diagnostics":[{"severity":"warning","code":"unused-function","message":"function `f` is never called","line":null,"col":null}],"explain":"select column 2","info":{"needs_fields":true,"needs_nf":false,"emits_output_in_rules":true,"max_field":2,"regex_literals":[]}}'
+
+# D70 --format from stdin, --check-format diff + exit code
+fmt="$(echo '{print $1}' | $FK --format | tr '\n' ';')"
+fmt2="$(echo '{print (1+2)*3}' | $FK --format - | $FK --format | tr '\n' ';')"
+rc1=0; printf '{\n  print $1\n}\n' | $FK --check-format || rc1=$?
+rc2=0; cf="$(echo '{print $1}' | $FK --check-format 2>&1)" || rc2=$?
+assert_eq "D70" "--format stdin / --check-format" "$fmt|$fmt2|$rc1|$rc2|$(echo "$cf" | tr '\n' ';')" \
+    '{;  print $1;};|{;  print (1 + 2) * 3;};|0|1|--- <stdin>;+++ <stdin> (formatted);@@ -1 +1,3 @@;-{print $1};+{;+  print $1;+};'
+
+# D71 @namespace: two -f libraries with the same helper name, builtin shadowing
+printf '@namespace "a"\nfunction helper(x) { return "a:" x }\nfunction run(v) { return helper(v) }\n' > "$W/ns_a.awk"
+printf '@namespace "b"\nfunction helper(x) { return "b:" x }\nfunction run(v) { return helper(v) }\n' > "$W/ns_b.awk"
+printf '{ print a::run($1), b::run($1) }\n' > "$W/ns_main.awk"
+ns="$(echo hi | $FK -f "$W/ns_a.awk" -f "$W/ns_b.awk" -f "$W/ns_main.awk")"
+rc=0; err="$($FK 'function join(a) { return a } BEGIN { }' 2>&1)" || rc=$?
+assert_eq "D71" "@namespace libraries + no builtin shadowing" "$ns|$rc|$err" \
+    'a:hi b:hi|2|fk: parse error: 1:10: function `join` would shadow a builtin; pick another name'
+
+# D72 --regex-limit: oversized dynamic regex reported once, limit can be raised
+big="$(seq 3 | $FK -v p='(a{1,1000}){1,1000}' '$0 ~ p { n++ } END { print n + 0 }' 2>&1 | tr '\n' ';')"
+small="$(seq 2 | $FK --regex-limit 1K -v p='[0-9]{40}' '$0 ~ p' 2>&1 | wc -l | tr -d ' ')"
+raised="$(printf 'aaa\n' | $FK --regex-limit=64M -v p='^(a{1,100}){1,100}$' '$0 ~ p')"
+assert_eq "D72" "--regex-limit and one-time complexity error" "$big|$small|$raised" \
+    'fk: regex /(a{1,1000}){1,1000}/ is too complex: compiled size exceeds 10485760 bytes (raise with --regex-limit);0;|1|aaa'
+
+# D73 -e fragments: joined in order (with -f and --where), errors name the fragment
+printf 'a,5,20\nb,6,3\nc,7,11\n' > "$W/frag.csv"
+frag="$($FK -e 'BEGIN { FS = "," }' -e '$3 > 10' -e '{ print $1 }' "$W/frag.csv" | tr '\n' ';')"
+whole="$($FK 'BEGIN { FS = "," }
+$3 > 10
+{ print $1 }' "$W/frag.csv" | tr '\n' ';')"
+printf 'function up(s) { return toupper(s) }\n' > "$W/frag_lib.awk"
+mixed="$($FK -f "$W/frag_lib.awk" -e 'BEGIN { FS = "," }' -e '{ print up($1) }' "$W/frag.csv" | tr '\n' ';')"
+where="$($FK -e 'BEGIN { FS = "," }' --where '$2 > 5' --select 1 "$W/frag.csv" | tr '\n' ';')"
+rc=0; err="$($FK -e 'BEGIN { x = 1 }' -e '$3 >' "$W/frag.csv" 2>&1)" || rc=$?
+assert_eq "D73" "-e fragments" "$frag|$whole|$mixed|$where|$rc|$err" \
+    'a;c;|a;c;|A;B;C;|b;c;|2|fk: parse error in -e #2: 1:5: unexpected token: Eof'
+
+# D74 display-width padding: CJK, combining accent and ZWJ emoji line up
+wide="$($FK 'BEGIN { printf "[%-6s][%4s]", "日本", "é"; print lpad("👨‍👩‍👧", 4, ".") }')"
+chars="$($FK -v WIDTHMODE=chars 'BEGIN { print lpad("日本", 4, ".") }')"
+assert_eq "D74" "display-width padding" "$wide|$chars" '[日本  ][   é]..👨‍👩‍👧|..日本'
+
+# D75 -y / -w: case-insensitive and whole-word regex literals
+printf 'ERROR disk\nerror net\nterror\nErrors: 2\nok\n' > "$W/grep.log"
+icase="$($FK -y '/error/ { n++ } END { print n }' "$W/grep.log")"
+word="$($FK -w '/error/' "$W/grep.log" | tr '\n' ';')"
+both="$($FK -y -w '/error/ { n++ } END { print n }' "$W/grep.log")"
+expl="$($FK -y -w --explain '/error/' "$W/grep.log")"
+assert_eq "D75" "-y / -w filters" "$icase|$word|$both|$expl" \
+    '4|error net;|2|where /error/ (ignore case, whole words, grep.log)'
+
+# D76 END keeps the last record, NF, FNR and FILENAME (stdin too)
+printf 'a b c\nd e\n' > "$W/end1.txt"
+printf 'one\n' > "$W/end2.txt"
+files="$($FK 'END { print NR, NF, $1, FNR, FILENAME }' "$W/end2.txt" "$W/end1.txt")"
+stdin="$(printf 'x y\nlast one here\n' | $FK 'END { print NR, NF, $0 }')"
+early="$($FK 'BEGIN { exit } END { print NF "[" $0 "]" }' "$W/end1.txt")"
+assert_eq "D76" "END sees the last record" "$files|$stdin|$early" \
+    "3 2 d 2 $W/end1.txt|2 3 last one here|0[]"
+
+# D77 getline in BEGIN consumes the main input (same reader, no re-delivery,
+# and under -H the header row is not data)
+begin="$(printf '1\n2\n3\n4\n5\n' | $FK 'BEGIN { getline; getline x; print "begin", $0, x } { n++ } END { print n, NR }' | tr '\n' ';')"
+printf 'name,note\n"Smith, J",a\nLee,b\n' > "$W/getline.csv"
+csv="$($FK 'BEGIN { getline; getline; print NF, $1 } { print FNR ":" $1 }' "$W/getline.csv" | tr '\n' ';')"
+hdr="$(printf 'h,i\n1,2\n3,4\n' | $FK -i csv -H 'BEGIN { getline; print $0, NR } { print NR, $"h" }' | tr '\n' ';')"
+assert_eq "D77" "getline in BEGIN" "$begin|$csv|$hdr" 'begin 1 2;3 5;|2 Smith, J;3:Lee;|1,2 2;3 3;'
+
+# D78 -H with JSON: HDR from keys, every object is data, missing keys empty
+# (also a key that only a later object has)
+printf '{"user":"ann","latency":12}\n{"latency":7,"path":"/x","user":"bob"}\n{"user":"cy"}\n' > "$W/mixed.jsonl"
+out="$($FK -i json -H '{ print NR, $"user", $"latency" "|" $latency } END { print HDR[3] }' "$W/mixed.jsonl" | tr '\n' ';')"
+late="$(printf '{"a":1}\n{"a":2,"extra":5}\n' | $FK -i json -H '{ print "[" $"extra" "]" }' | tr '\n' ';')"
+assert_eq "D78" "-H with JSON keys" "$out|$late" '1 ann 12|12;2 bob 7|7;3 cy |;path;|[];[5];'
+
+# D79 --version/-V exit 0 without a program; feature() agrees with the features line
+rc=0; ver="$($FK --version < /dev/null)" || rc=$?
+feats="$($FK -V | sed -n 's/^features: //p')"
+has="$($FK 'BEGIN { print feature("parquet") feature("nosuch") }')"
+case " $feats " in *" parquet "*) want=10 ;; *) want=00 ;; esac
+assert_eq "D79" "--version and feature()" "$rc|${ver%% *}|$has" "0|fk|$want"
+
+# D80 PROCINFO: pid seen by a child shell, open redirections tracked until close()
+out="$($FK -v f="$W/procinfo.txt" 'BEGIN {
+    "echo $PPID" | getline p; print (p == PROCINFO["pid"]), PROCINFO["echo $PPID", "type"]
+    print "x" > f; print PROCINFO[f, "type"]; close(f); print ((f, "type") in PROCINFO)
+}' | tr '\n' ';')"
+assert_eq "D80" "PROCINFO pid and streams" "$out" '1 pipe;file;0;'
+
+# D81 -o tsv escapes tabs/newlines/backslashes; -i tsv decodes them (--tsv-raw keeps them)
+printf 'id\tnote\n1\tx\n' > "$W/esc.tsv"
+$FK -i tsv -H -o tsv '{ print $id, "a\tb"; print $id + 1, "a\\tb" }' "$W/esc.tsv" > "$W/esc_out.tsv"
+out="$($FK -i tsv -H '{ print $id, length($2), ($2 ~ /\t/) }' "$W/esc_out.tsv" | tr '\n' ';')"
+raw="$($FK -i tsv --tsv-raw 'NR > 1 { print $2 }' "$W/esc_out.tsv" | tr '\n' ';')"
+assert_eq "D81" "-o tsv round trip" "$out|$raw" '1 3 1;2 4 0;|a\tb;a\\tb;'
+
+# D82 constant folding keeps runtime semantics; a constant zero divisor is left to the executor
+out="$(printf 'a\nb\n' | $FK '/z/ { print 1 / 0 } { printf "%s-" "%d;", $1, 60 * 60 } END { print 7 / 0 }' 2>&1 | tr '\n' ';')"
+assert_eq "D82" "constant folding" "$out" 'a-3600;b-3600;0;'
+
+# D83 ARRAY_SPILL_THRESHOLD moves big arrays to disk; print arr, stats and PROCINFO still work
+out="$(seq 1 6 | $FK -v ARRAY_SPILL_THRESHOLD=2 '{ a[NR] = $1 * 2 } END { print a; print PROCINFO["a", "spilled"], length(a), mean(a) }' | tr '\n' ';')"
+assert_eq "D83" "array spill" "$out" '2;4;6;8;10;12;3 6 7;'
+
+# D84 gsub on $0 re-splits fields lazily; NF and fields follow the new record
+out="$(printf 'a,b,c\nd e,f\n' | $FK '{ gsub(/,/, " "); print NF, $2; $0 = "p:q"; FS = ":"; print $1, NF }' | tr '\n' ';')"
+assert_eq "D84" "lazy \$0 re-split" "$out" '3 b;p:q 1;1 ;p 2;'
+
+# D85 print with multi-byte OFS/ORS, to stdout and through a redirect
+out="$(printf 'a b\nc d\n' | $FK 'BEGIN { OFS = "→"; ORS = "¶\n" } { print $2, $1; print $1, NR > "/dev/stdout" }' | tr '\n' ';')"
+assert_eq "D85" "print OFS/ORS" "$out" 'b→a¶;a→1¶;d→c¶;c→2¶;'
+
+# D86 --explain names output files, pipes and getline sources
+out="$($FK --explain '{ print $1 > $2 ".txt" }');$($FK --explain '{ print $2, $1 | "sort -k2 -n" }');$($FK --explain 'BEGIN { while ((getline l < "users.csv") > 0) { split(l, f, ","); n[f[1]] = f[2] } } { print $1, n[$2] }');$($FK --explain '{ print $1 > "out.txt"; print $2 | "sort"; "date" | getline d; getline l < "cfg" }')"
+assert_eq "D86" "--explain side effects" "$out" 'select column 1 → per-key files;select columns 2, 1 → pipes to sort;select column 1, reads users.csv;select…, reads from date, reads cfg → out.txt, pipes to sort'
+
+# D87 --format-width wraps print arguments; --format-align lines up assignments
+out="$(echo '{ a = 1; bb = 2; print "alpha", "beta", "gamma" }' | $FK --format --format-width 20 --format-align | tr '\n' ';')"
+assert_eq "D87" "--format-width/--format-align" "$out" '{;  a  = 1;  bb = 2;  print "alpha",;      "beta",;      "gamma";};'
+
+# D88 close() rewinds a getline file and reruns a command
+printf 'l1\nl2\nl3\n' > "$W/three.txt"
+out="$($FK -v f="$W/three.txt" 'BEGIN { for (pass = 1; pass <= 2; pass++) { while ((getline l < f) > 0) printf "%s ", l; printf "(%d) ", close(f) }; "exit 4" | getline; print close("exit 4"), close(f) }')"
+assert_eq "D88" "close + getline reread" "$out" 'l1 l2 l3 (0) l1 l2 l3 (0) 4 -1'
+
+# D89 fields() copies the record (or a slice of it) into an array
+out="$(printf 'a b c d\n1 2\n' | $FK '{ n = fields(f, 2); print n, join(f, "|") }' | tr '\n' ';')"
+assert_eq "D89" "fields(arr, from)" "$out" '3 b|c|d;1 2;'
+
+# D90 -v name[key]=value builds an array; a name cannot be scalar and array
+out="$($FK -v 'tier[gold]=3' -v 'tier[a=b]=2' -v 'pair[x,y]=1' 'BEGIN { print tier["gold"] + tier["a=b"], pair["x", "y"] }')"
+err="$($FK -v tier=1 -v 'tier[gold]=3' 'BEGIN { }' 2>&1; echo "rc=$?")"
+assert_eq "D90" "-v array elements" "$out;$err" '5 1;fk: -v assigns tier both as a scalar and as an array
+rc=1'
+
+# D91 ERRNO explains a failed getline; a later success leaves it
+out="$($FK 'BEGIN { r = getline l < "/no/such/file"; print r, ERRNO; getline l < "/dev/null"; print ERRNO }' | tr '\n' ';')"
+assert_eq "D91" "ERRNO after getline" "$out" '-1 No such file or directory;No such file or directory;'
+
+# D92 BYTEPOS/RECBYTES checkpoint a file; --seek resumes after it
+printf 'one\r\ntwo\r\n' > "$W/log.txt"
+ckpt="$($FK 'END { print BYTEPOS + RECBYTES }' "$W/log.txt")"
+printf 'three\r\nfour\n' >> "$W/log.txt"
+out="$($FK --seek "$ckpt" '{ print BYTEPOS, RECBYTES, $0 }' "$W/log.txt" | tr -d '\r' | tr '\n' ';')"
+assert_eq "D92" "BYTEPOS + --seek" "$ckpt;$out" '10;10 7 three;17 5 four;'
+
+# D93 --dump-tokens / --dump-ast show what the lexer and parser saw
+out="$($FK --dump-tokens '$2 > 1' | tr -s ' ' | tr '\n' ';')|$($FK --dump-ast 'NR > 1 { print $1 > "out" }' | tr '\n' ';')"
+assert_eq "D93" "--dump-tokens/--dump-ast" "$out" '1:1 Field 2;1:4 Gt;1:6 Number 1;1:7 Eof;|(rule (> NR 1);  (print ($ 1) (> "out")));'
+
+# D94 --max-depth: too-deep calls skip the record loudly (fatal under
+# --strict); a raised limit recurses
+deep='function f(n) { return n <= 0 ? 0 : 1 + f(n-1) } { print $1, f($1) }'
+out="$(printf '3\n300\n4\n' | $FK "$deep" 2>/dev/null | tr '\n' ';')|$(echo 300 | $FK "$deep" 2>&1 >/dev/null)|$(echo 4999 | $FK --max-depth 5000 "$deep")|$(printf '3\n300\n4\n' | $FK --strict "$deep" 2>&1; echo "rc=$?")"
+assert_eq "D94" "--max-depth" "$out" '3 3;4 4;|fk: call depth 200 exceeded in function f (NR=1, FILENAME=)|4999 4999|fk: call depth 200 exceeded in function f (NR=2, FILENAME=)
+3 3
+rc=2'
+
+# D95 --line-buffered / FK_UNBUFFERED / flushmode: same output, flushed per print
+out="$(printf 'a\nb\n' | $FK --line-buffered 'NR == 2 { exit } { m[$1] = 1; print; printf "<%s>", $1; print m }' | tr '\n' ';')|$(printf 'a\n' | FK_UNBUFFERED=1 $FK '{ print flushmode("block"), flushmode() }')"
+assert_eq "D95" "--line-buffered/flushmode" "$out" 'a;<a>a;|line block'
+
+# D96 printf ' flag groups thousands; GROUPSEP / DECIMALSEP localize output only
+out="$(echo '-1234567.891 1.5' | $FK '{ printf "%\x27d %\x27.2f %\x27012d|", $1, $1, $1; GROUPSEP = "."; DECIMALSEP = ","; printf "%\x27.2f %s\n", $1, $2 + 1 }')"
+assert_eq "D96" "printf %'d + GROUPSEP" "$out" '-1,234,567 -1,234,567.89 -001,234,567|-1.234.567,89 2.5'
+
+# D97 escape(): literal regex from input, sh-safe words, json/html round trip
+out="$(printf 'a.c\tabc a.c\n' | $FK -F'\t' '{ r = ($2 ~ escape($1, "regex")) (("abc" ~ escape($1, "regex")) ? "!" : ""); s = escape("it'\''s $(id)", "shell"); j = escape("q\"\n", "json"); print r, s, j, unescape(j, "json") == "q\"\n", escape("<&>", "html") }')"
+assert_eq "D97" "escape/unescape" "$out" "1 'it'\\''s \$(id)' \"q\\\"\\n\" 1 &lt;&amp;&gt;"
+
+# D98 rx(): %s arguments match literally
+out="$(printf 'a.b=1\naxb=2\n.*=3\n' | $FK -F= '$0 ~ rx("^%s=%s$", "a.b", $2) || $0 ~ rx("^%s=", ".*") { print $2 }')"
+assert_eq "D98" "rx escapes arguments" "$out" "1
+3"
+
+# D99 has()/hdrlen(): header columns beyond a short record are absent
+out="$(printf 'id,name,email\n1,ann,a@x\n2,bob\n3\n' | $FK -H -i csv '{ print hdrlen(), has("name"), has("email"), has("phone") }')"
+assert_eq "D99" "has and hdrlen" "$out" "3 1 1 0
+3 1 0 0
+3 0 0 0"
+
+# D100 CSVSEP/CSVQUOTE/CSVCOMMENT, switched per file in BEGINFILE
+d100="$(mktemp -d)"
+printf '# lab export\nid;name\n1;"O;Brien\n' > "$d100/semi.csv"
+printf '#id,name\n2,"Smith, J"\n' > "$d100/comma.csv"
+out="$($FK -i csv 'BEGINFILE { semi = FILENAME ~ /semi/; CSVSEP = semi ? ";" : ""; CSVQUOTE = semi ? "" : "\""; CSVCOMMENT = semi ? "#" : "" } { print FNR, NF, $2 }' "$d100/semi.csv" "$d100/comma.csv")"
+rm -rf "$d100"
+assert_eq "D100" "CSV dialect variables" "$out" "1 2 name
+2 3 \"O
+1 2 name
+2 2 Smith, J"
+
+# D101 uniqsplit()/joinuniq(): order-preserving dedupe of a joined list
+out="$(printf 'a,b,a,c\ntag|tag|x\n' | $FK -F'\t' '{ n = uniqsplit($0, u, NR == 1 ? "," : "|"); print n, u[n], joinuniq($0, NR == 1 ? "," : "|", ";") }')"
+assert_eq "D101" "uniqsplit and joinuniq" "$out" "3 c a;b;c
+2 x tag;x"
+
+# D102 --glob: sorted expansion, ** recursion, ARGV, no-match error
+d102="$(mktemp -d)"
+mkdir -p "$d102/x/y"
+printf 'b\n' > "$d102/b.log"; printf 'a\n' > "$d102/a.log"; printf 'c\n' > "$d102/x/y/c.log"
+out="$(cd "$d102" && $FK --glob 'BEGIN { printf "%s|", ARGC } { printf "%s ", $0 } END { print "" }' '*.log' '**/c.log'; $FK --glob 1 'none*.log' 2>&1; echo "rc=$?")"
+rm -rf "$d102"
+assert_eq "D102" "--glob expansion" "$out" "4|a b c 
+fk: no files match 'none*.log' (--nullglob to allow)
+rc=2"
+
+# D103 --max-records / --max-runtime: END runs, truncated marker, exit 124
+out="$(seq 10 | $FK --max-records 3 '{ s += $1 } END { print NR, s, PROCINFO["truncated"] }'; seq 10 | $FK --max-runtime 0 'END { print NR, PROCINFO["truncated"] }'; echo "rc=$?")"
+assert_eq "D103" "--max-records and --max-runtime" "$out" "3 6 1
+0 1
+rc=124"
+
+# D104 print arr shows SUBSEP in composite keys as DUMPSEP (default ",")
+out="$($FK 'BEGIN { a["x","y"] = 1; a["x","z"] = 2; print a; DUMPSEP = "::"; print a }')"
+assert_eq "D104" "print arr composite keys" "$out" "x,y
+x,z
+x::y
+x::z"
+
+# D105 --progress draws nothing when stderr is not a terminal
+out="$(printf '1\n2\n3\n' | $FK --progress '{ s += $1 } END { print s }' 2>&1; printf '1\n2\n' | $FK --progress 'END { print NR }' 2>&1)"
+assert_eq "D105" "--progress silent off a terminal" "$out" "6
+2"
+
+# D106 assignment through $"name" in header mode; unknown names go to stderr
+out="$(printf 'name,price,qty\na,10\nb,20,5\n' | $FK -H -i csv 'BEGIN { OFS = "," } { $"price" *= 2; $"qty" += 1; $"nope" = 1; print }' 2>&1)"
+assert_eq "D106" "named field assignment" "$out" 'fk: cannot assign to $"nope": no such column in HDR
+a,20,1
+b,40,6'
+
+# D107 printf/sprintf argument counts: warned per call, output
+# unchanged; --strict makes it an error
+out="$(printf 'a b\nc d\n' | $FK '{ printf "%s %s|\n", $1; s = s sprintf("%s;", $1, $2) } END { print s }' 2>&1; printf 'a\n' | $FK --strict '{ printf "%s %s\n", $1 }' 2>&1; echo "rc=$?")"
+assert_eq "D107" "printf argument count" "$out" 'fk: printf "%s %s|\n": no argument for conversion 2 (%s)
+fk: sprintf "%s;": 1 extra argument ignored
+fk: printf "%s %s|\n": no argument for conversion 2 (%s)
+fk: sprintf "%s;": 1 extra argument ignored
+a |
+c |
+a;c;
+fk: printf "%s %s\n": no argument for conversion 2 (%s)
+rc=2'
+
+# D108 @include "std/..." loads embedded modules; a failed assert exits 1
+out="$($FK --list-std | head -1; printf 'a 1\nb 2\n' | $FK '@include "std/assert"
+@include "std/strings"
+{ assert::equal($2, NR, "row"); print strings::title($1 " x") }' 2>&1; printf '3\n' | $FK '@include "std/assert"
+{ assert::equal($1, 4, "three") }' 2>&1; echo "rc=$?")"
+assert_eq "D108" "@include std library" "$out" 'std/assert   checks for test scripts
+A X
+B X
+assertion failed: three: got "3", want "4"
+rc=1'
+
+# D109 BEGINFILE sees the new FILENAME with FNR 0; a file named twice is two files (as gawk);
+# a plain getline that reads into the next file runs the transition itself, once
+d109="$(mktemp -d)"
+printf 'a1\na2\n' > "$d109/a"; printf 'b1\n' > "$d109/b"
+out="$(cd "$d109" && $FK 'BEGINFILE { print "begin", FILENAME, FNR, NR } { print FILENAME, FNR, NR } ENDFILE { print "end", FILENAME, FNR, NR }' a b a; printf 'x\n' | $FK 'BEGINFILE { print "stdin", FNR } ENDFILE { print "done", FNR }'
+    cd "$d109" && $FK 'BEGINFILE { print "begin", FILENAME, FNR, NR } { getline; print FILENAME, FNR, NR, $0 } ENDFILE { print "end", FILENAME, FNR, NR }' a b a)"
+assert_eq "D109" "FILENAME/FNR/NR at file boundaries" "$out" 'begin a 0 0
+a 1 1
+a 2 2
+end a 2 2
+begin b 0 2
+b 1 3
+end b 1 3
+begin a 0 3
+a 1 4
+a 2 5
+end a 2 5
+stdin 0
+done 1
+begin a 0 0
+a 2 2 a2
+end a 2 2
+begin b 0 2
+end b 1 3
+begin a 0 3
+a 1 4 a1
+a 2 5 a2
+end a 2 5'
+rm -rf "$d109"
+
+# D110 options after -- go to the script (cliargs), operands are still read as input
+d110="$(mktemp -d)"
+printf 'a 3\nb 9\nc 5\nd 7\n' > "$d110/in.txt"
+printf 'BEGIN { cliargs(opt); top = opt["top"] + 0 }\n$2 >= opt["min"] && ++n <= top { print FKARGS[1], $1 }\n' > "$d110/tool.fk"
+out="$($FK -f "$d110/tool.fk" -- --top 2 --min=5 "$d110/in.txt")"
+assert_eq "D110" "cliargs() with --opts after --" "$out" '--top b
+--top c'
+rm -rf "$d110"
+
+# D111 --emit-header re-emits the CSV header (quoted) before the first printed record
+d111="$(mktemp -d)"
+printf 'id,"name, full",n\n1,"Smith, J",5\n2,Bo,1\n3,Al,7\n' > "$d111/in.csv"
+out="$($FK -H --emit-header '$n > 2' "$d111/in.csv"; $FK -H --emit-header '$n > 9' "$d111/in.csv"; echo "rc=$?")"
+assert_eq "D111" "--emit-header with -H CSV" "$out" 'id,"name, full",n
+1,"Smith, J",5
+3,Al,7
+rc=0'
+rm -rf "$d111"
+
+# D112 a UTF-8 BOM does not stick to the first header name; UTF-16 is transcoded
+d112="$(mktemp -d)"
+printf '\357\273\277name,n\nx,1\n' > "$d112/bom.csv"
+printf '\377\376n\000a\000m\000e\000,\000n\000\n\000y\000,\0002\000\n\000' > "$d112/le.csv"
+tail -c +3 "$d112/le.csv" > "$d112/nobom.csv"
+out="$($FK -H '{ print $"name", $n }' "$d112/bom.csv"; $FK -H '{ print $"name", $n }' "$d112/le.csv"
+       $FK --encoding utf-16le -H '{ print $"name" }' "$d112/nobom.csv")"
+assert_eq "D112" "BOM and UTF-16 input" "$out" 'x 1
+y 2
+y'
+rm -rf "$d112"
+
+# D113 --preserve-cr passes CRLF and mixed line ends through byte for byte
+d113="$(mktemp -d)"
+printf 'id,name\r\n1,"a, b"\r\n2,c\n' > "$d113/in.csv"
+out="$($FK --preserve-cr 1 "$d113/in.csv" | cmp - "$d113/in.csv" && echo same
+       $FK --preserve-cr -i csv 1 "$d113/in.csv" | cmp - "$d113/in.csv" && echo same
+       $FK --preserve-cr -F, -v OFS=, '$1 == 2 { $2 = "C" } NR > 2 || $2 ~ /e$/' "$d113/in.csv" | od -An -c
+       $FK 1 "$d113/in.csv" | od -An -c)"
+assert_eq "D113" "--preserve-cr passthrough" "$(echo $out)" 'same same i d , n a m e \r \n 2 , C \n i d , n a m e \n 1 , " a , b " \n 2 , c \n'
+rm -rf "$d113"
+
+# D114 --max-pipes closes the least recently used pipe; its command reruns on the next print
+d114="$(mktemp -d)"
+out="$($FK --max-pipes 2 -v d="$d114" 'BEGIN { for (i = 1; i <= 9; i++) { print i | ("cat >> " d "/" i % 3); n = PROCINFO["open_pipes"] } print n }'
+       cat "$d114/0" "$d114/1" "$d114/2" | tr '\n' ' '
+       $FK --max-pipes x 1 </dev/null 2>&1; echo "rc=$?")"
+assert_eq "D114" "--max-pipes LRU" "$out" '2
+3 6 9 1 4 7 2 5 8 fk: --max-pipes requires a pipe count (0 for no limit)
+rc=1'
+rm -rf "$d114"
+
+# D115 a repeated warning prints three times plus a summary; WARNINGS counts them all; --quiet hides them
+out="$(seq 1000 | $FK '{ x = nope($1 > 0) } END { print WARNINGS }' 2>&1
+       seq 5 | $FK --quiet '{ x = nope() } END { print WARNINGS }' 2>&1)"
+assert_eq "D115" "warning dedup and WARNINGS" "$out" 'fk: unknown function: nope
+fk: unknown function: nope
+fk: unknown function: nope
+1000
+fk: … suppressed 997 more repeated warnings
+5'
+
+# D116 header names are not globals: a "count" column leaves count++ alone; --header-vars restores them
+out="$(printf 'count,sum\n5,1\n7,2\n' | $FK -i csv -H '{ count++; sum += $"sum"; c += $count } END { print count, sum, c }' 2>&1
+       printf 'count,sum\n5,1\n' | $FK --header-vars -i csv -H '{ print count, sum }')"
+assert_eq "D116" "header names vs program variables" "$out" 'fk: column "count" is also a variable in the program; $"count" is the column
+fk: column "sum" is also a variable in the program; $"sum" is the column
+2 3 7
+1 2'
+
+# D117 for-in without a modifier follows insertion order, the same on every run
+prog='{ n[$1]++ } END { for (k in n) printf "%s=%d ", k, n[k]; for (k in n) @unsorted printf "%s ", k; print "" }'
+out="$(printf 'pear\nfig\napple\nfig\n10\n9\n' | $FK "$prog"; printf 'pear\nfig\napple\nfig\n10\n9\n' | $FK "$prog")"
+assert_eq "D117" "insertion-ordered for-in" "$out" 'pear=1 fig=2 apple=1 10=1 9=1 pear fig apple 10 9 
+pear=1 fig=2 apple=1 10=1 9=1 pear fig apple 10 9 '
+
+# D118 --every N: same records as NR % N == 0, NR counts all, SKIPPED the rest; -H header still read
+a="$(seq 1 50 | $FK 'NR % 7 == 0 { printf "%s ", $1 * 2 } END { print NR }')"
+b="$(seq 1 50 | $FK --every 7 '{ printf "%s ", $1 * 2 } END { print NR }')"
+assert_eq "D118" "--every vs NR % N" "$b" "$a"
+out="$(printf 'n,sq\n1,1\n2,4\n3,9\n4,16\n5,25\n' | $FK -i csv -H --every 2 '{ printf "%s=%s ", NR, $sq } END { print NR, SKIPPED }'
+       printf 'n,sq\n1,1\n2,4\n3,9\n4,16\n5,25\n' | $FK -i csv -H 'NR % 2 == 0 { printf "%s=%s ", NR, $sq } END { print NR }')"
+assert_eq "D119" "--every -H NR/SKIPPED" "$out" "2=1 4=9 6=25 6 2
+2=1 4=9 6=25 6"
+
+# D120 NR % k == n runs on the fast path with the same output as the interpreter
+for prog in 'NR % 10 == 1' '3 == NR % 4 { print }' 'every 5' 'NR % 3 == 7'; do
+    fast="$(seq 1 95 | $FK "$prog"; printf 'a\r\nb\r\nc\r\n' | $FK --preserve-cr "$prog" | od -c)"
+    slow="$(seq 1 95 | $FK "BEGIN { } $prog"; printf 'a\r\nb\r\nc\r\n' | $FK --preserve-cr "BEGIN { } $prog" | od -c)"
+    assert_eq "D120" "stride fast path: $prog" "$fast" "$slow"
+done
+
+# D121 jpretty/jmin: indent, verbatim numbers and escapes, bad JSON unchanged with one warning
+out="$(printf '{"id":12345678901234567890,"s":"\\u00e9","a":[1,{}]}\nnope\n[1 ,2]\nnope\n' | $FK '{ print jpretty($0, 1); print jmin(jpretty($0)) }' 2>&1)"
+assert_eq "D121" "jpretty/jmin" "$out" 'fk: jpretty: not valid JSON, returned unchanged
+{
+ "id": 12345678901234567890,
+ "s": "\u00e9",
+ "a": [
+  1,
+  {}
+ ]
+}
+{"id":12345678901234567890,"s":"\u00e9","a":[1,{}]}
+nope
+nope
+[
+ 1,
+ 2
+]
+[1,2]
+nope
+nope'
+
+# D122 --describe on parquet reads the footer: exact rows, row groups, min/max; --emit-schema types
+if $FK 'BEGIN { exit !feature("parquet") }'; then
+    out="$($FK --describe "$ROOT_DIR/tests/data/sales.parquet" 2>&1 | sed 's/\x1b\[[0-9;]*m//g' | awk 'NR == 2 { print $2, $6, $9, $11 } /day/ { print $6, $7 }'
+           $FK --emit-schema "$ROOT_DIR/tests/data/sales.parquet" | tr -d ' \n')"
+    assert_eq "D122" "--describe parquet footer" "$out" 'parquet 5 2 601
+2024-01-01 2024-03-08
+{"id":"int","region":"string","amount":"float","day":"string"}'
+fi
+
+# D123 truthiness: string constants by emptiness, input values by number when numeric;
+# ARGV[0] is the program name, not the path it ran from
+out="$($FK 'BEGIN { print ("0" ? 1 : 2), ("" ? 1 : 2), (0 ? 1 : 2) }'
+       printf '0\n0.0\n00\nx\n 1 \n' | $FK '{ printf "%s", ($1 ? "t" : "f") } END { print "" }'
+       $FK -v z=0.0 -v s=abc 'BEGIN { print (z ? 1 : 2), (s ? 1 : 2) }'
+       echo 0 | $FK 'BEGIN { getline v; print (v ? 1 : 2); n = split("0 1 x", a); print (a[1] ? 1 : 2) (a[2] ? 1 : 2) (a[3] ? 1 : 2) }'
+       $FK 'BEGIN { print ARGV[0], ARGC, ARGV[1]; exit }' 0 x.csv)"
+assert_eq "D123" "POSIX truthiness" "$out" '1 2 2
+ffftt
+2 1
+2
+211
+fk 3 0'
+
+# D124 printf %c: numbers are code points, strings give their first character
+out="$(echo '65 955' | $FK '{ printf "%c%c|%c|%c|%c\n", $1, $2, "65", "", 128512 }')"
+assert_eq "D124" "printf %c code points and strings" "$out" 'Aλ|6||😀'
+
+# D125 delete a[i, j] and whole-array delete through a function parameter
+out="$($FK 'function wipe(x) { delete x } function drop(x, i, j) { delete x[i, j] }
+            BEGIN { a[1, 2]; a[1, 3]; drop(a, 1, 2); for (k in a) { split(k, p, SUBSEP); print p[1] "-" p[2] }
+                    wipe(a); print length(a) }')"
+rc=0; err="$($FK 'BEGIN { delete a[1][2] }' 2>&1)" || rc=$?
+assert_eq "D125" "delete via composite key and array parameter" "$out|$rc|$err" '1-3
+0|2|fk: parse error: 1:20: arrays of arrays are not supported; write a[i, j] for a two-part key'
+
+# D126 vars() / arrays(): the program's names in order, fk's own only on request
+out="$(printf 'a 1\nb 2\n' | $FK '{ seen[$1] = $2; last = $1 } END {
+        n = vars(v); for (k in v) printf "%s=%s ", k, v[k]; print n
+        n = arrays(c); for (k in c) printf "%s=%s ", k, c[k]; print n
+        vars(w, 1); print ("NR" in v), w["NR"] }')"
+assert_eq "D126" "vars() and arrays()" "$out" 'last=b 1
+seen=2 v=1 2
+0 2'
+
+# D127 cmd | getline with no GETLINE_TIMEOUT: the command reads our stdin
+out="$(printf 'hello\n' | $FK 'BEGIN { "head -1" | getline x; print "got[" x "]" }')"
+assert_eq "D127" "getline from a stdin reader" "$out" "got[hello]"
+
+# D128 a failed spill is a counted runtime warning, silenced by --quiet
+out="$(seq 1 5 | TMPDIR="$W/no/such/dir" $FK -v ARRAY_SPILL_THRESHOLD=2 '{ a[NR] = 1 } END { print length(a), WARNINGS }' 2>&1 | sed 's/: No such.*//'
+       seq 1 5 | TMPDIR="$W/no/such/dir" $FK --quiet -v ARRAY_SPILL_THRESHOLD=2 '{ a[NR] = 1 } END { print WARNINGS }' 2>&1)"
+assert_eq "D128" "spill failure warning" "$out" 'fk: cannot spill array a to disk
+5 1
+1'
+
+# D129 a closed stdout ends the record loop (no END, status 141); rules
+# that cannot print read all their input
+out="$(seq 1 2000000 | $FK '{ print } END { print "end" > "/dev/stderr" }' 2>&1 | head -1; echo "rc=${PIPESTATUS[1]}"
+       seq 1 100000 | $FK '{ n++ } END { print n }' | head -1)"
+assert_eq "D129" "closed stdout stops input" "$out" '1
+rc=141
+100000'
+
+# D130 single-dash script args are cliargs() operands, not input files
+out="$($FK 'BEGIN { n = cliargs(a); print n, a[1], a["v"] }' -- -n --v=1 2>&1; echo "rc=$?")"
+assert_eq "D130" "-x after -- is not an input file" "$out" '1 -n 1
+rc=0'
+
 # ════════════════════════════════════════════════════════════════════
 print_summary "fk_only"